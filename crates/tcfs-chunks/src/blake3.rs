@@ -9,6 +9,9 @@ use std::path::Path;
 /// A BLAKE3 hash digest (32 bytes), displayed as 64 hex chars
 pub type Hash = blake3::Hash;
 
+/// Incremental BLAKE3 hasher, for content that arrives in pieces
+pub type Hasher = blake3::Hasher;
+
 /// Hash a byte slice in memory. Fast for small inputs.
 pub fn hash_bytes(data: &[u8]) -> Hash {
     blake3::hash(data)
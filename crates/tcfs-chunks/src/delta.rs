@@ -1,2 +1,145 @@
-//! Delta sync using rsync rolling hash (Phase 4)
-//! Stub — full implementation deferred until after FUSE driver is complete.
+//! Chunk-level delta between two versions of a file
+//!
+//! rsync finds shifted data with a rolling hash over fixed-size blocks. With
+//! FastCDC the boundaries are already content-defined, so after an insert or
+//! an in-place edit they resynchronise within a chunk or two and the rest of
+//! the file chunks exactly as before. A delta is then a per-chunk choice:
+//! copy a chunk the base version already has (by hash), or insert new bytes.
+//!
+//! The chunk identities are whatever the caller keys storage by (plaintext
+//! BLAKE3, or the hash of the stored encoding), so the same delta drives
+//! both reporting and "skip the upload" decisions.
+
+use std::collections::HashMap;
+
+/// One chunk of the target version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeltaOp {
+    /// Same content as the base chunk at `base_index`
+    Copy { base_index: usize },
+    /// Content the base version does not have
+    Insert,
+}
+
+/// Chunk identities of a base version, indexed for lookup.
+#[derive(Debug, Clone)]
+pub struct DeltaBase<T> {
+    positions: HashMap<T, usize>,
+}
+
+impl<T: Eq + std::hash::Hash> DeltaBase<T> {
+    /// Index `chunks` (in file order); repeated chunks map to their first
+    /// occurrence.
+    pub fn new(chunks: impl IntoIterator<Item = T>) -> Self {
+        let mut positions = HashMap::new();
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            positions.entry(chunk).or_insert(i);
+        }
+        Self { positions }
+    }
+
+    /// Index of a base chunk with identity `chunk`, if any.
+    pub fn find<Q>(&self, chunk: &Q) -> Option<usize>
+    where
+        T: std::borrow::Borrow<Q>,
+        Q: Eq + std::hash::Hash + ?Sized,
+    {
+        self.positions.get(chunk).copied()
+    }
+
+    /// The operation producing a target chunk with identity `chunk`.
+    pub fn op<Q>(&self, chunk: &Q) -> DeltaOp
+    where
+        T: std::borrow::Borrow<Q>,
+        Q: Eq + std::hash::Hash + ?Sized,
+    {
+        match self.find(chunk) {
+            Some(base_index) => DeltaOp::Copy { base_index },
+            None => DeltaOp::Insert,
+        }
+    }
+}
+
+/// Delta turning a base version into a target version, one op per target
+/// chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delta {
+    pub ops: Vec<DeltaOp>,
+}
+
+impl Delta {
+    /// Compare the chunk identities of `target` against those of `base`.
+    pub fn compute<T: Eq + std::hash::Hash>(base: &[T], target: &[T]) -> Self {
+        let base = DeltaBase::new(base);
+        Self {
+            ops: target.iter().map(|chunk| base.op(&chunk)).collect(),
+        }
+    }
+
+    /// Target chunks copied from the base.
+    pub fn reused(&self) -> usize {
+        self.ops
+            .iter()
+            .filter(|op| matches!(op, DeltaOp::Copy { .. }))
+            .count()
+    }
+
+    /// Target chunks that are new.
+    pub fn inserted(&self) -> usize {
+        self.ops.len() - self.reused()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk_data, ChunkSizes};
+
+    fn noise(seed: u64, len: usize) -> Vec<u8> {
+        let mut x = seed;
+        (0..len)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect()
+    }
+
+    fn hashes(data: &[u8]) -> Vec<crate::Hash> {
+        chunk_data(data, ChunkSizes::SMALL)
+            .into_iter()
+            .map(|c| c.hash)
+            .collect()
+    }
+
+    #[test]
+    fn identical_versions_copy_everything() {
+        let data = noise(1, 256 * 1024);
+        let delta = Delta::compute(&hashes(&data), &hashes(&data));
+        assert_eq!(delta.inserted(), 0);
+        for (i, op) in delta.ops.iter().enumerate() {
+            assert_eq!(*op, DeltaOp::Copy { base_index: i });
+        }
+    }
+
+    #[test]
+    fn insert_only_touches_nearby_chunks() {
+        let base = noise(2, 512 * 1024);
+        let mut target = base.clone();
+        target.splice(200_000..200_000, noise(3, 1000));
+
+        let delta = Delta::compute(&hashes(&base), &hashes(&target));
+        assert!(delta.inserted() <= 3, "{} inserted", delta.inserted());
+        assert!(delta.reused() > delta.ops.len() - 3);
+    }
+
+    #[test]
+    fn base_lookup_by_borrowed_key() {
+        let base = DeltaBase::new(["aa".to_string(), "bb".to_string(), "aa".to_string()]);
+        assert_eq!(base.find("aa"), Some(0));
+        assert_eq!(base.op("bb"), DeltaOp::Copy { base_index: 1 });
+        assert_eq!(base.op("cc"), DeltaOp::Insert);
+    }
+}
@@ -11,6 +11,7 @@
 //! Each chunk is content-addressed by its BLAKE3 hash.
 
 use anyhow::Result;
+use std::io::Read;
 use std::path::Path;
 
 /// A single content-defined chunk
@@ -24,6 +25,18 @@ pub struct Chunk {
     pub hash: crate::blake3::Hash,
 }
 
+/// A content-defined chunk that carries its own bytes, as yielded by
+/// [`chunk_reader`]
+#[derive(Debug, Clone)]
+pub struct OwnedChunk {
+    /// Byte offset within the source stream
+    pub offset: u64,
+    /// The chunk's bytes
+    pub data: Vec<u8>,
+    /// BLAKE3 hash of `data`
+    pub hash: crate::blake3::Hash,
+}
+
 /// Chunk size configuration
 #[derive(Debug, Clone, Copy)]
 pub struct ChunkSizes {
@@ -80,6 +93,37 @@ pub fn chunk_data(data: &[u8], sizes: ChunkSizes) -> Vec<Chunk> {
         .collect()
 }
 
+/// Chunk a byte stream incrementally.
+///
+/// Boundaries are found over a sliding buffer of at most `sizes.max_size`
+/// bytes, so memory stays bounded no matter how long `reader` is, and they
+/// are identical to what [`chunk_data`] picks for the same bytes. A read
+/// error ends the iterator after yielding it.
+pub fn chunk_reader<R: Read>(
+    reader: R,
+    sizes: ChunkSizes,
+) -> impl Iterator<Item = Result<OwnedChunk>> {
+    let mut chunker =
+        fastcdc::v2020::StreamCDC::new(reader, sizes.min_size, sizes.avg_size, sizes.max_size);
+    let mut failed = false;
+    std::iter::from_fn(move || {
+        if failed {
+            return None;
+        }
+        match chunker.next()? {
+            Ok(c) => Some(Ok(OwnedChunk {
+                offset: c.offset,
+                hash: crate::blake3::hash_bytes(&c.data),
+                data: c.data,
+            })),
+            Err(e) => {
+                failed = true;
+                Some(Err(anyhow::anyhow!("chunking stream: {e}")))
+            }
+        }
+    })
+}
+
 /// Chunk a file from disk using auto-selected chunk sizes.
 pub fn chunk_file(path: &Path) -> Result<(Vec<Chunk>, Vec<u8>)> {
     let data = std::fs::read(path)
@@ -131,6 +175,46 @@ mod tests {
         assert_eq!(expected_offset as usize, data.len());
     }
 
+    #[test]
+    fn chunk_reader_matches_chunk_data() {
+        let mut x = 0x2545_f491_4f6c_dd1du64;
+        let data: Vec<u8> = (0..300 * 1024)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect();
+        let expected = chunk_data(&data, ChunkSizes::SMALL);
+        let streamed: Vec<OwnedChunk> = chunk_reader(&data[..], ChunkSizes::SMALL)
+            .collect::<Result<_>>()
+            .unwrap();
+
+        assert!(streamed.len() > 10);
+        assert_eq!(streamed.len(), expected.len());
+        for (s, e) in streamed.iter().zip(&expected) {
+            assert_eq!(s.offset, e.offset);
+            assert_eq!(s.data.len(), e.length);
+            assert_eq!(s.hash, e.hash);
+        }
+        let joined: Vec<u8> = streamed.into_iter().flat_map(|c| c.data).collect();
+        assert_eq!(joined, data);
+    }
+
+    #[test]
+    fn chunk_reader_surfaces_read_errors() {
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disk on fire"))
+            }
+        }
+        let mut chunks = chunk_reader(Broken, ChunkSizes::SMALL);
+        assert!(chunks.next().unwrap().is_err());
+        assert!(chunks.next().is_none());
+    }
+
     proptest! {
         /// FastCDC boundary stability: same input → same chunk boundaries
         #[test]
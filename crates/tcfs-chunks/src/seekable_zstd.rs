@@ -91,6 +91,14 @@ pub fn decompress_all(blob: &SeekableBlob) -> Result<Vec<u8>> {
     Ok(out)
 }
 
+/// Decompress concatenated frames produced by [`compress`] without a seek table.
+///
+/// zstd decodes back-to-back frames as one stream, so this is enough when the
+/// whole blob is wanted (e.g. a compressed chunk fetched from storage).
+pub fn decompress_frames(compressed: &[u8]) -> Result<Vec<u8>> {
+    zstd::decode_all(compressed).context("zstd decompress frames")
+}
+
 /// Decompress a specific byte range from the seekable blob.
 ///
 /// `range_start` and `range_end` are offsets into the uncompressed data.
@@ -154,6 +162,13 @@ mod tests {
         assert_eq!(out, data);
     }
 
+    #[test]
+    fn frames_decompress_without_seek_table() {
+        let data: Vec<u8> = (0u8..=255).cycle().take(3 * 1024 * 1024).collect();
+        let blob = compress(&data, 1024 * 1024, 1).unwrap();
+        assert_eq!(decompress_frames(&blob.compressed).unwrap(), data);
+    }
+
     #[test]
     fn range_decompress_spanning_frames() {
         let data: Vec<u8> = (0u8..=255).cycle().take(3 * 1024 * 1024).collect();
@@ -0,0 +1,558 @@
+//! `tcfs doctor`: checks for the misconfigurations new users hit first
+//!
+//! Each check looks at one thing (config, credentials, storage, keychain,
+//! FUSE, daemon socket, clock) and reports pass, warn or fail with a hint.
+//! The checks read a [`DoctorEnv`] rather than the system directly, so
+//! tests can run them against a controlled environment.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use opendal::Operator;
+use tcfs_core::config::TcfsConfig;
+
+/// Outcome of one check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl Status {
+    fn label(self) -> &'static str {
+        match self {
+            Status::Pass => "pass",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+        }
+    }
+}
+
+/// One line of the report.
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+    /// What to do about a warning or failure
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Warn,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Fail,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// What the checks inspect. [`DoctorEnv::gather`] reads the real system.
+pub struct DoctorEnv {
+    pub config_path: PathBuf,
+    /// The config in effect (defaults when the file is missing or broken)
+    pub config: TcfsConfig,
+    /// Why the config file could not be read or parsed
+    pub config_error: Option<String>,
+    /// Where credentials came from, or `None` if none were found
+    pub credential_source: Option<String>,
+    /// Why loading credentials failed outright
+    pub credential_error: Option<String>,
+    /// Storage operator built from the credentials
+    pub operator: Option<Operator>,
+    pub keychain_available: bool,
+    /// Whether `tcfs auth unlock` has stored a session token
+    pub session_unlocked: bool,
+    /// Directories searched for FUSE helpers (`PATH` format)
+    pub search_path: Option<OsString>,
+}
+
+impl DoctorEnv {
+    /// Read the config at `config_path`, load credentials through the same
+    /// discovery chain as tcfsd, and probe the platform keychain.
+    pub async fn gather(config_path: &Path) -> Self {
+        let (config, config_error) = match crate::load_config(config_path).await {
+            Ok(config) => (config, None),
+            Err(e) => (TcfsConfig::default(), Some(format!("{e:#}"))),
+        };
+
+        let (credential_source, credential_error, operator) =
+            match tcfs_secrets::CredStore::load(&config.secrets, &config.storage).await {
+                Ok(store) => match store.s3 {
+                    Some(s3) => {
+                        use secrecy::ExposeSecret;
+                        let op = tcfs_storage::operator::build_from_core_config(
+                            &config.storage,
+                            &s3.access_key_id,
+                            s3.secret_access_key.expose_secret(),
+                        );
+                        match op {
+                            Ok(op) => (Some(store.source), None, Some(op)),
+                            Err(e) => (Some(store.source), Some(format!("{e:#}")), None),
+                        }
+                    }
+                    None => (None, None, None),
+                },
+                Err(e) => (None, Some(format!("{e:#}")), None),
+            };
+
+        let keychain_available = tcfs_secrets::keychain::is_available();
+        let session_unlocked = keychain_available
+            && matches!(
+                tcfs_secrets::keychain::get_secret(tcfs_secrets::keychain::keys::SESSION_TOKEN),
+                Ok(Some(_))
+            );
+
+        Self {
+            config_path: config_path.to_path_buf(),
+            config,
+            config_error,
+            credential_source,
+            credential_error,
+            operator,
+            keychain_available,
+            session_unlocked,
+            search_path: std::env::var_os("PATH"),
+        }
+    }
+}
+
+/// Run every check against `env`, in report order.
+pub async fn run(env: &DoctorEnv) -> Vec<Check> {
+    let mut checks = vec![check_config(env), check_credentials(env)];
+    checks.extend(check_storage(env).await);
+    checks.push(check_keychain(env));
+    checks.push(check_fuse(env));
+    checks.push(check_daemon(env).await);
+    checks
+}
+
+/// Print `checks` as an aligned report.
+pub fn print_report(checks: &[Check]) {
+    for check in checks {
+        println!(
+            "  [{}] {:<12} {}",
+            check.status.label(),
+            check.name,
+            check.detail
+        );
+        if let Some(hint) = &check.hint {
+            println!("         {:<12} hint: {hint}", "");
+        }
+    }
+}
+
+fn check_config(env: &DoctorEnv) -> Check {
+    const NAME: &str = "config";
+    let path = env.config_path.display();
+    if let Some(e) = &env.config_error {
+        return Check::fail(NAME, e.clone(), format!("fix the TOML in {path}"));
+    }
+    if !env.config_path.exists() {
+        return Check::warn(
+            NAME,
+            format!("no file at {path}; using defaults"),
+            "write a config there, or point --config / TCFS_CONFIG at yours",
+        );
+    }
+
+    let config = &env.config;
+    let storage = &config.storage;
+    let problem = if storage.bucket.is_empty() {
+        Some("storage.bucket is empty".to_string())
+    } else if storage.enforce_tls && storage.endpoint.starts_with("http://") {
+        Some(format!(
+            "storage.enforce_tls is set but {} is plain HTTP",
+            storage.endpoint
+        ))
+    } else if storage.chunk_shard_depth > tcfs_core::layout::MAX_CHUNK_SHARD_DEPTH {
+        Some(format!(
+            "storage.chunk_shard_depth {} is above {}",
+            storage.chunk_shard_depth,
+            tcfs_core::layout::MAX_CHUNK_SHARD_DEPTH
+        ))
+    } else if config.sync.transfer_concurrency_min > config.sync.transfer_concurrency_max {
+        Some("sync.transfer_concurrency_min exceeds sync.transfer_concurrency_max".to_string())
+    } else {
+        None
+    };
+    if let Some(problem) = problem {
+        return Check::fail(NAME, problem, format!("edit {path}"));
+    }
+
+    #[cfg(unix)]
+    if config.config_file_mode_check {
+        use std::os::unix::fs::PermissionsExt;
+        if let Ok(meta) = std::fs::metadata(&env.config_path) {
+            if meta.permissions().mode() & 0o004 != 0 {
+                return Check::warn(
+                    NAME,
+                    format!("{path} is world-readable"),
+                    format!("chmod 600 {path}"),
+                );
+            }
+        }
+    }
+    Check::pass(NAME, format!("{path}"))
+}
+
+fn check_credentials(env: &DoctorEnv) -> Check {
+    const NAME: &str = "credentials";
+    if let Some(e) = &env.credential_error {
+        return Check::fail(
+            NAME,
+            e.clone(),
+            "check storage.credentials_file and the age identity that decrypts it",
+        );
+    }
+    match &env.credential_source {
+        Some(source) => Check::pass(NAME, format!("S3 credentials from {source}")),
+        None => Check::fail(
+            NAME,
+            "no S3 credentials found",
+            "set storage.credentials_file, or export AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY",
+        ),
+    }
+}
+
+/// Reachability, a write/read/delete probe, and the clock skew against the
+/// backend's timestamp on the probe object.
+async fn check_storage(env: &DoctorEnv) -> Vec<Check> {
+    let endpoint = &env.config.storage.endpoint;
+    let Some(op) = &env.operator else {
+        let skipped = |name| Check::warn(name, "skipped: no credentials", "fix credentials first");
+        return vec![skipped("storage"), skipped("write probe"), skipped("clock")];
+    };
+
+    let reach = match tcfs_storage::check_health(op).await {
+        Ok(()) => Check::pass("storage", format!("{endpoint} reachable")),
+        Err(e) => {
+            return vec![
+                Check::fail(
+                    "storage",
+                    format!("{endpoint}: {e:#}"),
+                    "check storage.endpoint, storage.bucket and that the S3 service is up",
+                ),
+                Check::warn(
+                    "write probe",
+                    "skipped: storage unreachable",
+                    "fix storage first",
+                ),
+                Check::warn("clock", "skipped: storage unreachable", "fix storage first"),
+            ];
+        }
+    };
+
+    let key = format!(
+        ".tcfs-doctor/probe-{}-{}",
+        std::process::id(),
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+    );
+    let payload = b"tcfs doctor write probe".to_vec();
+    let probe = async {
+        op.write(&key, payload.clone()).await?;
+        let read = op.read(&key).await?.to_vec();
+        let meta = op.stat(&key).await?;
+        op.delete(&key).await?;
+        anyhow::ensure!(read == payload, "read back different bytes");
+        Ok::<_, anyhow::Error>(meta.last_modified().map(SystemTime::from))
+    }
+    .await;
+
+    match probe {
+        Ok(server_time) => vec![
+            reach,
+            Check::pass("write probe", format!("wrote, read and deleted {key}")),
+            check_clock(
+                server_time,
+                SystemTime::now(),
+                env.config.sync.max_clock_skew_secs,
+            ),
+        ],
+        Err(e) => vec![
+            reach,
+            Check::fail(
+                "write probe",
+                format!("{key}: {e:#}"),
+                "the credentials may be read-only, or the bucket may not exist",
+            ),
+            Check::warn("clock", "skipped: write probe failed", "fix storage first"),
+        ],
+    }
+}
+
+/// Compare the backend's timestamp on a fresh object with local time.
+///
+/// Past `max_skew_secs` manifests from this device get flagged by others;
+/// anything over a minute is worth a warning.
+pub fn check_clock(server: Option<SystemTime>, local: SystemTime, max_skew_secs: u64) -> Check {
+    const NAME: &str = "clock";
+    let Some(server) = server else {
+        return Check::warn(
+            NAME,
+            "backend reported no modification time",
+            "compare `date -u` with a trusted time source",
+        );
+    };
+    let (skew, direction) = match local.duration_since(server) {
+        Ok(ahead) => (ahead, "ahead of"),
+        Err(behind) => (behind.duration(), "behind"),
+    };
+    let detail = format!("local clock {}s {direction} storage", skew.as_secs());
+    if skew > Duration::from_secs(max_skew_secs) {
+        Check::fail(
+            NAME,
+            detail,
+            "enable NTP (timedatectl set-ntp true); other devices will flag this one's manifests",
+        )
+    } else if skew > Duration::from_secs(60) {
+        Check::warn(NAME, detail, "enable NTP (timedatectl set-ntp true)")
+    } else {
+        Check::pass(NAME, detail)
+    }
+}
+
+fn check_keychain(env: &DoctorEnv) -> Check {
+    const NAME: &str = "keychain";
+    if !env.keychain_available {
+        let hint = "on Linux, run GNOME Keyring or KDE Wallet; `tcfs auth unlock` needs one";
+        return if env.config.crypto.enabled {
+            Check::fail(NAME, "platform keychain unavailable", hint)
+        } else {
+            Check::warn(NAME, "platform keychain unavailable", hint)
+        };
+    }
+    if env.config.crypto.enabled && !env.session_unlocked {
+        return Check::warn(
+            NAME,
+            "available, but the encryption session is locked",
+            "run `tcfs auth unlock`",
+        );
+    }
+    Check::pass(NAME, "available")
+}
+
+fn check_fuse(env: &DoctorEnv) -> Check {
+    const NAME: &str = "fuse";
+    if !cfg!(feature = "fuse") {
+        return Check::warn(
+            NAME,
+            "this tcfs was built without FUSE support",
+            "install a build with the `fuse` feature to use `tcfs mount`",
+        );
+    }
+    if cfg!(target_os = "macos") {
+        let installed = [
+            "/Library/Filesystems/macfuse.fs",
+            "/usr/local/lib/libfuse-t.dylib",
+        ]
+        .iter()
+        .any(|p| Path::new(p).exists());
+        return if installed {
+            Check::pass(NAME, "macFUSE or FUSE-T installed")
+        } else {
+            Check::warn(
+                NAME,
+                "neither macFUSE nor FUSE-T found",
+                "install FUSE-T (brew install macos-fuse-t/homebrew-cask/fuse-t)",
+            )
+        };
+    }
+
+    let found = env.search_path.as_ref().and_then(|paths| {
+        std::env::split_paths(paths)
+            .flat_map(|dir| ["fusermount3", "fusermount"].map(|bin| dir.join(bin)))
+            .find(|bin| bin.is_file())
+    });
+    match found {
+        Some(bin) => Check::pass(NAME, format!("{}", bin.display())),
+        None => Check::warn(
+            NAME,
+            "fusermount3 not found on PATH",
+            "install fuse3 (e.g. apt install fuse3 / dnf install fuse3) to use `tcfs mount`",
+        ),
+    }
+}
+
+async fn check_daemon(env: &DoctorEnv) -> Check {
+    const NAME: &str = "daemon";
+    let socket = &env.config.daemon.socket;
+    if !socket.exists() {
+        return Check::warn(
+            NAME,
+            format!("no socket at {}", socket.display()),
+            "start tcfsd (push and pull work without it; status, sync and mounts do not)",
+        );
+    }
+    #[cfg(unix)]
+    {
+        let connected = async {
+            let mut client = crate::connect_daemon(socket).await?;
+            Ok::<_, anyhow::Error>(client.status().await?)
+        };
+        match tokio::time::timeout(Duration::from_secs(5), connected).await {
+            Ok(Ok(status)) => Check::pass(
+                NAME,
+                format!("tcfsd v{} at {}", status.version, socket.display()),
+            ),
+            Ok(Err(e)) => Check::fail(
+                NAME,
+                format!("{}: {e:#}", socket.display()),
+                "the socket is stale or unreadable; restart tcfsd",
+            ),
+            Err(_) => Check::fail(
+                NAME,
+                format!("{}: no answer in 5s", socket.display()),
+                "restart tcfsd",
+            ),
+        }
+    }
+    #[cfg(not(unix))]
+    Check::warn(
+        NAME,
+        format!("{} exists", socket.display()),
+        "daemon checks need a Unix socket",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory_operator() -> Operator {
+        Operator::new(opendal::services::Memory::default())
+            .expect("memory operator")
+            .finish()
+    }
+
+    #[tokio::test]
+    async fn every_check_reports_a_status() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let config_path = tmp.path().join("config.toml");
+        std::fs::write(&config_path, "[storage]\nbucket = \"doctor\"\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&config_path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        let bin = tmp.path().join("bin");
+        std::fs::create_dir(&bin).unwrap();
+        std::fs::write(bin.join("fusermount3"), "").unwrap();
+
+        let mut config = TcfsConfig::default();
+        config.storage.bucket = "doctor".into();
+        config.daemon.socket = tmp.path().join("tcfsd.sock");
+        let mut env = DoctorEnv {
+            config_path,
+            config,
+            config_error: None,
+            credential_source: Some("env".into()),
+            credential_error: None,
+            operator: Some(memory_operator()),
+            keychain_available: true,
+            session_unlocked: false,
+            search_path: Some(bin.into_os_string()),
+        };
+
+        let checks = run(&env).await;
+        let status = |checks: &[Check], name: &str| {
+            checks
+                .iter()
+                .find(|c| c.name == name)
+                .unwrap_or_else(|| panic!("no {name} check"))
+                .status
+        };
+        let names: Vec<_> = checks.iter().map(|c| c.name).collect();
+        assert_eq!(
+            names,
+            [
+                "config",
+                "credentials",
+                "storage",
+                "write probe",
+                "clock",
+                "keychain",
+                "fuse",
+                "daemon"
+            ]
+        );
+        assert_eq!(status(&checks, "config"), Status::Pass);
+        assert_eq!(status(&checks, "credentials"), Status::Pass);
+        assert_eq!(status(&checks, "storage"), Status::Pass);
+        assert_eq!(status(&checks, "write probe"), Status::Pass);
+        assert_eq!(status(&checks, "keychain"), Status::Pass);
+        assert_eq!(
+            status(&checks, "fuse"),
+            if cfg!(all(feature = "fuse", not(target_os = "macos"))) {
+                Status::Pass
+            } else {
+                Status::Warn
+            }
+        );
+        assert_eq!(status(&checks, "daemon"), Status::Warn);
+        // The probe leaves nothing behind
+        let op = env.operator.as_ref().unwrap();
+        assert!(op.list(".tcfs-doctor/").await.unwrap().is_empty());
+
+        // Break everything that can be broken from here
+        env.config_error = Some("parsing config: expected `=`".into());
+        env.credential_source = None;
+        env.operator = None;
+        env.keychain_available = false;
+        env.config.crypto.enabled = true;
+        env.search_path = None;
+        let checks = run(&env).await;
+        assert_eq!(checks.len(), names.len());
+        assert_eq!(status(&checks, "config"), Status::Fail);
+        assert_eq!(status(&checks, "credentials"), Status::Fail);
+        assert_eq!(status(&checks, "storage"), Status::Warn);
+        assert_eq!(status(&checks, "keychain"), Status::Fail);
+        assert!(checks
+            .iter()
+            .filter(|c| c.status != Status::Pass)
+            .all(|c| c.hint.is_some()));
+    }
+
+    #[test]
+    fn clock_check_grades_skew() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let at = |secs: i64| {
+            Some(if secs >= 0 {
+                now + Duration::from_secs(secs as u64)
+            } else {
+                now - Duration::from_secs(secs.unsigned_abs())
+            })
+        };
+        assert_eq!(check_clock(at(2), now, 3600).status, Status::Pass);
+        assert_eq!(check_clock(at(-300), now, 3600).status, Status::Warn);
+        let far = check_clock(at(7200), now, 3600);
+        assert_eq!(far.status, Status::Fail);
+        assert!(far.detail.contains("7200s behind"), "{}", far.detail);
+        assert_eq!(check_clock(None, now, 3600).status, Status::Warn);
+    }
+}
@@ -16,13 +16,12 @@ use indicatif::{ProgressBar, ProgressStyle};
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
-#[cfg(unix)]
-use tonic::transport::{Channel, Endpoint, Uri};
-#[cfg(unix)]
-use tower::service_fn;
+mod doctor;
 
 #[cfg(unix)]
-use tcfs_core::proto::{tcfs_daemon_client::TcfsDaemonClient, Empty, StatusRequest};
+use tcfs_client::TcfsClient;
+#[cfg(unix)]
+use tcfs_core::proto::{ReloadRequest, SyncNowRequest};
 
 // ── CLI structure ──────────────────────────────────────────────────────────────
 
@@ -52,6 +51,40 @@ enum Commands {
     /// Show daemon and storage status
     Status,
 
+    /// Ask the running daemon to re-read its config file and credentials
+    Reload,
+
+    /// Compare the prefix's sync root with the remote and report what a sync would do
+    ///
+    /// With --dry-run the planned pulls, pushes and conflicts are printed and
+    /// nothing changes (tcfsd is not needed); without it tcfsd carries the
+    /// sync out, as `tcfs sync` does.
+    Reconcile {
+        /// Remote prefix to reconcile (default: storage.bucket)
+        #[arg(long, short = 'p')]
+        prefix: Option<String>,
+        /// Print the plan without pulling or pushing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Sync state cache to plan against (default: tcfsd's, sync.state_db)
+        #[arg(long, env = "TCFS_STATE_PATH")]
+        state: Option<PathBuf>,
+    },
+
+    /// Check config, credentials, storage, keychain, FUSE, daemon and clock,
+    /// with a hint for each problem found
+    Doctor,
+
+    /// Ask the running daemon to reconcile a prefix's sync root with the remote now
+    Sync {
+        /// Remote prefix to reconcile (default: storage.bucket)
+        #[arg(long, short = 'p')]
+        prefix: Option<String>,
+    },
+
+    /// Show which fleet devices the running daemon has heard from, and when
+    Fleet,
+
     /// Configuration management
     Config {
         #[command(subcommand)]
@@ -78,15 +111,25 @@ enum Commands {
         /// Path to the sync state cache JSON file (overrides config)
         #[arg(long, env = "TCFS_STATE_PATH")]
         state: Option<PathBuf>,
+        /// Show what would be uploaded without writing anything to storage
+        #[arg(long)]
+        dry_run: bool,
+        /// Publish a directory push all or nothing: no index entries are
+        /// written unless every file uploads
+        #[arg(long)]
+        transactional: bool,
     },
 
-    /// Download a file from SeaweedFS using a manifest path
+    /// Download a file from SeaweedFS by relative path or manifest path
     ///
-    /// The manifest path is in format: {prefix}/manifests/{hash}
+    /// Either `<rel_path> --prefix P` (resolved through {prefix}/index/<rel_path>),
+    /// a glob with `--prefix P` (e.g. 'src/**/*.rs', pulls every match into a tree),
+    /// or a manifest path in format: {prefix}/manifests/{hash}
     Pull {
-        /// Remote manifest path (e.g. mydata/manifests/abc123...)
+        /// File path relative to --prefix, or remote manifest path (e.g. mydata/manifests/abc123...)
         manifest: String,
-        /// Local destination path (default: current dir + hash basename)
+        /// Local destination path (default: the relative path, or the hash basename;
+        /// for a glob, the directory to pull into, default: current directory)
         local: Option<PathBuf>,
         /// Remote prefix to look up chunks (default: derived from manifest path)
         #[arg(long, short = 'p')]
@@ -94,6 +137,21 @@ enum Commands {
         /// Path to the sync state cache JSON file (overrides config)
         #[arg(long, env = "TCFS_STATE_PATH")]
         state: Option<PathBuf>,
+        /// Overwrite the local file even if it has changes that were never pushed
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Write a remote file to stdout, one chunk at a time
+    ///
+    /// Either `<rel_path> --prefix P` or a manifest path ({prefix}/manifests/{hash}).
+    /// Encrypted files are decrypted with the unlocked session's master key.
+    Cat {
+        /// File path relative to --prefix, or remote manifest path
+        path: String,
+        /// Remote prefix (default: derived from the manifest path)
+        #[arg(long, short = 'p')]
+        prefix: Option<String>,
     },
 
     /// Show local sync state for a file or directory
@@ -104,6 +162,125 @@ enum Commands {
         /// Path to the sync state cache JSON file (overrides config)
         #[arg(long, env = "TCFS_STATE_PATH")]
         state: Option<PathBuf>,
+        /// Walk the directory and print the status of every file
+        #[arg(long)]
+        tree: bool,
+    },
+
+    /// List the recorded versions of a file (needs sync.keep_history)
+    History {
+        /// File path relative to the remote prefix (e.g. docs/readme.md)
+        rel_path: String,
+        /// Remote prefix the file was pushed under
+        #[arg(long, short = 'p')]
+        prefix: String,
+    },
+
+    /// Restore the version of a file that was current at a given time
+    Restore {
+        /// File path relative to the remote prefix (e.g. docs/readme.md)
+        rel_path: String,
+        /// Unix timestamp; the newest version recorded at or before it is restored
+        #[arg(long)]
+        at: u64,
+        /// Local destination path (default: the relative path under the current dir)
+        local: Option<PathBuf>,
+        /// Remote prefix the file was pushed under
+        #[arg(long, short = 'p')]
+        prefix: String,
+    },
+
+    /// Delete old version history, then the manifests and chunks only it kept
+    #[command(name = "prune-history")]
+    PruneHistory {
+        /// Remote prefix to prune
+        prefix: String,
+        /// Newest versions to keep per file
+        #[arg(long, required_unless_present = "older_than")]
+        keep: Option<usize>,
+        /// Only prune versions older than this (e.g. 90d, 12h, 2w)
+        #[arg(long, value_parser = parse_age)]
+        older_than: Option<u64>,
+    },
+
+    /// Chunk-size histogram, dedup ratio and largest files under a remote prefix
+    Stats {
+        /// Remote prefix to report on
+        prefix: String,
+        /// Largest files to list
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+
+    /// Restore a whole pushed tree from the root hash `tcfs push` printed
+    ///
+    /// With `--since`, mirror a prefix incrementally instead:
+    /// `tcfs pull-tree <prefix> <local> --since <unix-ts>` downloads only the
+    /// files written after the timestamp into LOCAL (which may hold an
+    /// earlier mirror).
+    #[command(name = "pull-tree")]
+    PullTree {
+        /// Root hash of the tree manifest ({prefix}/trees/<root-hash>.json),
+        /// or with --since the remote prefix to mirror
+        #[arg(value_name = "ROOT_HASH|PREFIX")]
+        root_hash: String,
+        /// Local directory to restore into; must not exist or be empty
+        /// unless --since is given
+        local: PathBuf,
+        /// Remote prefix the tree was pushed under
+        #[arg(
+            long,
+            short = 'p',
+            required_unless_present = "since",
+            conflicts_with = "since"
+        )]
+        prefix: Option<String>,
+        /// Only pull files written after this unix timestamp, or `last` for
+        /// the start of the previous successful --since pull into LOCAL
+        #[arg(long)]
+        since: Option<String>,
+    },
+
+    /// Take, list and restore point-in-time snapshots of a remote prefix
+    Snapshot {
+        #[command(subcommand)]
+        action: SnapshotAction,
+    },
+
+    /// Write every file under a remote prefix to a tar archive
+    /// (zstd-compressed when the name ends in `.zst`)
+    Export {
+        /// Remote prefix to export
+        prefix: String,
+        /// Archive to write, e.g. `backup.tar.zst`
+        out: PathBuf,
+    },
+
+    /// Push the files in a tar archive (`.tar` or `.tar.zst`) into a remote prefix
+    Import {
+        /// Archive to read
+        archive: PathBuf,
+        /// Remote prefix to import into
+        #[arg(long, short = 'p')]
+        prefix: String,
+        /// Path to the sync state cache JSON file (overrides config)
+        #[arg(long, env = "TCFS_STATE_PATH")]
+        state: Option<PathBuf>,
+    },
+
+    /// Check every chunk under a remote prefix for loss or corruption
+    Verify {
+        /// Remote prefix to verify
+        prefix: String,
+        /// Re-upload damaged chunks from local copies of their files
+        #[arg(long)]
+        repair: bool,
+        /// Local directory the prefix was pushed from (for files not in the state cache)
+        #[arg(long)]
+        local: Option<PathBuf>,
+        /// Path to the sync state cache JSON file (overrides config)
+        #[arg(long, env = "TCFS_STATE_PATH")]
+        state: Option<PathBuf>,
     },
 
     // ── Phase 3: FUSE mount + stub management ────────────────────────────────
@@ -183,6 +360,11 @@ enum DeviceAction {
     },
     /// List enrolled devices
     List,
+    /// Show the full identity and enrollment details of a device
+    Show {
+        /// Device name
+        name: String,
+    },
     /// Revoke a device by name
     Revoke {
         /// Device name to revoke
@@ -192,6 +374,29 @@ enum DeviceAction {
     Status,
 }
 
+#[derive(Subcommand, Debug)]
+enum SnapshotAction {
+    /// Record everything currently indexed under a prefix as a snapshot
+    Create {
+        /// Remote prefix to snapshot
+        prefix: String,
+    },
+    /// List the snapshots recorded under a prefix
+    List {
+        /// Remote prefix
+        prefix: String,
+    },
+    /// Materialize a snapshot into a local directory
+    Restore {
+        /// Remote prefix the snapshot was taken of
+        prefix: String,
+        /// Snapshot id from `tcfs snapshot list` (or its full root hash)
+        snapshot_id: String,
+        /// Local directory to restore into; must not exist or be empty
+        dest: PathBuf,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 enum AuthAction {
     /// Unlock the encryption session (store master key in keychain)
@@ -239,6 +444,10 @@ enum KdbxAction {
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    // The doctor reports a broken config instead of failing on it
+    if let Commands::Doctor = cli.command {
+        return cmd_doctor(&cli.config).await;
+    }
     let config = load_config(&cli.config).await?;
 
     match cli.command {
@@ -248,6 +457,36 @@ async fn main() -> Result<()> {
         Commands::Status => {
             anyhow::bail!("status command requires Unix daemon socket (not available on Windows)")
         }
+        #[cfg(unix)]
+        Commands::Reload => cmd_reload(&config).await,
+        #[cfg(not(unix))]
+        Commands::Reload => {
+            anyhow::bail!("reload command requires Unix daemon socket (not available on Windows)")
+        }
+        #[cfg(unix)]
+        Commands::Sync { prefix } => cmd_sync(&config, prefix.as_deref()).await,
+        #[cfg(not(unix))]
+        Commands::Sync { .. } => {
+            anyhow::bail!("sync command requires Unix daemon socket (not available on Windows)")
+        }
+        #[cfg(unix)]
+        Commands::Fleet => cmd_fleet(&config).await,
+        #[cfg(not(unix))]
+        Commands::Fleet => {
+            anyhow::bail!("fleet command requires Unix daemon socket (not available on Windows)")
+        }
+        Commands::Reconcile {
+            prefix,
+            dry_run: true,
+            state,
+        } => cmd_reconcile_plan(&config, prefix.as_deref(), state.as_deref()).await,
+        #[cfg(unix)]
+        Commands::Reconcile { prefix, .. } => cmd_sync(&config, prefix.as_deref()).await,
+        #[cfg(not(unix))]
+        Commands::Reconcile { .. } => {
+            anyhow::bail!("reconcile without --dry-run requires Unix daemon socket (not available on Windows)")
+        }
+        Commands::Doctor => unreachable!("handled before the config is loaded"),
         Commands::Config {
             action: ConfigAction::Show,
         } => cmd_config_show(&config, &cli.config),
@@ -271,12 +510,26 @@ async fn main() -> Result<()> {
             local,
             prefix,
             state,
-        } => cmd_push(&config, &local, prefix.as_deref(), state.as_deref()).await,
+            dry_run,
+            transactional,
+        } => {
+            cmd_push(
+                &config,
+                &local,
+                prefix.as_deref(),
+                state.as_deref(),
+                dry_run,
+                transactional,
+            )
+            .await
+        }
+        Commands::Cat { path, prefix } => cmd_cat(&config, &path, prefix.as_deref()).await,
         Commands::Pull {
             manifest,
             local,
             prefix,
             state,
+            force,
         } => {
             cmd_pull(
                 &config,
@@ -284,12 +537,57 @@ async fn main() -> Result<()> {
                 local.as_deref(),
                 prefix.as_deref(),
                 state.as_deref(),
+                force,
             )
             .await
         }
-        Commands::SyncStatus { path, state } => {
-            cmd_sync_status(&config, path.as_deref(), state.as_deref())
+        Commands::SyncStatus { path, state, tree } => {
+            cmd_sync_status(&config, path.as_deref(), state.as_deref(), tree)
         }
+        Commands::History { rel_path, prefix } => cmd_history(&config, &rel_path, &prefix).await,
+        Commands::Restore {
+            rel_path,
+            at,
+            local,
+            prefix,
+        } => cmd_restore(&config, &rel_path, at, local.as_deref(), &prefix).await,
+        Commands::PruneHistory {
+            prefix,
+            keep,
+            older_than,
+        } => cmd_prune_history(&config, &prefix, keep, older_than).await,
+        Commands::Stats { prefix, top } => cmd_stats(&config, &prefix, top).await,
+        Commands::PullTree {
+            root_hash,
+            local,
+            prefix,
+            since,
+        } => match (prefix, since) {
+            (_, Some(since)) => cmd_pull_since(&config, &root_hash, &local, &since).await,
+            (Some(prefix), None) => cmd_pull_tree(&config, &root_hash, &local, &prefix).await,
+            (None, None) => unreachable!("clap requires --prefix without --since"),
+        },
+        Commands::Snapshot { action } => match action {
+            SnapshotAction::Create { prefix } => cmd_snapshot_create(&config, &prefix).await,
+            SnapshotAction::List { prefix } => cmd_snapshot_list(&config, &prefix).await,
+            SnapshotAction::Restore {
+                prefix,
+                snapshot_id,
+                dest,
+            } => cmd_snapshot_restore(&config, &prefix, &snapshot_id, &dest).await,
+        },
+        Commands::Export { prefix, out } => cmd_export(&config, &prefix, &out).await,
+        Commands::Import {
+            archive,
+            prefix,
+            state,
+        } => cmd_import(&config, &archive, &prefix, state.as_deref()).await,
+        Commands::Verify {
+            prefix,
+            repair,
+            local,
+            state,
+        } => cmd_verify(&config, &prefix, repair, local.as_deref(), state.as_deref()).await,
         #[cfg(feature = "fuse")]
         Commands::Mount {
             remote,
@@ -307,6 +605,7 @@ async fn main() -> Result<()> {
         Commands::Device { action } => match action {
             DeviceAction::Enroll { name } => cmd_device_enroll(name),
             DeviceAction::List => cmd_device_list(),
+            DeviceAction::Show { name } => cmd_device_show(&name),
             DeviceAction::Revoke { name } => cmd_device_revoke(&name),
             DeviceAction::Status => cmd_device_status(),
         },
@@ -352,6 +651,30 @@ async fn load_config(path: &Path) -> Result<tcfs_core::config::TcfsConfig> {
 /// Reads AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY (standard S3 env vars).
 /// These override any config file credentials for direct CLI use.
 fn build_operator_from_env(config: &tcfs_core::config::TcfsConfig) -> Result<opendal::Operator> {
+    let (access_key, secret_key) = s3_credentials_from_env()?;
+    tcfs_storage::operator::build_from_core_config(&config.storage, &access_key, &secret_key)
+        .context("building storage operator")
+}
+
+/// Read-through mirrors from `storage.read_mirrors`, using the same
+/// credentials as [`build_operator_from_env`].
+fn read_mirrors_from_env(
+    config: &tcfs_core::config::TcfsConfig,
+) -> Result<tcfs_sync::store::ReadMirrors> {
+    if config.storage.read_mirrors.is_empty() {
+        return Ok(tcfs_sync::store::ReadMirrors::default());
+    }
+    let (access_key, secret_key) = s3_credentials_from_env()?;
+    let ops =
+        tcfs_storage::operator::build_read_mirrors(&config.storage, &access_key, &secret_key)?;
+    Ok(tcfs_sync::store::ReadMirrors::new(
+        ops,
+        config.storage.read_mirror_backfill,
+    ))
+}
+
+/// S3 access key and secret from the environment.
+fn s3_credentials_from_env() -> Result<(String, String)> {
     let access_key = std::env::var("AWS_ACCESS_KEY_ID")
         .or_else(|_| std::env::var("TCFS_ACCESS_KEY_ID"))
         .context(
@@ -364,9 +687,7 @@ fn build_operator_from_env(config: &tcfs_core::config::TcfsConfig) -> Result<ope
     let secret_key = std::env::var("AWS_SECRET_ACCESS_KEY")
         .or_else(|_| std::env::var("TCFS_SECRET_ACCESS_KEY"))
         .context("AWS_SECRET_ACCESS_KEY environment variable not set")?;
-
-    tcfs_storage::operator::build_from_core_config(&config.storage, &access_key, &secret_key)
-        .context("building storage operator")
+    Ok((access_key, secret_key))
 }
 
 /// Expand `~` in path to the user's home directory
@@ -419,7 +740,14 @@ fn make_spinner(prefix: &str) -> ProgressBar {
 }
 
 /// Load the device_id from the registry, using config for device name and registry path.
+///
+/// A valid `sync.device_id` pin takes precedence, matching the daemon.
 fn load_device_id(config: &tcfs_core::config::TcfsConfig) -> String {
+    if let Some(pinned) = &config.sync.device_id {
+        if tcfs_secrets::device::validate_device_id(pinned).is_ok() {
+            return pinned.clone();
+        }
+    }
     let device_name = config
         .sync
         .device_name
@@ -440,16 +768,34 @@ fn load_device_id(config: &tcfs_core::config::TcfsConfig) -> String {
     }
 }
 
+/// This device's manifest signing key, if tcfsd has created one next to the
+/// device registry (pushes are unsigned otherwise).
+fn load_signing_key(
+    config: &tcfs_core::config::TcfsConfig,
+) -> Option<std::sync::Arc<tcfs_crypto::DeviceSigningKey>> {
+    let registry_path = config
+        .sync
+        .device_identity
+        .clone()
+        .unwrap_or_else(tcfs_secrets::device::default_registry_path);
+    let key_path = tcfs_secrets::device::signing_key_path(&registry_path);
+    if !key_path.exists() {
+        return None;
+    }
+    match tcfs_crypto::DeviceSigningKey::load_or_generate(&key_path) {
+        Ok(key) => Some(std::sync::Arc::new(key)),
+        Err(e) => {
+            eprintln!("warning: manifests will be unsigned: {e:#}");
+            None
+        }
+    }
+}
+
 /// Build a CollectConfig from the sync config.
 fn collect_config_from_sync(
     config: &tcfs_core::config::TcfsConfig,
 ) -> tcfs_sync::engine::CollectConfig {
-    tcfs_sync::engine::CollectConfig {
-        sync_git_dirs: config.sync.sync_git_dirs,
-        git_sync_mode: config.sync.git_sync_mode.clone(),
-        sync_hidden_dirs: config.sync.sync_hidden_dirs,
-        exclude_patterns: config.sync.exclude_patterns.clone(),
-    }
+    tcfs_sync::engine::CollectConfig::from_config(&config.sync)
 }
 
 // ── `tcfs push` ───────────────────────────────────────────────────────────────
@@ -459,11 +805,15 @@ async fn cmd_push(
     local: &Path,
     prefix: Option<&str>,
     state_override: Option<&Path>,
+    dry_run: bool,
+    transactional: bool,
 ) -> Result<()> {
     let op = build_operator_from_env(config)?;
     let state_path = resolve_state_path(config, state_override);
     let mut state = tcfs_sync::state::StateCache::open(&state_path)
         .with_context(|| format!("opening state cache: {}", state_path.display()))?;
+    state.enable_chunk_filter(config.sync.chunk_filter_fp_rate);
+    state.set_signing_key(load_signing_key(config));
 
     let device_id = load_device_id(config);
     let collect_cfg = collect_config_from_sync(config);
@@ -477,6 +827,21 @@ async fn cmd_push(
                 .map(|n| n.to_string_lossy().to_string())
                 .unwrap_or_else(|| "tcfs".to_string())
         });
+    state.set_read_only(config.sync.is_read_only_prefix(&remote_prefix));
+    state.set_quota(config.storage.quota_bytes);
+    state.set_chunk_shard_depth(config.storage.chunk_shard_depth);
+    state.set_cache_control(config.storage.cache_control.clone());
+    state.set_transfer_concurrency(
+        config.sync.transfer_concurrency_min,
+        config.sync.transfer_concurrency_max,
+    );
+    state.set_max_clock_skew(config.sync.max_clock_skew_secs);
+    state.set_pack_threshold(
+        config
+            .sync
+            .pack_small_files
+            .then_some(config.sync.pack_threshold_bytes),
+    );
 
     println!(
         "Pushing {} → {}:{} (endpoint: {}{})",
@@ -491,32 +856,59 @@ async fn cmd_push(
         },
     );
 
+    if dry_run {
+        return push_dry_run(
+            &op,
+            local,
+            &remote_prefix,
+            &mut state,
+            &device_id,
+            &collect_cfg,
+        )
+        .await;
+    }
+
     if local.is_file() {
-        // Single-file push
+        // Single-file push: the bar tracks bytes, chunk progress goes to the log
         let pb = make_progress_bar(0, "push");
+        pb.set_style(
+            ProgressStyle::with_template(
+                "{prefix:.bold} [{bar:40.cyan/blue}] {bytes}/{total_bytes} {msg}",
+            )
+            .unwrap()
+            .progress_chars("=>-"),
+        );
         pb.set_message(format!("{}", local.display()));
 
         let pb_clone = pb.clone();
-        let progress: tcfs_sync::engine::ProgressFn = Box::new(move |done, total, msg| {
+        let byte_progress: tcfs_sync::engine::ProgressFn = Box::new(move |done, total, msg| {
             pb_clone.set_length(total);
             pb_clone.set_position(done);
             pb_clone.set_message(msg.to_string());
         });
+        let chunk_progress: tcfs_sync::engine::ProgressFn = Box::new(|done, total, _| {
+            tracing::debug!(done, total, "chunk uploaded");
+        });
 
         let rel = local
             .file_name()
             .map(|n| n.to_string_lossy().to_string())
             .unwrap_or_default();
 
-        let result = tcfs_sync::engine::upload_file_with_device(
+        let result = tcfs_sync::engine::upload_file_with_progress(
             &op,
             local,
             &remote_prefix,
-            &mut state,
-            Some(&progress),
+            &state,
+            tcfs_sync::engine::UploadProgress {
+                chunks: Some(&chunk_progress),
+                bytes: Some(&byte_progress),
+            },
             &device_id,
             Some(&rel),
             None,
+            config.sync.compress_skip_extensions.as_deref(),
+            false,
         )
         .await
         .with_context(|| format!("uploading {}", local.display()))?;
@@ -551,7 +943,10 @@ async fn cmd_push(
         } else {
             pb.finish_with_message("done".to_string());
             println!("  hash:    {}", &result.hash[..16.min(result.hash.len())]);
-            println!("  chunks:  {}", result.chunks);
+            println!(
+                "  chunks:  {} ({} new, {} deduplicated)",
+                result.chunks, result.new_chunks, result.deduped_chunks
+            );
             println!("  bytes:   {}", fmt_bytes(result.bytes));
             println!("  remote:  {}", result.remote_path);
         }
@@ -576,32 +971,200 @@ async fn cmd_push(
             pb_clone.set_message(msg.to_string());
         });
 
-        let (uploaded, skipped, bytes) = tcfs_sync::engine::push_tree_with_device(
-            &op,
-            local,
-            &remote_prefix,
-            &mut state,
-            Some(&progress),
-            &device_id,
-            Some(&collect_cfg),
-            None,
-        )
-        .await
+        let history = tcfs_sync::history::HistoryPolicy::from_config(&config.sync);
+        let stats = if transactional {
+            tcfs_sync::engine::push_tree_transactional(
+                &op,
+                local,
+                &remote_prefix,
+                &state,
+                Some(&progress),
+                &device_id,
+                Some(&collect_cfg),
+                None,
+                config.sync.push_concurrency,
+                history.as_ref(),
+            )
+            .await
+        } else {
+            tcfs_sync::engine::push_tree_with_stats(
+                &op,
+                local,
+                &remote_prefix,
+                &state,
+                Some(&progress),
+                &device_id,
+                Some(&collect_cfg),
+                None,
+                config.sync.push_concurrency,
+                history.as_ref(),
+            )
+            .await
+        }
         .with_context(|| format!("pushing tree: {}", local.display()))?;
 
         pb.finish_with_message("done".to_string());
         println!();
         println!("Push complete:");
-        println!("  uploaded: {} files ({})", uploaded, fmt_bytes(bytes));
-        println!("  skipped:  {} files (unchanged)", skipped);
-        println!("  total:    {} files", uploaded + skipped);
+        println!(
+            "  uploaded: {} files ({})",
+            stats.uploaded,
+            fmt_bytes(stats.bytes)
+        );
+        println!("  skipped:  {} files (unchanged)", stats.skipped);
+        println!("  total:    {} files", stats.uploaded + stats.skipped);
+        println!(
+            "  chunks:   {} new, {} deduplicated",
+            stats.new_chunks, stats.deduped_chunks
+        );
+        match &stats.root_hash {
+            Some(root_hash) => println!("  tree:     {root_hash}"),
+            None => println!("  tree:     not recorded (some files failed or conflicted)"),
+        }
+    } else {
+        anyhow::bail!(
+            "path not found or not a file/directory: {}",
+            local.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Classify files as they would be pushed, without writing to storage.
+///
+/// Prints each file that would upload with its size and an estimate of how
+/// many of its chunks are new versus already present remotely.
+async fn push_dry_run(
+    op: &opendal::Operator,
+    local: &Path,
+    remote_prefix: &str,
+    state: &mut tcfs_sync::state::StateCache,
+    device_id: &str,
+    collect_cfg: &tcfs_sync::engine::CollectConfig,
+) -> Result<()> {
+    let (root, files) = if local.is_file() {
+        (
+            local.parent().unwrap_or(Path::new("")).to_path_buf(),
+            vec![local.to_path_buf()],
+        )
+    } else if local.is_dir() {
+        (
+            local.to_path_buf(),
+            tcfs_sync::engine::collect_files(local, collect_cfg)?,
+        )
     } else {
         anyhow::bail!(
             "path not found or not a file/directory: {}",
             local.display()
         );
+    };
+
+    let mut would_upload = 0usize;
+    let mut unchanged = 0usize;
+    let mut bytes = 0u64;
+    let mut new_chunks = 0usize;
+    let mut dedup_chunks = 0usize;
+    let mut symlinks = 0usize;
+
+    println!();
+    println!("Dry run — nothing will be written to storage:");
+    for path in &files {
+        let rel = path.strip_prefix(&root).unwrap_or(path);
+        let rel_str = rel.to_string_lossy().replace('\\', "/");
+
+        if tcfs_sync::engine::is_symlink(path) {
+            let target = std::fs::read_link(path).unwrap_or_default();
+            symlinks += 1;
+            println!("  {:<50} -> {}", rel_str, target.display());
+            continue;
+        }
+
+        let result = tcfs_sync::engine::upload_file_with_device(
+            op,
+            path,
+            remote_prefix,
+            state,
+            None,
+            device_id,
+            Some(&rel_str),
+            None,
+            true,
+        )
+        .await
+        .with_context(|| format!("classifying {}", path.display()))?;
+
+        if result.skipped {
+            unchanged += 1;
+            continue;
+        }
+
+        would_upload += 1;
+        bytes += result.bytes;
+        new_chunks += result.new_chunks;
+        dedup_chunks += result.deduped_chunks;
+        println!(
+            "  {:<50} {:>10}  {}/{} chunks new",
+            rel_str,
+            fmt_bytes(result.bytes),
+            result.new_chunks,
+            result.chunks
+        );
     }
 
+    println!();
+    println!("Would push:");
+    println!("  upload:    {} files ({})", would_upload, fmt_bytes(bytes));
+    println!("  skip:      {} files (unchanged)", unchanged);
+    if symlinks > 0 {
+        println!("  symlinks:  {} (recorded, not followed)", symlinks);
+    }
+    println!("  chunks:    {} new, {} deduped", new_chunks, dedup_chunks);
+
+    Ok(())
+}
+
+// ── `tcfs cat` ────────────────────────────────────────────────────────────────
+
+async fn cmd_cat(
+    config: &tcfs_core::config::TcfsConfig,
+    path: &str,
+    prefix: Option<&str>,
+) -> Result<()> {
+    let op = build_operator_from_env(config)?;
+    let encryption = keychain_master_key().map(|master_key| tcfs_sync::engine::EncryptionContext {
+        master_key,
+        convergent: false,
+    });
+    let mut out = std::io::BufWriter::new(std::io::stdout());
+
+    let (prefix, manifest_path) = match prefix.map(|p| p.trim_end_matches('/')) {
+        Some(p) if path.starts_with(&format!("{p}/manifests/")) => {
+            (p.to_string(), path.to_string())
+        }
+        Some(p) => {
+            let entry = tcfs_sync::engine::resolve_index_entry(&op, p, path).await?;
+            if entry.is_packed() {
+                // Packed files are small: read their range of the pack whole
+                let rel = path.trim_start_matches('/');
+                let data = tcfs_sync::engine::read_indexed(&op, p, rel, &entry, None).await?;
+                std::io::Write::write_all(&mut out, &data)?;
+                std::io::Write::flush(&mut out)?;
+                return Ok(());
+            }
+            (p.to_string(), entry.manifest_path(p))
+        }
+        None => match path.split_once("/manifests/") {
+            Some((p, _)) => (p.to_string(), path.to_string()),
+            None => anyhow::bail!(
+                "{path} is not a manifest path; pass --prefix to cat it by relative path"
+            ),
+        },
+    };
+
+    tcfs_sync::engine::stream_file(&op, &manifest_path, &prefix, encryption.as_ref(), &mut out)
+        .await
+        .with_context(|| format!("reading {path}"))?;
     Ok(())
 }
 
@@ -613,10 +1176,52 @@ async fn cmd_pull(
     local: Option<&Path>,
     prefix: Option<&str>,
     state_override: Option<&Path>,
+    force: bool,
 ) -> Result<()> {
     let op = build_operator_from_env(config)?;
     let device_id = load_device_id(config);
 
+    // A glob selects every matching indexed file under --prefix
+    if let Some(p) = prefix.filter(|_| manifest_path.contains(['*', '?', '['])) {
+        let dest = local.unwrap_or(Path::new("."));
+        println!("Pulling {p}/{manifest_path} → {}", dest.display());
+
+        let pb = make_progress_bar(0, "pull");
+        let pb_clone = pb.clone();
+        let progress: tcfs_sync::engine::ProgressFn = Box::new(move |done, total, msg| {
+            pb_clone.set_length(total);
+            pb_clone.set_position(done);
+            pb_clone.set_message(msg.to_string());
+        });
+        let (files, bytes) = tcfs_sync::engine::pull_matching(
+            &op,
+            p,
+            manifest_path,
+            dest,
+            Some(&progress),
+            config.sync.mode_umask,
+        )
+        .await
+        .with_context(|| format!("pulling {manifest_path} from {p}"))?;
+
+        pb.finish_with_message("done".to_string());
+        println!();
+        println!("Downloaded:");
+        println!("  files:  {files}");
+        println!("  bytes:  {}", fmt_bytes(bytes));
+        return Ok(());
+    }
+
+    // A logical path needs --prefix and is resolved through the index;
+    // anything under {prefix}/manifests/ is used as-is
+    let by_rel_path = match prefix {
+        Some(p) => !manifest_path.starts_with(&format!("{}/manifests/", p.trim_end_matches('/'))),
+        None if manifest_path.contains("/manifests/") => false,
+        None => anyhow::bail!(
+            "{manifest_path} is not a manifest path; pass --prefix to pull it by relative path"
+        ),
+    };
+
     // Derive the remote prefix from the manifest path if not provided
     // e.g. "mydata/manifests/abc123" → prefix = "mydata"
     let remote_prefix = prefix
@@ -629,11 +1234,37 @@ async fn cmd_pull(
                 .to_string()
         });
 
-    // Default local path: current dir + manifest hash (last path component)
-    let hash_basename = manifest_path.split('/').next_back().unwrap_or("downloaded");
-    let local_path = local
-        .map(|p| p.to_path_buf())
-        .unwrap_or_else(|| PathBuf::from(hash_basename));
+    // Open state cache for vclock merge during pull; unless forced, it also
+    // stops the download from overwriting unpushed local edits
+    let state_path = resolve_state_path(config, state_override);
+    let mut state = tcfs_sync::state::StateCache::open(&state_path)
+        .with_context(|| format!("opening state cache: {}", state_path.display()))?;
+    state.set_guard_local_edits(!force);
+    state.set_read_mirrors(read_mirrors_from_env(config)?);
+
+    // Resolved through the index, a packed file is read from its pack
+    let (rel_path, entry, manifest_path) = if by_rel_path {
+        let entry =
+            tcfs_sync::engine::resolve_index_entry(&op, &remote_prefix, manifest_path).await?;
+        let remote_path = if entry.is_packed() {
+            format!("{manifest_path} (packed)")
+        } else {
+            entry.manifest_path(&remote_prefix)
+        };
+        let rel = manifest_path.trim_start_matches('/');
+        (Some(rel), Some(entry), remote_path)
+    } else {
+        (None, None, manifest_path.to_string())
+    };
+    let manifest_path = manifest_path.as_str();
+
+    // Default local path: the relative path under the current dir, or the
+    // manifest hash (last path component)
+    let local_path = match (local, rel_path) {
+        (Some(p), _) => p.to_path_buf(),
+        (None, Some(rel)) => tcfs_core::paths::normalize_rel_path(rel)?,
+        (None, None) => PathBuf::from(manifest_path.split('/').next_back().unwrap_or("downloaded")),
+    };
 
     println!("Pulling {} → {}", manifest_path, local_path.display(),);
 
@@ -647,88 +1278,748 @@ async fn cmd_pull(
         pb_clone.set_message(msg.to_string());
     });
 
-    // Open state cache for vclock merge during pull
+    let umask = config.sync.mode_umask;
+    let result = match (rel_path, &entry) {
+        (Some(rel), Some(entry)) => {
+            tcfs_sync::engine::download_indexed(
+                &op,
+                &remote_prefix,
+                rel,
+                entry,
+                &local_path,
+                tcfs_sync::engine::DownloadProgress {
+                    chunks: Some(&progress),
+                    bytes: None,
+                },
+                &device_id,
+                Some(&state),
+                None,
+                umask,
+            )
+            .await
+        }
+        _ => {
+            tcfs_sync::engine::download_file_with_device(
+                &op,
+                manifest_path,
+                &local_path,
+                &remote_prefix,
+                Some(&progress),
+                &device_id,
+                Some(&state),
+                None,
+                tcfs_sync::engine::RestoreMode::manifest(umask),
+            )
+            .await
+        }
+    }
+    .with_context(|| format!("downloading {}", manifest_path))?;
+
+    state.flush().context("flushing state cache")?;
+
+    pb.finish_with_message("done".to_string());
+    println!();
+    println!("Downloaded:");
+    println!("  local:  {}", result.local_path.display());
+    println!("  bytes:  {}", fmt_bytes(result.bytes));
+
+    Ok(())
+}
+
+// ── `tcfs sync-status` ────────────────────────────────────────────────────────
+
+fn cmd_sync_status(
+    config: &tcfs_core::config::TcfsConfig,
+    path: Option<&Path>,
+    state_override: Option<&Path>,
+    tree: bool,
+) -> Result<()> {
     let state_path = resolve_state_path(config, state_override);
-    let mut state = tcfs_sync::state::StateCache::open(&state_path)
+    let state = tcfs_sync::state::StateCache::open(&state_path)
         .with_context(|| format!("opening state cache: {}", state_path.display()))?;
 
-    let result = tcfs_sync::engine::download_file_with_device(
+    println!("State cache: {}", state_path.display());
+    println!("Tracked files: {}", state.len());
+
+    if tree {
+        let root = std::fs::canonicalize(path.unwrap_or(Path::new("."))).with_context(|| {
+            format!(
+                "resolving path: {}",
+                path.unwrap_or(Path::new(".")).display()
+            )
+        })?;
+        return print_tree_status(config, &state, &root);
+    }
+
+    if let Some(p) = path {
+        let canonical =
+            std::fs::canonicalize(p).with_context(|| format!("resolving path: {}", p.display()))?;
+
+        match state.get(&canonical) {
+            Some(entry) => {
+                println!();
+                println!("File: {}", canonical.display());
+                println!(
+                    "  hash:       {}",
+                    &entry.blake3[..16.min(entry.blake3.len())]
+                );
+                println!("  size:       {}", fmt_bytes(entry.size));
+                println!("  chunks:     {}", entry.chunk_count);
+                println!("  remote:     {}", entry.remote_path);
+                println!("  last sync:  {} seconds ago", {
+                    let now = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs();
+                    now.saturating_sub(entry.last_synced)
+                });
+
+                // Check if it needs re-sync
+                match state.needs_sync(&canonical)? {
+                    None => println!("  status:     up to date"),
+                    Some(reason) => println!("  status:     needs sync ({reason})"),
+                }
+            }
+            None => {
+                println!();
+                println!(
+                    "File: {} — not in sync state (never pushed)",
+                    canonical.display()
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_tree_status(
+    config: &tcfs_core::config::TcfsConfig,
+    state: &tcfs_sync::state::StateCache,
+    root: &Path,
+) -> Result<()> {
+    use tcfs_sync::engine::FileSyncStatus;
+
+    anyhow::ensure!(
+        root.is_dir(),
+        "--tree expects a directory: {}",
+        root.display()
+    );
+    let rows = tcfs_sync::engine::tree_status(root, state, &collect_config_from_sync(config))?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    println!();
+    println!("{:<12} {:>10}  PATH", "STATUS", "LAST SYNC");
+    let (mut synced, mut changed, mut untracked) = (0usize, 0usize, 0usize);
+    for row in &rows {
+        let status = match &row.status {
+            FileSyncStatus::Synced => {
+                synced += 1;
+                "synced"
+            }
+            FileSyncStatus::NeedsSync(_) => {
+                changed += 1;
+                "needs-sync"
+            }
+            FileSyncStatus::Untracked => {
+                untracked += 1;
+                "untracked"
+            }
+        };
+        let age = row
+            .last_synced
+            .map(|t| fmt_age(now.saturating_sub(t)))
+            .unwrap_or_else(|| "-".into());
+        let rel = row.path.strip_prefix(root).unwrap_or(&row.path);
+        println!("{status:<12} {age:>10}  {}", rel.display());
+    }
+
+    println!();
+    println!("{synced} synced, {changed} need sync, {untracked} untracked");
+    Ok(())
+}
+
+/// Compact age for tables: `42s ago`, `7m ago`, `3h ago`, `12d ago`.
+fn fmt_age(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{secs}s ago"),
+        60..3600 => format!("{}m ago", secs / 60),
+        3600..86400 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+// ── `tcfs history` / `tcfs restore` ──────────────────────────────────────────
+
+async fn cmd_history(
+    config: &tcfs_core::config::TcfsConfig,
+    rel_path: &str,
+    prefix: &str,
+) -> Result<()> {
+    let op = build_operator_from_env(config)?;
+    let versions = tcfs_sync::history::list_versions(&op, prefix, rel_path).await?;
+
+    if versions.is_empty() {
+        println!("No history recorded for {rel_path}");
+        if !config.sync.keep_history {
+            println!("  (sync.keep_history is off; enable it to record versions on push)");
+        }
+        return Ok(());
+    }
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    println!("{:<12} {:>10} {:>10}  HASH", "TIMESTAMP", "AGE", "SIZE");
+    for v in versions.iter().rev() {
+        println!(
+            "{:<12} {:>10} {:>10}  {}",
+            v.timestamp,
+            fmt_age(now.saturating_sub(v.timestamp)),
+            fmt_bytes(v.size),
+            &v.file_hash[..16.min(v.file_hash.len())]
+        );
+    }
+    println!();
+    println!("Restore with: tcfs restore {rel_path} --prefix {prefix} --at <TIMESTAMP>");
+    Ok(())
+}
+
+async fn cmd_restore(
+    config: &tcfs_core::config::TcfsConfig,
+    rel_path: &str,
+    at: u64,
+    local: Option<&Path>,
+    prefix: &str,
+) -> Result<()> {
+    let op = build_operator_from_env(config)?;
+    let local_path = match local {
+        Some(p) => p.to_path_buf(),
+        None => tcfs_core::paths::normalize_rel_path(rel_path.trim_start_matches('/'))?,
+    };
+
+    let (version, result) =
+        tcfs_sync::history::restore_version(&op, prefix, rel_path, at, &local_path).await?;
+
+    println!("Restored {rel_path} as of {}:", version.timestamp);
+    println!("  local:  {}", result.local_path.display());
+    println!(
+        "  hash:   {}",
+        &version.file_hash[..16.min(version.file_hash.len())]
+    );
+    println!("  bytes:  {}", fmt_bytes(result.bytes));
+    println!("  (run `tcfs push` to make this the current version)");
+    Ok(())
+}
+
+// ── `tcfs prune-history` ──────────────────────────────────────────────────────
+
+/// Unreferenced chunks are deleted by a prune at least this long after the
+/// one that first found them unreferenced, so a push running elsewhere does
+/// not lose chunks its manifest is about to list.
+const PRUNE_CHUNK_GRACE_SECS: u64 = 3600;
+
+async fn cmd_prune_history(
+    config: &tcfs_core::config::TcfsConfig,
+    prefix: &str,
+    keep: Option<usize>,
+    older_than: Option<u64>,
+) -> Result<()> {
+    let op = build_operator_from_env(config)?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let policy = tcfs_sync::engine::PruneHistory {
+        keep,
+        older_than,
+        chunk_grace_secs: PRUNE_CHUNK_GRACE_SECS,
+    };
+    let stats = tcfs_sync::engine::prune_history(&op, prefix, &policy, now)
+        .await
+        .with_context(|| format!("pruning history under {prefix}"))?;
+
+    println!("Pruned history under {prefix}:");
+    println!("  versions:   {}", stats.pointers);
+    println!("  manifests:  {}", stats.manifests);
+    println!(
+        "  chunks:     {} ({} reclaimed)",
+        stats.chunks,
+        fmt_bytes(stats.bytes)
+    );
+    Ok(())
+}
+
+async fn cmd_stats(config: &tcfs_core::config::TcfsConfig, prefix: &str, top: usize) -> Result<()> {
+    let op = build_operator_from_env(config)?;
+    let stats = tcfs_sync::engine::prefix_stats(&op, prefix, top)
+        .await
+        .with_context(|| format!("reading stats under {prefix}"))?;
+
+    println!("Stats for {prefix}:");
+    println!(
+        "  files:        {} ({})",
+        stats.manifests,
+        fmt_bytes(stats.logical_bytes)
+    );
+    println!(
+        "  chunks:       {} unique, {} references",
+        stats.unique_chunks, stats.chunk_refs
+    );
+    if stats.missing_chunks > 0 {
+        println!("  missing:      {} chunks", stats.missing_chunks);
+    }
+    println!(
+        "  stored:       {} ({} before dedup)",
+        fmt_bytes(stats.unique_bytes),
+        fmt_bytes(stats.referenced_bytes)
+    );
+    println!("  dedup ratio:  {:.2}x", stats.dedup_ratio());
+    println!(
+        "  chunk size:   {} average, {} median",
+        fmt_bytes(stats.average_chunk()),
+        fmt_bytes(stats.median_chunk)
+    );
+
+    if !stats.histogram.is_empty() {
+        println!();
+        println!("Chunk sizes (stored):");
+        let widest = stats.histogram.iter().map(|(_, n)| *n).max().unwrap_or(1);
+        for (bound, count) in &stats.histogram {
+            let bar = "#".repeat((count * 40).div_ceil(widest));
+            println!("  <= {:>10}  {count:>8}  {bar}", fmt_bytes(*bound));
+        }
+    }
+
+    if !stats.largest.is_empty() {
+        println!();
+        println!("Largest files:");
+        for (name, size) in &stats.largest {
+            println!("  {:>10}  {name}", fmt_bytes(*size));
+        }
+    }
+    Ok(())
+}
+
+/// Parse an age like `90d`, `12h`, `30m`, `2w` or plain seconds.
+fn parse_age(s: &str) -> std::result::Result<u64, String> {
+    let (num, unit) = s
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or((s, ""), |i| s.split_at(i));
+    let n: u64 = num
+        .parse()
+        .map_err(|_| format!("invalid age {s:?}: expected e.g. 90d, 12h, 30m"))?;
+    let scale = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        "w" => 7 * 86_400,
+        _ => return Err(format!("invalid age unit {unit:?}: use s, m, h, d or w")),
+    };
+    n.checked_mul(scale)
+        .ok_or_else(|| format!("age {s:?} is too large"))
+}
+
+// ── `tcfs pull-tree` ──────────────────────────────────────────────────────────
+
+async fn cmd_pull_tree(
+    config: &tcfs_core::config::TcfsConfig,
+    root_hash: &str,
+    local: &Path,
+    prefix: &str,
+) -> Result<()> {
+    let op = build_operator_from_env(config)?;
+    let stats = tcfs_sync::tree::pull_tree(&op, prefix, root_hash, local, None)
+        .await
+        .with_context(|| format!("pulling tree {root_hash}"))?;
+
+    println!("Restored tree {}:", &root_hash[..16.min(root_hash.len())]);
+    println!("  local:  {}", local.display());
+    println!("  files:  {}", stats.files);
+    println!("  bytes:  {}", fmt_bytes(stats.bytes));
+    Ok(())
+}
+
+/// Pull the files under `prefix` written after `since` (a unix timestamp,
+/// or `last`) into `local`, then record when this pull started.
+async fn cmd_pull_since(
+    config: &tcfs_core::config::TcfsConfig,
+    prefix: &str,
+    local: &Path,
+    since: &str,
+) -> Result<()> {
+    let record = last_pulls_path();
+    let mut pulls = read_last_pulls(&record);
+    let key = last_pull_key(prefix, local);
+    let since = match since {
+        "last" => match pulls.get(&key) {
+            Some(&ts) => ts,
+            None => {
+                println!(
+                    "No earlier pull of {prefix} into {} recorded; pulling everything",
+                    local.display()
+                );
+                0
+            }
+        },
+        ts => ts.parse().with_context(|| {
+            format!("invalid --since {ts:?}: expected a unix timestamp or `last`")
+        })?,
+    };
+
+    let op = build_operator_from_env(config)?;
+    let started = now_epoch();
+    println!(
+        "Pulling {prefix} changes since {} → {}",
+        format_epoch(since),
+        local.display()
+    );
+
+    let pb = make_progress_bar(0, "pull");
+    let pb_clone = pb.clone();
+    let progress: tcfs_sync::engine::ProgressFn = Box::new(move |done, total, msg| {
+        pb_clone.set_length(total);
+        pb_clone.set_position(done);
+        pb_clone.set_message(msg.to_string());
+    });
+    let (files, dirs, bytes) = tcfs_sync::engine::pull_tree_since(
         &op,
-        manifest_path,
-        &local_path,
-        &remote_prefix,
+        prefix,
+        local,
+        since,
         Some(&progress),
-        &device_id,
-        Some(&mut state),
-        None,
+        config.sync.mode_umask,
     )
     .await
-    .with_context(|| format!("downloading {}", manifest_path))?;
+    .with_context(|| format!("pulling {prefix} since {since}"))?;
+    pb.finish_with_message("done".to_string());
+
+    pulls.insert(key, started);
+    if let Err(e) = write_last_pulls(&record, &pulls) {
+        eprintln!("warning: could not record pull time: {e:#}");
+    }
+
+    println!();
+    println!("Downloaded:");
+    println!("  files:  {files}");
+    println!("  dirs:   {dirs}");
+    println!("  bytes:  {}", fmt_bytes(bytes));
+    println!(
+        "  next:   tcfs pull-tree {prefix} {} --since {started}",
+        local.display()
+    );
+    Ok(())
+}
+
+/// Start times of successful `pull-tree --since` runs, by prefix and
+/// destination.
+fn last_pulls_path() -> PathBuf {
+    dirs_cache_path().join("last-pulls.json")
+}
+
+fn last_pull_key(prefix: &str, local: &Path) -> String {
+    let local = std::fs::canonicalize(local).unwrap_or_else(|_| local.to_path_buf());
+    format!("{}:{}", prefix.trim_matches('/'), local.display())
+}
+
+fn read_last_pulls(path: &Path) -> std::collections::BTreeMap<String, u64> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn write_last_pulls(path: &Path, pulls: &std::collections::BTreeMap<String, u64>) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("creating cache dir: {}", parent.display()))?;
+    }
+    let json = serde_json::to_string_pretty(pulls).context("serializing pull times")?;
+    std::fs::write(path, json).with_context(|| format!("writing {}", path.display()))
+}
+
+// ── `tcfs snapshot` ───────────────────────────────────────────────────────────
+
+async fn cmd_snapshot_create(config: &tcfs_core::config::TcfsConfig, prefix: &str) -> Result<()> {
+    let op = build_operator_from_env(config)?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let snapshot =
+        tcfs_sync::snapshot::create_snapshot(&op, prefix, &load_device_id(config), now).await?;
+
+    println!("Snapshot {} of {prefix}:", snapshot.id);
+    println!("  taken:  {}", format_epoch(snapshot.timestamp));
+    println!("  files:  {}", snapshot.files);
+    println!("  tree:   {}", snapshot.root_hash);
+    Ok(())
+}
+
+async fn cmd_snapshot_list(config: &tcfs_core::config::TcfsConfig, prefix: &str) -> Result<()> {
+    let op = build_operator_from_env(config)?;
+    let snapshots = tcfs_sync::snapshot::list_snapshots(&op, prefix).await?;
+    if snapshots.is_empty() {
+        println!("No snapshots of {prefix}");
+        return Ok(());
+    }
+
+    println!("{:<26} {:<24} {:>8}  DEVICE", "ID", "TAKEN", "FILES");
+    for s in &snapshots {
+        println!(
+            "{:<26} {:<24} {:>8}  {}",
+            s.id,
+            format_epoch(s.timestamp),
+            s.files,
+            if s.written_by.is_empty() {
+                "-"
+            } else {
+                &s.written_by
+            }
+        );
+    }
+    Ok(())
+}
+
+async fn cmd_snapshot_restore(
+    config: &tcfs_core::config::TcfsConfig,
+    prefix: &str,
+    snapshot_id: &str,
+    dest: &Path,
+) -> Result<()> {
+    let op = build_operator_from_env(config)?;
+    let (snapshot, stats) =
+        tcfs_sync::snapshot::restore_snapshot(&op, prefix, snapshot_id, dest, None).await?;
+
+    println!(
+        "Restored snapshot {} ({}):",
+        snapshot.id,
+        format_epoch(snapshot.timestamp)
+    );
+    println!("  local:  {}", dest.display());
+    println!("  files:  {}", stats.files);
+    println!("  bytes:  {}", fmt_bytes(stats.bytes));
+    Ok(())
+}
+
+// ── `tcfs export` / `tcfs import` ─────────────────────────────────────────────
+
+/// Whether an archive path names a zstd-compressed stream.
+fn is_zstd_archive(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "zst")
+}
+
+async fn cmd_export(
+    config: &tcfs_core::config::TcfsConfig,
+    prefix: &str,
+    out: &Path,
+) -> Result<()> {
+    let op = build_operator_from_env(config)?;
+    let prefix = prefix.trim_end_matches('/');
+    let encryption = keychain_master_key().map(|master_key| tcfs_sync::engine::EncryptionContext {
+        master_key,
+        convergent: false,
+    });
+
+    // Write beside the destination and rename, so a failed export leaves no
+    // truncated archive behind
+    let tmp = tcfs_sync::engine::download_tmp_path(out);
+    let stats =
+        match write_export(&op, prefix, &tmp, is_zstd_archive(out), encryption.as_ref()).await {
+            Ok(stats) => stats,
+            Err(e) => {
+                let _ = std::fs::remove_file(&tmp);
+                return Err(e).with_context(|| format!("exporting {prefix}"));
+            }
+        };
+    std::fs::rename(&tmp, out)
+        .with_context(|| format!("moving archive into place: {}", out.display()))?;
+
+    println!("Exported {prefix} → {}:", out.display());
+    println!("  files:    {}", stats.files);
+    println!("  symlinks: {}", stats.symlinks);
+    println!("  dirs:     {}", stats.dirs);
+    println!("  bytes:    {}", fmt_bytes(stats.bytes));
+    Ok(())
+}
+
+async fn write_export(
+    op: &opendal::Operator,
+    prefix: &str,
+    path: &Path,
+    zstd: bool,
+    encryption: Option<&tcfs_sync::engine::EncryptionContext>,
+) -> Result<tcfs_sync::archive::ArchiveStats> {
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("creating archive: {}", path.display()))?;
+    let mut writer = std::io::BufWriter::new(file);
+    let stats = if zstd {
+        let mut encoder = zstd::Encoder::new(&mut writer, 3).context("starting zstd stream")?;
+        let stats = tcfs_sync::archive::export_prefix(op, prefix, &mut encoder, encryption).await?;
+        encoder.finish().context("finishing zstd stream")?;
+        stats
+    } else {
+        tcfs_sync::archive::export_prefix(op, prefix, &mut writer, encryption).await?
+    };
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())
+        .and_then(|file| file.sync_all())
+        .with_context(|| format!("writing archive: {}", path.display()))?;
+    Ok(stats)
+}
+
+async fn cmd_import(
+    config: &tcfs_core::config::TcfsConfig,
+    archive: &Path,
+    prefix: &str,
+    state_override: Option<&Path>,
+) -> Result<()> {
+    let op = build_operator_from_env(config)?;
+    let prefix = prefix.trim_end_matches('/');
+    let state_path = resolve_state_path(config, state_override);
+    let mut state = tcfs_sync::state::StateCache::open(&state_path)
+        .with_context(|| format!("opening state cache: {}", state_path.display()))?;
+    state.enable_chunk_filter(config.sync.chunk_filter_fp_rate);
+    state.set_signing_key(load_signing_key(config));
+    state.set_read_only(config.sync.is_read_only_prefix(prefix));
+    state.set_quota(config.storage.quota_bytes);
+    state.set_chunk_shard_depth(config.storage.chunk_shard_depth);
+    state.set_cache_control(config.storage.cache_control.clone());
+    state.set_transfer_concurrency(
+        config.sync.transfer_concurrency_min,
+        config.sync.transfer_concurrency_max,
+    );
+    state.set_max_clock_skew(config.sync.max_clock_skew_secs);
+    let device_id = load_device_id(config);
 
+    let file = std::fs::File::open(archive)
+        .with_context(|| format!("opening archive: {}", archive.display()))?;
+    let mut reader: Box<dyn std::io::Read> = if is_zstd_archive(archive) {
+        Box::new(zstd::Decoder::new(file).context("starting zstd stream")?)
+    } else {
+        Box::new(std::io::BufReader::new(file))
+    };
+    let stats =
+        tcfs_sync::archive::import_archive(&op, &mut reader, prefix, &state, &device_id, None)
+            .await
+            .with_context(|| format!("importing {}", archive.display()))?;
     state.flush().context("flushing state cache")?;
 
-    pb.finish_with_message("done".to_string());
-    println!();
-    println!("Downloaded:");
-    println!("  local:  {}", result.local_path.display());
-    println!("  bytes:  {}", fmt_bytes(result.bytes));
-
+    println!("Imported {} → {prefix}:", archive.display());
+    println!("  files:    {}", stats.files);
+    println!("  symlinks: {}", stats.symlinks);
+    println!("  dirs:     {}", stats.dirs);
+    println!("  bytes:    {}", fmt_bytes(stats.bytes));
     Ok(())
 }
 
-// ── `tcfs sync-status` ────────────────────────────────────────────────────────
+// ── `tcfs verify` ─────────────────────────────────────────────────────────────
 
-fn cmd_sync_status(
+async fn cmd_verify(
     config: &tcfs_core::config::TcfsConfig,
-    path: Option<&Path>,
+    prefix: &str,
+    repair: bool,
+    local: Option<&Path>,
     state_override: Option<&Path>,
 ) -> Result<()> {
+    let op = build_operator_from_env(config)?;
+    let prefix = prefix.trim_end_matches('/');
+    let items = tcfs_sync::engine::verify_prefix(&op, prefix).await?;
+
+    if items.is_empty() {
+        println!("All chunks under {prefix} verified");
+        return Ok(());
+    }
+
+    // Group damaged chunks by the manifest (file) they belong to
+    let mut by_manifest: std::collections::BTreeMap<&str, Vec<&tcfs_sync::engine::VerifyItem>> =
+        std::collections::BTreeMap::new();
+    for item in &items {
+        by_manifest.entry(&item.manifest).or_default().push(item);
+    }
+
     let state_path = resolve_state_path(config, state_override);
     let state = tcfs_sync::state::StateCache::open(&state_path)
         .with_context(|| format!("opening state cache: {}", state_path.display()))?;
+    let entries = state.entries();
+
+    let mut unrepairable = 0usize;
+    for (manifest, damaged) in &by_manifest {
+        let name = damaged[0]
+            .rel_path
+            .clone()
+            .unwrap_or_else(|| manifest.to_string());
+        for item in damaged {
+            let problem = match &item.problem {
+                tcfs_sync::engine::ChunkProblem::Missing => "missing".to_string(),
+                tcfs_sync::engine::ChunkProblem::HashMismatch { actual } => {
+                    format!("hash mismatch (stored {})", &actual[..16.min(actual.len())])
+                }
+            };
+            println!(
+                "{name}: chunk {} {}: {problem}",
+                item.chunk_index,
+                &item.chunk_hash[..16.min(item.chunk_hash.len())]
+            );
+        }
+        if !repair {
+            continue;
+        }
 
-    println!("State cache: {}", state_path.display());
-    println!("Tracked files: {}", state.len());
-
-    if let Some(p) = path {
-        let canonical =
-            std::fs::canonicalize(p).with_context(|| format!("resolving path: {}", p.display()))?;
-
-        match state.get(&canonical) {
-            Some(entry) => {
-                println!();
-                println!("File: {}", canonical.display());
-                println!(
-                    "  hash:       {}",
-                    &entry.blake3[..16.min(entry.blake3.len())]
-                );
-                println!("  size:       {}", fmt_bytes(entry.size));
-                println!("  chunks:     {}", entry.chunk_count);
-                println!("  remote:     {}", entry.remote_path);
-                println!("  last sync:  {} seconds ago", {
-                    let now = std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap_or_default()
-                        .as_secs();
-                    now.saturating_sub(entry.last_synced)
-                });
+        // Prefer the tracked local file for this manifest, then --local/<rel_path>
+        let local_copy = entries
+            .iter()
+            .find(|(_, s)| s.remote_path == *manifest)
+            .map(|(path, _)| PathBuf::from(path))
+            .filter(|p| p.is_file())
+            .or_else(|| {
+                let rel = damaged[0].rel_path.as_deref()?;
+                let path = local?.join(tcfs_core::paths::normalize_rel_path(rel).ok()?);
+                path.is_file().then_some(path)
+            });
+        let Some(local_copy) = local_copy else {
+            println!("  {name}: unrepairable (no local copy found)");
+            unrepairable += 1;
+            continue;
+        };
 
-                // Check if it needs re-sync
-                match state.needs_sync(&canonical)? {
-                    None => println!("  status:     up to date"),
-                    Some(reason) => println!("  status:     needs sync ({reason})"),
-                }
+        let mut failed = None;
+        for item in damaged {
+            if let Err(e) =
+                tcfs_sync::engine::repair_chunk(&op, prefix, item, &local_copy, None).await
+            {
+                failed = Some(e);
+                break;
             }
-            None => {
-                println!();
-                println!(
-                    "File: {} — not in sync state (never pushed)",
-                    canonical.display()
-                );
+        }
+        match failed {
+            None => println!("  {name}: repaired from {}", local_copy.display()),
+            Some(e) => {
+                println!("  {name}: unrepairable ({e:#})");
+                unrepairable += 1;
             }
         }
     }
 
+    println!();
+    println!(
+        "{} damaged chunk(s) in {} file(s)",
+        items.len(),
+        by_manifest.len()
+    );
+    if !repair {
+        println!("Repair from local copies with: tcfs verify {prefix} --repair");
+        anyhow::bail!("verification found damaged chunks");
+    }
+    if unrepairable > 0 {
+        anyhow::bail!("{unrepairable} file(s) could not be repaired");
+    }
     Ok(())
 }
 
@@ -747,18 +2038,10 @@ async fn cmd_status(config: &tcfs_core::config::TcfsConfig) -> Result<()> {
     let mut client = connect_daemon(socket).await?;
 
     // Daemon status
-    let status = client
-        .status(tonic::Request::new(StatusRequest {}))
-        .await
-        .context("status RPC failed")?
-        .into_inner();
+    let status = client.status().await?;
 
     // Credential status
-    let creds = client
-        .credential_status(tonic::Request::new(Empty {}))
-        .await
-        .context("credential_status RPC failed")?
-        .into_inner();
+    let creds = client.credential_status().await?;
 
     let uptime = format_uptime(status.uptime_secs);
 
@@ -791,6 +2074,20 @@ async fn cmd_status(config: &tcfs_core::config::TcfsConfig) -> Result<()> {
         }
     );
     println!("  active mounts: {}", status.active_mounts);
+    if status.total_logical_bytes > 0 {
+        println!(
+            "  logical:       {} (dedup {:.2}x)",
+            fmt_bytes(status.total_logical_bytes),
+            status.dedup_ratio
+        );
+    }
+    if status.total_stored_bytes > 0 {
+        println!(
+            "  stored:        {} (compression {:.2}x)",
+            fmt_bytes(status.total_stored_bytes),
+            status.compression_ratio
+        );
+    }
     println!(
         "  credentials:   {} (source: {})",
         if creds.loaded { "loaded" } else { "NOT LOADED" },
@@ -801,7 +2098,7 @@ async fn cmd_status(config: &tcfs_core::config::TcfsConfig) -> Result<()> {
     }
 
     // Check for newer version (non-blocking, best-effort)
-    check_for_update(&status.version);
+    check_for_update(&status.version).await;
 
     Ok(())
 }
@@ -810,7 +2107,10 @@ async fn cmd_status(config: &tcfs_core::config::TcfsConfig) -> Result<()> {
 ///
 /// Results are cached in ~/.cache/tcfs/version-check.json for 24 hours
 /// to avoid hitting the API on every invocation. Failures are silently ignored.
-fn check_for_update(current_version: &str) {
+async fn check_for_update(current_version: &str) {
+    if !cfg!(feature = "update-check") {
+        return;
+    }
     let cache_dir = dirs_cache_path();
     let cache_file = cache_dir.join("version-check.json");
 
@@ -826,7 +2126,7 @@ fn check_for_update(current_version: &str) {
     }
 
     // Fetch the latest release tag from GitHub
-    let latest = fetch_latest_version();
+    let latest = fetch_latest_version(RELEASES_LATEST_URL).await;
 
     // Cache the result (even on failure, to avoid hammering the API)
     let entry = VersionCacheEntry {
@@ -841,6 +2141,10 @@ fn check_for_update(current_version: &str) {
 }
 
 const VERSION_CHECK_TTL_SECS: u64 = 86400; // 24 hours
+#[cfg(feature = "update-check")]
+const VERSION_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+const RELEASES_LATEST_URL: &str =
+    "https://api.github.com/repos/tinyland-inc/tummycrypt/releases/latest";
 
 #[derive(serde::Serialize, serde::Deserialize)]
 struct VersionCacheEntry {
@@ -877,78 +2181,279 @@ fn write_version_cache(path: &Path, entry: &VersionCacheEntry) -> Result<()> {
     Ok(())
 }
 
-/// Fetch the latest release version from GitHub using curl.
-/// Returns None on any error (network, parse, missing curl, etc.).
-fn fetch_latest_version() -> Option<String> {
-    let output = std::process::Command::new("curl")
-        .args([
-            "-fsSL",
-            "--max-time",
-            "5",
-            "-H",
-            "Accept: application/vnd.github+json",
-            "https://api.github.com/repos/tinyland-inc/tummycrypt/releases/latest",
-        ])
-        .output()
+/// Fetch the latest release version from the GitHub releases API at `url`.
+/// Returns None on any error (network, timeout, HTTP status, parse, etc.).
+#[cfg(feature = "update-check")]
+async fn fetch_latest_version(url: &str) -> Option<String> {
+    let client = reqwest::Client::builder()
+        .timeout(VERSION_CHECK_TIMEOUT)
+        .user_agent(concat!("tcfs/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .ok()?;
+    let body = client
+        .get(url)
+        .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+        .send()
+        .await
+        .ok()?
+        .error_for_status()
+        .ok()?
+        .text()
+        .await
         .ok()?;
 
-    if !output.status.success() {
-        return None;
-    }
-
-    let body = String::from_utf8(output.stdout).ok()?;
     let json: serde_json::Value = serde_json::from_str(&body).ok()?;
     let tag = json.get("tag_name")?.as_str()?;
     Some(tag.strip_prefix('v').unwrap_or(tag).to_string())
 }
 
-/// Compare semver-style versions and print a notice if a newer one is available.
+/// Built without `update-check`: there is no HTTP client, so never any news.
+#[cfg(not(feature = "update-check"))]
+async fn fetch_latest_version(_url: &str) -> Option<String> {
+    None
+}
+
+/// Whether release `latest` takes precedence over the running `current`.
+///
+/// Both must be valid semver (a leading `v` is accepted); pre-releases sort
+/// below their release and build metadata is ignored. Anything unparseable,
+/// such as a dev build's version, never reports an update.
+fn update_available(current: &str, latest: &str) -> bool {
+    let parse = |v: &str| semver::Version::parse(v.trim().trim_start_matches('v')).ok();
+    match (parse(current), parse(latest)) {
+        (Some(cur), Some(lat)) => lat.cmp_precedence(&cur).is_gt(),
+        _ => false,
+    }
+}
+
+/// Print a notice if `latest` is newer than `current`.
 fn print_update_notice(current: &str, latest: &str) {
-    // Simple semver comparison: split on '.' and compare numerically
-    let parse = |v: &str| -> Option<(u64, u64, u64)> {
-        let parts: Vec<&str> = v.split('.').collect();
-        if parts.len() >= 3 {
-            Some((
-                parts[0].parse().ok()?,
-                parts[1].parse().ok()?,
-                parts[2].parse().ok()?,
-            ))
-        } else {
-            None
-        }
-    };
+    if update_available(current, latest) {
+        println!();
+        println!(
+            "  A newer version (v{}) is available. You are running v{}.",
+            latest, current
+        );
+        println!("  Update: curl -fsSL https://github.com/tinyland-inc/tummycrypt/releases/latest/download/install.sh | sh");
+    }
+}
 
-    if let (Some(cur), Some(lat)) = (parse(current), parse(latest)) {
-        if lat > cur {
-            println!();
-            println!(
-                "  A newer version (v{}) is available. You are running v{}.",
-                latest, current
-            );
-            println!("  Update: curl -fsSL https://github.com/tinyland-inc/tummycrypt/releases/latest/download/install.sh | sh");
+// ── `tcfs reload` ─────────────────────────────────────────────────────────────
+
+#[cfg(unix)]
+async fn cmd_reload(config: &tcfs_core::config::TcfsConfig) -> Result<()> {
+    let socket = &config.daemon.socket;
+    if !socket.exists() {
+        anyhow::bail!(
+            "tcfsd socket not found at {} — is tcfsd running?",
+            socket.display()
+        );
+    }
+
+    let mut client = connect_daemon(socket).await?;
+    let reply = client
+        .raw()
+        .reload(tonic::Request::new(ReloadRequest {}))
+        .await
+        .context("reload RPC failed")?
+        .into_inner();
+
+    if !reply.success {
+        anyhow::bail!("tcfsd rejected the new config: {}", reply.error);
+    }
+    if reply.changed.is_empty() && reply.restart_required.is_empty() {
+        println!("tcfsd: config reloaded (no changes)");
+    } else if !reply.changed.is_empty() {
+        println!("tcfsd: config reloaded, changed:");
+        for field in &reply.changed {
+            println!("  {field}");
         }
     }
+    if !reply.restart_required.is_empty() {
+        println!("tcfsd: restart to apply:");
+        for field in &reply.restart_required {
+            println!("  {field}");
+        }
+    }
+    Ok(())
 }
 
-// ── gRPC connection ───────────────────────────────────────────────────────────
+// ── `tcfs sync` ───────────────────────────────────────────────────────────────
 
 #[cfg(unix)]
-async fn connect_daemon(socket_path: &Path) -> Result<TcfsDaemonClient<Channel>> {
-    let path = socket_path.to_path_buf();
-
-    // tonic over Unix domain socket: use a tower service_fn connector
-    let channel = Endpoint::from_static("http://[::]:0")
-        .connect_with_connector(service_fn(move |_: Uri| {
-            let path = path.clone();
-            async move {
-                let stream = tokio::net::UnixStream::connect(&path).await?;
-                Ok::<_, std::io::Error>(hyper_util::rt::TokioIo::new(stream))
-            }
+async fn cmd_sync(config: &tcfs_core::config::TcfsConfig, prefix: Option<&str>) -> Result<()> {
+    let socket = &config.daemon.socket;
+    if !socket.exists() {
+        anyhow::bail!(
+            "tcfsd socket not found at {} — is tcfsd running?",
+            socket.display()
+        );
+    }
+
+    let mut client = connect_daemon(socket).await?;
+    let reply = client
+        .raw()
+        .sync_now(tonic::Request::new(SyncNowRequest {
+            prefix: prefix.unwrap_or_default().to_string(),
         }))
         .await
-        .with_context(|| format!("connecting to tcfsd at {}", socket_path.display()))?;
+        .context("sync_now RPC failed")?
+        .into_inner();
+
+    println!(
+        "tcfsd: sync finished: {} pulled, {} pushed, {} conflicts",
+        reply.pulled, reply.pushed, reply.conflicts
+    );
+    if reply.conflicts > 0 {
+        println!("  Conflicting files were left untouched; resolve with conflict_mode config");
+    }
+    Ok(())
+}
+
+// ── `tcfs fleet` ──────────────────────────────────────────────────────────────
+
+#[cfg(unix)]
+async fn cmd_fleet(config: &tcfs_core::config::TcfsConfig) -> Result<()> {
+    let socket = &config.daemon.socket;
+    if !socket.exists() {
+        anyhow::bail!(
+            "tcfsd socket not found at {} — is tcfsd running?",
+            socket.display()
+        );
+    }
+
+    let mut client = connect_daemon(socket).await?;
+    let devices = client.fleet_status().await?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let online = devices.iter().filter(|d| d.online).count();
+    println!("Fleet ({} devices, {online} online):", devices.len());
+    for device in &devices {
+        let name = if device.name.is_empty() {
+            "(unenrolled)"
+        } else {
+            &device.name
+        };
+        let id_short = device.device_id.get(..8).unwrap_or(&device.device_id);
+        let status = if device.online { "online" } else { "offline" };
+        let seen = match device.last_seen {
+            0 => "never seen".to_string(),
+            t => format!("seen {}", fmt_age(now.saturating_sub(t))),
+        };
+        let this = if device.this_device {
+            "  (this device)"
+        } else {
+            ""
+        };
+        println!("  {name:<20} {id_short:<8}  {status:<7}  {seen}{this}");
+    }
+    Ok(())
+}
+
+// ── `tcfs reconcile --dry-run` ────────────────────────────────────────────────
+
+async fn cmd_reconcile_plan(
+    config: &tcfs_core::config::TcfsConfig,
+    prefix: Option<&str>,
+    state_override: Option<&Path>,
+) -> Result<()> {
+    use tcfs_sync::engine::ReconcileAction;
+
+    let prefix = prefix
+        .unwrap_or(&config.storage.bucket)
+        .trim_end_matches('/');
+    let sync_root = config
+        .sync
+        .local_root_for(prefix)
+        .map(expand_tilde)
+        .with_context(|| format!("no sync root for prefix '{prefix}': set sync.sync_root or add a [[sync.roots]] entry"))?;
+    let op = build_operator_from_env(config)?;
+    // Plan against the state tcfsd keeps, so the plan is what its sync would do
+    let state_path = state_override
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| expand_tilde(&config.sync.state_db));
+    let state = tcfs_sync::state::StateCache::open(&state_path)
+        .with_context(|| format!("opening state cache: {}", state_path.display()))?;
+    state.set_read_only(config.sync.is_read_only_prefix(prefix));
+    state.set_max_clock_skew(config.sync.max_clock_skew_secs);
+    let device_id = load_device_id(config);
+    let collect_cfg = collect_config_from_sync(config);
+
+    println!(
+        "Reconcile plan for {} ↔ {}:{} (dry run, nothing is changed)",
+        sync_root.display(),
+        config.storage.bucket,
+        prefix
+    );
+    let plan = tcfs_sync::engine::plan_reconcile(
+        &op,
+        &sync_root,
+        prefix,
+        &state,
+        &device_id,
+        Some(&collect_cfg),
+    )
+    .await?;
+
+    let (mut pulls, mut pushes, mut conflicts, mut up_to_date) = (0, 0, 0, 0);
+    for action in &plan {
+        let rel = action
+            .local_path()
+            .strip_prefix(&sync_root)
+            .unwrap_or(action.local_path())
+            .display();
+        match action {
+            ReconcileAction::PullRemote { manifest_path, .. } => {
+                pulls += 1;
+                println!("  pull      {rel}  ({manifest_path})");
+            }
+            ReconcileAction::PushLocal { reason, .. } => {
+                pushes += 1;
+                println!("  push      {rel}  ({reason})");
+            }
+            ReconcileAction::Conflict { .. } => {
+                conflicts += 1;
+                println!("  conflict  {rel}  (local and remote diverged; left alone)");
+            }
+            ReconcileAction::UpToDate { .. } => up_to_date += 1,
+        }
+    }
+    println!();
+    println!("{pulls} to pull, {pushes} to push, {conflicts} conflicts, {up_to_date} up to date");
+    if config.sync.is_read_only_prefix(prefix) {
+        println!("  (prefix is read-only: local changes would not be pushed)");
+    }
+    Ok(())
+}
+
+// ── `tcfs doctor` ─────────────────────────────────────────────────────────────
+
+async fn cmd_doctor(config_path: &Path) -> Result<()> {
+    println!("tcfs doctor");
+    let env = doctor::DoctorEnv::gather(config_path).await;
+    let checks = doctor::run(&env).await;
+    doctor::print_report(&checks);
+
+    let failed = checks
+        .iter()
+        .filter(|c| c.status == doctor::Status::Fail)
+        .count();
+    if failed > 0 {
+        println!();
+        println!("{failed} check(s) failed");
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// ── gRPC connection ───────────────────────────────────────────────────────────
 
-    Ok(TcfsDaemonClient::new(channel))
+#[cfg(unix)]
+async fn connect_daemon(socket_path: &Path) -> Result<TcfsClient> {
+    Ok(TcfsClient::connect(socket_path).await?)
 }
 
 // ── `tcfs config show` ────────────────────────────────────────────────────────
@@ -1075,6 +2580,13 @@ async fn cmd_mount(
         .await
         .with_context(|| format!("creating mountpoint: {}", mountpoint.display()))?;
 
+    let master_key = keychain_master_key();
+    if master_key.is_none() && config.crypto.enabled {
+        eprintln!(
+            "No master key loaded: encrypted files will be unreadable until `tcfs auth unlock`."
+        );
+    }
+
     let cache_dir = expand_tilde(&config.fuse.cache_dir);
     let neg_ttl = config.fuse.negative_cache_ttl_secs;
     let cache_max = config.fuse.cache_max_mb * 1024 * 1024;
@@ -1098,13 +2610,51 @@ async fn cmd_mount(
         cache_dir,
         cache_max_bytes: cache_max,
         negative_ttl_secs: neg_ttl,
+        attr_ttl_secs: config.fuse.attr_ttl_secs,
+        entry_ttl_secs: config.fuse.entry_ttl_secs,
+        transparent_names: config.fuse.transparent_names,
+        encrypted_names: config.fuse.encrypted_names,
         read_only,
-        allow_other: false,
+        allow_other: config.fuse.allow_other,
+        uid: config.fuse.uid,
+        gid: config.fuse.gid,
+        mode_umask: config.sync.mode_umask,
+        master_key,
     })
     .await
     .context("FUSE mount failed")
 }
 
+/// The master key held in the platform keychain (base64 of the raw 32 bytes),
+/// if a session is unlocked.
+fn keychain_master_key() -> Option<tcfs_crypto::MasterKey> {
+    use secrecy::ExposeSecret;
+
+    let secret = match tcfs_secrets::keychain::get_secret(tcfs_secrets::keychain::keys::MASTER_KEY)
+    {
+        Ok(secret) => secret?,
+        Err(e) => {
+            tracing::debug!("keychain unavailable: {e}");
+            return None;
+        }
+    };
+    let bytes = base64::Engine::decode(
+        &base64::engine::general_purpose::STANDARD,
+        secret.expose_secret().trim(),
+    )
+    .ok()?;
+    match <[u8; tcfs_crypto::KEY_SIZE]>::try_from(bytes.as_slice()) {
+        Ok(key) => Some(tcfs_crypto::MasterKey::from_bytes(key)),
+        Err(_) => {
+            tracing::warn!(
+                "ignoring keychain master key: expected {} bytes",
+                tcfs_crypto::KEY_SIZE
+            );
+            None
+        }
+    }
+}
+
 // ── `tcfs unmount` (requires fuse feature) ──────────────────────────────────
 
 #[cfg(feature = "fuse")]
@@ -1182,6 +2732,7 @@ async fn cmd_unsync(
         println!("{} is already a stub — nothing to do.", path.display());
         return Ok(());
     }
+    tcfs_fuse::ensure_outside_mounts(path, &tcfs_fuse::tcfs_mountpoints())?;
 
     // Read file content and compute hash
     let data = tokio::fs::read(path)
@@ -1192,12 +2743,13 @@ async fn cmd_unsync(
     let hash_hex = tcfs_chunks::hash_to_hex(&hash);
     let size = data.len() as u64;
 
-    if !force {
-        let state_path = resolve_state_path(config, None);
-        let state = tcfs_sync::state::StateCache::open(&state_path)
-            .with_context(|| format!("opening state cache: {}", state_path.display()))?;
+    let state_path = resolve_state_path(config, None);
+    let state = tcfs_sync::state::StateCache::open(&state_path)
+        .with_context(|| format!("opening state cache: {}", state_path.display()))?;
+    let entry = state.get(path);
 
-        match state.get(path) {
+    if !force {
+        match entry {
             None => anyhow::bail!(
                 "{} is not tracked (never pushed). Use --force to unsync anyway.",
                 path.display()
@@ -1210,29 +2762,24 @@ async fn cmd_unsync(
         }
     }
 
-    // Build stub at path.tc
-    let stub_path = tcfs_fuse::real_to_stub_name(path.file_name().context("path has no filename")?);
-    let stub_full = path
-        .parent()
-        .unwrap_or(std::path::Path::new("."))
-        .join(stub_path);
-
-    let stub = tcfs_fuse::StubMeta {
-        chunks: 0, // unknown without state — leave as 0
-        compressed: false,
-        fetched: false,
-        oid: format!("blake3:{}", hash_hex),
-        origin: format!("seaweedfs://{}/{}", config.storage.endpoint, hash_hex),
-        size,
+    // Populate the stub from the state cache so a later hydrate has the real
+    // chunk count and manifest; fall back to content-only metadata when forced
+    let stub = match entry {
+        Some(entry) if entry.blake3 == hash_hex => {
+            tcfs_fuse::StubMeta::for_synced(&entry, &config.storage.bucket)
+        }
+        _ => tcfs_fuse::StubMeta {
+            chunks: 0,
+            compressed: false,
+            fetched: false,
+            oid: format!("blake3:{}", hash_hex),
+            origin: format!("seaweedfs://{}/{}", config.storage.endpoint, hash_hex),
+            size,
+        },
     };
 
     // Write stub then remove original
-    tokio::fs::write(&stub_full, stub.to_bytes())
-        .await
-        .with_context(|| format!("writing stub: {}", stub_full.display()))?;
-    tokio::fs::remove_file(path)
-        .await
-        .with_context(|| format!("removing hydrated file: {}", path.display()))?;
+    let stub_full = tcfs_fuse::unsync_file(path, &stub).await?;
 
     println!("Unsynced: {} → {}", path.display(), stub_full.display());
     println!("  hash: {}", &hash_hex[..16]);
@@ -1293,7 +2840,7 @@ async fn cmd_init(
 
     // Register device
     let mut registry = tcfs_secrets::device::DeviceRegistry::load(&registry_path)?;
-    let public_key = format!("age1-device-{}", &blake3_short(&device_name));
+    let public_key = format!("age1-device-{}", blake3_short(&device_name));
     let device_id = registry.enroll(&device_name, &public_key, None);
     registry.save(&registry_path)?;
 
@@ -1358,6 +2905,56 @@ fn cmd_device_list() -> Result<()> {
     Ok(())
 }
 
+// ── `tcfs device show` ───────────────────────────────────────────────────────
+
+fn cmd_device_show(name: &str) -> Result<()> {
+    let registry_path = tcfs_secrets::device::default_registry_path();
+    let registry = tcfs_secrets::device::DeviceRegistry::load(&registry_path)?;
+
+    let device = registry
+        .find(name)
+        .with_context(|| format!("Device '{}' not found", name))?;
+    print!("{}", device_details(device));
+    Ok(())
+}
+
+/// Multi-line report printed by `tcfs device show`.
+fn device_details(device: &tcfs_secrets::device::DeviceIdentity) -> String {
+    let mut out = format!("Device: {}\n", device.name);
+    out += &format!("  device_id:       {}\n", device.device_id);
+    out += &format!("  public_key:      {}\n", device.public_key);
+    out += &format!("  signing_key:     {}\n", device.signing_key_hash);
+    if let Some(ref key) = device.signing_public_key {
+        out += &format!("  manifest_key:    {}\n", key);
+    }
+    out += &format!("  enrolled_at:     {}\n", format_epoch(device.enrolled_at));
+    out += &format!(
+        "  status:          {}\n",
+        if device.revoked { "REVOKED" } else { "active" }
+    );
+    out += &format!(
+        "  last_seen:       {}\n",
+        device
+            .last_seen
+            .map(format_epoch)
+            .unwrap_or_else(|| "never".into())
+    );
+    out += &format!("  last_nats_seq:   {}\n", device.last_nats_seq);
+    if let Some(ref desc) = device.description {
+        out += &format!("  description:     {}\n", desc);
+    }
+    out
+}
+
+/// Unix seconds as `YYYY-MM-DD HH:MM:SS UTC`.
+fn format_epoch(secs: u64) -> String {
+    i64::try_from(secs)
+        .ok()
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| secs.to_string())
+}
+
 // ── `tcfs device revoke` ─────────────────────────────────────────────────────
 
 fn cmd_device_revoke(name: &str) -> Result<()> {
@@ -1546,6 +3143,7 @@ async fn cmd_rotate_credentials(
 // ── Interactive conflict resolver ──────────────────────────────────────────
 
 /// Prompt the user to resolve a sync conflict interactively.
+#[allow(dead_code)]
 fn resolve_conflict_interactive(
     info: &tcfs_sync::conflict::ConflictInfo,
 ) -> tcfs_sync::conflict::Resolution {
@@ -1597,3 +3195,99 @@ fn fmt_bytes(bytes: u64) -> String {
         format!("{} B", bytes)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_available_orders_pre_releases_below_releases() {
+        assert!(update_available("1.2.0-rc1", "1.2.0"));
+        assert!(update_available("1.2.0-rc.1", "1.2.0-rc.2"));
+        assert!(update_available("0.9.9", "v1.0.0"));
+        assert!(!update_available("1.2.0", "1.2.0-rc1"));
+        assert!(!update_available("1.3.0", "1.2.9"));
+    }
+
+    #[test]
+    fn update_available_is_quiet_for_equal_or_unparseable_versions() {
+        assert!(!update_available("1.2.0", "1.2.0"));
+        assert!(!update_available("1.2.0+build.5", "1.2.0+build.9"));
+        assert!(!update_available("dev", "1.2.0"));
+        assert!(!update_available("1.2.0", "latest"));
+    }
+
+    #[test]
+    fn parse_age_accepts_units_and_rejects_garbage() {
+        assert_eq!(parse_age("90d"), Ok(90 * 86_400));
+        assert_eq!(parse_age("12h"), Ok(12 * 3600));
+        assert_eq!(parse_age("2w"), Ok(14 * 86_400));
+        assert_eq!(parse_age("45"), Ok(45));
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("3y").is_err());
+        assert!(parse_age("-1d").is_err());
+    }
+
+    #[test]
+    fn device_show_prints_id_and_key() {
+        let mut registry = tcfs_secrets::device::DeviceRegistry::default();
+        let id = registry.enroll("lab-node", "age1labnode", Some("rack 2".into()));
+        let device = registry.find("lab-node").unwrap();
+
+        let details = device_details(device);
+        assert!(
+            details.contains(&format!("device_id:       {id}")),
+            "{details}"
+        );
+        assert!(
+            details.contains("public_key:      age1labnode"),
+            "{details}"
+        );
+        assert!(details.contains("last_seen:       never"), "{details}");
+        assert!(details.contains("description:     rack 2"), "{details}");
+
+        registry.record_seen(&id, 1_700_000_000);
+        let details = device_details(registry.find("lab-node").unwrap());
+        assert!(
+            details.contains("last_seen:       2023-11-14 22:13:20 UTC"),
+            "{details}"
+        );
+    }
+
+    /// Serve one canned HTTP response on a local port and return its URL.
+    #[cfg(feature = "update-check")]
+    async fn serve_once(status: &'static str, body: &'static str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = conn.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+            let response = format!(
+                "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            conn.write_all(response.as_bytes()).await.unwrap();
+        });
+        format!("http://{addr}/repos/tinyland-inc/tummycrypt/releases/latest")
+    }
+
+    #[cfg(feature = "update-check")]
+    #[tokio::test]
+    async fn fetch_latest_version_parses_release_json() {
+        let url = serve_once("200 OK", r#"{"tag_name":"v0.7.1","name":"tcfs 0.7.1"}"#).await;
+        assert_eq!(fetch_latest_version(&url).await.as_deref(), Some("0.7.1"));
+
+        let url = serve_once("404 Not Found", r#"{"message":"Not Found"}"#).await;
+        assert_eq!(fetch_latest_version(&url).await, None);
+    }
+}
@@ -0,0 +1,278 @@
+//! tcfs-client: typed async client for the tcfsd gRPC API
+//!
+//! tcfsd listens on a Unix domain socket (`daemon.socket`). [`TcfsClient`]
+//! hides the tonic connector needed to reach it and the streaming halves of
+//! the API: `push` sends a buffer as a stream of `PushChunk`s and `push` and
+//! `pull` drain their progress streams, returning the final reply. Replies
+//! that carry an `error` string come back as [`ClientError::Daemon`], so
+//! callers only match on `Ok`. RPCs without a wrapper are reachable through
+//! [`TcfsClient::raw`].
+//!
+//! ```no_run
+//! # async fn demo() -> Result<(), tcfs_client::ClientError> {
+//! let mut client = tcfs_client::TcfsClient::connect("/run/tcfsd/tcfsd.sock").await?;
+//! let status = client.status().await?;
+//! println!("tcfsd v{} on {}", status.version, status.device_name);
+//! # Ok(())
+//! # }
+//! ```
+
+use tcfs_core::proto::{
+    tcfs_daemon_client::TcfsDaemonClient, ChunkInfoRequest, ChunkInfoResponse,
+    CredentialStatusResponse, Empty, FleetDevice, PullProgress, PullRequest, PushChunk,
+    PushProgress, ReadManifestRequest, ReadManifestResponse, ResolveConflictRequest, StatusRequest,
+    StatusResponse, SyncStatusRequest, SyncStatusResponse,
+};
+use tokio_stream::{Stream, StreamExt};
+use tonic::transport::Channel;
+
+/// Largest `PushChunk` payload sent by [`TcfsClient::push`].
+pub const PUSH_CHUNK_BYTES: usize = 1024 * 1024;
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+/// Ways a call to tcfsd can fail.
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    /// The socket could not be connected to
+    #[error("connecting to tcfsd at {socket}: {error}")]
+    Connect {
+        socket: String,
+        error: tonic::transport::Error,
+    },
+    /// The RPC itself failed (daemon unreachable, bad argument, ...)
+    #[error("{rpc} RPC failed: {}", status.message())]
+    Rpc {
+        rpc: &'static str,
+        status: tonic::Status,
+    },
+    /// The daemon answered with an error in its reply
+    #[error("tcfsd {rpc} failed: {message}")]
+    Daemon { rpc: &'static str, message: String },
+    /// A streaming RPC finished without sending a reply
+    #[error("{rpc} stream ended without a reply")]
+    NoReply { rpc: &'static str },
+}
+
+impl ClientError {
+    fn rpc(rpc: &'static str) -> impl FnOnce(tonic::Status) -> Self {
+        move |status| ClientError::Rpc { rpc, status }
+    }
+}
+
+/// Connection to a running tcfsd.
+///
+/// Cloning is cheap and clones share the underlying channel.
+#[derive(Debug, Clone)]
+pub struct TcfsClient {
+    inner: TcfsDaemonClient<Channel>,
+}
+
+impl TcfsClient {
+    /// Connect to the daemon listening on the Unix socket at `socket`.
+    #[cfg(unix)]
+    pub async fn connect(socket: impl AsRef<std::path::Path>) -> Result<Self> {
+        use tonic::transport::{Endpoint, Uri};
+
+        let path = socket.as_ref().to_path_buf();
+        let display = path.display().to_string();
+        // tonic over Unix domain socket: the URI is ignored by the connector
+        let channel = Endpoint::from_static("http://[::]:0")
+            .connect_with_connector(tower::service_fn(move |_: Uri| {
+                let path = path.clone();
+                async move {
+                    let stream = tokio::net::UnixStream::connect(&path).await?;
+                    Ok::<_, std::io::Error>(hyper_util::rt::TokioIo::new(stream))
+                }
+            }))
+            .await
+            .map_err(|error| ClientError::Connect {
+                socket: display,
+                error,
+            })?;
+        Ok(Self::from_channel(channel))
+    }
+
+    /// Wrap an already established channel.
+    pub fn from_channel(channel: Channel) -> Self {
+        TcfsClient {
+            inner: TcfsDaemonClient::new(channel),
+        }
+    }
+
+    /// The generated client, for RPCs without a wrapper here.
+    pub fn raw(&mut self) -> &mut TcfsDaemonClient<Channel> {
+        &mut self.inner
+    }
+
+    /// Daemon version, storage and NATS health, device and usage totals.
+    pub async fn status(&mut self) -> Result<StatusResponse> {
+        self.inner
+            .status(StatusRequest {})
+            .await
+            .map(tonic::Response::into_inner)
+            .map_err(ClientError::rpc("status"))
+    }
+
+    /// Whether storage credentials are loaded, and from where.
+    pub async fn credential_status(&mut self) -> Result<CredentialStatusResponse> {
+        self.inner
+            .credential_status(Empty {})
+            .await
+            .map(tonic::Response::into_inner)
+            .map_err(ClientError::rpc("credential_status"))
+    }
+
+    /// Upload `data` as the file `path`, returning the final progress
+    /// report (bytes sent, content hash, chunk dedup counts).
+    pub async fn push(&mut self, path: &str, data: &[u8]) -> Result<PushProgress> {
+        let mut chunks: Vec<PushChunk> = data
+            .chunks(PUSH_CHUNK_BYTES)
+            .enumerate()
+            .map(|(i, piece)| PushChunk {
+                path: path.to_string(),
+                data: piece.to_vec(),
+                offset: (i * PUSH_CHUNK_BYTES) as u64,
+                last: false,
+            })
+            .collect();
+        if chunks.is_empty() {
+            chunks.push(PushChunk {
+                path: path.to_string(),
+                ..Default::default()
+            });
+        }
+        if let Some(last) = chunks.last_mut() {
+            last.last = true;
+        }
+
+        let stream = self
+            .inner
+            .push(tokio_stream::iter(chunks))
+            .await
+            .map_err(ClientError::rpc("push"))?
+            .into_inner();
+        let done = last_reply("push", stream).await?;
+        if !done.error.is_empty() {
+            return Err(ClientError::Daemon {
+                rpc: "push",
+                message: done.error,
+            });
+        }
+        Ok(done)
+    }
+
+    /// Download the manifest at `remote_path` to `local_path` on the
+    /// daemon's host, returning the final progress report.
+    pub async fn pull(&mut self, remote_path: &str, local_path: &str) -> Result<PullProgress> {
+        let stream = self
+            .inner
+            .pull(PullRequest {
+                remote_path: remote_path.to_string(),
+                local_path: local_path.to_string(),
+            })
+            .await
+            .map_err(ClientError::rpc("pull"))?
+            .into_inner();
+        let done = last_reply("pull", stream).await?;
+        if !done.error.is_empty() {
+            return Err(ClientError::Daemon {
+                rpc: "pull",
+                message: done.error,
+            });
+        }
+        Ok(done)
+    }
+
+    /// Sync state of a local path: `synced`, `pending` or `unknown`.
+    pub async fn sync_status(&mut self, path: &str) -> Result<SyncStatusResponse> {
+        self.inner
+            .sync_status(SyncStatusRequest {
+                path: path.to_string(),
+            })
+            .await
+            .map(tonic::Response::into_inner)
+            .map_err(ClientError::rpc("sync_status"))
+    }
+
+    /// Resolve the conflict on `path` with `resolution` (`keep_local`,
+    /// `keep_remote`, `keep_both` or `defer`), returning the path the
+    /// daemon reports as resolved.
+    pub async fn resolve_conflict(&mut self, path: &str, resolution: &str) -> Result<String> {
+        let reply = self
+            .inner
+            .resolve_conflict(ResolveConflictRequest {
+                path: path.to_string(),
+                resolution: resolution.to_string(),
+            })
+            .await
+            .map_err(ClientError::rpc("resolve_conflict"))?
+            .into_inner();
+        if !reply.success {
+            return Err(ClientError::Daemon {
+                rpc: "resolve_conflict",
+                message: reply.error,
+            });
+        }
+        Ok(reply.resolved_path)
+    }
+
+    /// Every device tcfsd knows of, from its registry or from state events,
+    /// with whether it is online and when it was last heard from.
+    pub async fn fleet_status(&mut self) -> Result<Vec<FleetDevice>> {
+        self.inner
+            .fleet_status(Empty {})
+            .await
+            .map(|reply| reply.into_inner().devices)
+            .map_err(ClientError::rpc("fleet_status"))
+    }
+
+    /// The manifest stored under `prefix` ("" for the bucket prefix) for
+    /// content `file_hash`, or for the file at `rel_path` when `file_hash`
+    /// is empty. The reply carries it as JSON.
+    pub async fn read_manifest(
+        &mut self,
+        prefix: &str,
+        file_hash: &str,
+        rel_path: &str,
+    ) -> Result<ReadManifestResponse> {
+        self.inner
+            .read_manifest(ReadManifestRequest {
+                prefix: prefix.to_string(),
+                file_hash: file_hash.to_string(),
+                rel_path: rel_path.to_string(),
+            })
+            .await
+            .map(tonic::Response::into_inner)
+            .map_err(ClientError::rpc("read_manifest"))
+    }
+
+    /// Whether the chunk `chunk_hash` is stored under `prefix` ("" for the
+    /// bucket prefix), with its stored size and key.
+    pub async fn chunk_info(
+        &mut self,
+        prefix: &str,
+        chunk_hash: &str,
+    ) -> Result<ChunkInfoResponse> {
+        self.inner
+            .chunk_info(ChunkInfoRequest {
+                prefix: prefix.to_string(),
+                chunk_hash: chunk_hash.to_string(),
+            })
+            .await
+            .map(tonic::Response::into_inner)
+            .map_err(ClientError::rpc("chunk_info"))
+    }
+}
+
+/// Drain a progress stream, keeping its last message.
+async fn last_reply<T>(
+    rpc: &'static str,
+    mut stream: impl Stream<Item = std::result::Result<T, tonic::Status>> + Unpin,
+) -> Result<T> {
+    let mut last = None;
+    while let Some(item) = stream.next().await {
+        last = Some(item.map_err(ClientError::rpc(rpc))?);
+    }
+    last.ok_or(ClientError::NoReply { rpc })
+}
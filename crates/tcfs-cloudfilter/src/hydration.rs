@@ -4,9 +4,9 @@
 //! minifilter driver intercepts the I/O and calls our registered
 //! FETCH_DATA callback. This module:
 //!
-//! 1. Extracts the file identity (content hash) from the callback info
-//! 2. Looks up the manifest path for the hash
-//! 3. Fetches chunks from SeaweedFS via OpenDAL
+//! 1. Extracts the file identity (relative path) from the callback info
+//! 2. Reads the file's index entry
+//! 3. Fetches its chunks, or its range of a pack, from SeaweedFS via OpenDAL
 //! 4. Streams data to the placeholder via CfExecute(CF_OPERATION_TYPE_TRANSFER_DATA)
 //! 5. Acknowledges completion
 //!
@@ -20,15 +20,14 @@ use tracing::{debug, info, warn};
 /// Handle a FETCH_DATA callback from the Cloud Files minifilter.
 ///
 /// Called when a user or application opens a dehydrated placeholder.
-/// Uses `tcfs_sync::manifest::SyncManifest` for manifest parsing and
-/// `tcfs_chunks` for chunk integrity verification.
+/// Uses `tcfs_sync::engine::read_indexed`, which reads a packed file from
+/// its pack and any other through its manifest, verifying it via BLAKE3.
 ///
 /// # Flow
-/// 1. Parse file identity → content hash
-/// 2. Look up manifest path: `{prefix}/manifests/{hash}`
-/// 3. Read and parse SyncManifest (v1/v2 auto-detect)
-/// 4. Fetch and verify each chunk via BLAKE3
-/// 5. Return assembled data (Windows CfExecute transfer would go here)
+/// 1. Parse file identity → relative path
+/// 2. Read the index entry: `{prefix}/index/{rel_path}`
+/// 3. Fetch and verify the content the entry points at
+/// 4. Return assembled data (Windows CfExecute transfer would go here)
 pub async fn handle_fetch_data(
     op: &opendal::Operator,
     remote_prefix: &str,
@@ -37,72 +36,19 @@ pub async fn handle_fetch_data(
     _required_length: u64,
     // transfer_key: CF_TRANSFER_KEY, // Windows handle for data transfer
 ) -> Result<Vec<u8>> {
-    let content_hash = String::from_utf8_lossy(file_identity);
-    let prefix = remote_prefix.trim_end_matches('/');
-    let manifest_path = format!("{}/manifests/{}", prefix, content_hash);
+    let rel_path = String::from_utf8_lossy(file_identity);
 
-    debug!(hash = %content_hash, manifest = %manifest_path, "hydrating via CFAPI callback");
+    debug!(path = %rel_path, "hydrating via CFAPI callback");
 
-    // Read and parse manifest using SyncManifest (supports v1 + v2)
-    let manifest_bytes = op
-        .read(&manifest_path)
+    let entry = tcfs_sync::engine::resolve_index_entry(op, remote_prefix, &rel_path).await?;
+    let assembled = tcfs_sync::engine::read_indexed(op, remote_prefix, &rel_path, &entry, None)
         .await
-        .with_context(|| format!("reading manifest: {}", manifest_path))?;
-
-    let manifest = tcfs_sync::manifest::SyncManifest::from_bytes(&manifest_bytes.to_bytes())
-        .with_context(|| format!("parsing manifest: {}", manifest_path))?;
-
-    let chunk_hashes = manifest.chunk_hashes();
-
-    if chunk_hashes.is_empty() {
-        anyhow::bail!("empty manifest: {}", manifest_path);
-    }
-
-    // Fetch and assemble all chunks with integrity verification
-    let mut assembled = Vec::new();
-    for (i, hash) in chunk_hashes.iter().enumerate() {
-        let chunk_key = format!("{}/chunks/{}", prefix, hash);
-        let chunk_data = op.read(&chunk_key).await.with_context(|| {
-            format!(
-                "downloading chunk {}/{}: {}",
-                i + 1,
-                chunk_hashes.len(),
-                chunk_key
-            )
-        })?;
-
-        let chunk_bytes = chunk_data.to_bytes();
-
-        // Verify chunk integrity via BLAKE3
-        let actual_hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&chunk_bytes));
-        if actual_hash != *hash {
-            anyhow::bail!(
-                "chunk integrity check failed for {}: expected {}, got {}",
-                chunk_key,
-                hash,
-                actual_hash
-            );
-        }
-
-        assembled.extend_from_slice(&chunk_bytes);
-    }
-
-    // Verify reassembled file hash if manifest has one (v2)
-    if !manifest.file_hash.is_empty() {
-        let actual_file_hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&assembled));
-        if actual_file_hash != manifest.file_hash {
-            anyhow::bail!(
-                "file integrity check failed: expected {}, got {}",
-                manifest.file_hash,
-                actual_file_hash
-            );
-        }
-    }
+        .with_context(|| format!("hydrating {rel_path}"))?;
 
     info!(
-        hash = %content_hash,
+        path = %rel_path,
         bytes = assembled.len(),
-        chunks = chunk_hashes.len(),
+        packed = entry.is_packed(),
         "CFAPI hydration complete"
     );
 
@@ -133,8 +79,8 @@ pub async fn handle_fetch_data(
 /// Called when the application closes the file before hydration completes,
 /// or when the user cancels a download. Clean up any in-progress transfers.
 pub async fn handle_cancel_fetch(file_identity: &[u8]) -> Result<()> {
-    let content_hash = String::from_utf8_lossy(file_identity);
-    warn!(hash = %content_hash, "CFAPI hydration cancelled");
+    let rel_path = String::from_utf8_lossy(file_identity);
+    warn!(path = %rel_path, "CFAPI hydration cancelled");
     // TODO: Cancel any in-progress chunk downloads
     Ok(())
 }
@@ -85,13 +85,14 @@ pub enum PopulationPolicy {
 /// Placeholder file metadata for CFAPI registration.
 #[derive(Debug, Clone)]
 pub struct PlaceholderInfo {
-    /// Relative path within the sync root
+    /// Relative path within the sync root (stored as file identity, so
+    /// hydration finds the file's index entry)
     pub relative_path: std::path::PathBuf,
     /// File size in bytes (shown in Explorer even when dehydrated)
     pub file_size: u64,
     /// Last modified timestamp
     pub modified: std::time::SystemTime,
-    /// BLAKE3 hash of the file content (stored as file identity)
+    /// BLAKE3 hash of the file content
     pub content_hash: String,
     /// Manifest path for hydration
     pub manifest_path: String,
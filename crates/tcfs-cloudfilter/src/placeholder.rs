@@ -15,6 +15,7 @@
 
 use anyhow::{Context, Result};
 use std::path::Path;
+use tcfs_core::index::{IndexEntry, DIR_MARKER};
 use tracing::{debug, info};
 
 use crate::PlaceholderInfo;
@@ -52,7 +53,8 @@ pub async fn create_placeholder(sync_root: &Path, info: &PlaceholderInfo) -> Res
     //
     // use windows::Win32::Storage::CloudFilters::*;
     //
-    // let identity = info.content_hash.as_bytes();
+    // let identity = info.relative_path.to_string_lossy().replace('\\', "/");
+    // let identity = identity.as_bytes();
     // let file_name = full_path.file_name().unwrap().to_string_lossy();
     //
     // let placeholder = CF_PLACEHOLDER_CREATE_INFO {
@@ -87,7 +89,7 @@ pub async fn populate_root(
     op: &opendal::Operator,
     remote_prefix: &str,
 ) -> Result<usize> {
-    let index_prefix = format!("{}/index/", remote_prefix.trim_end_matches('/'));
+    let index_prefix = tcfs_core::layout::RemoteLayout::new(remote_prefix).index_dir("");
 
     info!(
         root = %sync_root.display(),
@@ -106,7 +108,7 @@ pub async fn populate_root(
         let entry_path = entry.path();
         let rel_path = entry_path.strip_prefix(&index_prefix).unwrap_or(entry_path);
 
-        if rel_path.is_empty() || rel_path.ends_with('/') {
+        if rel_path.is_empty() || rel_path.ends_with('/') || rel_path == DIR_MARKER {
             continue; // skip directory markers
         }
 
@@ -119,32 +121,33 @@ pub async fn populate_root(
             }
         };
 
-        let text = String::from_utf8_lossy(&data.to_bytes());
-        // Parse index entry: "manifest_hash=...\nsize=...\nchunks=...\n" format
-        let mut manifest_hash = String::new();
-        let mut size = 0u64;
-        for line in text.lines() {
-            if let Some(val) = line.strip_prefix("manifest_hash=") {
-                manifest_hash = val.to_string();
-            } else if let Some(val) = line.strip_prefix("size=") {
-                size = val.parse().unwrap_or(0);
+        let index = match IndexEntry::from_bytes(&data.to_bytes()) {
+            Ok(index) => index,
+            Err(e) => {
+                tracing::warn!(path = %entry_path, "skipping invalid index entry: {e}");
+                continue;
             }
+        };
+
+        if index.is_symlink() {
+            debug!(path = %entry_path, "skipping symlink index entry");
+            continue;
         }
 
-        if manifest_hash.is_empty() {
+        if index.is_tombstone() {
+            debug!(path = %entry_path, "skipping tombstoned index entry");
             continue;
         }
 
         let info = PlaceholderInfo {
             relative_path: std::path::PathBuf::from(rel_path),
-            file_size: size,
-            modified: std::time::SystemTime::now(),
-            content_hash: manifest_hash.clone(),
-            manifest_path: format!(
-                "{}/manifests/{}",
-                remote_prefix.trim_end_matches('/'),
-                manifest_hash
-            ),
+            file_size: index.size,
+            modified: index
+                .modified
+                .map(|secs| std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs))
+                .unwrap_or_else(std::time::SystemTime::now),
+            manifest_path: index.manifest_path(remote_prefix),
+            content_hash: index.manifest_hash,
             is_directory: false,
         };
 
@@ -187,7 +190,8 @@ pub async fn convert_to_placeholder(file_path: &Path, info: &PlaceholderInfo) ->
     // TODO: CfConvertToPlaceholder + CfSetInSyncState implementation
     //
     // use windows::Win32::Storage::CloudFilters::*;
-    // let identity = info.content_hash.as_bytes();
+    // let identity = info.relative_path.to_string_lossy().replace('\\', "/");
+    // let identity = identity.as_bytes();
     // let handle = open_cf_handle(file_path)?;
     // unsafe {
     //     CfConvertToPlaceholder(handle, identity.as_ptr() as _, identity.len() as u32,
@@ -0,0 +1,123 @@
+//! Time source shared by the sync engine, daemon and FUSE driver
+//!
+//! Timestamps written to manifests, state entries and events, and the
+//! monotonic instants behind TTLs, are read from a [`Clock`] instead of
+//! `SystemTime::now()`/`Instant::now()` directly. Production code uses
+//! [`SystemClock`]; tests install a [`MockClock`] and move it by hand, so
+//! expiry and ordering can be checked at exact boundaries without sleeping.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A source of wall-clock and monotonic time.
+pub trait Clock: Send + Sync + std::fmt::Debug {
+    /// Wall-clock time, for timestamps that are stored or sent
+    fn now(&self) -> SystemTime;
+
+    /// Monotonic time, for TTLs and intervals
+    fn instant(&self) -> Instant;
+
+    /// Wall-clock time as unix seconds (0 before the epoch)
+    fn unix_secs(&self) -> u64 {
+        self.now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    }
+}
+
+/// Shared handle to a clock.
+pub type SharedClock = Arc<dyn Clock>;
+
+/// The host's clocks.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A [`SystemClock`] behind a [`SharedClock`].
+pub fn system() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+/// A clock that only moves when told to.
+///
+/// Wall-clock and monotonic time advance together; [`MockClock::set_unix`]
+/// jumps the wall clock alone, as a host clock adjustment would.
+#[derive(Debug)]
+pub struct MockClock {
+    origin: Instant,
+    state: Mutex<MockState>,
+}
+
+#[derive(Debug)]
+struct MockState {
+    wall: SystemTime,
+    elapsed: Duration,
+}
+
+impl MockClock {
+    /// A clock reading `unix_secs` on the wall clock.
+    pub fn new(unix_secs: u64) -> Self {
+        Self {
+            origin: Instant::now(),
+            state: Mutex::new(MockState {
+                wall: UNIX_EPOCH + Duration::from_secs(unix_secs),
+                elapsed: Duration::ZERO,
+            }),
+        }
+    }
+
+    /// Move both clocks forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.wall += by;
+        state.elapsed += by;
+    }
+
+    /// Set the wall clock to `unix_secs`, leaving monotonic time alone.
+    pub fn set_unix(&self, unix_secs: u64) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.wall = UNIX_EPOCH + Duration::from_secs(unix_secs);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).wall
+    }
+
+    fn instant(&self) -> Instant {
+        self.origin + self.state.lock().unwrap_or_else(|e| e.into_inner()).elapsed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_clock_moves_only_when_told() {
+        let clock = MockClock::new(1_700_000_000);
+        let start = clock.instant();
+        assert_eq!(clock.unix_secs(), 1_700_000_000);
+        assert_eq!(clock.instant(), start);
+
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(clock.unix_secs(), 1_700_000_090);
+        assert_eq!(clock.instant() - start, Duration::from_secs(90));
+
+        // A wall clock step does not move monotonic time
+        clock.set_unix(1_600_000_000);
+        assert_eq!(clock.unix_secs(), 1_600_000_000);
+        assert_eq!(clock.instant() - start, Duration::from_secs(90));
+    }
+}
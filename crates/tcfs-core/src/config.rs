@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// Top-level daemon configuration (loaded from tcfs.toml)
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -21,6 +21,17 @@ fn default_true() -> bool {
     true
 }
 
+impl TcfsConfig {
+    /// Fleet whose NATS state events this device exchanges:
+    /// `sync.fleet_id`, or the storage bucket when unset.
+    pub fn fleet_id(&self) -> &str {
+        match self.sync.fleet_id.as_deref() {
+            Some(id) if !id.trim().is_empty() => id,
+            _ => &self.storage.bucket,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct DaemonConfig {
@@ -34,6 +45,9 @@ pub struct DaemonConfig {
     pub log_level: String,
     /// Log format: "json" or "text"
     pub log_format: String,
+    /// Directory buffers received over the `Push` RPC are staged in before
+    /// upload, one subdirectory per push (default: the system temp dir)
+    pub push_staging_dir: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -51,6 +65,22 @@ pub struct StorageConfig {
     pub enforce_tls: bool,
     /// Path to a custom CA certificate for S3 TLS verification
     pub ca_cert_path: Option<PathBuf>,
+    /// Most bytes of chunks and manifests a push may leave under the
+    /// prefix; pushes that would go past it fail with `QuotaExceeded`
+    pub quota_bytes: Option<u64>,
+    /// Levels of `chunks/<hh>/` fan-out for newly written chunks (0 = flat,
+    /// at most 4); existing manifests keep the layout they were written with
+    pub chunk_shard_depth: u8,
+    /// Stores tried in order for chunks and manifests a download finds
+    /// missing here, e.g. a regional copy of the bucket. Each is reached with
+    /// this store's credentials; their own `read_mirrors` are ignored
+    pub read_mirrors: Vec<StorageConfig>,
+    /// Copy objects found on a read mirror into this store (default false)
+    pub read_mirror_backfill: bool,
+    /// `Cache-Control` header stored with chunk objects, for serving them
+    /// through a CDN; chunks never change once written, so e.g.
+    /// `"public, max-age=31536000, immutable"` is safe. Unset by default
+    pub cache_control: Option<String>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -73,6 +103,22 @@ pub struct SyncConfig {
     pub nats_tls: bool,
     /// Path to a custom CA certificate for NATS TLS verification
     pub nats_ca_cert: Option<PathBuf>,
+    /// NATS namespace: state events go to `tcfs.<fleet_id>.state.>` and only
+    /// devices in the same fleet see them (default: `storage.bucket`)
+    pub fleet_id: Option<String>,
+    /// Appended to this device's durable state consumer name
+    /// (`state-<device_id>-<suffix>`) so a second process running as the
+    /// same device gets its own cursor instead of splitting the first one's
+    /// messages. Each cursor is separate, so `last_nats_seq` in a state
+    /// cache only describes the consumer that wrote it: give each process
+    /// its own `state_db` too (default empty: `state-<device_id>`)
+    pub consumer_suffix: String,
+    /// zstd-compress published state events (default false; consumers
+    /// decode both forms, but devices older than 0.6 only read plain JSON)
+    pub nats_compress: bool,
+    /// Largest state event payload published, in bytes; bigger events have
+    /// their vector clock trimmed to fit (default 1 MiB, the NATS default)
+    pub nats_max_payload: usize,
     /// RocksDB state cache path
     pub state_db: PathBuf,
     /// Worker thread count (0 = cpu_count)
@@ -83,8 +129,17 @@ pub struct SyncConfig {
     pub device_identity: Option<PathBuf>,
     /// Device name (defaults to hostname)
     pub device_name: Option<String>,
-    /// Conflict resolution mode: "auto", "interactive", or "defer"
-    pub conflict_mode: String,
+    /// Device id used verbatim instead of the registry's (for CI and ephemeral hosts)
+    pub device_id: Option<String>,
+    /// Conflict resolution mode: "auto", "interactive" (fast-forwards are
+    /// applied, concurrent edits held for review), or "defer"
+    pub conflict_mode: ConflictMode,
+    /// Per-path overrides of `conflict_mode`, tried in order; the first rule
+    /// whose pattern matches a relative path decides its mode
+    pub path_rules: Vec<PathRule>,
+    /// Resolver used by `conflict_mode = "auto"`: "device-order" (default),
+    /// "remote-wins", "local-wins", "newest", or "prefer-device:ID"
+    pub auto_strategy: String,
     /// Whether to sync .git directories
     pub sync_git_dirs: bool,
     /// Git sync mode: "bundle" or "raw"
@@ -93,8 +148,189 @@ pub struct SyncConfig {
     pub sync_hidden_dirs: bool,
     /// Glob patterns to exclude from sync
     pub exclude_patterns: Vec<String>,
-    /// Local directory root for synced files (used by auto-pull)
+    /// Whether to push zero-byte files (default true). FIFOs, sockets and
+    /// device files are always skipped
+    pub sync_empty_files: bool,
+    /// Local directory root for synced files (used by auto-pull), for
+    /// prefixes no `roots` entry claims
     pub sync_root: Option<PathBuf>,
+    /// `[[sync.roots]]`: local directories each synced with their own
+    /// remote prefix; remote events are routed to the root of their prefix
+    pub roots: Vec<SyncRoot>,
+    /// Maximum files uploaded concurrently by a tree push (0 = cpu_count)
+    pub push_concurrency: usize,
+    /// Bundle files smaller than `pack_threshold_bytes` into shared packfiles
+    /// under `{prefix}/packs/` instead of one manifest and chunk each
+    /// (default false; unencrypted pushes only)
+    pub pack_small_files: bool,
+    /// Size below which a file is packed when `pack_small_files` is on
+    /// (default 64 KiB)
+    pub pack_threshold_bytes: u64,
+    /// Fewest chunk transfers kept in flight per file by the adaptive controller (default 1)
+    pub transfer_concurrency_min: usize,
+    /// Most chunk transfers kept in flight per file by the adaptive controller (default 16)
+    pub transfer_concurrency_max: usize,
+    /// Seconds a remote manifest's `written_at` may lie ahead of the local
+    /// clock before its timestamp is ignored for ordering (default 3600)
+    pub max_clock_skew_secs: u64,
+    /// Consecutive auto-pull failures after which auto-pulls pause for
+    /// `auto_pull_cooldown_secs` (0 = never pause; default 5)
+    pub auto_pull_failure_threshold: u32,
+    /// Seconds auto-pulls stay paused before storage is re-probed (default 60)
+    pub auto_pull_cooldown_secs: u64,
+    /// Permission bits cleared from restored file modes, like a umask (default 0o022)
+    pub mode_umask: u32,
+    /// Record a `{prefix}/history/` pointer for every pushed version (default false)
+    pub keep_history: bool,
+    /// Versions kept per file when `keep_history` is on (0 = unlimited)
+    pub history_max: usize,
+    /// False-positive rate of the per-prefix chunk Bloom filter that lets
+    /// pushes skip chunk `exists` checks (0 disables the filter; default 0.01)
+    pub chunk_filter_fp_rate: f64,
+    /// File extensions stored without zstd compression, replacing the
+    /// built-in list of already-compressed formats when set
+    pub compress_skip_extensions: Option<Vec<String>>,
+    /// Remote prefixes that must never be written to (e.g. a shared template
+    /// bucket); pushes to them fail with `ReadOnlyStore`
+    pub read_only_prefixes: Vec<String>,
+    /// Device ids whose unsigned manifests are still accepted (devices
+    /// enrolled before manifest signing). Any other device's manifests must
+    /// be signed by a key enrolled locally or published under the prefix
+    pub unsigned_manifest_devices: Vec<String>,
+}
+
+/// A `[[sync.path_rules]]` entry: a conflict mode for paths matching a glob.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathRule {
+    /// Glob over sync-root-relative paths (`build/**`, `**/*.rs`)
+    pub pattern: String,
+    /// "auto", "interactive", or "defer"
+    pub conflict_mode: ConflictMode,
+}
+
+/// A `[[sync.roots]]` entry: a local directory synced with one prefix.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncRoot {
+    /// Local directory the prefix's files live in
+    pub local_path: PathBuf,
+    /// Remote prefix synced with `local_path`
+    pub prefix: String,
+    /// Conflict mode for files under this root, used where no `path_rules`
+    /// entry matches (default: `sync.conflict_mode`)
+    #[serde(default)]
+    pub conflict_mode: Option<ConflictMode>,
+}
+
+/// How a remote edit that conflicts with the local copy is handled.
+///
+/// Parsed from the lowercase names when the config is loaded, so an
+/// unknown mode (`"Auto"`, `"yolo"`) fails the load instead of quietly
+/// acting like another mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase", try_from = "String")]
+pub enum ConflictMode {
+    /// Concurrent edits are settled by `auto_strategy`
+    #[default]
+    Auto,
+    /// Fast-forwards are applied, concurrent edits held for review
+    Interactive,
+    /// Remote changes are left for `tcfs reconcile` / `tcfs sync`
+    Defer,
+}
+
+impl ConflictMode {
+    /// The name used in the config file and on the wire.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ConflictMode::Auto => "auto",
+            ConflictMode::Interactive => "interactive",
+            ConflictMode::Defer => "defer",
+        }
+    }
+}
+
+impl std::fmt::Display for ConflictMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for ConflictMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ConflictMode::Auto),
+            "interactive" => Ok(ConflictMode::Interactive),
+            "defer" => Ok(ConflictMode::Defer),
+            other => Err(format!(
+                "invalid conflict_mode {other:?} (expected auto, interactive, or defer)"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for ConflictMode {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl SyncConfig {
+    /// Conflict mode for `rel_path`: that of the first matching
+    /// `path_rules` entry, else the global `conflict_mode`. Rules whose
+    /// pattern does not parse never match.
+    pub fn conflict_mode_for(&self, rel_path: &str) -> ConflictMode {
+        self.conflict_mode_in(None, rel_path)
+    }
+
+    /// Conflict mode for `rel_path` under `root`: the first matching
+    /// `path_rules` entry, else the root's `conflict_mode`, else the global
+    /// one.
+    pub fn conflict_mode_in(&self, root: Option<&SyncRoot>, rel_path: &str) -> ConflictMode {
+        let rel_path = rel_path.trim_start_matches('/');
+        self.path_rules
+            .iter()
+            .find(|rule| {
+                globset::Glob::new(&rule.pattern)
+                    .is_ok_and(|glob| glob.compile_matcher().is_match(rel_path))
+            })
+            .map(|rule| rule.conflict_mode)
+            .or_else(|| root.and_then(|r| r.conflict_mode))
+            .unwrap_or(self.conflict_mode)
+    }
+
+    /// The `roots` entry syncing `prefix`, compared without surrounding
+    /// slashes.
+    pub fn root_for_prefix(&self, prefix: &str) -> Option<&SyncRoot> {
+        let prefix = prefix.trim_matches('/');
+        self.roots
+            .iter()
+            .find(|root| root.prefix.trim_matches('/') == prefix)
+    }
+
+    /// Local directory `prefix` syncs into: its `roots` entry's, else
+    /// `sync_root`.
+    pub fn local_root_for(&self, prefix: &str) -> Option<&Path> {
+        self.root_for_prefix(prefix)
+            .map(|root| root.local_path.as_path())
+            .or(self.sync_root.as_deref())
+    }
+
+    /// Whether `prefix` is, or lies under, one of `read_only_prefixes`.
+    pub fn is_read_only_prefix(&self, prefix: &str) -> bool {
+        let prefix = prefix.trim_matches('/');
+        self.read_only_prefixes.iter().any(|ro| {
+            let ro = ro.trim_matches('/');
+            !ro.is_empty()
+                && (prefix == ro
+                    || prefix
+                        .strip_prefix(ro)
+                        .is_some_and(|rest| rest.starts_with('/')))
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -102,6 +338,29 @@ pub struct SyncConfig {
 pub struct FuseConfig {
     /// Negative dentry cache TTL in seconds (default: 30)
     pub negative_cache_ttl_secs: u64,
+    /// Kernel attribute cache TTL in seconds (default: 5). Longer saves
+    /// index reads on stat-heavy workloads but delays size/mode changes
+    /// pushed from other devices
+    pub attr_ttl_secs: u64,
+    /// Kernel dentry cache TTL in seconds for names that exist (default: 5).
+    /// A file removed remotely keeps resolving for this long, just as one
+    /// added remotely stays hidden for `negative_cache_ttl_secs` after a miss
+    pub entry_ttl_secs: u64,
+    /// Show files under their real names (`README.md`) instead of as `.tc`
+    /// stubs (`README.md.tc`); the suffix mode is kept for debugging
+    /// (default false)
+    pub transparent_names: bool,
+    /// Index key components are AES-SIV encrypted names: shown decrypted
+    /// when the master key is loaded, masked otherwise (default false)
+    pub encrypted_names: bool,
+    /// Let users other than the one mounting access the mount (default
+    /// false). Unprivileged mounts also need `user_allow_other` in
+    /// `/etc/fuse.conf`
+    pub allow_other: bool,
+    /// Owner uid reported for every entry (default: the mounting process's)
+    pub uid: Option<u32>,
+    /// Owner gid reported for every entry (default: the mounting process's)
+    pub gid: Option<u32>,
     /// Disk cache directory for partial downloads
     pub cache_dir: PathBuf,
     /// Maximum disk cache size in MB
@@ -124,6 +383,9 @@ pub struct CryptoConfig {
     pub master_key_file: Option<PathBuf>,
     /// Path to the device identity file
     pub device_identity: Option<PathBuf>,
+    /// Derive each chunk's key from its plaintext hash so identical chunks
+    /// dedup across files; leaks chunk equality to the storage backend
+    pub convergent: bool,
 }
 
 impl Default for CryptoConfig {
@@ -135,6 +397,7 @@ impl Default for CryptoConfig {
             argon2_parallelism: 4,
             master_key_file: None,
             device_identity: None,
+            convergent: false,
         }
     }
 }
@@ -178,6 +441,7 @@ impl Default for DaemonConfig {
             metrics_addr: Some("127.0.0.1:9100".into()),
             log_level: "info".into(),
             log_format: "json".into(),
+            push_staging_dir: None,
         }
     }
 }
@@ -191,6 +455,11 @@ impl Default for StorageConfig {
             credentials_file: None,
             enforce_tls: false,
             ca_cert_path: None,
+            quota_bytes: None,
+            chunk_shard_depth: 0,
+            read_mirrors: Vec::new(),
+            read_mirror_backfill: false,
+            cache_control: None,
         }
     }
 }
@@ -201,17 +470,41 @@ impl Default for SyncConfig {
             nats_url: "nats://localhost:4222".into(),
             nats_tls: false,
             nats_ca_cert: None,
+            fleet_id: None,
+            consumer_suffix: String::new(),
+            nats_compress: false,
+            nats_max_payload: 1024 * 1024,
             state_db: PathBuf::from("~/.local/share/tcfsd/state.db"),
             workers: 0,
             max_retries: 3,
             device_identity: None,
             device_name: None,
-            conflict_mode: "auto".into(),
+            device_id: None,
+            conflict_mode: ConflictMode::Auto,
+            path_rules: Vec::new(),
+            auto_strategy: "device-order".into(),
             sync_git_dirs: false,
             git_sync_mode: "bundle".into(),
             sync_hidden_dirs: false,
             exclude_patterns: Vec::new(),
+            sync_empty_files: true,
             sync_root: None,
+            roots: Vec::new(),
+            push_concurrency: 0,
+            pack_small_files: false,
+            pack_threshold_bytes: 64 * 1024,
+            transfer_concurrency_min: 1,
+            transfer_concurrency_max: 16,
+            max_clock_skew_secs: 3600,
+            auto_pull_failure_threshold: 5,
+            auto_pull_cooldown_secs: 60,
+            mode_umask: 0o022,
+            keep_history: false,
+            history_max: 20,
+            chunk_filter_fp_rate: 0.01,
+            compress_skip_extensions: None,
+            read_only_prefixes: Vec::new(),
+            unsigned_manifest_devices: Vec::new(),
         }
     }
 }
@@ -220,6 +513,13 @@ impl Default for FuseConfig {
     fn default() -> Self {
         Self {
             negative_cache_ttl_secs: 30,
+            attr_ttl_secs: 5,
+            entry_ttl_secs: 5,
+            transparent_names: false,
+            encrypted_names: false,
+            allow_other: false,
+            uid: None,
+            gid: None,
             cache_dir: PathBuf::from("~/.cache/tcfs"),
             cache_max_mb: 10240,
         }
@@ -254,10 +554,13 @@ nats_url = "tls://nats.example.com:4222"
 nats_tls = true
 workers = 4
 max_retries = 5
+push_concurrency = 8
+mode_umask = 0o077
 sync_root = "/home/user/tcfs"
 
 [fuse]
 negative_cache_ttl_secs = 60
+attr_ttl_secs = 30
 cache_dir = "/var/cache/tcfs"
 cache_max_mb = 20480
 
@@ -276,11 +579,15 @@ argon2_parallelism = 8
         assert_eq!(config.storage.bucket, "my-bucket");
         assert!(config.sync.nats_tls);
         assert_eq!(config.sync.workers, 4);
+        assert_eq!(config.sync.push_concurrency, 8);
+        assert_eq!(config.sync.mode_umask, 0o077);
         assert_eq!(
             config.sync.sync_root,
             Some(PathBuf::from("/home/user/tcfs"))
         );
         assert_eq!(config.fuse.cache_max_mb, 20480);
+        assert_eq!(config.fuse.attr_ttl_secs, 30);
+        assert_eq!(config.fuse.entry_ttl_secs, 5);
         assert!(config.crypto.enabled);
         assert_eq!(config.crypto.argon2_mem_cost_kib, 131072);
         assert!(config.config_file_mode_check);
@@ -328,4 +635,137 @@ endpoint = "http://192.168.1.100:8333"
         assert_eq!(config.storage.endpoint, parsed.storage.endpoint);
         assert_eq!(config.sync.nats_url, parsed.sync.nats_url);
     }
+
+    #[test]
+    fn test_read_only_prefix_matching() {
+        let sync = SyncConfig {
+            read_only_prefixes: vec!["templates/".into(), "".into()],
+            ..SyncConfig::default()
+        };
+        assert!(sync.is_read_only_prefix("templates"));
+        assert!(sync.is_read_only_prefix("/templates/base"));
+        assert!(!sync.is_read_only_prefix("templates-dev"));
+        assert!(!sync.is_read_only_prefix("home"));
+    }
+
+    #[test]
+    fn test_path_rules_first_match_wins() {
+        let config: TcfsConfig = toml::from_str(
+            r#"
+[sync]
+conflict_mode = "defer"
+
+[[sync.path_rules]]
+pattern = "build/**"
+conflict_mode = "auto"
+
+[[sync.path_rules]]
+pattern = "**/*.rs"
+conflict_mode = "interactive"
+"#,
+        )
+        .unwrap();
+        let sync = &config.sync;
+        assert_eq!(sync.conflict_mode_for("build/out.rs"), ConflictMode::Auto);
+        assert_eq!(
+            sync.conflict_mode_for("src/main.rs"),
+            ConflictMode::Interactive
+        );
+        assert_eq!(
+            sync.conflict_mode_for("/main.rs"),
+            ConflictMode::Interactive
+        );
+        assert_eq!(sync.conflict_mode_for("notes.md"), ConflictMode::Defer);
+    }
+
+    #[test]
+    fn test_unknown_conflict_mode_fails_to_load() {
+        for (toml, at) in [
+            ("[sync]\nconflict_mode = \"Auto\"\n", "\"Auto\""),
+            (
+                "[[sync.path_rules]]\npattern = \"**\"\nconflict_mode = \"yolo\"\n",
+                "\"yolo\"",
+            ),
+            (
+                "[[sync.roots]]\nlocal_path = \"/a\"\nprefix = \"a\"\nconflict_mode = \"\"\n",
+                "\"\"",
+            ),
+        ] {
+            let err = toml::from_str::<TcfsConfig>(toml).unwrap_err().to_string();
+            assert!(
+                err.contains(&format!("invalid conflict_mode {at}")),
+                "{toml}: {err}"
+            );
+        }
+
+        let config: TcfsConfig =
+            toml::from_str("[sync]\nconflict_mode = \"interactive\"\n").unwrap();
+        assert_eq!(config.sync.conflict_mode, ConflictMode::Interactive);
+        assert_eq!(config.sync.conflict_mode.to_string(), "interactive");
+        assert_eq!(TcfsConfig::default().sync.conflict_mode, ConflictMode::Auto);
+    }
+
+    #[test]
+    fn test_sync_roots_route_by_prefix() {
+        let config: TcfsConfig = toml::from_str(
+            r#"
+[sync]
+conflict_mode = "defer"
+sync_root = "/home/user/tcfs"
+
+[[sync.roots]]
+local_path = "/home/user/docs"
+prefix = "docs"
+conflict_mode = "auto"
+
+[[sync.roots]]
+local_path = "/home/user/photos"
+prefix = "/media/photos/"
+
+[[sync.path_rules]]
+pattern = "**/*.lock"
+conflict_mode = "interactive"
+"#,
+        )
+        .unwrap();
+        let sync = &config.sync;
+        assert_eq!(sync.roots.len(), 2);
+        let docs = sync.root_for_prefix("docs/").unwrap();
+        assert_eq!(docs.local_path, PathBuf::from("/home/user/docs"));
+        let photos = sync.root_for_prefix("media/photos").unwrap();
+        assert_eq!(photos.conflict_mode, None);
+        assert!(sync.root_for_prefix("media").is_none());
+
+        assert_eq!(
+            sync.conflict_mode_in(Some(docs), "a.md"),
+            ConflictMode::Auto
+        );
+        assert_eq!(
+            sync.conflict_mode_in(Some(docs), "Cargo.lock"),
+            ConflictMode::Interactive
+        );
+        assert_eq!(
+            sync.conflict_mode_in(Some(photos), "a.jpg"),
+            ConflictMode::Defer
+        );
+
+        // Prefixes without a root fall back to sync_root
+        assert_eq!(
+            sync.local_root_for("media/photos"),
+            Some(Path::new("/home/user/photos"))
+        );
+        assert_eq!(
+            sync.local_root_for("other"),
+            Some(Path::new("/home/user/tcfs"))
+        );
+    }
+
+    #[test]
+    fn test_fleet_id_defaults_to_bucket() {
+        let mut config = TcfsConfig::default();
+        config.storage.bucket = "team-files".into();
+        assert_eq!(config.fleet_id(), "team-files");
+        config.sync.fleet_id = Some("lab".into());
+        assert_eq!(config.fleet_id(), "lab");
+    }
 }
@@ -0,0 +1,380 @@
+//! Remote index entry format — parse and write
+//!
+//! Index entries live at `{prefix}/index/{rel_path}` and map a file's
+//! relative path to its manifest plus the metadata needed for `getattr`
+//! and `readdir` without fetching the manifest itself.
+//!
+//! Format (UTF-8, `key=value` per line, Unix newlines):
+//! ```text
+//! version=2
+//! manifest_hash=4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e239
+//! size=94371840
+//! chunks=23
+//! modified=1700000000
+//! mode=755
+//! ```
+//!
+//! Rules:
+//! - v1 entries have no `version` line and no `modified` field
+//! - Symlinks (v2) carry `symlink=<target>` and no `manifest_hash`
+//! - `mode` (v2) holds the source file's Unix permission bits in octal
+//! - Tombstones (v2) carry `deleted=1` and no `manifest_hash`; readers hide them
+//! - Packed files (v2) carry `pack=<id>`, `pack_offset` and `pack_len`: their
+//!   content is that byte range of `{prefix}/packs/<id>`, no manifest is
+//!   stored, and `manifest_hash` is the BLAKE3 of the content alone
+//! - `version`, when present, must be the first line
+//! - Unknown keys are ignored; unknown versions are rejected
+
+use anyhow::{Context, Result};
+
+/// Index entry format written by this build.
+pub const INDEX_VERSION: u32 = 2;
+
+/// Marker object name recording a directory, at `{prefix}/index/{dir}/.tcfsdir`.
+///
+/// Object stores have no directories, so an empty directory only survives a
+/// push if something is written beneath it. Markers are never listed as files.
+pub const DIR_MARKER: &str = ".tcfsdir";
+
+/// Metadata stored in an index entry at `{prefix}/index/{rel_path}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// Format version (1 = legacy, no `version` line)
+    pub version: u32,
+    /// BLAKE3 hex hash naming the manifest under `{prefix}/manifests/`
+    pub manifest_hash: String,
+    /// File size in bytes
+    pub size: u64,
+    /// Number of FastCDC chunks
+    pub chunks: usize,
+    /// Last-modified time (Unix epoch seconds); always `None` for v1
+    pub modified: Option<u64>,
+    /// Link target when the entry is a symlink (v2 only)
+    pub symlink: Option<String>,
+    /// Unix permission bits of the source file (v2 only)
+    pub mode: Option<u32>,
+    /// True if the file was deleted; the entry is a tombstone (v2 only)
+    pub deleted: bool,
+    /// Where the content lives when the file was packed (v2 only)
+    pub pack: Option<PackRef>,
+}
+
+/// A file's byte range within a packfile.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct PackRef {
+    /// Pack id, naming `{prefix}/packs/{id}`
+    pub id: String,
+    /// Offset of the file's first byte in the pack
+    pub offset: u64,
+    /// Length of the file in bytes
+    pub len: u64,
+}
+
+impl IndexEntry {
+    /// Build an entry in the current format.
+    pub fn new(manifest_hash: &str, size: u64, chunks: usize, modified: Option<u64>) -> Self {
+        IndexEntry {
+            version: INDEX_VERSION,
+            manifest_hash: manifest_hash.to_string(),
+            size,
+            chunks,
+            modified,
+            symlink: None,
+            mode: None,
+            deleted: false,
+            pack: None,
+        }
+    }
+
+    /// Build a symlink entry pointing at `target`.
+    ///
+    /// Symlinks have no manifest; `size` is the length of the target, as
+    /// `lstat` reports it.
+    pub fn new_symlink(target: &str, modified: Option<u64>) -> Self {
+        IndexEntry {
+            version: INDEX_VERSION,
+            manifest_hash: String::new(),
+            size: target.len() as u64,
+            chunks: 0,
+            modified,
+            symlink: Some(target.to_string()),
+            mode: None,
+            deleted: false,
+            pack: None,
+        }
+    }
+
+    /// Build a tombstone recording that the file at this path was deleted.
+    pub fn new_tombstone(modified: Option<u64>) -> Self {
+        IndexEntry {
+            version: INDEX_VERSION,
+            manifest_hash: String::new(),
+            size: 0,
+            chunks: 0,
+            modified,
+            symlink: None,
+            mode: None,
+            deleted: true,
+            pack: None,
+        }
+    }
+
+    /// True if this entry describes a symlink rather than file content.
+    pub fn is_symlink(&self) -> bool {
+        self.symlink.is_some()
+    }
+
+    /// True if the content is stored in a packfile rather than as chunks.
+    pub fn is_packed(&self) -> bool {
+        self.pack.is_some()
+    }
+
+    /// True if this entry is a deletion tombstone.
+    pub fn is_tombstone(&self) -> bool {
+        self.deleted
+    }
+
+    /// Parse an index entry from its text content.
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut version = 1;
+        let mut manifest_hash = None;
+        let mut size = None;
+        let mut chunks = None;
+        let mut modified = None;
+        let mut symlink = None;
+        let mut mode = None;
+        let mut deleted = false;
+        let mut pack_id = None;
+        let mut pack_offset = None;
+        let mut pack_len = None;
+
+        for (lineno, line) in content.lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let Some((k, v)) = line.split_once('=') else {
+                continue;
+            };
+            match k {
+                "version" => {
+                    anyhow::ensure!(lineno == 0, "version must be the first line");
+                    version = v
+                        .parse::<u32>()
+                        .with_context(|| format!("invalid version: {v}"))?;
+                    anyhow::ensure!(
+                        (1..=INDEX_VERSION).contains(&version),
+                        "unsupported index entry version: {version}"
+                    );
+                }
+                "manifest_hash" => manifest_hash = Some(v.to_string()),
+                "size" => size = Some(v.parse::<u64>().context("invalid size")?),
+                "chunks" => chunks = Some(v.parse::<usize>().context("invalid chunks")?),
+                "modified" if version >= 2 => {
+                    modified = Some(v.parse::<u64>().context("invalid modified")?)
+                }
+                "symlink" if version >= 2 => symlink = Some(v.to_string()),
+                "mode" if version >= 2 => {
+                    mode = Some(u32::from_str_radix(v, 8).context("invalid mode")?)
+                }
+                "deleted" if version >= 2 => deleted = v == "1",
+                "pack" if version >= 2 => pack_id = Some(v.to_string()),
+                "pack_offset" if version >= 2 => {
+                    pack_offset = Some(v.parse::<u64>().context("invalid pack_offset")?)
+                }
+                "pack_len" if version >= 2 => {
+                    pack_len = Some(v.parse::<u64>().context("invalid pack_len")?)
+                }
+                _ => {}
+            }
+        }
+
+        let manifest_hash = match manifest_hash {
+            Some(hash) => hash,
+            None if symlink.is_some() || deleted => String::new(),
+            None => anyhow::bail!("missing manifest_hash"),
+        };
+        let pack = match pack_id {
+            Some(id) => Some(PackRef {
+                id,
+                offset: pack_offset.context("missing pack_offset")?,
+                len: pack_len.context("missing pack_len")?,
+            }),
+            None => None,
+        };
+
+        Ok(IndexEntry {
+            version,
+            manifest_hash,
+            size: size.context("missing size")?,
+            chunks: chunks.unwrap_or(0),
+            modified,
+            symlink,
+            mode,
+            deleted,
+            pack,
+        })
+    }
+
+    /// Parse an index entry from raw bytes read from storage.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let text = std::str::from_utf8(data).context("index entry is not valid UTF-8")?;
+        Self::parse(text)
+    }
+
+    /// Serialize to the wire format for this entry's version.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_entry_string().into_bytes()
+    }
+
+    /// Serialize to the text format for this entry's version.
+    ///
+    /// v1 entries are written without a `version` line so older readers
+    /// continue to accept them.
+    pub fn to_entry_string(&self) -> String {
+        if self.version < 2 {
+            return format!(
+                "manifest_hash={}\nsize={}\nchunks={}\n",
+                self.manifest_hash, self.size, self.chunks
+            );
+        }
+        let mut out = format!("version={}\n", self.version);
+        if self.symlink.is_none() && !self.deleted {
+            out.push_str(&format!("manifest_hash={}\n", self.manifest_hash));
+        }
+        out.push_str(&format!("size={}\nchunks={}\n", self.size, self.chunks));
+        if let Some(modified) = self.modified {
+            out.push_str(&format!("modified={modified}\n"));
+        }
+        if let Some(mode) = self.mode {
+            out.push_str(&format!("mode={mode:o}\n"));
+        }
+        if let Some(target) = &self.symlink {
+            out.push_str(&format!("symlink={target}\n"));
+        }
+        if self.deleted {
+            out.push_str("deleted=1\n");
+        }
+        if let Some(pack) = &self.pack {
+            out.push_str(&format!(
+                "pack={}\npack_offset={}\npack_len={}\n",
+                pack.id, pack.offset, pack.len
+            ));
+        }
+        out
+    }
+
+    /// Manifest path under `{prefix}/manifests/`.
+    pub fn manifest_path(&self, prefix: &str) -> String {
+        crate::layout::RemoteLayout::new(prefix).manifest_key(&self.manifest_hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn v1_roundtrip() {
+        let raw = "manifest_hash=abc123\nsize=4096\nchunks=1\n";
+        let entry = IndexEntry::parse(raw).unwrap();
+        assert_eq!(entry.version, 1);
+        assert_eq!(entry.manifest_hash, "abc123");
+        assert_eq!(entry.size, 4096);
+        assert_eq!(entry.chunks, 1);
+        assert_eq!(entry.modified, None);
+        assert_eq!(entry.to_entry_string(), raw);
+    }
+
+    #[test]
+    fn v2_roundtrip() {
+        let entry = IndexEntry::new("def456", 94_371_840, 23, Some(1_700_000_000));
+        let bytes = entry.to_bytes();
+        assert!(bytes.starts_with(b"version=2\n"));
+
+        let reparsed = IndexEntry::from_bytes(&bytes).unwrap();
+        assert_eq!(reparsed, entry);
+        assert_eq!(reparsed.modified, Some(1_700_000_000));
+    }
+
+    #[test]
+    fn mode_roundtrip_as_octal() {
+        let mut entry = IndexEntry::new("abc", 10, 1, None);
+        entry.mode = Some(0o755);
+        let text = entry.to_entry_string();
+        assert!(text.contains("mode=755\n"));
+        assert_eq!(IndexEntry::parse(&text).unwrap().mode, Some(0o755));
+
+        assert!(IndexEntry::parse("version=2\nmanifest_hash=a\nsize=1\nmode=9z\n").is_err());
+    }
+
+    #[test]
+    fn symlink_roundtrip() {
+        let entry = IndexEntry::new_symlink("../shared/config.toml", Some(1_700_000_000));
+        let text = entry.to_entry_string();
+        assert!(text.contains("symlink=../shared/config.toml\n"));
+        assert!(!text.contains("manifest_hash"));
+
+        let reparsed = IndexEntry::parse(&text).unwrap();
+        assert_eq!(reparsed, entry);
+        assert!(reparsed.is_symlink());
+        assert_eq!(reparsed.size, "../shared/config.toml".len() as u64);
+    }
+
+    #[test]
+    fn tombstone_roundtrip() {
+        let entry = IndexEntry::new_tombstone(Some(1_700_000_000));
+        let text = entry.to_entry_string();
+        assert!(text.contains("deleted=1\n"));
+        assert!(!text.contains("manifest_hash"));
+
+        let reparsed = IndexEntry::parse(&text).unwrap();
+        assert_eq!(reparsed, entry);
+        assert!(reparsed.is_tombstone());
+        assert!(!IndexEntry::new("abc", 1, 1, None).is_tombstone());
+    }
+
+    #[test]
+    fn pack_ref_roundtrip() {
+        let mut entry = IndexEntry::new("abc", 12, 0, Some(1_700_000_000));
+        entry.pack = Some(PackRef {
+            id: "p1".into(),
+            offset: 4096,
+            len: 12,
+        });
+        let text = entry.to_entry_string();
+        assert!(text.contains("pack=p1\npack_offset=4096\npack_len=12\n"));
+
+        let reparsed = IndexEntry::parse(&text).unwrap();
+        assert_eq!(reparsed, entry);
+        assert!(reparsed.is_packed());
+        assert!(IndexEntry::parse("version=2\nmanifest_hash=a\nsize=1\npack=p1\n").is_err());
+    }
+
+    #[test]
+    fn v1_ignores_symlink_key() {
+        let raw = "manifest_hash=abc\nsize=1\nchunks=1\nsymlink=elsewhere\n";
+        let entry = IndexEntry::parse(raw).unwrap();
+        assert!(!entry.is_symlink());
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        let raw = "version=99\nmanifest_hash=abc\nsize=1\nchunks=1\n";
+        let err = IndexEntry::parse(raw).unwrap_err();
+        assert!(err.to_string().contains("unsupported index entry version"));
+
+        assert!(IndexEntry::parse("version=0\nmanifest_hash=abc\nsize=1\n").is_err());
+        assert!(IndexEntry::parse("version=two\nmanifest_hash=abc\nsize=1\n").is_err());
+    }
+
+    #[test]
+    fn missing_manifest_hash_fails() {
+        assert!(IndexEntry::parse("size=1\nchunks=1\n").is_err());
+    }
+
+    #[test]
+    fn manifest_path_trims_prefix() {
+        let entry = IndexEntry::new("abc123", 1, 1, None);
+        assert_eq!(entry.manifest_path("mydata/"), "mydata/manifests/abc123");
+    }
+}
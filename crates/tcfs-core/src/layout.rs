@@ -0,0 +1,237 @@
+//! Object key layout under a remote prefix
+//!
+//! Everything pushed under a prefix lives in a few namespaces:
+//! `{prefix}/chunks/{hash}`, `{prefix}/manifests/{hash}`,
+//! `{prefix}/index/{rel_path}`, `{prefix}/history/{rel_path}/`,
+//! `{prefix}/trees/{root_hash}.json`, `{prefix}/snapshots/` and
+//! `{prefix}/packs/{id}` (small files bundled together, each pack beside its
+//! `{prefix}/packs/{id}.idx`), plus `{prefix}/gc/condemned.json`, the chunks
+//! the last history prune found unreferenced.
+//! `RemoteLayout` normalizes the prefix once (no leading or trailing
+//! slashes), so the engine, the FUSE driver and the FFI bridge all build
+//! identical keys. An empty prefix places the tree at the bucket root
+//! (`index/a.txt`, never `/index/a.txt`).
+//!
+//! Chunks can be fanned out over subdirectories named by leading hex pairs of
+//! their hash (`chunks/ab/abcd...` at depth 1, `chunks/ab/cd/abcd...` at
+//! depth 2) so no single listing grows to millions of keys. Manifests record
+//! the depth their chunks were written with.
+
+/// Deepest chunk fan-out supported (`storage.chunk_shard_depth`).
+pub const MAX_CHUNK_SHARD_DEPTH: u8 = 4;
+
+/// Builds storage keys for one remote prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteLayout {
+    prefix: String,
+    chunk_shard_depth: u8,
+}
+
+impl RemoteLayout {
+    /// Layout for `prefix`, ignoring leading and trailing slashes.
+    pub fn new(prefix: &str) -> Self {
+        RemoteLayout {
+            prefix: prefix.trim_matches('/').to_string(),
+            chunk_shard_depth: 0,
+        }
+    }
+
+    /// The same layout with chunks fanned out `depth` levels deep (0 = flat,
+    /// capped at [`MAX_CHUNK_SHARD_DEPTH`]).
+    pub fn with_chunk_shard_depth(mut self, depth: u8) -> Self {
+        self.chunk_shard_depth = depth.min(MAX_CHUNK_SHARD_DEPTH);
+        self
+    }
+
+    /// Levels of chunk fan-out (0 = flat).
+    pub fn chunk_shard_depth(&self) -> u8 {
+        self.chunk_shard_depth
+    }
+
+    /// The normalized prefix (empty for the bucket root).
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// `{prefix}/chunks/{hash}`, or `{prefix}/chunks/{hh}/.../{hash}` with one
+    /// directory per shard level taken from the leading hex pairs of `hash`.
+    pub fn chunk_key(&self, hash: &str) -> String {
+        let mut key = String::from("chunks/");
+        for level in 0..self.chunk_shard_depth as usize {
+            match hash.get(level * 2..level * 2 + 2) {
+                Some(pair) => {
+                    key.push_str(pair);
+                    key.push('/');
+                }
+                None => break,
+            }
+        }
+        key.push_str(hash);
+        self.join(&key)
+    }
+
+    /// `{prefix}/chunks/`, for listing every chunk (recursively, when sharded).
+    pub fn chunks_dir(&self) -> String {
+        self.join("chunks/")
+    }
+
+    /// `{prefix}/manifests/{hash}`
+    pub fn manifest_key(&self, hash: &str) -> String {
+        self.join(&format!("manifests/{hash}"))
+    }
+
+    /// `{prefix}/manifests/`, for listing every manifest.
+    pub fn manifests_dir(&self) -> String {
+        self.join("manifests/")
+    }
+
+    /// `{prefix}/index/{rel}`, with empty path segments dropped.
+    pub fn index_key(&self, rel: &str) -> String {
+        self.join(&format!("index/{}", clean_rel(rel)))
+    }
+
+    /// `{prefix}/index/{rel}/`, or `{prefix}/index/` for the root.
+    pub fn index_dir(&self, rel: &str) -> String {
+        let rel = clean_rel(rel);
+        if rel.is_empty() {
+            self.join("index/")
+        } else {
+            self.join(&format!("index/{rel}/"))
+        }
+    }
+
+    /// `{prefix}/history/{rel}/`, holding the version pointers for `rel`
+    /// (`{prefix}/history/` for `""`).
+    pub fn history_dir(&self, rel: &str) -> String {
+        let rel = clean_rel(rel);
+        if rel.is_empty() {
+            self.join("history/")
+        } else {
+            self.join(&format!("history/{rel}/"))
+        }
+    }
+
+    /// `{prefix}/trees/{root_hash}.json`
+    pub fn tree_key(&self, root_hash: &str) -> String {
+        self.join(&format!("trees/{root_hash}.json"))
+    }
+
+    /// `{prefix}/snapshots/`, holding one pointer per snapshot.
+    pub fn snapshots_dir(&self) -> String {
+        self.join("snapshots/")
+    }
+
+    /// `{prefix}/gc/condemned.json`, unreferenced chunks awaiting deletion.
+    pub fn condemned_key(&self) -> String {
+        self.join("gc/condemned.json")
+    }
+
+    /// `{prefix}/packs/{id}`, the concatenated content of a packfile.
+    pub fn pack_key(&self, id: &str) -> String {
+        self.join(&format!("packs/{id}"))
+    }
+
+    /// `{prefix}/packs/{id}.idx`, the pack index listing what a pack holds.
+    pub fn pack_index_key(&self, id: &str) -> String {
+        self.join(&format!("packs/{id}.idx"))
+    }
+
+    fn join(&self, rest: &str) -> String {
+        if self.prefix.is_empty() {
+            rest.to_string()
+        } else {
+            format!("{}/{rest}", self.prefix)
+        }
+    }
+}
+
+/// The prefix the manifest key `{prefix}/manifests/{hash}` was written
+/// under, or `None` if `key` is not a manifest key.
+pub fn manifest_key_prefix(key: &str) -> Option<&str> {
+    let (prefix, hash) = match key.strip_prefix("manifests/") {
+        Some(hash) => ("", hash),
+        None => key.rsplit_once("/manifests/")?,
+    };
+    (!hash.is_empty() && !hash.contains('/')).then_some(prefix)
+}
+
+/// `rel` with leading, trailing and repeated slashes removed.
+fn clean_rel(rel: &str) -> String {
+    rel.split('/')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trailing_and_leading_slashes_are_ignored() {
+        for prefix in ["data", "data/", "/data", "/data//"] {
+            let layout = RemoteLayout::new(prefix);
+            assert_eq!(layout.prefix(), "data");
+            assert_eq!(layout.chunk_key("abc"), "data/chunks/abc");
+            assert_eq!(layout.manifest_key("abc"), "data/manifests/abc");
+            assert_eq!(layout.manifests_dir(), "data/manifests/");
+            assert_eq!(layout.chunks_dir(), "data/chunks/");
+            assert_eq!(layout.index_key("a.txt"), "data/index/a.txt");
+            assert_eq!(layout.index_dir(""), "data/index/");
+            assert_eq!(layout.pack_key("p1"), "data/packs/p1");
+            assert_eq!(layout.pack_index_key("p1"), "data/packs/p1.idx");
+        }
+    }
+
+    #[test]
+    fn empty_prefix_keys_start_at_bucket_root() {
+        for prefix in ["", "/"] {
+            let layout = RemoteLayout::new(prefix);
+            assert_eq!(layout.chunk_key("abc"), "chunks/abc");
+            assert_eq!(layout.manifest_key("abc"), "manifests/abc");
+            assert_eq!(layout.index_key("/a.txt"), "index/a.txt");
+            assert_eq!(layout.index_dir("/"), "index/");
+        }
+    }
+
+    #[test]
+    fn manifest_keys_name_their_prefix() {
+        for prefix in ["", "data", "team/proj"] {
+            let key = RemoteLayout::new(prefix).manifest_key("abc");
+            assert_eq!(manifest_key_prefix(&key), Some(prefix), "{key}");
+        }
+        assert_eq!(manifest_key_prefix("data/index/a.txt"), None);
+        assert_eq!(manifest_key_prefix("data/manifests/"), None);
+    }
+
+    #[test]
+    fn chunk_keys_fan_out_by_leading_hex_pairs() {
+        let layout = RemoteLayout::new("data");
+        assert_eq!(layout.chunk_key("abcdef"), "data/chunks/abcdef");
+        let sharded = layout.clone().with_chunk_shard_depth(1);
+        assert_eq!(sharded.chunk_key("abcdef"), "data/chunks/ab/abcdef");
+        let deeper = layout.with_chunk_shard_depth(2);
+        assert_eq!(deeper.chunk_key("abcdef"), "data/chunks/ab/cd/abcdef");
+        assert_eq!(deeper.chunks_dir(), "data/chunks/");
+        assert_eq!(deeper.manifest_key("abcdef"), "data/manifests/abcdef");
+
+        let capped = RemoteLayout::new("").with_chunk_shard_depth(9);
+        assert_eq!(capped.chunk_shard_depth(), MAX_CHUNK_SHARD_DEPTH);
+        assert_eq!(capped.chunk_key("abcdef"), "chunks/ab/cd/ef/abcdef");
+    }
+
+    #[test]
+    fn nested_rel_paths_are_normalized() {
+        let layout = RemoteLayout::new("team/proj/");
+        assert_eq!(
+            layout.index_key("/src//lib/main.rs"),
+            "team/proj/index/src/lib/main.rs"
+        );
+        assert_eq!(layout.index_dir("src/lib/"), "team/proj/index/src/lib/");
+        assert_eq!(layout.index_dir("/src"), "team/proj/index/src/");
+        assert_eq!(
+            layout.history_dir("/docs/notes.md"),
+            "team/proj/history/docs/notes.md/"
+        );
+    }
+}
@@ -1,7 +1,11 @@
 //! tcfs-core: shared types, config parsing, and protobuf definitions for the tcfs workspace.
 
+pub mod clock;
 pub mod config;
 pub mod error;
+pub mod index;
+pub mod layout;
+pub mod paths;
 pub mod types;
 
 pub use error::{TcfsError, TcfsResult};
@@ -0,0 +1,266 @@
+//! Relative path handling for paths that cross device boundaries.
+//!
+//! `rel_path` values arrive from other devices (NATS events, manifests,
+//! index keys) and always use `/` as the separator on the wire, though
+//! older Windows senders may use `\`. Before one becomes a local path it
+//! must be checked so it cannot escape the sync root.
+//!
+//! Devices also disagree on case: a tree pushed from Linux may hold both
+//! `README.md` and `readme.md`, which name one file on macOS and Windows.
+//! [`case_collisions`] finds such paths and [`case_insensitive_renames`]
+//! picks distinct local names for them.
+
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+/// Convert a wire-format relative path into a local relative path.
+///
+/// Accepts `/` and `\` separators and emits the platform separator.
+/// Empty and `.` components are dropped. Rejects empty paths, absolute
+/// paths (leading separator or a drive prefix like `C:`), and any `..`
+/// component, so the result can always be joined safely onto a root.
+pub fn normalize_rel_path(rel: &str) -> Result<PathBuf> {
+    if rel.starts_with('/') || rel.starts_with('\\') || has_drive_prefix(rel) {
+        anyhow::bail!("absolute path not allowed: {rel}");
+    }
+
+    let mut out = PathBuf::new();
+    for part in rel.split(['/', '\\']) {
+        match part {
+            "" | "." => continue,
+            ".." => anyhow::bail!("path traversal not allowed: {rel}"),
+            _ => out.push(part),
+        }
+    }
+
+    anyhow::ensure!(!out.as_os_str().is_empty(), "empty relative path");
+    anyhow::ensure!(
+        Path::new(&out)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_))),
+        "invalid relative path: {rel}"
+    );
+    Ok(out)
+}
+
+/// Check that a symlink stored at the wire-format path `rel` and pointing
+/// at `target` resolves inside the sync root.
+///
+/// The target must be relative, and its `..` components must not climb
+/// above the root from the link's directory. Targets are compared
+/// lexically, which is enough because every link restored under the root
+/// passes the same check.
+pub fn check_symlink_target(rel: &str, target: &str) -> Result<()> {
+    anyhow::ensure!(!target.is_empty(), "empty symlink target: {rel}");
+    if target.starts_with('/') || target.starts_with('\\') || has_drive_prefix(target) {
+        anyhow::bail!("absolute symlink target not allowed: {rel} -> {target}");
+    }
+    let mut depth = normalize_rel_path(rel)?.components().count() - 1;
+    for part in target.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                depth = depth.checked_sub(1).ok_or_else(|| {
+                    anyhow::anyhow!("symlink target escapes the sync root: {rel} -> {target}")
+                })?;
+            }
+            _ => depth += 1,
+        }
+    }
+    Ok(())
+}
+
+/// Fail if a directory on the way from `root` to the relative path `rel`
+/// (not `rel` itself) is a symlink, so writing `root.join(rel)` cannot be
+/// redirected outside `root`. Missing directories are fine: they will be
+/// created as real ones.
+pub fn check_no_symlink_ancestors(root: &Path, rel: &Path) -> Result<()> {
+    let mut dir = root.to_path_buf();
+    for part in rel.parent().into_iter().flat_map(Path::components) {
+        dir.push(part);
+        match std::fs::symlink_metadata(&dir) {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                anyhow::bail!("refusing to write through symlink: {}", dir.display())
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+fn has_drive_prefix(rel: &str) -> bool {
+    let bytes = rel.as_bytes();
+    bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+/// Relative paths that name the same file on a case-insensitive filesystem,
+/// one group per collision. Each group is sorted, and groups are ordered by
+/// their first path.
+pub fn case_collisions<'a>(rels: impl IntoIterator<Item = &'a str>) -> Vec<Vec<&'a str>> {
+    let mut by_folded: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for rel in rels {
+        by_folded.entry(rel.to_lowercase()).or_default().push(rel);
+    }
+    let mut groups: Vec<_> = by_folded
+        .into_values()
+        .map(|mut group| {
+            group.sort_unstable();
+            group.dedup();
+            group
+        })
+        .filter(|group| group.len() > 1)
+        .collect();
+    groups.sort_unstable();
+    groups
+}
+
+/// Local names for `rels` that stay distinct on a case-insensitive
+/// filesystem, for the paths that need one.
+///
+/// In each group of [`case_collisions`] the first path in byte order keeps
+/// its name and each later one becomes `name (2).ext`, `name (3).ext`, ...,
+/// skipping names already taken. Returns original → renamed path.
+pub fn case_insensitive_renames(rels: &[&str]) -> HashMap<String, String> {
+    let mut taken: HashSet<String> = rels.iter().map(|rel| rel.to_lowercase()).collect();
+    let mut renames = HashMap::new();
+    for group in case_collisions(rels.iter().copied()) {
+        for rel in &group[1..] {
+            let renamed = (2..)
+                .map(|n| numbered(rel, n))
+                .find(|candidate| taken.insert(candidate.to_lowercase()))
+                .expect("some numbered name is free");
+            renames.insert(rel.to_string(), renamed);
+        }
+    }
+    renames
+}
+
+/// `dir/name.ext` as `dir/name (n).ext`; a leading dot does not start an
+/// extension.
+fn numbered(rel: &str, n: usize) -> String {
+    let (dir, name) = match rel.rsplit_once('/') {
+        Some((dir, name)) => (Some(dir), name),
+        None => (None, rel),
+    };
+    let (stem, ext) = match name.rfind('.') {
+        Some(i) if i > 0 => name.split_at(i),
+        _ => (name, ""),
+    };
+    match dir {
+        Some(dir) => format!("{dir}/{stem} ({n}){ext}"),
+        None => format!("{stem} ({n}){ext}"),
+    }
+}
+
+/// Whether the filesystem holding the existing directory `dir` ignores case
+/// in file names, probed by creating a lowercase file and looking it up in
+/// uppercase.
+pub fn is_case_insensitive(dir: &Path) -> std::io::Result<bool> {
+    let name = format!(".tcfs-case-probe-{}", std::process::id());
+    let probe = dir.join(&name);
+    std::fs::write(&probe, b"")?;
+    let insensitive = std::fs::symlink_metadata(dir.join(name.to_uppercase())).is_ok();
+    std::fs::remove_file(&probe)?;
+    Ok(insensitive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_separators_to_platform_path() {
+        let expected: PathBuf = ["a", "b", "c"].iter().collect();
+        assert_eq!(normalize_rel_path("a/b/c").unwrap(), expected);
+        assert_eq!(normalize_rel_path("a\\b\\c").unwrap(), expected);
+        assert_eq!(normalize_rel_path("a//./b/c/").unwrap(), expected);
+    }
+
+    #[test]
+    fn rejects_traversal() {
+        assert!(normalize_rel_path("../../etc/passwd").is_err());
+        assert!(normalize_rel_path("a/../../b").is_err());
+        assert!(normalize_rel_path("a\\..\\b").is_err());
+    }
+
+    #[test]
+    fn rejects_absolute_and_empty() {
+        assert!(normalize_rel_path("/etc/passwd").is_err());
+        assert!(normalize_rel_path("\\Windows\\System32").is_err());
+        assert!(normalize_rel_path("C:\\Users").is_err());
+        assert!(normalize_rel_path("c:/Users").is_err());
+        assert!(normalize_rel_path("").is_err());
+        assert!(normalize_rel_path("./").is_err());
+    }
+
+    #[test]
+    fn joined_path_stays_under_root() {
+        let root = Path::new("/srv/tcfs");
+        let joined = root.join(normalize_rel_path("docs/readme.md").unwrap());
+        assert!(joined.starts_with(root));
+    }
+
+    #[test]
+    fn symlink_targets_stay_inside_root() {
+        assert!(check_symlink_target("a/link", "b/c").is_ok());
+        assert!(check_symlink_target("a/link", "../b").is_ok());
+        assert!(check_symlink_target("a/b/link", "../../c").is_ok());
+        assert!(check_symlink_target("link", "../outside").is_err());
+        assert!(check_symlink_target("a/link", "../../outside").is_err());
+        assert!(check_symlink_target("a/link", "b/../../../x").is_err());
+        assert!(check_symlink_target("a", "/etc").is_err());
+        assert!(check_symlink_target("a", "C:\\Windows").is_err());
+        assert!(check_symlink_target("a", "").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_ancestor_is_refused() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let tmp = tmp.path();
+        std::fs::create_dir_all(tmp.join("real")).unwrap();
+        std::os::unix::fs::symlink("/tmp", tmp.join("link")).unwrap();
+
+        assert!(check_no_symlink_ancestors(tmp, Path::new("real/file")).is_ok());
+        assert!(check_no_symlink_ancestors(tmp, Path::new("new/dir/file")).is_ok());
+        // The final component may itself be a link: it is replaced, not followed
+        assert!(check_no_symlink_ancestors(tmp, Path::new("link")).is_ok());
+        assert!(check_no_symlink_ancestors(tmp, Path::new("link/passwd")).is_err());
+    }
+
+    #[test]
+    fn finds_case_collisions() {
+        let rels = [
+            "docs/README.md",
+            "a.txt",
+            "docs/readme.md",
+            "Docs/ReadMe.md",
+            "b.txt",
+        ];
+        assert_eq!(
+            case_collisions(rels),
+            vec![vec!["Docs/ReadMe.md", "docs/README.md", "docs/readme.md"]]
+        );
+        assert!(case_collisions(["a.txt", "docs/a.txt"]).is_empty());
+    }
+
+    #[test]
+    fn renames_later_collisions_deterministically() {
+        let rels = [
+            "readme.md",
+            "README.md",
+            "README (2).md",
+            ".bashrc",
+            ".BASHRC",
+        ];
+        let renames = case_insensitive_renames(&rels);
+        assert_eq!(renames.len(), 2);
+        // "README (2).md" is taken, so the next free number is used
+        assert_eq!(renames["readme.md"], "readme (3).md");
+        assert_eq!(renames[".bashrc"], ".bashrc (2)");
+        assert_eq!(numbered("a/b/archive.tar.gz", 2), "a/b/archive.tar (2).gz");
+    }
+}
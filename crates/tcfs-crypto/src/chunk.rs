@@ -2,23 +2,66 @@
 //!
 //! Encrypted chunk format (binary):
 //! ```text
-//! [24 bytes: random nonce][N bytes: ciphertext][16 bytes: Poly1305 tag]
+//! [24 bytes: nonce][N bytes: ciphertext][16 bytes: Poly1305 tag]
 //! AAD = chunk_index (8 bytes, big-endian) || file_id (32 bytes)
 //! ```
 //!
 //! The AAD (Additional Authenticated Data) binds each chunk to its position
 //! and file, preventing chunk reordering and cross-file substitution attacks.
+//!
+//! Nonces are random by default. [`NonceStrategy::Counter`] derives them from
+//! the file key, chunk index and file id instead, so they are unique without
+//! relying on the RNG and re-encrypting a chunk reproduces its ciphertext.
 
 use chacha20poly1305::{
     aead::{Aead, KeyInit, Payload},
     XChaCha20Poly1305, XNonce,
 };
+use hkdf::Hkdf;
 use rand::RngCore;
+use sha2::Sha256;
 
 use crate::keys::FileKey;
 use crate::NONCE_SIZE;
 
-/// Encrypt a single chunk with XChaCha20-Poly1305.
+/// How chunk nonces are chosen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NonceStrategy {
+    /// 192 random bits per chunk
+    #[default]
+    Random,
+    /// `HKDF-SHA256(file_key, "tcfs-chunk-nonce" || chunk_index || file_id)`.
+    ///
+    /// Unique per (key, index, file) with no randomness. Since `file_id`
+    /// commits to the file's plaintext, a repeated nonce only ever encrypts
+    /// the same chunk again, and yields the same ciphertext.
+    Counter,
+}
+
+/// The nonce `strategy` gives chunk `chunk_index` of `file_id`.
+pub fn chunk_nonce(
+    file_key: &FileKey,
+    chunk_index: u64,
+    file_id: &[u8; 32],
+    strategy: NonceStrategy,
+) -> anyhow::Result<[u8; NONCE_SIZE]> {
+    let mut nonce = [0u8; NONCE_SIZE];
+    match strategy {
+        NonceStrategy::Random => rand::thread_rng().fill_bytes(&mut nonce),
+        NonceStrategy::Counter => {
+            let mut info = Vec::with_capacity(16 + 8 + 32);
+            info.extend_from_slice(b"tcfs-chunk-nonce");
+            info.extend_from_slice(&chunk_index.to_be_bytes());
+            info.extend_from_slice(file_id);
+            Hkdf::<Sha256>::new(None, file_key.as_bytes())
+                .expand(&info, &mut nonce)
+                .map_err(|e| anyhow::anyhow!("HKDF expand failed: {e}"))?;
+        }
+    }
+    Ok(nonce)
+}
+
+/// Encrypt a single chunk with XChaCha20-Poly1305 and a random nonce.
 ///
 /// - `file_key`: The per-file encryption key
 /// - `chunk_index`: Zero-based index of this chunk within the file
@@ -31,11 +74,30 @@ pub fn encrypt_chunk(
     chunk_index: u64,
     file_id: &[u8; 32],
     plaintext: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    encrypt_chunk_with(
+        file_key,
+        chunk_index,
+        file_id,
+        plaintext,
+        NonceStrategy::Random,
+    )
+}
+
+/// [`encrypt_chunk`] with the nonce chosen by `strategy`.
+///
+/// The output format is the same for every strategy, so [`decrypt_chunk`]
+/// reads either.
+pub fn encrypt_chunk_with(
+    file_key: &FileKey,
+    chunk_index: u64,
+    file_id: &[u8; 32],
+    plaintext: &[u8],
+    strategy: NonceStrategy,
 ) -> anyhow::Result<Vec<u8>> {
     let cipher = XChaCha20Poly1305::new(file_key.as_bytes().into());
 
-    let mut nonce_bytes = [0u8; NONCE_SIZE];
-    rand::thread_rng().fill_bytes(&mut nonce_bytes);
+    let nonce_bytes = chunk_nonce(file_key, chunk_index, file_id, strategy)?;
     let nonce = XNonce::from_slice(&nonce_bytes);
 
     let aad = build_aad(chunk_index, file_id);
@@ -215,4 +277,38 @@ mod tests {
         let result = decrypt_chunk(&key, 0, &file_id, &encrypted);
         assert!(result.is_err(), "tampered ciphertext must fail");
     }
+
+    #[test]
+    fn test_counter_nonce_is_deterministic() {
+        let key = generate_file_key();
+        let file_id = [0x42u8; 32];
+        let chunk = b"the same chunk, encrypted twice";
+
+        let a = encrypt_chunk_with(&key, 3, &file_id, chunk, NonceStrategy::Counter).unwrap();
+        let b = encrypt_chunk_with(&key, 3, &file_id, chunk, NonceStrategy::Counter).unwrap();
+        assert_eq!(a, b, "counter nonces make encrypted chunks dedupable");
+        assert_eq!(decrypt_chunk(&key, 3, &file_id, &a).unwrap(), chunk);
+
+        let r1 = encrypt_chunk_with(&key, 3, &file_id, chunk, NonceStrategy::Random).unwrap();
+        let r2 = encrypt_chunk(&key, 3, &file_id, chunk).unwrap();
+        assert_ne!(r1, r2, "random nonces differ per encryption");
+    }
+
+    #[test]
+    fn test_counter_nonces_differ_by_index_and_file() {
+        let key = generate_file_key();
+        let nonce =
+            |index, id: [u8; 32]| chunk_nonce(&key, index, &id, NonceStrategy::Counter).unwrap();
+
+        let mut seen = std::collections::HashSet::new();
+        for index in 0..256 {
+            assert!(seen.insert(nonce(index, [1u8; 32])));
+            assert!(seen.insert(nonce(index, [2u8; 32])));
+        }
+        let other_key = generate_file_key();
+        assert_ne!(
+            nonce(0, [1u8; 32]),
+            chunk_nonce(&other_key, 0, &[1u8; 32], NonceStrategy::Counter).unwrap()
+        );
+    }
 }
@@ -0,0 +1,98 @@
+//! Convergent chunk encryption
+//!
+//! Each chunk gets its own key derived from the master key and the chunk's
+//! plaintext hash:
+//! ```text
+//! chunk_key = HKDF-SHA256(master_key, "tcfs-convergent" || BLAKE3(plaintext))
+//! nonce     = HKDF-SHA256(chunk_key, "tcfs-chunk-nonce" || 0 || CONVERGENT_FILE_ID)
+//! ```
+//!
+//! Identical plaintext chunks therefore encrypt to identical ciphertext under
+//! one master key and share a single content-addressed object. A key is only
+//! ever used for one plaintext, so the derived nonce never repeats across
+//! different messages.
+//!
+//! Caveats, inherent to convergent encryption:
+//! - Ciphertext equality leaks plaintext equality: anyone who can list the
+//!   store learns which chunks (across all files under the same master key)
+//!   are identical.
+//! - Confirmation of a file: a holder of the master key who guesses a chunk's
+//!   contents can check whether it is stored. An attacker without the master
+//!   key cannot.
+//! - Learn-the-remaining-information: for low-entropy content (a form letter
+//!   with a PIN), a holder of the master key can brute-force the unknown
+//!   part by encrypting candidates and comparing.
+//! - The AAD no longer binds a chunk to its file and position; the manifest's
+//!   ordered ciphertext hashes and whole-file hash take over that role.
+
+use blake3::Hasher;
+
+use crate::chunk::{decrypt_chunk, encrypt_chunk_with, NonceStrategy};
+use crate::kdf::MasterKey;
+use crate::keys::{hkdf_derive, FileKey};
+
+/// `file_id` used in the AAD of every convergent chunk.
+pub const CONVERGENT_FILE_ID: [u8; 32] = [0u8; 32];
+
+/// Derive the key for a chunk whose plaintext hashes to `plaintext_hash`.
+pub fn derive_convergent_key(
+    master: &MasterKey,
+    plaintext_hash: &[u8; 32],
+) -> anyhow::Result<FileKey> {
+    let mut info = Vec::with_capacity(15 + 32);
+    info.extend_from_slice(b"tcfs-convergent");
+    info.extend_from_slice(plaintext_hash);
+    Ok(FileKey::from_bytes(hkdf_derive(master.as_bytes(), &info)?))
+}
+
+/// Encrypt `plaintext` under its convergent key.
+///
+/// Returns the key (to be wrapped into the manifest) and
+/// `[24-byte nonce][ciphertext][16-byte tag]`, which is the same for every
+/// call with the same master key and plaintext.
+pub fn encrypt_chunk_convergent(
+    master: &MasterKey,
+    plaintext: &[u8],
+) -> anyhow::Result<(FileKey, Vec<u8>)> {
+    let mut hasher = Hasher::new();
+    hasher.update(plaintext);
+    let key = derive_convergent_key(master, hasher.finalize().as_bytes())?;
+    let ciphertext = encrypt_chunk_with(
+        &key,
+        0,
+        &CONVERGENT_FILE_ID,
+        plaintext,
+        NonceStrategy::Counter,
+    )?;
+    Ok((key, ciphertext))
+}
+
+/// Decrypt a chunk written by [`encrypt_chunk_convergent`].
+pub fn decrypt_chunk_convergent(key: &FileKey, encrypted: &[u8]) -> anyhow::Result<Vec<u8>> {
+    decrypt_chunk(key, 0, &CONVERGENT_FILE_ID, encrypted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KEY_SIZE;
+
+    #[test]
+    fn identical_plaintext_encrypts_identically() {
+        let master = MasterKey::from_bytes([7u8; KEY_SIZE]);
+        let (k1, c1) = encrypt_chunk_convergent(&master, b"shared chunk").unwrap();
+        let (k2, c2) = encrypt_chunk_convergent(&master, b"shared chunk").unwrap();
+        assert_eq!(c1, c2);
+        assert_eq!(k1.as_bytes(), k2.as_bytes());
+        assert_eq!(decrypt_chunk_convergent(&k1, &c1).unwrap(), b"shared chunk");
+
+        let (_, other) = encrypt_chunk_convergent(&master, b"other chunk").unwrap();
+        assert_ne!(c1, other);
+
+        // A different master key does not converge with the first
+        let stranger = MasterKey::from_bytes([8u8; KEY_SIZE]);
+        let (k3, c3) = encrypt_chunk_convergent(&stranger, b"shared chunk").unwrap();
+        assert_ne!(c1, c3);
+        assert!(decrypt_chunk_convergent(&k3, &c1).is_err());
+    }
+}
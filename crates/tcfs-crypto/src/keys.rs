@@ -60,7 +60,7 @@ pub fn derive_name_key(master: &MasterKey) -> anyhow::Result<[u8; KEY_SIZE]> {
 }
 
 /// HKDF-SHA256 key derivation with a domain-specific info string.
-fn hkdf_derive(ikm: &[u8; KEY_SIZE], info: &[u8]) -> anyhow::Result<[u8; KEY_SIZE]> {
+pub(crate) fn hkdf_derive(ikm: &[u8; KEY_SIZE], info: &[u8]) -> anyhow::Result<[u8; KEY_SIZE]> {
     let hkdf = Hkdf::<Sha256>::new(None, ikm);
     let mut okm = [0u8; KEY_SIZE];
     hkdf.expand(info, &mut okm)
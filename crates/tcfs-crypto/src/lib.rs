@@ -8,19 +8,29 @@
 //! ```text
 //! Master Key (256-bit, Argon2id from passphrase)
 //!   ├── File Encryption Key (per-file, 256-bit random, wrapped by master key)
-//!   │   └── Chunk AEAD: XChaCha20-Poly1305 (key=file_key, nonce=random_192bit, AAD=chunk_idx||file_id)
+//!   │   └── Chunk AEAD: XChaCha20-Poly1305 (key=file_key, nonce=random_192bit or
+//!   │       HKDF(file_key, chunk_idx||file_id), AAD=chunk_idx||file_id)
+//!   ├── Convergent Chunk Key (per-chunk, HKDF from master key and the chunk's
+//!   │   plaintext hash, wrapped by master key; opt-in, see `convergent`)
 //!   ├── Manifest Encryption Key (HKDF from master key, domain="tcfs-manifest")
 //!   └── Name Encryption Key (HKDF from master key, domain="tcfs-names", AES-SIV)
 //! ```
+//!
+//! Independently of the master key, each device signs the manifests it
+//! writes with its own Ed25519 key (see `signing`). The public halves are
+//! published with a tag keyed by the master key, binding each to its device.
 
 pub mod chunk;
+pub mod convergent;
 pub mod kdf;
 pub mod keys;
 pub mod manifest;
 pub mod names;
 pub mod recovery;
+pub mod signing;
 
-pub use chunk::{decrypt_chunk, encrypt_chunk};
+pub use chunk::{decrypt_chunk, encrypt_chunk, encrypt_chunk_with, NonceStrategy};
+pub use convergent::{decrypt_chunk_convergent, derive_convergent_key, encrypt_chunk_convergent};
 pub use kdf::{derive_master_key, MasterKey};
 pub use keys::{
     derive_manifest_key, derive_name_key, generate_file_key, unwrap_key, wrap_key, FileKey,
@@ -28,6 +38,9 @@ pub use keys::{
 pub use manifest::{EncryptedManifest, ManifestEntry};
 pub use names::{decrypt_name, encrypt_name};
 pub use recovery::{generate_mnemonic, mnemonic_to_master_key};
+pub use signing::{
+    key_binding_tag, verify_key_binding, verify_signature, BadSignature, DeviceSigningKey,
+};
 
 /// Size of a master key in bytes (256-bit)
 pub const KEY_SIZE: usize = 32;
@@ -0,0 +1,197 @@
+//! Ed25519 device signing keys
+//!
+//! Each device holds a signing key and enrolls its public half in the
+//! device registry. Manifests are signed with it so a peer can check that a
+//! manifest attributed to a device was really written by that device, not
+//! forged by anyone else with write access to the bucket. Public keys are
+//! published for the rest of the fleet with a [`key_binding_tag`], which only
+//! a holder of the master key can produce.
+//!
+//! Keys, public keys and signatures are exchanged as standard base64.
+
+use std::path::Path;
+
+use anyhow::Context;
+use base64::Engine as _;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use rand::RngCore;
+use zeroize::Zeroize;
+
+use crate::MasterKey;
+
+const B64: base64::engine::GeneralPurpose = base64::engine::general_purpose::STANDARD;
+
+/// A signature did not verify against the expected public key.
+#[derive(Debug, thiserror::Error)]
+#[error("signature verification failed: {0}")]
+pub struct BadSignature(pub String);
+
+/// A device's Ed25519 signing key.
+pub struct DeviceSigningKey {
+    key: SigningKey,
+}
+
+impl DeviceSigningKey {
+    /// Generate a new random signing key.
+    pub fn generate() -> Self {
+        let mut seed = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut seed);
+        let key = Self::from_bytes(&seed);
+        seed.zeroize();
+        key
+    }
+
+    /// Rebuild a key from its 32-byte seed.
+    pub fn from_bytes(seed: &[u8; 32]) -> Self {
+        Self {
+            key: SigningKey::from_bytes(seed),
+        }
+    }
+
+    /// Load the key stored at `path`, generating and saving a new one
+    /// (owner-only permissions on Unix) if the file does not exist.
+    pub fn load_or_generate(path: &Path) -> anyhow::Result<Self> {
+        if path.exists() {
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("reading signing key: {}", path.display()))?;
+            let mut bytes = B64
+                .decode(text.trim())
+                .with_context(|| format!("decoding signing key: {}", path.display()))?;
+            let seed: [u8; 32] = bytes
+                .as_slice()
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("signing key {} is not 32 bytes", path.display()))?;
+            bytes.zeroize();
+            return Ok(Self::from_bytes(&seed));
+        }
+
+        let key = Self::generate();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("creating dir: {}", parent.display()))?;
+        }
+        let mut encoded = B64.encode(key.key.to_bytes());
+        let written = std::fs::write(path, &encoded)
+            .with_context(|| format!("writing signing key: {}", path.display()));
+        encoded.zeroize();
+        written?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+                .with_context(|| format!("restricting signing key: {}", path.display()))?;
+        }
+        Ok(key)
+    }
+
+    /// The base64 public key to enroll in the device registry.
+    pub fn public_key(&self) -> String {
+        B64.encode(self.key.verifying_key().to_bytes())
+    }
+
+    /// Sign `message`, returning the base64 signature.
+    pub fn sign(&self, message: &[u8]) -> String {
+        B64.encode(self.key.sign(message).to_bytes())
+    }
+}
+
+impl std::fmt::Debug for DeviceSigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeviceSigningKey")
+            .field("public_key", &self.public_key())
+            .finish()
+    }
+}
+
+/// Check a base64 `signature` over `message` against a base64 `public_key`.
+pub fn verify_signature(
+    public_key: &str,
+    message: &[u8],
+    signature: &str,
+) -> Result<(), BadSignature> {
+    let key: [u8; 32] = B64
+        .decode(public_key)
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| BadSignature("malformed public key".into()))?;
+    let key = VerifyingKey::from_bytes(&key)
+        .map_err(|e| BadSignature(format!("invalid public key: {e}")))?;
+    let signature = B64
+        .decode(signature)
+        .ok()
+        .and_then(|b| Signature::from_slice(&b).ok())
+        .ok_or_else(|| BadSignature("malformed signature".into()))?;
+    key.verify_strict(message, &signature)
+        .map_err(|_| BadSignature("signature does not match the public key".into()))
+}
+
+/// Tag binding a device's signing `public_key` to `device_id` under the
+/// master key: a key published to the bucket is only trusted when a holder
+/// of the fleet's master key wrote it, not anyone with bucket access.
+pub fn key_binding_tag(master_key: &MasterKey, device_id: &str, public_key: &str) -> String {
+    binding_hash(master_key, device_id, public_key)
+        .to_hex()
+        .to_string()
+}
+
+/// Check a [`key_binding_tag`] (in constant time).
+pub fn verify_key_binding(
+    master_key: &MasterKey,
+    device_id: &str,
+    public_key: &str,
+    tag: &str,
+) -> Result<(), BadSignature> {
+    let tag = blake3::Hash::from_hex(tag).map_err(|_| BadSignature("malformed tag".into()))?;
+    if binding_hash(master_key, device_id, public_key) == tag {
+        Ok(())
+    } else {
+        Err(BadSignature(format!(
+            "key for {device_id} is not bound by this master key"
+        )))
+    }
+}
+
+fn binding_hash(master_key: &MasterKey, device_id: &str, public_key: &str) -> blake3::Hash {
+    let key = blake3::derive_key("tcfs signing key binding v1", master_key.as_bytes());
+    let mut hasher = blake3::Hasher::new_keyed(&key);
+    hasher.update(device_id.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(public_key.as_bytes());
+    hasher.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_verify_and_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("device.key");
+        let key = DeviceSigningKey::load_or_generate(&path).unwrap();
+        let again = DeviceSigningKey::load_or_generate(&path).unwrap();
+        assert_eq!(key.public_key(), again.public_key());
+
+        let sig = key.sign(b"manifest body");
+        assert!(verify_signature(&key.public_key(), b"manifest body", &sig).is_ok());
+        assert!(verify_signature(&key.public_key(), b"tampered body", &sig).is_err());
+
+        let other = DeviceSigningKey::generate();
+        assert!(verify_signature(&other.public_key(), b"manifest body", &sig).is_err());
+        assert!(verify_signature("not base64!", b"manifest body", &sig).is_err());
+    }
+
+    #[test]
+    fn key_binding_needs_the_master_key() {
+        let master = MasterKey::from_bytes([7u8; 32]);
+        let key = DeviceSigningKey::generate().public_key();
+        let tag = key_binding_tag(&master, "device-a", &key);
+        assert!(verify_key_binding(&master, "device-a", &key, &tag).is_ok());
+        assert!(verify_key_binding(&master, "device-b", &key, &tag).is_err());
+        let other = DeviceSigningKey::generate().public_key();
+        assert!(verify_key_binding(&master, "device-a", &other, &tag).is_err());
+        let wrong = MasterKey::from_bytes([8u8; 32]);
+        assert!(verify_key_binding(&wrong, "device-a", &key, &tag).is_err());
+        assert!(verify_key_binding(&master, "device-a", &key, "zz").is_err());
+    }
+}
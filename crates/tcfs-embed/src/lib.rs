@@ -0,0 +1,257 @@
+//! tcfs-embed: blocking access to a tcfs prefix for embedders
+//!
+//! [`Remote`] uploads, fetches and enumerates files under a remote prefix
+//! through a caller-built `opendal::Operator`, from synchronous code and
+//! without nesting a runtime. Each call runs on, in order of preference:
+//!
+//! 1. the handle given with [`Remote::with_handle`],
+//! 2. the runtime the caller is already inside (`Handle::try_current`),
+//! 3. a current-thread runtime the `Remote` starts on first use.
+//!
+//! Calls block the calling thread. Inside a multi-thread runtime they step
+//! off the worker with `block_in_place`. A current-thread runtime cannot be
+//! blocked without stalling it, so calls made under one fail with
+//! [`AsyncContext`] rather than panic; run them from `spawn_blocking` on a
+//! multi-thread runtime instead.
+//!
+//! The async bodies ([`upload_file`], [`fetch_file`], [`list_dir`]) are
+//! shared with the FileProvider FFI.
+
+use std::future::Future;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use opendal::Operator;
+use tcfs_core::layout::RemoteLayout;
+use tcfs_sync::store::ChunkStore;
+use tokio::runtime::{Handle, Runtime, RuntimeFlavor};
+
+/// A blocking call was made on a current-thread runtime, which it would stall.
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "tcfs-embed called from a current-thread tokio runtime; call it from a \
+     multi-thread runtime, from spawn_blocking, or outside any runtime"
+)]
+pub struct AsyncContext;
+
+/// A file stored by [`Remote::upload`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Uploaded {
+    /// BLAKE3 of the file's content, which keys its manifest
+    pub hash: String,
+    pub bytes: u64,
+    pub chunks: usize,
+}
+
+/// An entry listed by [`Remote::enumerate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    /// Storage key of the index entry (or directory), as passed to [`fetch_file`]
+    pub key: String,
+    /// Name within the listed directory
+    pub name: String,
+    pub is_dir: bool,
+}
+
+/// Synchronous handle on the files under one remote prefix.
+#[derive(Debug)]
+pub struct Remote {
+    store: ChunkStore,
+    handle: Option<Handle>,
+    runtime: OnceLock<Runtime>,
+}
+
+impl Remote {
+    /// Files under `prefix` of `op`, stored flat (`chunk_shard_depth` 0).
+    pub fn new(op: Operator, prefix: &str) -> Self {
+        Self::with_layout(op, RemoteLayout::new(prefix))
+    }
+
+    /// Files stored under `layout`, e.g. with the `storage.chunk_shard_depth`
+    /// the rest of the fleet pushes with.
+    pub fn with_layout(op: Operator, layout: RemoteLayout) -> Self {
+        Self {
+            store: ChunkStore::new(op, layout),
+            handle: None,
+            runtime: OnceLock::new(),
+        }
+    }
+
+    /// Run calls on `handle`, which should belong to a multi-thread runtime:
+    /// a current-thread runtime only makes progress inside its own `block_on`.
+    pub fn with_handle(mut self, handle: Handle) -> Self {
+        self.handle = Some(handle);
+        self
+    }
+
+    /// The chunk store calls go through.
+    pub fn store(&self) -> &ChunkStore {
+        &self.store
+    }
+
+    /// Upload `local` as `rel_path`: chunks not already stored, its manifest
+    /// and its index entry.
+    pub fn upload(&self, local: &Path, rel_path: &str) -> Result<Uploaded> {
+        self.block_on(upload_file(&self.store, local, rel_path))?
+    }
+
+    /// Download the file at `rel_path` to `dest`, returning its size.
+    pub fn fetch(&self, rel_path: &str, dest: &Path) -> Result<u64> {
+        let key = self.store.layout().index_key(rel_path);
+        self.block_on(fetch_file(&self.store, &key, dest))?
+    }
+
+    /// Files and directories directly under `dir` ("" for the root).
+    pub fn enumerate(&self, dir: &str) -> Result<Vec<Item>> {
+        let prefix = self.store.layout().index_dir(dir);
+        self.block_on(list_dir(self.store.operator(), &prefix))?
+    }
+
+    fn block_on<F: Future>(&self, fut: F) -> Result<F::Output> {
+        let ambient = Handle::try_current().ok();
+        if ambient
+            .as_ref()
+            .is_some_and(|h| h.runtime_flavor() == RuntimeFlavor::CurrentThread)
+        {
+            return Err(AsyncContext.into());
+        }
+        match self.handle.clone().or(ambient) {
+            // Outside a worker thread block_in_place just runs the closure
+            Some(handle) => Ok(tokio::task::block_in_place(|| handle.block_on(fut))),
+            None => Ok(self.own_runtime()?.block_on(fut)),
+        }
+    }
+
+    fn own_runtime(&self) -> Result<&Runtime> {
+        if let Some(runtime) = self.runtime.get() {
+            return Ok(runtime);
+        }
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("starting tokio runtime")?;
+        Ok(self.runtime.get_or_init(|| runtime))
+    }
+}
+
+/// Upload `local` into `store` as `rel_path`: chunks not already stored,
+/// the manifest and the index entry.
+pub async fn upload_file(store: &ChunkStore, local: &Path, rel_path: &str) -> Result<Uploaded> {
+    let data = tokio::fs::read(local)
+        .await
+        .with_context(|| format!("reading {}", local.display()))?;
+    let file_hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&data));
+
+    let chunks = tcfs_chunks::chunk_data(&data, tcfs_chunks::ChunkSizes::SMALL);
+    let mut chunk_hashes = Vec::new();
+    for chunk in &chunks {
+        let chunk_bytes = &data[chunk.offset as usize..chunk.offset as usize + chunk.length];
+        let hash = tcfs_chunks::hash_to_hex(&chunk.hash);
+        if !store.has_intact_chunk(&hash, chunk.length as u64).await? {
+            store.put_chunk(&hash, chunk_bytes.to_vec()).await?;
+        }
+        chunk_hashes.push(hash);
+    }
+
+    let mode = tcfs_sync::engine::file_mode(local);
+    let manifest = tcfs_sync::manifest::SyncManifest {
+        version: 2,
+        file_hash: file_hash.clone(),
+        file_size: data.len() as u64,
+        chunks: chunk_hashes,
+        vclock: Default::default(),
+        written_by: String::new(),
+        written_at: 0,
+        rel_path: Some(rel_path.to_string()),
+        encrypted_file_key: None,
+        mode,
+        compressed: Vec::new(),
+        chunk_keys: Vec::new(),
+        chunk_shard_depth: store.layout().chunk_shard_depth(),
+        signature: None,
+        manifest_checksum: None,
+    };
+    store.put_manifest(&manifest).await?;
+
+    let index_key = store.layout().index_key(rel_path);
+    let modified = tokio::fs::metadata(local)
+        .await?
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs());
+    let mut index_entry =
+        tcfs_core::index::IndexEntry::new(&file_hash, data.len() as u64, chunks.len(), modified);
+    index_entry.mode = mode;
+    store
+        .operator()
+        .write(&index_key, index_entry.to_bytes())
+        .await
+        .with_context(|| format!("writing index entry: {index_key}"))?;
+
+    Ok(Uploaded {
+        hash: file_hash,
+        bytes: data.len() as u64,
+        chunks: chunks.len(),
+    })
+}
+
+/// Download the file whose index entry is stored at `index_key` to `dest`,
+/// returning its size. A packed file is read from its pack, any other
+/// through its manifest; either way the content is verified against its
+/// BLAKE3.
+pub async fn fetch_file(store: &ChunkStore, index_key: &str, dest: &Path) -> Result<u64> {
+    let data = store
+        .operator()
+        .read(index_key)
+        .await
+        .with_context(|| format!("reading index entry: {index_key}"))?;
+    let entry = tcfs_core::index::IndexEntry::from_bytes(&data.to_bytes())?;
+    let index_dir = store.layout().index_dir("");
+    let rel_path = index_key
+        .strip_prefix(&index_dir)
+        .with_context(|| format!("{index_key} is not under {index_dir}"))?;
+
+    let result = tcfs_sync::engine::download_indexed(
+        store.operator(),
+        store.layout().prefix(),
+        rel_path,
+        &entry,
+        dest,
+        Default::default(),
+        "",
+        None,
+        None,
+        tcfs_sync::engine::DEFAULT_MODE_UMASK,
+    )
+    .await?;
+    Ok(result.bytes)
+}
+
+/// Entries directly under the index directory `prefix`, leaving out the
+/// directory itself and its marker.
+pub async fn list_dir(op: &Operator, prefix: &str) -> Result<Vec<Item>> {
+    let entries = op
+        .list(prefix)
+        .await
+        .with_context(|| format!("listing {prefix}"))?;
+    Ok(entries
+        .iter()
+        .filter_map(|entry| {
+            let name = entry
+                .path()
+                .strip_prefix(prefix)
+                .unwrap_or(entry.path())
+                .trim_start_matches('/');
+            if name.is_empty() || name == tcfs_core::index::DIR_MARKER {
+                return None;
+            }
+            Some(Item {
+                key: entry.path().to_string(),
+                name: name.trim_end_matches('/').to_string(),
+                is_dir: name.ends_with('/'),
+            })
+        })
+        .collect())
+}
@@ -0,0 +1,117 @@
+//! Integration test: the blocking API runs with or without a runtime
+//!
+//! The same upload → enumerate → fetch round trip is driven from a plain
+//! `#[test]` (no runtime at all), with a caller-provided handle, and from a
+//! multi-thread runtime through `block_in_place`. Under a current-thread
+//! runtime the call is refused with `AsyncContext` instead of panicking.
+
+use std::path::Path;
+
+use opendal::Operator;
+use tcfs_embed::{AsyncContext, Remote};
+use tempfile::TempDir;
+
+const PREFIX: &str = "devices/embed";
+
+fn memory_operator() -> Operator {
+    Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish()
+}
+
+/// Upload two files, list them and fetch one back.
+fn round_trip(remote: &Remote, dir: &Path) {
+    let content: Vec<u8> = (0..40_000u32).map(|i| (i * 7 % 251) as u8).collect();
+    let local = dir.join("notes.txt");
+    std::fs::write(&local, &content).unwrap();
+    let uploaded = remote.upload(&local, "docs/notes.txt").unwrap();
+    assert_eq!(uploaded.bytes, content.len() as u64);
+    assert!(uploaded.chunks > 0);
+    std::fs::write(dir.join("top.txt"), b"top").unwrap();
+    remote.upload(&dir.join("top.txt"), "top.txt").unwrap();
+
+    let mut root: Vec<_> = remote
+        .enumerate("")
+        .unwrap()
+        .into_iter()
+        .map(|i| (i.name, i.is_dir))
+        .collect();
+    root.sort();
+    assert_eq!(
+        root,
+        [("docs".to_string(), true), ("top.txt".to_string(), false)]
+    );
+    let docs = remote.enumerate("docs").unwrap();
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].name, "notes.txt");
+
+    let dest = dir.join("fetched.txt");
+    let bytes = remote.fetch("docs/notes.txt", &dest).unwrap();
+    assert_eq!(bytes, content.len() as u64);
+    assert_eq!(std::fs::read(&dest).unwrap(), content);
+}
+
+#[test]
+fn works_without_a_runtime() {
+    let tmp = TempDir::new().unwrap();
+    let remote = Remote::new(memory_operator(), PREFIX);
+    round_trip(&remote, tmp.path());
+}
+
+#[test]
+fn runs_on_a_caller_provided_handle() {
+    let tmp = TempDir::new().unwrap();
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .unwrap();
+    let remote = Remote::new(memory_operator(), PREFIX).with_handle(runtime.handle().clone());
+    round_trip(&remote, tmp.path());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn runs_inside_a_runtime_via_block_in_place() {
+    let tmp = TempDir::new().unwrap();
+    let remote = Remote::new(memory_operator(), PREFIX);
+    tokio::task::block_in_place(|| round_trip(&remote, tmp.path()));
+}
+
+#[tokio::test]
+async fn current_thread_runtime_is_refused() {
+    let tmp = TempDir::new().unwrap();
+    let remote = Remote::new(memory_operator(), PREFIX);
+    let err = remote.enumerate("").unwrap_err();
+    assert!(err.is::<AsyncContext>(), "{err:#}");
+    let local = tmp.path().join("a.txt");
+    std::fs::write(&local, b"a").unwrap();
+    let err = remote.upload(&local, "a.txt").unwrap_err();
+    assert!(err.is::<AsyncContext>(), "{err:#}");
+}
+
+#[test]
+fn fetches_packed_files_from_their_pack() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let root = tmp.path().join("root");
+    std::fs::create_dir_all(&root).unwrap();
+    let small = root.join("small.txt");
+    std::fs::write(&small, b"packed content").unwrap();
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let packed = runtime.block_on(tcfs_sync::pack::push_packed(
+        &op,
+        &root,
+        PREFIX,
+        &state,
+        &[small],
+        "dev",
+    ));
+    assert!(packed[0].as_ref().unwrap().pack.is_some());
+
+    let remote = Remote::new(op, PREFIX);
+    let dest = tmp.path().join("fetched.txt");
+    assert_eq!(remote.fetch("small.txt", &dest).unwrap(), 14);
+    assert_eq!(std::fs::read(&dest).unwrap(), b"packed content");
+}
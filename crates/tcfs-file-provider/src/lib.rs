@@ -15,6 +15,7 @@
 //!       |                   |
 //!       +-- tcfs-file-provider (this crate, staticlib)
 //!                   |
+//!                   +-- tcfs-embed    -> upload/fetch bodies
 //!                   +-- tcfs-storage  -> S3/SeaweedFS access
 //!                   +-- tcfs-chunks   -> FastCDC + BLAKE3 + zstd
 //!                   +-- tcfs-sync     -> state cache, manifests
@@ -26,12 +27,23 @@
 //! Initial FFI skeleton -- see [RFC 0002](../../docs/rfc/0002-darwin-integration.md)
 //! and [RFC 0003](../../docs/rfc/0003-ios-file-provider.md) for roadmap.
 
+use std::cell::RefCell;
 use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
 use std::panic::AssertUnwindSafe;
 use std::ptr;
 
+use tcfs_core::layout::RemoteLayout;
+use tcfs_sync::store::ChunkStore;
+
+thread_local! {
+    /// Message for the last error returned by an FFI call on this thread.
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
 /// Error codes returned by FFI functions.
+///
+/// `tcfs_last_error` describes the most recent failure in words.
 #[repr(C)]
 pub enum TcfsError {
     /// Success (no error).
@@ -66,13 +78,20 @@ pub struct TcfsFileItem {
     pub content_hash: *mut c_char,
 }
 
-/// Opaque provider handle wrapping a tokio runtime + OpenDAL operator.
+/// Opaque provider handle wrapping a tokio runtime + the chunk store.
 ///
 /// Created via `tcfs_provider_new`, freed via `tcfs_provider_free`.
 pub struct TcfsProvider {
     runtime: tokio::runtime::Runtime,
-    operator: opendal::Operator,
-    remote_prefix: String,
+    store: ChunkStore,
+}
+
+/// Opaque continuation cursor returned by `tcfs_provider_enumerate_page`.
+///
+/// Freed via `tcfs_enumerate_cursor_free`.
+pub struct TcfsEnumerateCursor {
+    /// Full key of the last entry on the previous page
+    after: String,
 }
 
 /// Create a new provider from a JSON configuration string.
@@ -84,10 +103,13 @@ pub struct TcfsProvider {
 ///   "s3_bucket": "tcfs",
 ///   "s3_access": "...",
 ///   "s3_secret": "...",
-///   "remote_prefix": "devices/mydevice"
+///   "remote_prefix": "devices/mydevice",
+///   "chunk_shard_depth": 0
 /// }
 /// ```
 ///
+/// `chunk_shard_depth` is optional and should match `storage.chunk_shard_depth`.
+///
 /// Returns a pointer to `TcfsProvider` on success, or null on failure.
 /// The caller must free the provider via `tcfs_provider_free`.
 ///
@@ -96,7 +118,9 @@ pub struct TcfsProvider {
 /// `config_json` must be a valid null-terminated UTF-8 C string.
 #[no_mangle]
 pub unsafe extern "C" fn tcfs_provider_new(config_json: *const c_char) -> *mut TcfsProvider {
+    clear_last_error();
     if config_json.is_null() {
+        set_last_error("config_json is null");
         return ptr::null_mut();
     }
 
@@ -104,12 +128,18 @@ pub unsafe extern "C" fn tcfs_provider_new(config_json: *const c_char) -> *mut T
         let c_str = unsafe { CStr::from_ptr(config_json) };
         let json_str = match c_str.to_str() {
             Ok(s) => s,
-            Err(_) => return ptr::null_mut(),
+            Err(e) => {
+                set_last_error(format!("config_json is not UTF-8: {e}"));
+                return ptr::null_mut();
+            }
         };
 
         let config: serde_json::Value = match serde_json::from_str(json_str) {
             Ok(v) => v,
-            Err(_) => return ptr::null_mut(),
+            Err(e) => {
+                set_last_error(format!("parsing config_json: {e}"));
+                return ptr::null_mut();
+            }
         };
 
         let endpoint = config["s3_endpoint"].as_str().unwrap_or_default();
@@ -120,10 +150,18 @@ pub unsafe extern "C" fn tcfs_provider_new(config_json: *const c_char) -> *mut T
             .as_str()
             .unwrap_or("default")
             .to_string();
+        let shard_depth = config["chunk_shard_depth"].as_u64().unwrap_or(0);
+        if shard_depth > u64::from(tcfs_core::layout::MAX_CHUNK_SHARD_DEPTH) {
+            set_last_error(format!("invalid chunk_shard_depth {shard_depth}"));
+            return ptr::null_mut();
+        }
 
         let runtime = match tokio::runtime::Runtime::new() {
             Ok(rt) => rt,
-            Err(_) => return ptr::null_mut(),
+            Err(e) => {
+                set_last_error(format!("starting tokio runtime: {e}"));
+                return ptr::null_mut();
+            }
         };
 
         let operator =
@@ -137,17 +175,25 @@ pub unsafe extern "C" fn tcfs_provider_new(config_json: *const c_char) -> *mut T
 
         let operator = match operator {
             Ok(op) => op,
-            Err(_) => return ptr::null_mut(),
+            Err(e) => {
+                set_last_error(format!("{e:#}"));
+                return ptr::null_mut();
+            }
         };
 
         Box::into_raw(Box::new(TcfsProvider {
             runtime,
-            operator,
-            remote_prefix: prefix,
+            store: ChunkStore::new(
+                operator,
+                RemoteLayout::new(&prefix).with_chunk_shard_depth(shard_depth as u8),
+            ),
         }))
     }));
 
-    result.unwrap_or(ptr::null_mut())
+    result.unwrap_or_else(|payload| {
+        set_last_error(panic_message(&*payload));
+        ptr::null_mut()
+    })
 }
 
 /// Enumerate files under a relative path within the remote prefix.
@@ -168,8 +214,9 @@ pub unsafe extern "C" fn tcfs_provider_enumerate(
     out_items: *mut *mut TcfsFileItem,
     out_count: *mut usize,
 ) -> TcfsError {
+    clear_last_error();
     if provider.is_null() || path.is_null() || out_items.is_null() || out_count.is_null() {
-        return TcfsError::TcfsErrorInvalidArg;
+        return fail(TcfsError::TcfsErrorInvalidArg, "null pointer argument");
     }
 
     let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
@@ -177,58 +224,119 @@ pub unsafe extern "C" fn tcfs_provider_enumerate(
         let c_path = unsafe { CStr::from_ptr(path) };
         let rel_path = match c_path.to_str() {
             Ok(s) => s,
-            Err(_) => return TcfsError::TcfsErrorInvalidArg,
+            Err(e) => {
+                return fail(
+                    TcfsError::TcfsErrorInvalidArg,
+                    format!("path is not UTF-8: {e}"),
+                )
+            }
         };
 
-        let prefix = format!(
-            "{}/index/{}",
-            prov.remote_prefix.trim_end_matches('/'),
-            rel_path.trim_start_matches('/')
-        );
+        let prefix = prov.store.layout().index_dir(rel_path);
 
-        let entries = match prov.runtime.block_on(prov.operator.list(&prefix)) {
+        let entries = match prov.runtime.block_on(prov.store.operator().list(&prefix)) {
             Ok(e) => e,
-            Err(_) => return TcfsError::TcfsErrorStorage,
+            Err(e) => {
+                return fail(
+                    TcfsError::TcfsErrorStorage,
+                    format!("listing {prefix}: {e}"),
+                )
+            }
         };
 
-        let mut items: Vec<TcfsFileItem> = Vec::new();
-        for entry in entries {
-            let entry_path = entry.path();
-            let name = entry_path
-                .strip_prefix(&prefix)
-                .unwrap_or(entry_path)
-                .trim_start_matches('/');
+        let items: Vec<TcfsFileItem> = entries
+            .iter()
+            .filter_map(|entry| file_item(&prefix, entry))
+            .collect();
 
-            if name.is_empty() {
-                continue;
-            }
+        unsafe { write_items(items, out_items, out_count) };
+        TcfsError::TcfsErrorNone
+    }));
 
-            let is_dir = name.ends_with('/');
-            let display_name = name.trim_end_matches('/');
+    result.unwrap_or_else(|payload| fail(TcfsError::TcfsErrorInternal, panic_message(&*payload)))
+}
 
-            items.push(TcfsFileItem {
-                item_id: to_c_string(entry_path),
-                filename: to_c_string(display_name),
-                file_size: entry.metadata().content_length(),
-                modified_timestamp: 0,
-                is_directory: is_dir,
-                content_hash: to_c_string(""),
-            });
-        }
+/// Enumerate one page of at most `page_size` files under a relative path.
+///
+/// Pass a null `cursor` for the first page. On success, writes the page to
+/// `*out_items` / `*out_count` (freed via `tcfs_file_items_free`) and the
+/// cursor for the next page to `*out_next_cursor`, or null once the listing
+/// is exhausted. Cursors are freed via `tcfs_enumerate_cursor_free`; the
+/// cursor passed in is not consumed.
+///
+/// Pages are read from the storage lister as it streams, starting after the
+/// cursor's key where the backend supports it (S3), so a listing is never
+/// held in memory whole.
+///
+/// # Safety
+///
+/// - `provider` must be a valid pointer from `tcfs_provider_new`.
+/// - `path` must be a valid null-terminated UTF-8 C string (use "" for root).
+/// - `cursor` must be null or a cursor returned for the same `path` and not
+///   yet freed.
+/// - `out_items`, `out_count` and `out_next_cursor` must be valid writable
+///   pointers.
+#[no_mangle]
+pub unsafe extern "C" fn tcfs_provider_enumerate_page(
+    provider: *mut TcfsProvider,
+    path: *const c_char,
+    cursor: *const TcfsEnumerateCursor,
+    page_size: usize,
+    out_items: *mut *mut TcfsFileItem,
+    out_count: *mut usize,
+    out_next_cursor: *mut *mut TcfsEnumerateCursor,
+) -> TcfsError {
+    clear_last_error();
+    if provider.is_null()
+        || path.is_null()
+        || out_items.is_null()
+        || out_count.is_null()
+        || out_next_cursor.is_null()
+    {
+        return fail(TcfsError::TcfsErrorInvalidArg, "null pointer argument");
+    }
+    if page_size == 0 {
+        return fail(TcfsError::TcfsErrorInvalidArg, "page_size is 0");
+    }
 
-        let count = items.len();
-        let boxed = items.into_boxed_slice();
-        let ptr = Box::into_raw(boxed) as *mut TcfsFileItem;
+    let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        let prov = unsafe { &*provider };
+        let c_path = unsafe { CStr::from_ptr(path) };
+        let rel_path = match c_path.to_str() {
+            Ok(s) => s,
+            Err(e) => {
+                return fail(
+                    TcfsError::TcfsErrorInvalidArg,
+                    format!("path is not UTF-8: {e}"),
+                )
+            }
+        };
+        let after = unsafe { cursor.as_ref() }.map(|c| c.after.as_str());
+
+        let prefix = prov.store.layout().index_dir(rel_path);
+        let page =
+            prov.runtime
+                .block_on(list_page(prov.store.operator(), &prefix, after, page_size));
+        let (items, next) = match page {
+            Ok(page) => page,
+            Err(e) => {
+                return fail(
+                    TcfsError::TcfsErrorStorage,
+                    format!("listing {prefix}: {e}"),
+                )
+            }
+        };
 
         unsafe {
-            *out_items = ptr;
-            *out_count = count;
+            write_items(items, out_items, out_count);
+            *out_next_cursor = next.map_or(ptr::null_mut(), |after| {
+                Box::into_raw(Box::new(TcfsEnumerateCursor { after }))
+            });
         }
-
         TcfsError::TcfsErrorNone
     }));
 
-    result.unwrap_or(TcfsError::TcfsErrorInternal)
+    result.unwrap_or_else(|payload| fail(TcfsError::TcfsErrorInternal, panic_message(&*payload)))
 }
 
 /// Fetch (hydrate) a file by its item ID to a local destination path.
@@ -246,8 +354,9 @@ pub unsafe extern "C" fn tcfs_provider_fetch(
     item_id: *const c_char,
     dest_path: *const c_char,
 ) -> TcfsError {
+    clear_last_error();
     if provider.is_null() || item_id.is_null() || dest_path.is_null() {
-        return TcfsError::TcfsErrorInvalidArg;
+        return fail(TcfsError::TcfsErrorInvalidArg, "null pointer argument");
     }
 
     let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
@@ -257,75 +366,42 @@ pub unsafe extern "C" fn tcfs_provider_fetch(
 
         let item_str = match c_item.to_str() {
             Ok(s) => s,
-            Err(_) => return TcfsError::TcfsErrorInvalidArg,
+            Err(e) => {
+                return fail(
+                    TcfsError::TcfsErrorInvalidArg,
+                    format!("path is not UTF-8: {e}"),
+                )
+            }
         };
         let dest_str = match c_dest.to_str() {
             Ok(s) => s,
-            Err(_) => return TcfsError::TcfsErrorInvalidArg,
-        };
-
-        let fetch_result = prov.runtime.block_on(async {
-            // Read the index entry to get manifest hash
-            let data = prov.operator.read(item_str).await?;
-            let bytes = data.to_bytes();
-            let text = String::from_utf8_lossy(&bytes);
-
-            let mut manifest_hash = String::new();
-            for line in text.lines() {
-                if let Some(val) = line.strip_prefix("manifest_hash=") {
-                    manifest_hash = val.to_string();
-                }
-            }
-
-            if manifest_hash.is_empty() {
-                anyhow::bail!("no manifest_hash in index entry");
-            }
-
-            let manifest_path = format!(
-                "{}/manifests/{}",
-                prov.remote_prefix.trim_end_matches('/'),
-                manifest_hash
-            );
-
-            let manifest_bytes = prov.operator.read(&manifest_path).await?;
-            let manifest =
-                tcfs_sync::manifest::SyncManifest::from_bytes(&manifest_bytes.to_bytes())?;
-
-            let mut assembled = Vec::new();
-            for hash in manifest.chunk_hashes() {
-                let chunk_key = format!(
-                    "{}/chunks/{}",
-                    prov.remote_prefix.trim_end_matches('/'),
-                    hash
-                );
-                let chunk_data = prov.operator.read(&chunk_key).await?;
-                let chunk_bytes = chunk_data.to_bytes();
-
-                // BLAKE3 integrity verification
-                let actual = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&chunk_bytes));
-                if actual != *hash {
-                    anyhow::bail!("chunk integrity failure: expected {}, got {}", hash, actual);
-                }
-                assembled.extend_from_slice(&chunk_bytes);
+            Err(e) => {
+                return fail(
+                    TcfsError::TcfsErrorInvalidArg,
+                    format!("path is not UTF-8: {e}"),
+                )
             }
+        };
 
-            tokio::fs::write(dest_str, &assembled).await?;
-            Ok::<(), anyhow::Error>(())
-        });
+        let fetch_result = prov.runtime.block_on(tcfs_embed::fetch_file(
+            &prov.store,
+            item_str,
+            std::path::Path::new(dest_str),
+        ));
 
         match fetch_result {
-            Ok(()) => TcfsError::TcfsErrorNone,
-            Err(_) => TcfsError::TcfsErrorStorage,
+            Ok(_) => TcfsError::TcfsErrorNone,
+            Err(e) => fail(TcfsError::TcfsErrorStorage, format!("{e:#}")),
         }
     }));
 
-    result.unwrap_or(TcfsError::TcfsErrorInternal)
+    result.unwrap_or_else(|payload| fail(TcfsError::TcfsErrorInternal, panic_message(&*payload)))
 }
 
 /// Upload a local file to the remote prefix.
 ///
-/// Chunks the file with FastCDC, hashes with BLAKE3, uploads chunks
-/// and manifest to S3.
+/// Chunks the file with FastCDC, hashes with BLAKE3, uploads the chunks
+/// not already stored and the manifest to S3.
 ///
 /// # Safety
 ///
@@ -337,8 +413,9 @@ pub unsafe extern "C" fn tcfs_provider_upload(
     local_path: *const c_char,
     remote_rel: *const c_char,
 ) -> TcfsError {
+    clear_last_error();
     if provider.is_null() || local_path.is_null() || remote_rel.is_null() {
-        return TcfsError::TcfsErrorInvalidArg;
+        return fail(TcfsError::TcfsErrorInvalidArg, "null pointer argument");
     }
 
     let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
@@ -348,79 +425,36 @@ pub unsafe extern "C" fn tcfs_provider_upload(
 
         let local_str = match c_local.to_str() {
             Ok(s) => s,
-            Err(_) => return TcfsError::TcfsErrorInvalidArg,
+            Err(e) => {
+                return fail(
+                    TcfsError::TcfsErrorInvalidArg,
+                    format!("path is not UTF-8: {e}"),
+                )
+            }
         };
         let remote_str = match c_remote.to_str() {
             Ok(s) => s,
-            Err(_) => return TcfsError::TcfsErrorInvalidArg,
-        };
-
-        let upload_result = prov.runtime.block_on(async {
-            let data = tokio::fs::read(local_str).await?;
-            let file_hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&data));
-
-            let chunks = tcfs_chunks::chunk_data(&data, tcfs_chunks::ChunkSizes::SMALL);
-            let mut chunk_hashes = Vec::new();
-
-            for chunk in &chunks {
-                let chunk_bytes =
-                    &data[chunk.offset as usize..chunk.offset as usize + chunk.length];
-                let hash = tcfs_chunks::hash_to_hex(&chunk.hash);
-                let chunk_key = format!(
-                    "{}/chunks/{}",
-                    prov.remote_prefix.trim_end_matches('/'),
-                    hash
-                );
-                prov.operator
-                    .write(&chunk_key, chunk_bytes.to_vec())
-                    .await?;
-                chunk_hashes.push(hash);
+            Err(e) => {
+                return fail(
+                    TcfsError::TcfsErrorInvalidArg,
+                    format!("path is not UTF-8: {e}"),
+                )
             }
+        };
 
-            let manifest = tcfs_sync::manifest::SyncManifest {
-                version: 2,
-                file_hash: file_hash.clone(),
-                file_size: data.len() as u64,
-                chunks: chunk_hashes,
-                vclock: Default::default(),
-                written_by: String::new(),
-                written_at: 0,
-                rel_path: Some(remote_str.to_string()),
-                encrypted_file_key: None,
-            };
-
-            let manifest_json = serde_json::to_vec_pretty(&manifest)?;
-            let manifest_key = format!(
-                "{}/manifests/{}",
-                prov.remote_prefix.trim_end_matches('/'),
-                file_hash
-            );
-            prov.operator.write(&manifest_key, manifest_json).await?;
-
-            // Write index entry
-            let index_key = format!(
-                "{}/index/{}",
-                prov.remote_prefix.trim_end_matches('/'),
-                remote_str.trim_start_matches('/')
-            );
-            let index_entry = format!(
-                "manifest_hash={}\nsize={}\nchunks={}\n",
-                file_hash,
-                data.len(),
-                chunks.len()
-            );
-            prov.operator.write(&index_key, index_entry).await?;
-
-            Ok::<(), anyhow::Error>(())
-        });
+        let upload_result = prov.runtime.block_on(tcfs_embed::upload_file(
+            &prov.store,
+            std::path::Path::new(local_str),
+            remote_str,
+        ));
 
         match upload_result {
-            Ok(()) => TcfsError::TcfsErrorNone,
-            Err(_) => TcfsError::TcfsErrorStorage,
+            Ok(_) => TcfsError::TcfsErrorNone,
+            Err(e) => fail(TcfsError::TcfsErrorStorage, format!("{e:#}")),
         }
     }));
 
-    result.unwrap_or(TcfsError::TcfsErrorInternal)
+    result.unwrap_or_else(|payload| fail(TcfsError::TcfsErrorInternal, panic_message(&*payload)))
 }
 
 /// Free a provider handle.
@@ -462,6 +496,45 @@ pub unsafe extern "C" fn tcfs_file_items_free(items: *mut TcfsFileItem, count: u
     }
 }
 
+/// Free a cursor returned by `tcfs_provider_enumerate_page`.
+///
+/// # Safety
+///
+/// `cursor` must be a pointer returned by `tcfs_provider_enumerate_page`, or
+/// null (no-op). Must not be called more than once for the same pointer.
+#[no_mangle]
+pub unsafe extern "C" fn tcfs_enumerate_cursor_free(cursor: *mut TcfsEnumerateCursor) {
+    if !cursor.is_null() {
+        unsafe {
+            drop(Box::from_raw(cursor));
+        }
+    }
+}
+
+/// Describe the last error returned by an FFI call on the calling thread.
+///
+/// Every FFI call clears the message on entry and sets it when it returns an
+/// error code or null, so this must be read before the next call. Returns a
+/// new string the caller must free via `tcfs_string_free`, or null if the
+/// last call succeeded.
+#[no_mangle]
+pub extern "C" fn tcfs_last_error() -> *mut c_char {
+    LAST_ERROR.with(|last| match last.borrow().as_ref() {
+        Some(msg) => msg.clone().into_raw(),
+        None => ptr::null_mut(),
+    })
+}
+
+/// Version of this library, e.g. `0.6.0`.
+///
+/// Returns a static NUL-terminated string owned by the library: it stays
+/// valid for the life of the process and must not be passed to
+/// `tcfs_string_free`.
+#[no_mangle]
+pub extern "C" fn tcfs_provider_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
 /// Free a C string allocated by this crate.
 ///
 /// # Safety
@@ -474,6 +547,113 @@ pub unsafe extern "C" fn tcfs_string_free(s: *mut c_char) {
 
 // --- Internal helpers ---
 
+/// The item for a listing entry under `prefix`, or `None` for the listed
+/// directory itself and its marker.
+fn file_item(prefix: &str, entry: &opendal::Entry) -> Option<TcfsFileItem> {
+    let entry_path = entry.path();
+    let name = item_name(prefix, entry_path)?;
+    let is_dir = name.ends_with('/');
+    let display_name = name.trim_end_matches('/');
+
+    Some(TcfsFileItem {
+        item_id: to_c_string(entry_path),
+        filename: to_c_string(display_name),
+        file_size: entry.metadata().content_length(),
+        modified_timestamp: 0,
+        is_directory: is_dir,
+        content_hash: to_c_string(""),
+    })
+}
+
+fn item_name<'a>(prefix: &str, entry_path: &'a str) -> Option<&'a str> {
+    let name = entry_path
+        .strip_prefix(prefix)
+        .unwrap_or(entry_path)
+        .trim_start_matches('/');
+    (!name.is_empty() && name != tcfs_core::index::DIR_MARKER).then_some(name)
+}
+
+/// Hand `items` to the caller as a boxed slice.
+unsafe fn write_items(
+    items: Vec<TcfsFileItem>,
+    out_items: *mut *mut TcfsFileItem,
+    out_count: *mut usize,
+) {
+    let count = items.len();
+    let ptr = Box::into_raw(items.into_boxed_slice()) as *mut TcfsFileItem;
+    unsafe {
+        *out_items = ptr;
+        *out_count = count;
+    }
+}
+
+/// Up to `page_size` items listed under `prefix` after the key `after`,
+/// and the key to continue from if the listing goes on.
+///
+/// Listings come back in key order, so backends without `start_after`
+/// skip up to the cursor while streaming.
+async fn list_page(
+    op: &opendal::Operator,
+    prefix: &str,
+    after: Option<&str>,
+    page_size: usize,
+) -> opendal::Result<(Vec<TcfsFileItem>, Option<String>)> {
+    use futures::TryStreamExt;
+
+    let capability = op.info().full_capability();
+    let mut lister = op.lister_with(prefix);
+    if capability.list_with_limit {
+        lister = lister.limit(page_size);
+    }
+    let seek = after.filter(|_| capability.list_with_start_after);
+    if let Some(key) = seek {
+        lister = lister.start_after(key);
+    }
+    let mut lister = lister.await?;
+
+    let mut items = Vec::with_capacity(page_size);
+    let mut last = String::new();
+    while let Some(entry) = lister.try_next().await? {
+        if seek.is_none() && after.is_some_and(|key| entry.path() <= key) {
+            continue;
+        }
+        if item_name(prefix, entry.path()).is_none() {
+            continue;
+        }
+        if items.len() == page_size {
+            // Another item exists: this page ends at the previous one
+            return Ok((items, Some(last)));
+        }
+        last = entry.path().to_string();
+        items.extend(file_item(prefix, &entry));
+    }
+    Ok((items, None))
+}
+
+fn set_last_error(msg: impl std::fmt::Display) {
+    let msg = CString::new(msg.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(msg));
+}
+
+fn clear_last_error() {
+    LAST_ERROR.with(|last| *last.borrow_mut() = None);
+}
+
+/// Record `msg` as the last error and return `code`.
+fn fail(code: TcfsError, msg: impl std::fmt::Display) -> TcfsError {
+    set_last_error(msg);
+    code
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    let detail = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic");
+    format!("internal error: {detail}")
+}
+
 fn to_c_string(s: &str) -> *mut c_char {
     CString::new(s)
         .unwrap_or_else(|_| CString::new("").unwrap())
@@ -487,3 +667,199 @@ unsafe fn free_c_string(s: *mut c_char) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn storage_error_sets_freeable_last_error() {
+        // Nothing listens on port 1, so the first request fails to connect
+        let operator = opendal::Operator::new(
+            opendal::services::S3::default()
+                .endpoint("http://127.0.0.1:1")
+                .region("us-east-1")
+                .bucket("tcfs")
+                .access_key_id("test")
+                .secret_access_key("test"),
+        )
+        .unwrap()
+        .finish();
+        let provider = Box::into_raw(Box::new(TcfsProvider {
+            runtime: tokio::runtime::Runtime::new().unwrap(),
+            store: ChunkStore::new(operator, RemoteLayout::new("devices/test")),
+        }));
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let local = tmp.path().join("notes.txt");
+        std::fs::write(&local, b"hello").unwrap();
+        let local = CString::new(local.to_str().unwrap()).unwrap();
+        let remote = CString::new("notes.txt").unwrap();
+
+        let code = unsafe { tcfs_provider_upload(provider, local.as_ptr(), remote.as_ptr()) };
+        assert!(matches!(code, TcfsError::TcfsErrorStorage));
+
+        let msg = tcfs_last_error();
+        assert!(!msg.is_null());
+        let text = unsafe { CStr::from_ptr(msg) }.to_str().unwrap().to_string();
+        assert!(!text.is_empty());
+        unsafe { tcfs_string_free(msg) };
+
+        // A failed argument check replaces the message; success clears it
+        let code = unsafe { tcfs_provider_upload(provider, ptr::null(), remote.as_ptr()) };
+        assert!(matches!(code, TcfsError::TcfsErrorInvalidArg));
+        let msg = tcfs_last_error();
+        let text = unsafe { CStr::from_ptr(msg) }.to_str().unwrap().to_string();
+        assert!(text.contains("null"), "{text}");
+        unsafe { tcfs_string_free(msg) };
+
+        unsafe { tcfs_provider_free(provider) };
+        let config = CString::new(r#"{"s3_endpoint": "http://127.0.0.1:1"}"#).unwrap();
+        let provider = unsafe { tcfs_provider_new(config.as_ptr()) };
+        assert!(!provider.is_null());
+        assert!(tcfs_last_error().is_null());
+        unsafe { tcfs_provider_free(provider) };
+    }
+
+    #[test]
+    fn provider_version_matches_crate_version() {
+        let version = unsafe { CStr::from_ptr(tcfs_provider_version()) };
+        assert_eq!(version.to_str().unwrap(), env!("CARGO_PKG_VERSION"));
+        // Static: the same pointer every call
+        assert_eq!(tcfs_provider_version(), tcfs_provider_version());
+    }
+
+    #[test]
+    fn header_declares_error_and_version_functions() {
+        let header =
+            std::fs::read_to_string(concat!(env!("OUT_DIR"), "/tcfs_file_provider.h")).unwrap();
+        for decl in [
+            "const char *tcfs_provider_version(void);",
+            "char *tcfs_last_error(void);",
+            "void tcfs_string_free(char *s);",
+        ] {
+            assert!(header.contains(decl), "missing {decl}");
+        }
+    }
+
+    #[test]
+    fn enumerate_pages_cover_the_listing() {
+        let operator = opendal::Operator::new(opendal::services::Memory::default())
+            .unwrap()
+            .finish();
+        let store = ChunkStore::new(operator.clone(), RemoteLayout::new("devices/test"));
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        for i in 0..50 {
+            let key = store.layout().index_key(&format!("docs/file{i:02}.txt"));
+            rt.block_on(operator.write(&key, format!("manifest_hash=h{i}\n")))
+                .unwrap();
+        }
+        let marker = format!(
+            "{}{}",
+            store.layout().index_dir("docs"),
+            tcfs_core::index::DIR_MARKER
+        );
+        rt.block_on(operator.write(&marker, "")).unwrap();
+        let provider = Box::into_raw(Box::new(TcfsProvider { runtime: rt, store }));
+
+        let path = CString::new("docs").unwrap();
+        let mut cursor: *mut TcfsEnumerateCursor = ptr::null_mut();
+        let mut names = Vec::new();
+        let mut pages = 0;
+        loop {
+            let mut items = ptr::null_mut();
+            let mut count = 0;
+            let mut next = ptr::null_mut();
+            let code = unsafe {
+                tcfs_provider_enumerate_page(
+                    provider,
+                    path.as_ptr(),
+                    cursor,
+                    10,
+                    &mut items,
+                    &mut count,
+                    &mut next,
+                )
+            };
+            assert!(matches!(code, TcfsError::TcfsErrorNone));
+            assert!(count <= 10);
+            let page = unsafe { std::slice::from_raw_parts(items, count) };
+            for item in page {
+                let name = unsafe { CStr::from_ptr(item.filename) };
+                names.push(name.to_str().unwrap().to_string());
+            }
+            unsafe {
+                tcfs_file_items_free(items, count);
+                tcfs_enumerate_cursor_free(cursor);
+            }
+            pages += 1;
+            cursor = next;
+            if cursor.is_null() {
+                break;
+            }
+        }
+
+        assert_eq!(pages, 5);
+        let expected: Vec<String> = (0..50).map(|i| format!("file{i:02}.txt")).collect();
+        assert_eq!(names, expected);
+
+        let mut items = ptr::null_mut();
+        let mut count = 0;
+        let mut next = ptr::null_mut();
+        let code = unsafe {
+            tcfs_provider_enumerate_page(
+                provider,
+                path.as_ptr(),
+                ptr::null(),
+                0,
+                &mut items,
+                &mut count,
+                &mut next,
+            )
+        };
+        assert!(matches!(code, TcfsError::TcfsErrorInvalidArg));
+        unsafe { tcfs_provider_free(provider) };
+    }
+
+    #[test]
+    fn upload_then_fetch_through_the_chunk_store() {
+        let operator = opendal::Operator::new(opendal::services::Memory::default())
+            .unwrap()
+            .finish();
+        let store = ChunkStore::new(
+            operator,
+            RemoteLayout::new("devices/test").with_chunk_shard_depth(1),
+        );
+        let provider = Box::into_raw(Box::new(TcfsProvider {
+            runtime: tokio::runtime::Runtime::new().unwrap(),
+            store: store.clone(),
+        }));
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let content: Vec<u8> = (0..40_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let local = tmp.path().join("notes.txt");
+        std::fs::write(&local, &content).unwrap();
+        let local = CString::new(local.to_str().unwrap()).unwrap();
+        let remote = CString::new("notes.txt").unwrap();
+        let code = unsafe { tcfs_provider_upload(provider, local.as_ptr(), remote.as_ptr()) };
+        assert!(matches!(code, TcfsError::TcfsErrorNone));
+
+        // What the FFI wrote is what the engine's store reads back
+        let file_hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&content));
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let manifest = rt.block_on(store.get_manifest(&file_hash)).unwrap();
+        assert_eq!(manifest.chunk_shard_depth, 1);
+        for hash in manifest.chunk_hashes() {
+            assert!(rt.block_on(store.has_chunk(hash)).unwrap());
+        }
+
+        let item = CString::new(store.layout().index_key("notes.txt")).unwrap();
+        let dest = tmp.path().join("fetched.txt");
+        let dest_c = CString::new(dest.to_str().unwrap()).unwrap();
+        let code = unsafe { tcfs_provider_fetch(provider, item.as_ptr(), dest_c.as_ptr()) };
+        assert!(matches!(code, TcfsError::TcfsErrorNone));
+        assert_eq!(std::fs::read(&dest).unwrap(), content);
+
+        unsafe { tcfs_provider_free(provider) };
+    }
+}
@@ -1,6 +1,7 @@
 //! Disk cache for hydrated file content.
 //!
-//! Stores fully-assembled file content keyed by manifest hash. Files are
+//! Stores fully-assembled file content keyed by manifest hash, and the
+//! manifest itself as `{hash}.manifest` so hits can be checked. Files are
 //! written atomically (temp → rename) and evicted LRU-style when the cache
 //! exceeds `max_bytes`.
 //!
@@ -18,8 +18,121 @@
 //!     README.md.tc
 //! ```
 //!
-//! On `open()` of a `.tc` file, the content is fetched from SeaweedFS (via
+//! With `fuse.transparent_names` files are shown under their real names
+//! instead (`README.md`), and whether a name is a file or a directory is
+//! decided by reading its index entry and listing beneath it rather than by
+//! suffix. Stub names stay the default, as they make hydration visible.
+//!
+//! Index entries that record a symlink (`symlink=<target>`) appear under their
+//! own name as symlinks, and `readlink` returns the stored target.
+//!
+//! Directory markers (`{prefix}/index/logs/.tcfsdir`) make otherwise empty
+//! directories visible but are never listed themselves. Tombstone entries
+//! (`deleted=1`) are hidden from listings and lookups.
+//!
+//! On `open()` of a `.tc` file (or a real name in transparent mode), the content is fetched from SeaweedFS (via
 //! the manifest) and served transparently. Fetched content is cached in `DiskCache`.
+//! Encrypted files are decrypted with the mount's master key and never cached
+//! on disk; without a key, opening one fails with `EACCES`.
+//!
+//! With `fuse.encrypted_names`, each component of an index key below
+//! `{prefix}/index/` is the hex AES-SIV ciphertext of the real name
+//! (`tcfs_crypto::encrypt_name` under the master key's name key); directory
+//! markers stay in the clear. Given the master key the mount lists the
+//! decrypted names and encrypts looked-up names back to find their entries.
+//! Without it, or for a component that does not decrypt, it shows a stable
+//! masked name (`encrypted-` and the first 16 hex digits of the ciphertext)
+//! that still resolves, so the tree can be browsed but not read.
+
+// ── Encrypted names ───────────────────────────────────────────────────────────
+
+/// Prefix of the names shown for components that cannot be decrypted.
+pub const MASKED_NAME_PREFIX: &str = "encrypted-";
+
+/// Hex digits of the ciphertext kept in a masked name.
+const MASKED_NAME_DIGITS: usize = 16;
+
+/// Stable stand-in for the stored name component `stored`.
+pub fn mask_name(stored: &str) -> String {
+    let digits = stored.get(..MASKED_NAME_DIGITS).unwrap_or(stored);
+    format!("{MASKED_NAME_PREFIX}{digits}")
+}
+
+/// Name shown for the stored component `stored`: its decryption under
+/// `name_key`, or [`mask_name`] without a key or if it does not decrypt to
+/// a single path component.
+pub fn shown_name(name_key: Option<&[u8; tcfs_crypto::KEY_SIZE]>, stored: &str) -> String {
+    name_key
+        .and_then(|key| tcfs_crypto::decrypt_name(key, stored).ok())
+        .filter(|name| !name.is_empty() && name != "." && name != ".." && !name.contains('/'))
+        .unwrap_or_else(|| mask_name(stored))
+}
+
+// ── Directory listing ─────────────────────────────────────────────────────────
+
+/// A virtual directory entry synthesized from index keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirListing {
+    /// Name shown in the mount (`main.rs.tc` for files, `src` for directories)
+    pub name: String,
+    /// True for directories implied by deeper index keys
+    pub is_dir: bool,
+    /// Full index key for file entries (used to read the entry's size)
+    pub index_key: Option<String>,
+}
+
+impl DirListing {
+    /// Name shown when the entry turns out to be a symlink, or for every file
+    /// with transparent names (no `.tc` suffix).
+    pub fn link_name(&self) -> &str {
+        self.name.strip_suffix(".tc").unwrap_or(&self.name)
+    }
+}
+
+/// Collapse the index keys under `index_prefix` into one listing per child.
+///
+/// Files become `.tc` stubs, deeper keys collapse into a single directory
+/// entry, directory markers are dropped, and the result is sorted by name so that offsets are stable
+/// across repeated `readdir` calls regardless of backend list order.
+pub fn list_dir_entries<'a>(
+    index_prefix: &str,
+    keys: impl IntoIterator<Item = &'a str>,
+) -> Vec<DirListing> {
+    let mut seen_dirs = std::collections::HashSet::new();
+    let mut listing = Vec::new();
+
+    for full_path in keys {
+        let rel = full_path
+            .trim_start_matches(index_prefix)
+            .trim_start_matches('/');
+        if rel.is_empty() || rel == tcfs_core::index::DIR_MARKER {
+            continue;
+        }
+
+        let first_component = rel.split('/').next().unwrap_or(rel);
+        let is_dir = rel.contains('/') || rel.ends_with('/');
+
+        if is_dir {
+            let dir_name = first_component.trim_end_matches('/').to_string();
+            if seen_dirs.insert(dir_name.clone()) {
+                listing.push(DirListing {
+                    name: dir_name,
+                    is_dir: true,
+                    index_key: None,
+                });
+            }
+        } else {
+            listing.push(DirListing {
+                name: format!("{}.tc", first_component),
+                is_dir: false,
+                index_key: Some(full_path.to_string()),
+            });
+        }
+    }
+
+    listing.sort_by(|a, b| a.name.cmp(&b.name));
+    listing
+}
 
 #[cfg(feature = "fuse")]
 mod inner {
@@ -39,18 +152,22 @@ mod inner {
     use tracing::{debug, info, warn};
 
     use crate::cache::DiskCache;
-    use crate::hydrate::fetch_cached;
+    use crate::hydrate::{fetch_cached, fetch_packed_cached};
     use crate::negative_cache::NegativeCache;
     use crate::stub::IndexEntry;
+    use tcfs_core::layout::RemoteLayout;
+    use tcfs_crypto::MasterKey;
+    use tcfs_sync::engine::KeyRequired;
 
     // ── Configuration ─────────────────────────────────────────────────────────
 
-    /// TTL for positive dentry/attr cache entries (FUSE kernel cache)
-    const ATTR_TTL: Duration = Duration::from_secs(5);
+    /// Default TTL for positive dentry/attr cache entries (FUSE kernel cache)
+    pub const DEFAULT_ATTR_TTL: Duration = Duration::from_secs(5);
 
     /// Fake uid/gid used for all files (real process uid/gid set at mount)
     const PERM_FILE: u16 = 0o444; // r--r--r--
     const PERM_DIR: u16 = 0o555; // r-xr-xr-x
+    const PERM_LINK: u16 = 0o777; // lrwxrwxrwx
 
     // ── File handle table ─────────────────────────────────────────────────────
 
@@ -64,7 +181,7 @@ mod inner {
     /// The FUSE filesystem driver.
     pub struct TcfsFs {
         op: Operator,
-        prefix: String,
+        layout: RemoteLayout,
         uid: u32,
         gid: u32,
         negative_cache: Arc<NegativeCache>,
@@ -75,6 +192,20 @@ mod inner {
         next_fh: Arc<AtomicU64>,
         /// Mount timestamp (used as atime/mtime for all synthetic entries)
         mount_time: SystemTime,
+        /// Bits cleared from recorded file modes (see `sync.mode_umask`)
+        mode_umask: u32,
+        /// Master key for encrypted files (`None` = session locked)
+        master_key: Option<MasterKey>,
+        /// How long the kernel may cache attributes we return
+        attr_ttl: Duration,
+        /// How long the kernel may cache names we resolve
+        entry_ttl: Duration,
+        /// Show files under their real names rather than as `.tc` stubs
+        transparent_names: bool,
+        /// Index key components are encrypted names
+        encrypted_names: bool,
+        /// Key for encrypted names, derived from `master_key`
+        name_key: Option<[u8; tcfs_crypto::KEY_SIZE]>,
     }
 
     impl TcfsFs {
@@ -85,17 +216,21 @@ mod inner {
         /// - `cache_dir` — local dir for hydrated file cache
         /// - `cache_max_bytes` — max disk cache size
         /// - `negative_ttl` — TTL for negative dentry cache
+        /// - `mode_umask` — bits cleared from file modes recorded in index entries
+        /// - `master_key` — key for decrypting encrypted files, if unlocked
         pub fn new(
             op: Operator,
             prefix: String,
             cache_dir: std::path::PathBuf,
             cache_max_bytes: u64,
             negative_ttl: Duration,
+            mode_umask: u32,
+            master_key: Option<MasterKey>,
         ) -> Self {
             let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
             TcfsFs {
                 op,
-                prefix,
+                layout: RemoteLayout::new(&prefix),
                 uid,
                 gid,
                 negative_cache: Arc::new(NegativeCache::new(negative_ttl)),
@@ -103,62 +238,232 @@ mod inner {
                 handles: Arc::new(Mutex::new(HashMap::new())),
                 next_fh: Arc::new(AtomicU64::new(1)),
                 mount_time: SystemTime::now(),
+                mode_umask,
+                master_key,
+                attr_ttl: DEFAULT_ATTR_TTL,
+                entry_ttl: DEFAULT_ATTR_TTL,
+                transparent_names: false,
+                encrypted_names: false,
+                name_key: None,
+            }
+        }
+
+        /// Let the kernel cache attributes for `attr_ttl` and resolved names
+        /// for `entry_ttl` instead of [`DEFAULT_ATTR_TTL`].
+        pub fn with_ttls(mut self, attr_ttl: Duration, entry_ttl: Duration) -> Self {
+            self.attr_ttl = attr_ttl;
+            self.entry_ttl = entry_ttl;
+            self
+        }
+
+        /// Report `uid`/`gid` as the owner of every entry instead of the
+        /// mounting process's ids.
+        pub fn with_owner(mut self, uid: Option<u32>, gid: Option<u32>) -> Self {
+            if let Some(uid) = uid {
+                self.uid = uid;
+            }
+            if let Some(gid) = gid {
+                self.gid = gid;
+            }
+            self
+        }
+
+        /// Present files as `README.md` rather than `README.md.tc`.
+        pub fn with_transparent_names(mut self, transparent: bool) -> Self {
+            self.transparent_names = transparent;
+            self
+        }
+
+        /// Treat index key components as encrypted names: decrypted with the
+        /// master key's name key when one is loaded, masked otherwise.
+        pub fn with_encrypted_names(mut self, encrypted: bool) -> Self {
+            self.encrypted_names = encrypted;
+            self.name_key = None;
+            if encrypted {
+                if let Some(master) = &self.master_key {
+                    match tcfs_crypto::derive_name_key(master) {
+                        Ok(key) => self.name_key = Some(key),
+                        Err(e) => warn!("deriving name key failed, masking names: {e}"),
+                    }
+                }
             }
+            self
+        }
+
+        /// Whether `name` is addressed as a `.tc`/`.tcf` stub in this mount.
+        fn is_stub_name(&self, name: &str) -> bool {
+            !self.transparent_names && (name.ends_with(".tc") || name.ends_with(".tcf"))
         }
 
         /// Build the index path for a virtual FS path.
         ///
-        /// `/src/main.rs.tc` → `{prefix}/index/src/main.rs`
-        fn index_key_for(&self, vpath: &str) -> Option<String> {
+        /// `/src/main.rs.tc` → `{prefix}/index/src/main.rs` (with transparent
+        /// names, `/src/main.rs` → the same key; with encrypted names, each
+        /// component encrypted or unmasked)
+        async fn index_key_for(&self, vpath: &str) -> Option<String> {
             // Strip leading slash
             let rel = vpath.trim_start_matches('/');
             if rel.is_empty() {
                 return None; // root directory — no index key
             }
             // Strip .tc suffix to get the real filename
-            let real = rel
-                .strip_suffix(".tc")
-                .or_else(|| rel.strip_suffix(".tcf"))
-                .unwrap_or(rel);
-            Some(format!(
-                "{}/index/{}",
-                self.prefix.trim_end_matches('/'),
-                real
-            ))
+            let real = if self.transparent_names {
+                rel
+            } else {
+                rel.strip_suffix(".tc")
+                    .or_else(|| rel.strip_suffix(".tcf"))
+                    .unwrap_or(rel)
+            };
+            // Refuse traversal out of the prefix; markers are never entries
+            tcfs_core::paths::normalize_rel_path(real).ok()?;
+            if real.rsplit('/').next() == Some(tcfs_core::index::DIR_MARKER) {
+                return None;
+            }
+            Some(self.layout.index_key(&self.stored_rel(real).await?))
         }
 
         /// The index prefix for directory listing: `{prefix}/index/{rel_dir}/`
-        fn index_prefix_for_dir(&self, vdir: &str) -> String {
-            let rel = vdir.trim_start_matches('/').trim_end_matches('/');
-            let prefix = self.prefix.trim_end_matches('/');
+        async fn index_prefix_for_dir(&self, vdir: &str) -> Option<String> {
+            let rel = vdir.trim_matches('/');
             if rel.is_empty() {
-                format!("{}/index/", prefix)
-            } else {
-                format!("{}/index/{}/", prefix, rel)
+                return Some(self.layout.index_dir(""));
+            }
+            Some(self.layout.index_dir(&self.stored_rel(rel).await?))
+        }
+
+        /// Whether any index entries exist under the virtual directory `vdir`.
+        async fn has_entries_under(&self, vdir: &str) -> bool {
+            let Some(dir) = self.index_prefix_for_dir(vdir).await else {
+                return false;
+            };
+            self.op
+                .list(&dir)
+                .await
+                .is_ok_and(|entries| !entries.is_empty())
+        }
+
+        /// The relative path stored in the index for the shown path `rel`.
+        ///
+        /// With encrypted names each component is encrypted with the name
+        /// key, or, being masked, matched against the stored names of its
+        /// parent directory.
+        async fn stored_rel(&self, rel: &str) -> Option<String> {
+            if !self.encrypted_names {
+                return Some(rel.to_string());
+            }
+            let mut stored: Vec<String> = Vec::new();
+            for component in rel.split('/').filter(|c| !c.is_empty()) {
+                let name = if component == tcfs_core::index::DIR_MARKER {
+                    component.to_string()
+                } else if let Some(digits) = component.strip_prefix(super::MASKED_NAME_PREFIX) {
+                    self.unmask(&stored.join("/"), digits).await?
+                } else {
+                    tcfs_crypto::encrypt_name(self.name_key.as_ref()?, component).ok()?
+                };
+                stored.push(name);
             }
+            Some(stored.join("/"))
+        }
+
+        /// The stored component under `stored_dir` whose masked name ends in
+        /// `digits`.
+        async fn unmask(&self, stored_dir: &str, digits: &str) -> Option<String> {
+            let dir = self.layout.index_dir(stored_dir);
+            let entries = self.op.list(&dir).await.ok()?;
+            let mut names: Vec<&str> = entries
+                .iter()
+                .filter_map(|e| e.path().strip_prefix(dir.as_str()))
+                .map(|rel| rel.trim_end_matches('/'))
+                .filter(|name| !name.is_empty() && !name.contains('/'))
+                .collect();
+            names.sort_unstable();
+            names
+                .into_iter()
+                .find(|name| {
+                    super::mask_name(name) == format!("{}{digits}", super::MASKED_NAME_PREFIX)
+                })
+                .map(str::to_string)
+        }
+
+        /// `item` with its stored name replaced by the name shown for it
+        /// (keeping the `.tc` suffix of files).
+        fn shown_listing(&self, mut item: super::DirListing) -> super::DirListing {
+            if !self.encrypted_names {
+                return item;
+            }
+            let stored = if item.is_dir {
+                item.name.as_str()
+            } else {
+                item.link_name()
+            };
+            let shown = super::shown_name(self.name_key.as_ref(), stored);
+            item.name = if item.is_dir {
+                shown
+            } else {
+                format!("{shown}.tc")
+            };
+            item
         }
 
         /// Fetch and parse an IndexEntry for a virtual path.
+        ///
+        /// Tombstones are reported as missing.
         async fn get_index_entry(&self, vpath: &str) -> Option<IndexEntry> {
-            let key = self.index_key_for(vpath)?;
+            let key = self.index_key_for(vpath).await?;
             let data = self.op.read(&key).await.ok()?;
-            let text = String::from_utf8(data.to_bytes().to_vec()).ok()?;
-            IndexEntry::parse(&text).ok()
+            IndexEntry::from_bytes(&data.to_bytes())
+                .ok()
+                .filter(|e| !e.is_tombstone())
+        }
+
+        /// Fetch and parse an IndexEntry by its S3 key.
+        async fn read_index_entry(&self, index_key: &str) -> Option<IndexEntry> {
+            let data = self.op.read(index_key).await.ok()?;
+            IndexEntry::from_bytes(&data.to_bytes()).ok()
         }
 
-        /// Fetch the real file size from an index entry by its S3 key.
-        async fn read_index_entry_size(&self, index_key: &str) -> u64 {
-            match self.op.read(index_key).await {
-                Ok(data) => {
-                    let text = String::from_utf8(data.to_bytes().to_vec()).unwrap_or_default();
-                    IndexEntry::parse(&text).map(|e| e.size).unwrap_or(0)
+        /// Name, kind, and attributes for a file listing, read from its index entry.
+        ///
+        /// Returns `None` for tombstones, which are left out of the listing.
+        async fn listing_attr(
+            &self,
+            item: &super::DirListing,
+        ) -> Option<(String, FileType, FileAttr)> {
+            let entry = match &item.index_key {
+                Some(key) => self.read_index_entry(key).await,
+                None => None,
+            };
+            match entry {
+                Some(entry) if entry.is_tombstone() => None,
+                Some(entry) if entry.is_symlink() => Some((
+                    item.link_name().to_string(),
+                    FileType::Symlink,
+                    self.symlink_attr(entry.size),
+                )),
+                entry => {
+                    let (size, mode) = entry.map(|e| (e.size, e.mode)).unwrap_or((0, None));
+                    let name = if self.transparent_names {
+                        item.link_name()
+                    } else {
+                        &item.name
+                    };
+                    Some((
+                        name.to_string(),
+                        FileType::RegularFile,
+                        self.file_attr(size, mode),
+                    ))
                 }
-                Err(_) => 0,
             }
         }
 
-        /// Synthesize a `FileAttr` for a stub file given its size.
-        fn file_attr(&self, size: u64) -> FileAttr {
+        /// Synthesize a `FileAttr` for a stub file given its size and recorded mode.
+        ///
+        /// The mount is read-only, so write bits are always dropped; files
+        /// without a recorded mode are shown as `r--r--r--`.
+        fn file_attr(&self, size: u64, mode: Option<u32>) -> FileAttr {
+            let perm = mode
+                .map(|m| (m & 0o555 & !self.mode_umask) as u16)
+                .unwrap_or(PERM_FILE);
             FileAttr {
                 size,
                 blocks: size.div_ceil(512),
@@ -168,7 +473,7 @@ mod inner {
                 #[cfg(target_os = "macos")]
                 crtime: self.mount_time,
                 kind: FileType::RegularFile,
-                perm: PERM_FILE,
+                perm,
                 nlink: 1,
                 uid: self.uid,
                 gid: self.gid,
@@ -179,6 +484,16 @@ mod inner {
             }
         }
 
+        /// Synthesize a `FileAttr` for a symlink whose target is `size` bytes long.
+        fn symlink_attr(&self, size: u64) -> FileAttr {
+            FileAttr {
+                kind: FileType::Symlink,
+                perm: PERM_LINK,
+                blocks: 0,
+                ..self.file_attr(size, None)
+            }
+        }
+
         /// Synthesize a `FileAttr` for a directory.
         fn dir_attr(&self) -> FileAttr {
             FileAttr {
@@ -206,7 +521,7 @@ mod inner {
 
     impl PathFilesystem for TcfsFs {
         async fn init(&self, _req: Request) -> fuse3::Result<ReplyInit> {
-            debug!(prefix = %self.prefix, "tcfs-fuse init");
+            debug!(prefix = %self.layout.prefix(), "tcfs-fuse init");
             Ok(ReplyInit {
                 max_write: NonZeroU32::new(128 * 1024).unwrap(),
             })
@@ -231,7 +546,7 @@ mod inner {
             // Root directory
             if path_str == "/" {
                 return Ok(ReplyAttr {
-                    ttl: ATTR_TTL,
+                    ttl: self.attr_ttl,
                     attr: self.dir_attr(),
                 });
             }
@@ -242,32 +557,47 @@ mod inner {
             }
 
             // Check if it's a stub file (.tc)
-            if path_str.ends_with(".tc") || path_str.ends_with(".tcf") {
+            if self.is_stub_name(path_str) {
                 match self.get_index_entry(path_str).await {
-                    Some(entry) => {
+                    Some(entry) if !entry.is_symlink() => {
                         return Ok(ReplyAttr {
-                            ttl: ATTR_TTL,
-                            attr: self.file_attr(entry.size),
+                            ttl: self.attr_ttl,
+                            attr: self.file_attr(entry.size, entry.mode),
                         });
                     }
-                    None => {
+                    _ => {
                         self.negative_cache.insert(path_str);
                         return Err(Errno::from(libc::ENOENT));
                     }
                 }
             }
 
+            // Symlinks (and, with transparent names, files) are shown under
+            // their own name, without a .tc suffix
+            if let Some(entry) = self.get_index_entry(path_str).await {
+                if entry.is_symlink() {
+                    return Ok(ReplyAttr {
+                        ttl: self.attr_ttl,
+                        attr: self.symlink_attr(entry.size),
+                    });
+                }
+                if self.transparent_names {
+                    return Ok(ReplyAttr {
+                        ttl: self.attr_ttl,
+                        attr: self.file_attr(entry.size, entry.mode),
+                    });
+                }
+            }
+
             // Otherwise treat as a directory: check if any index entries exist under it
-            let dir_prefix = self.index_prefix_for_dir(path_str);
-            match self.op.list(&dir_prefix).await {
-                Ok(entries) if !entries.is_empty() => Ok(ReplyAttr {
-                    ttl: ATTR_TTL,
+            if self.has_entries_under(path_str).await {
+                Ok(ReplyAttr {
+                    ttl: self.attr_ttl,
                     attr: self.dir_attr(),
-                }),
-                _ => {
-                    self.negative_cache.insert(path_str);
-                    Err(Errno::from(libc::ENOENT))
-                }
+                })
+            } else {
+                self.negative_cache.insert(path_str);
+                Err(Errno::from(libc::ENOENT))
             }
         }
 
@@ -292,32 +622,46 @@ mod inner {
             }
 
             // Stub file lookup
-            if name_str.ends_with(".tc") || name_str.ends_with(".tcf") {
+            if self.is_stub_name(name_str) {
                 match self.get_index_entry(&full_path).await {
-                    Some(entry) => {
+                    Some(entry) if !entry.is_symlink() => {
                         return Ok(ReplyEntry {
-                            ttl: ATTR_TTL,
-                            attr: self.file_attr(entry.size),
+                            ttl: self.entry_ttl,
+                            attr: self.file_attr(entry.size, entry.mode),
                         });
                     }
-                    None => {
+                    _ => {
                         self.negative_cache.insert(&full_path);
                         return Err(Errno::from(libc::ENOENT));
                     }
                 }
             }
 
+            // Symlink (or transparent-name file) lookup
+            if let Some(entry) = self.get_index_entry(&full_path).await {
+                if entry.is_symlink() {
+                    return Ok(ReplyEntry {
+                        ttl: self.entry_ttl,
+                        attr: self.symlink_attr(entry.size),
+                    });
+                }
+                if self.transparent_names {
+                    return Ok(ReplyEntry {
+                        ttl: self.entry_ttl,
+                        attr: self.file_attr(entry.size, entry.mode),
+                    });
+                }
+            }
+
             // Directory lookup
-            let dir_prefix = self.index_prefix_for_dir(&full_path);
-            match self.op.list(&dir_prefix).await {
-                Ok(entries) if !entries.is_empty() => Ok(ReplyEntry {
-                    ttl: ATTR_TTL,
+            if self.has_entries_under(&full_path).await {
+                Ok(ReplyEntry {
+                    ttl: self.entry_ttl,
                     attr: self.dir_attr(),
-                }),
-                _ => {
-                    self.negative_cache.insert(&full_path);
-                    Err(Errno::from(libc::ENOENT))
-                }
+                })
+            } else {
+                self.negative_cache.insert(&full_path);
+                Err(Errno::from(libc::ENOENT))
             }
         }
 
@@ -340,7 +684,10 @@ mod inner {
             offset: i64,
         ) -> fuse3::Result<ReplyDirectory<Self::DirEntryStream<'a>>> {
             let path_str = path.to_str().unwrap_or("/");
-            let index_prefix = self.index_prefix_for_dir(path_str);
+            let index_prefix = self
+                .index_prefix_for_dir(path_str)
+                .await
+                .ok_or(Errno::from(libc::ENOENT))?;
 
             let raw_entries = self
                 .op
@@ -348,7 +695,6 @@ mod inner {
                 .await
                 .map_err(|_| Errno::from(libc::EIO))?;
 
-            let mut seen_dirs: std::collections::HashSet<String> = std::collections::HashSet::new();
             let mut entries: Vec<fuse3::Result<DirectoryEntry>> = Vec::new();
 
             if offset == 0 {
@@ -366,39 +712,28 @@ mod inner {
                 }));
             }
 
-            let mut next_offset = 3i64;
-            for entry in raw_entries {
-                let full_path = entry.path();
-                let rel = full_path
-                    .trim_start_matches(&index_prefix)
-                    .trim_start_matches('/');
-                if rel.is_empty() {
+            let keys: Vec<&str> = raw_entries.iter().map(|e| e.path()).collect();
+            let listing = super::list_dir_entries(&index_prefix, keys);
+
+            for (i, item) in listing.into_iter().enumerate() {
+                let item = self.shown_listing(item);
+                let next_offset = i as i64 + 3;
+                if next_offset <= offset {
                     continue;
                 }
-
-                let first_component = rel.split('/').next().unwrap_or(rel);
-                let is_dir = rel.contains('/') || rel.ends_with('/');
-
-                let (dir_entry_name, kind) = if is_dir {
-                    let dir_name = first_component.trim_end_matches('/').to_string();
-                    if seen_dirs.contains(&dir_name) {
-                        continue;
-                    }
-                    seen_dirs.insert(dir_name.clone());
-                    (dir_name, FileType::Directory)
+                let (name, kind) = if item.is_dir {
+                    (item.name, FileType::Directory)
                 } else {
-                    let stub_name = format!("{}.tc", first_component);
-                    (stub_name, FileType::RegularFile)
+                    match self.listing_attr(&item).await {
+                        Some((name, kind, _)) => (name, kind),
+                        None => continue,
+                    }
                 };
-
-                if next_offset > offset {
-                    entries.push(Ok(DirectoryEntry {
-                        kind,
-                        name: dir_entry_name.into(),
-                        offset: next_offset,
-                    }));
-                }
-                next_offset += 1;
+                entries.push(Ok(DirectoryEntry {
+                    kind,
+                    name: name.into(),
+                    offset: next_offset,
+                }));
             }
 
             Ok(ReplyDirectory {
@@ -415,7 +750,10 @@ mod inner {
             _lock_owner: u64,
         ) -> fuse3::Result<ReplyDirectoryPlus<Self::DirEntryPlusStream<'a>>> {
             let path_str = path.to_str().unwrap_or("/");
-            let index_prefix = self.index_prefix_for_dir(path_str);
+            let index_prefix = self
+                .index_prefix_for_dir(path_str)
+                .await
+                .ok_or(Errno::from(libc::ENOENT))?;
 
             let raw_entries = self
                 .op
@@ -423,7 +761,6 @@ mod inner {
                 .await
                 .map_err(|_| Errno::from(libc::EIO))?;
 
-            let mut seen_dirs: std::collections::HashSet<String> = std::collections::HashSet::new();
             let mut entries: Vec<fuse3::Result<DirectoryEntryPlus>> = Vec::new();
             let offset = offset as i64;
 
@@ -433,8 +770,8 @@ mod inner {
                     name: ".".into(),
                     offset: 1,
                     attr: self.dir_attr(),
-                    entry_ttl: ATTR_TTL,
-                    attr_ttl: ATTR_TTL,
+                    entry_ttl: self.entry_ttl,
+                    attr_ttl: self.attr_ttl,
                 }));
             }
             if offset <= 1 {
@@ -443,49 +780,37 @@ mod inner {
                     name: "..".into(),
                     offset: 2,
                     attr: self.dir_attr(),
-                    entry_ttl: ATTR_TTL,
-                    attr_ttl: ATTR_TTL,
+                    entry_ttl: self.entry_ttl,
+                    attr_ttl: self.attr_ttl,
                 }));
             }
 
-            let mut next_offset = 3i64;
-            for entry in raw_entries {
-                let full_path = entry.path().to_string();
-                let rel = full_path
-                    .trim_start_matches(&index_prefix)
-                    .trim_start_matches('/');
-                if rel.is_empty() {
+            let keys: Vec<&str> = raw_entries.iter().map(|e| e.path()).collect();
+            let listing = super::list_dir_entries(&index_prefix, keys);
+
+            for (i, item) in listing.into_iter().enumerate() {
+                let item = self.shown_listing(item);
+                let next_offset = i as i64 + 3;
+                if next_offset <= offset {
                     continue;
                 }
-
-                let first_component = rel.split('/').next().unwrap_or(rel);
-                let is_dir = rel.contains('/') || rel.ends_with('/');
-
-                let (dir_entry_name, kind, attr) = if is_dir {
-                    let dir_name = first_component.trim_end_matches('/').to_string();
-                    if seen_dirs.contains(&dir_name) {
-                        continue;
-                    }
-                    seen_dirs.insert(dir_name.clone());
-                    (dir_name, FileType::Directory, self.dir_attr())
+                let (name, kind, attr) = if item.is_dir {
+                    (item.name, FileType::Directory, self.dir_attr())
                 } else {
-                    let stub_name = format!("{}.tc", first_component);
-                    // Read actual file size from the index entry content
-                    let size = self.read_index_entry_size(&full_path).await;
-                    (stub_name, FileType::RegularFile, self.file_attr(size))
+                    // Read actual size (and symlink target) from the index entry content
+                    match self.listing_attr(&item).await {
+                        Some(listed) => listed,
+                        None => continue,
+                    }
                 };
-
-                if next_offset > offset {
-                    entries.push(Ok(DirectoryEntryPlus {
-                        kind,
-                        name: dir_entry_name.into(),
-                        offset: next_offset,
-                        attr,
-                        entry_ttl: ATTR_TTL,
-                        attr_ttl: ATTR_TTL,
-                    }));
-                }
-                next_offset += 1;
+                entries.push(Ok(DirectoryEntryPlus {
+                    kind,
+                    name: name.into(),
+                    offset: next_offset,
+                    attr,
+                    entry_ttl: self.entry_ttl,
+                    attr_ttl: self.attr_ttl,
+                }));
             }
 
             Ok(ReplyDirectoryPlus {
@@ -493,6 +818,18 @@ mod inner {
             })
         }
 
+        async fn readlink(&self, _req: Request, path: &OsStr) -> fuse3::Result<ReplyData> {
+            let path_str = path.to_str().ok_or(Errno::from(libc::ENOENT))?;
+            let entry = self
+                .get_index_entry(path_str)
+                .await
+                .ok_or(Errno::from(libc::ENOENT))?;
+            let target = entry.symlink.ok_or(Errno::from(libc::EINVAL))?;
+            Ok(ReplyData {
+                data: Bytes::from(target.into_bytes()),
+            })
+        }
+
         async fn opendir(
             &self,
             _req: Request,
@@ -505,8 +842,8 @@ mod inner {
         async fn open(&self, _req: Request, path: &OsStr, _flags: u32) -> fuse3::Result<ReplyOpen> {
             let path_str = path.to_str().ok_or(Errno::from(libc::ENOENT))?;
 
-            // Only handle .tc stub files
-            if !path_str.ends_with(".tc") && !path_str.ends_with(".tcf") {
+            // Only handle .tc stub files (any name with transparent names)
+            if !self.transparent_names && !self.is_stub_name(path_str) {
                 return Err(Errno::from(libc::ENOENT));
             }
 
@@ -514,19 +851,42 @@ mod inner {
                 .get_index_entry(path_str)
                 .await
                 .ok_or(Errno::from(libc::ENOENT))?;
+            if entry.is_symlink() {
+                return Err(Errno::from(libc::ELOOP));
+            }
 
-            let manifest_path = entry.manifest_path(&self.prefix);
-            let prefix = self.prefix.trim_end_matches('/');
+            let manifest_path = self.layout.manifest_key(&entry.manifest_hash);
+            let prefix = self.layout.prefix();
 
             debug!(path = %path_str, manifest = %manifest_path, "hydrating on open");
 
             // Fetch content (disk-cache backed)
-            let data = fetch_cached(&self.op, &manifest_path, prefix, &self.disk_cache)
+            let data = if entry.is_packed() {
+                let rel = path_str.trim_start_matches('/');
+                fetch_packed_cached(&self.op, prefix, rel, &entry, &self.disk_cache).await
+            } else {
+                fetch_cached(
+                    &self.op,
+                    &manifest_path,
+                    prefix,
+                    &self.disk_cache,
+                    self.master_key.as_ref(),
+                    // 0: not recorded (directory markers, older entries)
+                    (entry.chunks > 0).then_some(entry.chunks),
+                )
                 .await
-                .map_err(|e| {
-                    warn!(path = %path_str, "hydration failed: {e}");
-                    Errno::from(libc::EIO)
-                })?;
+            }
+            .map_err(|e| {
+                if e.downcast_ref::<KeyRequired>().is_some() {
+                    warn!(
+                        path = %path_str,
+                        "file is encrypted and no master key is loaded; run `tcfs auth unlock` and remount"
+                    );
+                    return Errno::from(libc::EACCES);
+                }
+                warn!(path = %path_str, "hydration failed: {e}");
+                Errno::from(libc::EIO)
+            })?;
 
             // Store in handle table
             let fh = self.next_fh.fetch_add(1, Ordering::Relaxed);
@@ -604,8 +964,24 @@ mod inner {
         pub cache_dir: std::path::PathBuf,
         pub cache_max_bytes: u64,
         pub negative_ttl_secs: u64,
+        /// Kernel attribute cache TTL (`fuse.attr_ttl_secs`)
+        pub attr_ttl_secs: u64,
+        /// Kernel dentry cache TTL (`fuse.entry_ttl_secs`)
+        pub entry_ttl_secs: u64,
+        /// Real filenames instead of `.tc` stubs (`fuse.transparent_names`)
+        pub transparent_names: bool,
+        /// Index keys hold encrypted names (`fuse.encrypted_names`)
+        pub encrypted_names: bool,
         pub read_only: bool,
+        /// Let other users access the mount (`fuse.allow_other`)
         pub allow_other: bool,
+        /// Owner reported for entries; `None` is the mounting process's
+        /// (`fuse.uid` / `fuse.gid`)
+        pub uid: Option<u32>,
+        pub gid: Option<u32>,
+        pub mode_umask: u32,
+        /// Master key for encrypted files; without it they fail with `EACCES`
+        pub master_key: Option<MasterKey>,
     }
 
     /// Mount the FUSE filesystem and block until unmounted.
@@ -619,7 +995,16 @@ mod inner {
             cfg.cache_dir,
             cfg.cache_max_bytes,
             Duration::from_secs(cfg.negative_ttl_secs),
-        );
+            cfg.mode_umask,
+            cfg.master_key,
+        )
+        .with_ttls(
+            Duration::from_secs(cfg.attr_ttl_secs),
+            Duration::from_secs(cfg.entry_ttl_secs),
+        )
+        .with_transparent_names(cfg.transparent_names)
+        .with_encrypted_names(cfg.encrypted_names)
+        .with_owner(cfg.uid, cfg.gid);
 
         let mut opts = MountOptions::default();
         opts.fs_name("tcfs");
@@ -640,4 +1025,74 @@ mod inner {
 }
 
 #[cfg(feature = "fuse")]
-pub use inner::{mount, MountConfig, TcfsFs};
+pub use inner::{mount, MountConfig, TcfsFs, DEFAULT_ATTR_TTL};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(listing: &[DirListing]) -> Vec<&str> {
+        listing.iter().map(|e| e.name.as_str()).collect()
+    }
+
+    #[test]
+    fn listing_is_sorted_and_stable() {
+        let prefix = "data/index/";
+        let keys = [
+            "data/index/zeta.txt",
+            "data/index/src/main.rs",
+            "data/index/README.md",
+            "data/index/src/lib.rs",
+            "data/index/alpha.txt",
+        ];
+
+        let first = list_dir_entries(prefix, keys.iter().copied());
+        let second = list_dir_entries(prefix, keys.iter().rev().copied());
+
+        assert_eq!(first, second);
+        assert_eq!(
+            names(&first),
+            vec!["README.md.tc", "alpha.txt.tc", "src", "zeta.txt.tc"]
+        );
+    }
+
+    #[test]
+    fn listing_collapses_subdirectories() {
+        let keys = ["p/index/a/1", "p/index/a/2", "p/index/a/b/3", "p/index/"];
+        let listing = list_dir_entries("p/index/", keys.iter().copied());
+
+        assert_eq!(listing.len(), 1);
+        assert!(listing[0].is_dir);
+        assert_eq!(listing[0].name, "a");
+        assert_eq!(listing[0].index_key, None);
+    }
+
+    #[test]
+    fn file_entries_keep_index_key() {
+        let listing = list_dir_entries("p/index/", ["p/index/notes.md"]);
+        assert_eq!(listing[0].index_key.as_deref(), Some("p/index/notes.md"));
+    }
+
+    #[test]
+    fn directory_markers_imply_dir_but_are_not_listed() {
+        let root = list_dir_entries("p/index/", ["p/index/logs/.tcfsdir"]);
+        assert_eq!(names(&root), vec!["logs"]);
+        assert!(root[0].is_dir);
+
+        let logs = list_dir_entries("p/index/logs/", ["p/index/logs/.tcfsdir"]);
+        assert!(logs.is_empty());
+    }
+
+    #[test]
+    fn encrypted_names_decrypt_or_mask() {
+        let key = [0x42u8; tcfs_crypto::KEY_SIZE];
+        let stored = tcfs_crypto::encrypt_name(&key, "notes.md").unwrap();
+
+        assert_eq!(shown_name(Some(&key), &stored), "notes.md");
+        let masked = shown_name(None, &stored);
+        assert_eq!(masked, format!("encrypted-{}", &stored[..16]));
+        assert_eq!(shown_name(Some(&[0x17; 32]), &stored), masked);
+        // A name that was never encrypted is masked too
+        assert_eq!(shown_name(Some(&key), "plain"), "encrypted-plain");
+    }
+}
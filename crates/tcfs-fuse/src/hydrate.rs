@@ -2,96 +2,236 @@
 //!
 //! Unlike `tcfs_sync::engine::download_file` (which writes to disk), this
 //! returns the assembled bytes in memory so the FUSE driver can cache and
-//! serve them without touching the local filesystem.
+//! serve them without touching the local filesystem. Packed files (see
+//! `tcfs_sync::pack`) are read from their pack range instead.
 
 use anyhow::{Context, Result};
 use opendal::Operator;
+use tcfs_core::index::IndexEntry;
+use tcfs_core::layout::RemoteLayout;
+use tcfs_crypto::MasterKey;
+use tcfs_sync::engine::{assemble_chunks, EncryptionContext, FileHashMismatch};
+use tcfs_sync::manifest::SyncManifest;
 use tracing::{debug, warn};
 
 use crate::cache::{cache_key_for_path, DiskCache};
 
+/// The index entry pointing at a manifest records a different number of
+/// chunks than the manifest lists, so one of them is stale or half-written.
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "index entry for {manifest} records {index_chunks} chunks but the manifest lists \
+     {manifest_chunks}; the index and manifest have drifted, re-push the file to repair it"
+)]
+pub struct ChunkCountMismatch {
+    pub manifest: String,
+    pub index_chunks: usize,
+    pub manifest_chunks: usize,
+}
+
 /// Fetch the fully-assembled content for a manifest path.
 ///
-/// Reads the manifest to get chunk hashes, fetches each chunk from
-/// `{prefix}/chunks/{hash}`, and returns the concatenated bytes.
+/// Reads the manifest (v1 text or v2 JSON), fetches each chunk from
+/// `{prefix}/chunks/{hash}`, and returns the verified plaintext. Chunks of
+/// an encrypted manifest are decrypted with `master_key`; without one the
+/// call fails with `tcfs_sync::engine::KeyRequired`.
 ///
 /// # Arguments
 /// - `op` — OpenDAL operator pointing at the SeaweedFS bucket
 /// - `manifest_path` — full path of the manifest object (e.g. `data/manifests/abc123`)
 /// - `remote_prefix` — prefix used to look up chunks (e.g. `data`)
+/// - `master_key` — key that wraps per-file keys of encrypted manifests
 pub async fn fetch_content(
     op: &Operator,
     manifest_path: &str,
     remote_prefix: &str,
+    master_key: Option<&MasterKey>,
 ) -> Result<Vec<u8>> {
+    let (_, data) =
+        fetch_manifest_content(op, manifest_path, remote_prefix, master_key, None).await?;
+    Ok(data)
+}
+
+async fn fetch_manifest_content(
+    op: &Operator,
+    manifest_path: &str,
+    remote_prefix: &str,
+    master_key: Option<&MasterKey>,
+    expected_chunks: Option<usize>,
+) -> Result<(SyncManifest, Vec<u8>)> {
     debug!(manifest = %manifest_path, "hydrating");
 
-    // Read manifest: newline-separated chunk hashes
     let manifest_bytes = op
         .read(manifest_path)
         .await
         .with_context(|| format!("reading manifest: {}", manifest_path))?;
+    let manifest = SyncManifest::from_bytes(&manifest_bytes.to_bytes())
+        .with_context(|| format!("parsing manifest: {}", manifest_path))?;
 
-    let manifest_str = String::from_utf8(manifest_bytes.to_bytes().to_vec())
-        .context("manifest is not valid UTF-8")?;
-
-    let chunk_hashes: Vec<&str> = manifest_str.lines().filter(|l| !l.is_empty()).collect();
-
-    if chunk_hashes.is_empty() {
+    if manifest.chunk_hashes().is_empty() {
         anyhow::bail!("empty manifest: {}", manifest_path);
     }
+    if let Some(index_chunks) = expected_chunks {
+        check_chunk_count(manifest_path, &manifest, index_chunks)?;
+    }
 
+    let encryption = master_key.map(|key| EncryptionContext {
+        master_key: key.clone(),
+        convergent: false,
+    });
     let prefix = remote_prefix.trim_end_matches('/');
-    let mut assembled = Vec::new();
-
-    for (i, hash) in chunk_hashes.iter().enumerate() {
-        let chunk_key = format!("{}/chunks/{}", prefix, hash);
-        let chunk = op.read(&chunk_key).await.with_context(|| {
-            format!(
-                "downloading chunk {}/{}: {}",
-                i + 1,
-                chunk_hashes.len(),
-                chunk_key
-            )
-        })?;
-        assembled.extend_from_slice(&chunk.to_bytes());
+    let assembled = assemble_chunks(
+        op,
+        &manifest,
+        manifest_path,
+        prefix,
+        encryption.as_ref(),
+        None,
+    )
+    .await?;
+
+    // v1 manifests carry no whole-file hash
+    if !manifest.is_legacy() {
+        let actual = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&assembled));
+        if actual != manifest.file_hash {
+            return Err(FileHashMismatch {
+                manifest: manifest_path.to_string(),
+                expected: manifest.file_hash.clone(),
+                actual,
+            }
+            .into());
+        }
     }
 
     debug!(
         manifest = %manifest_path,
         bytes = assembled.len(),
-        chunks = chunk_hashes.len(),
+        chunks = manifest.chunk_hashes().len(),
         "hydrated"
     );
 
-    Ok(assembled)
+    Ok((manifest, assembled))
+}
+
+fn check_chunk_count(
+    manifest_path: &str,
+    manifest: &SyncManifest,
+    index_chunks: usize,
+) -> Result<()> {
+    let manifest_chunks = manifest.chunk_hashes().len();
+    if index_chunks != manifest_chunks {
+        return Err(ChunkCountMismatch {
+            manifest: manifest_path.to_string(),
+            index_chunks,
+            manifest_chunks,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Cache key of the manifest cached beside the content under `key`.
+fn manifest_cache_key(key: &str) -> String {
+    format!("{key}.manifest")
 }
 
 /// Fetch content using the disk cache as a read-through layer.
 ///
 /// Returns cached bytes if present; otherwise fetches from SeaweedFS and
-/// stores in the cache before returning.
+/// stores in the cache before returning. Decrypted content of encrypted
+/// manifests is never written to the cache.
+///
+/// `expected_chunks` is the chunk count recorded in the index entry that
+/// led here; when the manifest lists a different number the call fails with
+/// [`ChunkCountMismatch`] instead of serving it. The manifest is cached
+/// beside the content so a cache hit is checked too; a hit without it is
+/// treated as a miss.
 pub async fn fetch_cached(
     op: &Operator,
     manifest_path: &str,
     remote_prefix: &str,
     cache: &DiskCache,
+    master_key: Option<&MasterKey>,
+    expected_chunks: Option<usize>,
 ) -> Result<Vec<u8>> {
     let key = cache_key_for_path(manifest_path);
 
     // Cache hit
     if let Some(data) = cache.get(&key).await {
-        debug!(manifest = %manifest_path, "hydration cache hit");
-        return Ok(data);
+        let checked = match expected_chunks {
+            None => true,
+            Some(index_chunks) => match cache.get(&manifest_cache_key(&key)).await {
+                Some(bytes) => match SyncManifest::from_bytes(&bytes) {
+                    Ok(manifest) => {
+                        check_chunk_count(manifest_path, &manifest, index_chunks)?;
+                        true
+                    }
+                    Err(_) => false,
+                },
+                None => false,
+            },
+        };
+        if checked {
+            debug!(manifest = %manifest_path, "hydration cache hit");
+            return Ok(data);
+        }
     }
 
     // Cache miss — fetch from storage
-    let data = fetch_content(op, manifest_path, remote_prefix).await?;
+    let (manifest, data) = fetch_manifest_content(
+        op,
+        manifest_path,
+        remote_prefix,
+        master_key,
+        expected_chunks,
+    )
+    .await?;
 
     // Write to cache (best-effort; failure is non-fatal)
-    if let Err(e) = cache.put(&key, &data).await {
-        warn!(manifest = %manifest_path, "failed to cache hydrated content: {e}");
+    if manifest.encrypted_file_key.is_none() && !manifest.is_convergent() {
+        // The manifest goes in first, so cached content has it beside it
+        let cached = async {
+            cache
+                .put(&manifest_cache_key(&key), &manifest.to_bytes()?)
+                .await?;
+            cache.put(&key, &data).await
+        }
+        .await;
+        if let Err(e) = cached {
+            warn!(manifest = %manifest_path, "failed to cache hydrated content: {e}");
+        }
     }
 
     Ok(data)
 }
+
+/// Read a packed file's content from its pack range, with disk cache.
+///
+/// The cache key is the manifest key the content hash would have, so the
+/// same content is shared with an unpacked copy of it.
+pub async fn fetch_packed_cached(
+    op: &Operator,
+    remote_prefix: &str,
+    rel_path: &str,
+    entry: &IndexEntry,
+    cache: &DiskCache,
+) -> Result<Vec<u8>> {
+    let pack = entry
+        .pack
+        .as_ref()
+        .with_context(|| format!("{rel_path} is not packed"))?;
+    let key =
+        cache_key_for_path(&RemoteLayout::new(remote_prefix).manifest_key(&entry.manifest_hash));
+    if let Some(data) = cache.get(&key).await {
+        debug!(path = %rel_path, "hydration cache hit");
+        return Ok(data);
+    }
+
+    let data =
+        tcfs_sync::pack::read_packed(op, remote_prefix, rel_path, &entry.manifest_hash, pack)
+            .await?;
+    if let Err(e) = cache.put(&key, &data).await {
+        warn!(path = %rel_path, "failed to cache hydrated content: {e}");
+    }
+    Ok(data)
+}
@@ -8,6 +8,7 @@ pub mod cache;
 pub mod driver;
 pub mod erofs;
 pub mod hydrate;
+pub mod mounts;
 pub mod negative_cache;
 pub mod stub;
 
@@ -16,5 +17,8 @@ pub mod stub;
 pub use driver::{mount, MountConfig};
 
 pub use cache::DiskCache;
+pub use mounts::{ensure_outside_mounts, tcfs_mountpoints, InsideMount};
 pub use negative_cache::NegativeCache;
-pub use stub::{is_stub_path, real_to_stub_name, stub_to_real_name, IndexEntry, StubMeta};
+pub use stub::{
+    is_stub_path, real_to_stub_name, stub_to_real_name, unsync_file, IndexEntry, StubMeta,
+};
@@ -0,0 +1,119 @@
+//! Active mount detection for stub operations
+//!
+//! Unsyncing or hydrating a path that lives inside a tcfs FUSE mount writes
+//! back through the mount itself, which can deadlock the driver or shadow
+//! the virtual file with a real one. Callers check the target against the
+//! known mountpoints first and refuse with `InsideMount`.
+
+use std::path::{Path, PathBuf};
+
+/// The target path is inside an active tcfs mount.
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "{} is inside the tcfs mount at {}; run this on a path outside the mount",
+    path.display(),
+    mountpoint.display()
+)]
+pub struct InsideMount {
+    pub path: PathBuf,
+    pub mountpoint: PathBuf,
+}
+
+/// Mountpoints of tcfs FUSE mounts listed in `/proc/mounts` format.
+///
+/// Matches entries whose source is `tcfs` (the driver's `fs_name`) and
+/// whose type is `fuse` or `fuse.*`. Octal escapes (`\040` for a space)
+/// in the mountpoint are decoded.
+pub fn parse_proc_mounts(content: &str) -> Vec<PathBuf> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let source = fields.next()?;
+            let target = fields.next()?;
+            let fstype = fields.next()?;
+            let is_fuse = fstype == "fuse" || fstype.starts_with("fuse.");
+            (source == "tcfs" && is_fuse).then(|| PathBuf::from(unescape_mount_field(target)))
+        })
+        .collect()
+}
+
+/// Mountpoints of tcfs FUSE mounts on this machine (Linux only; empty elsewhere).
+pub fn tcfs_mountpoints() -> Vec<PathBuf> {
+    std::fs::read_to_string("/proc/mounts")
+        .map(|content| parse_proc_mounts(&content))
+        .unwrap_or_default()
+}
+
+/// The mountpoint in `mountpoints` that contains `path`, if any.
+///
+/// `path` is canonicalized when it exists, so relative paths and symlinks
+/// into a mount are caught.
+pub fn mount_containing<'a>(path: &Path, mountpoints: &'a [PathBuf]) -> Option<&'a Path> {
+    let resolved = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    mountpoints
+        .iter()
+        .find(|mp| resolved.starts_with(mp) || path.starts_with(mp))
+        .map(PathBuf::as_path)
+}
+
+/// Fail with `InsideMount` if `path` is under any of `mountpoints`.
+pub fn ensure_outside_mounts(path: &Path, mountpoints: &[PathBuf]) -> Result<(), InsideMount> {
+    match mount_containing(path, mountpoints) {
+        Some(mountpoint) => Err(InsideMount {
+            path: path.to_path_buf(),
+            mountpoint: mountpoint.to_path_buf(),
+        }),
+        None => Ok(()),
+    }
+}
+
+fn unescape_mount_field(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 4 <= bytes.len() {
+            let digits = std::str::from_utf8(&bytes[i + 1..i + 4]).unwrap_or("");
+            if let Ok(code) = u8::from_str_radix(digits, 8) {
+                out.push(code);
+                i += 4;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROC_MOUNTS: &str = "\
+proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0
+tcfs /home/u/tcfs fuse rw,nosuid,nodev,relatime,user_id=1000,group_id=1000 0 0
+tcfs /mnt/my\\040data fuse.tcfs ro,nosuid,nodev 0 0
+sshfs /mnt/remote fuse.sshfs rw 0 0
+";
+
+    #[test]
+    fn parses_tcfs_fuse_entries_only() {
+        assert_eq!(
+            parse_proc_mounts(PROC_MOUNTS),
+            vec![PathBuf::from("/home/u/tcfs"), PathBuf::from("/mnt/my data")]
+        );
+    }
+
+    #[test]
+    fn paths_under_a_mount_are_rejected() {
+        let mounts = parse_proc_mounts(PROC_MOUNTS);
+        let err = ensure_outside_mounts(Path::new("/home/u/tcfs/docs/a.txt"), &mounts).unwrap_err();
+        assert_eq!(err.mountpoint, PathBuf::from("/home/u/tcfs"));
+        assert!(err.to_string().contains("outside the mount"));
+
+        assert!(ensure_outside_mounts(Path::new("/home/u/tcfs-other/a.txt"), &mounts).is_ok());
+        assert!(ensure_outside_mounts(Path::new("/mnt/remote/a.txt"), &mounts).is_ok());
+    }
+}
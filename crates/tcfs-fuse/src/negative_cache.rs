@@ -9,32 +9,43 @@ use std::collections::HashMap;
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
 
+use tcfs_core::clock::SharedClock;
+
 /// Thread-safe negative dentry cache with TTL-based expiry.
 pub struct NegativeCache {
     entries: Mutex<HashMap<String, Instant>>,
     ttl: Duration,
+    clock: SharedClock,
 }
 
 impl NegativeCache {
     /// Create a new negative cache with the given TTL.
     pub fn new(ttl: Duration) -> Self {
+        Self::with_clock(ttl, tcfs_core::clock::system())
+    }
+
+    /// Create a negative cache that measures its TTL on `clock`.
+    pub fn with_clock(ttl: Duration, clock: SharedClock) -> Self {
         NegativeCache {
             entries: Mutex::new(HashMap::new()),
             ttl,
+            clock,
         }
     }
 
     /// Record that `path` does not exist (ENOENT).
     pub fn insert(&self, path: &str) {
+        let now = self.clock.instant();
         let mut map = self.entries.lock().unwrap();
-        map.insert(path.to_string(), Instant::now());
+        map.insert(path.to_string(), now);
     }
 
     /// Returns true if `path` is known to be absent and the TTL has not expired.
     pub fn is_negative(&self, path: &str) -> bool {
+        let now = self.clock.instant();
         let map = self.entries.lock().unwrap();
         match map.get(path) {
-            Some(&inserted_at) => inserted_at.elapsed() < self.ttl,
+            Some(&inserted_at) => now.saturating_duration_since(inserted_at) < self.ttl,
             None => false,
         }
     }
@@ -47,8 +58,9 @@ impl NegativeCache {
 
     /// Evict all entries whose TTL has expired. Call periodically to avoid unbounded growth.
     pub fn evict_expired(&self) {
+        let now = self.clock.instant();
         let mut map = self.entries.lock().unwrap();
-        map.retain(|_, inserted_at| inserted_at.elapsed() < self.ttl);
+        map.retain(|_, inserted_at| now.saturating_duration_since(*inserted_at) < self.ttl);
     }
 }
 
@@ -81,4 +93,27 @@ mod tests {
         thread::sleep(Duration::from_millis(80));
         assert!(!cache.is_negative("/tmp/test"));
     }
+
+    #[test]
+    fn ttl_boundary_is_exact_with_mock_clock() {
+        let clock = std::sync::Arc::new(tcfs_core::clock::MockClock::new(1_700_000_000));
+        let cache = NegativeCache::with_clock(Duration::from_secs(30), clock.clone());
+        cache.insert("/repo/.git/index.lock");
+
+        clock.advance(Duration::from_secs(29) + Duration::from_millis(999));
+        assert!(cache.is_negative("/repo/.git/index.lock"));
+        cache.evict_expired();
+        assert!(cache.is_negative("/repo/.git/index.lock"));
+
+        // Expired exactly at the TTL
+        clock.advance(Duration::from_millis(1));
+        assert!(!cache.is_negative("/repo/.git/index.lock"));
+        cache.evict_expired();
+        assert!(cache.entries.lock().unwrap().is_empty());
+
+        // A wall clock step does not expire entries early
+        cache.insert("/repo/missing");
+        clock.set_unix(1_900_000_000);
+        assert!(cache.is_negative("/repo/missing"));
+    }
 }
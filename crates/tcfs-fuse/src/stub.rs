@@ -160,6 +160,28 @@ impl StubMeta {
             size,
         }
     }
+
+    /// Build a stub for a tracked file from its state cache entry: the size
+    /// and chunk count it was pushed with, and the manifest hash named by
+    /// its `remote_path` (the content hash for entries without one).
+    ///
+    /// `compressed` stays false: compression is recorded per chunk in the
+    /// manifest, which hydration reads anyway.
+    pub fn for_synced(entry: &tcfs_sync::state::SyncState, bucket: &str) -> Self {
+        let manifest_hash = entry
+            .remote_path
+            .rsplit_once("/manifests/")
+            .map(|(_, hash)| hash)
+            .filter(|hash| !hash.is_empty() && !hash.contains('/'))
+            .unwrap_or(&entry.blake3);
+        Self::for_upload(
+            manifest_hash,
+            entry.size,
+            entry.chunk_count,
+            bucket,
+            &entry.remote_path,
+        )
+    }
 }
 
 /// Returns true if the path ends with `.tc` or `.tcf`.
@@ -188,53 +210,42 @@ pub fn real_to_stub_name(name: &OsStr) -> PathBuf {
     PathBuf::from(s)
 }
 
-// ── Index entry format ────────────────────────────────────────────────────────
-
-/// Metadata stored in an index entry at `{prefix}/index/{rel_path}`.
+/// Replace a hydrated file with its `.tc` stub, returning the stub path.
 ///
-/// This is the lightweight record the FUSE driver uses for `getattr` and
-/// `readdir` without fetching the full manifest.
-#[derive(Debug, Clone)]
-pub struct IndexEntry {
-    pub manifest_hash: String,
-    pub size: u64,
-    pub chunks: usize,
+/// Refuses paths that are already stubs (`.tc` or `.tcf`), directories
+/// (directory stubs are not produced by unsync), and files whose stub
+/// already exists, so a file is never stubbed twice. The stub is written
+/// before the original is removed.
+pub async fn unsync_file(path: &Path, stub: &StubMeta) -> Result<PathBuf> {
+    anyhow::ensure!(!is_stub_path(path), "{} is already a stub", path.display());
+    let meta = tokio::fs::metadata(path)
+        .await
+        .with_context(|| format!("stat: {}", path.display()))?;
+    anyhow::ensure!(meta.is_file(), "{} is not a regular file", path.display());
+
+    let stub_name = real_to_stub_name(path.file_name().context("path has no filename")?);
+    let stub_path = path.parent().unwrap_or(Path::new(".")).join(stub_name);
+    anyhow::ensure!(
+        !stub_path.exists(),
+        "stub already exists: {}",
+        stub_path.display()
+    );
+
+    tokio::fs::write(&stub_path, stub.to_bytes())
+        .await
+        .with_context(|| format!("writing stub: {}", stub_path.display()))?;
+    tokio::fs::remove_file(path)
+        .await
+        .with_context(|| format!("removing hydrated file: {}", path.display()))?;
+
+    Ok(stub_path)
 }
 
-impl IndexEntry {
-    /// Parse an index entry from its text content.
-    pub fn parse(content: &str) -> Result<Self> {
-        let mut manifest_hash = None;
-        let mut size = None;
-        let mut chunks = None;
-
-        for line in content.lines() {
-            if let Some((k, v)) = line.split_once('=') {
-                match k {
-                    "manifest_hash" => manifest_hash = Some(v.to_string()),
-                    "size" => size = Some(v.parse::<u64>().context("invalid size")?),
-                    "chunks" => chunks = Some(v.parse::<usize>().context("invalid chunks")?),
-                    _ => {}
-                }
-            }
-        }
-
-        Ok(IndexEntry {
-            manifest_hash: manifest_hash.context("missing manifest_hash")?,
-            size: size.context("missing size")?,
-            chunks: chunks.unwrap_or(0),
-        })
-    }
+// ── Index entry format ────────────────────────────────────────────────────────
 
-    /// Manifest path under `{prefix}/manifests/`.
-    pub fn manifest_path(&self, prefix: &str) -> String {
-        format!(
-            "{}/manifests/{}",
-            prefix.trim_end_matches('/'),
-            self.manifest_hash
-        )
-    }
-}
+/// Index entries are shared with the sync engine and the platform providers;
+/// the canonical definition lives in `tcfs_core::index`.
+pub use tcfs_core::index::IndexEntry;
 
 // ── Tests ─────────────────────────────────────────────────────────────────────
 
@@ -0,0 +1,78 @@
+//! Integration test: configurable kernel cache TTLs
+//!
+//! Builds the FUSE driver with non-default attribute and dentry TTLs and
+//! checks that the replies the kernel caches carry them.
+
+#![cfg(feature = "fuse")]
+
+use std::ffi::OsStr;
+use std::time::Duration;
+
+use fuse3::path::prelude::*;
+use opendal::Operator;
+use tcfs_fuse::driver::{TcfsFs, DEFAULT_ATTR_TTL};
+use tempfile::TempDir;
+
+fn request() -> Request {
+    Request {
+        unique: 1,
+        uid: 0,
+        gid: 0,
+        pid: 0,
+    }
+}
+
+fn driver(tmp: &TempDir, op: Operator, prefix: &str) -> TcfsFs {
+    TcfsFs::new(
+        op,
+        prefix.to_string(),
+        tmp.path().join("cache"),
+        1024 * 1024,
+        Duration::from_secs(1),
+        0o022,
+        None,
+    )
+}
+
+#[tokio::test]
+async fn replies_carry_configured_ttls() {
+    let tmp = TempDir::new().unwrap();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish();
+    let prefix = "test/ttl";
+
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::write(src.join("a.txt"), b"hello").unwrap();
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    tcfs_sync::engine::push_tree(&op, &src, prefix, &state, None)
+        .await
+        .expect("push_tree");
+
+    let default = driver(&tmp, op.clone(), prefix);
+    let root = default
+        .getattr(request(), Some(OsStr::new("/")), None, 0)
+        .await
+        .expect("getattr root");
+    assert_eq!(root.ttl, DEFAULT_ATTR_TTL);
+
+    let fs = driver(&tmp, op, prefix).with_ttls(Duration::from_secs(42), Duration::from_secs(7));
+    let root = fs
+        .getattr(request(), Some(OsStr::new("/")), None, 0)
+        .await
+        .expect("getattr root");
+    assert_eq!(root.ttl, Duration::from_secs(42));
+
+    let file = fs
+        .getattr(request(), Some(OsStr::new("/a.txt.tc")), None, 0)
+        .await
+        .expect("getattr file");
+    assert_eq!(file.ttl, Duration::from_secs(42));
+
+    let entry = fs
+        .lookup(request(), OsStr::new("/"), OsStr::new("a.txt.tc"))
+        .await
+        .expect("lookup");
+    assert_eq!(entry.ttl, Duration::from_secs(7));
+}
@@ -0,0 +1,139 @@
+//! Integration test: index/manifest chunk count cross-check on hydrate
+//!
+//! Pushes a file, then rewrites its index entry with the wrong `chunks=`
+//! and checks that hydration refuses it with a descriptive error (and that
+//! FUSE `open` fails) instead of serving whatever the manifest lists, even
+//! once the content is cached.
+
+use opendal::Operator;
+use tcfs_core::index::IndexEntry;
+use tcfs_core::layout::RemoteLayout;
+use tcfs_fuse::hydrate::{fetch_cached, ChunkCountMismatch};
+use tcfs_fuse::DiskCache;
+use tempfile::TempDir;
+
+const PREFIX: &str = "test/chunk-count";
+
+/// Push `notes.txt` and give its index entry one chunk too many.
+async fn push_with_drifted_index(tmp: &TempDir) -> (Operator, IndexEntry) {
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish();
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::write(src.join("notes.txt"), b"drifting notes\n".repeat(100)).unwrap();
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    tcfs_sync::engine::push_tree(&op, &src, PREFIX, &state, None)
+        .await
+        .expect("push_tree");
+
+    let key = RemoteLayout::new(PREFIX).index_key("notes.txt");
+    let mut entry = IndexEntry::from_bytes(&op.read(&key).await.unwrap().to_bytes()).unwrap();
+    entry.chunks += 1;
+    op.write(&key, entry.to_bytes()).await.unwrap();
+    (op, entry)
+}
+
+#[tokio::test]
+async fn hydrate_refuses_index_with_wrong_chunk_count() {
+    let tmp = TempDir::new().unwrap();
+    let (op, entry) = push_with_drifted_index(&tmp).await;
+    let layout = RemoteLayout::new(PREFIX);
+    let manifest_path = layout.manifest_key(&entry.manifest_hash);
+    let cache = DiskCache::new(tmp.path().join("cache"), 1024 * 1024);
+
+    let err = fetch_cached(
+        &op,
+        &manifest_path,
+        layout.prefix(),
+        &cache,
+        None,
+        Some(entry.chunks),
+    )
+    .await
+    .expect_err("drifted index must not hydrate");
+    let mismatch = err
+        .downcast_ref::<ChunkCountMismatch>()
+        .expect("typed error");
+    assert_eq!(mismatch.index_chunks, entry.chunks);
+    assert_eq!(mismatch.manifest_chunks, entry.chunks - 1);
+    let message = err.to_string();
+    assert!(message.contains(&manifest_path), "{message}");
+    assert!(message.contains("drifted"), "{message}");
+
+    // The matching count hydrates
+    let data = fetch_cached(
+        &op,
+        &manifest_path,
+        layout.prefix(),
+        &cache,
+        None,
+        Some(entry.chunks - 1),
+    )
+    .await
+    .unwrap();
+    assert_eq!(data, b"drifting notes\n".repeat(100));
+}
+
+#[tokio::test]
+async fn cached_content_is_still_checked_against_the_index() {
+    let tmp = TempDir::new().unwrap();
+    let (op, entry) = push_with_drifted_index(&tmp).await;
+    let layout = RemoteLayout::new(PREFIX);
+    let manifest_path = layout.manifest_key(&entry.manifest_hash);
+    let cache = DiskCache::new(tmp.path().join("cache"), 1024 * 1024);
+
+    // A hydrate with the manifest's real count fills the cache
+    fetch_cached(
+        &op,
+        &manifest_path,
+        layout.prefix(),
+        &cache,
+        None,
+        Some(entry.chunks - 1),
+    )
+    .await
+    .unwrap();
+
+    let err = fetch_cached(
+        &op,
+        &manifest_path,
+        layout.prefix(),
+        &cache,
+        None,
+        Some(entry.chunks),
+    )
+    .await
+    .expect_err("drifted index must not hydrate from the cache");
+    assert!(err.downcast_ref::<ChunkCountMismatch>().is_some(), "{err}");
+}
+
+#[cfg(feature = "fuse")]
+#[tokio::test]
+async fn open_fails_on_drifted_index() {
+    use fuse3::path::prelude::*;
+    use std::ffi::OsStr;
+
+    let tmp = TempDir::new().unwrap();
+    let (op, _) = push_with_drifted_index(&tmp).await;
+    let fs = tcfs_fuse::driver::TcfsFs::new(
+        op,
+        PREFIX.to_string(),
+        tmp.path().join("cache"),
+        1024 * 1024,
+        std::time::Duration::from_secs(1),
+        0o022,
+        None,
+    );
+    let request = Request {
+        unique: 1,
+        uid: 0,
+        gid: 0,
+        pid: 0,
+    };
+    let err = fs
+        .open(request, OsStr::new("/notes.txt.tc"), libc::O_RDONLY as u32)
+        .await
+        .expect_err("open must fail");
+    assert_eq!(err, fuse3::Errno::from(libc::EIO));
+}
@@ -0,0 +1,95 @@
+//! Integration test: empty directories and tombstones through the FUSE driver
+//!
+//! Pushes a tree with an empty directory to the in-memory backend, adds a
+//! tombstone for a deleted file, and drives the `PathFilesystem` callbacks
+//! directly to check that the directory is shown, its marker is not, and the
+//! deleted file is hidden.
+
+#![cfg(feature = "fuse")]
+
+use std::ffi::OsStr;
+use std::time::Duration;
+
+use fuse3::path::prelude::*;
+use futures_util::StreamExt;
+use opendal::Operator;
+use tcfs_fuse::driver::TcfsFs;
+use tempfile::TempDir;
+
+fn request() -> Request {
+    Request {
+        unique: 1,
+        uid: 0,
+        gid: 0,
+        pid: 0,
+    }
+}
+
+async fn list(fs: &TcfsFs, path: &str) -> Vec<(String, FileType)> {
+    let reply = fs
+        .readdir(request(), OsStr::new(path), 0, 0)
+        .await
+        .expect("readdir");
+    reply
+        .entries
+        .map(|e| {
+            let e = e.unwrap();
+            (e.name.to_string_lossy().into_owned(), e.kind)
+        })
+        .filter(|(name, _)| std::future::ready(name != "." && name != ".."))
+        .collect()
+        .await
+}
+
+#[tokio::test]
+async fn empty_dir_and_tombstone_listing() {
+    let tmp = TempDir::new().unwrap();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish();
+    let prefix = "test/dirs";
+
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(src.join("logs")).unwrap();
+    std::fs::write(src.join("keep.txt"), b"kept").unwrap();
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    tcfs_sync::engine::push_tree(&op, &src, prefix, &state, None)
+        .await
+        .expect("push_tree");
+
+    let tombstone = tcfs_core::index::IndexEntry::new_tombstone(None);
+    op.write(&format!("{prefix}/index/gone.txt"), tombstone.to_bytes())
+        .await
+        .unwrap();
+
+    let fs = TcfsFs::new(
+        op,
+        prefix.to_string(),
+        tmp.path().join("cache"),
+        1024 * 1024,
+        Duration::from_secs(1),
+        0o022,
+        None,
+    );
+
+    assert_eq!(
+        list(&fs, "/").await,
+        vec![
+            ("keep.txt.tc".to_string(), FileType::RegularFile),
+            ("logs".to_string(), FileType::Directory),
+        ]
+    );
+    assert!(list(&fs, "/logs").await.is_empty());
+
+    let logs = fs
+        .getattr(request(), Some(OsStr::new("/logs")), None, 0)
+        .await
+        .expect("getattr logs");
+    assert_eq!(logs.attr.kind, FileType::Directory);
+
+    assert!(fs
+        .lookup(request(), OsStr::new("/"), OsStr::new("gone.txt.tc"))
+        .await
+        .is_err());
+}
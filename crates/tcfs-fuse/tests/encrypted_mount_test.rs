@@ -0,0 +1,95 @@
+//! Integration test: encrypted files read through the FUSE driver
+//!
+//! Pushes a tree with encryption enabled, then drives the `PathFilesystem`
+//! callbacks directly: with the master key the stub opens to plaintext, and
+//! without it `open` fails with `EACCES` instead of serving ciphertext.
+
+#![cfg(feature = "fuse")]
+
+use std::ffi::OsStr;
+use std::path::Path;
+use std::time::Duration;
+
+use fuse3::path::prelude::*;
+use fuse3::Errno;
+use opendal::Operator;
+use tcfs_crypto::MasterKey;
+use tcfs_fuse::driver::TcfsFs;
+use tempfile::TempDir;
+
+fn request() -> Request {
+    Request {
+        unique: 1,
+        uid: 0,
+        gid: 0,
+        pid: 0,
+    }
+}
+
+fn driver(op: &Operator, prefix: &str, cache: &Path, key: Option<MasterKey>) -> TcfsFs {
+    TcfsFs::new(
+        op.clone(),
+        prefix.to_string(),
+        cache.to_path_buf(),
+        1024 * 1024,
+        Duration::from_secs(1),
+        0o022,
+        key,
+    )
+}
+
+#[tokio::test]
+async fn encrypted_tree_reads_with_key_and_refuses_without() {
+    let tmp = TempDir::new().unwrap();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish();
+    let prefix = "test/encrypted-mount";
+    let key = MasterKey::from_bytes([7u8; 32]);
+    let plaintext = b"quarterly numbers nobody else should read\n".repeat(200);
+
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(src.join("docs")).unwrap();
+    std::fs::write(src.join("docs/report.txt"), &plaintext).unwrap();
+
+    let ctx = tcfs_sync::engine::EncryptionContext {
+        master_key: key.clone(),
+        convergent: false,
+    };
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let (uploaded, _, _) = tcfs_sync::engine::push_tree_with_device(
+        &op,
+        &src,
+        prefix,
+        &state,
+        None,
+        "dev",
+        None,
+        Some(&ctx),
+        0,
+        None,
+    )
+    .await
+    .expect("encrypted push");
+    assert_eq!(uploaded, 1);
+
+    // With the key: plaintext, and nothing decrypted lands in the disk cache
+    let cache = tmp.path().join("cache");
+    let fs = driver(&op, prefix, &cache, Some(key));
+    let path = OsStr::new("/docs/report.txt.tc");
+    let opened = fs.open(request(), path, 0).await.expect("open with key");
+    let reply = fs
+        .read(request(), Some(path), opened.fh, 0, plaintext.len() as u32)
+        .await
+        .expect("read");
+    assert_eq!(&reply.data[..], &plaintext[..]);
+    let cached = std::fs::read_dir(&cache)
+        .map(|entries| entries.count())
+        .unwrap_or(0);
+    assert_eq!(cached, 0, "decrypted content must not be cached");
+
+    // Without a key: EACCES rather than ciphertext
+    let fs = driver(&op, prefix, &tmp.path().join("cache-locked"), None);
+    let err = fs.open(request(), path, 0).await.unwrap_err();
+    assert_eq!(err, Errno::from(libc::EACCES));
+}
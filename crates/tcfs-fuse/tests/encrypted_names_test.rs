@@ -0,0 +1,176 @@
+//! Integration test: a mount over encrypted names
+//!
+//! Pushes an encrypted tree, then rewrites its index so every path
+//! component is the `encrypt_name` ciphertext of the real name. With the
+//! master key and `encrypted_names`, the driver must list and open files
+//! under their plaintext names; without the key it shows stable masked
+//! names that still resolve.
+
+#![cfg(feature = "fuse")]
+
+use std::ffi::OsStr;
+use std::time::Duration;
+
+use fuse3::path::prelude::*;
+use futures_util::StreamExt;
+use opendal::Operator;
+use tcfs_crypto::MasterKey;
+use tcfs_fuse::driver::{TcfsFs, MASKED_NAME_PREFIX};
+use tempfile::TempDir;
+
+const PREFIX: &str = "test/encrypted-names";
+
+fn request() -> Request {
+    Request {
+        unique: 1,
+        uid: 0,
+        gid: 0,
+        pid: 0,
+    }
+}
+
+fn driver(op: &Operator, cache: &std::path::Path, key: Option<MasterKey>) -> TcfsFs {
+    TcfsFs::new(
+        op.clone(),
+        PREFIX.to_string(),
+        cache.to_path_buf(),
+        1024 * 1024,
+        Duration::from_secs(1),
+        0o022,
+        key,
+    )
+    .with_encrypted_names(true)
+}
+
+async fn list(fs: &TcfsFs, path: &str) -> Vec<(String, FileType)> {
+    let reply = fs
+        .readdir(request(), OsStr::new(path), 0, 0)
+        .await
+        .expect("readdir");
+    reply
+        .entries
+        .map(|e| {
+            let e = e.unwrap();
+            (e.name.to_string_lossy().into_owned(), e.kind)
+        })
+        .filter(|(name, _)| std::future::ready(name != "." && name != ".."))
+        .collect()
+        .await
+}
+
+/// Move every index entry to a key whose components are encrypted names.
+async fn encrypt_index_names(op: &Operator, key: &MasterKey) {
+    let name_key = tcfs_crypto::derive_name_key(key).unwrap();
+    let index = format!("{PREFIX}/index/");
+    let entries = op.list_with(&index).recursive(true).await.unwrap();
+    for entry in entries.iter().filter(|e| !e.metadata().is_dir()) {
+        let rel = entry.path().strip_prefix(&index).unwrap();
+        let encrypted: Vec<String> = rel
+            .split('/')
+            .map(|c| {
+                if c == tcfs_core::index::DIR_MARKER {
+                    c.to_string()
+                } else {
+                    tcfs_crypto::encrypt_name(&name_key, c).unwrap()
+                }
+            })
+            .collect();
+        let data = op.read(entry.path()).await.unwrap();
+        op.write(&format!("{index}{}", encrypted.join("/")), data)
+            .await
+            .unwrap();
+        op.delete(entry.path()).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn key_shows_plaintext_names_and_masks_without_it() {
+    let tmp = TempDir::new().unwrap();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish();
+    let key = MasterKey::from_bytes([9u8; 32]);
+    let report = b"names and numbers\n".repeat(50);
+
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(src.join("docs")).unwrap();
+    std::fs::write(src.join("docs/report.txt"), &report).unwrap();
+    std::fs::write(src.join("todo.md"), b"- ship it\n").unwrap();
+    let ctx = tcfs_sync::engine::EncryptionContext {
+        master_key: key.clone(),
+        convergent: false,
+    };
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    tcfs_sync::engine::push_tree_with_device(
+        &op,
+        &src,
+        PREFIX,
+        &state,
+        None,
+        "dev",
+        None,
+        Some(&ctx),
+        0,
+        None,
+    )
+    .await
+    .expect("encrypted push");
+    encrypt_index_names(&op, &key).await;
+
+    // With the key: plaintext names, and they resolve and open
+    let fs = driver(&op, &tmp.path().join("cache"), Some(key));
+    assert_eq!(
+        list(&fs, "/").await,
+        vec![
+            ("docs".to_string(), FileType::Directory),
+            ("todo.md.tc".to_string(), FileType::RegularFile),
+        ]
+    );
+    assert_eq!(
+        list(&fs, "/docs").await,
+        vec![("report.txt.tc".to_string(), FileType::RegularFile)]
+    );
+    let entry = fs
+        .lookup(request(), OsStr::new("/docs"), OsStr::new("report.txt.tc"))
+        .await
+        .expect("lookup plaintext name");
+    assert_eq!(entry.attr.size, report.len() as u64);
+    let path = OsStr::new("/docs/report.txt.tc");
+    let opened = fs.open(request(), path, 0).await.expect("open");
+    let reply = fs
+        .read(request(), Some(path), opened.fh, 0, report.len() as u32)
+        .await
+        .expect("read");
+    assert_eq!(&reply.data[..], &report[..]);
+
+    // Without it: masked names, stable across listings, that still resolve
+    let fs = driver(&op, &tmp.path().join("cache-locked"), None);
+    let root = list(&fs, "/").await;
+    assert_eq!(root, list(&fs, "/").await);
+    assert_eq!(root.len(), 2);
+    assert!(
+        root.iter()
+            .all(|(name, _)| name.starts_with(MASKED_NAME_PREFIX)),
+        "{root:?}"
+    );
+    let (docs, _) = root
+        .iter()
+        .find(|(_, kind)| *kind == FileType::Directory)
+        .unwrap();
+    let inner = list(&fs, &format!("/{docs}")).await;
+    assert_eq!(inner.len(), 1, "{inner:?}");
+    let (masked_report, _) = &inner[0];
+    let entry = fs
+        .lookup(
+            request(),
+            OsStr::new(&format!("/{docs}")),
+            OsStr::new(masked_report),
+        )
+        .await
+        .expect("lookup masked name");
+    assert_eq!(entry.attr.size, report.len() as u64);
+    assert!(fs
+        .lookup(request(), OsStr::new("/"), OsStr::new("docs"))
+        .await
+        .is_err());
+}
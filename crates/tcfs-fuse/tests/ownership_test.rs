@@ -0,0 +1,77 @@
+//! Integration test: configured owner of mounted entries
+//!
+//! Builds the FUSE driver with `fuse.uid` / `fuse.gid` overrides and checks
+//! that the root, directories and files all report that owner, and that a
+//! driver without overrides reports the mounting process's ids.
+
+#![cfg(feature = "fuse")]
+
+use std::ffi::OsStr;
+use std::time::Duration;
+
+use fuse3::path::prelude::*;
+use opendal::Operator;
+use tcfs_fuse::driver::TcfsFs;
+use tempfile::TempDir;
+
+fn request() -> Request {
+    Request {
+        unique: 1,
+        uid: 0,
+        gid: 0,
+        pid: 0,
+    }
+}
+
+fn driver(op: &Operator, tmp: &TempDir) -> TcfsFs {
+    TcfsFs::new(
+        op.clone(),
+        "test/owner".to_string(),
+        tmp.path().join("cache"),
+        1024 * 1024,
+        Duration::from_secs(1),
+        0o022,
+        None,
+    )
+}
+
+async fn owner(fs: &TcfsFs, path: &str) -> (u32, u32) {
+    let reply = fs
+        .getattr(request(), Some(OsStr::new(path)), None, 0)
+        .await
+        .expect("getattr");
+    (reply.attr.uid, reply.attr.gid)
+}
+
+#[tokio::test]
+async fn synthesized_attrs_use_configured_owner() {
+    let tmp = TempDir::new().unwrap();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish();
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(src.join("docs")).unwrap();
+    std::fs::write(src.join("docs/guide.md"), b"guide").unwrap();
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    tcfs_sync::engine::push_tree(&op, &src, "test/owner", &state, None)
+        .await
+        .expect("push_tree");
+
+    let fs = driver(&op, &tmp).with_owner(Some(4242), Some(4343));
+    assert_eq!(owner(&fs, "/").await, (4242, 4343));
+    assert_eq!(owner(&fs, "/docs").await, (4242, 4343));
+    assert_eq!(owner(&fs, "/docs/guide.md.tc").await, (4242, 4343));
+    let entry = fs
+        .lookup(request(), OsStr::new("/docs"), OsStr::new("guide.md.tc"))
+        .await
+        .expect("lookup");
+    assert_eq!((entry.attr.uid, entry.attr.gid), (4242, 4343));
+
+    // Overriding only the uid keeps the process gid
+    let process = unsafe { (libc::getuid(), libc::getgid()) };
+    let fs = driver(&op, &tmp).with_owner(Some(4242), None);
+    assert_eq!(owner(&fs, "/docs").await, (4242, process.1));
+
+    let fs = driver(&op, &tmp);
+    assert_eq!(owner(&fs, "/docs/guide.md.tc").await, process);
+}
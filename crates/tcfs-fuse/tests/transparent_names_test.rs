@@ -0,0 +1,128 @@
+//! Integration test: mounting with transparent names
+//!
+//! Pushes a small tree, builds the FUSE driver with `transparent_names`
+//! and checks that files are listed, looked up and opened under their real
+//! names (`README.md`), that directories are still told apart by listing,
+//! and that the `.tc` stub names no longer resolve.
+
+#![cfg(feature = "fuse")]
+
+use std::ffi::OsStr;
+use std::time::Duration;
+
+use fuse3::path::prelude::*;
+use fuse3::Errno;
+use futures_util::StreamExt;
+use opendal::Operator;
+use tcfs_fuse::driver::TcfsFs;
+use tempfile::TempDir;
+
+fn request() -> Request {
+    Request {
+        unique: 1,
+        uid: 0,
+        gid: 0,
+        pid: 0,
+    }
+}
+
+async fn list(fs: &TcfsFs, path: &str) -> Vec<(String, FileType)> {
+    let reply = fs
+        .readdir(request(), OsStr::new(path), 0, 0)
+        .await
+        .expect("readdir");
+    reply
+        .entries
+        .map(|e| {
+            let e = e.unwrap();
+            (e.name.to_string_lossy().into_owned(), e.kind)
+        })
+        .filter(|(name, _)| std::future::ready(name != "." && name != ".."))
+        .collect()
+        .await
+}
+
+#[tokio::test]
+async fn real_names_list_and_open() {
+    let tmp = TempDir::new().unwrap();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish();
+    let prefix = "test/transparent";
+
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(src.join("docs")).unwrap();
+    std::fs::create_dir_all(src.join("empty")).unwrap();
+    std::fs::write(src.join("README.md"), b"# tcfs\n").unwrap();
+    std::fs::write(src.join("docs/guide.md"), b"guide").unwrap();
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    tcfs_sync::engine::push_tree(&op, &src, prefix, &state, None)
+        .await
+        .expect("push_tree");
+
+    let fs = TcfsFs::new(
+        op,
+        prefix.to_string(),
+        tmp.path().join("cache"),
+        1024 * 1024,
+        Duration::from_secs(1),
+        0o022,
+        None,
+    )
+    .with_transparent_names(true);
+
+    assert_eq!(
+        list(&fs, "/").await,
+        vec![
+            ("README.md".to_string(), FileType::RegularFile),
+            ("docs".to_string(), FileType::Directory),
+            ("empty".to_string(), FileType::Directory),
+        ]
+    );
+    assert_eq!(
+        list(&fs, "/docs").await,
+        vec![("guide.md".to_string(), FileType::RegularFile)]
+    );
+
+    // Files and directories are told apart without a suffix
+    let readme = fs
+        .lookup(request(), OsStr::new("/"), OsStr::new("README.md"))
+        .await
+        .expect("lookup README.md");
+    assert_eq!(readme.attr.kind, FileType::RegularFile);
+    assert_eq!(readme.attr.size, 7);
+    let docs = fs
+        .getattr(request(), Some(OsStr::new("/docs")), None, 0)
+        .await
+        .expect("getattr docs");
+    assert_eq!(docs.attr.kind, FileType::Directory);
+
+    // Reading README.md directly hydrates it
+    let opened = fs
+        .open(request(), OsStr::new("/README.md"), libc::O_RDONLY as u32)
+        .await
+        .expect("open README.md");
+    let data = fs
+        .read(
+            request(),
+            Some(OsStr::new("/README.md")),
+            opened.fh,
+            0,
+            4096,
+        )
+        .await
+        .expect("read");
+    assert_eq!(&data.data[..], b"# tcfs\n");
+
+    // Stub names are not part of a transparent mount
+    let err = fs
+        .lookup(request(), OsStr::new("/"), OsStr::new("README.md.tc"))
+        .await
+        .expect_err("stub name");
+    assert_eq!(err, Errno::from(libc::ENOENT));
+    let err = fs
+        .open(request(), OsStr::new("/docs"), libc::O_RDONLY as u32)
+        .await
+        .expect_err("open a directory");
+    assert_eq!(err, Errno::from(libc::ENOENT));
+}
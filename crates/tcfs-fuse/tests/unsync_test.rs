@@ -0,0 +1,84 @@
+//! Integration test: unsync a tracked file back to a `.tc` stub
+//!
+//! Pushes a multi-chunk file through the sync engine (in-memory backend),
+//! builds the stub from the resulting state cache entry, and checks that
+//! the stub written by `unsync_file` parses back with the cached chunk
+//! count and the manifest hash as its oid.
+
+use opendal::Operator;
+use tcfs_fuse::StubMeta;
+use tempfile::TempDir;
+
+/// Deterministic pseudo-random bytes, so FastCDC produces several chunks.
+fn noisy_bytes(len: usize) -> Vec<u8> {
+    let mut x: u32 = 0x9e37_79b9;
+    (0..len)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x as u8
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn unsync_writes_stub_from_state_cache() {
+    let tmp = TempDir::new().unwrap();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish();
+
+    let src = tmp.path().join("data.bin");
+    std::fs::write(&src, noisy_bytes(512 * 1024)).unwrap();
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    tcfs_sync::engine::upload_file(&op, &src, "test/unsync", &state, None)
+        .await
+        .expect("upload");
+
+    let entry = state.get(&src).expect("state entry");
+    assert!(
+        entry.chunk_count > 1,
+        "test file should span several chunks"
+    );
+
+    let stub = StubMeta::for_synced(&entry, "tcfs");
+    let stub_path = tcfs_fuse::unsync_file(&src, &stub).await.expect("unsync");
+
+    assert_eq!(stub_path, tmp.path().join("data.bin.tc"));
+    assert!(!src.exists(), "hydrated file should be removed");
+
+    let written = StubMeta::parse(&std::fs::read_to_string(&stub_path).unwrap()).unwrap();
+    assert_eq!(written.chunks, entry.chunk_count);
+    assert_eq!(written.size, entry.size);
+    let manifest_hash = entry.remote_path.rsplit('/').next().unwrap();
+    assert_eq!(written.blake3_hex(), Some(manifest_hash));
+    assert_eq!(
+        written.origin,
+        format!("seaweedfs://tcfs/{}", entry.remote_path)
+    );
+    assert!(!written.fetched);
+    assert_eq!(written, stub);
+}
+
+#[tokio::test]
+async fn unsync_refuses_stubs_and_existing_stub() {
+    let tmp = TempDir::new().unwrap();
+    let stub = StubMeta::for_upload("abc", 1, 1, "tcfs", "x");
+
+    let dir_stub = tmp.path().join("photos.tcf");
+    std::fs::write(&dir_stub, stub.to_bytes()).unwrap();
+    assert!(tcfs_fuse::unsync_file(&dir_stub, &stub).await.is_err());
+    assert!(dir_stub.exists(), "refused stub must be left untouched");
+
+    let file = tmp.path().join("notes.txt");
+    std::fs::write(&file, b"hydrated").unwrap();
+    std::fs::write(tmp.path().join("notes.txt.tc"), stub.to_bytes()).unwrap();
+    assert!(tcfs_fuse::unsync_file(&file, &stub).await.is_err());
+    assert!(file.exists(), "file must not be removed when a stub exists");
+
+    let dir = tmp.path().join("subdir");
+    std::fs::create_dir(&dir).unwrap();
+    assert!(tcfs_fuse::unsync_file(&dir, &stub).await.is_err());
+}
@@ -10,11 +10,7 @@ use rmcp::{
     schemars, tool, tool_handler, tool_router, ServerHandler,
 };
 
-use tcfs_core::proto::{
-    tcfs_daemon_client::TcfsDaemonClient, Empty, PullRequest, ResolveConflictRequest,
-    StatusRequest, SyncStatusRequest,
-};
-use tonic::transport::Channel;
+use tcfs_client::{ClientError, TcfsClient};
 
 // ── Input schemas ────────────────────────────────────────────────────────
 
@@ -36,6 +32,8 @@ pub struct PullInput {
 pub struct PushInput {
     #[schemars(description = "Local file path to upload to remote storage")]
     pub local_path: String,
+    #[schemars(description = "Relative path to store the file as (default: its file name)")]
+    pub rel_path: Option<String>,
 }
 
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
@@ -46,13 +44,31 @@ pub struct ResolveConflictInput {
     pub resolution: String,
 }
 
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct ReadManifestInput {
+    #[schemars(description = "Remote prefix (default: the configured storage bucket)")]
+    pub prefix: Option<String>,
+    #[schemars(description = "BLAKE3 hash of the file content naming the manifest")]
+    pub file_hash: Option<String>,
+    #[schemars(description = "Relative path of the file, resolved through its index entry")]
+    pub rel_path: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct ChunkInfoInput {
+    #[schemars(description = "Remote prefix (default: the configured storage bucket)")]
+    pub prefix: Option<String>,
+    #[schemars(description = "BLAKE3 hash of the chunk")]
+    pub chunk_hash: String,
+}
+
 // ── MCP Server ───────────────────────────────────────────────────────────
 
 #[derive(Clone)]
 pub struct TcfsMcp {
     socket_path: PathBuf,
     config_path: Option<PathBuf>,
-    client: Arc<Mutex<Option<TcfsDaemonClient<Channel>>>>,
+    client: Arc<Mutex<Option<TcfsClient>>>,
     tool_router: ToolRouter<Self>,
 }
 
@@ -68,30 +84,15 @@ impl TcfsMcp {
     }
 
     /// Connect to the daemon, reusing existing connection if available
-    async fn connect(&self) -> Result<TcfsDaemonClient<Channel>, String> {
+    async fn connect(&self) -> Result<TcfsClient, String> {
         let mut guard = self.client.lock().await;
         if let Some(ref client) = *guard {
             return Ok(client.clone());
         }
 
-        let path = self.socket_path.clone();
-        let channel = tonic::transport::Endpoint::from_static("http://[::]:0")
-            .connect_with_connector(tower::service_fn(move |_: tonic::transport::Uri| {
-                let path = path.clone();
-                async move {
-                    let stream = tokio::net::UnixStream::connect(&path).await?;
-                    Ok::<_, std::io::Error>(hyper_util::rt::TokioIo::new(stream))
-                }
-            }))
+        let client = TcfsClient::connect(&self.socket_path)
             .await
-            .map_err(|e| {
-                format!(
-                    "failed to connect to daemon at {}: {e}",
-                    self.socket_path.display()
-                )
-            })?;
-
-        let client = TcfsDaemonClient::new(channel);
+            .map_err(|e| e.to_string())?;
         *guard = Some(client.clone());
         Ok(client)
     }
@@ -103,23 +104,20 @@ impl TcfsMcp {
     )]
     async fn daemon_status(&self) -> String {
         match self.connect().await {
-            Ok(mut client) => match client.status(StatusRequest {}).await {
-                Ok(resp) => {
-                    let s = resp.into_inner();
-                    serde_json::json!({
-                        "version": s.version,
-                        "storage_endpoint": s.storage_endpoint,
-                        "storage_ok": s.storage_ok,
-                        "nats_ok": s.nats_ok,
-                        "active_mounts": s.active_mounts,
-                        "uptime_secs": s.uptime_secs,
-                        "device_id": s.device_id,
-                        "device_name": s.device_name,
-                        "conflict_mode": s.conflict_mode,
-                    })
-                    .to_string()
-                }
-                Err(e) => format!("{{\"error\": \"status RPC failed: {e}\"}}"),
+            Ok(mut client) => match client.status().await {
+                Ok(s) => serde_json::json!({
+                    "version": s.version,
+                    "storage_endpoint": s.storage_endpoint,
+                    "storage_ok": s.storage_ok,
+                    "nats_ok": s.nats_ok,
+                    "active_mounts": s.active_mounts,
+                    "uptime_secs": s.uptime_secs,
+                    "device_id": s.device_id,
+                    "device_name": s.device_name,
+                    "conflict_mode": s.conflict_mode,
+                })
+                .to_string(),
+                Err(e) => format!("{{\"error\": \"{e}\"}}"),
             },
             Err(e) => format!("{{\"error\": \"{e}\"}}"),
         }
@@ -128,18 +126,15 @@ impl TcfsMcp {
     #[tool(description = "Get credential status: whether S3/storage credentials are loaded")]
     async fn credential_status(&self) -> String {
         match self.connect().await {
-            Ok(mut client) => match client.credential_status(Empty {}).await {
-                Ok(resp) => {
-                    let c = resp.into_inner();
-                    serde_json::json!({
-                        "loaded": c.loaded,
-                        "source": c.source,
-                        "loaded_at": c.loaded_at,
-                        "needs_reload": c.needs_reload,
-                    })
-                    .to_string()
-                }
-                Err(e) => format!("{{\"error\": \"credential_status RPC failed: {e}\"}}"),
+            Ok(mut client) => match client.credential_status().await {
+                Ok(c) => serde_json::json!({
+                    "loaded": c.loaded,
+                    "source": c.source,
+                    "loaded_at": c.loaded_at,
+                    "needs_reload": c.needs_reload,
+                })
+                .to_string(),
+                Err(e) => format!("{{\"error\": \"{e}\"}}"),
             },
             Err(e) => format!("{{\"error\": \"{e}\"}}"),
         }
@@ -170,25 +165,17 @@ impl TcfsMcp {
     #[tool(description = "Check sync status of a file: synced, pending, or unknown")]
     async fn sync_status(&self, Parameters(input): Parameters<SyncStatusInput>) -> String {
         match self.connect().await {
-            Ok(mut client) => {
-                match client
-                    .sync_status(SyncStatusRequest { path: input.path })
-                    .await
-                {
-                    Ok(resp) => {
-                        let s = resp.into_inner();
-                        serde_json::json!({
-                            "path": s.path,
-                            "state": s.state,
-                            "blake3": s.blake3,
-                            "size": s.size,
-                            "last_synced": s.last_synced,
-                        })
-                        .to_string()
-                    }
-                    Err(e) => format!("{{\"error\": \"sync_status RPC failed: {e}\"}}"),
-                }
-            }
+            Ok(mut client) => match client.sync_status(&input.path).await {
+                Ok(s) => serde_json::json!({
+                    "path": s.path,
+                    "state": s.state,
+                    "blake3": s.blake3,
+                    "size": s.size,
+                    "last_synced": s.last_synced,
+                })
+                .to_string(),
+                Err(e) => error_json(&e),
+            },
             Err(e) => format!("{{\"error\": \"{e}\"}}"),
         }
     }
@@ -196,40 +183,15 @@ impl TcfsMcp {
     #[tool(description = "Pull (download) a file from remote storage to a local path")]
     async fn pull(&self, Parameters(input): Parameters<PullInput>) -> String {
         match self.connect().await {
-            Ok(mut client) => {
-                match client
-                    .pull(PullRequest {
-                        remote_path: input.remote_path,
-                        local_path: input.local_path,
-                    })
-                    .await
-                {
-                    Ok(resp) => {
-                        use tokio_stream::StreamExt;
-                        let mut stream = resp.into_inner();
-                        let mut last_progress = None;
-                        while let Some(item) = stream.next().await {
-                            match item {
-                                Ok(p) => last_progress = Some(p),
-                                Err(e) => {
-                                    return format!("{{\"error\": \"pull stream error: {e}\"}}")
-                                }
-                            }
-                        }
-                        match last_progress {
-                            Some(p) => serde_json::json!({
-                                "bytes_received": p.bytes_received,
-                                "total_bytes": p.total_bytes,
-                                "done": p.done,
-                                "error": if p.error.is_empty() { None } else { Some(&p.error) },
-                            })
-                            .to_string(),
-                            None => "{\"error\": \"no progress received\"}".to_string(),
-                        }
-                    }
-                    Err(e) => format!("{{\"error\": \"pull RPC failed: {e}\"}}"),
-                }
-            }
+            Ok(mut client) => match client.pull(&input.remote_path, &input.local_path).await {
+                Ok(p) => serde_json::json!({
+                    "bytes_received": p.bytes_received,
+                    "total_bytes": p.total_bytes,
+                    "done": p.done,
+                })
+                .to_string(),
+                Err(e) => error_json(&e),
+            },
             Err(e) => format!("{{\"error\": \"{e}\"}}"),
         }
     }
@@ -242,26 +204,22 @@ impl TcfsMcp {
         Parameters(input): Parameters<ResolveConflictInput>,
     ) -> String {
         match self.connect().await {
-            Ok(mut client) => {
-                match client
-                    .resolve_conflict(ResolveConflictRequest {
-                        path: input.rel_path.clone(),
-                        resolution: input.resolution.clone(),
-                    })
-                    .await
-                {
-                    Ok(resp) => {
-                        let r = resp.into_inner();
-                        serde_json::json!({
-                            "success": r.success,
-                            "resolved_path": r.resolved_path,
-                            "error": if r.error.is_empty() { None } else { Some(&r.error) },
-                        })
-                        .to_string()
-                    }
-                    Err(e) => format!("{{\"error\": \"resolve_conflict RPC failed: {e}\"}}"),
-                }
-            }
+            Ok(mut client) => match client
+                .resolve_conflict(&input.rel_path, &input.resolution)
+                .await
+            {
+                Ok(resolved_path) => serde_json::json!({
+                    "success": true,
+                    "resolved_path": resolved_path,
+                })
+                .to_string(),
+                Err(ClientError::Daemon { message, .. }) => serde_json::json!({
+                    "success": false,
+                    "error": message,
+                })
+                .to_string(),
+                Err(e) => error_json(&e),
+            },
             Err(e) => format!("{{\"error\": \"{e}\"}}"),
         }
     }
@@ -282,6 +240,7 @@ impl TcfsMcp {
                             "enrolled_at": d.enrolled_at,
                             "revoked": d.revoked,
                             "last_nats_seq": d.last_nats_seq,
+                            "last_seen": d.last_seen,
                             "description": d.description,
                         })
                     })
@@ -303,51 +262,98 @@ impl TcfsMcp {
             Ok(d) => d,
             Err(e) => return format!("{{\"error\": \"read file: {e}\"}}"),
         };
+        // tcfsd only accepts relative paths
+        let rel_path = input.rel_path.unwrap_or_else(|| {
+            std::path::Path::new(&input.local_path)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
 
-        let chunk = tcfs_core::proto::PushChunk {
-            path: input.local_path.clone(),
-            data,
-            offset: 0,
-            last: true,
-        };
+        match self.connect().await {
+            Ok(mut client) => match client.push(&rel_path, &data).await {
+                Ok(p) => serde_json::json!({
+                    "bytes_sent": p.bytes_sent,
+                    "total_bytes": p.total_bytes,
+                    "chunk_hash": p.chunk_hash,
+                    "done": p.done,
+                    "new_chunks": p.new_chunks,
+                    "deduped_chunks": p.deduped_chunks,
+                })
+                .to_string(),
+                Err(e) => error_json(&e),
+            },
+            Err(e) => format!("{{\"error\": \"{e}\"}}"),
+        }
+    }
 
+    #[tool(
+        description = "Read a stored manifest (chunk list, sizes, vector clock, writer) by file_hash or rel_path. Read-only"
+    )]
+    async fn tcfs_read_manifest(&self, Parameters(input): Parameters<ReadManifestInput>) -> String {
+        let prefix = input.prefix.unwrap_or_default();
+        let file_hash = input.file_hash.unwrap_or_default();
+        let rel_path = input.rel_path.unwrap_or_default();
         match self.connect().await {
-            Ok(mut client) => match client.push(tokio_stream::once(chunk)).await {
-                Ok(resp) => {
-                    use tokio_stream::StreamExt;
-                    let mut stream = resp.into_inner();
-                    let mut last_progress = None;
-                    while let Some(item) = stream.next().await {
-                        match item {
-                            Ok(p) => last_progress = Some(p),
-                            Err(e) => return format!("{{\"error\": \"push stream error: {e}\"}}"),
-                        }
-                    }
-                    match last_progress {
-                        Some(p) => serde_json::json!({
-                            "bytes_sent": p.bytes_sent,
-                            "total_bytes": p.total_bytes,
-                            "chunk_hash": p.chunk_hash,
-                            "done": p.done,
-                            "error": if p.error.is_empty() { None } else { Some(&p.error) },
-                        })
-                        .to_string(),
-                        None => "{\"error\": \"no progress received\"}".to_string(),
-                    }
-                }
-                Err(e) => format!("{{\"error\": \"push RPC failed: {e}\"}}"),
+            Ok(mut client) => match client.read_manifest(&prefix, &file_hash, &rel_path).await {
+                Ok(m) => manifest_json(&m.manifest_path, &m.manifest_json),
+                Err(e) => error_json(&e),
+            },
+            Err(e) => format!("{{\"error\": \"{e}\"}}"),
+        }
+    }
+
+    #[tool(description = "Check whether a chunk is stored, and its stored size and key. Read-only")]
+    async fn tcfs_chunk_info(&self, Parameters(input): Parameters<ChunkInfoInput>) -> String {
+        let prefix = input.prefix.unwrap_or_default();
+        match self.connect().await {
+            Ok(mut client) => match client.chunk_info(&prefix, &input.chunk_hash).await {
+                Ok(c) => serde_json::json!({
+                    "chunk_hash": input.chunk_hash,
+                    "exists": c.exists,
+                    "size": c.size,
+                    "key": c.key,
+                })
+                .to_string(),
+                Err(e) => error_json(&e),
             },
             Err(e) => format!("{{\"error\": \"{e}\"}}"),
         }
     }
 }
 
+/// Reply for `tcfs_read_manifest`: the manifest's key and its parsed body.
+fn manifest_json(manifest_path: &str, manifest: &str) -> String {
+    match serde_json::from_str::<serde_json::Value>(manifest) {
+        Ok(manifest) => serde_json::json!({
+            "manifest_path": manifest_path,
+            "manifest": manifest,
+        })
+        .to_string(),
+        Err(e) => serde_json::json!({ "error": format!("parse manifest: {e}") }).to_string(),
+    }
+}
+
+/// JSON error reply for a failed daemon call, with the gRPC code when the
+/// RPC itself failed.
+fn error_json(e: &ClientError) -> String {
+    match e {
+        ClientError::Rpc { status, .. } => serde_json::json!({
+            "error": e.to_string(),
+            "code": format!("{:?}", status.code()),
+        }),
+        _ => serde_json::json!({ "error": e.to_string() }),
+    }
+    .to_string()
+}
+
 #[tool_handler]
 impl ServerHandler for TcfsMcp {
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
             instructions: Some(
-                "tcfs daemon control — query status, push/pull files, check sync state. \
+                "tcfs daemon control — query status, push/pull files, check sync state, \
+                 inspect stored manifests and chunks. \
                  Connects to tcfsd over Unix domain socket gRPC."
                     .into(),
             ),
@@ -356,3 +362,24 @@ impl ServerHandler for TcfsMcp {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_reply_nests_the_parsed_manifest() {
+        let reply = manifest_json(
+            "tcfs/manifests/abcd",
+            r#"{"version":2,"file_hash":"abcd","file_size":5,"chunks":["c1"]}"#,
+        );
+        let reply: serde_json::Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reply["manifest_path"], "tcfs/manifests/abcd");
+        assert_eq!(reply["manifest"]["file_size"], 5);
+        assert_eq!(reply["manifest"]["chunks"][0], "c1");
+
+        let reply: serde_json::Value =
+            serde_json::from_str(&manifest_json("k", "not json")).unwrap();
+        assert!(reply["error"].as_str().unwrap().contains("parse manifest"));
+    }
+}
@@ -12,7 +12,8 @@ use std::path::{Path, PathBuf};
 pub struct DeviceIdentity {
     /// Human-readable device name (e.g., "yoga-laptop")
     pub name: String,
-    /// UUID v4 device identifier (generated at enrollment)
+    /// UUID device identifier: derived from the device name and machine id
+    /// when the machine id is readable (see `derive_id`), otherwise random v4
     #[serde(default)]
     pub device_id: String,
     /// age public key (age1...)
@@ -30,6 +31,32 @@ pub struct DeviceIdentity {
     /// Last NATS JetStream sequence processed by this device
     #[serde(default)]
     pub last_nats_seq: u64,
+    /// Unix timestamp of the latest `DeviceOnline` event seen from this device
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<u64>,
+    /// Base64 Ed25519 public key that verifies this device's manifest
+    /// signatures (absent for devices enrolled before manifest signing)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_public_key: Option<String>,
+}
+
+impl DeviceIdentity {
+    /// Stable device id for `device_name` on the machine identified by `machine_id`.
+    ///
+    /// Re-enrolling the same name on the same machine (even into a fresh
+    /// registry) yields the same id, so vector clocks are not split between
+    /// two identities. The id is a UUID v8 built from a BLAKE3 hash of both inputs.
+    pub fn derive_id(device_name: &str, machine_id: &str) -> String {
+        let mut hasher = blake3::Hasher::new_derive_key("tcfs device id v1");
+        hasher.update(machine_id.trim().as_bytes());
+        hasher.update(b"\0");
+        hasher.update(device_name.as_bytes());
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&hasher.finalize().as_bytes()[..16]);
+        uuid::Builder::from_custom_bytes(bytes)
+            .into_uuid()
+            .to_string()
+    }
 }
 
 /// Device registry: tracks all enrolled devices for this user
@@ -92,9 +119,83 @@ impl DeviceRegistry {
         self.devices.iter().find(|d| d.device_id == device_id)
     }
 
-    /// Enroll a new device: generates a UUID, creates identity, adds to registry.
+    /// Enroll `public_key` as the manifest signing key of `device_id`.
+    /// Returns whether the registry changed.
+    pub fn set_signing_key(&mut self, device_id: &str, public_key: &str) -> bool {
+        match self.devices.iter_mut().find(|d| d.device_id == device_id) {
+            Some(device) if device.signing_public_key.as_deref() != Some(public_key) => {
+                device.signing_public_key = Some(public_key.to_string());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Enroll a signing key another device published (already checked by
+    /// the caller): the device is added if unknown, or given the key if it
+    /// had none. A different key already enrolled is never replaced, and a
+    /// revoked device is never revived. Returns whether the registry changed.
+    pub fn enroll_published(&mut self, published: &PublishedSigningKey) -> Result<bool> {
+        validate_device_id(&published.device_id)?;
+        let Some(device) = self
+            .devices
+            .iter_mut()
+            .find(|d| d.device_id == published.device_id)
+        else {
+            self.add(DeviceIdentity {
+                name: published.name.clone(),
+                device_id: published.device_id.clone(),
+                public_key: String::new(),
+                signing_key_hash: String::new(),
+                description: Some("enrolled from its published signing key".into()),
+                enrolled_at: std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+                revoked: false,
+                last_nats_seq: 0,
+                last_seen: None,
+                signing_public_key: Some(published.signing_public_key.clone()),
+            });
+            return Ok(true);
+        };
+        anyhow::ensure!(!device.revoked, "device {} is revoked", published.device_id);
+        match &device.signing_public_key {
+            None => {
+                device.signing_public_key = Some(published.signing_public_key.clone());
+                Ok(true)
+            }
+            Some(key) if *key == published.signing_public_key => Ok(false),
+            Some(_) => anyhow::bail!(
+                "device {} published a signing key that differs from the enrolled one",
+                published.device_id
+            ),
+        }
+    }
+
+    /// Record that the device with `device_id` was online at `timestamp`.
+    ///
+    /// Keeps the latest time seen, so events replayed out of order never move
+    /// it backwards. Returns whether the registry changed.
+    pub fn record_seen(&mut self, device_id: &str, timestamp: u64) -> bool {
+        match self.devices.iter_mut().find(|d| d.device_id == device_id) {
+            Some(device) if device.last_seen.is_none_or(|seen| seen < timestamp) => {
+                device.last_seen = Some(timestamp);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Enroll a new device: assigns an id, creates identity, adds to registry.
+    ///
+    /// The id is derived from `name` and this machine's id when one is
+    /// available, falling back to a random UUID.
     pub fn enroll(&mut self, name: &str, public_key: &str, description: Option<String>) -> String {
-        let device_id = uuid::Uuid::new_v4().to_string();
+        let device_id = match machine_id() {
+            Some(machine) => DeviceIdentity::derive_id(name, &machine),
+            None => uuid::Uuid::new_v4().to_string(),
+        };
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap_or_default()
@@ -111,6 +212,8 @@ impl DeviceRegistry {
             enrolled_at: now,
             revoked: false,
             last_nats_seq: 0,
+            last_seen: None,
+            signing_public_key: None,
         });
 
         device_id
@@ -164,12 +267,70 @@ impl DeviceRegistry {
     }
 }
 
+/// A device's manifest signing key as published under a storage prefix, so
+/// the rest of the fleet can enroll it (see [`DeviceRegistry::enroll_published`]).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublishedSigningKey {
+    pub device_id: String,
+    pub name: String,
+    /// Base64 Ed25519 public key
+    pub signing_public_key: String,
+    /// `tcfs_crypto::key_binding_tag` of the id and key under the master key
+    pub tag: String,
+}
+
+impl PublishedSigningKey {
+    /// Storage key of `device_id`'s record under `prefix`.
+    pub fn storage_key(prefix: &str, device_id: &str) -> String {
+        format!(
+            "{}/tcfs-meta/signing-keys/{device_id}.json",
+            prefix.trim_end_matches('/')
+        )
+    }
+
+    /// Write this record under `prefix`.
+    pub async fn publish(&self, op: &opendal::Operator, prefix: &str) -> Result<()> {
+        let key = Self::storage_key(prefix, &self.device_id);
+        let json = serde_json::to_vec_pretty(self).context("serializing signing key record")?;
+        op.write(&key, json)
+            .await
+            .map_err(|e| anyhow::anyhow!("writing {key}: {e}"))?;
+        Ok(())
+    }
+
+    /// The record `device_id` published under `prefix`, if any.
+    pub async fn load(
+        op: &opendal::Operator,
+        prefix: &str,
+        device_id: &str,
+    ) -> Result<Option<Self>> {
+        validate_device_id(device_id)?;
+        let key = Self::storage_key(prefix, device_id);
+        match op.read(&key).await {
+            Ok(data) => serde_json::from_slice(&data.to_bytes())
+                .map(Some)
+                .with_context(|| format!("parsing {key}")),
+            Err(e) if e.kind() == opendal::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(anyhow::anyhow!("reading {key}: {e}")),
+        }
+    }
+}
+
 /// Get the default device registry path
 pub fn default_registry_path() -> PathBuf {
     let config_dir = dirs_path();
     config_dir.join("devices.json")
 }
 
+/// Path of this device's manifest signing key: `device-signing.key` next to
+/// the device registry at `registry_path`
+pub fn signing_key_path(registry_path: &Path) -> PathBuf {
+    registry_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join("device-signing.key")
+}
+
 /// Get the default tcfs config directory
 fn dirs_path() -> PathBuf {
     std::env::var("XDG_CONFIG_HOME")
@@ -181,6 +342,56 @@ fn dirs_path() -> PathBuf {
         .join("tcfs")
 }
 
+/// Check that a configured device id (`sync.device_id`) is usable verbatim.
+///
+/// Ids end up in vector clocks, NATS consumer names and file names, so only
+/// ASCII letters, digits, `-`, `_` and `.` are allowed (at most 128 chars,
+/// not `.` or `..`).
+pub fn validate_device_id(id: &str) -> Result<()> {
+    anyhow::ensure!(!id.is_empty(), "device id must not be empty");
+    anyhow::ensure!(
+        id.len() <= 128,
+        "device id is {} characters (at most 128)",
+        id.len()
+    );
+    anyhow::ensure!(id != "." && id != "..", "device id must not be {id:?}");
+    if let Some(bad) = id
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+    {
+        anyhow::bail!("device id {id:?} contains {bad:?} (allowed: A-Z a-z 0-9 - _ .)");
+    }
+    Ok(())
+}
+
+/// A stable identifier for this machine, if the platform exposes one.
+///
+/// Linux reads `/etc/machine-id` (or the D-Bus copy); macOS asks `ioreg` for
+/// the hardware `IOPlatformUUID`.
+pub fn machine_id() -> Option<String> {
+    #[cfg(target_os = "macos")]
+    {
+        let out = std::process::Command::new("ioreg")
+            .args(["-rd1", "-c", "IOPlatformExpertDevice"])
+            .output()
+            .ok()?;
+        let text = String::from_utf8_lossy(&out.stdout);
+        text.lines()
+            .find(|l| l.contains("IOPlatformUUID"))
+            .and_then(|l| l.split('"').nth(3))
+            .map(str::to_string)
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    {
+        ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+            .iter()
+            .filter_map(|p| std::fs::read_to_string(p).ok())
+            .map(|id| id.trim().to_string())
+            .find(|id| !id.is_empty())
+    }
+}
+
 /// Get the default hostname for device naming
 pub fn default_device_name() -> String {
     hostname::get()
@@ -205,6 +416,8 @@ mod tests {
             enrolled_at: 1000,
             revoked: false,
             last_nats_seq: 0,
+            last_seen: None,
+            signing_public_key: None,
         });
 
         assert_eq!(reg.devices.len(), 1);
@@ -224,6 +437,8 @@ mod tests {
             enrolled_at: 1000,
             revoked: false,
             last_nats_seq: 0,
+            last_seen: None,
+            signing_public_key: None,
         });
 
         assert!(reg.revoke("old-phone"));
@@ -246,6 +461,8 @@ mod tests {
             enrolled_at: 2000,
             revoked: false,
             last_nats_seq: 42,
+            last_seen: None,
+            signing_public_key: None,
         });
         reg.save(&path).unwrap();
 
@@ -265,6 +482,44 @@ mod tests {
         assert_eq!(reg.find("yoga").unwrap().device_id, id);
     }
 
+    #[test]
+    fn test_derive_id_is_stable() {
+        let a = DeviceIdentity::derive_id("yoga", "4c4c4544-0042-3510-8052-b4c04f4d3732");
+        let b = DeviceIdentity::derive_id("yoga", "4c4c4544-0042-3510-8052-b4c04f4d3732\n");
+        assert_eq!(a, b);
+        assert!(uuid::Uuid::parse_str(&a).is_ok());
+
+        assert_ne!(a, DeviceIdentity::derive_id("yoga", "other-machine"));
+        assert_ne!(
+            a,
+            DeviceIdentity::derive_id("neo", "4c4c4544-0042-3510-8052-b4c04f4d3732")
+        );
+    }
+
+    #[test]
+    fn test_validate_device_id() {
+        assert!(validate_device_id("ci-runner_1.local").is_ok());
+        assert!(validate_device_id("9b2f6c1e-5d1a-4f7e-8a0b-0c6d3e2f1a4b").is_ok());
+
+        assert!(validate_device_id("").is_err());
+        assert!(validate_device_id("..").is_err());
+        assert!(validate_device_id("a/b").is_err());
+        assert!(validate_device_id("has space").is_err());
+        assert!(validate_device_id(&"x".repeat(129)).is_err());
+    }
+
+    #[test]
+    fn test_record_seen_keeps_latest() {
+        let mut reg = DeviceRegistry::default();
+        let id = reg.enroll("neo", "age1neo", None);
+        assert_eq!(reg.find("neo").unwrap().last_seen, None);
+
+        assert!(reg.record_seen(&id, 2000));
+        assert!(!reg.record_seen(&id, 1500));
+        assert!(!reg.record_seen("unknown-uuid", 3000));
+        assert_eq!(reg.find("neo").unwrap().last_seen, Some(2000));
+    }
+
     #[test]
     fn test_find_by_id() {
         let mut reg = DeviceRegistry::default();
@@ -272,4 +527,34 @@ mod tests {
         assert!(reg.find_by_id(&id).is_some());
         assert!(reg.find_by_id("nonexistent-uuid").is_none());
     }
+
+    #[test]
+    fn test_enroll_published_pins_first_key() {
+        let mut reg = DeviceRegistry::default();
+        let id = reg.enroll("neo", "age1neo", None);
+        let published = |device_id: &str, key: &str| PublishedSigningKey {
+            device_id: device_id.into(),
+            name: "peer".into(),
+            signing_public_key: key.into(),
+            tag: String::new(),
+        };
+
+        // Keyless and unknown devices take the published key
+        assert!(reg.enroll_published(&published(&id, "key-1")).unwrap());
+        assert!(!reg.enroll_published(&published(&id, "key-1")).unwrap());
+        assert!(reg.enroll_published(&published("peer-1", "key-p")).unwrap());
+        assert_eq!(
+            reg.find_by_id("peer-1")
+                .unwrap()
+                .signing_public_key
+                .as_deref(),
+            Some("key-p")
+        );
+
+        // An enrolled key is never swapped, a revoked device never revived
+        assert!(reg.enroll_published(&published(&id, "key-2")).is_err());
+        assert!(reg.revoke("peer"));
+        assert!(reg.enroll_published(&published("peer-1", "key-p")).is_err());
+        assert!(reg.enroll_published(&published("../x", "key")).is_err());
+    }
 }
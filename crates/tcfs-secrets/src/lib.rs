@@ -87,12 +87,21 @@ impl CredStore {
         Self::load_from_env(storage)
     }
 
-    async fn load_from_sops(
+    /// Load credentials from a SOPS-encrypted file only, without falling back.
+    ///
+    /// Fails unless the file fully decrypts and yields a non-empty access key
+    /// and secret, so a half-written file is never mistaken for credentials.
+    pub async fn load_from_sops(
         cred_file: &Path,
         secrets_config: &tcfs_core::config::SecretsConfig,
     ) -> Result<Self> {
         let identity = identity::find_age_identity(secrets_config).await?;
         let mut creds = sops::decrypt_sops_file(cred_file, &identity).await?;
+        anyhow::ensure!(
+            !creds.access_key_id.is_empty() && !creds.secret_access_key.is_empty(),
+            "{} is missing access_key_id or secret_access_key",
+            cred_file.display()
+        );
 
         let s3 = S3Credentials {
             access_key_id: creds.access_key_id.clone(),
@@ -29,17 +29,30 @@ pub struct RotationResult {
 
 /// Atomically replace a file with new content.
 ///
-/// Writes to a temp file in the same directory, then renames to ensure
-/// no partial reads by concurrent watchers.
+/// Writes to a temp file in the same directory, fsyncs it, then renames to
+/// ensure no partial reads by concurrent watchers. The temp file is removed
+/// if any step fails.
 pub async fn atomic_replace(path: &Path, new_content: &str) -> Result<()> {
+    use tokio::io::AsyncWriteExt;
+
     let parent = path.parent().unwrap_or(Path::new("."));
     let tmp_path = parent.join(format!(
         ".{}.tmp",
         path.file_name().unwrap_or_default().to_string_lossy()
     ));
 
-    tokio::fs::write(&tmp_path, new_content.as_bytes()).await?;
-    tokio::fs::rename(&tmp_path, path).await?;
+    let result = async {
+        let mut file = tokio::fs::File::create(&tmp_path).await?;
+        file.write_all(new_content.as_bytes()).await?;
+        file.sync_all().await?;
+        drop(file);
+        tokio::fs::rename(&tmp_path, path).await
+    }
+    .await;
+    if let Err(e) = result {
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+        return Err(e).with_context(|| format!("replacing {}", path.display()));
+    }
 
     tracing::info!("credential file rotated: {}", path.display());
     Ok(())
@@ -286,4 +299,19 @@ mod tests {
         let tmp_path = dir.path().join(".test-creds.yaml.tmp");
         assert!(!tmp_path.exists());
     }
+
+    #[tokio::test]
+    async fn test_atomic_replace_failure_keeps_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test-creds.yaml");
+        tokio::fs::write(&path, "initial content").await.unwrap();
+
+        // A directory at the temp path makes the write fail
+        let tmp_path = dir.path().join(".test-creds.yaml.tmp");
+        std::fs::create_dir(&tmp_path).unwrap();
+        assert!(atomic_replace(&path, "updated content").await.is_err());
+
+        let content = tokio::fs::read_to_string(&path).await.unwrap();
+        assert_eq!(content, "initial content");
+    }
 }
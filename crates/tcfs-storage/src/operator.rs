@@ -74,6 +74,25 @@ pub fn build_from_core_config(
     })
 }
 
+/// Build an operator for each of `storage.read_mirrors`, in order.
+///
+/// Mirrors are reached with the primary store's credentials, and each is
+/// held to its own `enforce_tls`.
+pub fn build_read_mirrors(
+    storage: &tcfs_core::config::StorageConfig,
+    access_key_id: &str,
+    secret_access_key: &str,
+) -> Result<Vec<Operator>> {
+    storage
+        .read_mirrors
+        .iter()
+        .map(|mirror| {
+            build_from_core_config(mirror, access_key_id, secret_access_key)
+                .with_context(|| format!("building read mirror: {}", mirror.endpoint))
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
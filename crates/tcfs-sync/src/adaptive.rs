@@ -0,0 +1,364 @@
+//! Adaptive transfer concurrency
+//!
+//! A fixed number of in-flight chunk transfers is too few for a
+//! high-latency WAN link and too many for a saturated one. The controller
+//! here watches per-chunk latency and the throughput it implies, and tunes
+//! the in-flight limit AIMD-style between configured bounds: one more slot
+//! per window while each extra slot raises throughput, half the slots once
+//! latency has doubled over the best recently seen without throughput
+//! improving. An extra slot that buys nothing is handed back and the next
+//! probe waits [`PROBE_INTERVAL`] windows, so a saturated link settles low
+//! while a high-latency one climbs to the maximum. A window is as many
+//! completions as the current limit (at least [`MIN_WINDOW`]); transfers
+//! started before the last change of limit are not counted, so each window
+//! measures one setting.
+//!
+//! [`run_adaptive`] drives a set of transfers under a controller; the engine
+//! uses it for chunk uploads, and [`run_adaptive_ordered`] to stream
+//! downloaded chunks out in file order.
+
+use anyhow::Result;
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Completions per adjustment when the limit is smaller than this.
+pub const MIN_WINDOW: usize = 16;
+
+/// Windows to hold the limit after a probe or a decrease before probing again.
+pub const PROBE_INTERVAL: usize = 4;
+
+/// Share of the ideal gain from one more slot (`1/limit`) that a window's
+/// throughput must show to count as an improvement.
+const MIN_GAIN_SHARE: f64 = 0.5;
+
+/// Windows whose fastest sample makes up the latency baseline; older ones
+/// are forgotten so the baseline follows a link that got slower for good.
+const BASELINE_WINDOWS: usize = 8;
+
+/// Lower in-flight bound used when none is configured.
+pub const DEFAULT_MIN_CONCURRENCY: usize = 1;
+
+/// Upper in-flight bound used when none is configured.
+pub const DEFAULT_MAX_CONCURRENCY: usize = 16;
+
+/// AIMD controller for the number of transfers kept in flight.
+#[derive(Debug)]
+pub struct AdaptiveConcurrency {
+    min: usize,
+    max: usize,
+    state: Mutex<Window>,
+}
+
+#[derive(Debug)]
+struct Window {
+    limit: usize,
+    /// Bumped whenever `limit` changes
+    generation: u64,
+    samples: usize,
+    bytes: u64,
+    latency: Duration,
+    fastest: Option<Duration>,
+    baseline: VecDeque<Duration>,
+    /// Throughput estimate (bytes/s) and limit of the previous window
+    last: Option<(f64, usize)>,
+    /// Whether the previous window ended by adding a slot
+    probing: bool,
+    /// Windows left before the next probe
+    cooldown: usize,
+}
+
+impl AdaptiveConcurrency {
+    /// A controller starting at `min` in-flight transfers and never going
+    /// past `max`. Bounds are clamped so that `1 <= min <= max`.
+    pub fn new(min: usize, max: usize) -> Self {
+        let min = min.max(1);
+        let max = max.max(min);
+        Self {
+            min,
+            max,
+            state: Mutex::new(Window {
+                limit: min,
+                generation: 0,
+                samples: 0,
+                bytes: 0,
+                latency: Duration::ZERO,
+                fastest: None,
+                baseline: VecDeque::new(),
+                last: None,
+                probing: false,
+                cooldown: 0,
+            }),
+        }
+    }
+
+    /// The bounds from `sync.transfer_concurrency_min` / `_max`.
+    pub fn from_config(sync: &tcfs_core::config::SyncConfig) -> Self {
+        Self::new(sync.transfer_concurrency_min, sync.transfer_concurrency_max)
+    }
+
+    /// Current number of transfers to keep in flight.
+    pub fn limit(&self) -> usize {
+        self.lock().limit
+    }
+
+    /// Tag for a transfer about to start, to hand back to [`Self::record`].
+    pub fn generation(&self) -> u64 {
+        self.lock().generation
+    }
+
+    /// Configured (min, max) bounds.
+    pub fn bounds(&self) -> (usize, usize) {
+        (self.min, self.max)
+    }
+
+    /// Record one finished transfer of `bytes` that took `latency`, adjusting
+    /// the limit at the end of each window. `generation` is the value of
+    /// [`Self::generation`] when the transfer started; stale samples are
+    /// ignored.
+    pub fn record(&self, generation: u64, bytes: u64, latency: Duration) {
+        let mut w = self.lock();
+        if generation != w.generation {
+            return;
+        }
+        w.samples += 1;
+        w.bytes += bytes;
+        w.latency += latency;
+        w.fastest = Some(w.fastest.map_or(latency, |f| f.min(latency)));
+        if w.samples < w.limit.max(MIN_WINDOW) {
+            return;
+        }
+
+        let avg = w.latency / w.samples as u32;
+        // Little's law: `limit` transfers each taking `avg` on average
+        let throughput = if avg.is_zero() {
+            f64::INFINITY
+        } else {
+            w.limit as f64 * (w.bytes as f64 / w.samples as f64) / avg.as_secs_f64()
+        };
+        if let Some(fastest) = w.fastest.take() {
+            w.baseline.push_back(fastest);
+            if w.baseline.len() > BASELINE_WINDOWS {
+                w.baseline.pop_front();
+            }
+        }
+        let base = w.baseline.iter().min().copied().unwrap_or(avg);
+
+        let improved = w
+            .last
+            .is_none_or(|(last, limit)| throughput > last * (1.0 + MIN_GAIN_SHARE / limit as f64));
+        let probing = std::mem::take(&mut w.probing);
+        let before = w.limit;
+        if avg > base * 2 && !improved && w.limit > self.min {
+            // Requests are queueing somewhere: back off multiplicatively
+            w.limit = (w.limit / 2).max(self.min);
+            w.cooldown = PROBE_INTERVAL;
+        } else if probing && !improved && w.limit > self.min {
+            // The last slot added bought nothing
+            w.limit = (w.limit - 1).max(self.min);
+            w.cooldown = PROBE_INTERVAL;
+        } else if w.cooldown > 0 {
+            w.cooldown -= 1;
+        } else if w.limit < self.max {
+            w.limit += 1;
+            w.probing = true;
+        }
+        if w.limit != before {
+            w.generation += 1;
+        }
+
+        w.last = Some((throughput, before));
+        w.samples = 0;
+        w.bytes = 0;
+        w.latency = Duration::ZERO;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Window> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for AdaptiveConcurrency {
+    fn default() -> Self {
+        Self::new(DEFAULT_MIN_CONCURRENCY, DEFAULT_MAX_CONCURRENCY)
+    }
+}
+
+/// Run `task` over `items`, keeping up to `controller.limit()` in flight.
+///
+/// Each task resolves to its output and the bytes it moved; the bytes and
+/// the task's wall-clock time are fed back into the controller (tasks that
+/// moved nothing, such as deduplicated chunks, are not sampled). Outputs are
+/// returned in input order. The first error is returned as soon as it
+/// completes, dropping (and so cancelling) the transfers still in flight.
+pub async fn run_adaptive<I, F, Fut, T>(
+    controller: &AdaptiveConcurrency,
+    items: I,
+    mut task: F,
+) -> Result<Vec<T>>
+where
+    I: IntoIterator,
+    F: FnMut(I::Item) -> Fut,
+    Fut: Future<Output = Result<(T, u64)>>,
+{
+    let mut items = items.into_iter().enumerate();
+    let mut in_flight = FuturesUnordered::new();
+    let mut outputs = Vec::new();
+
+    loop {
+        while in_flight.len() < controller.limit() {
+            let Some((i, item)) = items.next() else {
+                break;
+            };
+            let fut = task(item);
+            let generation = controller.generation();
+            in_flight.push(async move {
+                let started = tokio::time::Instant::now();
+                let result = fut.await;
+                (i, generation, started.elapsed(), result)
+            });
+        }
+
+        let Some((i, generation, elapsed, result)) = in_flight.next().await else {
+            break;
+        };
+        let (output, bytes) = result?;
+        if bytes > 0 {
+            controller.record(generation, bytes, elapsed);
+        }
+        outputs.push((i, output));
+    }
+
+    outputs.sort_by_key(|(i, _)| *i);
+    Ok(outputs.into_iter().map(|(_, output)| output).collect())
+}
+
+/// [`run_adaptive`] handing each output to `sink` in input order as soon as
+/// the outputs before it have been handed over, instead of collecting them.
+///
+/// Outputs that completed ahead of an earlier one wait for it and count
+/// against the in-flight limit, so at most `controller.limit()` outputs are
+/// held at once. An error from `sink` stops the run like a task error.
+pub async fn run_adaptive_ordered<I, F, Fut, T, S>(
+    controller: &AdaptiveConcurrency,
+    items: I,
+    mut task: F,
+    mut sink: S,
+) -> Result<()>
+where
+    I: IntoIterator,
+    F: FnMut(I::Item) -> Fut,
+    Fut: Future<Output = Result<(T, u64)>>,
+    S: FnMut(T) -> Result<()>,
+{
+    let mut items = items.into_iter().enumerate();
+    let mut in_flight = FuturesUnordered::new();
+    let mut waiting = BTreeMap::new();
+    let mut next = 0;
+
+    loop {
+        while in_flight.len() + waiting.len() < controller.limit() {
+            let Some((i, item)) = items.next() else {
+                break;
+            };
+            let fut = task(item);
+            let generation = controller.generation();
+            in_flight.push(async move {
+                let started = tokio::time::Instant::now();
+                let result = fut.await;
+                (i, generation, started.elapsed(), result)
+            });
+        }
+
+        let Some((i, generation, elapsed, result)) = in_flight.next().await else {
+            break;
+        };
+        let (output, bytes) = result?;
+        if bytes > 0 {
+            controller.record(generation, bytes, elapsed);
+        }
+        waiting.insert(i, output);
+        while let Some(output) = waiting.remove(&next) {
+            sink(output)?;
+            next += 1;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(c: &AdaptiveConcurrency, windows: usize, latency: impl Fn(usize) -> Duration) {
+        for _ in 0..windows {
+            let n = c.limit().max(MIN_WINDOW);
+            for _ in 0..n {
+                c.record(c.generation(), 4096, latency(c.limit()));
+            }
+        }
+    }
+
+    #[test]
+    fn steady_latency_grows_to_max() {
+        let c = AdaptiveConcurrency::new(1, 6);
+        feed(&c, 20, |_| Duration::from_millis(40));
+        assert_eq!(c.limit(), 6);
+    }
+
+    #[test]
+    fn queueing_latency_halves_the_limit() {
+        let c = AdaptiveConcurrency::new(2, 16);
+        feed(&c, 30, |_| Duration::from_millis(5));
+        assert_eq!(c.limit(), 16);
+        // Saturated link: every extra transfer just queues
+        feed(&c, 6, |limit| Duration::from_millis(10 * limit as u64));
+        assert!(c.limit() <= 4, "limit {}", c.limit());
+        assert!(c.limit() >= 2);
+    }
+
+    #[tokio::test]
+    async fn ordered_run_hands_outputs_over_in_input_order() {
+        let c = AdaptiveConcurrency::new(4, 4);
+        let mut seen = Vec::new();
+        run_adaptive_ordered(
+            &c,
+            0..12u64,
+            |i| async move {
+                // Later items finish first within each batch of in-flight tasks
+                tokio::time::sleep(Duration::from_millis(12 - i)).await;
+                Ok((i, 1))
+            },
+            |i| {
+                seen.push(i);
+                Ok(())
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(seen, (0..12).collect::<Vec<_>>());
+
+        let err = run_adaptive_ordered(
+            &c,
+            0..12u64,
+            |i| async move { Ok((i, 0)) },
+            |i| {
+                anyhow::ensure!(i < 5, "sink full");
+                Ok(())
+            },
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("sink full"));
+    }
+
+    #[test]
+    fn bounds_are_clamped() {
+        let c = AdaptiveConcurrency::new(0, 0);
+        assert_eq!(c.bounds(), (1, 1));
+        assert_eq!(c.limit(), 1);
+    }
+}
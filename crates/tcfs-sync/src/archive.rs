@@ -0,0 +1,318 @@
+//! Tar export and import of a whole prefix
+//!
+//! `export_prefix` walks the index under a prefix and writes each file, as
+//! plaintext, to a tar stream one at a time, so nothing is staged on disk
+//! and only one file is held in memory. Paths are the index rel_paths,
+//! modes and mtimes come from the index entries, symlinks and empty
+//! directories are kept. `import_archive` reads such a stream (or any
+//! ustar, pax or GNU archive) entry by entry and pushes each file through
+//! the normal upload path under another prefix. Compression of the stream
+//! is left to the caller (`tcfs export` wraps it in zstd).
+//!
+//! Both directions use the `tar` crate; names too long for the header
+//! fields are written as GNU long-name entries.
+
+use anyhow::{Context, Result};
+use opendal::Operator;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Read, Write};
+use std::path::Path;
+use tar::{EntryType, Header};
+use tcfs_core::index::{IndexEntry, DIR_MARKER};
+use tcfs_core::layout::RemoteLayout;
+use tracing::{debug, warn};
+
+use crate::engine::{OptionalEncryption, ReadOnlyStore};
+use crate::state::StateCache;
+
+/// Mode written for files whose index entry records none.
+const DEFAULT_FILE_MODE: u32 = 0o644;
+
+/// Mode written for directory entries.
+const DIR_MODE: u32 = 0o755;
+
+/// Mode written for symlink entries.
+const SYMLINK_MODE: u32 = 0o777;
+
+/// Counts from one export or import.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArchiveStats {
+    /// Regular files
+    pub files: usize,
+    /// Symlinks
+    pub symlinks: usize,
+    /// Empty directories
+    pub dirs: usize,
+    /// Plaintext bytes of the regular files
+    pub bytes: u64,
+}
+
+/// One index entry to export.
+enum Item {
+    File(IndexEntry),
+    Symlink(IndexEntry),
+    EmptyDir,
+}
+
+/// Write every file indexed under `prefix` to `out` as a tar stream.
+///
+/// Each file is fetched (from its pack range, if packed), decrypted with
+/// `encryption` if its manifest is encrypted, and checked against its content hash before it is written.
+/// Tombstones are skipped. The stream is finished with the tar end marker;
+/// `out` is not flushed.
+pub async fn export_prefix<W: Write>(
+    op: &Operator,
+    prefix: &str,
+    out: &mut W,
+    encryption: OptionalEncryption<'_>,
+) -> Result<ArchiveStats> {
+    let layout = RemoteLayout::new(prefix);
+    let index_dir = layout.index_dir("");
+    let listed = op
+        .list_with(&index_dir)
+        .recursive(true)
+        .await
+        .with_context(|| format!("listing index: {index_dir}"))?;
+
+    let mut items = BTreeMap::new();
+    for entry in listed.iter().filter(|e| !e.metadata().is_dir()) {
+        let key = entry.path();
+        let rel = key.trim_start_matches(&index_dir);
+        if let Some(dir) = rel.strip_suffix(&format!("/{DIR_MARKER}")) {
+            items.insert(dir.to_string(), Item::EmptyDir);
+            continue;
+        }
+        let data = op
+            .read(key)
+            .await
+            .with_context(|| format!("reading index entry: {key}"))?;
+        let index = match IndexEntry::from_bytes(&data.to_bytes()) {
+            Ok(index) => index,
+            Err(e) => {
+                warn!(key = %key, "skipping unreadable index entry: {e}");
+                continue;
+            }
+        };
+        if index.is_tombstone() {
+            continue;
+        }
+        let item = if index.symlink.is_some() {
+            Item::Symlink(index)
+        } else {
+            Item::File(index)
+        };
+        items.insert(rel.to_string(), item);
+    }
+
+    let mut builder = tar::Builder::new(out);
+    let mut stats = ArchiveStats::default();
+    for (rel, item) in items {
+        match item {
+            Item::File(index) => {
+                let data = crate::engine::read_indexed(op, prefix, &rel, &index, encryption)
+                    .await
+                    .with_context(|| format!("fetching {rel}"))?;
+                let mut header = entry_header(
+                    EntryType::Regular,
+                    index.mode.unwrap_or(DEFAULT_FILE_MODE),
+                    index.modified.unwrap_or(0),
+                );
+                header.set_size(data.len() as u64);
+                builder
+                    .append_data(&mut header, &rel, data.as_slice())
+                    .with_context(|| format!("writing {rel} to archive"))?;
+                stats.files += 1;
+                stats.bytes += data.len() as u64;
+            }
+            Item::Symlink(index) => {
+                let mut header = entry_header(
+                    EntryType::Symlink,
+                    SYMLINK_MODE,
+                    index.modified.unwrap_or(0),
+                );
+                let target = index.symlink.unwrap_or_default();
+                builder
+                    .append_link(&mut header, &rel, &target)
+                    .with_context(|| format!("writing {rel} to archive"))?;
+                stats.symlinks += 1;
+            }
+            Item::EmptyDir => {
+                let mut header = entry_header(EntryType::Directory, DIR_MODE, 0);
+                builder
+                    .append_data(&mut header, format!("{rel}/"), std::io::empty())
+                    .with_context(|| format!("writing {rel}/ to archive"))?;
+                stats.dirs += 1;
+            }
+        }
+    }
+
+    builder.finish().context("writing tar end marker")?;
+    Ok(stats)
+}
+
+/// A header for an entry of `kind`, owned by uid/gid 0, of size 0.
+fn entry_header(kind: EntryType, mode: u32, mtime: u64) -> Header {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(kind);
+    header.set_mode(mode);
+    header.set_mtime(mtime);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_size(0);
+    header
+}
+
+/// Push every entry of the tar stream `input` into `prefix`.
+///
+/// Regular files are written one at a time to a scratch directory and
+/// uploaded with their archived rel_path and mode, then removed (along
+/// with their state cache entry); symlinks and empty directories are
+/// recorded in the index directly. Entry paths are checked with
+/// `normalize_rel_path` and symlink targets with `check_symlink_target`,
+/// so an archive cannot name or point at anything outside the prefix.
+/// Such entries, hard links and special files are skipped with a warning.
+pub async fn import_archive<R: Read>(
+    op: &Operator,
+    input: &mut R,
+    prefix: &str,
+    state: &StateCache,
+    device_id: &str,
+    encryption: OptionalEncryption<'_>,
+) -> Result<ArchiveStats> {
+    if state.is_read_only() {
+        return Err(ReadOnlyStore {
+            prefix: prefix.to_string(),
+        }
+        .into());
+    }
+
+    let scratch = std::env::temp_dir().join(format!("tcfs-import-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&scratch)
+        .with_context(|| format!("creating scratch dir: {}", scratch.display()))?;
+    let result = import_entries(op, input, prefix, state, device_id, encryption, &scratch).await;
+    let _ = std::fs::remove_dir_all(&scratch);
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn import_entries<R: Read>(
+    op: &Operator,
+    input: &mut R,
+    prefix: &str,
+    state: &StateCache,
+    device_id: &str,
+    encryption: OptionalEncryption<'_>,
+    scratch: &Path,
+) -> Result<ArchiveStats> {
+    let layout = RemoteLayout::new(prefix);
+    let mut stats = ArchiveStats::default();
+    let mut dirs = BTreeSet::new();
+    let mut occupied = BTreeSet::new();
+
+    let mut archive = tar::Archive::new(input);
+    for entry in archive.entries().context("reading archive")? {
+        let mut entry = entry.context("reading archive")?;
+        let path = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        let kind = entry.header().entry_type();
+        let rel = match tcfs_core::paths::normalize_rel_path(&path) {
+            Ok(rel) => rel.to_string_lossy().replace('\\', "/"),
+            Err(e) => {
+                warn!(path = %path, "skipping archive entry: {e:#}");
+                continue;
+            }
+        };
+        for (i, _) in rel.match_indices('/') {
+            occupied.insert(rel[..i].to_string());
+        }
+        let mode = entry.header().mode().unwrap_or(DEFAULT_FILE_MODE);
+        let mtime = entry
+            .header()
+            .mtime()
+            .with_context(|| format!("reading mtime of {rel}"))?;
+
+        match kind {
+            EntryType::Regular | EntryType::Continuous if !path.ends_with('/') => {
+                let size = entry.size();
+                let name = rel.rsplit('/').next().unwrap_or(&rel);
+                let local = scratch.join(name);
+                {
+                    let mut file = std::fs::File::create(&local)
+                        .with_context(|| format!("creating {}", local.display()))?;
+                    let copied = std::io::copy(&mut entry, &mut file)
+                        .with_context(|| format!("reading {rel} from archive"))?;
+                    anyhow::ensure!(copied == size, "archive truncated in {rel}");
+                }
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    std::fs::set_permissions(&local, std::fs::Permissions::from_mode(mode & 0o777))
+                        .with_context(|| format!("setting mode on {}", local.display()))?;
+                }
+
+                let uploaded = crate::engine::upload_file_with_device(
+                    op,
+                    &local,
+                    prefix,
+                    state,
+                    None,
+                    device_id,
+                    Some(&rel),
+                    encryption,
+                    false,
+                )
+                .await
+                .with_context(|| format!("uploading {rel}"));
+                state.remove(&local);
+                let _ = std::fs::remove_file(&local);
+                let uploaded = uploaded?;
+
+                let mut index =
+                    IndexEntry::new(&uploaded.hash, uploaded.bytes, uploaded.chunks, Some(mtime));
+                index.mode = Some(mode & 0o7777);
+                let key = layout.index_key(&rel);
+                op.write(&key, index.to_bytes())
+                    .await
+                    .with_context(|| format!("writing index entry: {key}"))?;
+                stats.files += 1;
+                stats.bytes += uploaded.bytes;
+            }
+            EntryType::Regular | EntryType::Continuous | EntryType::Directory => {
+                dirs.insert(rel);
+            }
+            EntryType::Symlink => {
+                let target = entry
+                    .link_name_bytes()
+                    .map(|t| String::from_utf8_lossy(&t).into_owned())
+                    .unwrap_or_default();
+                if let Err(e) = tcfs_core::paths::check_symlink_target(&rel, &target) {
+                    warn!(path = %rel, "skipping archive entry: {e:#}");
+                    continue;
+                }
+                let key = layout.index_key(&rel);
+                op.write(
+                    &key,
+                    IndexEntry::new_symlink(&target, Some(mtime)).to_bytes(),
+                )
+                .await
+                .with_context(|| format!("writing index entry: {key}"))?;
+                stats.symlinks += 1;
+            }
+            EntryType::XGlobalHeader => {}
+            other => {
+                warn!(path = %rel, kind = ?other, "skipping unsupported archive entry");
+            }
+        }
+    }
+
+    // Directories with nothing beneath them need a marker to survive
+    for dir in dirs.difference(&occupied) {
+        let key = layout.index_key(&format!("{dir}/{DIR_MARKER}"));
+        op.write(&key, Vec::<u8>::new())
+            .await
+            .with_context(|| format!("writing directory marker: {key}"))?;
+        stats.dirs += 1;
+    }
+
+    debug!(prefix, files = stats.files, "imported archive");
+    Ok(stats)
+}
@@ -0,0 +1,113 @@
+//! Bloom filter of chunk hashes known to be uploaded under a prefix
+//!
+//! Every chunk upload used to start with an `exists` round-trip. The state
+//! cache now keeps one filter per remote prefix (persisted next to the state
+//! file) recording the chunk keys this machine has written or seen. A miss
+//! means the chunk was definitely never recorded, so the engine uploads it
+//! without asking the backend (chunk writes are content-addressed and
+//! idempotent). A hit may be a false positive, so it is still verified with
+//! `exists` before the upload is skipped.
+
+use serde::{Deserialize, Serialize};
+
+/// Smallest capacity a filter is sized for, so tiny trees still get a usable
+/// bit array.
+const MIN_CAPACITY: usize = 1024;
+
+/// Upper bound on probes per lookup.
+const MAX_HASHES: u32 = 16;
+
+/// A fixed-size Bloom filter over chunk hash strings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkFilter {
+    /// Target false-positive rate the filter was sized for
+    pub fp_rate: f64,
+    /// Number of items the filter was sized for
+    pub capacity: usize,
+    /// Number of items inserted so far
+    pub items: usize,
+    num_bits: u64,
+    num_hashes: u32,
+    bits: Vec<u64>,
+}
+
+impl ChunkFilter {
+    /// An empty filter holding up to `capacity` items at `fp_rate`.
+    pub fn new(capacity: usize, fp_rate: f64) -> Self {
+        let capacity = capacity.max(MIN_CAPACITY);
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-(capacity as f64) * fp_rate.ln() / (ln2 * ln2)).ceil() as u64;
+        let num_bits = num_bits.max(64);
+        let num_hashes = ((num_bits as f64 / capacity as f64) * ln2).round() as u32;
+        ChunkFilter {
+            fp_rate,
+            capacity,
+            items: 0,
+            num_bits,
+            num_hashes: num_hashes.clamp(1, MAX_HASHES),
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+        }
+    }
+
+    /// Record `hash` as present.
+    pub fn insert(&mut self, hash: &str) {
+        for bit in self.probes(hash) {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+        self.items += 1;
+    }
+
+    /// `false` if `hash` was definitely never inserted; `true` if it probably was.
+    pub fn contains(&self, hash: &str) -> bool {
+        self.probes(hash)
+            .all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Whether more items have been inserted than the filter was sized for,
+    /// so its false-positive rate is above target and it should be rebuilt.
+    pub fn is_saturated(&self) -> bool {
+        self.items > self.capacity
+    }
+
+    /// Bit positions for `hash` via double hashing over a BLAKE3 digest.
+    fn probes(&self, hash: &str) -> impl Iterator<Item = u64> {
+        let digest = tcfs_chunks::hash_bytes(hash.as_bytes());
+        let bytes = digest.as_bytes();
+        let h1 = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(bytes[8..16].try_into().unwrap()) | 1;
+        let num_bits = self.num_bits;
+        (0..self.num_hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(i: usize) -> String {
+        tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&i.to_le_bytes()))
+    }
+
+    #[test]
+    fn inserted_hashes_are_always_found() {
+        let mut filter = ChunkFilter::new(2000, 0.01);
+        for i in 0..2000 {
+            filter.insert(&key(i));
+        }
+        assert!((0..2000).all(|i| filter.contains(&key(i))));
+        assert!(!filter.is_saturated());
+        filter.insert(&key(2000));
+        assert!(filter.is_saturated());
+    }
+
+    #[test]
+    fn false_positive_rate_near_target() {
+        let mut filter = ChunkFilter::new(5000, 0.01);
+        for i in 0..5000 {
+            filter.insert(&key(i));
+        }
+        let false_positives = (5000..25_000).filter(|&i| filter.contains(&key(i))).count();
+        // 1% of 20k is 200; allow generous slack for variance
+        assert!(false_positives < 400, "{false_positives} false positives");
+    }
+}
@@ -7,6 +7,7 @@
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
+use tcfs_core::clock::Clock;
 
 // ── Vector Clock ──────────────────────────────────────────────────────────────
 
@@ -120,6 +121,12 @@ pub struct ConflictInfo {
     pub remote_device: String,
     /// Unix timestamp when conflict was detected
     pub detected_at: u64,
+    /// Unix timestamp of the last local modification (0 = unknown)
+    #[serde(default)]
+    pub local_modified: u64,
+    /// Unix timestamp at which the remote version was written (0 = unknown)
+    #[serde(default)]
+    pub remote_modified: u64,
 }
 
 // ── Resolution ────────────────────────────────────────────────────────────────
@@ -159,6 +166,89 @@ impl ConflictResolver for AutoResolver {
     }
 }
 
+/// Always takes the incoming remote version.
+pub struct RemoteWinsResolver;
+
+impl ConflictResolver for RemoteWinsResolver {
+    fn resolve(&self, _conflict: &ConflictInfo) -> Option<Resolution> {
+        Some(Resolution::KeepRemote)
+    }
+}
+
+/// Always keeps the local version.
+pub struct LocalWinsResolver;
+
+impl ConflictResolver for LocalWinsResolver {
+    fn resolve(&self, _conflict: &ConflictInfo) -> Option<Resolution> {
+        Some(Resolution::KeepLocal)
+    }
+}
+
+/// Keeps whichever side was modified last.
+///
+/// Ties and unknown (zero) timestamps fall back to [`AutoResolver`], so two
+/// devices seeing the same conflict still pick the same winner.
+pub struct NewestResolver;
+
+impl ConflictResolver for NewestResolver {
+    fn resolve(&self, conflict: &ConflictInfo) -> Option<Resolution> {
+        let (local, remote) = (conflict.local_modified, conflict.remote_modified);
+        if local == 0 || remote == 0 || local == remote {
+            return AutoResolver.resolve(conflict);
+        }
+        if local > remote {
+            Some(Resolution::KeepLocal)
+        } else {
+            Some(Resolution::KeepRemote)
+        }
+    }
+}
+
+/// Keeps the version written by one designated device (matched against the
+/// device IDs in [`ConflictInfo`]); conflicts between other devices fall
+/// back to [`AutoResolver`].
+pub struct PreferDeviceResolver {
+    pub device: String,
+}
+
+impl ConflictResolver for PreferDeviceResolver {
+    fn resolve(&self, conflict: &ConflictInfo) -> Option<Resolution> {
+        if conflict.local_device == self.device {
+            Some(Resolution::KeepLocal)
+        } else if conflict.remote_device == self.device {
+            Some(Resolution::KeepRemote)
+        } else {
+            AutoResolver.resolve(conflict)
+        }
+    }
+}
+
+/// Strategy names accepted by [`resolver_for`], besides `prefer-device:NAME`.
+pub const AUTO_STRATEGIES: &[&str] = &["device-order", "remote-wins", "local-wins", "newest"];
+
+/// `sync.auto_strategy` names no built-in resolver.
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "unknown auto strategy {0:?} (expected device-order, remote-wins, local-wins, newest, or prefer-device:NAME)"
+)]
+pub struct UnknownStrategy(pub String);
+
+/// Build the resolver named by `sync.auto_strategy`.
+pub fn resolver_for(strategy: &str) -> Result<Box<dyn ConflictResolver>, UnknownStrategy> {
+    match strategy {
+        "device-order" => Ok(Box::new(AutoResolver)),
+        "remote-wins" => Ok(Box::new(RemoteWinsResolver)),
+        "local-wins" => Ok(Box::new(LocalWinsResolver)),
+        "newest" => Ok(Box::new(NewestResolver)),
+        other => match other.strip_prefix("prefer-device:") {
+            Some(device) if !device.is_empty() => Ok(Box::new(PreferDeviceResolver {
+                device: device.to_string(),
+            })),
+            _ => Err(UnknownStrategy(other.to_string())),
+        },
+    }
+}
+
 /// Compare a local and remote vector clock to produce a SyncOutcome.
 pub fn compare_clocks(
     local: &VectorClock,
@@ -168,6 +258,30 @@ pub fn compare_clocks(
     rel_path: &str,
     local_device: &str,
     remote_device: &str,
+) -> SyncOutcome {
+    compare_clocks_at(
+        local,
+        remote,
+        local_blake3,
+        remote_blake3,
+        rel_path,
+        local_device,
+        remote_device,
+        &tcfs_core::clock::SystemClock,
+    )
+}
+
+/// [`compare_clocks`] stamping a conflict's `detected_at` from `clock`.
+#[allow(clippy::too_many_arguments)]
+pub fn compare_clocks_at(
+    local: &VectorClock,
+    remote: &VectorClock,
+    local_blake3: &str,
+    remote_blake3: &str,
+    rel_path: &str,
+    local_device: &str,
+    remote_device: &str,
+    clock: &dyn Clock,
 ) -> SyncOutcome {
     // Content-identical means up-to-date regardless of clocks
     if local_blake3 == remote_blake3 {
@@ -177,39 +291,19 @@ pub fn compare_clocks(
     match local.partial_cmp_vc(remote) {
         Some(Ordering::Greater) => SyncOutcome::LocalNewer,
         Some(Ordering::Less) => SyncOutcome::RemoteNewer,
-        Some(Ordering::Equal) => {
-            // Same clock but different content — treat as conflict
-            let now = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs();
-            SyncOutcome::Conflict(ConflictInfo {
-                rel_path: rel_path.to_string(),
-                local_vclock: local.clone(),
-                remote_vclock: remote.clone(),
-                local_blake3: local_blake3.to_string(),
-                remote_blake3: remote_blake3.to_string(),
-                local_device: local_device.to_string(),
-                remote_device: remote_device.to_string(),
-                detected_at: now,
-            })
-        }
-        None => {
-            let now = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs();
-            SyncOutcome::Conflict(ConflictInfo {
-                rel_path: rel_path.to_string(),
-                local_vclock: local.clone(),
-                remote_vclock: remote.clone(),
-                local_blake3: local_blake3.to_string(),
-                remote_blake3: remote_blake3.to_string(),
-                local_device: local_device.to_string(),
-                remote_device: remote_device.to_string(),
-                detected_at: now,
-            })
-        }
+        // Same clock but different content, or concurrent edits: conflict
+        Some(Ordering::Equal) | None => SyncOutcome::Conflict(ConflictInfo {
+            rel_path: rel_path.to_string(),
+            local_vclock: local.clone(),
+            remote_vclock: remote.clone(),
+            local_blake3: local_blake3.to_string(),
+            remote_blake3: remote_blake3.to_string(),
+            local_device: local_device.to_string(),
+            remote_device: remote_device.to_string(),
+            detected_at: clock.unix_secs(),
+            local_modified: 0,
+            remote_modified: 0,
+        }),
     }
 }
 
@@ -393,6 +487,8 @@ mod tests {
             local_device: "alpha".into(),
             remote_device: "beta".into(),
             detected_at: 0,
+            local_modified: 0,
+            remote_modified: 0,
         };
         // "alpha" < "beta" → keep local
         assert_eq!(resolver.resolve(&info), Some(Resolution::KeepLocal));
@@ -406,6 +502,101 @@ mod tests {
         assert_eq!(resolver.resolve(&info2), Some(Resolution::KeepRemote));
     }
 
+    fn synthetic_conflict(
+        local: &str,
+        remote: &str,
+        local_mod: u64,
+        remote_mod: u64,
+    ) -> ConflictInfo {
+        ConflictInfo {
+            rel_path: "notes.md".into(),
+            local_vclock: VectorClock::new(),
+            remote_vclock: VectorClock::new(),
+            local_blake3: "aaa".into(),
+            remote_blake3: "bbb".into(),
+            local_device: local.into(),
+            remote_device: remote.into(),
+            detected_at: 0,
+            local_modified: local_mod,
+            remote_modified: remote_mod,
+        }
+    }
+
+    fn resolve_with(strategy: &str, conflict: &ConflictInfo) -> Option<Resolution> {
+        resolver_for(strategy).unwrap().resolve(conflict)
+    }
+
+    #[test]
+    fn test_fixed_side_strategies() {
+        let info = synthetic_conflict("alpha", "beta", 100, 200);
+        assert_eq!(
+            resolve_with("remote-wins", &info),
+            Some(Resolution::KeepRemote)
+        );
+        assert_eq!(
+            resolve_with("local-wins", &info),
+            Some(Resolution::KeepLocal)
+        );
+        assert_eq!(
+            resolve_with("device-order", &info),
+            Some(Resolution::KeepLocal)
+        );
+    }
+
+    #[test]
+    fn test_newest_strategy() {
+        let remote_newer = synthetic_conflict("alpha", "beta", 100, 200);
+        assert_eq!(
+            resolve_with("newest", &remote_newer),
+            Some(Resolution::KeepRemote)
+        );
+
+        let local_newer = synthetic_conflict("alpha", "beta", 300, 200);
+        assert_eq!(
+            resolve_with("newest", &local_newer),
+            Some(Resolution::KeepLocal)
+        );
+
+        // Ties and unknown times use the device-order tie-break
+        let tie = synthetic_conflict("zeta", "beta", 200, 200);
+        assert_eq!(resolve_with("newest", &tie), Some(Resolution::KeepRemote));
+        let unknown = synthetic_conflict("alpha", "beta", 0, 200);
+        assert_eq!(
+            resolve_with("newest", &unknown),
+            Some(Resolution::KeepLocal)
+        );
+    }
+
+    #[test]
+    fn test_prefer_device_strategy() {
+        let strategy = "prefer-device:laptop";
+        let local = synthetic_conflict("laptop", "desktop", 100, 200);
+        assert_eq!(resolve_with(strategy, &local), Some(Resolution::KeepLocal));
+
+        let remote = synthetic_conflict("desktop", "laptop", 300, 200);
+        assert_eq!(
+            resolve_with(strategy, &remote),
+            Some(Resolution::KeepRemote)
+        );
+
+        // Neither side is preferred: device-order tie-break
+        let neither = synthetic_conflict("zeta", "beta", 0, 0);
+        assert_eq!(
+            resolve_with(strategy, &neither),
+            Some(Resolution::KeepRemote)
+        );
+    }
+
+    #[test]
+    fn test_unknown_strategy_rejected() {
+        for bad in ["largest", "prefer-device:", "", "Remote-Wins"] {
+            assert!(resolver_for(bad).is_err(), "{bad:?}");
+        }
+        for name in AUTO_STRATEGIES {
+            assert!(resolver_for(name).is_ok(), "{name}");
+        }
+    }
+
     #[test]
     fn test_compare_clocks_up_to_date() {
         let a = VectorClock::new();
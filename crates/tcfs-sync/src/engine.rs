@@ -4,7 +4,12 @@
 //!   - `upload_file`: chunk → hash → skip if remote exists → upload via OpenDAL
 //!   - `download_file`: fetch chunk objects → reassemble → write to local path
 //!   - `push_tree`: walk a directory tree, upload changed files
+//!   - `pull_tree`: recreate a pushed tree (files, symlinks, empty dirs) locally
 //!   - `pull_file`: download a single remote path to local
+//!   - `resolve_manifest_path`: look up a file's manifest by its relative path
+//!   - `tree_status`: classify every local file as synced, changed, or untracked
+//!   - `verify_prefix` / `repair_chunk`: find missing or corrupt chunks and
+//!     re-upload them from a local copy
 //!
 //! Phase 6 additions:
 //!   - SyncManifest v2 (JSON with vector clocks)
@@ -12,21 +17,52 @@
 //!   - Config-driven file collection (.git handling, exclude patterns)
 
 use anyhow::{Context, Result};
+use futures::stream::{self, StreamExt};
 use opendal::Operator;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tcfs_core::index::{IndexEntry, DIR_MARKER};
 use tracing::{debug, info, warn};
 
-use crate::conflict::{compare_clocks, SyncOutcome};
+use crate::chunk_filter::ChunkFilter;
+use crate::conflict::{compare_clocks_at, ConflictInfo, SyncOutcome};
 use crate::manifest::SyncManifest;
-use crate::state::{make_sync_state_full, StateCache};
+use crate::state::{make_sync_state_at, StateCache, SyncState};
+use crate::store::{ChunkStore, ObjectMeta};
+use tcfs_core::layout::RemoteLayout;
 
 /// Optional encryption context for E2E encrypted push/pull.
 ///
 /// When present, chunks are encrypted before upload and decrypted after download
 /// using XChaCha20-Poly1305 with per-file keys wrapped by the master key.
+/// With `convergent` set, each chunk is instead keyed by its plaintext hash
+/// (see [`tcfs_crypto::convergent`]) so identical chunks dedup across files.
 #[cfg(feature = "crypto")]
 pub struct EncryptionContext {
     pub master_key: tcfs_crypto::MasterKey,
+    pub convergent: bool,
+}
+
+/// Wrap `key` with the master key and base64-encode it for a manifest.
+#[cfg(feature = "crypto")]
+fn wrap_key_b64(master: &tcfs_crypto::MasterKey, key: &tcfs_crypto::FileKey) -> Result<String> {
+    let wrapped = tcfs_crypto::wrap_key(master, key)?;
+    Ok(base64::Engine::encode(
+        &base64::engine::general_purpose::STANDARD,
+        &wrapped,
+    ))
+}
+
+/// Inverse of [`wrap_key_b64`].
+#[cfg(feature = "crypto")]
+fn unwrap_key_b64(
+    master: &tcfs_crypto::MasterKey,
+    wrapped_b64: &str,
+) -> Result<tcfs_crypto::FileKey> {
+    let wrapped = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, wrapped_b64)
+        .context("decoding wrapped key from manifest")?;
+    tcfs_crypto::unwrap_key(master, &wrapped)
 }
 
 /// Type alias for optional encryption context (feature-gated).
@@ -40,6 +76,27 @@ pub type OptionalEncryption<'a> = Option<&'a ()>;
 /// Progress callback type (bytes_done, bytes_total, message)
 pub type ProgressFn = Box<dyn Fn(u64, u64, &str) + Send + Sync>;
 
+/// Progress callbacks for a single-file upload, both invoked once per chunk.
+#[derive(Clone, Copy, Default)]
+pub struct UploadProgress<'a> {
+    /// Called with (chunks_done, chunks_total, message)
+    pub chunks: Option<&'a ProgressFn>,
+    /// Called with (bytes_done, bytes_total, message), where `bytes_done` is
+    /// the cumulative plaintext length of the chunks processed so far
+    pub bytes: Option<&'a ProgressFn>,
+}
+
+/// Progress callbacks for a single-file download, both invoked as each chunk
+/// is written out.
+#[derive(Clone, Copy, Default)]
+pub struct DownloadProgress<'a> {
+    /// Called with (chunks_done, chunks_total, message)
+    pub chunks: Option<&'a ProgressFn>,
+    /// Called with (bytes_done, bytes_total, message), where `bytes_done` is
+    /// the plaintext written to the destination so far
+    pub bytes: Option<&'a ProgressFn>,
+}
+
 /// Configuration for file collection (which files to include/exclude).
 #[derive(Debug, Clone)]
 pub struct CollectConfig {
@@ -51,6 +108,10 @@ pub struct CollectConfig {
     pub sync_hidden_dirs: bool,
     /// Glob patterns to exclude
     pub exclude_patterns: Vec<String>,
+    /// Extensions stored without compression (None = [`INCOMPRESSIBLE_EXTENSIONS`])
+    pub compress_skip_extensions: Option<Vec<String>>,
+    /// Whether to include zero-byte files
+    pub sync_empty_files: bool,
 }
 
 impl Default for CollectConfig {
@@ -60,10 +121,62 @@ impl Default for CollectConfig {
             git_sync_mode: "bundle".into(),
             sync_hidden_dirs: false,
             exclude_patterns: Vec::new(),
+            compress_skip_extensions: None,
+            sync_empty_files: true,
+        }
+    }
+}
+
+impl CollectConfig {
+    /// The collection rules from the `[sync]` config section.
+    pub fn from_config(sync: &tcfs_core::config::SyncConfig) -> Self {
+        Self {
+            sync_git_dirs: sync.sync_git_dirs,
+            git_sync_mode: sync.git_sync_mode.clone(),
+            sync_hidden_dirs: sync.sync_hidden_dirs,
+            exclude_patterns: sync.exclude_patterns.clone(),
+            compress_skip_extensions: sync.compress_skip_extensions.clone(),
+            sync_empty_files: sync.sync_empty_files,
+        }
+    }
+
+    /// Whether a tree walk skips a directory named `name` outright.
+    ///
+    /// `.git` is only descended into when raw git sync is enabled; the
+    /// safety checks `collect_tree` runs before doing so are not repeated.
+    pub fn skips_dir(&self, name: &str) -> bool {
+        if ALWAYS_SKIPPED_DIRS.contains(&name) {
+            return true;
         }
+        if name == ".git" {
+            return !self.sync_git_dirs || self.git_sync_mode == "bundle";
+        }
+        name.starts_with('.') && !self.sync_hidden_dirs
     }
 }
 
+/// Per-tree ignore file read from the root of every pushed or watched tree.
+///
+/// One glob per line, matched against file and directory names like
+/// `sync.exclude_patterns`; blank lines and `#` comments are ignored.
+pub const IGNORE_FILE: &str = ".tcfsignore";
+
+/// Directory names never collected, whatever the config says.
+const ALWAYS_SKIPPED_DIRS: &[&str] = &["target", "node_modules", ".DS_Store"];
+
+/// Patterns listed in `root/.tcfsignore`, or none if the file is absent.
+pub fn read_ignore_file(root: &Path) -> Vec<String> {
+    std::fs::read_to_string(root.join(IGNORE_FILE))
+        .map(|text| {
+            text.lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 /// Result of uploading a single file
 #[derive(Debug)]
 pub struct UploadResult {
@@ -76,6 +189,14 @@ pub struct UploadResult {
     pub skipped: bool,
     /// Sync outcome if conflict detection was performed
     pub outcome: Option<SyncOutcome>,
+    /// Number of chunks not already present remotely (uploaded, or would upload in dry-run)
+    pub new_chunks: usize,
+    /// Number of chunks found already stored and not re-uploaded (estimated in dry-run)
+    pub deduped_chunks: usize,
+    /// Of `deduped_chunks`, those shared with the file's previous version
+    pub reused_chunks: usize,
+    /// true if this was a dry run (nothing was written remotely or to the state cache)
+    pub dry_run: bool,
 }
 
 /// Result of downloading a single file
@@ -100,7 +221,7 @@ pub async fn upload_file(
     op: &Operator,
     local_path: &Path,
     remote_prefix: &str,
-    state: &mut StateCache,
+    state: &StateCache,
     progress: Option<&ProgressFn>,
 ) -> Result<UploadResult> {
     upload_file_with_device(
@@ -112,597 +233,4360 @@ pub async fn upload_file(
         "",
         None,
         None,
+        false,
     )
     .await
 }
 
 /// Upload with device identity, vector clock awareness, and optional encryption.
-#[allow(unused_variables)]
+///
+/// When `dry_run` is set, the file is still chunked, hashed and compared against
+/// the remote, but no objects are written and the state cache is left untouched.
+/// The returned `new_chunks` is an estimate of how many chunks would be uploaded.
+#[allow(clippy::too_many_arguments)]
 pub async fn upload_file_with_device(
     op: &Operator,
     local_path: &Path,
     remote_prefix: &str,
-    state: &mut StateCache,
+    state: &StateCache,
     progress: Option<&ProgressFn>,
     device_id: &str,
     rel_path: Option<&str>,
     encryption: OptionalEncryption<'_>,
+    dry_run: bool,
 ) -> Result<UploadResult> {
-    // Fast-path: check if file is already up-to-date
-    match state.needs_sync(local_path)? {
-        None => {
-            let cached = state.get(local_path).unwrap();
-            let result = UploadResult {
-                path: local_path.to_path_buf(),
-                remote_path: cached.remote_path.clone(),
-                hash: cached.blake3.clone(),
-                chunks: cached.chunk_count,
-                bytes: cached.size,
-                skipped: true,
-                outcome: Some(SyncOutcome::UpToDate),
-            };
-            debug!(path = %local_path.display(), "skip: unchanged since last sync");
-            return Ok(result);
-        }
-        Some(reason) => {
-            debug!(path = %local_path.display(), reason = %reason, "uploading");
+    let progress = UploadProgress {
+        chunks: progress,
+        bytes: None,
+    };
+    upload_file_with_progress(
+        op,
+        local_path,
+        remote_prefix,
+        state,
+        progress,
+        device_id,
+        rel_path,
+        encryption,
+        None,
+        dry_run,
+    )
+    .await
+}
+
+/// Like [`upload_file_with_device`], reporting progress by chunk count and/or
+/// by bytes. `compress_skip` replaces the built-in list of extensions stored
+/// without compression (see [`should_compress_with`]).
+#[allow(clippy::too_many_arguments)]
+pub async fn upload_file_with_progress(
+    op: &Operator,
+    local_path: &Path,
+    remote_prefix: &str,
+    state: &StateCache,
+    progress: UploadProgress<'_>,
+    device_id: &str,
+    rel_path: Option<&str>,
+    encryption: OptionalEncryption<'_>,
+    compress_skip: Option<&[String]>,
+    dry_run: bool,
+) -> Result<UploadResult> {
+    upload_file_shared(
+        op,
+        local_path,
+        remote_prefix,
+        state,
+        progress,
+        device_id,
+        rel_path,
+        encryption,
+        compress_skip,
+        dry_run,
+        None,
+    )
+    .await
+}
+
+/// Total size in bytes of the chunks and manifests stored under `remote_prefix`.
+pub async fn prefix_usage(op: &Operator, remote_prefix: &str) -> Result<u64> {
+    let layout = RemoteLayout::new(remote_prefix);
+    let mut total = 0u64;
+    for dir in [layout.chunks_dir(), layout.manifests_dir()] {
+        let entries = op
+            .list_with(&dir)
+            .recursive(true)
+            .await
+            .with_context(|| format!("listing {dir}"))?;
+        for entry in entries.iter().filter(|e| !e.metadata().is_dir()) {
+            let mut len = entry.metadata().content_length();
+            if len == 0 {
+                // Some services leave sizes out of listings
+                len = op
+                    .stat(entry.path())
+                    .await
+                    .with_context(|| format!("stat {}", entry.path()))?
+                    .content_length();
+            }
+            total += len;
         }
     }
+    Ok(total)
+}
 
-    // Chunk the file
-    let (chunks, data) = tcfs_chunks::chunk_file(local_path)
-        .with_context(|| format!("chunking: {}", local_path.display()))?;
+/// Hashes of every chunk stored under `remote_prefix`, at any shard depth.
+///
+/// Chunk keys end in the chunk's hash whether or not they are sharded, so
+/// this is the set a garbage collector compares against the hashes still
+/// referenced by manifests.
+pub async fn list_chunks(op: &Operator, remote_prefix: &str) -> Result<Vec<String>> {
+    let dir = RemoteLayout::new(remote_prefix).chunks_dir();
+    let entries = op
+        .list_with(&dir)
+        .recursive(true)
+        .await
+        .with_context(|| format!("listing {dir}"))?;
+    Ok(entries
+        .iter()
+        .filter(|e| !e.metadata().is_dir())
+        .filter_map(|e| e.path().rsplit('/').next().map(str::to_string))
+        .collect())
+}
 
-    let file_size = data.len() as u64;
-    let file_hash = tcfs_chunks::hash_bytes(&data);
-    let file_hash_hex = tcfs_chunks::hash_to_hex(&file_hash);
+/// Which version history [`prune_history`] removes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PruneHistory {
+    /// Newest versions kept per file whatever their age (`None` = no floor)
+    pub keep: Option<usize>,
+    /// Only versions recorded at least this many seconds ago are removed
+    /// (`None` = any age)
+    pub older_than: Option<u64>,
+    /// Seconds an unreferenced chunk must have been written, and condemned
+    /// by an earlier sweep, before it is deleted, so a push whose manifest
+    /// has not landed yet keeps its chunks (0 = delete on the first sweep)
+    pub chunk_grace_secs: u64,
+}
 
-    // Build remote manifest path (using the file's content hash)
-    let remote_manifest = format!("{remote_prefix}/manifests/{file_hash_hex}");
+/// What one [`prune_history`] removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PruneStats {
+    /// History pointers deleted
+    pub pointers: usize,
+    /// Manifests deleted because only pruned pointers referred to them
+    pub manifests: usize,
+    /// Chunks deleted because no remaining manifest lists them
+    pub chunks: usize,
+    /// Stored bytes of those chunks
+    pub bytes: u64,
+}
 
-    // Get the local vclock from state (or start fresh)
-    let mut local_vclock = state
-        .get(local_path)
-        .map(|s| s.vclock.clone())
-        .unwrap_or_default();
+/// Remove history pointers under `remote_prefix` beyond `policy`, then the
+/// manifests only they referred to, then every chunk no remaining manifest
+/// lists.
+///
+/// Per file, the newest `keep` pointers are kept, and of the rest only
+/// those recorded before `now - older_than` are removed. A manifest named by
+/// a live (non-tombstone) index entry, a kept pointer or a snapshot is never
+/// deleted, and neither is a chunk listed by any manifest left in place.
+/// An unreadable index entry or manifest aborts the sweep before anything
+/// it might protect is deleted.
+///
+/// An unreferenced chunk is first condemned, in `{prefix}/gc/condemned.json`,
+/// and deleted by a later sweep at least `chunk_grace_secs` on that finds it
+/// still unreferenced and written at least as long ago. Chunks whose
+/// modification time the store does not report are kept.
+pub async fn prune_history(
+    op: &Operator,
+    remote_prefix: &str,
+    policy: &PruneHistory,
+    now: u64,
+) -> Result<PruneStats> {
+    let layout = RemoteLayout::new(remote_prefix);
+    let mut stats = PruneStats::default();
 
-    // Check if remote manifest exists for conflict detection
-    let mut outcome = None;
-    if !device_id.is_empty() {
-        if let Ok(true) = op.exists(&remote_manifest).await {
-            if let Ok(remote_bytes) = op.read(&remote_manifest).await {
-                if let Ok(remote_manifest_obj) = SyncManifest::from_bytes(&remote_bytes.to_bytes())
-                {
-                    let local_hash = &file_hash_hex;
-                    let remote_hash = &remote_manifest_obj.file_hash;
-                    let rp = rel_path.unwrap_or("");
-
-                    let sync_outcome = compare_clocks(
-                        &local_vclock,
-                        &remote_manifest_obj.vclock,
-                        local_hash,
-                        remote_hash,
-                        rp,
-                        device_id,
-                        &remote_manifest_obj.written_by,
-                    );
+    // Pointers grouped by file, as (timestamp, file hash, key)
+    let history_dir = layout.history_dir("");
+    let entries = op
+        .list_with(&history_dir)
+        .recursive(true)
+        .await
+        .with_context(|| format!("listing {history_dir}"))?;
+    let mut by_file: BTreeMap<&str, Vec<(u64, &str, &str)>> = BTreeMap::new();
+    for entry in entries.iter().filter(|e| !e.metadata().is_dir()) {
+        let Some((dir, name)) = entry.path().rsplit_once('/') else {
+            continue;
+        };
+        let Some((ts, hash)) = name.split_once('-') else {
+            continue;
+        };
+        let Ok(timestamp) = ts.parse::<u64>() else {
+            continue;
+        };
+        by_file
+            .entry(dir)
+            .or_default()
+            .push((timestamp, hash, entry.path()));
+    }
 
-                    match &sync_outcome {
-                        SyncOutcome::RemoteNewer => {
-                            return Ok(UploadResult {
-                                path: local_path.to_path_buf(),
-                                remote_path: remote_manifest.clone(),
-                                hash: file_hash_hex,
-                                chunks: chunks.len(),
-                                bytes: file_size,
-                                skipped: true,
-                                outcome: Some(sync_outcome),
-                            });
-                        }
-                        SyncOutcome::Conflict(_) => {
-                            return Ok(UploadResult {
-                                path: local_path.to_path_buf(),
-                                remote_path: remote_manifest.clone(),
-                                hash: file_hash_hex,
-                                chunks: chunks.len(),
-                                bytes: file_size,
-                                skipped: true,
-                                outcome: Some(sync_outcome),
-                            });
-                        }
-                        SyncOutcome::UpToDate => {
-                            // Content dedup — already up to date
-                            let sync_state = make_sync_state_full(
-                                local_path,
-                                file_hash_hex.clone(),
-                                chunks.len(),
-                                remote_manifest.clone(),
-                                local_vclock,
-                                device_id.to_string(),
-                            )?;
-                            state.set(local_path, sync_state);
-                            return Ok(UploadResult {
-                                path: local_path.to_path_buf(),
-                                remote_path: remote_manifest,
-                                hash: file_hash_hex,
-                                chunks: chunks.len(),
-                                bytes: file_size,
-                                skipped: true,
-                                outcome: Some(sync_outcome),
-                            });
-                        }
-                        SyncOutcome::LocalNewer => {
-                            // Merge remote vclock into local before writing
-                            local_vclock.merge(&remote_manifest_obj.vclock);
-                            outcome = Some(SyncOutcome::LocalNewer);
-                        }
-                    }
-                }
+    let cutoff = policy.older_than.map(|age| now.saturating_sub(age));
+    let mut pruned = Vec::new();
+    let mut retained: HashSet<String> = HashSet::new();
+    for versions in by_file.values_mut() {
+        versions.sort();
+        let removable = versions.len().saturating_sub(policy.keep.unwrap_or(0));
+        for (i, &(timestamp, hash, key)) in versions.iter().enumerate() {
+            if i < removable && cutoff.is_none_or(|cutoff| timestamp < cutoff) {
+                pruned.push((hash, key));
+            } else {
+                retained.insert(hash.to_string());
             }
         }
     }
 
-    // Check if this exact content is already stored (content-addressed dedup)
-    // Only check when we haven't already done the remote manifest check above
-    if outcome.is_none()
-        && op.exists(&remote_manifest).await.unwrap_or(false)
-        && device_id.is_empty()
-    {
-        debug!(hash = %file_hash_hex, "dedup: manifest already exists");
-        let remote_path = remote_manifest.clone();
-        let sync_state = make_sync_state_full(
-            local_path,
-            file_hash_hex.clone(),
-            chunks.len(),
-            remote_path.clone(),
-            local_vclock,
-            device_id.to_string(),
-        )?;
-        state.set(local_path, sync_state);
-        return Ok(UploadResult {
-            path: local_path.to_path_buf(),
-            remote_path,
-            hash: file_hash_hex,
-            chunks: chunks.len(),
-            bytes: file_size,
-            skipped: false,
-            outcome: None,
-        });
+    // Manifests the live index and snapshots still point at
+    let index_dir = layout.index_dir("");
+    let entries = op
+        .list_with(&index_dir)
+        .recursive(true)
+        .await
+        .with_context(|| format!("listing {index_dir}"))?;
+    for entry in entries.iter().filter(|e| !e.metadata().is_dir()) {
+        if entry.name() == DIR_MARKER {
+            continue;
+        }
+        let data = op
+            .read(entry.path())
+            .await
+            .with_context(|| format!("reading index entry: {}", entry.path()))?;
+        let index = IndexEntry::from_bytes(&data.to_bytes())
+            .with_context(|| format!("parsing index entry: {}", entry.path()))?;
+        if !index.deleted {
+            retained.insert(index.manifest_hash);
+        }
+    }
+    for snapshot in crate::snapshot::list_snapshots(op, remote_prefix).await? {
+        let tree = crate::tree::read_tree(op, remote_prefix, &snapshot.root_hash).await?;
+        retained.extend(tree.files.into_values());
     }
 
-    // Tick local vclock before writing
-    if !device_id.is_empty() {
-        local_vclock.tick(device_id);
+    let mut orphaned = HashSet::new();
+    for (hash, key) in pruned {
+        op.delete(key)
+            .await
+            .with_context(|| format!("deleting history pointer: {key}"))?;
+        stats.pointers += 1;
+        if !retained.contains(hash) && orphaned.insert(hash) {
+            let manifest_key = layout.manifest_key(hash);
+            op.delete(&manifest_key)
+                .await
+                .with_context(|| format!("deleting manifest: {manifest_key}"))?;
+            stats.manifests += 1;
+        }
     }
 
-    // Upload each chunk (skip if already present — dedup by chunk hash)
-    let mut chunk_hashes = Vec::with_capacity(chunks.len());
-    let mut bytes_uploaded = 0u64;
+    // Chunks no remaining manifest lists
+    let manifests_dir = layout.manifests_dir();
+    let entries = op
+        .list(&manifests_dir)
+        .await
+        .with_context(|| format!("listing manifests: {manifests_dir}"))?;
+    let mut referenced = HashSet::new();
+    for entry in entries.iter().filter(|e| !e.metadata().is_dir()) {
+        let data = op
+            .read(entry.path())
+            .await
+            .with_context(|| format!("reading manifest: {}", entry.path()))?;
+        let manifest = SyncManifest::from_bytes(&data.to_bytes()).with_context(|| {
+            format!(
+                "not collecting chunks: unreadable manifest {}",
+                entry.path()
+            )
+        })?;
+        referenced.extend(manifest.chunk_hashes().iter().cloned());
+    }
 
-    // Generate per-file encryption key if encryption is enabled
-    #[cfg(feature = "crypto")]
-    let (file_key, file_id) = if encryption.is_some() {
-        let fk = tcfs_crypto::generate_file_key();
-        // Use the plaintext file hash as the file_id for AAD binding
-        let fid: [u8; 32] = {
-            let hash = tcfs_chunks::hash_from_hex(&file_hash_hex)
-                .context("parsing file hash for encryption file_id")?;
-            *hash.as_bytes()
-        };
-        (Some(fk), Some(fid))
-    } else {
-        (None, None)
+    // A push that deduplicated onto an unreferenced chunk writes nothing to
+    // it, so its age cannot tell whether a manifest is about to list it.
+    // Chunks are instead condemned by one sweep and only deleted by a later
+    // one, at least the grace period on, that still finds them unreferenced;
+    // by then such a push's manifest has landed and spares them.
+    let condemned_key = layout.condemned_key();
+    let condemned: BTreeMap<String, u64> = match op.read(&condemned_key).await {
+        Ok(data) => serde_json::from_slice(&data.to_bytes())
+            .with_context(|| format!("parsing {condemned_key}"))?,
+        Err(e) if e.kind() == opendal::ErrorKind::NotFound => BTreeMap::new(),
+        Err(e) => return Err(e).with_context(|| format!("reading {condemned_key}")),
     };
+    let mut still_condemned = BTreeMap::new();
 
-    for (i, chunk) in chunks.iter().enumerate() {
-        let chunk_data = &data[chunk.offset as usize..chunk.offset as usize + chunk.length];
-
-        // Encrypt chunk if encryption is enabled
-        #[cfg(feature = "crypto")]
-        let (upload_data, chunk_hash_hex) =
-            if let (Some(ref fk), Some(ref fid)) = (&file_key, &file_id) {
-                let ciphertext = tcfs_crypto::encrypt_chunk(fk, i as u64, fid, chunk_data)
-                    .with_context(|| format!("encrypting chunk {i}"))?;
-                // CAS key is ciphertext hash (not plaintext hash)
-                let ct_hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&ciphertext));
-                (ciphertext, ct_hash)
-            } else {
-                let h = tcfs_chunks::hash_to_hex(&chunk.hash);
-                (chunk_data.to_vec(), h)
-            };
-
-        #[cfg(not(feature = "crypto"))]
-        let (upload_data, chunk_hash_hex) = {
-            let h = tcfs_chunks::hash_to_hex(&chunk.hash);
-            (chunk_data.to_vec(), h)
+    let chunks_dir = layout.chunks_dir();
+    let entries = op
+        .list_with(&chunks_dir)
+        .recursive(true)
+        .await
+        .with_context(|| format!("listing {chunks_dir}"))?;
+    for entry in entries.iter().filter(|e| !e.metadata().is_dir()) {
+        let hash = entry.path().rsplit('/').next().unwrap_or_default();
+        if referenced.contains(hash) {
+            continue;
+        }
+        // Some services leave sizes and times out of listings
+        let listed = entry.metadata();
+        let meta = if listed.last_modified().is_some() && listed.content_length() > 0 {
+            listed.clone()
+        } else {
+            op.stat(entry.path())
+                .await
+                .with_context(|| format!("stat {}", entry.path()))?
         };
+        let written = meta
+            .last_modified()
+            .map(std::time::SystemTime::from)
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+        let Some(written) = written else {
+            debug!(chunk = %entry.path(), "keeping unreferenced chunk with no modification time");
+            continue;
+        };
+        if now.saturating_sub(written) < policy.chunk_grace_secs {
+            debug!(chunk = %entry.path(), "keeping recent unreferenced chunk");
+            continue;
+        }
+        let since = condemned.get(hash).copied().unwrap_or(now);
+        if now.saturating_sub(since) < policy.chunk_grace_secs {
+            debug!(chunk = %entry.path(), "condemning unreferenced chunk");
+            still_condemned.insert(hash.to_string(), since);
+            continue;
+        }
+        op.delete(entry.path())
+            .await
+            .with_context(|| format!("deleting chunk: {}", entry.path()))?;
+        stats.chunks += 1;
+        stats.bytes += meta.content_length();
+    }
 
-        let chunk_key = format!("{remote_prefix}/chunks/{chunk_hash_hex}");
-
-        if !op.exists(&chunk_key).await.unwrap_or(false) {
-            op.write(&chunk_key, upload_data)
+    // Chunks referenced again or deleted drop out of the record
+    if still_condemned != condemned {
+        if still_condemned.is_empty() {
+            op.delete(&condemned_key)
                 .await
-                .with_context(|| format!("uploading chunk {i}: {chunk_key}"))?;
-            bytes_uploaded += chunk.length as u64;
+                .with_context(|| format!("deleting {condemned_key}"))?;
+        } else {
+            let record = serde_json::to_vec(&still_condemned)?;
+            op.write(&condemned_key, record)
+                .await
+                .with_context(|| format!("writing {condemned_key}"))?;
         }
+    }
 
-        chunk_hashes.push(chunk_hash_hex);
+    info!(
+        prefix = %remote_prefix,
+        pointers = stats.pointers,
+        manifests = stats.manifests,
+        chunks = stats.chunks,
+        bytes = stats.bytes,
+        "pruned history"
+    );
+    Ok(stats)
+}
 
-        if let Some(cb) = progress {
-            cb(
-                (i + 1) as u64,
-                chunks.len() as u64,
-                &format!("chunk {}/{}", i + 1, chunks.len()),
-            );
+/// Chunk and manifest statistics for one prefix, from [`prefix_stats`].
+///
+/// Chunk sizes are stored sizes, so compressed chunks count at their
+/// compressed length.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrefixStats {
+    /// Manifests read
+    pub manifests: usize,
+    /// Sum of the file sizes the manifests describe
+    pub logical_bytes: u64,
+    /// Chunk references across all manifests
+    pub chunk_refs: usize,
+    /// Distinct stored chunks referenced by some manifest
+    pub unique_chunks: usize,
+    /// Referenced chunks not found in storage
+    pub missing_chunks: usize,
+    /// Stored bytes of every reference, counting shared chunks each time
+    pub referenced_bytes: u64,
+    /// Stored bytes of the distinct referenced chunks
+    pub unique_bytes: u64,
+    /// Median stored size of the distinct chunks
+    pub median_chunk: u64,
+    /// Distinct chunks per power-of-two size bucket, as (upper bound in
+    /// bytes, count), smallest bucket first and empty buckets left out
+    pub histogram: Vec<(u64, usize)>,
+    /// Largest files as (rel_path, or file hash if unrecorded; size),
+    /// largest first
+    pub largest: Vec<(String, u64)>,
+}
+
+impl PrefixStats {
+    /// Mean stored size of the distinct chunks (0 with none).
+    pub fn average_chunk(&self) -> u64 {
+        self.unique_bytes
+            .checked_div(self.unique_chunks as u64)
+            .unwrap_or(0)
+    }
+
+    /// Bytes the references would take without dedup over the bytes
+    /// stored (1.0 with nothing shared or nothing stored).
+    pub fn dedup_ratio(&self) -> f64 {
+        if self.unique_bytes == 0 {
+            return 1.0;
         }
+        self.referenced_bytes as f64 / self.unique_bytes as f64
     }
+}
 
-    // Wrap file key for manifest if encryption is enabled
-    #[cfg(feature = "crypto")]
-    let encrypted_file_key = if let (Some(ctx), Some(ref fk)) = (encryption, &file_key) {
-        let wrapped = tcfs_crypto::wrap_key(&ctx.master_key, fk).context("wrapping file key")?;
-        Some(base64::Engine::encode(
-            &base64::engine::general_purpose::STANDARD,
-            &wrapped,
-        ))
-    } else {
-        None
-    };
+/// Chunk-size distribution, dedup and largest files over every manifest
+/// under `remote_prefix`, keeping the `top` largest files. Read-only; an
+/// unreadable manifest fails the whole report.
+pub async fn prefix_stats(op: &Operator, remote_prefix: &str, top: usize) -> Result<PrefixStats> {
+    let layout = RemoteLayout::new(remote_prefix);
+    let mut stats = PrefixStats::default();
 
-    #[cfg(not(feature = "crypto"))]
-    let encrypted_file_key: Option<String> = None;
+    // Stored size of every chunk, at any shard depth
+    let chunks_dir = layout.chunks_dir();
+    let entries = op
+        .list_with(&chunks_dir)
+        .recursive(true)
+        .await
+        .with_context(|| format!("listing {chunks_dir}"))?;
+    let mut stored: HashMap<String, u64> = HashMap::new();
+    for entry in entries.iter().filter(|e| !e.metadata().is_dir()) {
+        let mut len = entry.metadata().content_length();
+        if len == 0 {
+            // Some services leave sizes out of listings
+            len = op
+                .stat(entry.path())
+                .await
+                .with_context(|| format!("stat {}", entry.path()))?
+                .content_length();
+        }
+        let hash = entry.path().rsplit('/').next().unwrap_or_default();
+        stored.insert(hash.to_string(), len);
+    }
 
-    // Build and upload SyncManifest v2
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
+    let manifests_dir = layout.manifests_dir();
+    let entries = op
+        .list(&manifests_dir)
+        .await
+        .with_context(|| format!("listing manifests: {manifests_dir}"))?;
+    let mut referenced: HashMap<&str, u64> = HashMap::new();
+    let mut missing = HashSet::new();
+    let mut files = Vec::new();
+    for entry in entries.iter().filter(|e| !e.metadata().is_dir()) {
+        let data = op
+            .read(entry.path())
+            .await
+            .with_context(|| format!("reading manifest: {}", entry.path()))?;
+        let manifest = SyncManifest::from_bytes(&data.to_bytes())
+            .with_context(|| format!("parsing manifest: {}", entry.path()))?;
+        stats.manifests += 1;
+        stats.logical_bytes += manifest.file_size;
+        for hash in manifest.chunk_hashes() {
+            stats.chunk_refs += 1;
+            match stored.get_key_value(hash.as_str()) {
+                Some((hash, &len)) => {
+                    stats.referenced_bytes += len;
+                    referenced.insert(hash, len);
+                }
+                None => {
+                    missing.insert(hash.clone());
+                }
+            }
+        }
+        let name = manifest
+            .rel_path
+            .clone()
+            .unwrap_or_else(|| manifest.file_hash.clone());
+        files.push((name, manifest.file_size));
+    }
 
-    let manifest = SyncManifest {
-        version: 2,
-        file_hash: file_hash_hex.clone(),
-        file_size,
-        chunks: chunk_hashes,
-        vclock: local_vclock.clone(),
-        written_by: device_id.to_string(),
-        written_at: now,
-        rel_path: rel_path.map(|s| s.to_string()),
-        encrypted_file_key,
+    stats.unique_chunks = referenced.len();
+    stats.missing_chunks = missing.len();
+    let mut sizes: Vec<u64> = referenced.into_values().collect();
+    sizes.sort_unstable();
+    stats.unique_bytes = sizes.iter().sum();
+    stats.median_chunk = match sizes.len() {
+        0 => 0,
+        n if n % 2 == 1 => sizes[n / 2],
+        n => (sizes[n / 2 - 1] + sizes[n / 2]) / 2,
     };
+    let mut buckets: BTreeMap<u64, usize> = BTreeMap::new();
+    for len in &sizes {
+        *buckets.entry(len.max(&1).next_power_of_two()).or_default() += 1;
+    }
+    stats.histogram = buckets.into_iter().collect();
 
-    let manifest_bytes = manifest.to_bytes()?;
-    op.write(&remote_manifest, manifest_bytes)
+    files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    files.truncate(top);
+    stats.largest = files;
+    Ok(stats)
+}
+
+/// Count the bytes stored under `remote_prefix` once, if a quota is set and
+/// the state cache has no count yet; later uploads keep it current.
+async fn ensure_usage(op: &Operator, remote_prefix: &str, state: &StateCache) -> Result<()> {
+    if state.quota().is_none() || state.cached_usage(remote_prefix).is_some() {
+        return Ok(());
+    }
+    let used = prefix_usage(op, remote_prefix)
         .await
-        .with_context(|| format!("uploading manifest: {remote_manifest}"))?;
+        .context("measuring prefix usage for quota")?;
+    debug!(prefix = %remote_prefix, used, "counted prefix usage");
+    state.set_usage(remote_prefix, used);
+    Ok(())
+}
 
-    info!(
-        path = %local_path.display(),
-        hash = %file_hash_hex,
-        chunks = chunks.len(),
-        bytes = file_size,
-        uploaded_bytes = bytes_uploaded,
-        "uploaded"
-    );
+/// Count `bytes` about to be written under `remote_prefix` against the quota.
+///
+/// The charge is taken before the write so concurrent writers cannot
+/// overshoot the quota together. It is given back when the returned guard
+/// is dropped, as on a failed or abandoned write, unless
+/// [`QuotaCharge::keep`] is called once the write has landed.
+pub(crate) fn charge_quota<'a>(
+    state: &'a StateCache,
+    remote_prefix: &'a str,
+    bytes: u64,
+) -> Result<QuotaCharge<'a>> {
+    state
+        .charge_usage(remote_prefix, bytes)
+        .map(|_| QuotaCharge {
+            state,
+            prefix: remote_prefix,
+            bytes,
+        })
+        .map_err(|used| {
+            QuotaExceeded {
+                prefix: remote_prefix.to_string(),
+                quota: state.quota().unwrap_or_default(),
+                used,
+                needed: bytes,
+            }
+            .into()
+        })
+}
 
-    // Update state cache
-    let sync_state = make_sync_state_full(
-        local_path,
-        file_hash_hex.clone(),
-        chunks.len(),
-        remote_manifest.clone(),
-        local_vclock,
-        device_id.to_string(),
-    )?;
-    state.set(local_path, sync_state);
+/// Usage charged by [`charge_quota`] for a write still in progress.
+#[must_use = "dropping the charge refunds it"]
+pub(crate) struct QuotaCharge<'a> {
+    state: &'a StateCache,
+    prefix: &'a str,
+    bytes: u64,
+}
 
-    Ok(UploadResult {
-        path: local_path.to_path_buf(),
-        remote_path: remote_manifest,
-        hash: file_hash_hex,
-        chunks: chunks.len(),
-        bytes: file_size,
-        skipped: false,
-        outcome,
-    })
+impl QuotaCharge<'_> {
+    /// The write landed: leave the bytes counted.
+    pub(crate) fn keep(mut self) {
+        self.bytes = 0;
+    }
 }
 
-/// Download a file from SeaweedFS using its manifest path.
+impl Drop for QuotaCharge<'_> {
+    fn drop(&mut self) {
+        if self.bytes > 0 {
+            self.state.refund_usage(self.prefix, self.bytes);
+        }
+    }
+}
+
+/// Rebuild the chunk filter for `remote_prefix` if filters are enabled and it
+/// is missing, built at another rate, or saturated.
 ///
-/// Reads the manifest to get chunk hashes, fetches each chunk, reassembles
-/// and writes to `local_path`. Supports both v1 (text) and v2 (JSON) manifests.
-pub async fn download_file(
-    op: &Operator,
-    remote_manifest: &str,
+/// The filter is seeded from the chunk lists of manifests the state cache
+/// tracks under the prefix. Unreadable manifests are skipped; their chunks
+/// just fall back to an upload on the next miss.
+async fn ensure_chunk_filter(op: &Operator, remote_prefix: &str, state: &StateCache) {
+    let Some(fp_rate) = state.chunk_filter_fp_rate() else {
+        return;
+    };
+    if state.has_chunk_filter(remote_prefix) {
+        return;
+    }
+    let manifests = state.manifests_under(remote_prefix);
+
+    let expected: usize = manifests.iter().map(|(_, chunks)| chunks).sum();
+    let mut filter = ChunkFilter::new(expected.saturating_mul(2), fp_rate);
+    for (path, _) in &manifests {
+        let Ok(bytes) = op.read(path).await else {
+            continue;
+        };
+        if let Ok(manifest) = SyncManifest::from_bytes(&bytes.to_bytes()) {
+            for chunk in manifest.chunk_hashes() {
+                filter.insert(chunk);
+            }
+        }
+    }
+    debug!(prefix = %remote_prefix, manifests = manifests.len(), items = filter.items, "rebuilt chunk filter");
+
+    if !state.has_chunk_filter(remote_prefix) {
+        state.install_chunk_filter(remote_prefix, filter);
+    }
+}
+
+/// Whether the chunk `chunk_hash`, `len` bytes as stored, still needs
+/// uploading to `store`.
+///
+/// With a chunk filter loaded, a miss skips the `stat` round-trip (the
+/// chunk was never recorded, and re-writing a content-addressed object is
+/// harmless) while a hit is verified, since it may be a false positive. A
+/// stored object of the wrong length is a torn upload and is rewritten.
+async fn chunk_missing(
+    store: &ChunkStore,
+    remote_prefix: &str,
+    chunk_hash: &str,
+    len: usize,
+    state: &StateCache,
+) -> bool {
+    let known = state.chunk_known(remote_prefix, chunk_hash);
+    match known {
+        Some(false) => true,
+        Some(true) | None => !store
+            .has_intact_chunk(chunk_hash, len as u64)
+            .await
+            .unwrap_or(false),
+    }
+}
+
+/// Chunks of the version of `local_path` last synced to `store`, to reuse
+/// by hash when uploading a new version.
+///
+/// FastCDC boundaries resynchronise after an edit, so most chunks of a
+/// changed file are listed in the previous manifest. Listing does not prove
+/// a chunk is still stored (pruning or a damaged store may have removed
+/// it), so each one is still checked with [`chunk_missing`] before it is
+/// skipped. Returns `None` when there is
+/// no previous version under this prefix, its manifest is gone or fails to
+/// parse, or its chunks live at a different shard depth than new ones would.
+async fn previous_chunks(
+    store: &ChunkStore,
+    state: &StateCache,
     local_path: &Path,
+    file_hash: &str,
+) -> Option<tcfs_chunks::DeltaBase<String>> {
+    let prev = state.get(local_path)?;
+    if prev.blake3 == file_hash || prev.remote_path != store.manifest_key(&prev.blake3) {
+        return None;
+    }
+    let manifest = store.get_manifest(&prev.blake3).await.ok()?;
+    if manifest.chunk_shard_depth != store.layout().chunk_shard_depth() {
+        return None;
+    }
+    Some(tcfs_chunks::DeltaBase::new(manifest.chunks))
+}
+
+/// Sync a change that left a file's content alone: a new mtime, or a move
+/// or copy to `rel_path` of content the state cache already tracks.
+///
+/// The file is matched by size and BLAKE3 against its own state entry, or
+/// failing that against any tracked entry, whose manifest must live under
+/// `remote_prefix`. On a match the content's manifest
+/// gets the new `rel_path`, `written_at`, mode and a ticked vclock, the index
+/// entry for `rel_path` is rewritten and the state cache updated, without
+/// chunking or writing any chunk. A moved file's old entry is dropped. A copy
+/// (the tracked path still exists) shares the manifest as it stands, so only
+/// its index entry and state are written.
+///
+/// Returns `None` when the content is not known unchanged (or its manifest
+/// changed underneath), in which case a full upload is needed.
+pub async fn update_metadata(
+    op: &Operator,
     remote_prefix: &str,
-    progress: Option<&ProgressFn>,
-) -> Result<DownloadResult> {
-    download_file_with_device(
+    state: &StateCache,
+    local_path: &Path,
+    rel_path: Option<&str>,
+    device_id: &str,
+) -> Result<Option<UploadResult>> {
+    metadata_update(
         op,
-        remote_manifest,
-        local_path,
         remote_prefix,
-        progress,
-        "",
+        state,
+        local_path,
+        rel_path,
+        device_id,
         None,
         None,
     )
     .await
 }
 
-/// Download with device identity, vector clock merge, and optional decryption.
+/// [`update_metadata`], skipping the hash when the caller has already
+/// verified the file against its own entry. With a `stage`, the index entry
+/// is held there rather than written.
+#[allow(clippy::too_many_arguments)]
+async fn metadata_update(
+    op: &Operator,
+    remote_prefix: &str,
+    state: &StateCache,
+    local_path: &Path,
+    rel_path: Option<&str>,
+    device_id: &str,
+    verified_hash: Option<&str>,
+    stage: Option<&IndexStage>,
+) -> Result<Option<UploadResult>> {
+    let size = std::fs::metadata(local_path)
+        .with_context(|| format!("stat: {}", local_path.display()))?
+        .len();
+    let own = state.get(local_path).filter(|s| s.size == size);
+    if own.is_none() && state.find(|s| s.size == size).is_none() {
+        return Ok(None);
+    }
+    let hash_hex = match verified_hash {
+        Some(hash) => hash.to_string(),
+        None => tcfs_chunks::hash_to_hex(
+            &tcfs_chunks::hash_file(local_path)
+                .with_context(|| format!("hashing: {}", local_path.display()))?,
+        ),
+    };
+    // Only content already stored under this prefix counts
+    let expected_manifest = RemoteLayout::new(remote_prefix).manifest_key(&hash_hex);
+    let matches = |s: &SyncState| s.blake3 == hash_hex && s.remote_path == expected_manifest;
+    let (cached, moved_from) = match own.filter(|s| matches(s)) {
+        Some(own) => (own, None),
+        None => match state.find(|s| s.size == size && matches(s)) {
+            Some((key, other)) => (other, Some(key)),
+            None => return Ok(None),
+        },
+    };
+
+    let remote_manifest = cached.remote_path.clone();
+    let manifest_version = stat_version(op, &remote_manifest).await?;
+    if manifest_version == ObjectVersion::Absent {
+        return Ok(None);
+    }
+    let index_key = rel_path.map(|rel| RemoteLayout::new(remote_prefix).index_key(rel));
+    let index_version = match &index_key {
+        Some(key) => stat_version(op, key).await?,
+        None => ObjectVersion::Unknown,
+    };
+    let data = op
+        .read(&remote_manifest)
+        .await
+        .with_context(|| format!("reading manifest: {remote_manifest}"))?;
+    let mut manifest = SyncManifest::from_bytes(&data.to_bytes())
+        .with_context(|| format!("parsing manifest: {remote_manifest}"))?;
+    if manifest.file_hash != hash_hex {
+        return Ok(None);
+    }
+
+    let mut vclock = cached.vclock.clone();
+    vclock.merge(&manifest.vclock);
+    // The manifest is keyed by content, so a copy already has one
+    let copied = moved_from.as_deref().is_some_and(|p| Path::new(p).exists());
+    if !copied {
+        if !device_id.is_empty() {
+            vclock.tick(device_id);
+            manifest.written_by = device_id.to_string();
+        }
+        manifest.vclock = vclock.clone();
+        manifest.written_at = state.now_secs();
+        manifest.mode = file_mode(local_path);
+        if let Some(rel) = rel_path {
+            manifest.rel_path = Some(rel.to_string());
+        }
+        sign_manifest(&mut manifest, state)?;
+        match write_conditional_with(
+            op,
+            &remote_manifest,
+            manifest.to_bytes()?,
+            &manifest_version,
+            ObjectMeta::MANIFEST,
+        )
+        .await
+        {
+            Ok(()) => {}
+            Err(e) if e.is::<ConcurrentModification>() => {
+                debug!(path = %local_path.display(), "metadata update raced a writer; full upload");
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    // A move leaves the old path tombstoned, locally and in the index, so
+    // neither this device nor any other brings the file back there
+    let moved_rel = match (moved_from.as_deref(), copied, rel_path) {
+        (Some(old), false, Some(rel)) => moved_from_rel(local_path, rel, Path::new(old)),
+        _ => None,
+    };
+    let sync_state = make_sync_state_at(
+        local_path,
+        hash_hex.clone(),
+        cached.chunk_count,
+        remote_manifest.clone(),
+        vclock.clone(),
+        device_id.to_string(),
+        state.now_secs(),
+    )?;
+    let mtime = sync_state.mtime;
+    state.set(local_path, sync_state);
+    if let (Some(old), false) = (moved_from.as_deref(), copied) {
+        state.remove(Path::new(old));
+        state.set_tombstone(Path::new(old), vclock);
+    }
+
+    if let Some(index_key) = index_key {
+        let mut index_entry = IndexEntry::new(&hash_hex, size, cached.chunk_count, Some(mtime));
+        index_entry.mode = file_mode(local_path);
+        let written = put_index(
+            op,
+            stage,
+            &index_key,
+            index_entry.to_bytes(),
+            &index_version,
+        )
+        .await;
+        if let Err(e) = written {
+            if e.is::<ConcurrentModification>() {
+                state.remove(local_path);
+            }
+            return Err(e).with_context(|| format!("writing index entry: {index_key}"));
+        }
+    }
+    if let Some(old_rel) = moved_rel {
+        let old_key = RemoteLayout::new(remote_prefix).index_key(&old_rel);
+        let tombstone = IndexEntry::new_tombstone(Some(state.now_secs()));
+        put_index(
+            op,
+            stage,
+            &old_key,
+            tombstone.to_bytes(),
+            &ObjectVersion::Unknown,
+        )
+        .await
+        .with_context(|| format!("writing index tombstone: {old_key}"))?;
+    }
+
+    debug!(path = %local_path.display(), moved = moved_from.is_some(), copied, "metadata-only sync");
+    Ok(Some(UploadResult {
+        path: local_path.to_path_buf(),
+        remote_path: remote_manifest,
+        hash: hash_hex,
+        chunks: cached.chunk_count,
+        bytes: size,
+        skipped: false,
+        outcome: None,
+        new_chunks: 0,
+        deduped_chunks: cached.chunk_count,
+        reused_chunks: 0,
+        dry_run: false,
+    }))
+}
+
+/// Upload body shared by single-file and concurrent tree pushes.
+///
+/// The state cache locks internally per entry, so several uploads can be in
+/// flight at once against the same cache. Index entries written along the
+/// way (metadata-only syncs) go to `stage` when one is given.
 #[allow(unused_variables)]
-pub async fn download_file_with_device(
+#[allow(clippy::too_many_arguments)]
+async fn upload_file_shared(
     op: &Operator,
-    remote_manifest: &str,
     local_path: &Path,
     remote_prefix: &str,
-    progress: Option<&ProgressFn>,
-    _device_id: &str,
-    state: Option<&mut StateCache>,
+    state: &StateCache,
+    progress: UploadProgress<'_>,
+    device_id: &str,
+    rel_path: Option<&str>,
     encryption: OptionalEncryption<'_>,
-) -> Result<DownloadResult> {
-    // Read manifest
-    let manifest_bytes = op
-        .read(remote_manifest)
-        .await
-        .with_context(|| format!("reading manifest: {remote_manifest}"))?;
+    compress_skip: Option<&[String]>,
+    dry_run: bool,
+    stage: Option<&IndexStage>,
+) -> Result<UploadResult> {
+    if state.is_read_only() && !dry_run {
+        return Err(ReadOnlyStore {
+            prefix: remote_prefix.to_string(),
+        }
+        .into());
+    }
 
-    let manifest = SyncManifest::from_bytes(&manifest_bytes.to_bytes())
-        .with_context(|| format!("parsing manifest: {remote_manifest}"))?;
+    // Fast-path: check if file is already up-to-date
+    {
+        match state.needs_sync(local_path)? {
+            None => {
+                let cached = state.get(local_path).unwrap();
+                // Same content under a new mtime: sync the metadata only
+                let mtime = std::fs::metadata(local_path)
+                    .ok()
+                    .and_then(|m| m.modified().ok())
+                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0);
+                let touched = mtime != cached.mtime;
+                if touched && !dry_run {
+                    if let Some(result) = metadata_update(
+                        op,
+                        remote_prefix,
+                        state,
+                        local_path,
+                        rel_path,
+                        device_id,
+                        Some(&cached.blake3),
+                        stage,
+                    )
+                    .await?
+                    {
+                        return Ok(result);
+                    }
+                }
+                let result = UploadResult {
+                    path: local_path.to_path_buf(),
+                    remote_path: cached.remote_path.clone(),
+                    hash: cached.blake3.clone(),
+                    chunks: cached.chunk_count,
+                    bytes: cached.size,
+                    skipped: true,
+                    outcome: Some(SyncOutcome::UpToDate),
+                    new_chunks: 0,
+                    deduped_chunks: 0,
+                    reused_chunks: 0,
+                    dry_run,
+                };
+                debug!(path = %local_path.display(), "skip: unchanged since last sync");
+                return Ok(result);
+            }
+            Some(reason) => {
+                // A new path may be a move or copy of tracked content
+                if state.get(local_path).is_none() && !dry_run {
+                    if let Some(result) = metadata_update(
+                        op,
+                        remote_prefix,
+                        state,
+                        local_path,
+                        rel_path,
+                        device_id,
+                        None,
+                        stage,
+                    )
+                    .await?
+                    {
+                        return Ok(result);
+                    }
+                }
+                debug!(path = %local_path.display(), reason = %reason, "uploading");
+            }
+        }
+    }
 
-    let chunk_hashes = manifest.chunk_hashes();
+    // Chunk the file
+    let (chunks, data) = tcfs_chunks::chunk_file(local_path)
+        .with_context(|| format!("chunking: {}", local_path.display()))?;
 
-    if chunk_hashes.is_empty() {
-        anyhow::bail!("manifest is empty: {remote_manifest}");
+    let file_size = data.len() as u64;
+    let file_hash = tcfs_chunks::hash_bytes(&data);
+    let file_hash_hex = tcfs_chunks::hash_to_hex(&file_hash);
+    let compress = should_compress_with(
+        local_path,
+        &data[..data.len().min(COMPRESSION_SAMPLE_BYTES)],
+        compress_skip,
+    );
+
+    // Build remote manifest path (using the file's content hash)
+    let store = ChunkStore::new(
+        op.clone(),
+        RemoteLayout::new(remote_prefix).with_chunk_shard_depth(state.chunk_shard_depth()),
+    )
+    .with_cache_control(state.cache_control().as_deref());
+    let remote_manifest = store.manifest_key(&file_hash_hex);
+
+    // Get the local vclock from state (or start fresh)
+    let mut local_vclock = state
+        .get(local_path)
+        .map(|s| s.vclock.clone())
+        .unwrap_or_default();
+
+    // Record the remote manifest's version so the final write can be conditional
+    let mut manifest_version = stat_version(op, &remote_manifest).await?;
+
+    // Check if remote manifest exists for conflict detection
+    let mut outcome = None;
+    if !device_id.is_empty() && manifest_version != ObjectVersion::Absent {
+        if let Ok(remote_manifest_obj) = store.get_manifest(&file_hash_hex).await {
+            // Ordering below is by vector clock only; a future stamp is
+            // flagged so the writer's clock gets fixed
+            let now = state.now_secs();
+            if let Some(ahead) = remote_manifest_obj.clock_skew(now, state.max_clock_skew()) {
+                warn!(
+                    manifest = %remote_manifest,
+                    writer = %remote_manifest_obj.written_by,
+                    ahead_secs = ahead,
+                    "remote manifest is stamped in the future; ignoring its timestamp"
+                );
+            }
+            let local_hash = &file_hash_hex;
+            let remote_hash = &remote_manifest_obj.file_hash;
+            let rp = rel_path.unwrap_or("");
+
+            let sync_outcome = compare_clocks_at(
+                &local_vclock,
+                &remote_manifest_obj.vclock,
+                local_hash,
+                remote_hash,
+                rp,
+                device_id,
+                &remote_manifest_obj.written_by,
+                state.clock().as_ref(),
+            );
+
+            match &sync_outcome {
+                SyncOutcome::RemoteNewer => {
+                    return Ok(UploadResult {
+                        path: local_path.to_path_buf(),
+                        remote_path: remote_manifest.clone(),
+                        hash: file_hash_hex,
+                        chunks: chunks.len(),
+                        bytes: file_size,
+                        skipped: true,
+                        outcome: Some(sync_outcome),
+                        new_chunks: 0,
+                        deduped_chunks: 0,
+                        reused_chunks: 0,
+                        dry_run,
+                    });
+                }
+                SyncOutcome::Conflict(_) => {
+                    return Ok(UploadResult {
+                        path: local_path.to_path_buf(),
+                        remote_path: remote_manifest.clone(),
+                        hash: file_hash_hex,
+                        chunks: chunks.len(),
+                        bytes: file_size,
+                        skipped: true,
+                        outcome: Some(sync_outcome),
+                        new_chunks: 0,
+                        deduped_chunks: 0,
+                        reused_chunks: 0,
+                        dry_run,
+                    });
+                }
+                SyncOutcome::UpToDate => {
+                    // Content dedup — already up to date
+                    if !dry_run {
+                        let sync_state = make_sync_state_at(
+                            local_path,
+                            file_hash_hex.clone(),
+                            chunks.len(),
+                            remote_manifest.clone(),
+                            local_vclock,
+                            device_id.to_string(),
+                            state.now_secs(),
+                        )?;
+                        state.set(local_path, sync_state);
+                    }
+                    return Ok(UploadResult {
+                        path: local_path.to_path_buf(),
+                        remote_path: remote_manifest,
+                        hash: file_hash_hex,
+                        chunks: chunks.len(),
+                        bytes: file_size,
+                        skipped: true,
+                        outcome: Some(sync_outcome),
+                        new_chunks: 0,
+                        deduped_chunks: chunks.len(),
+                        reused_chunks: 0,
+                        dry_run,
+                    });
+                }
+                SyncOutcome::LocalNewer => {
+                    // Merge remote vclock into local before writing
+                    local_vclock.merge(&remote_manifest_obj.vclock);
+                    outcome = Some(SyncOutcome::LocalNewer);
+                }
+            }
+        }
     }
 
-    // Unwrap file key if manifest is encrypted
+    // Check if this exact content is already stored (content-addressed dedup)
+    // Only check when we haven't already done the remote manifest check above
+    if outcome.is_none() && manifest_version != ObjectVersion::Absent && device_id.is_empty() {
+        debug!(hash = %file_hash_hex, "dedup: manifest already exists");
+        let remote_path = remote_manifest.clone();
+        if !dry_run {
+            let sync_state = make_sync_state_at(
+                local_path,
+                file_hash_hex.clone(),
+                chunks.len(),
+                remote_path.clone(),
+                local_vclock,
+                device_id.to_string(),
+                state.now_secs(),
+            )?;
+            state.set(local_path, sync_state);
+        }
+        return Ok(UploadResult {
+            path: local_path.to_path_buf(),
+            remote_path,
+            hash: file_hash_hex,
+            chunks: chunks.len(),
+            bytes: file_size,
+            skipped: false,
+            outcome: None,
+            new_chunks: 0,
+            deduped_chunks: chunks.len(),
+            reused_chunks: 0,
+            dry_run,
+        });
+    }
+
+    ensure_chunk_filter(op, remote_prefix, state).await;
+
+    let previous = previous_chunks(&store, state, local_path, &file_hash_hex).await;
+
+    // Dry run: estimate new vs. deduped chunks without writing anything.
+    // Encrypted chunks are keyed by ciphertext hash under a fresh file key,
+    // so they never dedup against existing objects.
+    if dry_run {
+        let mut new_chunks = 0usize;
+        let mut reused_chunks = 0usize;
+        for chunk in &chunks {
+            let chunk_data = &data[chunk.offset as usize..chunk.offset as usize + chunk.length];
+            let compressed = if compress {
+                compress_chunk(chunk_data)?
+            } else {
+                None
+            };
+            let (stored, chunk_hash_hex) = stored_chunk(chunk, chunk_data, compressed);
+            if encryption.is_some()
+                || chunk_missing(&store, remote_prefix, &chunk_hash_hex, stored.len(), state).await
+            {
+                new_chunks += 1;
+            } else if previous
+                .as_ref()
+                .is_some_and(|base| base.find(&chunk_hash_hex).is_some())
+            {
+                reused_chunks += 1;
+            }
+        }
+        debug!(path = %local_path.display(), new_chunks, "dry run: would upload");
+        return Ok(UploadResult {
+            path: local_path.to_path_buf(),
+            remote_path: remote_manifest,
+            hash: file_hash_hex,
+            chunks: chunks.len(),
+            bytes: file_size,
+            skipped: false,
+            outcome,
+            new_chunks,
+            deduped_chunks: chunks.len() - new_chunks,
+            reused_chunks,
+            dry_run,
+        });
+    }
+
+    ensure_usage(op, remote_prefix, state).await?;
+
+    // Tick local vclock before writing
+    if !device_id.is_empty() {
+        local_vclock.tick(device_id);
+    }
+
+    // Generate per-file encryption key if encryption is enabled; convergent
+    // mode keys each chunk separately instead
     #[cfg(feature = "crypto")]
-    let file_key = if let Some(ref wrapped_b64) = manifest.encrypted_file_key {
-        let ctx = encryption.ok_or_else(|| {
-            anyhow::anyhow!(
-                "manifest is encrypted but no encryption context provided for: {remote_manifest}"
-            )
-        })?;
-        let wrapped =
-            base64::Engine::decode(&base64::engine::general_purpose::STANDARD, wrapped_b64)
-                .context("decoding wrapped file key from manifest")?;
-        Some(
-            tcfs_crypto::unwrap_key(&ctx.master_key, &wrapped)
-                .context("unwrapping file key from manifest")?,
-        )
+    let convergent = encryption.filter(|ctx| ctx.convergent);
+    #[cfg(feature = "crypto")]
+    let (file_key, file_id) = if encryption.is_some() && convergent.is_none() {
+        let fk = tcfs_crypto::generate_file_key();
+        // Use the plaintext file hash as the file_id for AAD binding
+        let fid: [u8; 32] = {
+            let hash = tcfs_chunks::hash_from_hex(&file_hash_hex)
+                .context("parsing file hash for encryption file_id")?;
+            *hash.as_bytes()
+        };
+        (Some(fk), Some(fid))
     } else {
-        None
+        (None, None)
     };
 
+    // Upload each chunk (skip if already present — dedup by chunk hash),
+    // keeping as many in flight as the transfer controller allows
+    let total_chunks = chunks.len();
+    let chunks_done = AtomicUsize::new(0);
+    let bytes_done = std::sync::atomic::AtomicU64::new(0);
+    let stored = crate::adaptive::run_adaptive(
+        state.transfer_concurrency(),
+        chunks.iter().enumerate(),
+        |(i, chunk)| {
+            let (chunks_done, bytes_done, store, data, previous) =
+                (&chunks_done, &bytes_done, &store, &data, &previous);
+            #[cfg(feature = "crypto")]
+            let (file_key, file_id) = (&file_key, &file_id);
+            async move {
+                let chunk_data = &data[chunk.offset as usize..chunk.offset as usize + chunk.length];
+
+                // Compress before encrypting; incompressible chunks are stored raw
+                let compressed = if compress {
+                    compress_chunk(chunk_data).with_context(|| format!("compressing chunk {i}"))?
+                } else {
+                    None
+                };
+                let is_compressed = compressed.is_some();
+                #[allow(unused_mut)]
+                let mut wrapped_key = None;
+
+                // Encrypt chunk if encryption is enabled
+                #[cfg(feature = "crypto")]
+                let (upload_data, chunk_hash_hex) = if let Some(ctx) = convergent {
+                    let plaintext = compressed.as_deref().unwrap_or(chunk_data);
+                    let (chunk_key, ciphertext) =
+                        tcfs_crypto::encrypt_chunk_convergent(&ctx.master_key, plaintext)
+                            .with_context(|| format!("encrypting chunk {i}"))?;
+                    wrapped_key = Some(
+                        wrap_key_b64(&ctx.master_key, &chunk_key)
+                            .with_context(|| format!("wrapping key for chunk {i}"))?,
+                    );
+                    let ct_hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&ciphertext));
+                    (ciphertext, ct_hash)
+                } else if let (Some(fk), Some(fid)) = (file_key, file_id) {
+                    let plaintext = compressed.as_deref().unwrap_or(chunk_data);
+                    let ciphertext = tcfs_crypto::encrypt_chunk(fk, i as u64, fid, plaintext)
+                        .with_context(|| format!("encrypting chunk {i}"))?;
+                    // CAS key is ciphertext hash (not plaintext hash)
+                    let ct_hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&ciphertext));
+                    (ciphertext, ct_hash)
+                } else {
+                    stored_chunk(chunk, chunk_data, compressed)
+                };
+
+                #[cfg(not(feature = "crypto"))]
+                let (upload_data, chunk_hash_hex) = stored_chunk(chunk, chunk_data, compressed);
+
+                // Even a chunk of the previous version is checked against the
+                // remote: it may have been pruned since that version synced
+                let len = upload_data.len();
+                let missing =
+                    chunk_missing(store, remote_prefix, &chunk_hash_hex, len, state).await;
+                let reused = !missing
+                    && previous
+                        .as_ref()
+                        .is_some_and(|base| base.find(&chunk_hash_hex).is_some());
+                let mut moved = 0u64;
+                if missing {
+                    moved = upload_data.len() as u64;
+                    let charge = charge_quota(state, remote_prefix, moved)?;
+                    store
+                        .put_chunk(&chunk_hash_hex, upload_data)
+                        .await
+                        .with_context(|| format!("uploading chunk {i}"))?;
+                    charge.keep();
+                }
+                state.record_chunk(remote_prefix, &chunk_hash_hex);
+
+                let n = chunks_done.fetch_add(1, Ordering::Relaxed) + 1;
+                let done = bytes_done.fetch_add(chunk.length as u64, Ordering::Relaxed)
+                    + chunk.length as u64;
+                let msg = format!("chunk {n}/{total_chunks}");
+                if let Some(cb) = progress.chunks {
+                    cb(n as u64, total_chunks as u64, &msg);
+                }
+                if let Some(cb) = progress.bytes {
+                    cb(done, file_size, &msg);
+                }
+
+                let stored = StoredChunk {
+                    hash: chunk_hash_hex,
+                    compressed: is_compressed,
+                    wrapped_key,
+                    uploaded: moved > 0,
+                    reused,
+                };
+                Ok((stored, moved))
+            }
+        },
+    )
+    .await?;
+
+    let mut chunk_hashes = Vec::with_capacity(chunks.len());
+    let mut compressed_flags = Vec::with_capacity(chunks.len());
+    let mut chunk_keys = Vec::new();
+    let mut bytes_uploaded = 0u64;
+    let mut new_chunks = 0usize;
+    let mut reused_chunks = 0usize;
+    for (chunk, stored) in chunks.iter().zip(stored) {
+        if stored.uploaded {
+            bytes_uploaded += chunk.length as u64;
+            new_chunks += 1;
+        }
+        if stored.reused {
+            reused_chunks += 1;
+        }
+        chunk_keys.extend(stored.wrapped_key);
+        chunk_hashes.push(stored.hash);
+        compressed_flags.push(stored.compressed);
+    }
+
+    // Wrap file key for manifest if encryption is enabled
     #[cfg(feature = "crypto")]
-    let file_id: Option<[u8; 32]> = if file_key.is_some() {
-        let hash = tcfs_chunks::hash_from_hex(&manifest.file_hash)
-            .context("parsing manifest file_hash for decryption file_id")?;
-        Some(*hash.as_bytes())
+    let encrypted_file_key = if let (Some(ctx), Some(ref fk)) = (encryption, &file_key) {
+        Some(wrap_key_b64(&ctx.master_key, fk).context("wrapping file key")?)
     } else {
         None
     };
 
-    // Fetch and reassemble chunks, verifying each chunk's BLAKE3 hash
-    let mut assembled = Vec::new();
-    let total = chunk_hashes.len();
+    #[cfg(not(feature = "crypto"))]
+    let encrypted_file_key: Option<String> = None;
+
+    // Build and upload SyncManifest v2
+    let now = state.now_secs();
+
+    let mut manifest = SyncManifest {
+        version: 2,
+        file_hash: file_hash_hex.clone(),
+        file_size,
+        chunks: chunk_hashes,
+        vclock: local_vclock.clone(),
+        written_by: device_id.to_string(),
+        written_at: now,
+        rel_path: rel_path.map(|s| s.to_string()),
+        encrypted_file_key,
+        mode: file_mode(local_path),
+        compressed: compressed_flags,
+        chunk_keys,
+        chunk_shard_depth: store.layout().chunk_shard_depth(),
+        signature: None,
+        manifest_checksum: None,
+    };
+
+    // Conditional write: if another writer replaced the manifest since we
+    // looked at it, re-read it and re-run the clock comparison instead of
+    // clobbering their update. The manifest is only re-signed if that
+    // comparison changes its clock.
+    sign_manifest(&mut manifest, state)?;
+    let mut manifest_bytes = opendal::Buffer::from(manifest.to_bytes()?);
+    let charge = match manifest_version {
+        ObjectVersion::Absent => Some(charge_quota(
+            state,
+            remote_prefix,
+            manifest_bytes.len() as u64,
+        )?),
+        _ => None,
+    };
+    let mut attempt = 0;
+    loop {
+        let written = write_conditional_with(
+            op,
+            &remote_manifest,
+            manifest_bytes.clone(),
+            &manifest_version,
+            ObjectMeta::MANIFEST,
+        );
+        match written.await {
+            Ok(()) => break,
+            Err(e) if e.is::<ConcurrentModification>() && attempt < MAX_CAS_RETRIES => {
+                attempt += 1;
+                warn!(manifest = %remote_manifest, attempt, "manifest modified concurrently, re-checking");
+                manifest_version = stat_version(op, &remote_manifest).await?;
+
+                let Ok(remote) = store.get_manifest(&file_hash_hex).await else {
+                    // Gone or unreadable — retry against the fresh version
+                    continue;
+                };
+
+                let sync_outcome = compare_clocks_at(
+                    &manifest.vclock,
+                    &remote.vclock,
+                    &file_hash_hex,
+                    &remote.file_hash,
+                    rel_path.unwrap_or(""),
+                    device_id,
+                    &remote.written_by,
+                    state.clock().as_ref(),
+                );
+                match sync_outcome {
+                    SyncOutcome::LocalNewer => {
+                        manifest.vclock.merge(&remote.vclock);
+                        sign_manifest(&mut manifest, state)?;
+                        manifest_bytes = manifest.to_bytes()?.into();
+                        outcome = Some(SyncOutcome::LocalNewer);
+                    }
+                    other => {
+                        // The racing writer won; keep their manifest
+                        if matches!(other, SyncOutcome::UpToDate) {
+                            let mut vclock = manifest.vclock.clone();
+                            vclock.merge(&remote.vclock);
+                            let sync_state = make_sync_state_at(
+                                local_path,
+                                file_hash_hex.clone(),
+                                chunks.len(),
+                                remote_manifest.clone(),
+                                vclock,
+                                device_id.to_string(),
+                                state.now_secs(),
+                            )?;
+                            state.set(local_path, sync_state);
+                        }
+                        return Ok(UploadResult {
+                            path: local_path.to_path_buf(),
+                            remote_path: remote_manifest,
+                            hash: file_hash_hex,
+                            chunks: chunks.len(),
+                            bytes: file_size,
+                            skipped: true,
+                            outcome: Some(other),
+                            new_chunks,
+                            deduped_chunks: chunks.len() - new_chunks,
+                            reused_chunks,
+                            dry_run: false,
+                        });
+                    }
+                }
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("uploading manifest: {remote_manifest}"))
+            }
+        }
+    }
+    if let Some(charge) = charge {
+        charge.keep();
+    }
+    let local_vclock = manifest.vclock.clone();
+
+    info!(
+        path = %local_path.display(),
+        hash = %file_hash_hex,
+        chunks = chunks.len(),
+        bytes = file_size,
+        uploaded_bytes = bytes_uploaded,
+        new_chunks,
+        reused_chunks,
+        "uploaded"
+    );
+
+    // Update state cache
+    let sync_state = make_sync_state_at(
+        local_path,
+        file_hash_hex.clone(),
+        chunks.len(),
+        remote_manifest.clone(),
+        local_vclock,
+        device_id.to_string(),
+        state.now_secs(),
+    )?;
+    state.set(local_path, sync_state);
+
+    Ok(UploadResult {
+        path: local_path.to_path_buf(),
+        remote_path: remote_manifest,
+        hash: file_hash_hex,
+        chunks: chunks.len(),
+        bytes: file_size,
+        skipped: false,
+        outcome,
+        new_chunks,
+        deduped_chunks: chunks.len() - new_chunks,
+        reused_chunks,
+        dry_run: false,
+    })
+}
+
+/// Fetch the chunks listed in `manifest` and return the reassembled plaintext.
+///
+/// Each chunk's BLAKE3 hash is checked against the manifest, then the chunk is
+/// decrypted (when the manifest carries a wrapped file key) and decompressed.
+/// An encrypted manifest without an `encryption` context fails with
+/// [`KeyRequired`]. The whole-file hash is left to the caller.
+pub async fn assemble_chunks(
+    op: &Operator,
+    manifest: &SyncManifest,
+    remote_manifest: &str,
+    remote_prefix: &str,
+    encryption: OptionalEncryption<'_>,
+    progress: Option<&ProgressFn>,
+) -> Result<Vec<u8>> {
+    let transfer = crate::adaptive::AdaptiveConcurrency::default();
+    assemble_chunks_with(
+        op,
+        manifest,
+        remote_manifest,
+        remote_prefix,
+        encryption,
+        progress,
+        &transfer,
+    )
+    .await
+}
+
+/// [`assemble_chunks`] fetching chunks under the given transfer controller.
+pub async fn assemble_chunks_with(
+    op: &Operator,
+    manifest: &SyncManifest,
+    remote_manifest: &str,
+    remote_prefix: &str,
+    encryption: OptionalEncryption<'_>,
+    progress: Option<&ProgressFn>,
+    transfer: &crate::adaptive::AdaptiveConcurrency,
+) -> Result<Vec<u8>> {
+    let mut assembled = Vec::with_capacity(manifest.file_size as usize);
+    stream_chunks_with(
+        op,
+        manifest,
+        remote_manifest,
+        remote_prefix,
+        encryption,
+        progress,
+        transfer,
+        &crate::store::ReadMirrors::default(),
+        0,
+        |plaintext| {
+            assembled.extend_from_slice(&plaintext);
+            Ok(())
+        },
+    )
+    .await?;
+    Ok(assembled)
+}
+
+/// Write the file behind `remote_manifest` to `out` one chunk at a time,
+/// for `tcfs cat` of files too large to hold in memory.
+///
+/// Chunks are fetched serially, so only one is resident. Each stored chunk is
+/// a single AEAD unit and, when compressed, a single zstd frame (chunks are
+/// at most `ChunkSizes::PACK.max_size`, below the seekable frame size): it
+/// is verified, decrypted, decompressed, written and dropped before the
+/// next is fetched. The whole-file hash is checked once everything is
+/// written; on a mismatch the output is already out and the call fails with
+/// [`FileHashMismatch`].
+///
+/// Returns the number of bytes written.
+pub async fn stream_file(
+    op: &Operator,
+    remote_manifest: &str,
+    remote_prefix: &str,
+    encryption: OptionalEncryption<'_>,
+    out: &mut impl std::io::Write,
+) -> Result<u64> {
+    let data = op
+        .read(remote_manifest)
+        .await
+        .with_context(|| format!("reading manifest: {remote_manifest}"))?;
+    let manifest = SyncManifest::from_bytes(&data.to_bytes())
+        .with_context(|| format!("parsing manifest: {remote_manifest}"))?;
+
+    let serial = crate::adaptive::AdaptiveConcurrency::new(1, 1);
+    let mut hasher = tcfs_chunks::Hasher::new();
+    let mut bytes = 0u64;
+    stream_chunks_with(
+        op,
+        &manifest,
+        remote_manifest,
+        remote_prefix,
+        encryption,
+        None,
+        &serial,
+        &crate::store::ReadMirrors::default(),
+        0,
+        |plaintext| {
+            out.write_all(&plaintext).context("writing output")?;
+            hasher.update(&plaintext);
+            bytes += plaintext.len() as u64;
+            Ok(())
+        },
+    )
+    .await?;
+    out.flush().context("flushing output")?;
+
+    let actual = tcfs_chunks::hash_to_hex(&hasher.finalize());
+    if actual != manifest.file_hash {
+        return Err(FileHashMismatch {
+            manifest: remote_manifest.to_string(),
+            expected: manifest.file_hash,
+            actual,
+        }
+        .into());
+    }
+    Ok(bytes)
+}
+
+/// Fetch the chunks listed in `manifest`, handing each verified, decrypted
+/// and decompressed chunk to `sink` in file order as soon as it and every
+/// chunk before it have arrived.
+///
+/// Checks are those of [`assemble_chunks`]. At most the transfer limit's
+/// worth of chunks is held in memory, so `sink` can write a file of any
+/// size without buffering it whole. The progress callback counts chunks
+/// handed to `sink`. Chunks missing from `op` are read from `mirrors`.
+///
+/// Chunks before index `first` are neither fetched nor handed to `sink`;
+/// a resumed download passes the number it already holds.
+#[allow(unused_variables)]
+#[allow(clippy::too_many_arguments)]
+pub async fn stream_chunks_with(
+    op: &Operator,
+    manifest: &SyncManifest,
+    remote_manifest: &str,
+    remote_prefix: &str,
+    encryption: OptionalEncryption<'_>,
+    progress: Option<&ProgressFn>,
+    transfer: &crate::adaptive::AdaptiveConcurrency,
+    mirrors: &crate::store::ReadMirrors,
+    first: usize,
+    mut sink: impl FnMut(Vec<u8>) -> Result<()>,
+) -> Result<()> {
+    let chunk_hashes = manifest.chunk_hashes();
+
+    // Unwrap file key if manifest is encrypted
+    #[cfg(feature = "crypto")]
+    let file_key = if let Some(ref wrapped_b64) = manifest.encrypted_file_key {
+        let ctx = encryption.ok_or_else(|| KeyRequired {
+            manifest: remote_manifest.to_string(),
+        })?;
+        let wrapped =
+            base64::Engine::decode(&base64::engine::general_purpose::STANDARD, wrapped_b64)
+                .context("decoding wrapped file key from manifest")?;
+        Some(
+            tcfs_crypto::unwrap_key(&ctx.master_key, &wrapped)
+                .context("unwrapping file key from manifest")?,
+        )
+    } else {
+        None
+    };
+
+    // Convergent manifests carry one wrapped key per chunk instead
+    #[cfg(feature = "crypto")]
+    let chunk_keys = if manifest.is_convergent() {
+        let ctx = encryption.ok_or_else(|| KeyRequired {
+            manifest: remote_manifest.to_string(),
+        })?;
+        if manifest.chunk_keys.len() != chunk_hashes.len() {
+            anyhow::bail!(
+                "manifest {remote_manifest} has {} chunk keys for {} chunks",
+                manifest.chunk_keys.len(),
+                chunk_hashes.len()
+            );
+        }
+        manifest
+            .chunk_keys
+            .iter()
+            .enumerate()
+            .map(|(i, k)| {
+                unwrap_key_b64(&ctx.master_key, k)
+                    .with_context(|| format!("unwrapping key for chunk {i}"))
+            })
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        Vec::new()
+    };
+
+    #[cfg(feature = "crypto")]
+    let file_id: Option<[u8; 32]> = if file_key.is_some() {
+        let hash = tcfs_chunks::hash_from_hex(&manifest.file_hash)
+            .context("parsing manifest file_hash for decryption file_id")?;
+        Some(*hash.as_bytes())
+    } else {
+        None
+    };
+
+    // Fetch chunks, verifying each chunk's BLAKE3 hash
+    let total = chunk_hashes.len();
+    let mut done = first.min(total);
+    let store = ChunkStore::new(op.clone(), manifest.chunk_layout(remote_prefix))
+        .with_mirrors(mirrors.clone());
+    crate::adaptive::run_adaptive_ordered(
+        transfer,
+        chunk_hashes.iter().enumerate().skip(first),
+        |(i, hash)| {
+            let store = &store;
+            #[cfg(feature = "crypto")]
+            let (file_key, file_id, chunk_keys) = (&file_key, &file_id, &chunk_keys);
+            async move {
+                // The store checks the chunk's BLAKE3 against the manifest entry
+                let chunk_bytes = store.get_chunk(hash).await?;
+                let moved = chunk_bytes.len() as u64;
+
+                // Decrypt chunk if file key is present
+                #[cfg(feature = "crypto")]
+                let plaintext = if let Some(key) = chunk_keys.get(i) {
+                    tcfs_crypto::decrypt_chunk_convergent(key, &chunk_bytes)
+                        .with_context(|| format!("decrypting chunk {i}"))?
+                } else if let (Some(fk), Some(fid)) = (file_key, file_id) {
+                    tcfs_crypto::decrypt_chunk(fk, i as u64, fid, &chunk_bytes)
+                        .with_context(|| format!("decrypting chunk {i}"))?
+                } else {
+                    chunk_bytes
+                };
+
+                #[cfg(not(feature = "crypto"))]
+                let plaintext = chunk_bytes;
+
+                let plaintext = if manifest.chunk_compressed(i) {
+                    tcfs_chunks::decompress_frames(&plaintext)
+                        .with_context(|| format!("decompressing chunk {i}"))?
+                } else {
+                    plaintext
+                };
+
+                Ok((plaintext, moved))
+            }
+        },
+        |plaintext| {
+            sink(plaintext)?;
+            done += 1;
+            if let Some(cb) = progress {
+                cb(done as u64, total as u64, &format!("chunk {done}/{total}"));
+            }
+            Ok(())
+        },
+    )
+    .await
+}
+
+/// Fail with [`LocallyModified`] if `local_path` exists with content that is
+/// neither `incoming_hash` nor the hash `state` recorded at its last sync.
+pub fn check_local_edits(state: &StateCache, local_path: &Path, incoming_hash: &str) -> Result<()> {
+    if !local_path.is_file() {
+        return Ok(());
+    }
+    let local_hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_file(local_path)?);
+    if local_hash == incoming_hash {
+        return Ok(());
+    }
+    let synced_hash = state.get(local_path).map(|s| s.blake3);
+    if synced_hash.as_deref() == Some(local_hash.as_str()) {
+        return Ok(());
+    }
+    Err(LocallyModified {
+        path: local_path.to_path_buf(),
+        local_hash,
+        synced_hash,
+    }
+    .into())
+}
+
+/// Download a file from SeaweedFS using its manifest path.
+///
+/// Reads the manifest to get chunk hashes, fetches each chunk, reassembles
+/// and writes to `local_path`. Supports both v1 (text) and v2 (JSON) manifests.
+pub async fn download_file(
+    op: &Operator,
+    remote_manifest: &str,
+    local_path: &Path,
+    remote_prefix: &str,
+    progress: Option<&ProgressFn>,
+) -> Result<DownloadResult> {
+    download_file_with_device(
+        op,
+        remote_manifest,
+        local_path,
+        remote_prefix,
+        progress,
+        "",
+        None,
+        None,
+        RestoreMode::default(),
+    )
+    .await
+}
+
+/// Download with device identity, vector clock merge, and optional decryption.
+///
+/// The file mode is restored as `mode` gives it (see [`RestoreMode`]).
+#[allow(clippy::too_many_arguments)]
+pub async fn download_file_with_device(
+    op: &Operator,
+    remote_manifest: &str,
+    local_path: &Path,
+    remote_prefix: &str,
+    progress: Option<&ProgressFn>,
+    device_id: &str,
+    state: Option<&StateCache>,
+    encryption: OptionalEncryption<'_>,
+    mode: RestoreMode,
+) -> Result<DownloadResult> {
+    let progress = DownloadProgress {
+        chunks: progress,
+        bytes: None,
+    };
+    download_file_with_progress(
+        op,
+        remote_manifest,
+        local_path,
+        remote_prefix,
+        progress,
+        device_id,
+        state,
+        encryption,
+        mode,
+    )
+    .await
+}
+
+/// Like [`download_file_with_device`], reporting progress by chunk count
+/// and/or by bytes written.
+///
+/// Chunks are written to a temp file next to `local_path` as they arrive,
+/// hashed on the way, and the temp file is renamed into place once the
+/// whole-file hash checks out, so memory use does not grow with file size.
+/// If fetching fails part way, the temp file and its sidecar (see
+/// [`download_resume_path`]) are kept, and the next download of the same
+/// content re-verifies the chunks already written and fetches only the rest.
+/// The manifest and chunks are read through `state`'s read mirrors when
+/// the primary store lacks them.
+#[allow(clippy::too_many_arguments)]
+pub async fn download_file_with_progress(
+    op: &Operator,
+    remote_manifest: &str,
+    local_path: &Path,
+    remote_prefix: &str,
+    progress: DownloadProgress<'_>,
+    device_id: &str,
+    state: Option<&StateCache>,
+    encryption: OptionalEncryption<'_>,
+    mode: RestoreMode,
+) -> Result<DownloadResult> {
+    let mirrors = state.map(StateCache::read_mirrors).unwrap_or_default();
+
+    // Read manifest
+    let manifest_bytes = mirrors
+        .read(op, remote_manifest, ObjectMeta::MANIFEST, |data| {
+            SyncManifest::from_bytes(data).map(drop)
+        })
+        .await
+        .with_context(|| format!("reading manifest: {remote_manifest}"))?;
+
+    let manifest = SyncManifest::from_bytes(&manifest_bytes)
+        .with_context(|| format!("parsing manifest: {remote_manifest}"))?;
+    download_manifest(
+        op,
+        &manifest,
+        remote_manifest,
+        local_path,
+        remote_prefix,
+        progress,
+        device_id,
+        state,
+        encryption,
+        mode,
+    )
+    .await
+}
+
+/// Like [`download_file_with_progress`], restoring from a manifest the
+/// caller has already read (and checked) instead of reading
+/// `remote_manifest` again, so the content written is the content that was
+/// verified. `remote_manifest` names it in state and errors.
+#[allow(clippy::too_many_arguments)]
+pub async fn download_manifest(
+    op: &Operator,
+    manifest: &SyncManifest,
+    remote_manifest: &str,
+    local_path: &Path,
+    remote_prefix: &str,
+    progress: DownloadProgress<'_>,
+    device_id: &str,
+    state: Option<&StateCache>,
+    encryption: OptionalEncryption<'_>,
+    mode: RestoreMode,
+) -> Result<DownloadResult> {
+    let mirrors = state.map(StateCache::read_mirrors).unwrap_or_default();
+
+    if manifest.chunk_hashes().is_empty() {
+        anyhow::bail!("manifest is empty: {remote_manifest}");
+    }
+    if let Some(state) = state.filter(|s| s.guards_local_edits()) {
+        check_local_edits(state, local_path, &manifest.file_hash)?;
+    }
+
+    let default_transfer;
+    let transfer = match state {
+        Some(state) => state.transfer_concurrency(),
+        None => {
+            default_transfer = crate::adaptive::AdaptiveConcurrency::default();
+            &default_transfer
+        }
+    };
+
+    if let Some(parent) = local_path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .with_context(|| format!("creating dir: {}", parent.display()))?;
+    }
+    let tmp = download_tmp_path(local_path);
+    let resume = download_resume_path(local_path);
+    // Only a failure while fetching leaves the partial file for a retry;
+    // one that got as far as a whole file starts over next time
+    let mut resumable = false;
+    let written: Result<(u64, String)> = async {
+        use std::io::Write;
+
+        let PartialDownload {
+            mut file,
+            mut journal,
+            chunks: first,
+            mut hasher,
+            mut bytes,
+        } = PartialDownload::open(&tmp, &resume, manifest)?;
+        if first > 0 {
+            info!(
+                local = %local_path.display(),
+                chunks = first,
+                bytes,
+                "resuming partial download"
+            );
+        }
+        let mut next = first;
+        resumable = true;
+        stream_chunks_with(
+            op,
+            manifest,
+            remote_manifest,
+            remote_prefix,
+            encryption,
+            progress.chunks,
+            transfer,
+            &mirrors,
+            first,
+            |plaintext| {
+                file.write_all(&plaintext)
+                    .with_context(|| format!("writing tmp: {}", tmp.display()))?;
+                let chunk_hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&plaintext));
+                writeln!(journal, "{next} {} {chunk_hash}", plaintext.len())
+                    .with_context(|| format!("writing {}", resume.display()))?;
+                next += 1;
+                hasher.update(&plaintext);
+                bytes += plaintext.len() as u64;
+                if let Some(cb) = progress.bytes {
+                    cb(bytes, manifest.file_size, "downloading");
+                }
+                Ok(())
+            },
+        )
+        .await?;
+        resumable = false;
+        file.sync_all()
+            .with_context(|| format!("syncing tmp: {}", tmp.display()))?;
+
+        // Verify the written file against the manifest (plaintext hash)
+        let actual_file_hash = tcfs_chunks::hash_to_hex(&hasher.finalize());
+        if actual_file_hash != manifest.file_hash {
+            return Err(FileHashMismatch {
+                manifest: remote_manifest.to_string(),
+                expected: manifest.file_hash.clone(),
+                actual: actual_file_hash,
+            }
+            .into());
+        }
+
+        // State is only touched once the rename lands
+        apply_mode(&tmp, mode.mode(manifest), mode.umask)?;
+        rename_replacing(&tmp, local_path)
+            .await
+            .with_context(|| format!("renaming to: {}", local_path.display()))?;
+        Ok((bytes, actual_file_hash))
+    }
+    .await;
+    if written.is_err() && resumable {
+        info!(tmp = %tmp.display(), "keeping partial download to resume");
+    } else {
+        if written.is_err() {
+            remove_partial_download(&tmp).await;
+        }
+        remove_partial_download(&resume).await;
+    }
+    let (bytes, file_hash_hex) = written?;
+
+    // Merge remote vclock into local state if we have a state cache
+    if let Some(state) = state {
+        if !device_id.is_empty() {
+            let mut local_vclock = state
+                .get(local_path)
+                .map(|s| s.vclock.clone())
+                .unwrap_or_default();
+            local_vclock.merge(&manifest.vclock);
+
+            let sync_state = make_sync_state_at(
+                local_path,
+                file_hash_hex,
+                manifest.chunk_hashes().len(),
+                remote_manifest.to_string(),
+                local_vclock,
+                device_id.to_string(),
+                state.now_secs(),
+            )?;
+            state.set(local_path, sync_state);
+        }
+    }
+
+    info!(
+        remote = %remote_manifest,
+        local = %local_path.display(),
+        bytes,
+        "downloaded"
+    );
+
+    Ok(DownloadResult {
+        remote_path: remote_manifest.to_string(),
+        local_path: local_path.to_path_buf(),
+        bytes,
+    })
+}
+
+/// Walk a local directory and upload all changed files.
+///
+/// Returns stats: (files_uploaded, files_skipped, bytes_uploaded)
+pub async fn push_tree(
+    op: &Operator,
+    local_root: &Path,
+    remote_prefix: &str,
+    state: &StateCache,
+    progress: Option<&ProgressFn>,
+) -> Result<(usize, usize, u64)> {
+    push_tree_with_device(
+        op,
+        local_root,
+        remote_prefix,
+        state,
+        progress,
+        "",
+        None,
+        None,
+        0,
+        None,
+    )
+    .await
+}
+
+/// Totals from a tree push.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PushTreeStats {
+    /// Files uploaded (or deduplicated against an existing manifest)
+    pub uploaded: usize,
+    /// Files skipped as unchanged or not pushable
+    pub skipped: usize,
+    /// Plaintext bytes of the uploaded files
+    pub bytes: u64,
+    /// Chunks written to storage
+    pub new_chunks: usize,
+    /// Chunks found already stored and not re-uploaded
+    pub deduped_chunks: usize,
+    /// Files left alone because their clock conflicts with the remote's
+    pub conflicts: usize,
+    /// Root hash of the tree manifest written for this push; `None` when a
+    /// file failed or conflicted, so no consistent snapshot exists
+    pub root_hash: Option<String>,
+}
+
+/// Push tree with device identity, optional collection config, and optional encryption.
+///
+/// Returns (files_uploaded, files_skipped, bytes_uploaded); see
+/// [`push_tree_with_stats`] for chunk-level dedup counts.
+#[allow(clippy::too_many_arguments)]
+pub async fn push_tree_with_device(
+    op: &Operator,
+    local_root: &Path,
+    remote_prefix: &str,
+    state: &StateCache,
+    progress: Option<&ProgressFn>,
+    device_id: &str,
+    collect_cfg: Option<&CollectConfig>,
+    encryption: OptionalEncryption<'_>,
+    concurrency: usize,
+    history: Option<&crate::history::HistoryPolicy>,
+) -> Result<(usize, usize, u64)> {
+    let stats = push_tree_with_stats(
+        op,
+        local_root,
+        remote_prefix,
+        state,
+        progress,
+        device_id,
+        collect_cfg,
+        encryption,
+        concurrency,
+        history,
+    )
+    .await?;
+    Ok((stats.uploaded, stats.skipped, stats.bytes))
+}
+
+/// Like [`push_tree_with_device`], returning [`PushTreeStats`].
+///
+/// Up to `concurrency` files are uploaded at once (0 = number of CPUs). The
+/// progress callback is invoked as each file completes, so `done` counts
+/// finished files rather than the position in the collected list. With a
+/// `history` policy, each uploaded file also gets a version pointer (see
+/// `crate::history`).
+#[allow(clippy::too_many_arguments)]
+pub async fn push_tree_with_stats(
+    op: &Operator,
+    local_root: &Path,
+    remote_prefix: &str,
+    state: &StateCache,
+    progress: Option<&ProgressFn>,
+    device_id: &str,
+    collect_cfg: Option<&CollectConfig>,
+    encryption: OptionalEncryption<'_>,
+    concurrency: usize,
+    history: Option<&crate::history::HistoryPolicy>,
+) -> Result<PushTreeStats> {
+    push_tree_except(
+        op,
+        local_root,
+        remote_prefix,
+        state,
+        progress,
+        device_id,
+        collect_cfg,
+        encryption,
+        concurrency,
+        history,
+        &HashSet::new(),
+        None,
+    )
+    .await
+}
+
+/// Like [`push_tree_with_stats`], publishing the tree all or nothing.
+///
+/// Chunks and manifests are uploaded as usual, but every index entry the
+/// push would write (and its history pointer) is held back until all files
+/// have landed, then written in one final batch. If any file fails, nothing
+/// is published and the push returns [`TreePushRolledBack`]; a failure
+/// while publishing restores the entries already written to what they were
+/// before. Mounts and pulls therefore see either the old tree or the new
+/// one, never a mix. The uploaded chunks and manifests stay in place, so a
+/// retry only has to publish.
+#[allow(clippy::too_many_arguments)]
+pub async fn push_tree_transactional(
+    op: &Operator,
+    local_root: &Path,
+    remote_prefix: &str,
+    state: &StateCache,
+    progress: Option<&ProgressFn>,
+    device_id: &str,
+    collect_cfg: Option<&CollectConfig>,
+    encryption: OptionalEncryption<'_>,
+    concurrency: usize,
+    history: Option<&crate::history::HistoryPolicy>,
+) -> Result<PushTreeStats> {
+    let stage = IndexStage::default();
+    let mut stats = push_tree_except(
+        op,
+        local_root,
+        remote_prefix,
+        state,
+        progress,
+        device_id,
+        collect_cfg,
+        encryption,
+        concurrency,
+        history,
+        &HashSet::new(),
+        Some(&stage),
+    )
+    .await?;
+
+    let prefix = RemoteLayout::new(remote_prefix).prefix().to_string();
+    let failed = stage.failed.load(Ordering::Relaxed);
+    let staged = stage
+        .entries
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner());
+    if failed > 0 {
+        return Err(TreePushRolledBack {
+            prefix,
+            failed,
+            staged: staged.len(),
+        }
+        .into());
+    }
+    publish_index(op, &staged).await?;
+    info!(prefix = %prefix, entries = staged.len(), "published tree push");
+
+    if let Some(policy) = history {
+        let versions = stage
+            .history
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner());
+        for (rel_str, index_entry) in versions {
+            let now = state.now_secs();
+            if let Err(e) =
+                crate::history::record_version(op, &prefix, &rel_str, &index_entry, now, policy)
+                    .await
+            {
+                warn!(path = %rel_str, "failed to record history: {e:#}");
+            }
+        }
+    }
+    if let Some(tree) = stage.tree.into_inner().unwrap_or_else(|e| e.into_inner()) {
+        match crate::tree::write_tree(op, &prefix, &tree).await {
+            Ok(root_hash) => stats.root_hash = Some(root_hash),
+            Err(e) => warn!("failed to write tree manifest: {e:#}"),
+        }
+    }
+    Ok(stats)
+}
+
+/// Index writes held back by [`push_tree_transactional`].
+#[derive(Default)]
+pub(crate) struct IndexStage {
+    /// Index key → entry bytes and the version the key was read at, in the
+    /// order they were produced
+    entries: std::sync::Mutex<Vec<(String, Vec<u8>, ObjectVersion)>>,
+    /// rel_path → entry for history pointers to record once published
+    history: std::sync::Mutex<Vec<(String, IndexEntry)>>,
+    /// Tree manifest to write once published, if the push was complete
+    tree: std::sync::Mutex<Option<crate::tree::TreeManifest>>,
+    /// Files that failed to upload
+    failed: AtomicUsize,
+}
+
+/// Write an index entry if the key is still at `expected`, or hold it in
+/// `stage` when there is one. A lost race is [`ConcurrentModification`].
+pub(crate) async fn put_index(
+    op: &Operator,
+    stage: Option<&IndexStage>,
+    key: &str,
+    entry: Vec<u8>,
+    expected: &ObjectVersion,
+) -> Result<()> {
+    match stage {
+        Some(stage) => {
+            let mut entries = stage.entries.lock().unwrap_or_else(|e| e.into_inner());
+            entries.push((key.to_string(), entry, expected.clone()));
+            Ok(())
+        }
+        None => write_conditional(op, key, entry, expected).await,
+    }
+}
+
+/// How a push treats a path's live index entry.
+enum IndexClaim {
+    /// Absent, or holding what this device last synced or is pushing:
+    /// replace it if it is still at this version
+    Replace(ObjectVersion),
+    /// Holding another device's version, which stays in place; the result
+    /// reports it with the outcome of comparing the two
+    Yield(Box<UploadResult>),
+}
+
+/// Decide whether a push of `path` may replace the index entry at `key`.
+///
+/// `synced` is what this device last synced from `path` and `pushed` the
+/// state recorded by uploading it, once uploaded. An entry holding neither
+/// hash was written elsewhere: its manifest's clock is compared with the
+/// local one, as [`reconcile_entry`] does for a pull, and the entry is only
+/// replaced if the local version is newer. Entries with no clock (packed
+/// files, symlinks) are newer whenever the local file is unchanged, and in
+/// conflict otherwise; tombstones are always replaced.
+#[allow(clippy::too_many_arguments)]
+async fn claim_index(
+    op: &Operator,
+    prefix: &str,
+    key: &str,
+    rel_path: &str,
+    path: &Path,
+    synced: Option<&SyncState>,
+    pushed: Option<&SyncState>,
+    state: &StateCache,
+    device_id: &str,
+) -> Result<IndexClaim> {
+    let version = stat_version(op, key).await?;
+    if version == ObjectVersion::Absent {
+        return Ok(IndexClaim::Replace(version));
+    }
+    let data = op
+        .read(key)
+        .await
+        .with_context(|| format!("reading index entry: {key}"))?;
+    let entry = IndexEntry::from_bytes(&data.to_bytes())
+        .with_context(|| format!("parsing index entry: {key}"))?;
+    let owned = |hash: &str| {
+        synced.is_some_and(|s| s.blake3 == hash) || pushed.is_some_and(|s| s.blake3 == hash)
+    };
+    if entry.is_tombstone() || owned(&entry.manifest_hash) {
+        return Ok(IndexClaim::Replace(version));
+    }
+
+    let local_hash = match (pushed, synced) {
+        (Some(pushed), _) => pushed.blake3.clone(),
+        (None, Some(synced)) if state.needs_sync(path)?.is_none() => synced.blake3.clone(),
+        _ => tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_file(path)?),
+    };
+    let changed = synced.is_none_or(|s| s.blake3 != local_hash);
+    let local_vclock = pushed
+        .or(synced)
+        .map(|s| s.vclock.clone())
+        .unwrap_or_default();
+    let conflict = |remote_vclock, remote_hash: &str, remote_device: &str| {
+        SyncOutcome::Conflict(ConflictInfo {
+            rel_path: rel_path.to_string(),
+            local_vclock: local_vclock.clone(),
+            remote_vclock,
+            local_blake3: local_hash.clone(),
+            remote_blake3: remote_hash.to_string(),
+            local_device: device_id.to_string(),
+            remote_device: remote_device.to_string(),
+            detected_at: state.now_secs(),
+            local_modified: 0,
+            remote_modified: 0,
+        })
+    };
+
+    let manifest = match (&entry.pack, &entry.symlink) {
+        (None, None) => {
+            let manifest_path = entry.manifest_path(prefix);
+            match op.read(&manifest_path).await {
+                Ok(data) => SyncManifest::from_bytes(&data.to_bytes()).ok(),
+                Err(e) => {
+                    warn!(manifest = %manifest_path, "reading manifest: {e}");
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+    let outcome = match manifest {
+        Some(remote) => {
+            // An untracked local copy has no clock to order it by
+            let outcome = if synced.is_some() || remote.file_hash == local_hash {
+                compare_clocks_at(
+                    &local_vclock,
+                    &remote.vclock,
+                    &local_hash,
+                    &remote.file_hash,
+                    rel_path,
+                    device_id,
+                    &remote.written_by,
+                    state.clock().as_ref(),
+                )
+            } else {
+                SyncOutcome::RemoteNewer
+            };
+            match outcome {
+                SyncOutcome::RemoteNewer if changed => {
+                    conflict(remote.vclock, &remote.file_hash, &remote.written_by)
+                }
+                outcome => outcome,
+            }
+        }
+        None if changed => conflict(Default::default(), &entry.manifest_hash, ""),
+        None => SyncOutcome::RemoteNewer,
+    };
+    Ok(match outcome {
+        SyncOutcome::UpToDate | SyncOutcome::LocalNewer => IndexClaim::Replace(version),
+        outcome => IndexClaim::Yield(Box::new(yielded(path, prefix, entry, outcome))),
+    })
+}
+
+/// The result of a push that left `entry`, another device's version of
+/// `path`, in place.
+fn yielded(path: &Path, prefix: &str, entry: IndexEntry, outcome: SyncOutcome) -> UploadResult {
+    UploadResult {
+        path: path.to_path_buf(),
+        remote_path: entry.manifest_path(prefix),
+        hash: entry.manifest_hash,
+        chunks: entry.chunks,
+        bytes: entry.size,
+        skipped: true,
+        outcome: Some(outcome),
+        new_chunks: 0,
+        deduped_chunks: 0,
+        reused_chunks: 0,
+        dry_run: false,
+    }
+}
+
+/// Write `entries`, each only if its key is still at the version read
+/// before the push, putting back what was there before if any write fails.
+async fn publish_index(op: &Operator, entries: &[(String, Vec<u8>, ObjectVersion)]) -> Result<()> {
+    let mut previous = Vec::with_capacity(entries.len());
+    for (key, _, _) in entries {
+        let old = match op.read(key).await {
+            Ok(data) => Some(data.to_vec()),
+            Err(e) if e.kind() == opendal::ErrorKind::NotFound => None,
+            Err(e) => return Err(e).with_context(|| format!("reading index entry: {key}")),
+        };
+        previous.push(old);
+    }
+
+    for (i, (key, entry, expected)) in entries.iter().enumerate() {
+        if let Err(e) = write_conditional(op, key, entry.clone(), expected).await {
+            warn!(key = %key, "publishing index entry failed, rolling back: {e:#}");
+            for ((key, _, _), old) in entries[..i].iter().zip(&previous) {
+                let undone = match old {
+                    Some(old) => op.write(key, old.clone()).await.map(|_| ()),
+                    None => op.delete(key).await,
+                };
+                if let Err(e) = undone {
+                    warn!(key = %key, "failed to roll back index entry: {e}");
+                }
+            }
+            return Err(e).with_context(|| format!("writing index entry: {key}"));
+        }
+    }
+    Ok(())
+}
+
+/// [`push_tree_with_stats`] leaving out the files in `skip`, staging index
+/// writes in `stage` when given.
+#[allow(clippy::too_many_arguments)]
+async fn push_tree_except(
+    op: &Operator,
+    local_root: &Path,
+    remote_prefix: &str,
+    state: &StateCache,
+    progress: Option<&ProgressFn>,
+    device_id: &str,
+    collect_cfg: Option<&CollectConfig>,
+    encryption: OptionalEncryption<'_>,
+    concurrency: usize,
+    history: Option<&crate::history::HistoryPolicy>,
+    skip: &HashSet<PathBuf>,
+    stage: Option<&IndexStage>,
+) -> Result<PushTreeStats> {
+    if state.is_read_only() {
+        return Err(ReadOnlyStore {
+            prefix: remote_prefix.to_string(),
+        }
+        .into());
+    }
+    let mut stats = PushTreeStats::default();
+
+    let cfg = collect_cfg.cloned().unwrap_or_default();
+    let (mut files, empty_dirs) = collect_tree(local_root, &cfg)?;
+    files.retain(|f| !skip.contains(f));
+    let total = files.len();
+    let packs_dir = RemoteLayout::new(remote_prefix).pack_key("");
+    let (packed, mut files): (Vec<PathBuf>, Vec<PathBuf>) = files
+        .into_iter()
+        .partition(|path| packs_file(path, state, &packs_dir, encryption.is_none()));
+    let concurrency = effective_concurrency(concurrency);
+    let layout = RemoteLayout::new(remote_prefix);
+    let prefix = layout.prefix();
+    let compress_skip = cfg.compress_skip_extensions.as_deref();
+
+    let done = AtomicUsize::new(0);
+
+    // Build the chunk filter and count usage once up front rather than in
+    // every upload task
+    ensure_chunk_filter(op, prefix, state).await;
+    ensure_usage(op, prefix, state).await?;
+
+    let mut tree = crate::tree::TreeManifest {
+        written_by: device_id.to_string(),
+        written_at: state.now_secs(),
+        ..Default::default()
+    };
+    let mut complete = true;
+
+    if !packed.is_empty() {
+        let packed =
+            crate::pack::pack_files(op, local_root, prefix, state, &packed, device_id, stage).await;
+        for result in packed {
+            // Paths another device tracks go through the chunked upload,
+            // which detects conflicts
+            let result = match result.map_err(|e| e.downcast::<crate::pack::TrackedElsewhere>()) {
+                Ok(file) => Ok(file),
+                Err(Ok(tracked)) => {
+                    debug!(path = %tracked.rel_path, "not packing: {tracked}");
+                    files.push(tracked.path);
+                    continue;
+                }
+                Err(Err(e)) => Err(e),
+            };
+            let n = done.fetch_add(1, Ordering::Relaxed) + 1;
+            match result {
+                Ok(file) => {
+                    if let Some(cb) = progress {
+                        cb(
+                            n as u64,
+                            total as u64,
+                            &format!("[{n}/{total}] {}", file.rel_path),
+                        );
+                    }
+                    if let Some(pack) = file.pack {
+                        tree.packed.insert(file.rel_path.clone(), pack);
+                    }
+                    tree.files.insert(file.rel_path, file.hash);
+                    if file.skipped {
+                        stats.skipped += 1;
+                    } else {
+                        stats.uploaded += 1;
+                        stats.bytes += file.bytes;
+                    }
+                }
+                Err(e) => {
+                    warn!("packing failed: {e:#}");
+                    complete = false;
+                    if let Some(stage) = stage {
+                        stage.failed.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+    }
+
+    // Owned paths keep the stream future `Send` for callers that spawn it
+    let results: Vec<Result<UploadResult>> = stream::iter(files)
+        .map(|path| {
+            let (done, layout) = (&done, &layout);
+            async move {
+                let path = path.as_path();
+                let rel = path.strip_prefix(local_root).unwrap_or(path);
+                let rel_str = rel.to_string_lossy().replace('\\', "/");
+
+                let result = if is_symlink(path) {
+                    symlink_push(op, path, prefix, &rel_str, stage)
+                        .await
+                        .with_context(|| format!("recording symlink {}", path.display()))
+                } else {
+                    async {
+                        // Claim the entry before uploading, so another
+                        // device's version is only replaced if this one is newer
+                        let index_key = layout.index_key(&rel_str);
+                        let synced = state.get(path);
+                        let claim = claim_index(
+                            op,
+                            prefix,
+                            &index_key,
+                            &rel_str,
+                            path,
+                            synced.as_ref(),
+                            None,
+                            state,
+                            device_id,
+                        );
+                        let mut index_version = match claim.await? {
+                            IndexClaim::Replace(version) => version,
+                            IndexClaim::Yield(result) => {
+                                debug!(path = %path.display(), "index entry written elsewhere, not pushing");
+                                return Ok(*result);
+                            }
+                        };
+                        let result = upload_file_shared(
+                            op,
+                            path,
+                            prefix,
+                            state,
+                            UploadProgress::default(),
+                            device_id,
+                            Some(&rel_str),
+                            encryption,
+                            compress_skip,
+                            false,
+                            stage,
+                        )
+                        .await?;
+
+                        // Write index entry: maps relative path → manifest hash + metadata.
+                        // This allows the FUSE driver to list files by original name.
+                        let modified = state.get(path).map(|entry| entry.mtime);
+                        let mut index_entry =
+                            IndexEntry::new(&result.hash, result.bytes, result.chunks, modified);
+                        index_entry.mode = file_mode(path);
+                        let mut attempt = 0;
+                        loop {
+                            let written = put_index(
+                                op,
+                                stage,
+                                &index_key,
+                                index_entry.to_bytes(),
+                                &index_version,
+                            )
+                            .await;
+                            let lost = match written {
+                                Ok(()) => break,
+                                Err(e) if e.is::<ConcurrentModification>() => e,
+                                Err(e) => {
+                                    warn!(path = %path.display(), "failed to write index entry: {e:#}");
+                                    break;
+                                }
+                            };
+                            // Another device rewrote the entry during the
+                            // upload: re-read it and compare clocks again
+                            attempt += 1;
+                            warn!(path = %path.display(), attempt, "index entry modified concurrently, re-checking");
+                            let claim = if attempt <= MAX_CAS_RETRIES {
+                                let pushed = state.get(path);
+                                claim_index(
+                                    op,
+                                    prefix,
+                                    &index_key,
+                                    &rel_str,
+                                    path,
+                                    synced.as_ref(),
+                                    pushed.as_ref(),
+                                    state,
+                                    device_id,
+                                )
+                                .await
+                            } else {
+                                Err(lost)
+                            };
+                            let yielded = match claim {
+                                Ok(IndexClaim::Replace(version)) => {
+                                    index_version = version;
+                                    continue;
+                                }
+                                Ok(IndexClaim::Yield(result)) => Ok(*result),
+                                Err(e) => Err(e),
+                            };
+                            // The remote path holds another device's version,
+                            // so this file is only as synced as it was before
+                            match &synced {
+                                Some(synced) => state.set(path, synced.clone()),
+                                None => state.remove(path),
+                            }
+                            return yielded;
+                        }
+
+                        if let (Some(stage), Some(_), false) = (stage, history, result.skipped) {
+                            // Recorded once the staged entries are published
+                            let mut versions =
+                                stage.history.lock().unwrap_or_else(|e| e.into_inner());
+                            versions.push((rel_str.clone(), index_entry));
+                        } else if let (Some(policy), false) = (history, result.skipped) {
+                            let now = state.now_secs();
+                            if let Err(e) = crate::history::record_version(
+                                op,
+                                prefix,
+                                &rel_str,
+                                &index_entry,
+                                now,
+                                policy,
+                            )
+                            .await
+                            {
+                                warn!(path = %path.display(), "failed to record history: {e:#}");
+                            }
+                        }
+                        Ok(result)
+                    }
+                    .await
+                    .with_context(|| format!("uploading {}", path.display()))
+                };
+
+                let n = done.fetch_add(1, Ordering::Relaxed) + 1;
+                if let Some(cb) = progress {
+                    cb(
+                        n as u64,
+                        total as u64,
+                        &format!("[{n}/{total}] {}", rel.display()),
+                    );
+                }
+
+                result
+            }
+        })
+        .buffer_unordered(concurrency)
+        .collect()
+        .await;
+
+    for result in results {
+        match result {
+            Ok(result) => {
+                let rel = result.path.strip_prefix(local_root).unwrap_or(&result.path);
+                let rel_str = rel.to_string_lossy().replace('\\', "/");
+                if result.hash.is_empty() {
+                    if let Ok(target) = std::fs::read_link(&result.path) {
+                        tree.symlinks
+                            .insert(rel_str, target.to_string_lossy().into_owned());
+                    }
+                } else {
+                    tree.files.insert(rel_str, result.hash.clone());
+                }
+                stats.new_chunks += result.new_chunks;
+                stats.deduped_chunks += result.deduped_chunks;
+                if matches!(result.outcome, Some(SyncOutcome::Conflict(_))) {
+                    stats.conflicts += 1;
+                    complete = false;
+                }
+                if result.skipped {
+                    stats.skipped += 1;
+                } else {
+                    stats.uploaded += 1;
+                    stats.bytes += result.bytes;
+                }
+            }
+            Err(e) => {
+                warn!("upload failed: {e:#}");
+                if e.is::<ConcurrentModification>() {
+                    stats.conflicts += 1;
+                }
+                complete = false;
+                if let Some(stage) = stage {
+                    stage.failed.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    // Empty directories have no index entries beneath them; record each with
+    // a marker so pulls and mounts reproduce it
+    for dir in &empty_dirs {
+        let rel = dir.strip_prefix(local_root).unwrap_or(dir);
+        let rel_str = rel.to_string_lossy().replace('\\', "/");
+        let marker_key = layout.index_key(&format!("{rel_str}/{DIR_MARKER}"));
+        if let Err(e) = put_index(op, stage, &marker_key, Vec::new(), &ObjectVersion::Unknown).await
+        {
+            warn!(dir = %dir.display(), "failed to write directory marker: {e}");
+        }
+        tree.empty_dirs.push(rel_str);
+    }
+
+    // Only a push in which every file landed describes a consistent snapshot
+    if complete && skip.is_empty() {
+        tree.empty_dirs.sort();
+        if let Some(stage) = stage {
+            *stage.tree.lock().unwrap_or_else(|e| e.into_inner()) = Some(tree);
+        } else {
+            match crate::tree::write_tree(op, prefix, &tree).await {
+                Ok(root_hash) => stats.root_hash = Some(root_hash),
+                Err(e) => warn!("failed to write tree manifest: {e:#}"),
+            }
+        }
+    }
+
+    // Flush state cache after tree push
+    state.flush()?;
+
+    Ok(stats)
+}
+
+/// Whether a tree push should store `path` through `crate::pack`: it is a
+/// regular file below the state cache's pack threshold (and the push is
+/// unencrypted), or an unchanged file whose last push packed it.
+fn packs_file(path: &Path, state: &StateCache, packs_dir: &str, unencrypted: bool) -> bool {
+    let Ok(meta) = std::fs::symlink_metadata(path) else {
+        return false;
+    };
+    if !meta.is_file() {
+        return false;
+    }
+    if let (Some(threshold), true) = (state.pack_threshold(), unencrypted) {
+        if meta.len() < threshold {
+            return true;
+        }
+    }
+    let was_packed = state
+        .get(path)
+        .is_some_and(|s| s.remote_path.starts_with(packs_dir));
+    was_packed && matches!(state.needs_sync(path), Ok(None))
+}
+
+/// Sign `manifest` with the state cache's device key when one is set,
+/// otherwise drop any signature carried over from an earlier writer.
+fn sign_manifest(manifest: &mut SyncManifest, state: &StateCache) -> Result<()> {
+    manifest.signature = None;
+    #[cfg(feature = "crypto")]
+    if let Some(key) = state.signing_key() {
+        manifest.sign(key)?;
+    }
+    #[cfg(not(feature = "crypto"))]
+    let _ = state;
+    Ok(())
+}
+
+/// Counts from one [`reconcile_tree`] sweep.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReconcileStats {
+    /// Files downloaded because the remote copy was new or causally newer
+    pub pulled: usize,
+    /// Files uploaded (including metadata-only updates)
+    pub pushed: usize,
+    /// Files whose local and remote versions diverged; left untouched
+    pub conflicts: usize,
+}
+
+/// One full reconciliation pass between `local_root` and `remote_prefix`.
+///
+/// Every indexed file is compared with its local copy: files missing
+/// locally (and not tombstoned) or whose manifest clock is newer than the
+/// state entry, with the local copy unchanged since its last sync, are
+/// pulled. Then local changes are pushed as by [`push_tree_with_stats`]
+/// (skipped on read-only stores). A file whose local and remote versions
+/// diverged is neither pulled nor pushed and is counted as a conflict.
+/// Failures on individual files are logged and do not abort the sweep.
+#[allow(clippy::too_many_arguments)]
+pub async fn reconcile_tree(
+    op: &Operator,
+    local_root: &Path,
+    remote_prefix: &str,
+    state: &StateCache,
+    device_id: &str,
+    collect_cfg: Option<&CollectConfig>,
+    encryption: OptionalEncryption<'_>,
+    mode_umask: u32,
+) -> Result<ReconcileStats> {
+    let layout = RemoteLayout::new(remote_prefix);
+    let prefix = layout.prefix();
+    let index_prefix = layout.index_dir("");
+    let mut stats = ReconcileStats::default();
+    let mut conflicted = HashSet::new();
+
+    for key in list_index_files(op, &index_prefix).await? {
+        let rel = key.trim_start_matches(&index_prefix);
+        match reconcile_entry(op, prefix, &key, rel, local_root, state, device_id).await {
+            Ok(Reconcile::Pull(_, local_path, entry)) => {
+                match download_indexed(
+                    op,
+                    prefix,
+                    rel,
+                    &entry,
+                    &local_path,
+                    DownloadProgress::default(),
+                    device_id,
+                    Some(state),
+                    encryption,
+                    mode_umask,
+                )
+                .await
+                {
+                    Ok(_) => stats.pulled += 1,
+                    Err(e) => warn!(path = %rel, "reconcile pull failed: {e:#}"),
+                }
+            }
+            Ok(Reconcile::Conflict(local_path)) => {
+                info!(path = %rel, "reconcile: local and remote diverged, leaving both");
+                stats.conflicts += 1;
+                conflicted.insert(local_path);
+            }
+            Ok(Reconcile::Keep) => {}
+            Err(e) => warn!(key = %key, "reconcile failed: {e:#}"),
+        }
+    }
+
+    if !state.is_read_only() {
+        let pushed = push_tree_except(
+            op,
+            local_root,
+            prefix,
+            state,
+            None,
+            device_id,
+            collect_cfg,
+            encryption,
+            0,
+            None,
+            &conflicted,
+            None,
+        )
+        .await?;
+        stats.pushed = pushed.uploaded;
+        stats.conflicts += pushed.conflicts;
+    }
+    state.flush()?;
+
+    Ok(stats)
+}
+
+/// One step of a [`reconcile_tree`] sweep, as planned by [`plan_reconcile`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReconcileAction {
+    /// Download the remote version to `local_path`
+    PullRemote {
+        local_path: PathBuf,
+        /// Manifest key, or pack key for a packed file
+        manifest_path: String,
+    },
+    /// Upload the local file, for `reason` (as `StateCache::needs_sync` gives it)
+    PushLocal { local_path: PathBuf, reason: String },
+    /// Local and remote versions diverged; both would be left alone
+    Conflict { local_path: PathBuf },
+    /// Nothing to do
+    UpToDate { local_path: PathBuf },
+}
+
+impl ReconcileAction {
+    /// The local file the action is about.
+    pub fn local_path(&self) -> &Path {
+        match self {
+            ReconcileAction::PullRemote { local_path, .. }
+            | ReconcileAction::PushLocal { local_path, .. }
+            | ReconcileAction::Conflict { local_path }
+            | ReconcileAction::UpToDate { local_path } => local_path,
+        }
+    }
+}
+
+/// What [`reconcile_tree`] would do right now, without doing any of it.
+///
+/// Index entries are judged exactly as the sweep judges them (vector
+/// clocks via `compare_clocks`, tombstones, untracked copies), then every
+/// local file not already pulled or in conflict is planned as a push if
+/// `StateCache::needs_sync` says it changed, or as up to date. Pushes are
+/// left out for read-only stores, as the sweep skips them. Nothing is
+/// written to storage, to disk, or to `state`; entries that fail to read
+/// are logged and left out of the plan. Actions come in sweep order:
+/// index entries by key, then local files in walk order.
+pub async fn plan_reconcile(
+    op: &Operator,
+    local_root: &Path,
+    remote_prefix: &str,
+    state: &StateCache,
+    device_id: &str,
+    collect_cfg: Option<&CollectConfig>,
+) -> Result<Vec<ReconcileAction>> {
+    let layout = RemoteLayout::new(remote_prefix);
+    let prefix = layout.prefix();
+    let index_prefix = layout.index_dir("");
+    let mut plan = Vec::new();
+    // Local files the index pass already decided on
+    let mut decided = HashSet::new();
+
+    for key in list_index_files(op, &index_prefix).await? {
+        let rel = key.trim_start_matches(&index_prefix);
+        match reconcile_entry(op, prefix, &key, rel, local_root, state, device_id).await {
+            Ok(Reconcile::Pull(manifest_path, local_path, _)) => {
+                decided.insert(local_path.clone());
+                plan.push(ReconcileAction::PullRemote {
+                    local_path,
+                    manifest_path,
+                });
+            }
+            Ok(Reconcile::Conflict(local_path)) => {
+                decided.insert(local_path.clone());
+                plan.push(ReconcileAction::Conflict { local_path });
+            }
+            Ok(Reconcile::Keep) => {
+                // An untracked copy the index already matches needs no push
+                if let Ok(rel) = tcfs_core::paths::normalize_rel_path(rel) {
+                    let local_path = local_root.join(rel);
+                    if state.get(&local_path).is_none() && local_path.is_file() {
+                        decided.insert(local_path.clone());
+                        plan.push(ReconcileAction::UpToDate { local_path });
+                    }
+                }
+            }
+            Err(e) => warn!(key = %key, "reconcile plan: {e:#}"),
+        }
+    }
+
+    let cfg = collect_cfg.cloned().unwrap_or_default();
+    let (files, _) = collect_tree(local_root, &cfg)?;
+    for local_path in files {
+        if decided.contains(&local_path) || is_symlink(&local_path) {
+            continue;
+        }
+        match state.needs_sync(&local_path)? {
+            Some(reason) if !state.is_read_only() => {
+                plan.push(ReconcileAction::PushLocal { local_path, reason })
+            }
+            Some(_) => {}
+            None => plan.push(ReconcileAction::UpToDate { local_path }),
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Keys of the file entries under `index_prefix`, sorted (directory
+/// markers left out).
+async fn list_index_files(op: &Operator, index_prefix: &str) -> Result<Vec<String>> {
+    let entries = op
+        .list_with(index_prefix)
+        .recursive(true)
+        .await
+        .with_context(|| format!("listing index: {index_prefix}"))?;
+    let mut keys: Vec<String> = entries
+        .iter()
+        .filter(|e| !e.metadata().is_dir())
+        .map(|e| e.path())
+        .filter(|key| key.rsplit('/').next() != Some(DIR_MARKER))
+        .map(str::to_string)
+        .collect();
+    keys.sort();
+    Ok(keys)
+}
+
+/// What a reconciliation sweep does with one index entry.
+enum Reconcile {
+    /// Download the file behind the index entry, stored at the given
+    /// manifest or pack key, to the local path
+    Pull(String, PathBuf, IndexEntry),
+    /// Leave both sides alone and report a conflict
+    Conflict(PathBuf),
+    /// Nothing to pull; the push pass handles any local change
+    Keep,
+}
+
+async fn reconcile_entry(
+    op: &Operator,
+    prefix: &str,
+    key: &str,
+    rel: &str,
+    local_root: &Path,
+    state: &StateCache,
+    device_id: &str,
+) -> Result<Reconcile> {
+    let data = op
+        .read(key)
+        .await
+        .with_context(|| format!("reading index entry: {key}"))?;
+    let entry = IndexEntry::from_bytes(&data.to_bytes())
+        .with_context(|| format!("parsing index entry: {key}"))?;
+    if entry.is_tombstone() || entry.symlink.is_some() {
+        return Ok(Reconcile::Keep);
+    }
+
+    let local_path = local_root.join(tcfs_core::paths::normalize_rel_path(rel)?);
+    if let Some(pack) = &entry.pack {
+        let pack_key = RemoteLayout::new(prefix).pack_key(&pack.id);
+        return reconcile_packed(pack_key, local_path, entry, state);
+    }
+    let manifest_path = entry.manifest_path(prefix);
+    let data = op
+        .read(&manifest_path)
+        .await
+        .with_context(|| format!("reading manifest: {manifest_path}"))?;
+    let manifest = SyncManifest::from_bytes(&data.to_bytes())
+        .with_context(|| format!("parsing manifest: {manifest_path}"))?;
+
+    let Some(local) = state.get(&local_path) else {
+        if !local_path.exists() {
+            let stale = state.tombstone(&local_path).is_some_and(|t| {
+                t.vclock
+                    .partial_cmp_vc(&manifest.vclock)
+                    .is_some_and(|o| o.is_ge())
+            });
+            return Ok(if stale {
+                Reconcile::Keep
+            } else {
+                Reconcile::Pull(manifest_path, local_path, entry)
+            });
+        }
+        // Untracked local copy: fine if it already matches
+        let hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_file(&local_path)?);
+        return Ok(if hash == manifest.file_hash {
+            Reconcile::Keep
+        } else {
+            Reconcile::Conflict(local_path)
+        });
+    };
+
+    let outcome = compare_clocks_at(
+        &local.vclock,
+        &manifest.vclock,
+        &local.blake3,
+        &manifest.file_hash,
+        rel,
+        device_id,
+        &manifest.written_by,
+        state.clock().as_ref(),
+    );
+    Ok(match outcome {
+        SyncOutcome::RemoteNewer => {
+            if !local_path.exists() || state.needs_sync(&local_path)?.is_none() {
+                Reconcile::Pull(manifest_path, local_path, entry)
+            } else {
+                Reconcile::Conflict(local_path)
+            }
+        }
+        SyncOutcome::Conflict(_) => Reconcile::Conflict(local_path),
+        SyncOutcome::UpToDate | SyncOutcome::LocalNewer => Reconcile::Keep,
+    })
+}
+
+/// [`reconcile_entry`] for a packed file. With no vector clock to order
+/// versions by, the remote copy counts as newer whenever its content differs
+/// from what this device last synced; a file deleted here stays deleted.
+fn reconcile_packed(
+    pack_key: String,
+    local_path: PathBuf,
+    entry: IndexEntry,
+    state: &StateCache,
+) -> Result<Reconcile> {
+    let Some(local) = state.get(&local_path) else {
+        if !local_path.exists() {
+            return Ok(if state.tombstone(&local_path).is_some() {
+                Reconcile::Keep
+            } else {
+                Reconcile::Pull(pack_key, local_path, entry)
+            });
+        }
+        let hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_file(&local_path)?);
+        return Ok(if hash == entry.manifest_hash {
+            Reconcile::Keep
+        } else {
+            Reconcile::Conflict(local_path)
+        });
+    };
+    Ok(if local.blake3 == entry.manifest_hash {
+        Reconcile::Keep
+    } else if !local_path.exists() || state.needs_sync(&local_path)?.is_none() {
+        Reconcile::Pull(pack_key, local_path, entry)
+    } else {
+        Reconcile::Conflict(local_path)
+    })
+}
+
+/// Recreate a pushed tree under `local_root` from the remote index.
+///
+/// Files are downloaded through their manifests, symlinks are recreated from
+/// their recorded targets, and directory markers become (possibly empty)
+/// local directories. Tombstoned entries are skipped. Failures on individual
+/// entries are logged and do not abort the pull.
+///
+/// Returns stats: (files_downloaded, dirs_created, bytes_downloaded), where
+/// recreated symlinks count as files.
+///
+/// When `local_root` is on a case-insensitive filesystem, entries whose
+/// paths differ only in case are renamed (see [`pull_tree_with_case`]).
+/// Files get the mode their index entry records, with the bits in
+/// `mode_umask` cleared (see `sync.mode_umask`).
+pub async fn pull_tree(
+    op: &Operator,
+    remote_prefix: &str,
+    local_root: &Path,
+    progress: Option<&ProgressFn>,
+    mode_umask: u32,
+) -> Result<(usize, usize, u64)> {
+    let case_insensitive = folds_case(local_root).await?;
+    pull_tree_with_case(
+        op,
+        remote_prefix,
+        local_root,
+        progress,
+        case_insensitive,
+        mode_umask,
+    )
+    .await
+}
+
+/// [`pull_tree`] with the destination's case sensitivity given rather than
+/// probed, e.g. for a tree bound for a case-insensitive volume.
+///
+/// With `case_insensitive`, of the paths that differ only in case the first
+/// in byte order keeps its name and the others are written as
+/// `name (2).ext`, ... (see `tcfs_core::paths::case_insensitive_renames`),
+/// so no entry overwrites another.
+pub async fn pull_tree_with_case(
+    op: &Operator,
+    remote_prefix: &str,
+    local_root: &Path,
+    progress: Option<&ProgressFn>,
+    case_insensitive: bool,
+    mode_umask: u32,
+) -> Result<(usize, usize, u64)> {
+    pull_index(
+        op,
+        remote_prefix,
+        local_root,
+        None,
+        None,
+        progress,
+        case_insensitive,
+        mode_umask,
+    )
+    .await
+}
+
+/// [`pull_tree`], downloading only the files changed after `since` (unix
+/// seconds) for incremental mirrors of a prefix.
+///
+/// A file counts as changed when its manifest's `written_at` is newer than
+/// `since`, or, for manifests without one and for packed files and
+/// symlinks, when its index entry's mtime is. Entries recording neither
+/// are pulled. Directory markers are always recreated.
+///
+/// Returns stats as [`pull_tree`] does, counting only what was downloaded.
+pub async fn pull_tree_since(
+    op: &Operator,
+    remote_prefix: &str,
+    local_root: &Path,
+    since: u64,
+    progress: Option<&ProgressFn>,
+    mode_umask: u32,
+) -> Result<(usize, usize, u64)> {
+    let case_insensitive = folds_case(local_root).await?;
+    pull_index(
+        op,
+        remote_prefix,
+        local_root,
+        None,
+        Some(since),
+        progress,
+        case_insensitive,
+        mode_umask,
+    )
+    .await
+}
+
+/// Whether files written under `root` (created if missing) ignore case. A
+/// failed probe falls back to the platform's usual default.
+pub(crate) async fn folds_case(root: &Path) -> Result<bool> {
+    tokio::fs::create_dir_all(root)
+        .await
+        .with_context(|| format!("creating dir: {}", root.display()))?;
+    Ok(
+        tcfs_core::paths::is_case_insensitive(root).unwrap_or_else(|e| {
+            let fallback = cfg!(any(target_os = "macos", windows));
+            warn!(root = %root.display(), fallback, "probing case sensitivity failed: {e}");
+            fallback
+        }),
+    )
+}
+
+/// Pull only the indexed files whose relative path matches `pattern`,
+/// preserving their layout under `local_root`.
+///
+/// A pattern containing `/` is matched against the whole relative path, with
+/// `*` staying inside one component and `**` spanning directories
+/// (`src/**/*.rs`); one without is matched against the file name at any
+/// depth (`*.rs`). Directory markers are not recreated.
+///
+/// Returns (files_downloaded, bytes_downloaded).
+pub async fn pull_matching(
+    op: &Operator,
+    remote_prefix: &str,
+    pattern: &str,
+    local_root: &Path,
+    progress: Option<&ProgressFn>,
+    mode_umask: u32,
+) -> Result<(usize, u64)> {
+    let pattern =
+        glob::Pattern::new(pattern).with_context(|| format!("invalid pull pattern: {pattern}"))?;
+    let case_insensitive = folds_case(local_root).await?;
+    let (files, _, bytes) = pull_index(
+        op,
+        remote_prefix,
+        local_root,
+        Some(&pattern),
+        None,
+        progress,
+        case_insensitive,
+        mode_umask,
+    )
+    .await?;
+    Ok((files, bytes))
+}
+
+/// Whether a relative path is selected by a [`pull_matching`] pattern.
+pub fn pull_pattern_matches(pattern: &glob::Pattern, rel_path: &str) -> bool {
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+    if pattern.as_str().contains('/') {
+        pattern.matches_with(rel_path, options)
+    } else {
+        let name = rel_path.rsplit('/').next().unwrap_or(rel_path);
+        pattern.matches_with(name, options)
+    }
+}
+
+/// Pull every index entry under `remote_prefix`, or with a `filter` only the
+/// files it matches and with `since` only those changed after it, renaming
+/// case collisions if `case_insensitive`.
+#[allow(clippy::too_many_arguments)]
+async fn pull_index(
+    op: &Operator,
+    remote_prefix: &str,
+    local_root: &Path,
+    filter: Option<&glob::Pattern>,
+    since: Option<u64>,
+    progress: Option<&ProgressFn>,
+    case_insensitive: bool,
+    mode_umask: u32,
+) -> Result<(usize, usize, u64)> {
+    let layout = RemoteLayout::new(remote_prefix);
+    let prefix = layout.prefix();
+    let index_prefix = layout.index_dir("");
+
+    let entries = op
+        .list_with(&index_prefix)
+        .recursive(true)
+        .await
+        .with_context(|| format!("listing index: {index_prefix}"))?;
+    let mut keys: Vec<&str> = entries
+        .iter()
+        .filter(|e| !e.metadata().is_dir())
+        .map(|e| e.path())
+        .filter(|key| {
+            let Some(pattern) = filter else {
+                return true;
+            };
+            let rel = key.trim_start_matches(&index_prefix);
+            let is_marker = rel.rsplit('/').next() == Some(DIR_MARKER);
+            !is_marker && pull_pattern_matches(pattern, rel)
+        })
+        .collect();
+    keys.sort();
+
+    let renames = if case_insensitive {
+        let rels: Vec<&str> = keys
+            .iter()
+            .map(|key| key.trim_start_matches(&index_prefix))
+            .filter(|rel| rel.rsplit('/').next() != Some(DIR_MARKER))
+            .collect();
+        tcfs_core::paths::case_insensitive_renames(&rels)
+    } else {
+        std::collections::HashMap::new()
+    };
+    for (rel, renamed) in &renames {
+        warn!(rel = %rel, local = %renamed, "path differs only in case from another; pulling under a new name");
+    }
+
+    let mut downloaded = 0usize;
+    let mut dirs = 0usize;
+    let mut bytes = 0u64;
+    let total = keys.len();
+
+    for (i, key) in keys.iter().enumerate() {
+        let rel = key.trim_start_matches(&index_prefix);
+        let local_rel = renames.get(rel).map_or(rel, String::as_str);
+        let result = pull_index_key(
+            op, prefix, key, rel, local_rel, local_root, since, mode_umask,
+        )
+        .await;
+        match result {
+            Ok(PulledEntry::File(n)) => {
+                downloaded += 1;
+                bytes += n;
+            }
+            Ok(PulledEntry::Dir) => dirs += 1,
+            Ok(PulledEntry::Skipped) => {}
+            Err(e) => warn!(key = %key, "pull failed: {e:#}"),
+        }
+
+        if let Some(cb) = progress {
+            cb(
+                (i + 1) as u64,
+                total as u64,
+                &format!("[{}/{total}] {rel}", i + 1),
+            );
+        }
+    }
+
+    Ok((downloaded, dirs, bytes))
+}
+
+/// Resolve a file's path relative to `remote_prefix` to its manifest path.
+///
+/// Reads the index entry at `{prefix}/index/{rel_path}` and returns
+/// `{prefix}/manifests/{hash}`, ready for `download_file`. Fails if there is
+/// no entry, or if the entry is a symlink, a tombstone or a packed file
+/// (see [`resolve_index_entry`] and `crate::pack`).
+pub async fn resolve_manifest_path(
+    op: &Operator,
+    remote_prefix: &str,
+    rel_path: &str,
+) -> Result<String> {
+    let entry = resolve_index_entry(op, remote_prefix, rel_path).await?;
+    anyhow::ensure!(
+        !entry.is_packed(),
+        "{} is stored in a pack and has no manifest",
+        rel_path.trim_start_matches('/')
+    );
+    Ok(RemoteLayout::new(remote_prefix).manifest_key(&entry.manifest_hash))
+}
+
+/// Read the index entry of a file's path relative to `remote_prefix`.
+///
+/// Fails if there is no entry, or if the entry is a symlink or a tombstone.
+pub async fn resolve_index_entry(
+    op: &Operator,
+    remote_prefix: &str,
+    rel_path: &str,
+) -> Result<IndexEntry> {
+    let layout = RemoteLayout::new(remote_prefix);
+    let prefix = layout.prefix();
+    let rel = rel_path.trim_start_matches('/');
+    tcfs_core::paths::normalize_rel_path(rel)?;
+    let key = layout.index_key(rel);
+
+    let data = op
+        .read(&key)
+        .await
+        .with_context(|| format!("no index entry for {rel} under {prefix}"))?;
+    let entry = IndexEntry::from_bytes(&data.to_bytes())
+        .with_context(|| format!("parsing index entry: {key}"))?;
+    anyhow::ensure!(!entry.is_tombstone(), "{rel} was deleted");
+    if let Some(target) = &entry.symlink {
+        anyhow::bail!("{rel} is a symlink to {target}, not a file");
+    }
+    Ok(entry)
+}
+
+/// Download the file whose index entry for `rel_path` is `entry` to
+/// `local_path`: a packed file from its range of the pack, any other
+/// through its manifest as [`download_file_with_progress`] does. The mode
+/// the entry records is restored with the bits in `mode_umask` cleared.
+///
+/// Everything that fetches a file by its index entry goes through here or
+/// [`read_indexed`], so none of it needs to know how the file was stored.
+#[allow(clippy::too_many_arguments)]
+pub async fn download_indexed(
+    op: &Operator,
+    remote_prefix: &str,
+    rel_path: &str,
+    entry: &IndexEntry,
+    local_path: &Path,
+    progress: DownloadProgress<'_>,
+    device_id: &str,
+    state: Option<&StateCache>,
+    encryption: OptionalEncryption<'_>,
+    mode_umask: u32,
+) -> Result<DownloadResult> {
+    let Some(pack) = &entry.pack else {
+        return download_file_with_progress(
+            op,
+            &entry.manifest_path(remote_prefix),
+            local_path,
+            remote_prefix,
+            progress,
+            device_id,
+            state,
+            encryption,
+            RestoreMode::indexed(entry, mode_umask),
+        )
+        .await;
+    };
+
+    if let Some(state) = state.filter(|s| s.guards_local_edits()) {
+        check_local_edits(state, local_path, &entry.manifest_hash)?;
+    }
+    let bytes =
+        crate::pack::pull_packed(op, remote_prefix, rel_path, entry, local_path, mode_umask)
+            .await?;
+    for cb in [progress.chunks, progress.bytes].into_iter().flatten() {
+        cb(bytes, bytes, rel_path);
+    }
+
+    let remote_path = RemoteLayout::new(remote_prefix).pack_key(&pack.id);
+    if let (Some(state), false) = (state, device_id.is_empty()) {
+        // A packed file has no vector clock to merge; keep the local one
+        let vclock = state.get(local_path).map(|s| s.vclock).unwrap_or_default();
+        let sync_state = make_sync_state_at(
+            local_path,
+            entry.manifest_hash.clone(),
+            0,
+            remote_path.clone(),
+            vclock,
+            device_id.to_string(),
+            state.now_secs(),
+        )?;
+        state.set(local_path, sync_state);
+    }
+    info!(remote = %remote_path, local = %local_path.display(), bytes, "downloaded packed file");
+
+    Ok(DownloadResult {
+        remote_path,
+        local_path: local_path.to_path_buf(),
+        bytes,
+    })
+}
+
+/// Read the whole content of the file whose index entry for `rel_path` is
+/// `entry`: a packed file's range of its pack, any other file's manifest
+/// chunks. Either is checked against the content hash before it is
+/// returned.
+pub async fn read_indexed(
+    op: &Operator,
+    remote_prefix: &str,
+    rel_path: &str,
+    entry: &IndexEntry,
+    encryption: OptionalEncryption<'_>,
+) -> Result<Vec<u8>> {
+    if let Some(pack) = &entry.pack {
+        return crate::pack::read_packed(op, remote_prefix, rel_path, &entry.manifest_hash, pack)
+            .await;
+    }
+
+    let remote_manifest = entry.manifest_path(remote_prefix);
+    let data = op
+        .read(&remote_manifest)
+        .await
+        .with_context(|| format!("reading manifest: {remote_manifest}"))?;
+    let manifest = SyncManifest::from_bytes(&data.to_bytes())
+        .with_context(|| format!("parsing manifest: {remote_manifest}"))?;
+    let content = assemble_chunks(
+        op,
+        &manifest,
+        &remote_manifest,
+        remote_prefix,
+        encryption,
+        None,
+    )
+    .await?;
+    let actual = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&content));
+    if actual != manifest.file_hash {
+        return Err(FileHashMismatch {
+            manifest: remote_manifest,
+            expected: manifest.file_hash,
+            actual,
+        }
+        .into());
+    }
+    Ok(content)
+}
+
+/// Why a chunk referenced by a manifest failed verification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkProblem {
+    /// Nothing is stored under the chunk's key
+    Missing,
+    /// The stored bytes hash to `actual` rather than to the key
+    HashMismatch { actual: String },
+}
+
+/// A chunk found missing or corrupt by [`verify_prefix`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyItem {
+    /// Storage key of the manifest referencing the chunk
+    pub manifest: String,
+    /// Relative path recorded in the manifest, if any
+    pub rel_path: Option<String>,
+    /// Position of the chunk within the manifest
+    pub chunk_index: usize,
+    /// Chunk key (BLAKE3 of the stored bytes) listed in the manifest
+    pub chunk_hash: String,
+    pub problem: ChunkProblem,
+}
+
+/// Check every chunk referenced by the manifests under `remote_prefix`.
+///
+/// Each chunk is read and its stored bytes hashed against its key; chunks
+/// shared between manifests are only fetched once. Returns one item per
+/// manifest slot that is missing or corrupt, ready for [`repair_chunk`].
+/// Manifests that fail to parse are logged and skipped.
+pub async fn verify_prefix(op: &Operator, remote_prefix: &str) -> Result<Vec<VerifyItem>> {
+    let layout = RemoteLayout::new(remote_prefix);
+    let dir = layout.manifests_dir();
+    let entries = op
+        .list(&dir)
+        .await
+        .with_context(|| format!("listing manifests: {dir}"))?;
+
+    let mut checked: std::collections::HashMap<String, Option<ChunkProblem>> =
+        std::collections::HashMap::new();
+    let mut items = Vec::new();
+    for entry in entries {
+        if entry.metadata().is_dir() {
+            continue;
+        }
+        let manifest_key = entry.path().to_string();
+        let data = op
+            .read(&manifest_key)
+            .await
+            .with_context(|| format!("reading manifest: {manifest_key}"))?;
+        let manifest = match SyncManifest::from_bytes(&data.to_bytes()) {
+            Ok(m) => m,
+            Err(e) => {
+                warn!(manifest = %manifest_key, "skipping unreadable manifest: {e}");
+                continue;
+            }
+        };
+
+        let chunk_layout = manifest.chunk_layout(remote_prefix);
+        for (i, hash) in manifest.chunk_hashes().iter().enumerate() {
+            if !checked.contains_key(hash) {
+                let problem = check_chunk(op, &chunk_layout, hash).await?;
+                checked.insert(hash.clone(), problem);
+            }
+            if let Some(problem) = &checked[hash] {
+                items.push(VerifyItem {
+                    manifest: manifest_key.clone(),
+                    rel_path: manifest.rel_path.clone(),
+                    chunk_index: i,
+                    chunk_hash: hash.clone(),
+                    problem: problem.clone(),
+                });
+            }
+        }
+    }
+    Ok(items)
+}
+
+async fn check_chunk(
+    op: &Operator,
+    layout: &RemoteLayout,
+    hash: &str,
+) -> Result<Option<ChunkProblem>> {
+    let chunk_key = layout.chunk_key(hash);
+    let data = match op.read(&chunk_key).await {
+        Ok(data) => data,
+        Err(e) if e.kind() == opendal::ErrorKind::NotFound => {
+            return Ok(Some(ChunkProblem::Missing))
+        }
+        Err(e) => return Err(e).with_context(|| format!("reading chunk: {chunk_key}")),
+    };
+    let actual = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&data.to_bytes()));
+    Ok((actual != hash).then_some(ChunkProblem::HashMismatch { actual }))
+}
+
+/// Re-upload the chunk described by `item` from a local copy of its file.
+///
+/// `local_path` must hash to the manifest's `file_hash`; the chunk is then
+/// rebuilt from it with the same boundaries and compression as the original
+/// push. Unencrypted chunks are rewritten under their original key. Encrypted
+/// chunks cannot be reproduced byte-for-byte (nonces are random), so the
+/// chunk is re-encrypted under the manifest's file key, stored under its new
+/// key, and the manifest slot is updated with a conditional write.
+/// Fails with [`ChunkUnrepairable`] when the local copy cannot be used.
+#[allow(unused_variables, unused_mut)]
+pub async fn repair_chunk(
+    op: &Operator,
+    remote_prefix: &str,
+    item: &VerifyItem,
+    local_path: &Path,
+    encryption: OptionalEncryption<'_>,
+) -> Result<()> {
+    let unrepairable = |reason: &str| ChunkUnrepairable {
+        manifest: item.manifest.clone(),
+        chunk_index: item.chunk_index,
+        reason: reason.to_string(),
+    };
+
+    let manifest_version = stat_version(op, &item.manifest).await?;
+    let data = op
+        .read(&item.manifest)
+        .await
+        .with_context(|| format!("reading manifest: {}", item.manifest))?;
+    let mut manifest = SyncManifest::from_bytes(&data.to_bytes())
+        .with_context(|| format!("parsing manifest: {}", item.manifest))?;
+    let store = ChunkStore::new(op.clone(), manifest.chunk_layout(remote_prefix));
+    let i = item.chunk_index;
+    if manifest.chunk_hashes().get(i) != Some(&item.chunk_hash) {
+        return Err(unrepairable("manifest changed since it was verified").into());
+    }
+
+    let (chunks, data) = tcfs_chunks::chunk_file(local_path)
+        .with_context(|| format!("chunking: {}", local_path.display()))?;
+    let file_hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&data));
+    if file_hash != manifest.file_hash {
+        return Err(unrepairable("local copy does not match the manifest's file hash").into());
+    }
+    let Some(chunk) = chunks
+        .get(i)
+        .filter(|_| chunks.len() == manifest.chunks.len())
+    else {
+        return Err(unrepairable("local copy chunks differently than the manifest").into());
+    };
+
+    let chunk_data = &data[chunk.offset as usize..chunk.offset as usize + chunk.length];
+    let compressed = if manifest.chunk_compressed(i) {
+        match compress_chunk(chunk_data).with_context(|| format!("compressing chunk {i}"))? {
+            Some(bytes) => Some(bytes),
+            None => return Err(unrepairable("chunk no longer compresses as recorded").into()),
+        }
+    } else {
+        None
+    };
+
+    if manifest.is_convergent() {
+        #[cfg(feature = "crypto")]
+        {
+            let ctx = encryption.ok_or_else(|| KeyRequired {
+                manifest: item.manifest.clone(),
+            })?;
+            // Re-encryption is deterministic, so the rebuilt chunk must land
+            // on its original key and the manifest stays as it is
+            let plaintext = compressed.as_deref().unwrap_or(chunk_data);
+            let (_, ciphertext) = tcfs_crypto::encrypt_chunk_convergent(&ctx.master_key, plaintext)
+                .with_context(|| format!("encrypting chunk {i}"))?;
+            let ct_hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&ciphertext));
+            if ct_hash != item.chunk_hash {
+                return Err(unrepairable("rebuilt chunk does not hash to its key").into());
+            }
+            store
+                .put_chunk(&ct_hash, ciphertext)
+                .await
+                .with_context(|| format!("uploading chunk {i}"))?;
+            info!(manifest = %item.manifest, chunk = i, "repaired convergent chunk");
+            return Ok(());
+        }
+        #[cfg(not(feature = "crypto"))]
+        return Err(unrepairable("manifest is encrypted and crypto support is disabled").into());
+    }
+
+    if let Some(wrapped_b64) = manifest.encrypted_file_key.clone() {
+        #[cfg(feature = "crypto")]
+        {
+            let ctx = encryption.ok_or_else(|| KeyRequired {
+                manifest: item.manifest.clone(),
+            })?;
+            let wrapped =
+                base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &wrapped_b64)
+                    .context("decoding wrapped file key from manifest")?;
+            let file_key = tcfs_crypto::unwrap_key(&ctx.master_key, &wrapped)
+                .context("unwrapping file key from manifest")?;
+            let file_id = *tcfs_chunks::hash_from_hex(&manifest.file_hash)
+                .context("parsing manifest file_hash for encryption file_id")?
+                .as_bytes();
+            let plaintext = compressed.as_deref().unwrap_or(chunk_data);
+            let ciphertext = tcfs_crypto::encrypt_chunk(&file_key, i as u64, &file_id, plaintext)
+                .with_context(|| format!("encrypting chunk {i}"))?;
+            let ct_hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&ciphertext));
+            store
+                .put_chunk(&ct_hash, ciphertext)
+                .await
+                .with_context(|| format!("uploading chunk {i}"))?;
+
+            manifest.chunks[i] = ct_hash;
+            let bytes = manifest.to_bytes()?;
+            write_conditional_with(
+                op,
+                &item.manifest,
+                bytes,
+                &manifest_version,
+                ObjectMeta::MANIFEST,
+            )
+            .await
+            .with_context(|| format!("updating manifest: {}", item.manifest))?;
+            info!(manifest = %item.manifest, chunk = i, "repaired encrypted chunk");
+            return Ok(());
+        }
+        #[cfg(not(feature = "crypto"))]
+        return Err(unrepairable("manifest is encrypted and crypto support is disabled").into());
+    }
+
+    let (bytes, hash) = stored_chunk(chunk, chunk_data, compressed);
+    if hash != item.chunk_hash {
+        return Err(unrepairable("rebuilt chunk does not hash to its key").into());
+    }
+    store
+        .put_chunk(&hash, bytes)
+        .await
+        .with_context(|| format!("uploading chunk {i}"))?;
+    info!(manifest = %item.manifest, chunk = i, "repaired chunk");
+    Ok(())
+}
+
+pub(crate) enum PulledEntry {
+    File(u64),
+    Dir,
+    Skipped,
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn pull_index_key(
+    op: &Operator,
+    prefix: &str,
+    key: &str,
+    rel: &str,
+    local_rel: &str,
+    local_root: &Path,
+    since: Option<u64>,
+    mode_umask: u32,
+) -> Result<PulledEntry> {
+    let (dir, name) = rel.rsplit_once('/').unwrap_or(("", rel));
+    if name == DIR_MARKER {
+        if dir.is_empty() {
+            return Ok(PulledEntry::Skipped);
+        }
+        let marker = tcfs_core::paths::normalize_rel_path(rel)?;
+        tcfs_core::paths::check_no_symlink_ancestors(local_root, &marker)?;
+        let local_dir = local_root.join(tcfs_core::paths::normalize_rel_path(dir)?);
+        tokio::fs::create_dir_all(&local_dir)
+            .await
+            .with_context(|| format!("creating dir: {}", local_dir.display()))?;
+        return Ok(PulledEntry::Dir);
+    }
+
+    let local_rel = tcfs_core::paths::normalize_rel_path(local_rel)?;
+    // An earlier entry may have been a symlink to a directory outside the root
+    tcfs_core::paths::check_no_symlink_ancestors(local_root, &local_rel)?;
+    let local_path = local_root.join(local_rel);
+    let data = op
+        .read(key)
+        .await
+        .with_context(|| format!("reading index entry: {key}"))?;
+    let entry = IndexEntry::from_bytes(&data.to_bytes())
+        .with_context(|| format!("parsing index entry: {key}"))?;
+
+    if entry.is_tombstone() {
+        return Ok(PulledEntry::Skipped);
+    }
+    if let Some(since) = since {
+        if !changed_since(op, prefix, &entry, since).await? {
+            return Ok(PulledEntry::Skipped);
+        }
+    }
+
+    if let Some(target) = &entry.symlink {
+        if let Some(parent) = local_path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("creating dir: {}", parent.display()))?;
+        }
+        return restore_symlink(rel, target, &local_path).await;
+    }
+
+    let result = download_indexed(
+        op,
+        prefix,
+        rel,
+        &entry,
+        &local_path,
+        DownloadProgress::default(),
+        "",
+        None,
+        None,
+        mode_umask,
+    )
+    .await?;
+    Ok(PulledEntry::File(result.bytes))
+}
+
+/// Whether the file behind `entry` was written after `since`: by its
+/// manifest's `written_at`, else its index mtime, else assumed so.
+async fn changed_since(
+    op: &Operator,
+    prefix: &str,
+    entry: &IndexEntry,
+    since: u64,
+) -> Result<bool> {
+    let mut written_at = 0;
+    if entry.symlink.is_none() && !entry.is_packed() {
+        let key = entry.manifest_path(prefix);
+        let data = op
+            .read(&key)
+            .await
+            .with_context(|| format!("reading manifest: {key}"))?;
+        written_at = SyncManifest::from_bytes(&data.to_bytes())
+            .with_context(|| format!("parsing manifest: {key}"))?
+            .written_at;
+    }
+    Ok(match (written_at, entry.modified) {
+        (0, None) => true,
+        (0, Some(modified)) => modified > since,
+        (written_at, _) => written_at > since,
+    })
+}
+
+/// Recreate the symlink stored at `rel` as `local_path`, refusing targets
+/// that are absolute or climb out of the sync root.
+pub(crate) async fn restore_symlink(
+    rel: &str,
+    target: &str,
+    local_path: &Path,
+) -> Result<PulledEntry> {
+    tcfs_core::paths::check_symlink_target(rel, target)?;
+    #[cfg(unix)]
+    {
+        if is_symlink(local_path) {
+            tokio::fs::remove_file(local_path).await?;
+        }
+        tokio::fs::symlink(target, local_path)
+            .await
+            .with_context(|| format!("creating symlink: {}", local_path.display()))?;
+        Ok(PulledEntry::File(0))
+    }
+    #[cfg(not(unix))]
+    {
+        warn!(path = %local_path.display(), target = %target, "symlinks not supported, skipping");
+        Ok(PulledEntry::Skipped)
+    }
+}
+
+/// Default `mode_umask` for restored file modes (group/other write cleared).
+pub const DEFAULT_MODE_UMASK: u32 = 0o022;
+
+/// The file mode a download restores, and the bits it clears from it (see
+/// `sync.mode_umask`).
+///
+/// Paths with identical content share one manifest, so the mode a manifest
+/// records is that of whichever path pushed it last; a download for a known
+/// path takes the mode from the path's index entry instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestoreMode {
+    indexed: Option<Option<u32>>,
+    umask: u32,
+}
+
+impl RestoreMode {
+    /// Restore the mode the manifest records, for downloads not tied to an
+    /// index entry (by manifest path, from a stub, from history).
+    pub fn manifest(umask: u32) -> Self {
+        Self {
+            indexed: None,
+            umask,
+        }
+    }
+
+    /// Restore the mode `entry` records for its path; an entry without one
+    /// leaves the file as created.
+    pub fn indexed(entry: &IndexEntry, umask: u32) -> Self {
+        Self {
+            indexed: Some(entry.mode),
+            umask,
+        }
+    }
+
+    /// The mode to apply to a file downloaded through `manifest`.
+    fn mode(&self, manifest: &SyncManifest) -> Option<u32> {
+        self.indexed.unwrap_or(manifest.mode)
+    }
+}
+
+impl Default for RestoreMode {
+    fn default() -> Self {
+        Self::manifest(DEFAULT_MODE_UMASK)
+    }
+}
+
+/// Unix permission bits (`0o777`) of `path`; `None` where modes don't exist.
+pub fn file_mode(path: &Path) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::metadata(path)
+            .ok()
+            .map(|m| m.permissions().mode() & 0o777)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// Restore a recorded file mode on `path`, clearing the bits in `umask`.
+///
+/// A `None` mode (older manifests, non-Unix senders) leaves the file as created.
+pub fn apply_mode(path: &Path, mode: Option<u32>, umask: u32) -> Result<()> {
+    let Some(mode) = mode else {
+        return Ok(());
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let perms = std::fs::Permissions::from_mode(mode & 0o777 & !umask);
+        std::fs::set_permissions(path, perms)
+            .with_context(|| format!("setting mode {:o}: {}", mode, path.display()))?;
+    }
+    #[cfg(not(unix))]
+    let _ = (path, mode, umask);
+    Ok(())
+}
+
+/// Temporary path a download is written to before being renamed over `path`.
+///
+/// The suffix is appended to the full file name so `a.txt` and `a.md` never
+/// share a temp file.
+pub fn download_tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tcfs_tmp");
+    path.with_file_name(name)
+}
 
-    for (i, hash) in chunk_hashes.iter().enumerate() {
-        let chunk_key = format!("{remote_prefix}/chunks/{hash}");
-        let chunk_data = op
-            .read(&chunk_key)
-            .await
-            .with_context(|| format!("downloading chunk {i}: {chunk_key}"))?;
+/// Sidecar listing the chunks already written to a download's temp file.
+///
+/// One line per chunk, `<index> <length> <blake3>` of its plaintext, after
+/// a first line naming the `file_hash` being downloaded.
+pub fn download_resume_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tcfs_resume");
+    path.with_file_name(name)
+}
 
-        let chunk_bytes = chunk_data.to_bytes();
+/// A download's temp file, opened to continue after the chunks it already
+/// holds.
+struct PartialDownload {
+    file: std::fs::File,
+    journal: std::fs::File,
+    /// Chunks at the start of `file` that check out against the sidecar
+    chunks: usize,
+    /// Whole-file hash state over those chunks
+    hasher: tcfs_chunks::Hasher,
+    bytes: u64,
+}
 
-        // Verify chunk integrity: BLAKE3 hash must match the manifest entry
-        let actual_hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&chunk_bytes));
-        if actual_hash != *hash {
-            anyhow::bail!(
-                "chunk integrity check failed for {chunk_key}: expected {hash}, got {actual_hash}"
-            );
-        }
+impl PartialDownload {
+    /// Open `tmp` to download `manifest`, keeping the leading chunks the
+    /// sidecar at `resume` lists for the same `file_hash` whose bytes still
+    /// hash as recorded, and truncating everything after them.
+    ///
+    /// Neither file is fsynced per chunk: a sidecar line whose data never
+    /// reached the disk fails the re-hash and is fetched again.
+    fn open(tmp: &Path, resume: &Path, manifest: &SyncManifest) -> Result<Self> {
+        use std::io::{Read, Seek, Write};
 
-        // Decrypt chunk if file key is present
-        #[cfg(feature = "crypto")]
-        let plaintext = if let (Some(ref fk), Some(ref fid)) = (&file_key, &file_id) {
-            tcfs_crypto::decrypt_chunk(fk, i as u64, fid, &chunk_bytes)
-                .with_context(|| format!("decrypting chunk {i}"))?
+        let recorded = std::fs::read_to_string(resume).unwrap_or_default();
+        let mut lines = recorded.lines();
+        let same_file = lines.next() == Some(manifest.file_hash.as_str());
+        let file = if same_file {
+            std::fs::OpenOptions::new().read(true).write(true).open(tmp)
         } else {
-            chunk_bytes.to_vec()
+            std::fs::File::create(tmp)
+        };
+        let (mut file, same_file) = match file {
+            Ok(file) => (file, same_file),
+            Err(e) if same_file && e.kind() == std::io::ErrorKind::NotFound => (
+                std::fs::File::create(tmp)
+                    .with_context(|| format!("creating tmp: {}", tmp.display()))?,
+                false,
+            ),
+            Err(e) => return Err(e).with_context(|| format!("creating tmp: {}", tmp.display())),
         };
 
-        #[cfg(not(feature = "crypto"))]
-        let plaintext = chunk_bytes.to_vec();
-
-        assembled.extend_from_slice(&plaintext);
-
-        if let Some(cb) = progress {
-            cb(
-                (i + 1) as u64,
-                total as u64,
-                &format!("chunk {}/{total}", i + 1),
-            );
+        let mut kept = Vec::new();
+        let mut hasher = tcfs_chunks::Hasher::new();
+        let mut bytes = 0u64;
+        if same_file {
+            let total = manifest.chunk_hashes().len();
+            // Lengths come from the sidecar, so never allocate past what the
+            // tmp file holds or the manifest says the file has
+            let mut limit = file.metadata().map_or(0, |m| m.len());
+            if !manifest.is_legacy() {
+                limit = limit.min(manifest.file_size);
+            }
+            let mut buf = Vec::new();
+            for (i, line) in lines.enumerate().take(total) {
+                let mut fields = line.split(' ');
+                let (Some(index), Some(len), Some(hash), None) =
+                    (fields.next(), fields.next(), fields.next(), fields.next())
+                else {
+                    break;
+                };
+                let (Ok(index), Ok(len)) = (index.parse::<usize>(), len.parse::<usize>()) else {
+                    break;
+                };
+                if len as u64 > limit - bytes {
+                    break;
+                }
+                buf.resize(len, 0);
+                if index != i || file.read_exact(&mut buf).is_err() {
+                    break;
+                }
+                if tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&buf)) != hash {
+                    break;
+                }
+                hasher.update(&buf);
+                bytes += len as u64;
+                kept.push(line);
+            }
+            file.set_len(bytes)
+                .with_context(|| format!("truncating tmp: {}", tmp.display()))?;
+            file.seek(std::io::SeekFrom::Start(bytes))
+                .with_context(|| format!("seeking tmp: {}", tmp.display()))?;
         }
-    }
 
-    let bytes = assembled.len() as u64;
+        let mut journal = std::fs::File::create(resume)
+            .with_context(|| format!("creating {}", resume.display()))?;
+        writeln!(journal, "{}", manifest.file_hash)
+            .and_then(|()| kept.iter().try_for_each(|line| writeln!(journal, "{line}")))
+            .with_context(|| format!("writing {}", resume.display()))?;
 
-    // Verify reassembled file hash matches the manifest (plaintext hash)
-    let actual_file_hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&assembled));
-    if actual_file_hash != manifest.file_hash {
-        anyhow::bail!(
-            "file integrity check failed for {remote_manifest}: expected {}, got {actual_file_hash}",
-            manifest.file_hash
-        );
+        Ok(Self {
+            file,
+            journal,
+            chunks: kept.len(),
+            hasher,
+            bytes,
+        })
     }
+}
 
-    // Atomic write to local path
-    if let Some(parent) = local_path.parent() {
+/// Write `data` to `path` atomically: write and fsync a temp file next to it,
+/// restore `mode`, then rename it into place.
+///
+/// On failure the temp file is removed and any existing file at `path` is
+/// left untouched, so a crash or I/O error never leaves a truncated file.
+pub async fn write_file_atomic(
+    path: &Path,
+    data: &[u8],
+    mode: Option<u32>,
+    mode_umask: u32,
+) -> Result<()> {
+    if let Some(parent) = path.parent() {
         tokio::fs::create_dir_all(parent)
             .await
             .with_context(|| format!("creating dir: {}", parent.display()))?;
     }
 
-    let tmp = local_path.with_extension("tcfs_tmp");
-    tokio::fs::write(&tmp, &assembled)
-        .await
-        .with_context(|| format!("writing tmp: {}", tmp.display()))?;
-    tokio::fs::rename(&tmp, local_path)
-        .await
-        .with_context(|| format!("renaming to: {}", local_path.display()))?;
-
-    // Merge remote vclock into local state if we have a state cache
-    if let Some(state) = state {
-        if !_device_id.is_empty() {
-            let mut local_vclock = state
-                .get(local_path)
-                .map(|s| s.vclock.clone())
-                .unwrap_or_default();
-            local_vclock.merge(&manifest.vclock);
+    let tmp = download_tmp_path(path);
+    let result = async {
+        write_and_sync(&tmp, data)
+            .await
+            .with_context(|| format!("writing tmp: {}", tmp.display()))?;
+        apply_mode(&tmp, mode, mode_umask)?;
+        rename_replacing(&tmp, path)
+            .await
+            .with_context(|| format!("renaming to: {}", path.display()))
+    }
+    .await;
 
-            let file_hash = tcfs_chunks::hash_bytes(&assembled);
-            let file_hash_hex = tcfs_chunks::hash_to_hex(&file_hash);
+    if result.is_err() {
+        remove_partial_download(&tmp).await;
+    }
+    result
+}
 
-            let sync_state = make_sync_state_full(
-                local_path,
-                file_hash_hex,
-                total,
-                remote_manifest.to_string(),
-                local_vclock,
-                _device_id.to_string(),
-            )?;
-            state.set(local_path, sync_state);
+/// Remove a temp file left by a failed download, if there is one.
+async fn remove_partial_download(tmp: &Path) {
+    if let Err(e) = tokio::fs::remove_file(tmp).await {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!(tmp = %tmp.display(), "failed to remove partial download: {e}");
         }
     }
+}
 
-    info!(
-        remote = %remote_manifest,
-        local = %local_path.display(),
-        bytes,
-        "downloaded"
-    );
+async fn write_and_sync(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    use tokio::io::AsyncWriteExt;
 
-    Ok(DownloadResult {
-        remote_path: remote_manifest.to_string(),
-        local_path: local_path.to_path_buf(),
-        bytes,
-    })
+    let mut file = tokio::fs::File::create(path).await?;
+    file.write_all(data).await?;
+    file.sync_all().await
 }
 
-/// Walk a local directory and upload all changed files.
+/// Rename `from` over `to`.
 ///
-/// Returns stats: (files_uploaded, files_skipped, bytes_uploaded)
-pub async fn push_tree(
+/// Windows refuses to replace a read-only destination, so there the
+/// attribute is cleared and the rename retried once.
+async fn rename_replacing(from: &Path, to: &Path) -> std::io::Result<()> {
+    match tokio::fs::rename(from, to).await {
+        #[cfg(windows)]
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            let meta = tokio::fs::metadata(to).await.map_err(|_| e)?;
+            let mut perms = meta.permissions();
+            #[allow(clippy::permissions_set_readonly_false)]
+            perms.set_readonly(false);
+            tokio::fs::set_permissions(to, perms).await?;
+            tokio::fs::rename(from, to).await
+        }
+        result => result,
+    }
+}
+
+/// Returns true if `path` is itself a symlink (without following it).
+pub fn is_symlink(path: &Path) -> bool {
+    std::fs::symlink_metadata(path)
+        .map(|m| m.file_type().is_symlink())
+        .unwrap_or(false)
+}
+
+/// Record a symlink in the remote index without following it.
+///
+/// The link target is stored in the index entry at `{prefix}/index/{rel_path}`;
+/// no chunks or manifest are written. Returns a skipped result when the index
+/// already holds the same target.
+pub async fn push_symlink(
     op: &Operator,
-    local_root: &Path,
+    path: &Path,
     remote_prefix: &str,
-    state: &mut StateCache,
-    progress: Option<&ProgressFn>,
-) -> Result<(usize, usize, u64)> {
-    push_tree_with_device(
-        op,
-        local_root,
-        remote_prefix,
-        state,
-        progress,
-        "",
-        None,
-        None,
-    )
-    .await
+    rel_path: &str,
+) -> Result<UploadResult> {
+    symlink_push(op, path, remote_prefix, rel_path, None).await
 }
 
-/// Push tree with device identity, optional collection config, and optional encryption.
-pub async fn push_tree_with_device(
+/// [`push_symlink`], holding the index entry in `stage` when given.
+async fn symlink_push(
     op: &Operator,
-    local_root: &Path,
+    path: &Path,
     remote_prefix: &str,
-    state: &mut StateCache,
-    progress: Option<&ProgressFn>,
-    device_id: &str,
-    collect_cfg: Option<&CollectConfig>,
-    encryption: OptionalEncryption<'_>,
-) -> Result<(usize, usize, u64)> {
-    let mut uploaded = 0usize;
-    let mut skipped = 0usize;
-    let mut bytes = 0u64;
+    rel_path: &str,
+    stage: Option<&IndexStage>,
+) -> Result<UploadResult> {
+    let target =
+        std::fs::read_link(path).with_context(|| format!("reading symlink: {}", path.display()))?;
+    let target = target
+        .to_str()
+        .with_context(|| format!("symlink target is not UTF-8: {}", path.display()))?
+        .to_string();
+    anyhow::ensure!(
+        !target.contains('\n'),
+        "symlink target contains a newline: {}",
+        path.display()
+    );
 
-    let cfg = collect_cfg.cloned().unwrap_or_default();
-    let files = collect_files(local_root, &cfg)?;
-    let total = files.len();
+    let modified = std::fs::symlink_metadata(path)
+        .ok()
+        .and_then(|m| m.modified().ok())
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs());
 
-    for (i, path) in files.iter().enumerate() {
-        let rel = path.strip_prefix(local_root).unwrap_or(path);
-        let rel_str = rel.to_string_lossy().replace('\\', "/");
+    let index_key = RemoteLayout::new(remote_prefix).index_key(rel_path);
 
-        let msg = format!("[{}/{}] {}", i + 1, total, rel.display());
-        if let Some(cb) = progress {
-            cb(i as u64, total as u64, &msg);
-        }
+    let existing = match op.read(&index_key).await {
+        Ok(data) => IndexEntry::from_bytes(&data.to_bytes()).ok(),
+        Err(_) => None,
+    };
+    let skipped = existing.is_some_and(|e| e.symlink.as_deref() == Some(target.as_str()));
 
-        match upload_file_with_device(
+    if !skipped {
+        put_index(
             op,
-            path,
-            &remote_path_prefix(remote_prefix),
-            state,
-            None,
-            device_id,
-            Some(&rel_str),
-            encryption,
+            stage,
+            &index_key,
+            IndexEntry::new_symlink(&target, modified).to_bytes(),
+            &ObjectVersion::Unknown,
         )
         .await
-        {
-            Ok(result) => {
-                // Write index entry: maps relative path → manifest hash + metadata.
-                // This allows the FUSE driver to list files by original name.
-                let index_key = format!("{}/index/{}", remote_path_prefix(remote_prefix), rel_str);
-                let index_entry = format!(
-                    "manifest_hash={}\nsize={}\nchunks={}\n",
-                    result.hash, result.bytes, result.chunks
-                );
-                if let Err(e) = op.write(&index_key, index_entry.into_bytes()).await {
-                    warn!(path = %path.display(), "failed to write index entry: {e}");
-                }
+        .with_context(|| format!("writing index entry: {index_key}"))?;
+        debug!(path = %path.display(), target = %target, "recorded symlink");
+    }
 
-                if result.skipped {
-                    skipped += 1;
-                } else {
-                    uploaded += 1;
-                    bytes += result.bytes;
-                }
-            }
-            Err(e) => {
-                warn!(path = %path.display(), "upload failed: {e}");
-            }
+    Ok(UploadResult {
+        path: path.to_path_buf(),
+        remote_path: index_key,
+        hash: String::new(),
+        chunks: 0,
+        bytes: 0,
+        skipped,
+        outcome: None,
+        new_chunks: 0,
+        deduped_chunks: 0,
+        reused_chunks: 0,
+        dry_run: false,
+    })
+}
+
+/// One chunk after [`upload_file`] has stored it.
+struct StoredChunk {
+    /// Storage hash (of the ciphertext, when encrypted)
+    hash: String,
+    compressed: bool,
+    /// Wrapped per-chunk key, in convergent encryption mode
+    wrapped_key: Option<String>,
+    /// Whether this push wrote the chunk rather than finding it stored
+    uploaded: bool,
+    /// Whether the chunk was taken from the file's previous version
+    reused: bool,
+}
+
+/// zstd level used for chunk compression.
+pub const CHUNK_ZSTD_LEVEL: i32 = 3;
+
+/// Extensions of formats that are already compressed; their chunks are stored
+/// raw rather than run through zstd for no gain.
+pub const INCOMPRESSIBLE_EXTENSIONS: &[&str] = &[
+    "7z", "apk", "avi", "avif", "bz2", "deb", "dmg", "docx", "flac", "gif", "gz", "heic", "jar",
+    "jpeg", "jpg", "m4a", "m4v", "mkv", "mov", "mp3", "mp4", "odt", "ogg", "opus", "png", "pptx",
+    "rar", "rpm", "tgz", "webm", "webp", "whl", "xlsx", "xz", "zip", "zst",
+];
+
+/// Leading bytes of a file sampled by [`should_compress`].
+pub const COMPRESSION_SAMPLE_BYTES: usize = 64 * 1024;
+
+/// Shannon entropy (bits per byte) above which a sample is treated as
+/// already compressed or encrypted.
+const INCOMPRESSIBLE_ENTROPY: f64 = 7.5;
+
+/// Whether the chunks of `path` are worth compressing, judged by its
+/// extension and the byte entropy of `sample` (the start of its content).
+pub fn should_compress(path: &Path, sample: &[u8]) -> bool {
+    should_compress_with(path, sample, None)
+}
+
+/// [`should_compress`] with `skip_extensions` (case-insensitive, no leading
+/// dot) replacing [`INCOMPRESSIBLE_EXTENSIONS`] when given.
+pub fn should_compress_with(
+    path: &Path,
+    sample: &[u8],
+    skip_extensions: Option<&[String]>,
+) -> bool {
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        let skipped = match skip_extensions {
+            Some(list) => list
+                .iter()
+                .any(|s| s.trim_start_matches('.').eq_ignore_ascii_case(ext)),
+            None => INCOMPRESSIBLE_EXTENSIONS
+                .iter()
+                .any(|s| s.eq_ignore_ascii_case(ext)),
+        };
+        if skipped {
+            return false;
         }
     }
+    byte_entropy(sample) < INCOMPRESSIBLE_ENTROPY
+}
 
-    // Flush state cache after tree push
-    state.flush()?;
+/// Shannon entropy of `data` in bits per byte (0.0 for empty input).
+fn byte_entropy(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0usize; 256];
+    for &b in data {
+        counts[b as usize] += 1;
+    }
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// zstd-compress a chunk, returning `None` when that would not shrink it.
+pub fn compress_chunk(data: &[u8]) -> Result<Option<Vec<u8>>> {
+    let blob = tcfs_chunks::compress(
+        data,
+        tcfs_chunks::seekable_zstd::DEFAULT_FRAME_SIZE,
+        CHUNK_ZSTD_LEVEL,
+    )?;
+    Ok((blob.compressed.len() < data.len()).then_some(blob.compressed))
+}
+
+/// Bytes to store for an unencrypted chunk and the CAS key they live under.
+///
+/// Raw chunks keep their plaintext hash as key; compressed chunks are keyed
+/// by the hash of the compressed bytes, so a key always names exactly one
+/// stored encoding and chunk verification on download is unchanged.
+fn stored_chunk(
+    chunk: &tcfs_chunks::Chunk,
+    chunk_data: &[u8],
+    compressed: Option<Vec<u8>>,
+) -> (Vec<u8>, String) {
+    match compressed {
+        Some(bytes) => {
+            let hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&bytes));
+            (bytes, hash)
+        }
+        None => (chunk_data.to_vec(), tcfs_chunks::hash_to_hex(&chunk.hash)),
+    }
+}
 
-    Ok((uploaded, skipped, bytes))
+/// Resolve a configured concurrency: 0 means one slot per available CPU.
+pub fn effective_concurrency(requested: usize) -> usize {
+    if requested > 0 {
+        return requested;
+    }
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
 }
 
-/// Collect all regular files under `root` recursively, respecting config.
+/// Collect all regular files and symlinks under `root` recursively, respecting config.
+///
+/// Symlinks are returned as-is rather than followed; `push_tree` records
+/// them as link entries in the remote index.
 pub fn collect_files(root: &Path, config: &CollectConfig) -> Result<Vec<PathBuf>> {
+    collect_tree(root, config).map(|(files, _)| files)
+}
+
+/// Collect files as `collect_files` does, plus the directories under `root`
+/// that contain nothing collected.
+///
+/// Returns `(files, empty_dirs)`, both sorted. A directory whose only
+/// children are empty directories is not itself listed, since its
+/// descendants already imply it. Patterns in `root/.tcfsignore` are
+/// applied on top of `config.exclude_patterns`.
+pub fn collect_tree(root: &Path, config: &CollectConfig) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
     let mut files = Vec::new();
+    let mut empty_dirs = Vec::new();
     let exclude_matchers: Vec<glob::Pattern> = config
         .exclude_patterns
         .iter()
+        .chain(&read_ignore_file(root))
         .filter_map(|p| glob::Pattern::new(p).ok())
         .collect();
-    collect_files_inner(root, &mut files, config, &exclude_matchers)?;
+    collect_files_inner(root, &mut files, &mut empty_dirs, config, &exclude_matchers)?;
     files.sort(); // deterministic order
-    Ok(files)
+    empty_dirs.sort();
+
+    let rels: Vec<String> = files
+        .iter()
+        .filter_map(|f| f.strip_prefix(root).ok())
+        .map(|rel| rel.to_string_lossy().replace('\\', "/"))
+        .collect();
+    for group in tcfs_core::paths::case_collisions(rels.iter().map(String::as_str)) {
+        warn!(
+            paths = ?group,
+            "paths differ only in case; case-insensitive devices will pull them under distinct names"
+        );
+    }
+    Ok((files, empty_dirs))
 }
 
 fn collect_files_inner(
     dir: &Path,
     out: &mut Vec<PathBuf>,
+    empty_dirs: &mut Vec<PathBuf>,
     config: &CollectConfig,
     excludes: &[glob::Pattern],
 ) -> Result<()> {
@@ -721,7 +4605,7 @@ fn collect_files_inner(
 
             if meta.is_dir() {
                 // Always skip these
-                if name == "target" || name == "node_modules" || name == ".DS_Store" {
+                if ALWAYS_SKIPPED_DIRS.contains(&name) {
                     continue;
                 }
 
@@ -746,7 +4630,7 @@ fn collect_files_inner(
                             continue;
                         }
                         // In raw mode, recurse into .git
-                        collect_files_inner(&path, out, config, excludes)?;
+                        collect_files_inner(&path, out, empty_dirs, config, excludes)?;
                     }
                     continue;
                 }
@@ -756,16 +4640,368 @@ fn collect_files_inner(
                     continue;
                 }
 
-                collect_files_inner(&path, out, config, excludes)?;
+                let before = out.len() + empty_dirs.len();
+                collect_files_inner(&path, out, empty_dirs, config, excludes)?;
+                if out.len() + empty_dirs.len() == before {
+                    empty_dirs.push(path);
+                }
+            } else if meta.file_type().is_symlink() {
+                // Symlinks are recorded as links, never followed
+                out.push(path);
             } else if meta.is_file() {
+                if meta.len() == 0 && !config.sync_empty_files {
+                    debug!(path = %path.display(), "skipping empty file");
+                    continue;
+                }
                 out.push(path);
+            } else {
+                // Reading a FIFO blocks and a socket or device node fails
+                info!(
+                    path = %path.display(),
+                    kind = special_file_kind(&meta.file_type()),
+                    "skipping non-regular file"
+                );
             }
         }
     }
     Ok(())
 }
 
-/// Normalize a remote prefix: ensure it doesn't have trailing slash
-fn remote_path_prefix(prefix: &str) -> String {
-    prefix.trim_end_matches('/').to_string()
+/// What kind of non-regular, non-directory file `file_type` is, for logs.
+fn special_file_kind(file_type: &std::fs::FileType) -> &'static str {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if file_type.is_fifo() {
+            return "fifo";
+        }
+        if file_type.is_socket() {
+            return "socket";
+        }
+        if file_type.is_block_device() {
+            return "block device";
+        }
+        if file_type.is_char_device() {
+            return "character device";
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = file_type;
+    "special file"
+}
+
+/// Local sync state of one file, as reported by `tree_status`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileSyncStatus {
+    /// Tracked and unchanged since its last sync
+    Synced,
+    /// Tracked, but changed locally since its last sync (with the reason)
+    NeedsSync(String),
+    /// Never pushed
+    Untracked,
+}
+
+/// One row of `tree_status`.
+#[derive(Debug, Clone)]
+pub struct TreeStatusEntry {
+    pub path: PathBuf,
+    pub status: FileSyncStatus,
+    /// Unix timestamp of the last successful sync, for tracked files
+    pub last_synced: Option<u64>,
+}
+
+/// Report the sync state of every file under `root`.
+///
+/// Walks the tree with the same rules as `push_tree` (so excludes and
+/// hidden/`.git` handling apply) and classifies each file with
+/// `StateCache::needs_sync`. Symlinks are skipped: they are recorded in
+/// the remote index, not tracked in the state cache.
+pub fn tree_status(
+    root: &Path,
+    state: &StateCache,
+    config: &CollectConfig,
+) -> Result<Vec<TreeStatusEntry>> {
+    let mut entries = Vec::new();
+    for path in collect_files(root, config)? {
+        if is_symlink(&path) {
+            continue;
+        }
+        let Some(cached) = state.get(&path) else {
+            entries.push(TreeStatusEntry {
+                path,
+                status: FileSyncStatus::Untracked,
+                last_synced: None,
+            });
+            continue;
+        };
+        let last_synced = Some(cached.last_synced);
+        let status = match state.needs_sync(&path)? {
+            None => FileSyncStatus::Synced,
+            Some(reason) => FileSyncStatus::NeedsSync(reason),
+        };
+        entries.push(TreeStatusEntry {
+            path,
+            status,
+            last_synced,
+        });
+    }
+    Ok(entries)
+}
+
+/// The path, relative to the sync root, that `old` (a state cache key) had,
+/// given that `local_path` lies at `rel_path` under the same root.
+fn moved_from_rel(local_path: &Path, rel_path: &str, old: &Path) -> Option<String> {
+    let depth = rel_path.split('/').filter(|p| !p.is_empty()).count();
+    let local_path = std::fs::canonicalize(local_path).ok()?;
+    let root = local_path.ancestors().nth(depth)?;
+    let rel = old.strip_prefix(root).ok()?;
+    Some(rel.to_string_lossy().replace('\\', "/"))
+}
+
+/// Maximum number of re-checks after a conditional manifest write loses a race.
+const MAX_CAS_RETRIES: usize = 3;
+
+/// A conditional write failed because the object changed since it was read.
+#[derive(Debug, thiserror::Error)]
+#[error("concurrent modification of {path}")]
+pub struct ConcurrentModification {
+    pub path: String,
+}
+
+/// A downloaded chunk's bytes did not hash to the key it was stored under.
+#[derive(Debug, thiserror::Error)]
+#[error("chunk integrity check failed for {chunk_key}: expected {expected}, got {actual}")]
+pub struct ChunkHashMismatch {
+    pub chunk_key: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// A downloaded file's reassembled content did not hash to the manifest's
+/// `file_hash`, even though every chunk verified (e.g. chunks out of order).
+#[derive(Debug, thiserror::Error)]
+#[error("file integrity check failed for {manifest}: expected {expected}, got {actual}")]
+pub struct FileHashMismatch {
+    pub manifest: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// A download would overwrite a local file edited since its last sync.
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "{} has local changes (content {local_hash}, last synced {}); \
+     push them or pull with --force to overwrite",
+    path.display(),
+    synced_hash.as_deref().unwrap_or("never")
+)]
+pub struct LocallyModified {
+    pub path: PathBuf,
+    /// BLAKE3 hex hash of the file as it is now
+    pub local_hash: String,
+    /// Hash the state cache recorded at the last sync (`None` = untracked)
+    pub synced_hash: Option<String>,
+}
+
+/// A transactional tree push published nothing because files failed.
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "push to {prefix} rolled back: {failed} file(s) failed, {staged} index entries not published"
+)]
+pub struct TreePushRolledBack {
+    pub prefix: String,
+    /// Files that failed to upload
+    pub failed: usize,
+    /// Index entries that were held back
+    pub staged: usize,
+}
+
+/// A write was attempted through a state cache marked read-only.
+#[derive(Debug, thiserror::Error)]
+#[error("{prefix} is read-only; refusing to write to it")]
+pub struct ReadOnlyStore {
+    pub prefix: String,
+}
+
+/// A push would take the prefix past `storage.quota_bytes`.
+#[derive(Debug, thiserror::Error)]
+#[error("quota exceeded for {prefix}: {used} of {quota} bytes used, {needed} more needed")]
+pub struct QuotaExceeded {
+    pub prefix: String,
+    pub quota: u64,
+    pub used: u64,
+    pub needed: u64,
+}
+
+/// A chunk could not be rebuilt from the local copy offered for repair.
+#[derive(Debug, thiserror::Error)]
+#[error("cannot repair chunk {chunk_index} of {manifest}: {reason}")]
+pub struct ChunkUnrepairable {
+    pub manifest: String,
+    pub chunk_index: usize,
+    pub reason: String,
+}
+
+/// The manifest's chunks are encrypted and no master key was supplied.
+#[derive(Debug, thiserror::Error)]
+#[error("manifest is encrypted but no encryption context provided for: {manifest}")]
+pub struct KeyRequired {
+    pub manifest: String,
+}
+
+/// Coarse classification of an engine failure, for callers such as the gRPC
+/// server that branch on the kind of error rather than its message.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum EngineError {
+    /// Local and remote versions diverged, or a conditional write lost a race
+    #[error("{message}")]
+    Conflict {
+        message: String,
+        info: Option<ConflictInfo>,
+    },
+    /// A manifest, index entry, chunk or local file does not exist
+    #[error("{0}")]
+    NotFound(String),
+    /// The storage backend failed or could not be reached
+    #[error("{0}")]
+    Storage(String),
+    /// Stored data failed a hash or checksum check
+    #[error("{0}")]
+    Integrity(String),
+    /// Anything else
+    #[error("{0}")]
+    Other(String),
+}
+
+impl EngineError {
+    /// Classify `err` by the first typed cause found in its chain.
+    ///
+    /// The message keeps the full context chain (`{err:#}`); an `EngineError`
+    /// already in the chain is returned as-is.
+    pub fn classify(err: &anyhow::Error) -> Self {
+        let message = format!("{err:#}");
+        for cause in err.chain() {
+            if let Some(e) = cause.downcast_ref::<EngineError>() {
+                return e.clone();
+            }
+            if cause.is::<ConcurrentModification>() {
+                return EngineError::Conflict {
+                    message,
+                    info: None,
+                };
+            }
+            if cause.is::<ChunkHashMismatch>()
+                || cause.is::<FileHashMismatch>()
+                || cause.is::<crate::manifest::ManifestCorrupt>()
+            {
+                return EngineError::Integrity(message);
+            }
+            if let Some(e) = cause.downcast_ref::<opendal::Error>() {
+                return match e.kind() {
+                    opendal::ErrorKind::NotFound => EngineError::NotFound(message),
+                    _ => EngineError::Storage(message),
+                };
+            }
+            if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+                if e.kind() == std::io::ErrorKind::NotFound {
+                    return EngineError::NotFound(message);
+                }
+            }
+        }
+        EngineError::Other(message)
+    }
+
+    /// The error a successful upload stands for: a [`SyncOutcome::Conflict`]
+    /// is returned as `Ok` by the engine but is a failure to the caller.
+    pub fn from_upload(result: &UploadResult) -> Option<Self> {
+        match &result.outcome {
+            Some(SyncOutcome::Conflict(info)) => Some(EngineError::Conflict {
+                message: format!(
+                    "sync conflict on {}: local {} ({}) vs remote {} ({})",
+                    info.rel_path,
+                    &info.local_blake3[..16.min(info.local_blake3.len())],
+                    info.local_device,
+                    &info.remote_blake3[..16.min(info.remote_blake3.len())],
+                    info.remote_device,
+                ),
+                info: Some(info.clone()),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Version of a remote object observed before a conditional write.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ObjectVersion {
+    /// The object did not exist — the write must create it.
+    Absent,
+    /// The object existed with this ETag — the write must replace exactly it.
+    ETag(String),
+    /// The object existed but the backend reported no ETag.
+    Unknown,
+}
+
+/// Stat a remote object and record its version for a later conditional write.
+///
+/// Only a `NotFound` counts as absent; any other stat failure is returned,
+/// since guessing would turn the later write into a blind create.
+pub async fn stat_version(op: &Operator, path: &str) -> Result<ObjectVersion> {
+    match op.stat(path).await {
+        Ok(meta) => Ok(match meta.etag() {
+            Some(etag) => ObjectVersion::ETag(etag.to_string()),
+            None => ObjectVersion::Unknown,
+        }),
+        Err(e) if e.kind() == opendal::ErrorKind::NotFound => Ok(ObjectVersion::Absent),
+        Err(e) => Err(e).with_context(|| format!("stat: {path}")),
+    }
+}
+
+/// Write `data` to `path` only if the object is still at `expected`.
+///
+/// Uses If-None-Match / If-Match when the backend supports them and falls
+/// back to an unconditional write otherwise. A lost race is reported as
+/// [`ConcurrentModification`].
+pub async fn write_conditional(
+    op: &Operator,
+    path: &str,
+    data: Vec<u8>,
+    expected: &ObjectVersion,
+) -> Result<()> {
+    write_conditional_with(op, path, data, expected, ObjectMeta::default()).await
+}
+
+/// [`write_conditional`], storing `meta` with the object where the backend
+/// supports each field.
+pub async fn write_conditional_with(
+    op: &Operator,
+    path: &str,
+    data: impl Into<opendal::Buffer>,
+    expected: &ObjectVersion,
+    meta: ObjectMeta<'_>,
+) -> Result<()> {
+    let cap = op.info().full_capability();
+    let mut write = op.write_with(path, data.into());
+    if let Some(v) = meta.content_type.filter(|_| cap.write_with_content_type) {
+        write = write.content_type(v);
+    }
+    if let Some(v) = meta.cache_control.filter(|_| cap.write_with_cache_control) {
+        write = write.cache_control(v);
+    }
+    let result = match expected {
+        ObjectVersion::Absent if cap.write_with_if_not_exists => write.if_not_exists(true).await,
+        ObjectVersion::ETag(etag) if cap.write_with_if_match => write.if_match(etag).await,
+        _ => write.await,
+    };
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == opendal::ErrorKind::ConditionNotMatch => {
+            Err(ConcurrentModification {
+                path: path.to_string(),
+            }
+            .into())
+        }
+        Err(e) => Err(e.into()),
+    }
 }
@@ -0,0 +1,146 @@
+//! Per-file version history
+//!
+//! Manifests are keyed by content hash, so each push of new content leaves
+//! the previous manifest in place but nothing records which path it belonged
+//! to. When history is enabled, every push also writes a pointer at
+//! `{prefix}/history/{rel_path}/{timestamp}-{file_hash}` holding the index
+//! entry for that version; `list_versions` and `restore_version` read them
+//! back. Pointers beyond the configured maximum are pruned oldest-first
+//! (their manifests and chunks are left for `engine::prune_history`, which
+//! also prunes by age and collects what no kept version still needs).
+
+use anyhow::{Context, Result};
+use opendal::Operator;
+use std::path::Path;
+use tcfs_core::index::IndexEntry;
+
+use crate::engine::DownloadResult;
+
+/// How many versions to keep per file when history is enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryPolicy {
+    /// Maximum pointers kept per path (0 = unlimited)
+    pub max_versions: usize,
+}
+
+impl HistoryPolicy {
+    /// The policy from `sync.keep_history` / `sync.history_max`, if enabled.
+    pub fn from_config(sync: &tcfs_core::config::SyncConfig) -> Option<Self> {
+        sync.keep_history.then_some(HistoryPolicy {
+            max_versions: sync.history_max,
+        })
+    }
+}
+
+/// One recorded version of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileVersion {
+    /// Unix timestamp (seconds) of the push that recorded this version
+    pub timestamp: u64,
+    /// BLAKE3 hex hash of the file content, naming its manifest
+    pub file_hash: String,
+    /// File size in bytes
+    pub size: u64,
+    /// Storage key of the history pointer
+    pub key: String,
+}
+
+fn history_dir(prefix: &str, rel_path: &str) -> String {
+    tcfs_core::layout::RemoteLayout::new(prefix).history_dir(rel_path)
+}
+
+/// Record `entry` as a version of `rel_path` pushed at `timestamp`, then prune
+/// the oldest pointers beyond `policy.max_versions`.
+pub async fn record_version(
+    op: &Operator,
+    prefix: &str,
+    rel_path: &str,
+    entry: &IndexEntry,
+    timestamp: u64,
+    policy: &HistoryPolicy,
+) -> Result<()> {
+    let key = format!(
+        "{}{timestamp:020}-{}",
+        history_dir(prefix, rel_path),
+        entry.manifest_hash
+    );
+    op.write(&key, entry.to_bytes())
+        .await
+        .with_context(|| format!("writing history pointer: {key}"))?;
+
+    if policy.max_versions > 0 {
+        let versions = list_versions(op, prefix, rel_path).await?;
+        let excess = versions.len().saturating_sub(policy.max_versions);
+        for old in &versions[..excess] {
+            op.delete(&old.key)
+                .await
+                .with_context(|| format!("pruning history pointer: {}", old.key))?;
+        }
+    }
+    Ok(())
+}
+
+/// Versions recorded for `rel_path`, oldest first.
+pub async fn list_versions(
+    op: &Operator,
+    prefix: &str,
+    rel_path: &str,
+) -> Result<Vec<FileVersion>> {
+    let dir = history_dir(prefix, rel_path);
+    let entries = op
+        .list(&dir)
+        .await
+        .with_context(|| format!("listing history: {dir}"))?;
+
+    let mut versions = Vec::new();
+    for entry in entries {
+        if entry.metadata().is_dir() {
+            continue;
+        }
+        let name = entry.name();
+        let Some((ts, hash)) = name.split_once('-') else {
+            continue;
+        };
+        let Ok(timestamp) = ts.parse::<u64>() else {
+            continue;
+        };
+        let data = op
+            .read(entry.path())
+            .await
+            .with_context(|| format!("reading history pointer: {}", entry.path()))?;
+        let index = IndexEntry::from_bytes(&data.to_bytes())
+            .with_context(|| format!("parsing history pointer: {}", entry.path()))?;
+        versions.push(FileVersion {
+            timestamp,
+            file_hash: hash.to_string(),
+            size: index.size,
+            key: entry.path().to_string(),
+        });
+    }
+    versions.sort_by(|a, b| a.key.cmp(&b.key));
+    Ok(versions)
+}
+
+/// Download the version of `rel_path` that was current at `at` (the newest
+/// one recorded at or before it) to `local_path`.
+///
+/// The state cache is not touched, so the restored content shows up as a
+/// local change and the next push records it as a new version.
+pub async fn restore_version(
+    op: &Operator,
+    prefix: &str,
+    rel_path: &str,
+    at: u64,
+    local_path: &Path,
+) -> Result<(FileVersion, DownloadResult)> {
+    let version = list_versions(op, prefix, rel_path)
+        .await?
+        .into_iter()
+        .rev()
+        .find(|v| v.timestamp <= at)
+        .with_context(|| format!("no version of {rel_path} recorded at or before {at}"))?;
+
+    let manifest = tcfs_core::layout::RemoteLayout::new(prefix).manifest_key(&version.file_hash);
+    let result = crate::engine::download_file(op, &manifest, local_path, prefix, None).await?;
+    Ok((version, result))
+}
@@ -1,12 +1,20 @@
 //! tcfs-sync: sync engine with state cache, NATS JetStream, and conflict resolution
 
+pub mod adaptive;
+pub mod archive;
+pub mod chunk_filter;
 pub mod conflict;
 pub mod engine;
 pub mod git_safety;
+pub mod history;
 pub mod manifest;
 pub mod nats;
+pub mod pack;
 pub mod scheduler;
+pub mod snapshot;
 pub mod state;
+pub mod store;
+pub mod tree;
 pub mod watcher;
 
 // Re-export key NATS types for convenience
@@ -2,6 +2,18 @@
 //!
 //! Replaces the v1 newline-separated text format. v1 manifests are
 //! transparently migrated on read via `from_bytes()`.
+//!
+//! v2 manifests are written as canonical JSON: compact, with object keys
+//! sorted at every level, so the same logical manifest always has the same
+//! bytes. They carry a `manifest_checksum`: the BLAKE3 hash of the canonical
+//! body with the checksum field removed. It is written by `to_bytes()` and
+//! verified by `from_bytes()`; manifests without one are accepted as
+//! unverified. Older pretty-printed manifests still parse.
+//!
+//! With the `crypto` feature, the writing device can also sign the body
+//! (without checksum and signature) with its Ed25519 key; the base64
+//! signature is stored in `signature` and checked against the public key
+//! enrolled for `written_by`.
 
 use crate::conflict::VectorClock;
 use serde::{Deserialize, Serialize};
@@ -28,6 +40,96 @@ pub struct SyncManifest {
     /// Base64-encoded wrapped file key (present only when E2E encryption is enabled)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub encrypted_file_key: Option<String>,
+    /// Unix permission bits of the source file (absent on older manifests)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    /// Per-chunk zstd flags, parallel to `chunks` (empty = all stored raw)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub compressed: Vec<bool>,
+    /// Base64-encoded wrapped per-chunk keys, parallel to `chunks` (present
+    /// only on convergent-encrypted manifests, which have no file key)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunk_keys: Vec<String>,
+    /// Levels of `chunks/<hh>/` fan-out the chunks were stored with (0 = flat)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub chunk_shard_depth: u8,
+    /// Ed25519 signature by `written_by` over [`SyncManifest::signing_bytes`]
+    /// (absent on unsigned manifests)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// BLAKE3 of the canonical manifest body (absent on older manifests)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest_checksum: Option<String>,
+}
+
+fn is_zero(depth: &u8) -> bool {
+    *depth == 0
+}
+
+/// A manifest failed to parse or its checksum did not match its body.
+#[derive(Debug, thiserror::Error)]
+#[error("manifest corrupt: {0}")]
+pub struct ManifestCorrupt(pub String);
+
+/// JSON key holding the manifest checksum.
+const CHECKSUM_KEY: &str = "manifest_checksum";
+
+/// JSON key holding the author's signature.
+const SIGNATURE_KEY: &str = "signature";
+
+/// BLAKE3 over the canonical JSON of `value` without the checksum key.
+///
+/// Fields unknown to this version are covered too.
+fn canonical_checksum(value: &serde_json::Value) -> anyhow::Result<String> {
+    let mut body = value.clone();
+    if let Some(obj) = body.as_object_mut() {
+        obj.remove(CHECKSUM_KEY);
+    }
+    let canonical = canonical_json(&body)?;
+    Ok(tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(
+        &canonical,
+    )))
+}
+
+/// Compact JSON of `value` with object keys in byte order at every level.
+///
+/// Keys are sorted here rather than left to `serde_json::Map`, which keeps
+/// insertion order if any crate in the build enables `preserve_order`.
+fn canonical_json(value: &serde_json::Value) -> anyhow::Result<Vec<u8>> {
+    fn write(value: &serde_json::Value, out: &mut Vec<u8>) -> serde_json::Result<()> {
+        match value {
+            serde_json::Value::Object(map) => {
+                let mut entries: Vec<_> = map.iter().collect();
+                entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+                out.push(b'{');
+                for (i, (key, item)) in entries.into_iter().enumerate() {
+                    if i > 0 {
+                        out.push(b',');
+                    }
+                    serde_json::to_writer(&mut *out, key)?;
+                    out.push(b':');
+                    write(item, out)?;
+                }
+                out.push(b'}');
+            }
+            serde_json::Value::Array(items) => {
+                out.push(b'[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(b',');
+                    }
+                    write(item, out)?;
+                }
+                out.push(b']');
+            }
+            scalar => serde_json::to_writer(&mut *out, scalar)?,
+        }
+        Ok(())
+    }
+
+    let mut out = Vec::new();
+    write(value, &mut out).map_err(|e| anyhow::anyhow!("serializing manifest: {e}"))?;
+    Ok(out)
 }
 
 impl SyncManifest {
@@ -35,13 +137,30 @@ impl SyncManifest {
     ///
     /// v1 format: newline-separated chunk hashes (no JSON)
     /// v2 format: JSON object with version field
+    ///
+    /// A JSON manifest that fails to parse or whose `manifest_checksum` does
+    /// not match is rejected with [`ManifestCorrupt`].
     pub fn from_bytes(data: &[u8]) -> anyhow::Result<Self> {
         let text = String::from_utf8(data.to_vec())
-            .map_err(|e| anyhow::anyhow!("manifest is not UTF-8: {e}"))?;
+            .map_err(|e| ManifestCorrupt(format!("not UTF-8: {e}")))?;
+
+        // JSON (v2): anything that looks like an object must parse and verify
+        if text.trim_start().starts_with('{') {
+            let value: serde_json::Value = serde_json::from_str(&text)
+                .map_err(|e| ManifestCorrupt(format!("invalid JSON: {e}")))?;
+
+            if let Some(expected) = value.get(CHECKSUM_KEY).and_then(|v| v.as_str()) {
+                let actual = canonical_checksum(&value)?;
+                if actual != expected {
+                    return Err(ManifestCorrupt(format!(
+                        "checksum mismatch: expected {expected}, got {actual}"
+                    ))
+                    .into());
+                }
+            }
 
-        // Try JSON (v2) first
-        if let Ok(manifest) = serde_json::from_str::<SyncManifest>(&text) {
-            return Ok(manifest);
+            return serde_json::from_value(value)
+                .map_err(|e| ManifestCorrupt(format!("invalid manifest: {e}")).into());
         }
 
         // Fall back to v1 text format: newline-separated chunk hashes
@@ -65,12 +184,63 @@ impl SyncManifest {
             written_at: 0,
             rel_path: None,
             encrypted_file_key: None,
+            mode: None,
+            compressed: Vec::new(),
+            chunk_keys: Vec::new(),
+            chunk_shard_depth: 0,
+            signature: None,
+            manifest_checksum: None,
         })
     }
 
-    /// Serialize manifest to v2 JSON bytes.
+    /// Serialize manifest to canonical v2 JSON bytes, stamping a fresh
+    /// `manifest_checksum`.
     pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
-        serde_json::to_vec_pretty(self).map_err(|e| anyhow::anyhow!("serializing manifest: {e}"))
+        let mut value =
+            serde_json::to_value(self).map_err(|e| anyhow::anyhow!("serializing manifest: {e}"))?;
+        let checksum = canonical_checksum(&value)?;
+        if let Some(obj) = value.as_object_mut() {
+            obj.insert(CHECKSUM_KEY.into(), serde_json::Value::String(checksum));
+        }
+        canonical_json(&value)
+    }
+
+    /// The bytes the author signs: canonical JSON of the fields this
+    /// version knows, without the checksum and the signature.
+    pub fn signing_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        let mut value =
+            serde_json::to_value(self).map_err(|e| anyhow::anyhow!("serializing manifest: {e}"))?;
+        if let Some(obj) = value.as_object_mut() {
+            obj.remove(CHECKSUM_KEY);
+            obj.remove(SIGNATURE_KEY);
+        }
+        canonical_json(&value)
+    }
+
+    /// Sign the manifest as its author with `key`, replacing any signature.
+    #[cfg(feature = "crypto")]
+    pub fn sign(&mut self, key: &tcfs_crypto::DeviceSigningKey) -> anyhow::Result<()> {
+        self.signature = Some(key.sign(&self.signing_bytes()?));
+        Ok(())
+    }
+
+    /// Check the signature against `public_key`, the key enrolled for
+    /// `written_by`. Unsigned manifests fail.
+    #[cfg(feature = "crypto")]
+    pub fn verify_signature(&self, public_key: &str) -> Result<(), tcfs_crypto::BadSignature> {
+        let signature = self
+            .signature
+            .as_deref()
+            .ok_or_else(|| tcfs_crypto::BadSignature("manifest is unsigned".into()))?;
+        let body = self
+            .signing_bytes()
+            .map_err(|e| tcfs_crypto::BadSignature(format!("{e:#}")))?;
+        tcfs_crypto::verify_signature(public_key, &body, signature)
+    }
+
+    /// Whether this manifest carried a checksum (verified when parsed).
+    pub fn is_verified(&self) -> bool {
+        self.manifest_checksum.is_some()
     }
 
     /// Extract the ordered chunk hashes (compatible with v1 consumer code).
@@ -78,10 +248,34 @@ impl SyncManifest {
         &self.chunks
     }
 
+    /// Whether chunk `index` was stored zstd-compressed.
+    pub fn chunk_compressed(&self, index: usize) -> bool {
+        self.compressed.get(index).copied().unwrap_or(false)
+    }
+
+    /// Key layout for this manifest's chunks under `remote_prefix`.
+    pub fn chunk_layout(&self, remote_prefix: &str) -> tcfs_core::layout::RemoteLayout {
+        tcfs_core::layout::RemoteLayout::new(remote_prefix)
+            .with_chunk_shard_depth(self.chunk_shard_depth)
+    }
+
+    /// Whether chunks were encrypted with per-chunk convergent keys.
+    pub fn is_convergent(&self) -> bool {
+        !self.chunk_keys.is_empty()
+    }
+
     /// Check if this is a v1 (legacy) manifest.
     pub fn is_legacy(&self) -> bool {
         self.version < 2
     }
+
+    /// Seconds `written_at` lies ahead of `now`, when that is more than
+    /// `max_skew`: the writer's clock is off, and the timestamp must not be
+    /// used to order this version against others.
+    pub fn clock_skew(&self, now: u64, max_skew: u64) -> Option<u64> {
+        let ahead = self.written_at.saturating_sub(now);
+        (ahead > max_skew).then_some(ahead)
+    }
 }
 
 #[cfg(test)]
@@ -103,11 +297,18 @@ mod tests {
             written_at: 1000,
             rel_path: Some("docs/readme.md".into()),
             encrypted_file_key: None,
+            mode: None,
+            compressed: Vec::new(),
+            chunk_keys: Vec::new(),
+            chunk_shard_depth: 0,
+            signature: None,
+            manifest_checksum: None,
         };
 
         let bytes = manifest.to_bytes().unwrap();
         let parsed = SyncManifest::from_bytes(&bytes).unwrap();
 
+        assert!(parsed.is_verified());
         assert_eq!(parsed.version, 2);
         assert_eq!(parsed.file_hash, "abc123");
         assert_eq!(parsed.chunks.len(), 2);
@@ -132,6 +333,97 @@ mod tests {
         assert!(result.is_err());
     }
 
+    fn sample_manifest() -> SyncManifest {
+        let mut vc = VectorClock::new();
+        vc.tick("yoga");
+        SyncManifest {
+            version: 2,
+            file_hash: "abc123".into(),
+            file_size: 1024,
+            chunks: vec!["chunk1".into(), "chunk2".into()],
+            vclock: vc,
+            written_by: "yoga".into(),
+            written_at: 1000,
+            rel_path: Some("docs/readme.md".into()),
+            encrypted_file_key: None,
+            mode: None,
+            compressed: Vec::new(),
+            chunk_keys: Vec::new(),
+            chunk_shard_depth: 0,
+            signature: None,
+            manifest_checksum: None,
+        }
+    }
+
+    #[test]
+    fn test_corrupt_byte_fails_checksum() {
+        let mut bytes = sample_manifest().to_bytes().unwrap();
+        let pos = bytes
+            .windows(6)
+            .position(|w| w == b"chunk2")
+            .expect("chunk hash in body");
+        bytes[pos + 5] = b'9';
+
+        let err = SyncManifest::from_bytes(&bytes).unwrap_err();
+        assert!(err.is::<ManifestCorrupt>(), "unexpected error: {err}");
+    }
+
+    #[test]
+    fn test_truncated_manifest_is_corrupt() {
+        let bytes = sample_manifest().to_bytes().unwrap();
+        let err = SyncManifest::from_bytes(&bytes[..bytes.len() / 2]).unwrap_err();
+        assert!(err.is::<ManifestCorrupt>(), "unexpected error: {err}");
+    }
+
+    #[test]
+    fn test_missing_checksum_is_unverified() {
+        let legacy = serde_json::to_vec(&sample_manifest()).unwrap();
+        let parsed = SyncManifest::from_bytes(&legacy).unwrap();
+        assert!(!parsed.is_verified());
+        assert_eq!(parsed.chunks, vec!["chunk1", "chunk2"]);
+    }
+
+    #[test]
+    fn test_reserialized_manifest_is_byte_identical() {
+        let mut manifest = sample_manifest();
+        manifest.vclock.tick("laptop");
+        manifest.mode = Some(0o644);
+        manifest.compressed = vec![true, false];
+        let bytes = manifest.to_bytes().unwrap();
+
+        let reparsed = SyncManifest::from_bytes(&bytes).unwrap();
+        assert_eq!(reparsed.to_bytes().unwrap(), bytes);
+
+        // Compact, with keys sorted
+        let text = std::str::from_utf8(&bytes).unwrap();
+        assert!(!text.contains([' ', '\n']), "{text}");
+        let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        let keys: Vec<_> = value.as_object().unwrap().keys().collect();
+        let positions: Vec<_> = keys
+            .iter()
+            .map(|k| text.find(&format!("\"{k}\":")).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]), "{text}");
+    }
+
+    #[test]
+    fn test_pretty_manifest_reserializes_canonically() {
+        // An older writer's pretty-printed, differently ordered manifest
+        let canonical = sample_manifest().to_bytes().unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&canonical).unwrap();
+        let mut fields: Vec<_> = value.as_object().unwrap().iter().collect();
+        fields.reverse();
+        let body: Vec<String> = fields
+            .iter()
+            .map(|(k, v)| format!("  \"{k}\": {}", serde_json::to_string_pretty(v).unwrap()))
+            .collect();
+        let pretty = format!("{{\n{}\n}}\n", body.join(",\n"));
+
+        let parsed = SyncManifest::from_bytes(pretty.as_bytes()).unwrap();
+        assert!(parsed.is_verified());
+        assert_eq!(parsed.to_bytes().unwrap(), canonical);
+    }
+
     #[test]
     fn test_v1_single_chunk() {
         let v1 = "single_hash\n";
@@ -3,12 +3,21 @@
 //! Defines the `SyncTask` message format and provides:
 //! - `NatsClient` — connect, ensure streams exist, publish tasks
 //! - `task_stream()` — pull consumer for worker pods
-//! - `state_consumer()` — per-device durable consumer for STATE_UPDATES
+//! - `state_consumer()` — per-device durable consumer for the fleet's state stream
 //!
 //! Streams:
-//!   SYNC_TASKS         — push/pull/unsync work items (HPA-scaled workers consume)
-//!   HYDRATION_EVENTS   — FUSE hydration events (future Phase 3 daemon-side use)
-//!   STATE_UPDATES      — sync state change notifications (hierarchical subjects)
+//!   SYNC_TASKS              — push/pull/unsync work items (HPA-scaled workers consume)
+//!   HYDRATION_EVENTS        — FUSE hydration events (future Phase 3 daemon-side use)
+//!   STATE_UPDATES_{fleet}   — sync state change notifications, one stream per
+//!                             fleet on subjects `tcfs.{fleet}.state.>`
+//!
+//! A fleet is the set of devices sharing one store (`sync.fleet_id`, by
+//! default the bucket name). Fleets sharing a NATS server never see each
+//! other's state events: each has its own stream, and the consumer drops
+//! anything whose subject is outside its fleet.
+//!
+//! State event payloads are JSON, optionally zstd-compressed behind a small
+//! `TCE` frame header (see `EventCodec`); consumers accept either.
 //!
 //! Requires feature `nats` (async-nats optional dep).
 
@@ -33,11 +42,65 @@ mod inner {
     pub const STREAM_STATE: &str = "STATE_UPDATES";
     pub const CONSUMER_SYNC_WORKERS: &str = "sync-workers";
 
+    /// Fleet token used when the configured fleet id is empty.
+    pub const DEFAULT_FLEET: &str = "default";
+
+    /// `fleet_id` as a NATS subject token and stream-name suffix: characters
+    /// other than ASCII letters, digits, `-` and `_` become `_`.
+    pub fn fleet_token(fleet_id: &str) -> String {
+        let token: String = fleet_id
+            .trim()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        if token.is_empty() {
+            DEFAULT_FLEET.to_string()
+        } else {
+            token
+        }
+    }
+
+    /// Durable state consumer name for `device_id`: `state-{device_id}`, or
+    /// `state-{device_id}-{suffix}` with a non-empty `suffix`
+    /// (`sync.consumer_suffix`), sanitized like [`fleet_token`].
+    pub fn state_consumer_name(device_id: &str, suffix: &str) -> String {
+        if suffix.trim().is_empty() {
+            format!("state-{device_id}")
+        } else {
+            format!("state-{device_id}-{}", fleet_token(suffix))
+        }
+    }
+
+    /// Stream holding `fleet`'s state events.
+    pub fn state_stream(fleet: &str) -> String {
+        format!("{STREAM_STATE}_{fleet}")
+    }
+
+    /// Subject filter covering all of `fleet`'s state events.
+    pub fn state_subjects(fleet: &str) -> String {
+        format!("tcfs.{fleet}.state.>")
+    }
+
+    /// Whether `subject` is one of `fleet`'s state event subjects.
+    pub fn in_fleet(fleet: &str, subject: &str) -> bool {
+        subject
+            .strip_prefix("tcfs.")
+            .and_then(|rest| rest.strip_prefix(fleet))
+            .and_then(|rest| rest.strip_prefix(".state."))
+            .is_some_and(|rest| !rest.is_empty())
+    }
+
     // ── StateEvent ────────────────────────────────────────────────────────────
 
-    /// A state change event published to STATE_UPDATES stream.
+    /// A state change event published to a fleet's state stream.
     ///
-    /// Subject hierarchy: `STATE.{device_id}.{event_type}`
+    /// Subject hierarchy: `tcfs.{fleet}.state.{device_id}.{event_type}`
     #[derive(Debug, Clone, Serialize, Deserialize)]
     #[serde(tag = "type", rename_all = "snake_case")]
     pub enum StateEvent {
@@ -100,6 +163,18 @@ mod inner {
             }
         }
 
+        /// Unix time the publishing device stamped the event with.
+        pub fn timestamp(&self) -> u64 {
+            match self {
+                StateEvent::FileSynced { timestamp, .. }
+                | StateEvent::FileDeleted { timestamp, .. }
+                | StateEvent::FileRenamed { timestamp, .. }
+                | StateEvent::DeviceOnline { timestamp, .. }
+                | StateEvent::DeviceOffline { timestamp, .. }
+                | StateEvent::ConflictResolved { timestamp, .. } => *timestamp,
+            }
+        }
+
         pub fn event_type(&self) -> &'static str {
             match self {
                 StateEvent::FileSynced { .. } => "file_synced",
@@ -111,9 +186,34 @@ mod inner {
             }
         }
 
-        /// Build the NATS subject for this event.
-        pub fn subject(&self) -> String {
-            format!("STATE.{}.{}", self.device_id(), self.event_type())
+        /// The event's vector clock, if it carries one.
+        pub fn vclock(&self) -> Option<&VectorClock> {
+            match self {
+                StateEvent::FileSynced { vclock, .. }
+                | StateEvent::FileDeleted { vclock, .. }
+                | StateEvent::FileRenamed { vclock, .. } => Some(vclock),
+                StateEvent::ConflictResolved { merged_vclock, .. } => Some(merged_vclock),
+                StateEvent::DeviceOnline { .. } | StateEvent::DeviceOffline { .. } => None,
+            }
+        }
+
+        fn vclock_mut(&mut self) -> Option<&mut VectorClock> {
+            match self {
+                StateEvent::FileSynced { vclock, .. }
+                | StateEvent::FileDeleted { vclock, .. }
+                | StateEvent::FileRenamed { vclock, .. } => Some(vclock),
+                StateEvent::ConflictResolved { merged_vclock, .. } => Some(merged_vclock),
+                StateEvent::DeviceOnline { .. } | StateEvent::DeviceOffline { .. } => None,
+            }
+        }
+
+        /// Build the NATS subject for this event within `fleet`.
+        pub fn subject(&self, fleet: &str) -> String {
+            format!(
+                "tcfs.{fleet}.state.{}.{}",
+                self.device_id(),
+                self.event_type()
+            )
         }
 
         pub fn to_bytes(&self) -> Result<bytes::Bytes> {
@@ -127,13 +227,190 @@ mod inner {
                 .map_err(|e| anyhow::anyhow!("deserializing StateEvent: {e}"))
         }
 
-        /// Helper to get the current unix timestamp.
+        /// Current unix timestamp from the system clock; the daemon stamps
+        /// events from its state cache's clock instead.
         pub fn now() -> u64 {
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs()
+            use tcfs_core::clock::Clock;
+            tcfs_core::clock::SystemClock.unix_secs()
+        }
+    }
+
+    // ── State event encoding ──────────────────────────────────────────────────
+
+    /// Leading bytes of a framed state event payload; anything else is plain
+    /// JSON. A frame is `TCE`, a version byte, a flags byte, then the body.
+    pub const EVENT_MAGIC: &[u8; 3] = b"TCE";
+    const EVENT_FRAME_VERSION: u8 = 1;
+    /// Frame flag: the body is zstd-compressed JSON
+    const FLAG_ZSTD: u8 = 0x01;
+    /// Frame flag: the event's vector clock was trimmed to fit the payload cap
+    const FLAG_CLOCK_SUMMARIZED: u8 = 0x02;
+    const EVENT_ZSTD_LEVEL: i32 = 3;
+    /// Largest decompressed event accepted from the wire.
+    const MAX_DECODED_EVENT: usize = 16 * 1024 * 1024;
+
+    /// Default cap on a published state event (the NATS server default).
+    pub const DEFAULT_MAX_EVENT_PAYLOAD: usize = 1024 * 1024;
+
+    /// A state event that does not fit the payload cap even with its vector
+    /// clock trimmed to the publishing device's own entry.
+    #[derive(Debug, thiserror::Error)]
+    #[error("state event from {device_id} is {size} bytes, over the {max}-byte payload cap")]
+    pub struct EventTooLarge {
+        pub device_id: String,
+        pub size: usize,
+        pub max: usize,
+    }
+
+    /// How state events are written to the wire.
+    ///
+    /// Uncompressed events stay plain JSON, readable by every consumer. An
+    /// event over `max_payload` keeps only its largest vector clock entries
+    /// (always including the publisher's) and is flagged, so consumers know
+    /// the clock is a lower bound: a trimmed clock can only make the remote
+    /// look older or concurrent, never newer.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct EventCodec {
+        pub compress: bool,
+        pub max_payload: usize,
+    }
+
+    impl Default for EventCodec {
+        fn default() -> Self {
+            Self {
+                compress: false,
+                max_payload: DEFAULT_MAX_EVENT_PAYLOAD,
+            }
+        }
+    }
+
+    impl EventCodec {
+        /// Codec for `sync.nats_compress` / `sync.nats_max_payload`.
+        pub fn from_config(sync: &tcfs_core::config::SyncConfig) -> Self {
+            Self {
+                compress: sync.nats_compress,
+                max_payload: sync.nats_max_payload,
+            }
+        }
+
+        /// Serialize `event` for publishing.
+        pub fn encode(&self, event: &StateEvent) -> Result<bytes::Bytes> {
+            let payload = self.frame(event, false)?;
+            if payload.len() <= self.max_payload {
+                return Ok(payload);
+            }
+            let Some(clock) = event.vclock() else {
+                return Err(self.too_large(event, payload.len()));
+            };
+
+            // Halve the entries kept until the event fits
+            let mut trimmed = event.clone();
+            let mut keep = clock.clocks.len();
+            let mut size = payload.len();
+            while keep > 1 {
+                keep /= 2;
+                if let Some(c) = trimmed.vclock_mut() {
+                    *c = summarize_clock(clock, event.device_id(), keep);
+                }
+                let payload = self.frame(&trimmed, true)?;
+                if payload.len() <= self.max_payload {
+                    warn!(
+                        device = event.device_id(),
+                        event_type = event.event_type(),
+                        entries = clock.clocks.len(),
+                        kept = keep,
+                        "state event over payload cap: vector clock trimmed"
+                    );
+                    return Ok(payload);
+                }
+                size = payload.len();
+            }
+            Err(self.too_large(event, size))
+        }
+
+        fn frame(&self, event: &StateEvent, summarized: bool) -> Result<bytes::Bytes> {
+            let json = event.to_bytes()?;
+            if !self.compress && !summarized {
+                return Ok(json);
+            }
+            let mut flags = 0;
+            if summarized {
+                flags |= FLAG_CLOCK_SUMMARIZED;
+            }
+            let body = if self.compress {
+                flags |= FLAG_ZSTD;
+                zstd::bulk::compress(&json, EVENT_ZSTD_LEVEL)
+                    .map_err(|e| anyhow::anyhow!("compressing StateEvent: {e}"))?
+            } else {
+                json.to_vec()
+            };
+            let mut out = Vec::with_capacity(EVENT_MAGIC.len() + 2 + body.len());
+            out.extend_from_slice(EVENT_MAGIC);
+            out.push(EVENT_FRAME_VERSION);
+            out.push(flags);
+            out.extend_from_slice(&body);
+            Ok(bytes::Bytes::from(out))
+        }
+
+        fn too_large(&self, event: &StateEvent, size: usize) -> anyhow::Error {
+            EventTooLarge {
+                device_id: event.device_id().to_string(),
+                size,
+                max: self.max_payload,
+            }
+            .into()
+        }
+    }
+
+    /// `clock` cut down to its `keep` largest entries, `own` always among them.
+    fn summarize_clock(clock: &VectorClock, own: &str, keep: usize) -> VectorClock {
+        let mut entries: Vec<(&String, &u64)> = clock.clocks.iter().collect();
+        entries.sort_by_key(|(device, counter)| {
+            (device.as_str() != own, std::cmp::Reverse(**counter))
+        });
+        VectorClock {
+            clocks: entries
+                .into_iter()
+                .take(keep.max(1))
+                .map(|(device, counter)| (device.clone(), *counter))
+                .collect(),
+        }
+    }
+
+    /// A state event read off the wire.
+    #[derive(Debug, Clone)]
+    pub struct DecodedEvent {
+        pub event: StateEvent,
+        /// The publisher trimmed the event's vector clock to fit the payload
+        /// cap; use the manifest's clock where there is one.
+        pub clock_summarized: bool,
+    }
+
+    /// Parse a state event payload, plain JSON or framed.
+    pub fn decode_event(data: &[u8]) -> Result<DecodedEvent> {
+        let Some(rest) = data.strip_prefix(EVENT_MAGIC.as_slice()) else {
+            return Ok(DecodedEvent {
+                event: StateEvent::from_bytes(data)?,
+                clock_summarized: false,
+            });
+        };
+        let [version, flags, body @ ..] = rest else {
+            anyhow::bail!("truncated StateEvent frame");
+        };
+        if *version != EVENT_FRAME_VERSION {
+            anyhow::bail!("unsupported StateEvent frame version {version}");
         }
+        let event = if flags & FLAG_ZSTD != 0 {
+            let json = zstd::bulk::decompress(body, MAX_DECODED_EVENT)
+                .map_err(|e| anyhow::anyhow!("decompressing StateEvent: {e}"))?;
+            StateEvent::from_bytes(&json)?
+        } else {
+            StateEvent::from_bytes(body)?
+        };
+        Ok(DecodedEvent {
+            event,
+            clock_summarized: flags & FLAG_CLOCK_SUMMARIZED != 0,
+        })
     }
 
     // ── SyncTask message format ───────────────────────────────────────────────
@@ -191,20 +468,41 @@ mod inner {
 
     // ── NatsClient ────────────────────────────────────────────────────────────
 
-    /// Thin wrapper around an async-nats JetStream context.
+    /// Thin wrapper around an async-nats JetStream context, scoped to one fleet.
     pub struct NatsClient {
         js: jetstream::Context,
+        /// Fleet token (see [`fleet_token`])
+        fleet: String,
+        codec: EventCodec,
     }
 
     impl NatsClient {
-        /// Connect to NATS and return a client with JetStream enabled.
-        pub async fn connect(url: &str) -> Result<Self> {
+        /// Connect to NATS and return a client with JetStream enabled that
+        /// publishes and consumes state events of `fleet_id`.
+        pub async fn connect(url: &str, fleet_id: &str) -> Result<Self> {
             let client = async_nats::connect(url)
                 .await
                 .map_err(|e| anyhow::anyhow!("connecting to NATS at {url}: {e}"))?;
-            info!("NATS: connected to {url}");
+            let fleet = fleet_token(fleet_id);
+            info!(fleet = %fleet, "NATS: connected to {url}");
             let js = jetstream::new(client);
-            Ok(NatsClient { js })
+            Ok(NatsClient {
+                js,
+                fleet,
+                codec: EventCodec::default(),
+            })
+        }
+
+        /// Encode published state events with `codec` (default: plain JSON,
+        /// 1 MiB cap).
+        pub fn with_codec(mut self, codec: EventCodec) -> Self {
+            self.codec = codec;
+            self
+        }
+
+        /// Fleet token this client's state subjects are namespaced under.
+        pub fn fleet(&self) -> &str {
+            &self.fleet
         }
 
         /// Ensure all required JetStream streams exist (idempotent via CreateOrUpdate).
@@ -232,11 +530,12 @@ mod inner {
                 .await
                 .map_err(|e| anyhow::anyhow!("ensuring HYDRATION_EVENTS stream: {e}"))?;
 
-            // STATE_UPDATES: fan-out (Limits retention), hierarchical subjects, 7-day TTL
+            // Fleet state stream: fan-out (Limits retention), hierarchical subjects, 7-day TTL
+            let state_stream = state_stream(&self.fleet);
             self.js
                 .get_or_create_stream(stream::Config {
-                    name: STREAM_STATE.to_string(),
-                    subjects: vec!["STATE.>".to_string()],
+                    name: state_stream.clone(),
+                    subjects: vec![state_subjects(&self.fleet)],
                     max_messages: 500_000,
                     max_age: Duration::from_secs(7 * 24 * 3600),
                     retention: stream::RetentionPolicy::Limits,
@@ -244,9 +543,9 @@ mod inner {
                     ..Default::default()
                 })
                 .await
-                .map_err(|e| anyhow::anyhow!("ensuring STATE_UPDATES stream: {e}"))?;
+                .map_err(|e| anyhow::anyhow!("ensuring {state_stream} stream: {e}"))?;
 
-            info!("NATS: streams verified (SYNC_TASKS, HYDRATION_EVENTS, STATE_UPDATES)");
+            info!("NATS: streams verified (SYNC_TASKS, HYDRATION_EVENTS, {state_stream})");
             Ok(())
         }
 
@@ -269,10 +568,10 @@ mod inner {
             Ok(())
         }
 
-        /// Publish a state event to STATE_UPDATES.
+        /// Publish a state event to this client's fleet.
         pub async fn publish_state_event(&self, event: &StateEvent) -> Result<()> {
-            let subject = event.subject();
-            let payload = event.to_bytes()?;
+            let subject = event.subject(&self.fleet);
+            let payload = self.codec.encode(event)?;
             self.js
                 .publish(subject, payload)
                 .await
@@ -323,15 +622,19 @@ mod inner {
             Ok(stream)
         }
 
-        /// Create a per-device durable consumer for STATE_UPDATES.
+        /// Create a per-device durable consumer for the fleet's state stream.
         ///
-        /// Consumer name: `state-{device_id}` (durable, survives disconnects).
-        /// Subscribes to all `STATE.>` events, including own device events.
+        /// Consumer name: [`state_consumer_name`] (durable, survives
+        /// disconnects); processes with different `suffix`es keep separate
+        /// cursors and each receive every event. Receives all of the fleet's
+        /// events, including own device events. Messages on a subject
+        /// outside the fleet are acked and dropped.
         pub async fn state_consumer(
             &self,
             device_id: &str,
+            suffix: &str,
         ) -> Result<impl futures::Stream<Item = Result<StateEventMessage>>> {
-            let consumer_name = format!("state-{device_id}");
+            let consumer_name = state_consumer_name(device_id, suffix);
 
             let consumer: jetstream::consumer::Consumer<pull::Config> = self
                 .js
@@ -342,7 +645,7 @@ mod inner {
                         max_deliver: 5,
                         ..Default::default()
                     },
-                    STREAM_STATE,
+                    state_stream(&self.fleet),
                 )
                 .await
                 .map_err(|e| anyhow::anyhow!("creating state consumer '{consumer_name}': {e}"))?;
@@ -352,10 +655,27 @@ mod inner {
                 .await
                 .map_err(|e| anyhow::anyhow!("opening state consumer message stream: {e}"))?;
 
-            let stream = messages.map(|msg_result| {
-                let msg = msg_result.map_err(|e| anyhow::anyhow!("receiving state msg: {e}"))?;
-                let event = StateEvent::from_bytes(&msg.payload)?;
-                Ok(StateEventMessage { event, msg })
+            let fleet = self.fleet.clone();
+            let stream = messages.filter_map(move |msg_result| {
+                let fleet = fleet.clone();
+                async move {
+                    let msg = match msg_result {
+                        Ok(msg) => msg,
+                        Err(e) => return Some(Err(anyhow::anyhow!("receiving state msg: {e}"))),
+                    };
+                    if !in_fleet(&fleet, msg.subject.as_str()) {
+                        warn!(subject = %msg.subject, fleet = %fleet, "dropping state event from another fleet");
+                        if let Err(e) = msg.ack().await {
+                            warn!("acking foreign state event: {e}");
+                        }
+                        return None;
+                    }
+                    Some(decode_event(&msg.payload).map(|decoded| StateEventMessage {
+                        event: decoded.event,
+                        clock_summarized: decoded.clock_summarized,
+                        msg,
+                    }))
+                }
             });
 
             Ok(stream)
@@ -401,6 +721,8 @@ mod inner {
     /// A deserialized state event + the underlying NATS message (for ack).
     pub struct StateEventMessage {
         pub event: StateEvent,
+        /// See [`DecodedEvent::clock_summarized`]
+        pub clock_summarized: bool,
         pub(crate) msg: jetstream::Message,
     }
 
@@ -443,4 +765,161 @@ mod inner {
             }
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// NATS subject matching: `*` matches one token, `>` one or more
+        /// trailing tokens.
+        fn subject_matches(filter: &str, subject: &str) -> bool {
+            let mut subject = subject.split('.');
+            for token in filter.split('.') {
+                match (token, subject.next()) {
+                    (">", Some(_)) => return true,
+                    ("*", Some(_)) => {}
+                    (token, Some(s)) if token == s => {}
+                    _ => return false,
+                }
+            }
+            subject.next().is_none()
+        }
+
+        fn synced(device_id: &str) -> StateEvent {
+            StateEvent::FileSynced {
+                device_id: device_id.into(),
+                rel_path: "notes.md".into(),
+                blake3: "ab".repeat(32),
+                size: 5,
+                vclock: VectorClock::default(),
+                manifest_path: "files/manifests/x".into(),
+                timestamp: 1_700_000_000,
+            }
+        }
+
+        #[test]
+        fn consumer_suffixes_give_independent_durables() {
+            // No suffix keeps the name existing deployments already use
+            assert_eq!(state_consumer_name("dev-a1", ""), "state-dev-a1");
+            let daemon = state_consumer_name("dev-a1", "daemon");
+            let helper = state_consumer_name("dev-a1", "migration helper");
+            assert_eq!(helper, "state-dev-a1-migration_helper");
+            assert_ne!(daemon, helper);
+            assert_ne!(daemon, state_consumer_name("dev-a1", ""));
+            // Durable names are single NATS tokens
+            for name in [&daemon, &helper] {
+                assert!(!name.contains(['.', ' ', '*', '>']), "{name}");
+            }
+        }
+
+        #[test]
+        fn fleet_a_does_not_receive_fleet_b_events() {
+            let a = fleet_token("fleet-a");
+            let b = fleet_token("fleet-b");
+            assert_ne!(state_stream(&a), state_stream(&b));
+
+            let own = synced("dev-a1").subject(&a);
+            let foreign = synced("dev-b1").subject(&b);
+            assert_eq!(own, "tcfs.fleet-a.state.dev-a1.file_synced");
+
+            // Fleet A's stream does not capture fleet B's subjects...
+            assert!(subject_matches(&state_subjects(&a), &own));
+            assert!(!subject_matches(&state_subjects(&a), &foreign));
+            assert!(!subject_matches(&state_subjects(&b), &own));
+
+            // ...and the consumer refuses them if they arrive anyway
+            assert!(in_fleet(&a, &own));
+            assert!(!in_fleet(&a, &foreign));
+            assert!(!in_fleet(&a, "STATE.dev-b1.file_synced"));
+            // A fleet whose name extends another's is still a different fleet
+            assert!(!in_fleet("fleet", &own));
+            assert!(!in_fleet(&a, "tcfs.fleet-a.state."));
+        }
+
+        fn large_synced(devices: usize) -> StateEvent {
+            let mut vclock = VectorClock::default();
+            for i in 0..devices {
+                vclock.clocks.insert(format!("device-{i:05}"), i as u64 + 1);
+            }
+            vclock.clocks.insert("dev-a1".into(), 7);
+            let mut event = synced("dev-a1");
+            if let Some(clock) = event.vclock_mut() {
+                *clock = vclock;
+            }
+            event
+        }
+
+        #[test]
+        fn large_event_round_trips_compressed() {
+            let event = large_synced(5_000);
+            let plain = EventCodec::default().encode(&event).unwrap();
+            assert_eq!(plain[0], b'{');
+
+            let codec = EventCodec {
+                compress: true,
+                ..EventCodec::default()
+            };
+            let compressed = codec.encode(&event).unwrap();
+            assert!(compressed.starts_with(EVENT_MAGIC));
+            assert!(compressed.len() * 4 < plain.len());
+
+            for payload in [plain, compressed] {
+                let decoded = decode_event(&payload).unwrap();
+                assert!(!decoded.clock_summarized);
+                assert_eq!(decoded.event.vclock(), event.vclock());
+                assert_eq!(decoded.event.subject("f"), event.subject("f"));
+            }
+        }
+
+        #[test]
+        fn oversized_clock_is_trimmed_to_fit() {
+            let event = large_synced(5_000);
+            let codec = EventCodec {
+                compress: false,
+                max_payload: 8 * 1024,
+            };
+            let payload = codec.encode(&event).unwrap();
+            assert!(payload.len() <= codec.max_payload);
+
+            let decoded = decode_event(&payload).unwrap();
+            assert!(decoded.clock_summarized);
+            let clock = decoded.event.vclock().unwrap();
+            let full = event.vclock().unwrap();
+            assert_eq!(clock.get("dev-a1"), 7);
+            assert!(clock.clocks.len() < full.clocks.len());
+            // Kept entries are the largest counters, unchanged
+            for (device, counter) in &clock.clocks {
+                assert_eq!(full.get(device), *counter);
+            }
+            assert!(clock.get("device-04999") > 0);
+
+            // Events with nothing to trim are refused
+            let online = StateEvent::DeviceOnline {
+                device_id: "x".repeat(100),
+                last_seq: 0,
+                timestamp: 0,
+            };
+            let tiny = EventCodec {
+                compress: false,
+                max_payload: 16,
+            };
+            let err = tiny.encode(&online).unwrap_err();
+            assert!(err.downcast_ref::<EventTooLarge>().is_some());
+        }
+
+        #[test]
+        fn bad_frames_are_rejected() {
+            assert!(decode_event(b"TCE").is_err());
+            assert!(decode_event(b"TCE\x09\x00{}").is_err());
+            assert!(decode_event(b"TCE\x01\x01not zstd").is_err());
+        }
+
+        #[test]
+        fn fleet_token_is_subject_safe() {
+            assert_eq!(fleet_token("my.bucket name"), "my_bucket_name");
+            assert_eq!(fleet_token("a>*"), "a__");
+            assert_eq!(fleet_token("  "), DEFAULT_FLEET);
+            assert_eq!(fleet_token("tcfs-prod_1"), "tcfs-prod_1");
+        }
+    }
 }
@@ -0,0 +1,469 @@
+//! Packfiles: many small files stored as one object
+//!
+//! A tree of thousands of tiny files would otherwise cost a manifest and a
+//! chunk per file. With a pack threshold set on the state cache
+//! (`sync.pack_small_files`), `push_tree` gathers the files below it and
+//! writes their contents back to back as `{prefix}/packs/{id}`, named by the
+//! BLAKE3 of the pack bytes, plus a pack index at `{prefix}/packs/{id}.idx`
+//! mapping each rel_path to its offset, length and content hash. Every
+//! packed file's index entry carries the same [`PackRef`], so pulls and
+//! mounts read just that byte range and check it against the entry's
+//! content hash.
+//!
+//! Packed files have no manifest and so no vector clock to detect conflicts
+//! with. A packing push therefore only claims a path whose index entry is
+//! absent or holds the version this device last synced, replacing it with a
+//! conditional write; a path the remote tracks from another device is
+//! refused with [`TrackedElsewhere`], and a tree push sends it as chunks
+//! instead. No history pointers are recorded for packed files. Encrypted
+//! pushes never pack, since a pack holds plaintext.
+
+use anyhow::{Context, Result};
+use opendal::Operator;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tcfs_core::index::{IndexEntry, PackRef};
+use tcfs_core::layout::RemoteLayout;
+use tracing::debug;
+
+use crate::engine::{put_index, stat_version, ConcurrentModification, IndexStage, ObjectVersion};
+use crate::state::{make_sync_state_at, StateCache};
+
+/// Pack size at which a push closes the current pack and starts another.
+pub const PACK_TARGET_BYTES: u64 = 32 * 1024 * 1024;
+
+/// Bytes read back for a packed file that do not hash to its content hash.
+#[derive(Debug, thiserror::Error)]
+#[error("{rel_path} in pack {pack} hashes to {actual}, expected {expected}")]
+pub struct PackedHashMismatch {
+    pub rel_path: String,
+    pub pack: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// A file a packing push refused: its index entry holds a version this
+/// device never synced, which only a chunked push can check for conflicts.
+#[derive(Debug, thiserror::Error)]
+#[error("{rel_path} is tracked from another device and cannot be packed")]
+pub struct TrackedElsewhere {
+    pub path: PathBuf,
+    pub rel_path: String,
+}
+
+/// One file's place in a pack, as listed by its [`PackIndex`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackSlot {
+    pub offset: u64,
+    pub len: u64,
+    /// BLAKE3 hex hash of the file content
+    pub hash: String,
+}
+
+/// Contents of `{prefix}/packs/{id}.idx`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackIndex {
+    /// Pack id (BLAKE3 hex hash of the pack bytes)
+    pub id: String,
+    /// rel_path → where its content sits in the pack
+    pub files: BTreeMap<String, PackSlot>,
+    /// Device that wrote the pack
+    #[serde(default)]
+    pub written_by: String,
+    /// Unix timestamp (seconds) of the push
+    #[serde(default)]
+    pub written_at: u64,
+}
+
+/// A file handled by [`push_packed`].
+#[derive(Debug, Clone)]
+pub struct PackedFile {
+    pub path: PathBuf,
+    pub rel_path: String,
+    /// BLAKE3 hex hash of the file content
+    pub hash: String,
+    pub bytes: u64,
+    /// Where the content is stored; `None` for an unchanged file whose
+    /// earlier push stored it as chunks
+    pub pack: Option<PackRef>,
+    /// true if the file was unchanged and nothing was written
+    pub skipped: bool,
+}
+
+/// A file read into the pack being built.
+struct Member {
+    path: PathBuf,
+    rel_path: String,
+    slot: PackSlot,
+    /// Version of the index entry the pack replaces
+    index_version: ObjectVersion,
+}
+
+/// Pack `files` (all under `local_root`) into as few packs as
+/// [`PACK_TARGET_BYTES`] allows, writing their index entries and state.
+///
+/// Files unchanged since their last push are reported from their existing
+/// index entries without being read. A failed pack write fails each of its
+/// files; the rest of the push carries on.
+pub async fn push_packed(
+    op: &Operator,
+    local_root: &Path,
+    remote_prefix: &str,
+    state: &StateCache,
+    files: &[PathBuf],
+    device_id: &str,
+) -> Vec<Result<PackedFile>> {
+    pack_files(op, local_root, remote_prefix, state, files, device_id, None).await
+}
+
+/// [`push_packed`], holding index entries in `stage` when given.
+pub(crate) async fn pack_files(
+    op: &Operator,
+    local_root: &Path,
+    remote_prefix: &str,
+    state: &StateCache,
+    files: &[PathBuf],
+    device_id: &str,
+    stage: Option<&IndexStage>,
+) -> Vec<Result<PackedFile>> {
+    let layout = RemoteLayout::new(remote_prefix);
+    let mut results = Vec::with_capacity(files.len());
+    let mut data = Vec::new();
+    let mut members = Vec::new();
+
+    for path in files {
+        let rel = path.strip_prefix(local_root).unwrap_or(path);
+        let rel_str = rel.to_string_lossy().replace('\\', "/");
+
+        if let Some(unchanged) = unchanged_file(op, &layout, state, path, &rel_str).await {
+            results.push(Ok(unchanged));
+            continue;
+        }
+
+        let index_version = match claim(op, &layout, state, path, &rel_str).await {
+            Ok(version) => version,
+            Err(e) => {
+                results.push(Err(e));
+                continue;
+            }
+        };
+        let content = match tokio::fs::read(path).await {
+            Ok(content) => content,
+            Err(e) => {
+                results.push(Err(anyhow::Error::new(e)
+                    .context(format!("reading {} for packing", path.display()))));
+                continue;
+            }
+        };
+        let hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&content));
+        members.push(Member {
+            path: path.clone(),
+            rel_path: rel_str,
+            slot: PackSlot {
+                offset: data.len() as u64,
+                len: content.len() as u64,
+                hash,
+            },
+            index_version,
+        });
+        data.extend_from_slice(&content);
+
+        if data.len() as u64 >= PACK_TARGET_BYTES {
+            let batch = std::mem::take(&mut members);
+            let pack = std::mem::take(&mut data);
+            results.extend(write_pack(op, &layout, state, batch, pack, device_id, stage).await);
+        }
+    }
+    if !members.is_empty() {
+        results.extend(write_pack(op, &layout, state, members, data, device_id, stage).await);
+    }
+    results
+}
+
+/// The result for `path` if the state cache says it is unchanged and its
+/// index entry can still be read.
+async fn unchanged_file(
+    op: &Operator,
+    layout: &RemoteLayout,
+    state: &StateCache,
+    path: &Path,
+    rel_path: &str,
+) -> Option<PackedFile> {
+    if !matches!(state.needs_sync(path), Ok(None)) {
+        return None;
+    }
+    let data = op.read(&layout.index_key(rel_path)).await.ok()?;
+    let entry = IndexEntry::from_bytes(&data.to_bytes()).ok()?;
+    if entry.is_tombstone() || entry.is_symlink() {
+        return None;
+    }
+    debug!(path = %path.display(), "skip: unchanged since last sync");
+    Some(PackedFile {
+        path: path.to_path_buf(),
+        rel_path: rel_path.to_string(),
+        hash: entry.manifest_hash,
+        bytes: entry.size,
+        pack: entry.pack,
+        skipped: true,
+    })
+}
+
+/// The version of `rel_path`'s index entry for a pack to replace: absent, or
+/// holding the content this device last synced from `path`. Any other entry
+/// fails with [`TrackedElsewhere`].
+async fn claim(
+    op: &Operator,
+    layout: &RemoteLayout,
+    state: &StateCache,
+    path: &Path,
+    rel_path: &str,
+) -> Result<ObjectVersion> {
+    let key = layout.index_key(rel_path);
+    let version = stat_version(op, &key).await?;
+    if version == ObjectVersion::Absent {
+        return Ok(version);
+    }
+    let data = op
+        .read(&key)
+        .await
+        .with_context(|| format!("reading index entry: {key}"))?;
+    let entry = IndexEntry::from_bytes(&data.to_bytes())
+        .with_context(|| format!("parsing index entry: {key}"))?;
+    if state
+        .get(path)
+        .is_some_and(|s| s.blake3 == entry.manifest_hash)
+    {
+        return Ok(version);
+    }
+    Err(TrackedElsewhere {
+        path: path.to_path_buf(),
+        rel_path: rel_path.to_string(),
+    }
+    .into())
+}
+
+/// Store one pack and its index, then point each member's index entry and
+/// state at it.
+async fn write_pack(
+    op: &Operator,
+    layout: &RemoteLayout,
+    state: &StateCache,
+    members: Vec<Member>,
+    data: Vec<u8>,
+    device_id: &str,
+    stage: Option<&IndexStage>,
+) -> Vec<Result<PackedFile>> {
+    let id = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&data));
+    let index = PackIndex {
+        id: id.clone(),
+        files: members
+            .iter()
+            .map(|m| (m.rel_path.clone(), m.slot.clone()))
+            .collect(),
+        written_by: device_id.to_string(),
+        written_at: state.now_secs(),
+    };
+
+    if let Err(e) = store_pack(op, layout, state, &index, data).await {
+        let e = format!("{e:#}");
+        return members
+            .into_iter()
+            .map(|m| Err(anyhow::anyhow!("packing {}: {e}", m.rel_path)))
+            .collect();
+    }
+    debug!(pack = %id, files = members.len(), "wrote pack");
+
+    let pack_key = layout.pack_key(&id);
+    let mut results = Vec::with_capacity(members.len());
+    for member in members {
+        let pack = PackRef {
+            id: id.clone(),
+            offset: member.slot.offset,
+            len: member.slot.len,
+        };
+        results.push(
+            record_member(
+                op, layout, state, &member, &pack, &pack_key, device_id, stage,
+            )
+            .await
+            .map(|()| PackedFile {
+                path: member.path.clone(),
+                rel_path: member.rel_path.clone(),
+                hash: member.slot.hash.clone(),
+                bytes: member.slot.len,
+                pack: Some(pack),
+                skipped: false,
+            }),
+        );
+    }
+    results
+}
+
+async fn store_pack(
+    op: &Operator,
+    layout: &RemoteLayout,
+    state: &StateCache,
+    index: &PackIndex,
+    data: Vec<u8>,
+) -> Result<()> {
+    let pack_key = layout.pack_key(&index.id);
+    let index_key = layout.pack_index_key(&index.id);
+    let body = serde_json::to_vec_pretty(index).context("serializing pack index")?;
+    let charge =
+        crate::engine::charge_quota(state, layout.prefix(), (data.len() + body.len()) as u64)?;
+    op.write(&pack_key, data)
+        .await
+        .with_context(|| format!("writing pack: {pack_key}"))?;
+    op.write(&index_key, body)
+        .await
+        .with_context(|| format!("writing pack index: {index_key}"))?;
+    charge.keep();
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn record_member(
+    op: &Operator,
+    layout: &RemoteLayout,
+    state: &StateCache,
+    member: &Member,
+    pack: &PackRef,
+    pack_key: &str,
+    device_id: &str,
+    stage: Option<&IndexStage>,
+) -> Result<()> {
+    let mut vclock = state
+        .get(&member.path)
+        .map(|s| s.vclock.clone())
+        .unwrap_or_default();
+    if !device_id.is_empty() {
+        vclock.tick(device_id);
+    }
+    let sync_state = make_sync_state_at(
+        &member.path,
+        member.slot.hash.clone(),
+        0,
+        pack_key.to_string(),
+        vclock,
+        device_id.to_string(),
+        state.now_secs(),
+    )?;
+    let mut entry = IndexEntry::new(
+        &member.slot.hash,
+        member.slot.len,
+        0,
+        Some(sync_state.mtime),
+    );
+    entry.mode = crate::engine::file_mode(&member.path);
+    entry.pack = Some(pack.clone());
+    state.set(&member.path, sync_state);
+
+    let key = layout.index_key(&member.rel_path);
+    match put_index(op, stage, &key, entry.to_bytes(), &member.index_version).await {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            if e.is::<ConcurrentModification>() {
+                // Another device rewrote the entry since it was claimed
+                state.remove(&member.path);
+            }
+            Err(e.context(format!("writing index entry: {key}")))
+        }
+    }
+}
+
+/// Read the index of pack `id` under `remote_prefix`.
+pub async fn read_pack_index(op: &Operator, remote_prefix: &str, id: &str) -> Result<PackIndex> {
+    let key = RemoteLayout::new(remote_prefix).pack_index_key(id);
+    let data = op
+        .read(&key)
+        .await
+        .with_context(|| format!("reading pack index: {key}"))?;
+    serde_json::from_slice(&data.to_bytes()).with_context(|| format!("parsing pack index: {key}"))
+}
+
+/// The member of pack `id` whose content hashes to `hash`: its rel_path and
+/// an index entry for its range of the pack. The member's live index entry
+/// is used while it still names that range, so its mode is kept.
+pub async fn find_packed(
+    op: &Operator,
+    remote_prefix: &str,
+    id: &str,
+    hash: &str,
+) -> Result<(String, IndexEntry)> {
+    let index = read_pack_index(op, remote_prefix, id).await?;
+    let (rel_path, slot) = index
+        .files
+        .iter()
+        .find(|(_, slot)| slot.hash == hash)
+        .with_context(|| format!("no file with hash {hash} in pack {id}"))?;
+    let pack = PackRef {
+        id: id.to_string(),
+        offset: slot.offset,
+        len: slot.len,
+    };
+
+    let key = RemoteLayout::new(remote_prefix).index_key(rel_path);
+    let live = match op.read(&key).await {
+        Ok(data) => IndexEntry::from_bytes(&data.to_bytes()).ok(),
+        Err(_) => None,
+    };
+    let entry = match live {
+        Some(entry) if entry.manifest_hash == hash && entry.pack.as_ref() == Some(&pack) => entry,
+        _ => {
+            let mut entry = IndexEntry::new(hash, slot.len, 0, None);
+            entry.pack = Some(pack);
+            entry
+        }
+    };
+    Ok((rel_path.clone(), entry))
+}
+
+/// Read the content of `rel_path` from its pack range, checking it against
+/// `hash` (BLAKE3 hex of the content).
+pub async fn read_packed(
+    op: &Operator,
+    remote_prefix: &str,
+    rel_path: &str,
+    hash: &str,
+    pack: &PackRef,
+) -> Result<Vec<u8>> {
+    let key = RemoteLayout::new(remote_prefix).pack_key(&pack.id);
+    let data = op
+        .read_with(&key)
+        .range(pack.offset..pack.offset + pack.len)
+        .await
+        .with_context(|| format!("reading {rel_path} from pack: {key}"))?
+        .to_vec();
+    let actual = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&data));
+    if actual != hash || data.len() as u64 != pack.len {
+        return Err(PackedHashMismatch {
+            rel_path: rel_path.to_string(),
+            pack: pack.id.clone(),
+            expected: hash.to_string(),
+            actual,
+        }
+        .into());
+    }
+    Ok(data)
+}
+
+/// Restore the packed file described by `entry` to `local_path`.
+///
+/// Returns the bytes written.
+pub async fn pull_packed(
+    op: &Operator,
+    remote_prefix: &str,
+    rel_path: &str,
+    entry: &IndexEntry,
+    local_path: &Path,
+    mode_umask: u32,
+) -> Result<u64> {
+    let pack = entry
+        .pack
+        .as_ref()
+        .with_context(|| format!("{rel_path} is not packed"))?;
+    let data = read_packed(op, remote_prefix, rel_path, &entry.manifest_hash, pack).await?;
+    crate::engine::write_file_atomic(local_path, &data, entry.mode, mode_umask).await?;
+    Ok(data.len() as u64)
+}
@@ -0,0 +1,147 @@
+//! Point-in-time snapshots of a whole prefix
+//!
+//! `create_snapshot` captures the index as it stands into a tree manifest
+//! (see `crate::tree`) and records a pointer at
+//! `{prefix}/snapshots/{timestamp}-{root_hash}`; `list_snapshots` reads the
+//! pointers back, oldest first, and `restore_snapshot` materializes one with
+//! `pull_tree`. Snapshot ids are `{timestamp}-{first 12 hex of root_hash}`.
+//! Manifests and chunks a snapshot refers to must be kept by garbage
+//! collection for as long as its pointer exists.
+
+use anyhow::{Context, Result};
+use opendal::Operator;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tcfs_core::layout::RemoteLayout;
+
+use crate::engine::OptionalEncryption;
+use crate::tree::PullTreeStats;
+
+/// Length of the root-hash part of a snapshot id.
+const SNAPSHOT_ID_HASH_LEN: usize = 12;
+
+/// One recorded snapshot of a prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// `{timestamp}-{short root hash}`, as accepted by [`restore_snapshot`]
+    pub id: String,
+    /// Unix timestamp (seconds) the snapshot was taken at
+    pub timestamp: u64,
+    /// Root hash of the snapshot's tree manifest
+    pub root_hash: String,
+    /// Regular files in the snapshot
+    pub files: usize,
+    /// Device that took the snapshot
+    pub written_by: String,
+    /// Storage key of the snapshot pointer
+    pub key: String,
+}
+
+/// Body of a snapshot pointer.
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotPointer {
+    files: usize,
+    #[serde(default)]
+    written_by: String,
+}
+
+fn snapshot_id(timestamp: u64, root_hash: &str) -> String {
+    format!(
+        "{timestamp}-{}",
+        &root_hash[..SNAPSHOT_ID_HASH_LEN.min(root_hash.len())]
+    )
+}
+
+/// Snapshot everything currently indexed under `prefix` as of `timestamp`.
+pub async fn create_snapshot(
+    op: &Operator,
+    prefix: &str,
+    device_id: &str,
+    timestamp: u64,
+) -> Result<Snapshot> {
+    let mut tree = crate::tree::tree_from_index(op, prefix).await?;
+    tree.written_by = device_id.to_string();
+    tree.written_at = timestamp;
+    let root_hash = crate::tree::write_tree(op, prefix, &tree).await?;
+
+    let key = format!(
+        "{}{timestamp:020}-{root_hash}",
+        RemoteLayout::new(prefix).snapshots_dir()
+    );
+    let pointer = SnapshotPointer {
+        files: tree.files.len(),
+        written_by: device_id.to_string(),
+    };
+    op.write(&key, serde_json::to_vec(&pointer)?)
+        .await
+        .with_context(|| format!("writing snapshot pointer: {key}"))?;
+
+    Ok(Snapshot {
+        id: snapshot_id(timestamp, &root_hash),
+        timestamp,
+        root_hash,
+        files: pointer.files,
+        written_by: pointer.written_by,
+        key,
+    })
+}
+
+/// All snapshots recorded under `prefix`, oldest first.
+pub async fn list_snapshots(op: &Operator, prefix: &str) -> Result<Vec<Snapshot>> {
+    let dir = RemoteLayout::new(prefix).snapshots_dir();
+    let entries = op
+        .list(&dir)
+        .await
+        .with_context(|| format!("listing snapshots: {dir}"))?;
+
+    let mut snapshots = Vec::new();
+    for entry in entries {
+        if entry.metadata().is_dir() {
+            continue;
+        }
+        let Some((ts, root_hash)) = entry.name().split_once('-') else {
+            continue;
+        };
+        let Ok(timestamp) = ts.parse::<u64>() else {
+            continue;
+        };
+        let data = op
+            .read(entry.path())
+            .await
+            .with_context(|| format!("reading snapshot pointer: {}", entry.path()))?;
+        let pointer: SnapshotPointer = serde_json::from_slice(&data.to_bytes())
+            .with_context(|| format!("parsing snapshot pointer: {}", entry.path()))?;
+        snapshots.push(Snapshot {
+            id: snapshot_id(timestamp, root_hash),
+            timestamp,
+            root_hash: root_hash.to_string(),
+            files: pointer.files,
+            written_by: pointer.written_by,
+            key: entry.path().to_string(),
+        });
+    }
+    snapshots.sort_by(|a, b| a.key.cmp(&b.key));
+    Ok(snapshots)
+}
+
+/// Restore the snapshot `id` of `prefix` into `dest`, which must not exist
+/// or be an empty directory.
+///
+/// `id` is a snapshot id from [`list_snapshots`] or the snapshot's full root
+/// hash.
+pub async fn restore_snapshot(
+    op: &Operator,
+    prefix: &str,
+    id: &str,
+    dest: &Path,
+    encryption: OptionalEncryption<'_>,
+) -> Result<(Snapshot, PullTreeStats)> {
+    let snapshot = list_snapshots(op, prefix)
+        .await?
+        .into_iter()
+        .rev()
+        .find(|s| s.id == id || s.root_hash == id)
+        .with_context(|| format!("no snapshot {id} under {prefix}"))?;
+    let stats = crate::tree::pull_tree(op, prefix, &snapshot.root_hash, dest, encryption).await?;
+    Ok((snapshot, stats))
+}
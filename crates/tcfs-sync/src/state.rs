@@ -9,13 +9,30 @@
 //! Each entry records: blake3 hash, file size, mtime, chunk count, remote path,
 //! and last sync timestamp. This allows re-push to detect unchanged files in O(1)
 //! per file (stat + hash comparison against cached hash).
+//!
+//! The JSON cache is safe to share between tasks: `get`/`set`/`flush` take
+//! `&self`, entries live in independently locked shards so updates to
+//! different files don't contend, and `flush` serializes a snapshot under its
+//! own lock so the file on disk is always replaced whole.
+//!
+//! The JSON cache can also carry per-prefix chunk filters (see
+//! `crate::chunk_filter`), stored in a `<state file>.chunks` sidecar and
+//! loaded on first use. Tombstones for files removed by a remote deletion
+//! live in a `<state file>.tombstones` sidecar, so an older event for the
+//! same path cannot bring the file back.
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Mutex, MutexGuard, RwLock};
+use std::time::UNIX_EPOCH;
+use tcfs_core::clock::{Clock, SharedClock, SystemClock};
 
+use crate::chunk_filter::ChunkFilter;
 use crate::conflict::VectorClock;
 
 /// Sync state for a single local file
@@ -41,18 +58,181 @@ pub struct SyncState {
     pub device_id: String,
 }
 
+/// A file removed locally because another device deleted it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tombstone {
+    /// Vector clock of the deletion
+    pub vclock: VectorClock,
+    /// Unix timestamp at which the deletion was applied
+    pub deleted_at: u64,
+}
+
+/// Byte totals over the files a state cache tracks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContentTotals {
+    /// Sum of every tracked file's size
+    pub logical_bytes: u64,
+    /// Sum of sizes over distinct content hashes, counting identical files once
+    pub unique_bytes: u64,
+}
+
+impl ContentTotals {
+    /// Logical bytes per distinct-content byte (whole-file dedup); 0 when
+    /// nothing is tracked.
+    pub fn dedup_ratio(&self) -> f64 {
+        ratio(self.logical_bytes, self.unique_bytes)
+    }
+
+    /// Distinct-content bytes per byte stored under the prefix, which covers
+    /// compression and chunk-level dedup; 0 while `stored_bytes` is unknown.
+    pub fn compression_ratio(&self, stored_bytes: u64) -> f64 {
+        ratio(self.unique_bytes, stored_bytes)
+    }
+}
+
+fn ratio(numerator: u64, denominator: u64) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+/// Tombstones by path key, and whether they have unsaved changes.
+#[derive(Default)]
+struct Tombstones {
+    map: HashMap<String, Tombstone>,
+    dirty: bool,
+}
+
+/// Number of independently locked shards the entry map is split into.
+const SHARDS: usize = 16;
+
+/// Clock skew allowed for remote manifests when none is configured (1 hour).
+pub const DEFAULT_MAX_CLOCK_SKEW_SECS: u64 = 3600;
+
+/// Entry map split across `SHARDS` locks by key hash.
+struct ShardedEntries {
+    shards: Vec<RwLock<HashMap<String, SyncState>>>,
+}
+
+impl ShardedEntries {
+    fn new(entries: HashMap<String, SyncState>) -> Self {
+        let shards: Vec<_> = (0..SHARDS).map(|_| RwLock::new(HashMap::new())).collect();
+        let sharded = ShardedEntries { shards };
+        for (key, state) in entries {
+            sharded.insert(key, state);
+        }
+        sharded
+    }
+
+    fn shard(&self, key: &str) -> &RwLock<HashMap<String, SyncState>> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % SHARDS]
+    }
+
+    fn get(&self, key: &str) -> Option<SyncState> {
+        let shard = self.shard(key).read().unwrap_or_else(|e| e.into_inner());
+        shard.get(key).cloned()
+    }
+
+    fn insert(&self, key: String, state: SyncState) {
+        let mut shard = self.shard(&key).write().unwrap_or_else(|e| e.into_inner());
+        shard.insert(key, state);
+    }
+
+    fn remove(&self, key: &str) -> bool {
+        let mut shard = self.shard(key).write().unwrap_or_else(|e| e.into_inner());
+        shard.remove(key).is_some()
+    }
+
+    fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|s| s.read().unwrap_or_else(|e| e.into_inner()).len())
+            .sum()
+    }
+
+    /// First entry matching `pred`, shard by shard.
+    fn find(&self, pred: impl Fn(&SyncState) -> bool) -> Option<(String, SyncState)> {
+        self.shards.iter().find_map(|s| {
+            let shard = s.read().unwrap_or_else(|e| e.into_inner());
+            shard
+                .iter()
+                .find(|(_, state)| pred(state))
+                .map(|(k, v)| (k.clone(), v.clone()))
+        })
+    }
+
+    /// Copy of every entry, taking each shard's lock in turn.
+    fn snapshot(&self) -> HashMap<String, SyncState> {
+        let mut all = HashMap::new();
+        for shard in &self.shards {
+            let shard = shard.read().unwrap_or_else(|e| e.into_inner());
+            all.extend(shard.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        all
+    }
+}
+
+/// Per-prefix chunk filters and their dirty flag.
+#[derive(Default)]
+struct ChunkFilters {
+    /// `None` until the sidecar is first read
+    loaded: Option<HashMap<String, ChunkFilter>>,
+    /// Whether the filters have unsaved changes
+    dirty: bool,
+}
+
 /// In-memory state cache, persisted to a JSON file
 pub struct StateCache {
     /// Path to the JSON state file on disk
     db_path: PathBuf,
     /// In-memory map: canonicalized local path → SyncState
-    entries: HashMap<String, SyncState>,
+    entries: ShardedEntries,
     /// Whether there are unsaved changes
-    dirty: bool,
-    /// Last NATS JetStream sequence processed (for catch-up on restart)
+    dirty: AtomicBool,
+    /// Held for the whole of `flush`, so concurrent flushes don't interleave
+    flush_lock: Mutex<()>,
+    /// Last NATS JetStream sequence processed (for catch-up on restart).
+    /// Tracks one durable consumer; processes with different
+    /// `sync.consumer_suffix`es should not share a cache
     pub last_nats_seq: u64,
     /// Device ID for this machine
     pub device_id: String,
+    /// Target false-positive rate for chunk filters (`None` = filters off)
+    chunk_filter_fp_rate: Option<f64>,
+    /// Per-prefix chunk filters, loaded from the sidecar on first use
+    chunk_filters: Mutex<ChunkFilters>,
+    /// Refuse every storage write made through this cache
+    read_only: AtomicBool,
+    /// Refuse downloads over local edits that were never pushed
+    guard_local_edits: bool,
+    /// Remote deletions applied locally, persisted in the tombstone sidecar
+    tombstones: Mutex<Tombstones>,
+    /// Byte limit per prefix for pushes through this cache (`None` = unlimited)
+    quota_bytes: Mutex<Option<u64>>,
+    /// Stored bytes per prefix, listed once and then updated on upload
+    usage: Mutex<HashMap<String, u64>>,
+    /// Levels of `chunks/<hh>/` fan-out used for chunks pushed through this cache
+    chunk_shard_depth: AtomicU8,
+    /// In-flight limit for chunk transfers, tuned as they complete
+    transfer: crate::adaptive::AdaptiveConcurrency,
+    /// Where downloads through this cache look for chunks and manifests
+    /// missing from the primary store
+    read_mirrors: RwLock<crate::store::ReadMirrors>,
+    /// `Cache-Control` set on chunks pushed through this cache
+    cache_control: RwLock<Option<String>>,
+    /// Seconds a remote manifest's `written_at` may lie ahead of local time
+    max_clock_skew: AtomicU64,
+    /// Time source for the timestamps written through this cache
+    clock: SharedClock,
+    /// Files smaller than this are packed by tree pushes (`None` = never)
+    pack_threshold: Mutex<Option<u64>>,
+    /// This device's key for signing the manifests it writes
+    #[cfg(feature = "crypto")]
+    signing_key: Option<std::sync::Arc<tcfs_crypto::DeviceSigningKey>>,
 }
 
 impl StateCache {
@@ -67,34 +247,368 @@ impl StateCache {
         } else {
             HashMap::new()
         };
+        let tombstone_path = sidecar_path(db_path, "tombstones");
+        let tombstones = if tombstone_path.exists() {
+            let content = std::fs::read_to_string(&tombstone_path)
+                .with_context(|| format!("reading tombstones: {}", tombstone_path.display()))?;
+            serde_json::from_str(&content)
+                .with_context(|| format!("parsing tombstones: {}", tombstone_path.display()))?
+        } else {
+            HashMap::new()
+        };
 
         Ok(StateCache {
             db_path: db_path.to_path_buf(),
-            entries,
-            dirty: false,
+            entries: ShardedEntries::new(entries),
+            dirty: AtomicBool::new(false),
+            flush_lock: Mutex::new(()),
             last_nats_seq: 0,
             device_id: String::new(),
+            chunk_filter_fp_rate: None,
+            chunk_filters: Mutex::new(ChunkFilters::default()),
+            read_only: AtomicBool::new(false),
+            guard_local_edits: false,
+            tombstones: Mutex::new(Tombstones {
+                map: tombstones,
+                dirty: false,
+            }),
+            quota_bytes: Mutex::new(None),
+            usage: Mutex::new(HashMap::new()),
+            chunk_shard_depth: AtomicU8::new(0),
+            transfer: crate::adaptive::AdaptiveConcurrency::default(),
+            read_mirrors: RwLock::new(crate::store::ReadMirrors::default()),
+            cache_control: RwLock::new(None),
+            max_clock_skew: AtomicU64::new(DEFAULT_MAX_CLOCK_SKEW_SECS),
+            clock: tcfs_core::clock::system(),
+            pack_threshold: Mutex::new(None),
+            #[cfg(feature = "crypto")]
+            signing_key: None,
         })
     }
 
+    /// Enable chunk filters at the given false-positive rate.
+    ///
+    /// Rates outside `(0, 1)` disable them, so every chunk gets an `exists`
+    /// check as before.
+    pub fn enable_chunk_filter(&mut self, fp_rate: f64) {
+        self.chunk_filter_fp_rate = (fp_rate > 0.0 && fp_rate < 1.0).then_some(fp_rate);
+    }
+
+    /// Mark the store read-only: uploads and tree pushes through this cache
+    /// fail with `engine::ReadOnlyStore` before contacting storage.
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::Relaxed);
+    }
+
+    /// Make downloads through this cache fail with
+    /// `engine::LocallyModified` instead of overwriting a file whose content
+    /// matches neither the download nor the hash recorded at its last sync.
+    pub fn set_guard_local_edits(&mut self, guard: bool) {
+        self.guard_local_edits = guard;
+    }
+
+    /// Whether downloads refuse to overwrite local edits.
+    pub fn guards_local_edits(&self) -> bool {
+        self.guard_local_edits
+    }
+
+    /// Sign every manifest written through this cache with `key`.
+    #[cfg(feature = "crypto")]
+    pub fn set_signing_key(&mut self, key: Option<std::sync::Arc<tcfs_crypto::DeviceSigningKey>>) {
+        self.signing_key = key;
+    }
+
+    /// The key manifests written through this cache are signed with.
+    #[cfg(feature = "crypto")]
+    pub fn signing_key(&self) -> Option<&tcfs_crypto::DeviceSigningKey> {
+        self.signing_key.as_deref()
+    }
+
+    /// Whether the store was marked read-only.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    /// Cap the bytes pushes may leave under each prefix (`None` = unlimited).
+    pub fn set_quota(&self, quota_bytes: Option<u64>) {
+        *self.quota_bytes.lock().unwrap_or_else(|e| e.into_inner()) = quota_bytes;
+    }
+
+    /// The configured per-prefix quota, if any.
+    pub fn quota(&self) -> Option<u64> {
+        *self.quota_bytes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Shard newly pushed chunks this many levels deep (0 = flat).
+    pub fn set_chunk_shard_depth(&self, depth: u8) {
+        self.chunk_shard_depth.store(depth, Ordering::Relaxed);
+    }
+
+    /// Chunk shard depth used for pushes through this cache.
+    pub fn chunk_shard_depth(&self) -> u8 {
+        self.chunk_shard_depth.load(Ordering::Relaxed)
+    }
+
+    /// Bound the in-flight chunk transfers made through this cache.
+    pub fn set_transfer_concurrency(&mut self, min: usize, max: usize) {
+        self.transfer = crate::adaptive::AdaptiveConcurrency::new(min, max);
+    }
+
+    /// Controller for chunk transfers through this cache, shared so what one
+    /// file's transfers learn carries over to the next.
+    pub fn transfer_concurrency(&self) -> &crate::adaptive::AdaptiveConcurrency {
+        &self.transfer
+    }
+
+    /// Fall back to `mirrors` for chunks and manifests that downloads
+    /// through this cache find missing from the primary store.
+    pub fn set_read_mirrors(&self, mirrors: crate::store::ReadMirrors) {
+        *self.read_mirrors.write().unwrap_or_else(|e| e.into_inner()) = mirrors;
+    }
+
+    /// Read-through mirrors for downloads through this cache.
+    pub fn read_mirrors(&self) -> crate::store::ReadMirrors {
+        self.read_mirrors
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Write chunks pushed through this cache with `Cache-Control: value`.
+    pub fn set_cache_control(&self, value: Option<String>) {
+        *self
+            .cache_control
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = value;
+    }
+
+    /// `Cache-Control` for chunks pushed through this cache, if any.
+    pub fn cache_control(&self) -> Option<String> {
+        self.cache_control
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Flag remote manifests stamped more than `secs` ahead of local time.
+    pub fn set_max_clock_skew(&self, secs: u64) {
+        self.max_clock_skew.store(secs, Ordering::Relaxed);
+    }
+
+    /// Allowed clock skew for remote manifests, in seconds.
+    pub fn max_clock_skew(&self) -> u64 {
+        self.max_clock_skew.load(Ordering::Relaxed)
+    }
+
+    /// Pack files smaller than `threshold` bytes into shared packfiles on
+    /// tree pushes (`None` = upload every file on its own).
+    pub fn set_pack_threshold(&self, threshold: Option<u64>) {
+        *self
+            .pack_threshold
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = threshold;
+    }
+
+    /// Size below which tree pushes pack files.
+    pub fn pack_threshold(&self) -> Option<u64> {
+        *self
+            .pack_threshold
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Read time from `clock` instead of the system clock (tests install a
+    /// `MockClock`).
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock;
+    }
+
+    /// Time source for timestamps written through this cache.
+    pub fn clock(&self) -> &SharedClock {
+        &self.clock
+    }
+
+    /// Current time in unix seconds, per [`StateCache::clock`].
+    pub fn now_secs(&self) -> u64 {
+        self.clock.unix_secs()
+    }
+
+    /// Stored bytes under `prefix`, if they have been counted.
+    pub fn cached_usage(&self, prefix: &str) -> Option<u64> {
+        let usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        usage.get(prefix.trim_matches('/')).copied()
+    }
+
+    /// Record a fresh count of the bytes stored under `prefix`.
+    pub fn set_usage(&self, prefix: &str, bytes: u64) {
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        usage.insert(prefix.trim_matches('/').to_string(), bytes);
+    }
+
+    /// Add `bytes` to the usage of `prefix` if that stays within the quota.
+    ///
+    /// Returns the usage before the charge, or `Err` with it when the
+    /// charge would go over; nothing is added in that case. Without a quota
+    /// every charge succeeds.
+    pub fn charge_usage(&self, prefix: &str, bytes: u64) -> std::result::Result<u64, u64> {
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        let used = usage
+            .entry(prefix.trim_matches('/').to_string())
+            .or_insert(0);
+        let before = *used;
+        if self
+            .quota()
+            .is_some_and(|q| before.saturating_add(bytes) > q)
+        {
+            return Err(before);
+        }
+        *used = before.saturating_add(bytes);
+        Ok(before)
+    }
+
+    /// Take back `bytes` charged to `prefix` for a write that then failed.
+    pub fn refund_usage(&self, prefix: &str, bytes: u64) {
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(used) = usage.get_mut(prefix.trim_matches('/')) {
+            *used = used.saturating_sub(bytes);
+        }
+    }
+
+    /// The configured chunk filter false-positive rate, if filters are on.
+    pub fn chunk_filter_fp_rate(&self) -> Option<f64> {
+        self.chunk_filter_fp_rate
+    }
+
+    fn chunk_filter_path(&self) -> PathBuf {
+        sidecar_path(&self.db_path, "chunks")
+    }
+
+    /// Lock the chunk filters, reading the sidecar on first use.
+    fn chunk_filters(&self) -> MutexGuard<'_, ChunkFilters> {
+        let mut filters = self.chunk_filters.lock().unwrap_or_else(|e| e.into_inner());
+        if filters.loaded.is_none() {
+            let path = self.chunk_filter_path();
+            let loaded = std::fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| match serde_json::from_str(&content) {
+                    Ok(filters) => Some(filters),
+                    Err(e) => {
+                        tracing::warn!("ignoring unreadable chunk filter {}: {e}", path.display());
+                        None
+                    }
+                })
+                .unwrap_or_default();
+            filters.loaded = Some(loaded);
+        }
+        filters
+    }
+
+    /// Whether `prefix` has a usable chunk filter: one exists, was built at the
+    /// configured rate, and is not saturated. When this is false and filters
+    /// are enabled, the engine rebuilds it from the manifests of tracked files.
+    pub fn has_chunk_filter(&self, prefix: &str) -> bool {
+        let Some(fp_rate) = self.chunk_filter_fp_rate else {
+            return false;
+        };
+        self.chunk_filters()
+            .loaded
+            .as_ref()
+            .and_then(|map| map.get(prefix.trim_end_matches('/')))
+            .is_some_and(|f| f.fp_rate == fp_rate && !f.is_saturated())
+    }
+
+    /// Replace the chunk filter for `prefix`.
+    pub fn install_chunk_filter(&self, prefix: &str, filter: ChunkFilter) {
+        let mut filters = self.chunk_filters();
+        filters
+            .loaded
+            .get_or_insert_with(HashMap::new)
+            .insert(prefix.trim_end_matches('/').to_string(), filter);
+        filters.dirty = true;
+    }
+
+    /// Ask the filter for `prefix` about a chunk key.
+    ///
+    /// `None` when no usable filter is loaded (check the backend as usual),
+    /// `Some(false)` when the chunk was definitely never recorded, and
+    /// `Some(true)` when it probably was.
+    pub fn chunk_known(&self, prefix: &str, chunk_hash: &str) -> Option<bool> {
+        if !self.has_chunk_filter(prefix) {
+            return None;
+        }
+        self.chunk_filters()
+            .loaded
+            .as_ref()
+            .and_then(|map| map.get(prefix.trim_end_matches('/')))
+            .map(|f| f.contains(chunk_hash))
+    }
+
+    /// Record that a chunk is present under `prefix`.
+    pub fn record_chunk(&self, prefix: &str, chunk_hash: &str) {
+        if self.chunk_filter_fp_rate.is_none() {
+            return;
+        }
+        let key = prefix.trim_end_matches('/');
+        let mut filters = self.chunk_filters();
+        let filters = &mut *filters;
+        if let Some(filter) = filters.loaded.as_mut().and_then(|map| map.get_mut(key)) {
+            if !filter.contains(chunk_hash) {
+                filter.insert(chunk_hash);
+                filters.dirty = true;
+            }
+        }
+    }
+
+    /// Manifest paths of tracked files under `prefix`, with their chunk counts.
+    pub fn manifests_under(&self, prefix: &str) -> Vec<(String, usize)> {
+        let manifests = tcfs_core::layout::RemoteLayout::new(prefix).manifests_dir();
+        self.entries
+            .snapshot()
+            .into_values()
+            .filter(|s| s.remote_path.starts_with(&manifests))
+            .map(|s| (s.remote_path, s.chunk_count))
+            .collect()
+    }
+
     /// Look up the sync state for a local file path.
-    pub fn get(&self, local_path: &Path) -> Option<&SyncState> {
-        let key = path_key(local_path);
-        self.entries.get(&key)
+    pub fn get(&self, local_path: &Path) -> Option<SyncState> {
+        self.entries.get(&path_key(local_path))
     }
 
     /// Update (or insert) the sync state for a local file.
-    pub fn set(&mut self, local_path: &Path, state: SyncState) {
-        let key = path_key(local_path);
-        self.entries.insert(key, state);
-        self.dirty = true;
+    pub fn set(&self, local_path: &Path, state: SyncState) {
+        self.entries.insert(path_key(local_path), state);
+        self.dirty.store(true, Ordering::SeqCst);
     }
 
     /// Remove the sync state for a file (e.g. after deletion).
-    pub fn remove(&mut self, local_path: &Path) {
-        let key = path_key(local_path);
-        if self.entries.remove(&key).is_some() {
-            self.dirty = true;
+    pub fn remove(&self, local_path: &Path) {
+        if self.entries.remove(&path_key(local_path)) {
+            self.dirty.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Record that `local_path` was removed by a deletion with `vclock`.
+    pub fn set_tombstone(&self, local_path: &Path, vclock: VectorClock) {
+        let deleted_at = self.now_secs();
+        let mut tombstones = self.tombstones.lock().unwrap_or_else(|e| e.into_inner());
+        tombstones
+            .map
+            .insert(path_key(local_path), Tombstone { vclock, deleted_at });
+        tombstones.dirty = true;
+    }
+
+    /// The tombstone left by a remote deletion of `local_path`, if any.
+    pub fn tombstone(&self, local_path: &Path) -> Option<Tombstone> {
+        let tombstones = self.tombstones.lock().unwrap_or_else(|e| e.into_inner());
+        tombstones.map.get(&path_key(local_path)).cloned()
+    }
+
+    /// Forget the tombstone for `local_path` (e.g. when a newer version arrives).
+    pub fn clear_tombstone(&self, local_path: &Path) {
+        let mut tombstones = self.tombstones.lock().unwrap_or_else(|e| e.into_inner());
+        if tombstones.map.remove(&path_key(local_path)).is_some() {
+            tombstones.dirty = true;
         }
     }
 
@@ -104,34 +618,66 @@ impl StateCache {
     }
 
     pub fn is_empty(&self) -> bool {
-        self.entries.is_empty()
+        self.len() == 0
+    }
+
+    /// Every tracked entry as (key, state) pairs.
+    pub fn entries(&self) -> Vec<(String, SyncState)> {
+        self.entries.snapshot().into_iter().collect()
+    }
+
+    /// Logical and distinct-content byte totals over every tracked file.
+    pub fn content_totals(&self) -> ContentTotals {
+        let mut seen = std::collections::HashSet::new();
+        let mut totals = ContentTotals::default();
+        for (_, state) in self.entries.snapshot() {
+            totals.logical_bytes += state.size;
+            if seen.insert(state.blake3) {
+                totals.unique_bytes += state.size;
+            }
+        }
+        totals
+    }
+
+    /// First tracked entry matching `pred`, as (key, state).
+    pub fn find(&self, pred: impl Fn(&SyncState) -> bool) -> Option<(String, SyncState)> {
+        self.entries.find(pred)
     }
 
     /// Find a state entry by its remote path suffix (for NATS event lookups).
-    pub fn get_by_rel_path(&self, rel_path: &str) -> Option<(&str, &SyncState)> {
+    pub fn get_by_rel_path(&self, rel_path: &str) -> Option<(String, SyncState)> {
+        let suffix = format!("/{rel_path}");
         self.entries
-            .iter()
-            .find(|(_, state)| {
-                state.remote_path.ends_with(&format!("/{}", rel_path))
-                    || state.remote_path == rel_path
-            })
-            .map(|(k, v)| (k.as_str(), v))
+            .find(|state| state.remote_path.ends_with(&suffix) || state.remote_path == rel_path)
     }
 
     /// Flush dirty changes to disk using an atomic write (write then rename).
-    pub fn flush(&mut self) -> Result<()> {
-        if !self.dirty {
+    ///
+    /// Entries set while a flush is in progress may or may not be included;
+    /// either way they leave the cache dirty for the next flush.
+    pub fn flush(&self) -> Result<()> {
+        let _flushing = self.flush_lock.lock().unwrap_or_else(|e| e.into_inner());
+        self.flush_chunk_filters()?;
+        self.flush_tombstones()?;
+        if !self.dirty.swap(false, Ordering::SeqCst) {
             return Ok(());
         }
+        let result = self.write_entries();
+        if result.is_err() {
+            self.dirty.store(true, Ordering::SeqCst);
+        }
+        result
+    }
 
+    fn write_entries(&self) -> Result<()> {
         // Ensure parent directory exists
         if let Some(parent) = self.db_path.parent() {
             std::fs::create_dir_all(parent)
                 .with_context(|| format!("creating state dir: {}", parent.display()))?;
         }
 
-        let json =
-            serde_json::to_string_pretty(&self.entries).context("serializing state cache")?;
+        let json = serde_json::to_string_pretty(&self.entries.snapshot())
+            .context("serializing state cache")?;
 
         // Atomic write: write to temp file, then rename
         let tmp_path = self.db_path.with_extension("tmp");
@@ -139,8 +685,51 @@ impl StateCache {
             .with_context(|| format!("writing state cache temp: {}", tmp_path.display()))?;
         std::fs::rename(&tmp_path, &self.db_path)
             .with_context(|| format!("renaming state cache: {}", self.db_path.display()))?;
+        Ok(())
+    }
+
+    fn flush_chunk_filters(&self) -> Result<()> {
+        let mut filters = self.chunk_filters.lock().unwrap_or_else(|e| e.into_inner());
+        if !filters.dirty {
+            return Ok(());
+        }
+        let Some(loaded) = &filters.loaded else {
+            return Ok(());
+        };
+        if let Some(parent) = self.db_path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("creating state dir: {}", parent.display()))?;
+        }
+        let path = self.chunk_filter_path();
+        let json = serde_json::to_string(loaded).context("serializing chunk filters")?;
+        let mut tmp_name = path.as_os_str().to_owned();
+        tmp_name.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_name);
+        std::fs::write(&tmp_path, &json)
+            .with_context(|| format!("writing chunk filter temp: {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, &path)
+            .with_context(|| format!("renaming chunk filter: {}", path.display()))?;
+        filters.dirty = false;
+        Ok(())
+    }
 
-        self.dirty = false;
+    fn flush_tombstones(&self) -> Result<()> {
+        let mut tombstones = self.tombstones.lock().unwrap_or_else(|e| e.into_inner());
+        if !tombstones.dirty {
+            return Ok(());
+        }
+        if let Some(parent) = self.db_path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("creating state dir: {}", parent.display()))?;
+        }
+        let path = sidecar_path(&self.db_path, "tombstones");
+        let json = serde_json::to_string(&tombstones.map).context("serializing tombstones")?;
+        let tmp_path = sidecar_path(&path, "tmp");
+        std::fs::write(&tmp_path, &json)
+            .with_context(|| format!("writing tombstones temp: {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, &path)
+            .with_context(|| format!("renaming tombstones: {}", path.display()))?;
+        tombstones.dirty = false;
         Ok(())
     }
 
@@ -184,7 +773,11 @@ impl StateCache {
 
 impl Drop for StateCache {
     fn drop(&mut self) {
-        if self.dirty {
+        let filters_dirty = self
+            .chunk_filters
+            .get_mut()
+            .map_or(true, |filters| filters.dirty);
+        if *self.dirty.get_mut() || filters_dirty {
             if let Err(e) = self.flush() {
                 tracing::warn!("failed to flush state cache on drop: {e}");
             }
@@ -195,7 +788,7 @@ impl Drop for StateCache {
 /// Trait for state cache backends (JSON and RocksDB).
 pub trait StateCacheBackend {
     /// Look up the sync state for a local file path.
-    fn get(&self, local_path: &Path) -> Option<&SyncState>;
+    fn get(&self, local_path: &Path) -> Option<SyncState>;
     /// Update (or insert) the sync state for a local file.
     fn set(&mut self, local_path: &Path, state: SyncState);
     /// Remove the sync state for a file.
@@ -203,9 +796,9 @@ pub trait StateCacheBackend {
     /// Flush pending changes to durable storage.
     fn flush(&mut self) -> Result<()>;
     /// Return all entries as (key, state) pairs.
-    fn all_entries(&self) -> Vec<(String, &SyncState)>;
+    fn all_entries(&self) -> Vec<(String, SyncState)>;
     /// Find a state entry by its remote path suffix.
-    fn get_by_rel_path(&self, rel_path: &str) -> Option<(&str, &SyncState)>;
+    fn get_by_rel_path(&self, rel_path: &str) -> Option<(String, SyncState)>;
     /// Check if a file needs sync (returns reason or None if up-to-date).
     fn needs_sync(&self, local_path: &Path) -> Result<Option<String>>;
     /// Number of tracked files.
@@ -215,22 +808,22 @@ pub trait StateCacheBackend {
 }
 
 impl StateCacheBackend for StateCache {
-    fn get(&self, local_path: &Path) -> Option<&SyncState> {
+    fn get(&self, local_path: &Path) -> Option<SyncState> {
         self.get(local_path)
     }
     fn set(&mut self, local_path: &Path, state: SyncState) {
-        self.set(local_path, state);
+        StateCache::set(self, local_path, state);
     }
     fn remove(&mut self, local_path: &Path) {
-        self.remove(local_path);
+        StateCache::remove(self, local_path);
     }
     fn flush(&mut self) -> Result<()> {
-        self.flush()
+        StateCache::flush(self)
     }
-    fn all_entries(&self) -> Vec<(String, &SyncState)> {
-        self.entries.iter().map(|(k, v)| (k.clone(), v)).collect()
+    fn all_entries(&self) -> Vec<(String, SyncState)> {
+        self.entries()
     }
-    fn get_by_rel_path(&self, rel_path: &str) -> Option<(&str, &SyncState)> {
+    fn get_by_rel_path(&self, rel_path: &str) -> Option<(String, SyncState)> {
         self.get_by_rel_path(rel_path)
     }
     fn needs_sync(&self, local_path: &Path) -> Result<Option<String>> {
@@ -253,7 +846,7 @@ mod rocksdb_backend {
     /// RocksDB-backed state cache with in-memory mirror for API compatibility.
     ///
     /// On `open()`, all keys are loaded into a `HashMap` mirror so that
-    /// `get()` can return `&SyncState` references. Writes go through to
+    /// reads never touch the database. Writes go through to
     /// RocksDB immediately (write-through), so `flush()` is a no-op.
     pub struct RocksDbStateCache {
         db: rocksdb::DB,
@@ -295,9 +888,9 @@ mod rocksdb_backend {
     }
 
     impl StateCacheBackend for RocksDbStateCache {
-        fn get(&self, local_path: &Path) -> Option<&SyncState> {
+        fn get(&self, local_path: &Path) -> Option<SyncState> {
             let key = super::path_key(local_path);
-            self.entries.get(&key)
+            self.entries.get(&key).cloned()
         }
 
         fn set(&mut self, local_path: &Path, state: SyncState) {
@@ -324,18 +917,21 @@ mod rocksdb_backend {
             Ok(())
         }
 
-        fn all_entries(&self) -> Vec<(String, &SyncState)> {
-            self.entries.iter().map(|(k, v)| (k.clone(), v)).collect()
+        fn all_entries(&self) -> Vec<(String, SyncState)> {
+            self.entries
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect()
         }
 
-        fn get_by_rel_path(&self, rel_path: &str) -> Option<(&str, &SyncState)> {
+        fn get_by_rel_path(&self, rel_path: &str) -> Option<(String, SyncState)> {
             self.entries
                 .iter()
                 .find(|(_, state)| {
                     state.remote_path.ends_with(&format!("/{}", rel_path))
                         || state.remote_path == rel_path
                 })
-                .map(|(k, v)| (k.as_str(), v))
+                .map(|(k, v)| (k.clone(), v.clone()))
         }
 
         fn needs_sync(&self, local_path: &Path) -> Result<Option<String>> {
@@ -453,7 +1049,7 @@ impl StateBackend {
 }
 
 impl StateCacheBackend for StateBackend {
-    fn get(&self, local_path: &Path) -> Option<&SyncState> {
+    fn get(&self, local_path: &Path) -> Option<SyncState> {
         match self {
             StateBackend::Json(c) => c.get(local_path),
             #[cfg(feature = "full")]
@@ -476,19 +1072,19 @@ impl StateCacheBackend for StateBackend {
     }
     fn flush(&mut self) -> Result<()> {
         match self {
-            StateBackend::Json(c) => c.flush(),
+            StateBackend::Json(c) => StateCache::flush(c),
             #[cfg(feature = "full")]
             StateBackend::Rocks(c) => c.flush(),
         }
     }
-    fn all_entries(&self) -> Vec<(String, &SyncState)> {
+    fn all_entries(&self) -> Vec<(String, SyncState)> {
         match self {
             StateBackend::Json(c) => StateCacheBackend::all_entries(c),
             #[cfg(feature = "full")]
             StateBackend::Rocks(c) => c.all_entries(),
         }
     }
-    fn get_by_rel_path(&self, rel_path: &str) -> Option<(&str, &SyncState)> {
+    fn get_by_rel_path(&self, rel_path: &str) -> Option<(String, SyncState)> {
         match self {
             StateBackend::Json(c) => StateCacheBackend::get_by_rel_path(c, rel_path),
             #[cfg(feature = "full")]
@@ -518,6 +1114,14 @@ impl StateCacheBackend for StateBackend {
     }
 }
 
+/// `path` with `.{ext}` appended to the whole file name (`state.db.chunks`).
+fn sidecar_path(path: &Path, ext: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(ext);
+    PathBuf::from(name)
+}
+
 /// Convert a path to a normalized string key for the HashMap
 fn path_key(path: &Path) -> String {
     // Use the canonicalized absolute path as the key
@@ -552,6 +1156,27 @@ pub fn make_sync_state_full(
     remote_path: String,
     vclock: VectorClock,
     device_id: String,
+) -> Result<SyncState> {
+    make_sync_state_at(
+        local_path,
+        hash_hex,
+        chunk_count,
+        remote_path,
+        vclock,
+        device_id,
+        SystemClock.unix_secs(),
+    )
+}
+
+/// [`make_sync_state_full`] with `last_synced` set to `now` (unix seconds).
+pub fn make_sync_state_at(
+    local_path: &Path,
+    hash_hex: String,
+    chunk_count: usize,
+    remote_path: String,
+    vclock: VectorClock,
+    device_id: String,
+    now: u64,
 ) -> Result<SyncState> {
     let meta = std::fs::metadata(local_path)
         .with_context(|| format!("stat for sync state: {}", local_path.display()))?;
@@ -563,11 +1188,6 @@ pub fn make_sync_state_full(
         .map(|d| d.as_secs())
         .unwrap_or(0);
 
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
-
     Ok(SyncState {
         blake3: hash_hex,
         size: meta.len(),
@@ -597,7 +1217,7 @@ mod tests {
         let path = dir.path().join("state.json");
 
         // Write a state entry and flush
-        let mut cache = StateCache::open(&path).unwrap();
+        let cache = StateCache::open(&path).unwrap();
         let fake_path = dir.path().join("file.txt");
         std::fs::write(&fake_path, b"hello").unwrap();
 
@@ -627,7 +1247,7 @@ mod tests {
     fn test_remove_entry() {
         let dir = tempfile::tempdir().unwrap();
         let path = dir.path().join("state.json");
-        let mut cache = StateCache::open(&path).unwrap();
+        let cache = StateCache::open(&path).unwrap();
 
         let fake_path = dir.path().join("to_remove.txt");
         std::fs::write(&fake_path, b"data").unwrap();
@@ -652,11 +1272,30 @@ mod tests {
         assert!(cache.get(&fake_path).is_none());
     }
 
+    #[test]
+    fn tombstones_persist_across_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let gone = dir.path().join("gone.txt");
+        let mut vclock = VectorClock::new();
+        vclock.tick("remote");
+
+        let cache = StateCache::open(&path).unwrap();
+        cache.set_tombstone(&gone, vclock.clone());
+        cache.flush().unwrap();
+
+        let reloaded = StateCache::open(&path).unwrap();
+        assert_eq!(reloaded.tombstone(&gone).unwrap().vclock, vclock);
+        reloaded.clear_tombstone(&gone);
+        reloaded.flush().unwrap();
+        assert!(StateCache::open(&path).unwrap().tombstone(&gone).is_none());
+    }
+
     #[test]
     fn test_multiple_entries() {
         let dir = tempfile::tempdir().unwrap();
         let path = dir.path().join("state.json");
-        let mut cache = StateCache::open(&path).unwrap();
+        let cache = StateCache::open(&path).unwrap();
 
         for i in 0..5 {
             let fake_path = dir.path().join(format!("file_{i}.txt"));
@@ -703,11 +1342,58 @@ mod tests {
     fn test_flush_idempotent() {
         let dir = tempfile::tempdir().unwrap();
         let path = dir.path().join("state.json");
-        let mut cache = StateCache::open(&path).unwrap();
+        let cache = StateCache::open(&path).unwrap();
 
         // Flush empty cache — should succeed even though file doesn't exist
         cache.flush().unwrap();
         // Flush again — no-op
         cache.flush().unwrap();
     }
+
+    #[test]
+    fn concurrent_sets_on_distinct_paths_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let cache = StateCache::open(&path).unwrap();
+        let (threads, per_thread) = (16, 200);
+
+        std::thread::scope(|scope| {
+            for t in 0..threads {
+                let (cache, dir) = (&cache, dir.path());
+                scope.spawn(move || {
+                    for i in 0..per_thread {
+                        let file = dir.join(format!("t{t}/f{i}.txt"));
+                        let state = SyncState {
+                            blake3: format!("{t}-{i}"),
+                            size: 0,
+                            mtime: 0,
+                            chunk_count: 1,
+                            remote_path: format!("bucket/t{t}/f{i}.txt"),
+                            last_synced: 0,
+                            vclock: VectorClock::new(),
+                            device_id: String::new(),
+                        };
+                        cache.set(&file, state);
+                    }
+                });
+            }
+            // Flushes racing the writers must never leave a torn file
+            scope.spawn(|| {
+                for _ in 0..20 {
+                    cache.flush().unwrap();
+                    StateCache::open(&path).expect("state file parses mid-run");
+                }
+            });
+        });
+        cache.flush().unwrap();
+
+        let reloaded = StateCache::open(&path).unwrap();
+        assert_eq!(reloaded.len(), threads * per_thread);
+        for t in 0..threads {
+            for i in 0..per_thread {
+                let file = dir.path().join(format!("t{t}/f{i}.txt"));
+                assert_eq!(reloaded.get(&file).unwrap().blake3, format!("{t}-{i}"));
+            }
+        }
+    }
 }
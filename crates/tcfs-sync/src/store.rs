@@ -0,0 +1,313 @@
+//! Content-addressable store for chunks and manifests
+//!
+//! [`ChunkStore`] owns the key construction and the integrity checks for
+//! the two content-addressed object kinds under a prefix: chunks at
+//! `{prefix}/chunks/...` (sharded per the layout's depth) and manifests at
+//! `{prefix}/manifests/{file_hash}`. The sync engine and the FileProvider
+//! FFI both go through it, so a fix to either path applies to both.
+//! Encoding (compression, encryption) stays with the caller: the store
+//! moves bytes under the hash it is given and checks them on the way back.
+//!
+//! Reads that miss on the primary operator fall through to the store's
+//! [`ReadMirrors`], if any (`storage.read_mirrors`).
+
+use anyhow::{Context, Result};
+use opendal::Operator;
+use tcfs_core::layout::RemoteLayout;
+
+use tracing::{debug, warn};
+
+use crate::engine::ChunkHashMismatch;
+use crate::manifest::SyncManifest;
+
+/// Content type of chunk objects.
+pub const CHUNK_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Content type of manifest objects.
+pub const MANIFEST_CONTENT_TYPE: &str = "application/json";
+
+/// HTTP metadata written along with an object. Fields the backend cannot
+/// store are left off rather than failing the write.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ObjectMeta<'a> {
+    pub content_type: Option<&'a str>,
+    pub cache_control: Option<&'a str>,
+}
+
+impl ObjectMeta<'static> {
+    /// Metadata of a manifest object.
+    pub const MANIFEST: Self = Self {
+        content_type: Some(MANIFEST_CONTENT_TYPE),
+        cache_control: None,
+    };
+}
+
+/// Writes of a chunk whose read-back does not hash to its key before giving up.
+const CHUNK_WRITE_ATTEMPTS: usize = 3;
+
+/// Read-through mirrors: operators tried, in order, for objects the
+/// primary does not have.
+#[derive(Debug, Clone, Default)]
+pub struct ReadMirrors {
+    ops: Vec<Operator>,
+    backfill: bool,
+}
+
+impl ReadMirrors {
+    /// Mirrors tried in the order given. With `backfill`, an object found
+    /// on a mirror is also written to the primary so the next read hits.
+    pub fn new(ops: Vec<Operator>, backfill: bool) -> Self {
+        Self { ops, backfill }
+    }
+
+    /// Whether there are no mirrors to fall back to.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Read `key` from `primary`, or from the first mirror whose copy passes
+    /// `check` when the primary does not have it. A backfilled copy is
+    /// written with `meta`.
+    ///
+    /// Only a `NotFound` on the primary falls through; other primary errors
+    /// are returned as-is, and if no mirror has a good copy the primary's
+    /// `NotFound` is. The primary's bytes are not passed to `check`.
+    pub async fn read(
+        &self,
+        primary: &Operator,
+        key: &str,
+        meta: ObjectMeta<'_>,
+        check: impl Fn(&[u8]) -> Result<()>,
+    ) -> Result<Vec<u8>> {
+        let miss = match primary.read(key).await {
+            Ok(data) => return Ok(data.to_vec()),
+            Err(e) if e.kind() == opendal::ErrorKind::NotFound && !self.ops.is_empty() => e,
+            Err(e) => return Err(e.into()),
+        };
+
+        for (i, mirror) in self.ops.iter().enumerate() {
+            let data = match mirror.read(key).await {
+                Ok(data) => data.to_vec(),
+                Err(e) => {
+                    debug!(key, mirror = i, "read mirror miss: {e}");
+                    continue;
+                }
+            };
+            if let Err(e) = check(&data) {
+                warn!(key, mirror = i, "read mirror copy rejected: {e:#}");
+                continue;
+            }
+            debug!(key, mirror = i, "read from mirror");
+            if self.backfill {
+                // Only fills a gap: never replaces what the primary gained meanwhile
+                let absent = crate::engine::ObjectVersion::Absent;
+                if let Err(e) =
+                    crate::engine::write_conditional_with(primary, key, data.clone(), &absent, meta)
+                        .await
+                {
+                    warn!(key, "backfilling primary from read mirror failed: {e:#}");
+                }
+            }
+            return Ok(data);
+        }
+        Err(miss.into())
+    }
+}
+
+/// Chunks and manifests under one remote prefix.
+#[derive(Debug, Clone)]
+pub struct ChunkStore {
+    op: Operator,
+    layout: RemoteLayout,
+    mirrors: ReadMirrors,
+    cache_control: Option<String>,
+}
+
+impl ChunkStore {
+    /// A store over `op` keyed by `layout`; new chunks land at the layout's
+    /// shard depth.
+    pub fn new(op: Operator, layout: RemoteLayout) -> Self {
+        Self {
+            op,
+            layout,
+            mirrors: ReadMirrors::default(),
+            cache_control: None,
+        }
+    }
+
+    /// This store, reading chunks and manifests the primary lacks from
+    /// `mirrors`. Writes still go to the primary only.
+    pub fn with_mirrors(mut self, mirrors: ReadMirrors) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
+
+    /// This store, writing chunks with `Cache-Control: cache_control`.
+    /// Chunks never change once written, so a long-lived value is safe.
+    pub fn with_cache_control(mut self, cache_control: Option<&str>) -> Self {
+        self.cache_control = cache_control.map(str::to_string);
+        self
+    }
+
+    /// The store to read `manifest`'s chunks from, at the shard depth they
+    /// were written with.
+    pub fn for_manifest(&self, manifest: &SyncManifest) -> Self {
+        Self {
+            op: self.op.clone(),
+            layout: manifest.chunk_layout(self.layout.prefix()),
+            mirrors: self.mirrors.clone(),
+            cache_control: self.cache_control.clone(),
+        }
+    }
+
+    /// The operator chunks and manifests are read and written through.
+    pub fn operator(&self) -> &Operator {
+        &self.op
+    }
+
+    /// The layout new chunks are keyed by.
+    pub fn layout(&self) -> &RemoteLayout {
+        &self.layout
+    }
+
+    /// Whether a chunk is stored under `hash`.
+    pub async fn has_chunk(&self, hash: &str) -> Result<bool> {
+        let key = self.layout.chunk_key(hash);
+        self.op
+            .exists(&key)
+            .await
+            .with_context(|| format!("checking chunk: {key}"))
+    }
+
+    /// Whether a chunk of `len` bytes is stored under `hash`. An object of
+    /// any other length, such as one left by an interrupted upload, counts
+    /// as missing so that it gets rewritten rather than trusted.
+    pub async fn has_intact_chunk(&self, hash: &str, len: u64) -> Result<bool> {
+        let key = self.layout.chunk_key(hash);
+        match self.op.stat(&key).await {
+            Ok(meta) if meta.content_length() == len => Ok(true),
+            Ok(meta) => {
+                warn!(
+                    key,
+                    stored = meta.content_length(),
+                    expected = len,
+                    "stored chunk has the wrong length; rewriting it"
+                );
+                Ok(false)
+            }
+            Err(e) if e.kind() == opendal::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e).with_context(|| format!("checking chunk: {key}")),
+        }
+    }
+
+    /// Metadata chunks are written with.
+    pub fn chunk_meta(&self) -> ObjectMeta<'_> {
+        ObjectMeta {
+            content_type: Some(CHUNK_CONTENT_TYPE),
+            cache_control: self.cache_control.as_deref(),
+        }
+    }
+
+    /// Store `data` under `hash`, which must be the BLAKE3 of `data`.
+    ///
+    /// The object is read back and checked before this returns, and
+    /// rewritten if a torn write left it short or damaged, so dedup never
+    /// trusts a bad copy. Fails with [`ChunkHashMismatch`] if it still reads
+    /// back wrong after a few attempts.
+    pub async fn put_chunk(&self, hash: &str, data: Vec<u8>) -> Result<()> {
+        let key = self.layout.chunk_key(hash);
+        let data = opendal::Buffer::from(data);
+        // Content-addressed, so an unconditional write is always safe
+        let any = crate::engine::ObjectVersion::Unknown;
+        let mut attempt = 1;
+        loop {
+            crate::engine::write_conditional_with(
+                &self.op,
+                &key,
+                data.clone(),
+                &any,
+                self.chunk_meta(),
+            )
+            .await
+            .with_context(|| format!("uploading chunk: {key}"))?;
+            let stored = self
+                .op
+                .read(&key)
+                .await
+                .with_context(|| format!("verifying chunk: {key}"))?;
+            match check_chunk(&key, hash, &stored.to_vec()) {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < CHUNK_WRITE_ATTEMPTS => {
+                    warn!(key, attempt, "chunk read back wrong; rewriting: {e:#}");
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Fetch the chunk stored under `hash`, failing with
+    /// [`ChunkHashMismatch`] if its bytes do not hash to it.
+    pub async fn get_chunk(&self, hash: &str) -> Result<Vec<u8>> {
+        let key = self.layout.chunk_key(hash);
+        let data = self
+            .mirrors
+            .read(&self.op, &key, self.chunk_meta(), |data| {
+                check_chunk(&key, hash, data)
+            })
+            .await
+            .with_context(|| format!("downloading chunk: {key}"))?;
+        check_chunk(&key, hash, &data)?;
+        Ok(data)
+    }
+
+    /// Storage key of the manifest for content `file_hash`.
+    pub fn manifest_key(&self, file_hash: &str) -> String {
+        self.layout.manifest_key(file_hash)
+    }
+
+    /// Write `manifest` under its `file_hash`, replacing any manifest there,
+    /// and return the key. Writers racing on the same content should use
+    /// `engine::write_conditional` instead.
+    pub async fn put_manifest(&self, manifest: &SyncManifest) -> Result<String> {
+        let key = self.manifest_key(&manifest.file_hash);
+        let any = crate::engine::ObjectVersion::Unknown;
+        crate::engine::write_conditional_with(
+            &self.op,
+            &key,
+            manifest.to_bytes()?,
+            &any,
+            ObjectMeta::MANIFEST,
+        )
+        .await
+        .with_context(|| format!("uploading manifest: {key}"))?;
+        Ok(key)
+    }
+
+    /// Read and parse the manifest for content `file_hash`.
+    pub async fn get_manifest(&self, file_hash: &str) -> Result<SyncManifest> {
+        let key = self.manifest_key(file_hash);
+        let data = self
+            .mirrors
+            .read(&self.op, &key, ObjectMeta::MANIFEST, |data| {
+                SyncManifest::from_bytes(data).map(drop)
+            })
+            .await
+            .with_context(|| format!("reading manifest: {key}"))?;
+        SyncManifest::from_bytes(&data).with_context(|| format!("parsing manifest: {key}"))
+    }
+}
+
+/// Fail with [`ChunkHashMismatch`] unless `data` hashes to `hash`.
+fn check_chunk(key: &str, hash: &str, data: &[u8]) -> Result<()> {
+    let actual = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(data));
+    if actual != hash {
+        return Err(ChunkHashMismatch {
+            chunk_key: key.to_string(),
+            expected: hash.to_string(),
+            actual,
+        }
+        .into());
+    }
+    Ok(())
+}
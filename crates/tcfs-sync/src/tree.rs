@@ -0,0 +1,286 @@
+//! Tree manifests: point-in-time snapshots of a pushed tree
+//!
+//! Index entries are rewritten file by file as pushes land, so listing the
+//! index mid-push mixes old and new content. After a `push_tree` in which
+//! every file went through, the engine also writes
+//! `{prefix}/trees/{root_hash}.json` listing each file's rel_path → file_hash
+//! (plus symlinks and empty directories). The root hash is the BLAKE3 of the
+//! entry listing alone, so pushing the same content twice names the same
+//! tree. Packed files are also listed under `packed` with their pack range
+//! (see `crate::pack`). `pull_tree` restores a whole snapshot into a staging directory and
+//! renames it into place only once every file has been fetched.
+
+use anyhow::{Context, Result};
+use opendal::Operator;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use tcfs_core::index::{IndexEntry, PackRef, DIR_MARKER};
+use tcfs_core::layout::RemoteLayout;
+use tracing::warn;
+
+use crate::engine::OptionalEncryption;
+
+/// A tree manifest named by a root hash that its contents do not hash to.
+#[derive(Debug, thiserror::Error)]
+#[error("tree manifest {expected} hashes to {actual}")]
+pub struct TreeHashMismatch {
+    pub expected: String,
+    pub actual: String,
+}
+
+/// The files of one pushed tree, keyed by relative path.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeManifest {
+    /// rel_path → BLAKE3 hex hash of the file content (its manifest name)
+    pub files: BTreeMap<String, String>,
+    /// rel_path → link target
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub symlinks: BTreeMap<String, String>,
+    /// Relative paths of directories with nothing beneath them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub empty_dirs: Vec<String>,
+    /// rel_path → pack range, for the files stored in packfiles
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub packed: BTreeMap<String, PackRef>,
+    /// Device that pushed the tree
+    #[serde(default)]
+    pub written_by: String,
+    /// Unix timestamp (seconds) of the push
+    #[serde(default)]
+    pub written_at: u64,
+}
+
+impl TreeManifest {
+    /// BLAKE3 hex hash of the entries, ignoring who wrote them and when.
+    pub fn root_hash(&self) -> String {
+        let mut entries = serde_json::json!({
+            "files": self.files,
+            "symlinks": self.symlinks,
+            "empty_dirs": self.empty_dirs,
+        });
+        // Left out when empty so trees without packs keep their names
+        if !self.packed.is_empty() {
+            entries["packed"] = serde_json::json!(self.packed);
+        }
+        tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(entries.to_string().as_bytes()))
+    }
+}
+
+/// Counts from one [`pull_tree`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PullTreeStats {
+    /// Regular files restored
+    pub files: usize,
+    /// Plaintext bytes restored
+    pub bytes: u64,
+}
+
+/// Store `tree` under `prefix`, returning its root hash.
+pub async fn write_tree(op: &Operator, prefix: &str, tree: &TreeManifest) -> Result<String> {
+    let root_hash = tree.root_hash();
+    let key = RemoteLayout::new(prefix).tree_key(&root_hash);
+    let body = serde_json::to_vec_pretty(tree).context("serializing tree manifest")?;
+    op.write(&key, body)
+        .await
+        .with_context(|| format!("writing tree manifest: {key}"))?;
+    Ok(root_hash)
+}
+
+/// Build a tree manifest from the index entries currently under `prefix`.
+///
+/// Tombstones are left out; directory markers become `empty_dirs`. Entries
+/// that fail to parse are skipped with a warning.
+pub async fn tree_from_index(op: &Operator, prefix: &str) -> Result<TreeManifest> {
+    let index_dir = RemoteLayout::new(prefix).index_dir("");
+    let entries = op
+        .list_with(&index_dir)
+        .recursive(true)
+        .await
+        .with_context(|| format!("listing index: {index_dir}"))?;
+
+    let mut tree = TreeManifest::default();
+    for entry in entries.iter().filter(|e| !e.metadata().is_dir()) {
+        let key = entry.path();
+        let rel = key.trim_start_matches(&index_dir);
+        if let Some(dir) = rel.strip_suffix(&format!("/{DIR_MARKER}")) {
+            tree.empty_dirs.push(dir.to_string());
+            continue;
+        }
+        let data = op
+            .read(key)
+            .await
+            .with_context(|| format!("reading index entry: {key}"))?;
+        let index = match IndexEntry::from_bytes(&data.to_bytes()) {
+            Ok(index) => index,
+            Err(e) => {
+                warn!(key = %key, "skipping unreadable index entry: {e}");
+                continue;
+            }
+        };
+        if index.is_tombstone() {
+            continue;
+        }
+        if let Some(pack) = index.pack {
+            tree.packed.insert(rel.to_string(), pack);
+        }
+        match index.symlink {
+            Some(target) => tree.symlinks.insert(rel.to_string(), target),
+            None => tree.files.insert(rel.to_string(), index.manifest_hash),
+        };
+    }
+    tree.empty_dirs.sort();
+    Ok(tree)
+}
+
+/// Read the tree named `root_hash`, checking that its entries hash to it.
+pub async fn read_tree(op: &Operator, prefix: &str, root_hash: &str) -> Result<TreeManifest> {
+    let key = RemoteLayout::new(prefix).tree_key(root_hash);
+    let data = op
+        .read(&key)
+        .await
+        .with_context(|| format!("reading tree manifest: {key}"))?;
+    let tree: TreeManifest = serde_json::from_slice(&data.to_bytes())
+        .with_context(|| format!("parsing tree manifest: {key}"))?;
+    let actual = tree.root_hash();
+    if actual != root_hash {
+        return Err(TreeHashMismatch {
+            expected: root_hash.to_string(),
+            actual,
+        }
+        .into());
+    }
+    Ok(tree)
+}
+
+/// Restore the tree named `root_hash` to `dest`, which must not exist or be
+/// an empty directory.
+///
+/// Everything is written to a staging directory beside `dest` first; it is
+/// renamed into place only after every file has been downloaded and
+/// verified, so a failed pull leaves `dest` as it was. On a
+/// case-insensitive filesystem, paths differing only in case are restored
+/// under distinct names as `engine::pull_tree_with_case` does.
+pub async fn pull_tree(
+    op: &Operator,
+    prefix: &str,
+    root_hash: &str,
+    dest: &Path,
+    encryption: OptionalEncryption<'_>,
+) -> Result<PullTreeStats> {
+    let tree = read_tree(op, prefix, root_hash).await?;
+    if dest.exists() {
+        let mut entries = std::fs::read_dir(dest)
+            .with_context(|| format!("reading destination: {}", dest.display()))?;
+        anyhow::ensure!(
+            entries.next().is_none(),
+            "destination is not empty: {}",
+            dest.display()
+        );
+    }
+
+    let staging = crate::engine::download_tmp_path(dest);
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)
+            .with_context(|| format!("removing stale staging dir: {}", staging.display()))?;
+    }
+    std::fs::create_dir_all(&staging)
+        .with_context(|| format!("creating staging dir: {}", staging.display()))?;
+
+    match fill_tree(op, prefix, &tree, &staging, encryption).await {
+        Ok(stats) => {
+            if dest.exists() {
+                std::fs::remove_dir(dest)
+                    .with_context(|| format!("replacing destination: {}", dest.display()))?;
+            }
+            std::fs::rename(&staging, dest).with_context(|| {
+                format!(
+                    "moving {} into place at {}",
+                    staging.display(),
+                    dest.display()
+                )
+            })?;
+            Ok(stats)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&staging);
+            Err(e)
+        }
+    }
+}
+
+async fn fill_tree(
+    op: &Operator,
+    prefix: &str,
+    tree: &TreeManifest,
+    root: &Path,
+    encryption: OptionalEncryption<'_>,
+) -> Result<PullTreeStats> {
+    let layout = RemoteLayout::new(prefix);
+    let mut stats = PullTreeStats::default();
+
+    // Files and symlinks differing only in case would overwrite each other
+    let renames = if crate::engine::folds_case(root).await? {
+        let rels: Vec<&str> = tree
+            .files
+            .keys()
+            .chain(tree.symlinks.keys())
+            .map(String::as_str)
+            .collect();
+        tcfs_core::paths::case_insensitive_renames(&rels)
+    } else {
+        std::collections::HashMap::new()
+    };
+    for (rel, renamed) in &renames {
+        warn!(rel = %rel, local = %renamed, "path differs only in case from another; restoring under a new name");
+    }
+    let local_path = |rel: &str| -> Result<std::path::PathBuf> {
+        let local_rel = renames.get(rel).map_or(rel, String::as_str);
+        let local_rel = tcfs_core::paths::normalize_rel_path(local_rel)?;
+        tcfs_core::paths::check_no_symlink_ancestors(root, &local_rel)?;
+        Ok(root.join(local_rel))
+    };
+
+    for dir in &tree.empty_dirs {
+        let local = root.join(tcfs_core::paths::normalize_rel_path(dir)?);
+        std::fs::create_dir_all(&local)
+            .with_context(|| format!("creating dir: {}", local.display()))?;
+    }
+
+    for (rel, file_hash) in &tree.files {
+        let local = local_path(rel)?;
+        if let Some(pack) = tree.packed.get(rel) {
+            let data = crate::pack::read_packed(op, prefix, rel, file_hash, pack).await?;
+            crate::engine::write_file_atomic(&local, &data, None, 0).await?;
+            stats.files += 1;
+            stats.bytes += data.len() as u64;
+            continue;
+        }
+        let result = crate::engine::download_file_with_device(
+            op,
+            &layout.manifest_key(file_hash),
+            &local,
+            prefix,
+            None,
+            "",
+            None,
+            encryption,
+            crate::engine::RestoreMode::manifest(0),
+        )
+        .await
+        .with_context(|| format!("pulling {rel}"))?;
+        stats.files += 1;
+        stats.bytes += result.bytes;
+    }
+
+    for (rel, target) in &tree.symlinks {
+        let local = local_path(rel)?;
+        if let Some(parent) = local.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("creating dir: {}", parent.display()))?;
+        }
+        crate::engine::restore_symlink(rel, target, &local).await?;
+    }
+
+    Ok(stats)
+}
@@ -1 +1,214 @@
-//! tcfs-sync::watcher - stub (Phase 1 implementation pending)
+//! Filesystem watch event filtering.
+//!
+//! Raw notify events cover every path under a watched tree, including
+//! editor swap files and `.git/` churn. `WatchFilter` drops the paths a push
+//! would never upload (the same exclude patterns, `.tcfsignore` and skipped
+//! directories as `collect_tree`), plus common editor scratch files.
+//! `Debouncer` then coalesces bursts, so saving a file once yields one
+//! event rather than one per write call.
+
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::engine::{read_ignore_file, CollectConfig};
+
+/// Editor temp files ignored in watch events even though a push would
+/// upload them: vim swap files and its `4913` write probe, Office lock
+/// files, and emacs backup/lock files.
+pub const EDITOR_TEMP_PATTERNS: &[&str] = &["*.swp", "*.swo", "*.swx", "4913", "~$*", "*~", ".#*"];
+
+/// Decides which event paths under one watched root are reported.
+#[derive(Debug, Clone)]
+pub struct WatchFilter {
+    root: PathBuf,
+    config: CollectConfig,
+    patterns: Vec<String>,
+    matchers: Vec<glob::Pattern>,
+}
+
+impl WatchFilter {
+    /// Filter for `root`: `config.exclude_patterns`, the root's
+    /// `.tcfsignore` and [`EDITOR_TEMP_PATTERNS`].
+    pub fn new(root: &Path, config: &CollectConfig) -> Self {
+        let mut patterns = config.exclude_patterns.clone();
+        patterns.extend(read_ignore_file(root));
+        patterns.extend(EDITOR_TEMP_PATTERNS.iter().map(|p| p.to_string()));
+        let mut filter = WatchFilter {
+            // notify reports canonical paths, so compare against one
+            root: root.canonicalize().unwrap_or_else(|_| root.to_path_buf()),
+            config: config.clone(),
+            patterns: Vec::new(),
+            matchers: Vec::new(),
+        };
+        filter.add_patterns(&patterns);
+        filter
+    }
+
+    /// Adds more name globs, e.g. ones supplied by a watch client.
+    pub fn with_patterns(mut self, patterns: &[String]) -> Self {
+        self.add_patterns(patterns);
+        self
+    }
+
+    /// Every glob in effect, in the order they were added.
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// Whether an event for `path` should be dropped.
+    ///
+    /// Paths outside the root are never ignored by this filter.
+    pub fn ignores(&self, path: &Path) -> bool {
+        let Ok(rel) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        let names: Vec<&str> = rel.iter().filter_map(|c| c.to_str()).collect();
+        names.iter().enumerate().any(|(i, name)| {
+            let is_dir = i + 1 < names.len() || path.is_dir();
+            self.matchers.iter().any(|m| m.matches(name)) || (is_dir && self.config.skips_dir(name))
+        })
+    }
+
+    fn add_patterns(&mut self, patterns: &[String]) {
+        for p in patterns {
+            if let Ok(m) = glob::Pattern::new(p) {
+                self.patterns.push(p.clone());
+                self.matchers.push(m);
+            }
+        }
+    }
+}
+
+/// Window used when a watch client does not ask for one.
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Collects events for a fixed window and releases them deduplicated.
+///
+/// The window opens with the first pending event; everything pushed before
+/// [`deadline`](Self::deadline) is flushed together, each distinct key once
+/// and in first-seen order.
+#[derive(Debug)]
+pub struct Debouncer<K> {
+    window: Duration,
+    pending: Vec<K>,
+    seen: HashSet<K>,
+    deadline: Option<Instant>,
+}
+
+impl<K: Clone + Eq + Hash> Debouncer<K> {
+    /// Debouncer flushing `window` after the first event of each burst.
+    pub fn new(window: Duration) -> Self {
+        Debouncer {
+            window,
+            pending: Vec::new(),
+            seen: HashSet::new(),
+            deadline: None,
+        }
+    }
+
+    /// Queues `key` unless an equal key is already pending.
+    pub fn push(&mut self, key: K) {
+        self.push_at(key, Instant::now());
+    }
+
+    /// [`push`](Self::push) with an explicit clock, for callers and tests
+    /// that track time themselves.
+    pub fn push_at(&mut self, key: K, now: Instant) {
+        if self.deadline.is_none() {
+            self.deadline = Some(now + self.window);
+        }
+        if self.seen.insert(key.clone()) {
+            self.pending.push(key);
+        }
+    }
+
+    /// When the pending events are due, or `None` if nothing is pending.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Whether the current window has closed at `now`.
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.deadline.is_some_and(|d| now >= d)
+    }
+
+    /// Takes every pending key and starts a fresh window.
+    pub fn flush(&mut self) -> Vec<K> {
+        self.seen.clear();
+        self.deadline = None;
+        std::mem::take(&mut self.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_editor_temp_files_and_skipped_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::write(root.join(".tcfsignore"), "# build output\n*.tmp\n").unwrap();
+        let config = CollectConfig {
+            exclude_patterns: vec!["*.log".into()],
+            ..Default::default()
+        };
+        let filter = WatchFilter::new(&root, &config).with_patterns(&["scratch*".into()]);
+
+        for ignored in [
+            "notes.txt.swp",
+            "4913",
+            "~$report.docx",
+            "out.tmp",
+            "debug.log",
+            "scratch.md",
+            ".git/index",
+            "node_modules/pkg/index.js",
+            ".cache/blob",
+        ] {
+            assert!(filter.ignores(&root.join(ignored)), "{ignored}");
+        }
+        for kept in ["notes.txt", "src/main.rs", ".tcfsignore", "docs/4913.md"] {
+            assert!(!filter.ignores(&root.join(kept)), "{kept}");
+        }
+        assert!(!filter.ignores(Path::new("/elsewhere/a.swp")));
+        assert!(filter.patterns().iter().any(|p| p == "*.tmp"));
+    }
+
+    #[test]
+    fn debouncer_coalesces_a_burst_per_key() {
+        let mut debouncer = Debouncer::new(Duration::from_millis(100));
+        let start = Instant::now();
+        assert!(debouncer.deadline().is_none());
+
+        for i in 0..10 {
+            debouncer.push_at(("a.txt", "modified"), start + Duration::from_millis(i));
+        }
+        debouncer.push_at(("b.txt", "created"), start + Duration::from_millis(20));
+        debouncer.push_at(("a.txt", "deleted"), start + Duration::from_millis(30));
+
+        assert!(!debouncer.is_due(start + Duration::from_millis(99)));
+        assert!(debouncer.is_due(start + Duration::from_millis(100)));
+        assert_eq!(
+            debouncer.flush(),
+            vec![
+                ("a.txt", "modified"),
+                ("b.txt", "created"),
+                ("a.txt", "deleted")
+            ]
+        );
+        assert!(debouncer.deadline().is_none());
+        assert!(debouncer.flush().is_empty());
+
+        // A later write opens a new window and is reported again
+        let later = start + Duration::from_secs(1);
+        debouncer.push_at(("a.txt", "modified"), later);
+        assert_eq!(
+            debouncer.deadline(),
+            Some(later + Duration::from_millis(100))
+        );
+        assert_eq!(debouncer.flush(), vec![("a.txt", "modified")]);
+    }
+}
@@ -0,0 +1,163 @@
+//! Integration test: adaptive chunk-transfer concurrency
+//!
+//! A latency layer stands in for the network. It starts out as a saturated
+//! 1 MB/s link that sends one read at a time, so extra concurrent reads only
+//! queue, then turns into a fast but high-latency WAN link mid-transfer,
+//! where reads do not wait on each other. Pulling through the controller
+//! must stay near one read at a time on the first link and climb to the
+//! configured maximum on the second. Time is paused, so the sleeps cost
+//! nothing.
+
+mod common;
+
+use common::noise;
+use opendal::raw::*;
+use opendal::Operator;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tcfs_sync::state::StateCache;
+use tempfile::TempDir;
+use tokio::time::Instant;
+
+const PREFIX: &str = "test/adaptive";
+const MAX_IN_FLIGHT: usize = 8;
+
+/// Chunk reads before the link switches from saturated to WAN.
+const SWITCH_AFTER: usize = 60;
+
+#[derive(Debug, Clone, Default)]
+struct Link {
+    reads: Arc<AtomicUsize>,
+    in_flight: Arc<AtomicUsize>,
+    /// When the saturated link finishes sending what is already queued
+    busy_until: Arc<Mutex<Option<Instant>>>,
+    /// Highest in-flight count seen on the saturated link
+    peak_saturated: Arc<AtomicUsize>,
+    /// Highest in-flight count seen on the WAN link
+    peak_wan: Arc<AtomicUsize>,
+}
+
+impl Link {
+    async fn delay(&self, bytes: u64) {
+        let n = self.reads.fetch_add(1, Ordering::SeqCst);
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        let latency = if n < SWITCH_AFTER {
+            self.peak_saturated.fetch_max(in_flight, Ordering::SeqCst);
+            let now = Instant::now();
+            let mut busy = self.busy_until.lock().unwrap();
+            let start = busy.filter(|t| *t > now).unwrap_or(now);
+            let done = start + Duration::from_micros(bytes);
+            *busy = Some(done);
+            done - now
+        } else {
+            self.peak_wan.fetch_max(in_flight, Ordering::SeqCst);
+            Duration::from_millis(40) + Duration::from_micros(bytes / 100)
+        };
+        tokio::time::sleep(latency).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl<A: Access> Layer<A> for Link {
+    type LayeredAccess = LinkAccessor<A>;
+
+    fn layer(&self, inner: A) -> Self::LayeredAccess {
+        LinkAccessor {
+            inner,
+            link: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct LinkAccessor<A> {
+    inner: A,
+    link: Link,
+}
+
+impl<A: Access> LayeredAccess for LinkAccessor<A> {
+    type Inner = A;
+    type Reader = A::Reader;
+    type Writer = A::Writer;
+    type Lister = A::Lister;
+    type Deleter = A::Deleter;
+
+    fn inner(&self) -> &Self::Inner {
+        &self.inner
+    }
+
+    async fn read(&self, path: &str, args: OpRead) -> opendal::Result<(RpRead, Self::Reader)> {
+        if path.contains("/chunks/") {
+            let size = self.inner.stat(path, OpStat::new()).await?;
+            self.link.delay(size.into_metadata().content_length()).await;
+        }
+        self.inner.read(path, args).await
+    }
+
+    async fn write(&self, path: &str, args: OpWrite) -> opendal::Result<(RpWrite, Self::Writer)> {
+        self.inner.write(path, args).await
+    }
+
+    async fn delete(&self) -> opendal::Result<(RpDelete, Self::Deleter)> {
+        self.inner.delete().await
+    }
+
+    async fn list(&self, path: &str, args: OpList) -> opendal::Result<(RpList, Self::Lister)> {
+        self.inner.list(path, args).await
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn controller_follows_a_link_that_changes_mid_transfer() {
+    let tmp = TempDir::new().unwrap();
+    let memory = Operator::new(opendal::services::Memory::default())
+        .unwrap()
+        .finish();
+
+    let content = noise(0x9e37_79b9_7f4a_7c15, 2 * 1024 * 1024);
+    let src = tmp.path().join("data.dat");
+    std::fs::write(&src, &content).unwrap();
+    let pusher = StateCache::open(&tmp.path().join("push.db")).unwrap();
+    let upload = tcfs_sync::engine::upload_file(&memory, &src, PREFIX, &pusher, None)
+        .await
+        .unwrap();
+    assert!(
+        upload.chunks > 3 * SWITCH_AFTER,
+        "only {} chunks",
+        upload.chunks
+    );
+
+    let link = Link::default();
+    let op = memory.layer(link.clone());
+    let mut state = StateCache::open(&tmp.path().join("pull.db")).unwrap();
+    state.set_transfer_concurrency(1, MAX_IN_FLIGHT);
+
+    let dst = tmp.path().join("pulled.dat");
+    tcfs_sync::engine::download_file_with_device(
+        &op,
+        &upload.remote_path,
+        &dst,
+        PREFIX,
+        None,
+        "dev-b",
+        Some(&state),
+        None,
+        tcfs_sync::engine::RestoreMode::manifest(0),
+    )
+    .await
+    .unwrap();
+    assert_eq!(std::fs::read(&dst).unwrap(), content);
+
+    let peak_saturated = link.peak_saturated.load(Ordering::SeqCst);
+    let peak_wan = link.peak_wan.load(Ordering::SeqCst);
+    assert!(
+        peak_saturated <= 2,
+        "saturated link ran {peak_saturated} reads at once"
+    );
+    assert_eq!(
+        peak_wan, MAX_IN_FLIGHT,
+        "WAN link never reached the maximum"
+    );
+    assert_eq!(state.transfer_concurrency().limit(), MAX_IN_FLIGHT);
+}
@@ -0,0 +1,153 @@
+//! Integration test: tar export and import of a prefix
+//!
+//! Pushes a small tree, exports the prefix to a tar stream, imports the
+//! stream into a second prefix and checks both index the same content,
+//! symlinks, empty directories and modes. Also checks that an import skips
+//! symlinks pointing outside the prefix.
+
+use opendal::Operator;
+use tcfs_core::index::IndexEntry;
+use tcfs_core::layout::RemoteLayout;
+use tcfs_sync::archive;
+use tempfile::TempDir;
+
+fn memory_operator() -> Operator {
+    Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish()
+}
+
+async fn index_entry(op: &Operator, prefix: &str, rel: &str) -> IndexEntry {
+    let data = op
+        .read(&RemoteLayout::new(prefix).index_key(rel))
+        .await
+        .unwrap();
+    IndexEntry::from_bytes(&data.to_bytes()).unwrap()
+}
+
+#[tokio::test]
+async fn export_then_import_into_new_prefix() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(src.join("notes/deep")).unwrap();
+    std::fs::create_dir_all(src.join("empty")).unwrap();
+    std::fs::write(src.join("notes/plan.md"), b"plan v1\n").unwrap();
+    std::fs::write(src.join("notes/deep/big.dat"), vec![7u8; 100_000]).unwrap();
+    std::fs::write(src.join("run.sh"), b"#!/bin/sh\necho hi\n").unwrap();
+    // Too long for the header's name field
+    let long_dir = src.join("d".repeat(120));
+    std::fs::create_dir_all(&long_dir).unwrap();
+    std::fs::write(long_dir.join("f".repeat(90)), b"long").unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(src.join("run.sh"), std::fs::Permissions::from_mode(0o755))
+            .unwrap();
+        std::os::unix::fs::symlink("notes/plan.md", src.join("plan-link")).unwrap();
+    }
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    tcfs_sync::engine::push_tree(&op, &src, "test/export", &state, None)
+        .await
+        .unwrap();
+
+    let mut tar = Vec::new();
+    let exported = archive::export_prefix(&op, "test/export", &mut tar, None)
+        .await
+        .unwrap();
+    assert_eq!(exported.files, 4);
+    assert_eq!(exported.dirs, 1);
+    assert_eq!(exported.bytes, 8 + 100_000 + 18 + 4);
+    assert_eq!(tar.len() % 512, 0);
+
+    let import_state = tcfs_sync::state::StateCache::open(&tmp.path().join("import.db")).unwrap();
+    let imported = archive::import_archive(
+        &op,
+        &mut tar.as_slice(),
+        "test/import",
+        &import_state,
+        "dev-b",
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(imported, exported);
+
+    let before = tcfs_sync::tree::tree_from_index(&op, "test/export")
+        .await
+        .unwrap();
+    let after = tcfs_sync::tree::tree_from_index(&op, "test/import")
+        .await
+        .unwrap();
+    assert_eq!(after.files, before.files);
+    assert_eq!(after.symlinks, before.symlinks);
+    assert_eq!(after.empty_dirs, vec!["empty".to_string()]);
+    assert_eq!(
+        index_entry(&op, "test/import", "run.sh").await.mode,
+        index_entry(&op, "test/export", "run.sh").await.mode
+    );
+    #[cfg(unix)]
+    assert_eq!(
+        index_entry(&op, "test/import", "run.sh").await.mode,
+        Some(0o755)
+    );
+
+    // The imported prefix pulls back to the original bytes
+    let dest = tmp.path().join("pulled");
+    let root = tcfs_sync::tree::write_tree(&op, "test/import", &after)
+        .await
+        .unwrap();
+    tcfs_sync::tree::pull_tree(&op, "test/import", &root, &dest, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        std::fs::read(dest.join("notes/deep/big.dat")).unwrap(),
+        vec![7u8; 100_000]
+    );
+    assert_eq!(
+        std::fs::read(dest.join("notes/plan.md")).unwrap(),
+        b"plan v1\n"
+    );
+    // Scratch files leave nothing behind in the state cache
+    assert_eq!(import_state.content_totals().logical_bytes, 0);
+}
+
+#[tokio::test]
+async fn import_skips_symlinks_that_escape_the_prefix() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+
+    let mut builder = tar::Builder::new(Vec::new());
+    for (path, target) in [
+        ("docs/inside", "../notes.txt"),
+        ("docs/up", "../../etc/passwd"),
+        ("abs", "/etc/shadow"),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        builder.append_link(&mut header, path, target).unwrap();
+    }
+    let tar = builder.into_inner().unwrap();
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let imported = archive::import_archive(
+        &op,
+        &mut tar.as_slice(),
+        "test/links",
+        &state,
+        "dev-a",
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(imported.symlinks, 1);
+    assert_eq!(
+        index_entry(&op, "test/links", "docs/inside").await.symlink,
+        Some("../notes.txt".to_string())
+    );
+    let layout = RemoteLayout::new("test/links");
+    assert!(!op.exists(&layout.index_key("docs/up")).await.unwrap());
+    assert!(!op.exists(&layout.index_key("abs")).await.unwrap());
+}
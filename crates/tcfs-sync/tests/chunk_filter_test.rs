@@ -0,0 +1,179 @@
+//! Integration test: chunk filter skips existence checks for unknown chunks
+//!
+//! A counting layer records `stat` (what `exists` issues) and `write` calls on
+//! chunk keys. With the filter enabled, chunks it has never seen are written
+//! without a `stat`, while chunks it knows are verified and not re-uploaded.
+
+use opendal::raw::*;
+use opendal::Operator;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tcfs_sync::state::StateCache;
+use tempfile::TempDir;
+
+#[derive(Debug, Clone, Default)]
+struct CountLayer {
+    chunk_stats: Arc<AtomicUsize>,
+    chunk_writes: Arc<AtomicUsize>,
+}
+
+impl CountLayer {
+    fn take(&self) -> (usize, usize) {
+        (
+            self.chunk_stats.swap(0, Ordering::SeqCst),
+            self.chunk_writes.swap(0, Ordering::SeqCst),
+        )
+    }
+}
+
+impl<A: Access> Layer<A> for CountLayer {
+    type LayeredAccess = CountAccessor<A>;
+
+    fn layer(&self, inner: A) -> Self::LayeredAccess {
+        CountAccessor {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CountAccessor<A> {
+    inner: A,
+    layer: CountLayer,
+}
+
+impl<A: Access> LayeredAccess for CountAccessor<A> {
+    type Inner = A;
+    type Reader = A::Reader;
+    type Writer = A::Writer;
+    type Lister = A::Lister;
+    type Deleter = A::Deleter;
+
+    fn inner(&self) -> &Self::Inner {
+        &self.inner
+    }
+
+    async fn read(&self, path: &str, args: OpRead) -> opendal::Result<(RpRead, Self::Reader)> {
+        self.inner.read(path, args).await
+    }
+
+    async fn write(&self, path: &str, args: OpWrite) -> opendal::Result<(RpWrite, Self::Writer)> {
+        if path.contains("/chunks/") {
+            self.layer.chunk_writes.fetch_add(1, Ordering::SeqCst);
+        }
+        self.inner.write(path, args).await
+    }
+
+    async fn stat(&self, path: &str, args: OpStat) -> opendal::Result<RpStat> {
+        if path.contains("/chunks/") {
+            self.layer.chunk_stats.fetch_add(1, Ordering::SeqCst);
+        }
+        self.inner.stat(path, args).await
+    }
+
+    async fn delete(&self) -> opendal::Result<(RpDelete, Self::Deleter)> {
+        self.inner.delete().await
+    }
+
+    async fn list(&self, path: &str, args: OpList) -> opendal::Result<(RpList, Self::Lister)> {
+        self.inner.list(path, args).await
+    }
+}
+
+/// Deterministic pseudo-random bytes, so FastCDC finds several chunks.
+fn noise(seed: u64, len: usize) -> Vec<u8> {
+    let mut x = seed;
+    (0..len)
+        .map(|_| {
+            x = x
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (x >> 33) as u8
+        })
+        .collect()
+}
+
+async fn push(
+    op: &Operator,
+    state: &mut StateCache,
+    path: &Path,
+) -> tcfs_sync::engine::UploadResult {
+    tcfs_sync::engine::upload_file(op, path, "test/filter", state, None)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn known_chunks_are_verified_and_unknown_chunks_skip_exists() {
+    let tmp = TempDir::new().unwrap();
+    let counts = CountLayer::default();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .layer(counts.clone())
+        .finish();
+    let state_path = tmp.path().join("state.db");
+
+    let base = noise(7, 64 * 1024);
+    let first = tmp.path().join("first.txt");
+    std::fs::write(&first, &base).unwrap();
+
+    let mut state = StateCache::open(&state_path).unwrap();
+    state.enable_chunk_filter(0.01);
+
+    // Nothing is known yet: every chunk is written without an exists check
+    let result = push(&op, &mut state, &first).await;
+    assert!(result.chunks > 2, "expected several chunks");
+    assert_eq!(counts.take(), (0, result.new_chunks));
+    assert_eq!(result.new_chunks, result.chunks);
+
+    // Same prefix chunks plus a new tail: shared chunks are verified (one stat
+    // each) and not rewritten; only the new chunks are written, unchecked
+    let mut extended = base.clone();
+    extended.extend(noise(8, 16 * 1024));
+    let second = tmp.path().join("second.txt");
+    std::fs::write(&second, &extended).unwrap();
+
+    let result = push(&op, &mut state, &second).await;
+    let (stats, writes) = counts.take();
+    assert!(result.new_chunks > 0 && result.new_chunks < result.chunks);
+    assert_eq!(writes, result.new_chunks);
+    assert_eq!(stats, result.chunks - result.new_chunks);
+    state.flush().unwrap();
+    drop(state);
+
+    // Without the sidecar, the filter is rebuilt from tracked manifests
+    std::fs::remove_file(tmp.path().join("state.db.chunks")).unwrap();
+    let mut state = StateCache::open(&state_path).unwrap();
+    state.enable_chunk_filter(0.01);
+
+    let mut other_tail = base;
+    other_tail.extend(noise(9, 16 * 1024));
+    let third = tmp.path().join("third.txt");
+    std::fs::write(&third, &other_tail).unwrap();
+
+    let result = push(&op, &mut state, &third).await;
+    let (stats, writes) = counts.take();
+    assert_eq!(writes, result.new_chunks);
+    assert_eq!(stats, result.chunks - result.new_chunks);
+    assert!(stats > 0, "rebuilt filter should know the shared chunks");
+}
+
+#[tokio::test]
+async fn disabled_filter_checks_every_chunk() {
+    let tmp = TempDir::new().unwrap();
+    let counts = CountLayer::default();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .layer(counts.clone())
+        .finish();
+
+    let file = tmp.path().join("data.txt");
+    std::fs::write(&file, noise(3, 32 * 1024)).unwrap();
+
+    let mut state = StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let result = push(&op, &mut state, &file).await;
+    assert_eq!(counts.take(), (result.chunks, result.chunks));
+    assert!(!tmp.path().join("state.db.chunks").exists());
+}
@@ -0,0 +1,15 @@
+//! Helpers shared by the integration tests
+
+/// `len` deterministic bytes from an xorshift stream seeded with `seed`:
+/// they neither compress nor repeat, so FastCDC finds distinct chunks.
+pub fn noise(seed: u64, len: usize) -> Vec<u8> {
+    let mut x = seed;
+    (0..len)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x as u8
+        })
+        .collect()
+}
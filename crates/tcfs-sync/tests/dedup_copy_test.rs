@@ -0,0 +1,186 @@
+//! Integration test: identical content under two paths is stored once
+//!
+//! A counting layer records `write` calls on chunk and manifest keys. A
+//! second file with the same bytes as one already pushed, whether in the
+//! same tree push or a later single-file push, must only gain an index entry
+//! pointing at the existing manifest.
+
+mod common;
+
+use common::noise;
+use opendal::raw::*;
+use opendal::Operator;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tcfs_core::index::IndexEntry;
+use tcfs_core::layout::RemoteLayout;
+use tcfs_sync::manifest::SyncManifest;
+use tcfs_sync::state::StateCache;
+use tempfile::TempDir;
+
+const PREFIX: &str = "test/dedup";
+
+#[derive(Debug, Clone, Default)]
+struct Writes {
+    chunks: Arc<AtomicUsize>,
+    manifests: Arc<AtomicUsize>,
+}
+
+impl Writes {
+    /// `(chunk writes, manifest writes)` since the last call.
+    fn take(&self) -> (usize, usize) {
+        (
+            self.chunks.swap(0, Ordering::SeqCst),
+            self.manifests.swap(0, Ordering::SeqCst),
+        )
+    }
+}
+
+impl<A: Access> Layer<A> for Writes {
+    type LayeredAccess = WritesAccessor<A>;
+
+    fn layer(&self, inner: A) -> Self::LayeredAccess {
+        WritesAccessor {
+            inner,
+            writes: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct WritesAccessor<A> {
+    inner: A,
+    writes: Writes,
+}
+
+impl<A: Access> LayeredAccess for WritesAccessor<A> {
+    type Inner = A;
+    type Reader = A::Reader;
+    type Writer = A::Writer;
+    type Lister = A::Lister;
+    type Deleter = A::Deleter;
+
+    fn inner(&self) -> &Self::Inner {
+        &self.inner
+    }
+
+    async fn read(&self, path: &str, args: OpRead) -> opendal::Result<(RpRead, Self::Reader)> {
+        self.inner.read(path, args).await
+    }
+
+    async fn write(&self, path: &str, args: OpWrite) -> opendal::Result<(RpWrite, Self::Writer)> {
+        if path.contains("/chunks/") {
+            self.writes.chunks.fetch_add(1, Ordering::SeqCst);
+        } else if path.contains("/manifests/") {
+            self.writes.manifests.fetch_add(1, Ordering::SeqCst);
+        }
+        self.inner.write(path, args).await
+    }
+
+    async fn delete(&self) -> opendal::Result<(RpDelete, Self::Deleter)> {
+        self.inner.delete().await
+    }
+
+    async fn list(&self, path: &str, args: OpList) -> opendal::Result<(RpList, Self::Lister)> {
+        self.inner.list(path, args).await
+    }
+}
+
+fn counting_operator() -> (Operator, Writes) {
+    let writes = Writes::default();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .layer(writes.clone())
+        .finish();
+    (op, writes)
+}
+
+async fn read_index(op: &Operator, rel: &str) -> IndexEntry {
+    let key = RemoteLayout::new(PREFIX).index_key(rel);
+    IndexEntry::from_bytes(&op.read(&key).await.unwrap().to_bytes()).unwrap()
+}
+
+async fn manifest_bytes(op: &Operator, hash: &str) -> Vec<u8> {
+    let key = RemoteLayout::new(PREFIX).manifest_key(hash);
+    op.read(&key).await.unwrap().to_vec()
+}
+
+#[tokio::test]
+async fn identical_files_in_one_push_share_manifest_and_chunks() {
+    let tmp = TempDir::new().unwrap();
+    let (op, writes) = counting_operator();
+    let root = tmp.path().join("tree");
+    std::fs::create_dir_all(root.join("sub")).unwrap();
+    let data = noise(21, 256 * 1024);
+    std::fs::write(root.join("a.bin"), &data).unwrap();
+    std::fs::write(root.join("sub/b.bin"), &data).unwrap();
+    let state = StateCache::open(&tmp.path().join("state.db")).unwrap();
+
+    tcfs_sync::engine::push_tree_with_stats(
+        &op, &root, PREFIX, &state, None, "dev1", None, None, 1, None,
+    )
+    .await
+    .unwrap();
+
+    let a = read_index(&op, "a.bin").await;
+    let b = read_index(&op, "sub/b.bin").await;
+    assert_eq!(a.manifest_hash, b.manifest_hash);
+    let manifest = SyncManifest::from_bytes(&manifest_bytes(&op, &a.manifest_hash).await).unwrap();
+    let (chunks, manifests) = writes.take();
+    assert_eq!(manifests, 1, "one manifest for both paths");
+    assert_eq!(chunks, manifest.chunks.len(), "each chunk written once");
+    assert!(chunks > 1, "expected several chunks");
+}
+
+#[tokio::test]
+async fn copy_pushed_later_writes_only_an_index_entry() {
+    let tmp = TempDir::new().unwrap();
+    let (op, writes) = counting_operator();
+    let root = tmp.path().join("tree");
+    std::fs::create_dir_all(&root).unwrap();
+    let a = root.join("a.bin");
+    std::fs::write(&a, noise(22, 256 * 1024)).unwrap();
+    let state = StateCache::open(&tmp.path().join("state.db")).unwrap();
+
+    let upload = |path: &Path, rel: &'static str| {
+        let (op, state, path) = (&op, &state, path.to_path_buf());
+        async move {
+            tcfs_sync::engine::upload_file_with_device(
+                op,
+                &path,
+                PREFIX,
+                state,
+                None,
+                "dev1",
+                Some(rel),
+                None,
+                false,
+            )
+            .await
+            .unwrap()
+        }
+    };
+
+    let first = upload(&a, "a.bin").await;
+    let (chunks, manifests) = writes.take();
+    assert_eq!((chunks, manifests), (first.chunks, 1));
+    let before = manifest_bytes(&op, &first.hash).await;
+
+    let b = root.join("b.bin");
+    std::fs::copy(&a, &b).unwrap();
+    let second = upload(&b, "b.bin").await;
+    assert_eq!(second.hash, first.hash);
+    assert_eq!(second.new_chunks, 0);
+    assert_eq!(
+        writes.take(),
+        (0, 0),
+        "copy must write no chunk or manifest"
+    );
+    assert_eq!(manifest_bytes(&op, &first.hash).await, before);
+    assert_eq!(read_index(&op, "b.bin").await.manifest_hash, first.hash);
+
+    // Both paths stay tracked
+    assert_eq!(state.get(&a).unwrap().blake3, first.hash);
+    assert_eq!(state.get(&b).unwrap().blake3, first.hash);
+}
@@ -6,6 +6,9 @@
 
 #![cfg(feature = "crypto")]
 
+mod common;
+
+use common::noise;
 use opendal::Operator;
 use std::path::Path;
 use tempfile::TempDir;
@@ -24,7 +27,10 @@ fn write_test_file(dir: &Path, name: &str, content: &[u8]) -> std::path::PathBuf
 
 fn test_encryption_context() -> tcfs_sync::engine::EncryptionContext {
     let master_key = tcfs_crypto::MasterKey::from_bytes([42u8; 32]);
-    tcfs_sync::engine::EncryptionContext { master_key }
+    tcfs_sync::engine::EncryptionContext {
+        master_key,
+        convergent: false,
+    }
 }
 
 #[tokio::test]
@@ -39,18 +45,19 @@ async fn encrypted_upload_download_roundtrip() {
     let src = write_test_file(tmp.path(), "secret.txt", original);
     let dst = tmp.path().join("output/secret.txt");
 
-    let mut state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
 
     // Push with encryption
     let upload = tcfs_sync::engine::upload_file_with_device(
         &op,
         &src,
         prefix,
-        &mut state,
+        &state,
         None,
         "test-device",
         None,
         Some(&ctx),
+        false,
     )
     .await
     .expect("encrypted upload should succeed");
@@ -69,6 +76,7 @@ async fn encrypted_upload_download_roundtrip() {
         "test-device",
         None,
         Some(&ctx),
+        tcfs_sync::engine::RestoreMode::default(),
     )
     .await
     .expect("encrypted download should succeed");
@@ -90,17 +98,18 @@ async fn encrypted_manifest_has_wrapped_key() {
     let content = b"test file for manifest verification";
     let src = write_test_file(tmp.path(), "test.txt", content);
 
-    let mut state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
 
     let upload = tcfs_sync::engine::upload_file_with_device(
         &op,
         &src,
         prefix,
-        &mut state,
+        &state,
         None,
         "dev1",
         None,
         Some(&ctx),
+        false,
     )
     .await
     .expect("upload should succeed");
@@ -121,6 +130,36 @@ async fn encrypted_manifest_has_wrapped_key() {
     assert!(decoded.is_ok(), "encrypted_file_key should be valid base64");
 }
 
+#[tokio::test]
+async fn manifests_are_signed_with_the_device_key() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let key = std::sync::Arc::new(tcfs_crypto::DeviceSigningKey::generate());
+    let mut state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    state.set_signing_key(Some(key.clone()));
+
+    let src = write_test_file(tmp.path(), "signed.txt", b"signed by dev1");
+    let upload = tcfs_sync::engine::upload_file_with_device(
+        &op,
+        &src,
+        "test/signed",
+        &state,
+        None,
+        "dev1",
+        Some("signed.txt"),
+        None,
+        false,
+    )
+    .await
+    .unwrap();
+
+    let bytes = op.read(&upload.remote_path).await.unwrap().to_bytes();
+    let manifest = tcfs_sync::manifest::SyncManifest::from_bytes(&bytes).unwrap();
+    manifest.verify_signature(&key.public_key()).unwrap();
+    let stranger = tcfs_crypto::DeviceSigningKey::generate();
+    assert!(manifest.verify_signature(&stranger.public_key()).is_err());
+}
+
 #[tokio::test]
 async fn unencrypted_download_of_encrypted_fails() {
     let tmp = TempDir::new().unwrap();
@@ -132,18 +171,19 @@ async fn unencrypted_download_of_encrypted_fails() {
     let src = write_test_file(tmp.path(), "locked.txt", content);
     let dst = tmp.path().join("output/locked.txt");
 
-    let mut state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
 
     // Upload with encryption
     let upload = tcfs_sync::engine::upload_file_with_device(
         &op,
         &src,
         prefix,
-        &mut state,
+        &state,
         None,
         "dev1",
         None,
         Some(&ctx),
+        false,
     )
     .await
     .expect("encrypted upload should succeed");
@@ -158,6 +198,7 @@ async fn unencrypted_download_of_encrypted_fails() {
         "dev1",
         None,
         None, // no encryption context
+        tcfs_sync::engine::RestoreMode::default(),
     )
     .await;
 
@@ -174,13 +215,13 @@ async fn mixed_encrypted_unencrypted_coexist() {
     let prefix = "test/mixed";
     let ctx = test_encryption_context();
 
-    let mut state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
 
     // Upload unencrypted file
     let plain_content = b"plaintext file content";
     let plain_src = write_test_file(tmp.path(), "plain.txt", plain_content);
     let plain_upload = tcfs_sync::engine::upload_file_with_device(
-        &op, &plain_src, prefix, &mut state, None, "dev1", None, None,
+        &op, &plain_src, prefix, &state, None, "dev1", None, None, false,
     )
     .await
     .expect("plain upload should succeed");
@@ -192,11 +233,12 @@ async fn mixed_encrypted_unencrypted_coexist() {
         &op,
         &enc_src,
         prefix,
-        &mut state,
+        &state,
         None,
         "dev1",
         None,
         Some(&ctx),
+        false,
     )
     .await
     .expect("encrypted upload should succeed");
@@ -214,6 +256,7 @@ async fn mixed_encrypted_unencrypted_coexist() {
         "dev1",
         None,
         None,
+        tcfs_sync::engine::RestoreMode::default(),
     )
     .await
     .expect("plain download should succeed");
@@ -227,6 +270,7 @@ async fn mixed_encrypted_unencrypted_coexist() {
         "dev1",
         None,
         Some(&ctx),
+        tcfs_sync::engine::RestoreMode::default(),
     )
     .await
     .expect("encrypted download should succeed");
@@ -234,3 +278,100 @@ async fn mixed_encrypted_unencrypted_coexist() {
     assert_eq!(std::fs::read(&plain_dst).unwrap(), plain_content);
     assert_eq!(std::fs::read(&enc_dst).unwrap(), enc_content);
 }
+
+#[tokio::test]
+async fn convergent_chunks_dedup_across_files() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/convergent";
+    let ctx = tcfs_sync::engine::EncryptionContext {
+        convergent: true,
+        ..test_encryption_context()
+    };
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+
+    // Two files that share a long plaintext prefix but differ afterwards
+    let shared = noise(1, 512 * 1024);
+    let a = [shared.as_slice(), &noise(2, 64 * 1024)].concat();
+    let b = [shared.as_slice(), &noise(3, 64 * 1024)].concat();
+    let a_src = write_test_file(tmp.path(), "a.bin", &a);
+    let b_src = write_test_file(tmp.path(), "b.bin", &b);
+
+    let mut manifests = Vec::new();
+    for src in [&a_src, &b_src] {
+        let upload = tcfs_sync::engine::upload_file_with_device(
+            &op,
+            src,
+            prefix,
+            &state,
+            None,
+            "dev1",
+            None,
+            Some(&ctx),
+            false,
+        )
+        .await
+        .expect("convergent upload should succeed");
+        let bytes = op.read(&upload.remote_path).await.unwrap();
+        let manifest = tcfs_sync::manifest::SyncManifest::from_bytes(&bytes.to_bytes()).unwrap();
+        assert!(manifest.encrypted_file_key.is_none());
+        assert_eq!(manifest.chunk_keys.len(), manifest.chunks.len());
+        manifests.push((upload.remote_path, manifest));
+    }
+
+    let (_, ma) = &manifests[0];
+    let (b_path, mb) = &manifests[1];
+    let shared_chunks = ma.chunks.iter().filter(|h| mb.chunks.contains(h)).count();
+    assert!(shared_chunks > 0, "shared plaintext must share ciphertext");
+
+    // Every shared chunk is stored once: the store holds the union only
+    let mut distinct: Vec<&String> = ma.chunks.iter().chain(&mb.chunks).collect();
+    distinct.sort();
+    distinct.dedup();
+    let stored = op
+        .list(&tcfs_core::layout::RemoteLayout::new(prefix).chunks_dir())
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|e| e.metadata().is_file())
+        .count();
+    assert_eq!(stored, distinct.len());
+    assert_eq!(stored, ma.chunks.len() + mb.chunks.len() - shared_chunks);
+
+    // The shared chunks still decrypt into each file
+    let dst = tmp.path().join("out/b.bin");
+    tcfs_sync::engine::download_file_with_device(
+        &op,
+        b_path,
+        &dst,
+        prefix,
+        None,
+        "dev1",
+        None,
+        Some(&ctx),
+        tcfs_sync::engine::RestoreMode::default(),
+    )
+    .await
+    .expect("convergent download should succeed");
+    assert_eq!(std::fs::read(&dst).unwrap(), b);
+
+    // Per-file keys, by contrast, never collide
+    let plain_ctx = test_encryption_context();
+    let c_src = write_test_file(tmp.path(), "c.bin", &a);
+    let upload = tcfs_sync::engine::upload_file_with_device(
+        &op,
+        &c_src,
+        "test/per-file",
+        &state,
+        None,
+        "dev1",
+        None,
+        Some(&plain_ctx),
+        false,
+    )
+    .await
+    .unwrap();
+    let bytes = op.read(&upload.remote_path).await.unwrap();
+    let mc = tcfs_sync::manifest::SyncManifest::from_bytes(&bytes.to_bytes()).unwrap();
+    assert!(mc.chunks.iter().all(|h| !ma.chunks.contains(h)));
+}
@@ -0,0 +1,446 @@
+//! Integration test: per-file version history pointers
+//!
+//! Pushes several versions of one file with history enabled, then lists the
+//! recorded versions and restores an older one through its manifest. Pruning
+//! drops old pointers and collects the manifests and chunks only they kept,
+//! never those of the live version. The in-memory backend reports no
+//! modification times, so pruning tests stamp writes from a mock clock.
+
+use opendal::raw::*;
+use opendal::Operator;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tcfs_core::clock::{Clock, MockClock};
+use tcfs_core::index::IndexEntry;
+use tcfs_sync::engine::{prune_history, PruneHistory, PruneStats};
+use tcfs_sync::history::{self, HistoryPolicy};
+use tempfile::TempDir;
+
+fn memory_operator() -> Operator {
+    Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish()
+}
+
+/// Records when each object was written, per a mock clock, and reports it
+/// as the object's modification time.
+#[derive(Debug, Clone)]
+struct StampLayer {
+    clock: Arc<MockClock>,
+    written: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl<A: Access> Layer<A> for StampLayer {
+    type LayeredAccess = StampAccessor<A>;
+
+    fn layer(&self, inner: A) -> Self::LayeredAccess {
+        StampAccessor {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct StampAccessor<A> {
+    inner: A,
+    layer: StampLayer,
+}
+
+impl<A: Access> LayeredAccess for StampAccessor<A> {
+    type Inner = A;
+    type Reader = A::Reader;
+    type Writer = A::Writer;
+    type Lister = A::Lister;
+    type Deleter = A::Deleter;
+
+    fn inner(&self) -> &Self::Inner {
+        &self.inner
+    }
+
+    async fn read(&self, path: &str, args: OpRead) -> opendal::Result<(RpRead, Self::Reader)> {
+        self.inner.read(path, args).await
+    }
+
+    async fn write(&self, path: &str, args: OpWrite) -> opendal::Result<(RpWrite, Self::Writer)> {
+        let now = self.layer.clock.unix_secs();
+        self.layer
+            .written
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), now);
+        self.inner.write(path, args).await
+    }
+
+    async fn stat(&self, path: &str, args: OpStat) -> opendal::Result<RpStat> {
+        let rp = self.inner.stat(path, args).await?;
+        let written = self.layer.written.lock().unwrap().get(path).copied();
+        match written {
+            Some(at) => {
+                let at = Timestamp::from_second(at as i64)?;
+                Ok(RpStat::new(rp.into_metadata().with_last_modified(at)))
+            }
+            None => Ok(rp),
+        }
+    }
+
+    async fn delete(&self) -> opendal::Result<(RpDelete, Self::Deleter)> {
+        self.inner.delete().await
+    }
+
+    async fn list(&self, path: &str, args: OpList) -> opendal::Result<(RpList, Self::Lister)> {
+        self.inner.list(path, args).await
+    }
+}
+
+fn stamped_operator(clock: Arc<MockClock>) -> Operator {
+    memory_operator().layer(StampLayer {
+        clock,
+        written: Default::default(),
+    })
+}
+
+#[tokio::test]
+async fn restore_middle_of_three_versions() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/history";
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(src.join("docs")).unwrap();
+    let file = src.join("docs/notes.md");
+    let policy = HistoryPolicy { max_versions: 10 };
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let contents: [&[u8]; 3] = [b"first draft\n", b"second draft, longer\n", b"third\n"];
+    for (i, content) in contents.iter().enumerate() {
+        if i > 0 {
+            // Versions are timestamped in whole seconds
+            tokio::time::sleep(Duration::from_millis(1100)).await;
+        }
+        std::fs::write(&file, content).unwrap();
+        let (uploaded, _, _) = tcfs_sync::engine::push_tree_with_device(
+            &op,
+            &src,
+            prefix,
+            &state,
+            None,
+            "",
+            None,
+            None,
+            1,
+            Some(&policy),
+        )
+        .await
+        .expect("push_tree");
+        assert_eq!(uploaded, 1);
+    }
+
+    let versions = history::list_versions(&op, prefix, "docs/notes.md")
+        .await
+        .unwrap();
+    assert_eq!(versions.len(), 3);
+    assert!(versions.windows(2).all(|w| w[0].timestamp < w[1].timestamp));
+    assert_eq!(versions[1].size, contents[1].len() as u64);
+
+    let dst = tmp.path().join("restored/notes.md");
+    let (restored, _) =
+        history::restore_version(&op, prefix, "docs/notes.md", versions[1].timestamp, &dst)
+            .await
+            .expect("restore");
+    assert_eq!(restored, versions[1]);
+    assert_eq!(std::fs::read(&dst).unwrap(), contents[1]);
+
+    // Nothing recorded that early
+    assert!(
+        history::restore_version(&op, prefix, "docs/notes.md", 0, &dst)
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn history_is_pruned_to_max_versions() {
+    let op = memory_operator();
+    let policy = HistoryPolicy { max_versions: 2 };
+
+    for (ts, hash) in [(100, "aaa"), (200, "bbb"), (300, "ccc")] {
+        let entry = IndexEntry::new(hash, 1, 1, None);
+        history::record_version(&op, "test/prune", "a.txt", &entry, ts, &policy)
+            .await
+            .unwrap();
+    }
+
+    let versions = history::list_versions(&op, "test/prune", "a.txt")
+        .await
+        .unwrap();
+    let kept: Vec<(u64, &str)> = versions
+        .iter()
+        .map(|v| (v.timestamp, v.file_hash.as_str()))
+        .collect();
+    assert_eq!(kept, vec![(200, "bbb"), (300, "ccc")]);
+}
+
+#[tokio::test]
+async fn history_is_off_without_policy() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::write(src.join("a.txt"), b"no history").unwrap();
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    tcfs_sync::engine::push_tree(&op, &src, "test/off", &state, None)
+        .await
+        .unwrap();
+
+    assert!(history::list_versions(&op, "test/off", "a.txt")
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        HistoryPolicy::from_config(&tcfs_core::config::SyncConfig::default()),
+        None
+    );
+}
+
+/// `len` bytes of noise from `seed`, sharing no chunks with other seeds.
+fn noise(seed: u32, len: usize) -> Vec<u8> {
+    let mut x = seed.wrapping_mul(0x9e37_79b9) | 1;
+    (0..len)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x as u8
+        })
+        .collect()
+}
+
+fn version_len(seed: u32) -> usize {
+    256 * 1024 + seed as usize * 1000
+}
+
+async fn chunk_keys(op: &Operator, prefix: &str, file_hash: &str) -> Vec<String> {
+    let layout = tcfs_core::layout::RemoteLayout::new(prefix);
+    let data = op.read(&layout.manifest_key(file_hash)).await.unwrap();
+    let manifest = tcfs_sync::manifest::SyncManifest::from_bytes(&data.to_bytes()).unwrap();
+    let chunks = manifest.chunk_layout(prefix);
+    manifest
+        .chunk_hashes()
+        .iter()
+        .map(|h| chunks.chunk_key(h))
+        .collect()
+}
+
+#[tokio::test]
+async fn prune_keeps_newest_and_collects_orphaned_chunks() {
+    const DAY: u64 = 86_400;
+    let tmp = TempDir::new().unwrap();
+    let clock = Arc::new(MockClock::new(1_700_000_000));
+    let op = stamped_operator(clock.clone());
+    let prefix = "test/prune-gc";
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    let policy = HistoryPolicy { max_versions: 0 };
+
+    let mut state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    state.set_clock(clock.clone());
+    for seed in 1..=5 {
+        // Sizes differ so each rewrite is seen within the same mtime second
+        std::fs::write(src.join("disk.img"), noise(seed, version_len(seed))).unwrap();
+        tcfs_sync::engine::push_tree_with_device(
+            &op,
+            &src,
+            prefix,
+            &state,
+            None,
+            "",
+            None,
+            None,
+            1,
+            Some(&policy),
+        )
+        .await
+        .expect("push_tree");
+        clock.advance(Duration::from_secs(DAY));
+    }
+    let now = 1_700_000_000 + 5 * DAY;
+
+    let versions = history::list_versions(&op, prefix, "disk.img")
+        .await
+        .unwrap();
+    assert_eq!(versions.len(), 5);
+    let mut chunks = Vec::new();
+    for v in &versions {
+        chunks.push(chunk_keys(&op, prefix, &v.file_hash).await);
+    }
+
+    let stats = prune_history(
+        &op,
+        prefix,
+        &PruneHistory {
+            keep: Some(2),
+            ..Default::default()
+        },
+        now,
+    )
+    .await
+    .unwrap();
+    let collected: usize = chunks[..3].iter().map(Vec::len).sum();
+    assert_eq!(stats.pointers, 3);
+    assert_eq!(stats.manifests, 3);
+    assert_eq!(stats.chunks, collected);
+
+    let layout = tcfs_core::layout::RemoteLayout::new(prefix);
+    let kept = history::list_versions(&op, prefix, "disk.img")
+        .await
+        .unwrap();
+    assert_eq!(kept, versions[3..]);
+    for (i, v) in versions.iter().enumerate() {
+        let manifest = layout.manifest_key(&v.file_hash);
+        assert_eq!(op.exists(&manifest).await.unwrap(), i >= 3, "{manifest}");
+        for key in &chunks[i] {
+            assert_eq!(op.exists(key).await.unwrap(), i >= 3, "{key}");
+        }
+    }
+
+    // Age alone: version 4 goes, the live version 5 keeps its content
+    let stats = prune_history(
+        &op,
+        prefix,
+        &PruneHistory {
+            older_than: Some(DAY + DAY / 2),
+            ..Default::default()
+        },
+        now,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        stats,
+        PruneStats {
+            pointers: 1,
+            manifests: 1,
+            chunks: chunks[3].len(),
+            bytes: stats.bytes,
+        }
+    );
+    assert!(stats.bytes > 0);
+    assert_eq!(
+        history::list_versions(&op, prefix, "disk.img")
+            .await
+            .unwrap(),
+        versions[4..]
+    );
+
+    // The live file is untouched even once its own pointer is pruned
+    prune_history(&op, prefix, &PruneHistory::default(), now)
+        .await
+        .unwrap();
+    for key in &chunks[4] {
+        assert!(op.exists(key).await.unwrap(), "{key}");
+    }
+    let dst = tmp.path().join("pulled");
+    let (files, _, _) = tcfs_sync::engine::pull_tree(
+        &op,
+        prefix,
+        &dst,
+        None,
+        tcfs_sync::engine::DEFAULT_MODE_UMASK,
+    )
+    .await
+    .unwrap();
+    assert_eq!(files, 1);
+    assert_eq!(
+        std::fs::read(dst.join("disk.img")).unwrap(),
+        noise(5, version_len(5))
+    );
+}
+
+#[tokio::test]
+async fn prune_spares_chunks_a_push_reuses_between_sweeps() {
+    const HOUR: u64 = 3600;
+    let tmp = TempDir::new().unwrap();
+    let clock = Arc::new(MockClock::new(1_700_000_000));
+    let op = stamped_operator(clock.clone());
+    let prefix = "test/prune-reuse";
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    let mut state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    state.set_clock(clock.clone());
+    let layout = tcfs_core::layout::RemoteLayout::new(prefix);
+    let policy = PruneHistory {
+        keep: Some(1),
+        chunk_grace_secs: HOUR,
+        ..Default::default()
+    };
+    let push = |seed: u32| {
+        std::fs::write(src.join("disk.img"), noise(seed, version_len(seed))).unwrap();
+        tcfs_sync::engine::push_tree_with_device(
+            &op,
+            &src,
+            prefix,
+            &state,
+            None,
+            "",
+            None,
+            None,
+            1,
+            Some(&HistoryPolicy { max_versions: 0 }),
+        )
+    };
+    let exist = |keys: &[String]| {
+        let op = op.clone();
+        let keys = keys.to_vec();
+        async move {
+            for key in keys {
+                if !op.exists(&key).await.unwrap() {
+                    return false;
+                }
+            }
+            true
+        }
+    };
+
+    push(1).await.unwrap();
+    clock.advance(Duration::from_secs(2 * HOUR));
+    push(2).await.unwrap();
+    let versions = history::list_versions(&op, prefix, "disk.img")
+        .await
+        .unwrap();
+    let old_chunks = chunk_keys(&op, prefix, &versions[0].file_hash).await;
+    let new_chunks = chunk_keys(&op, prefix, &versions[1].file_hash).await;
+
+    // The first sweep only condemns the first version's chunks
+    clock.advance(Duration::from_secs(2 * HOUR));
+    let stats = prune_history(&op, prefix, &policy, clock.unix_secs())
+        .await
+        .unwrap();
+    assert_eq!((stats.pointers, stats.manifests, stats.chunks), (1, 1, 0));
+    assert!(exist(&old_chunks).await);
+    assert!(op.exists(&layout.condemned_key()).await.unwrap());
+
+    // Restoring the first version deduplicates onto its condemned chunks,
+    // which the next sweep must then keep
+    push(1).await.unwrap();
+    clock.advance(Duration::from_secs(2 * HOUR));
+    let stats = prune_history(&op, prefix, &policy, clock.unix_secs())
+        .await
+        .unwrap();
+    assert_eq!((stats.pointers, stats.manifests, stats.chunks), (1, 1, 0));
+    assert!(exist(&old_chunks).await);
+    assert!(exist(&new_chunks).await);
+
+    // Left unreferenced for the grace period, the second version's go
+    clock.advance(Duration::from_secs(2 * HOUR));
+    let stats = prune_history(&op, prefix, &policy, clock.unix_secs())
+        .await
+        .unwrap();
+    assert_eq!(stats.chunks, new_chunks.len());
+    for key in &new_chunks {
+        assert!(!op.exists(key).await.unwrap(), "{key}");
+    }
+    assert!(exist(&old_chunks).await);
+    assert!(!op.exists(&layout.condemned_key()).await.unwrap());
+}
@@ -0,0 +1,343 @@
+//! Integration test: conditional manifest writes under a racing writer
+//!
+//! The in-memory backend has no conditional-write support, so a test layer
+//! advertises If-None-Match / If-Match, tracks a version per object (exposed
+//! as the ETag), and can inject a rival manifest write just before the
+//! engine's own write lands. The engine must notice the lost race, re-read
+//! the manifest, and re-run the clock comparison instead of clobbering it.
+//! A rival index entry written during a tree push must likewise survive,
+//! with the push reporting the file as conflicted, and a push from a device
+//! that never saw another's newer entry must leave that entry in place.
+
+use opendal::raw::*;
+use opendal::{Buffer, Operator};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tcfs_sync::conflict::{SyncOutcome, VectorClock};
+use tcfs_sync::manifest::SyncManifest;
+use tempfile::TempDir;
+
+#[derive(Debug, Clone, Default)]
+struct RaceLayer {
+    versions: Arc<Mutex<HashMap<String, u64>>>,
+    /// Rival manifest bytes written on the next conditional manifest write.
+    rival: Arc<Mutex<Option<Vec<u8>>>>,
+    /// Rival index entry bytes written on the next conditional index write.
+    rival_index: Arc<Mutex<Option<Vec<u8>>>>,
+    raced: Arc<AtomicBool>,
+}
+
+impl<A: Access> Layer<A> for RaceLayer {
+    type LayeredAccess = RaceAccessor<A>;
+
+    fn layer(&self, inner: A) -> Self::LayeredAccess {
+        inner.info().update_full_capability(|mut cap| {
+            cap.write_with_if_not_exists = true;
+            cap.write_with_if_match = true;
+            cap
+        });
+        RaceAccessor {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct RaceAccessor<A> {
+    inner: A,
+    layer: RaceLayer,
+}
+
+impl<A: Access> RaceAccessor<A> {
+    fn bump(&self, path: &str) {
+        *self
+            .layer
+            .versions
+            .lock()
+            .unwrap()
+            .entry(path.to_string())
+            .or_insert(0) += 1;
+    }
+
+    async fn write_through(&self, path: &str, data: Vec<u8>) -> opendal::Result<()> {
+        let (_, mut w) = self.inner.write(path, OpWrite::default()).await?;
+        oio::Write::write(&mut w, Buffer::from(data)).await?;
+        oio::Write::close(&mut w).await?;
+        self.bump(path);
+        Ok(())
+    }
+}
+
+fn condition_not_match(msg: &str) -> opendal::Error {
+    opendal::Error::new(opendal::ErrorKind::ConditionNotMatch, msg.to_string())
+}
+
+impl<A: Access> LayeredAccess for RaceAccessor<A> {
+    type Inner = A;
+    type Reader = A::Reader;
+    type Writer = A::Writer;
+    type Lister = A::Lister;
+    type Deleter = A::Deleter;
+
+    fn inner(&self) -> &Self::Inner {
+        &self.inner
+    }
+
+    async fn read(&self, path: &str, args: OpRead) -> opendal::Result<(RpRead, Self::Reader)> {
+        self.inner.read(path, args).await
+    }
+
+    async fn write(&self, path: &str, args: OpWrite) -> opendal::Result<(RpWrite, Self::Writer)> {
+        let conditional = args.if_not_exists() || args.if_match().is_some();
+        let rival = match path {
+            _ if !conditional => None,
+            p if p.contains("/manifests/") => self.layer.rival.lock().unwrap().take(),
+            p if p.contains("/index/") => self.layer.rival_index.lock().unwrap().take(),
+            _ => None,
+        };
+        if let Some(rival) = rival {
+            self.write_through(path, rival).await?;
+            self.layer.raced.store(true, Ordering::SeqCst);
+            return Err(condition_not_match("rival write landed first"));
+        }
+
+        let current = self.layer.versions.lock().unwrap().get(path).copied();
+        if args.if_not_exists() && current.is_some() {
+            return Err(condition_not_match("object exists"));
+        }
+        if let Some(etag) = args.if_match() {
+            if current.map(|v| v.to_string()).as_deref() != Some(etag) {
+                return Err(condition_not_match("etag mismatch"));
+            }
+        }
+
+        self.bump(path);
+        self.inner.write(path, args).await
+    }
+
+    async fn stat(&self, path: &str, args: OpStat) -> opendal::Result<RpStat> {
+        let rp = self.inner.stat(path, args).await?;
+        let version = self.layer.versions.lock().unwrap().get(path).copied();
+        match version {
+            Some(v) => Ok(RpStat::new(rp.into_metadata().with_etag(v.to_string()))),
+            None => Ok(rp),
+        }
+    }
+
+    async fn delete(&self) -> opendal::Result<(RpDelete, Self::Deleter)> {
+        self.inner.delete().await
+    }
+
+    async fn list(&self, path: &str, args: OpList) -> opendal::Result<(RpList, Self::Lister)> {
+        self.inner.list(path, args).await
+    }
+}
+
+#[tokio::test]
+async fn racing_manifest_write_triggers_recheck() {
+    let tmp = TempDir::new().unwrap();
+    let race = RaceLayer::default();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .layer(race.clone())
+        .finish();
+    let prefix = "test/cas";
+
+    let content = b"two devices push this at the same moment";
+    let src = tmp.path().join("shared.txt");
+    std::fs::write(&src, content).unwrap();
+    let file_hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(content));
+
+    // The rival device's manifest for the same content lands first
+    let mut rival_clock = VectorClock::new();
+    rival_clock.tick("rival");
+    let rival = SyncManifest {
+        version: 2,
+        file_hash: file_hash.clone(),
+        file_size: content.len() as u64,
+        chunks: vec![file_hash.clone()],
+        vclock: rival_clock,
+        written_by: "rival".into(),
+        written_at: 1000,
+        rel_path: Some("shared.txt".into()),
+        encrypted_file_key: None,
+        mode: None,
+        compressed: Vec::new(),
+        chunk_keys: Vec::new(),
+        chunk_shard_depth: 0,
+        signature: None,
+        manifest_checksum: None,
+    };
+    *race.rival.lock().unwrap() = Some(rival.to_bytes().unwrap());
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let result = tcfs_sync::engine::upload_file_with_device(
+        &op,
+        &src,
+        prefix,
+        &state,
+        None,
+        "local",
+        Some("shared.txt"),
+        None,
+        false,
+    )
+    .await
+    .expect("upload should survive the race");
+
+    assert!(race.raced.load(Ordering::SeqCst), "race was not injected");
+    assert!(result.skipped, "losing writer must not overwrite");
+    assert!(matches!(result.outcome, Some(SyncOutcome::UpToDate)));
+
+    // The rival's manifest is still in place
+    let stored = op.read(&result.remote_path).await.unwrap();
+    let stored = SyncManifest::from_bytes(&stored.to_bytes()).unwrap();
+    assert_eq!(stored.written_by, "rival");
+
+    // Local state absorbed the rival's clock during the re-check
+    let entry = state.get(&src).expect("state recorded");
+    assert_eq!(entry.vclock.get("rival"), 1);
+    assert_eq!(entry.vclock.get("local"), 1);
+}
+
+#[tokio::test]
+async fn conditional_write_reports_concurrent_modification() {
+    let race = RaceLayer::default();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .layer(race)
+        .finish();
+
+    let path = "test/cas/objects/a";
+    let version = tcfs_sync::engine::stat_version(&op, path).await.unwrap();
+    assert_eq!(version, tcfs_sync::engine::ObjectVersion::Absent);
+
+    tcfs_sync::engine::write_conditional(&op, path, b"first".to_vec(), &version)
+        .await
+        .expect("create succeeds");
+
+    // A second create against the stale "absent" version must fail
+    let err = tcfs_sync::engine::write_conditional(&op, path, b"second".to_vec(), &version)
+        .await
+        .unwrap_err();
+    assert!(err.is::<tcfs_sync::engine::ConcurrentModification>());
+
+    // Writing against the fresh version succeeds
+    let fresh = tcfs_sync::engine::stat_version(&op, path).await.unwrap();
+    assert!(matches!(fresh, tcfs_sync::engine::ObjectVersion::ETag(_)));
+    tcfs_sync::engine::write_conditional(&op, path, b"second".to_vec(), &fresh)
+        .await
+        .expect("write against fresh etag succeeds");
+}
+
+#[tokio::test]
+async fn racing_index_write_is_not_overwritten() {
+    let tmp = TempDir::new().unwrap();
+    let race = RaceLayer::default();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .layer(race.clone())
+        .finish();
+    let prefix = "test/cas-index";
+    let root = tmp.path().join("tree");
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("shared.txt"), b"our edit").unwrap();
+
+    // Another device repoints shared.txt while this push is uploading
+    let rival = tcfs_core::index::IndexEntry::new(&"f".repeat(64), 11, 1, Some(1000)).to_bytes();
+    let index_key = format!("{prefix}/index/shared.txt");
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    for transactional in [false, true] {
+        race.raced.store(false, Ordering::SeqCst);
+        *race.rival_index.lock().unwrap() = Some(rival.clone());
+        let pushed = if transactional {
+            tcfs_sync::engine::push_tree_transactional(
+                &op, &root, prefix, &state, None, "local", None, None, 1, None,
+            )
+            .await
+        } else {
+            tcfs_sync::engine::push_tree_with_stats(
+                &op, &root, prefix, &state, None, "local", None, None, 1, None,
+            )
+            .await
+        };
+        assert!(race.raced.load(Ordering::SeqCst), "race was not injected");
+        match pushed {
+            Ok(stats) => {
+                assert!(!transactional);
+                assert_eq!(stats.conflicts, 1);
+                assert_eq!(stats.uploaded, 0);
+                assert!(stats.root_hash.is_none());
+                assert!(state.get(&root.join("shared.txt")).is_none());
+            }
+            Err(e) => {
+                assert!(transactional, "{e:#}");
+                assert!(e.is::<tcfs_sync::engine::ConcurrentModification>(), "{e:#}");
+            }
+        }
+        assert_eq!(op.read(&index_key).await.unwrap().to_vec(), rival);
+        op.delete(&index_key).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn unchanged_push_keeps_a_newer_entry_from_another_device() {
+    let tmp = TempDir::new().unwrap();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish();
+    let prefix = "test/cas-stale";
+    let index_key = format!("{prefix}/index/shared.txt");
+    let root_a = tmp.path().join("a");
+    let root_b = tmp.path().join("b");
+    std::fs::create_dir_all(&root_a).unwrap();
+    std::fs::create_dir_all(&root_b).unwrap();
+    let state_a = tcfs_sync::state::StateCache::open(&tmp.path().join("a.db")).unwrap();
+    let state_b = tcfs_sync::state::StateCache::open(&tmp.path().join("b.db")).unwrap();
+
+    // B pushes the first version, A pulls it and pushes an edit
+    std::fs::write(root_b.join("shared.txt"), b"first version").unwrap();
+    tcfs_sync::engine::push_tree_with_stats(
+        &op, &root_b, prefix, &state_b, None, "dev-b", None, None, 1, None,
+    )
+    .await
+    .unwrap();
+    let pulled = tcfs_sync::engine::reconcile_tree(
+        &op, &root_a, prefix, &state_a, "dev-a", None, None, 0o022,
+    )
+    .await
+    .unwrap();
+    assert_eq!(pulled.pulled, 1);
+    std::fs::write(root_a.join("shared.txt"), b"second version, from a").unwrap();
+    let stats = tcfs_sync::engine::push_tree_with_stats(
+        &op, &root_a, prefix, &state_a, None, "dev-a", None, None, 1, None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(stats.uploaded, 1);
+    let a_entry = op.read(&index_key).await.unwrap().to_vec();
+
+    // B's copy is unchanged since its push, so its next push must not
+    // put the first version back
+    let stats = tcfs_sync::engine::push_tree_with_stats(
+        &op, &root_b, prefix, &state_b, None, "dev-b", None, None, 1, None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(stats.uploaded, 0);
+    assert_eq!(stats.conflicts, 0);
+    assert_eq!(op.read(&index_key).await.unwrap().to_vec(), a_entry);
+
+    // An edit on B that never saw A's version is a conflict, not an overwrite
+    std::fs::write(root_b.join("shared.txt"), b"concurrent edit on b").unwrap();
+    let stats = tcfs_sync::engine::push_tree_with_stats(
+        &op, &root_b, prefix, &state_b, None, "dev-b", None, None, 1, None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(stats.uploaded, 0);
+    assert_eq!(stats.conflicts, 1);
+    assert_eq!(op.read(&index_key).await.unwrap().to_vec(), a_entry);
+}
@@ -0,0 +1,176 @@
+//! Integration test: touching or moving a file syncs metadata only
+//!
+//! A counting layer records `write` calls on chunk keys. Once a tree is
+//! pushed, a new mtime or a rename must update the manifest, index and state
+//! cache without writing a single chunk, and a rename must tombstone the
+//! old path.
+
+mod common;
+
+use common::noise;
+use opendal::raw::*;
+use opendal::Operator;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tcfs_core::index::IndexEntry;
+use tcfs_sync::manifest::SyncManifest;
+use tcfs_sync::state::StateCache;
+use tempfile::TempDir;
+
+const PREFIX: &str = "test/meta";
+
+#[derive(Debug, Clone, Default)]
+struct ChunkWrites(Arc<AtomicUsize>);
+
+impl ChunkWrites {
+    fn take(&self) -> usize {
+        self.0.swap(0, Ordering::SeqCst)
+    }
+}
+
+impl<A: Access> Layer<A> for ChunkWrites {
+    type LayeredAccess = ChunkWritesAccessor<A>;
+
+    fn layer(&self, inner: A) -> Self::LayeredAccess {
+        ChunkWritesAccessor {
+            inner,
+            writes: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ChunkWritesAccessor<A> {
+    inner: A,
+    writes: ChunkWrites,
+}
+
+impl<A: Access> LayeredAccess for ChunkWritesAccessor<A> {
+    type Inner = A;
+    type Reader = A::Reader;
+    type Writer = A::Writer;
+    type Lister = A::Lister;
+    type Deleter = A::Deleter;
+
+    fn inner(&self) -> &Self::Inner {
+        &self.inner
+    }
+
+    async fn read(&self, path: &str, args: OpRead) -> opendal::Result<(RpRead, Self::Reader)> {
+        self.inner.read(path, args).await
+    }
+
+    async fn write(&self, path: &str, args: OpWrite) -> opendal::Result<(RpWrite, Self::Writer)> {
+        if path.contains("/chunks/") {
+            self.writes.0.fetch_add(1, Ordering::SeqCst);
+        }
+        self.inner.write(path, args).await
+    }
+
+    async fn delete(&self) -> opendal::Result<(RpDelete, Self::Deleter)> {
+        self.inner.delete().await
+    }
+
+    async fn list(&self, path: &str, args: OpList) -> opendal::Result<(RpList, Self::Lister)> {
+        self.inner.list(path, args).await
+    }
+}
+
+async fn push(op: &Operator, root: &Path, state: &StateCache) -> tcfs_sync::engine::PushTreeStats {
+    tcfs_sync::engine::push_tree_with_stats(
+        op, root, PREFIX, state, None, "dev1", None, None, 1, None,
+    )
+    .await
+    .unwrap()
+}
+
+async fn read_index(op: &Operator, rel: &str) -> IndexEntry {
+    let key = tcfs_core::layout::RemoteLayout::new(PREFIX).index_key(rel);
+    IndexEntry::from_bytes(&op.read(&key).await.unwrap().to_bytes()).unwrap()
+}
+
+async fn read_manifest(op: &Operator, hash: &str) -> SyncManifest {
+    let key = tcfs_core::layout::RemoteLayout::new(PREFIX).manifest_key(hash);
+    SyncManifest::from_bytes(&op.read(&key).await.unwrap().to_bytes()).unwrap()
+}
+
+#[tokio::test]
+async fn touch_and_rename_write_no_chunks() {
+    let tmp = TempDir::new().unwrap();
+    let writes = ChunkWrites::default();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .layer(writes.clone())
+        .finish();
+    let root = tmp.path().join("tree");
+    std::fs::create_dir_all(&root).unwrap();
+    let a = root.join("a.bin");
+    std::fs::write(&a, noise(11, 256 * 1024)).unwrap();
+    let state = StateCache::open(&tmp.path().join("state.db")).unwrap();
+
+    let stats = push(&op, &root, &state).await;
+    assert_eq!(stats.uploaded, 1);
+    assert!(writes.take() > 1, "expected several chunks");
+    let hash = read_index(&op, "a.bin").await.manifest_hash;
+    let first_tick = read_manifest(&op, &hash).await.vclock.get("dev1");
+
+    // Touch: same bytes, mtime moved forward
+    let later = SystemTime::now() + Duration::from_secs(3600);
+    std::fs::File::options()
+        .write(true)
+        .open(&a)
+        .unwrap()
+        .set_modified(later)
+        .unwrap();
+    let later_secs = later
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let stats = push(&op, &root, &state).await;
+    assert_eq!(writes.take(), 0, "touch must not write chunks");
+    assert_eq!((stats.uploaded, stats.new_chunks), (1, 0));
+    assert_eq!(state.get(&a).unwrap().mtime, later_secs);
+    assert_eq!(read_index(&op, "a.bin").await.modified, Some(later_secs));
+    let manifest = read_manifest(&op, &hash).await;
+    assert_eq!(manifest.vclock.get("dev1"), first_tick + 1);
+
+    // Pushing again with nothing changed stays a plain skip
+    let stats = push(&op, &root, &state).await;
+    assert_eq!((stats.uploaded, stats.skipped), (0, 1));
+
+    // Rename: the content is tracked under the old path
+    let b = root.join("b.bin");
+    std::fs::rename(&a, &b).unwrap();
+    let stats = push(&op, &root, &state).await;
+    assert_eq!(writes.take(), 0, "rename must not write chunks");
+    assert_eq!((stats.uploaded, stats.new_chunks), (1, 0));
+    assert_eq!(read_index(&op, "b.bin").await.manifest_hash, hash);
+    let manifest = read_manifest(&op, &hash).await;
+    assert_eq!(manifest.rel_path.as_deref(), Some("b.bin"));
+    assert_eq!(manifest.vclock.get("dev1"), first_tick + 2);
+    assert!(state.get(&a).is_none());
+    assert_eq!(state.get(&b).unwrap().blake3, hash);
+
+    // The old path is tombstoned, so a sweep does not pull the file back
+    assert!(read_index(&op, "a.bin").await.is_tombstone());
+    assert!(state.tombstone(&a).is_some());
+    let plan = tcfs_sync::engine::plan_reconcile(&op, &root, PREFIX, &state, "dev1", None)
+        .await
+        .unwrap();
+    assert!(
+        plan.iter().all(|action| action.local_path() != a),
+        "{plan:?}"
+    );
+
+    // The direct API refuses content it does not know
+    let c = root.join("c.bin");
+    std::fs::write(&c, noise(12, 1024)).unwrap();
+    let result = tcfs_sync::engine::update_metadata(&op, PREFIX, &state, &c, Some("c.bin"), "dev1")
+        .await
+        .unwrap();
+    assert!(result.is_none());
+    assert_eq!(writes.take(), 0);
+}
@@ -9,7 +9,7 @@
 //!   5. Eventual convergence — after draining events, all online machines agree
 
 use proptest::prelude::*;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::HashMap;
 use tcfs_sync::conflict::{compare_clocks, SyncOutcome, VectorClock};
 use tcfs_sync::manifest::SyncManifest;
 
@@ -161,6 +161,12 @@ fn execute_op(
                 written_at: 0,
                 rel_path: Some(path.clone()),
                 encrypted_file_key: None,
+                mode: None,
+                compressed: Vec::new(),
+                chunk_keys: Vec::new(),
+                chunk_shard_depth: 0,
+                signature: None,
+                manifest_checksum: None,
             };
 
             remote.manifests.insert(path.clone(), manifest);
@@ -598,7 +604,7 @@ proptest! {
 
             if hashes.len() > 1 {
                 let first = hashes[0];
-                for (i, h) in hashes.iter().enumerate().skip(1) {
+                for h in hashes.iter().skip(1) {
                     prop_assert_eq!(
                         *h,
                         first,
@@ -755,6 +761,12 @@ fn test_manifest_serialization_in_sim() {
         written_at: 1000,
         rel_path: Some("src/main.rs".into()),
         encrypted_file_key: None,
+        mode: None,
+        compressed: Vec::new(),
+        chunk_keys: Vec::new(),
+        chunk_shard_depth: 0,
+        signature: None,
+        manifest_checksum: None,
     };
 
     let bytes = manifest.to_bytes().unwrap();
@@ -0,0 +1,92 @@
+//! Integration test: uploads carry HTTP object metadata
+//!
+//! The in-memory backend records `Content-Type` and `Cache-Control`, so a
+//! push can be checked for the metadata a CDN or S3 console would see:
+//! chunks are `application/octet-stream` with the configured cache control,
+//! manifests are `application/json`.
+
+use opendal::Operator;
+use tcfs_sync::state::StateCache;
+use tcfs_sync::store::{CHUNK_CONTENT_TYPE, MANIFEST_CONTENT_TYPE};
+use tempfile::TempDir;
+
+const PREFIX: &str = "test/meta";
+const CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+fn memory_operator() -> Operator {
+    Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish()
+}
+
+/// Paths of every object under `dir` of the prefix.
+async fn objects(op: &Operator, dir: &str) -> Vec<String> {
+    let entries = op
+        .list_with(&format!("{PREFIX}/{dir}/"))
+        .recursive(true)
+        .await
+        .unwrap();
+    entries
+        .iter()
+        .filter(|e| e.metadata().is_file())
+        .map(|e| e.path().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn chunks_and_manifests_carry_metadata() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let src = tmp.path().join("data.bin");
+    std::fs::write(&src, b"served through a cdn ".repeat(8192)).unwrap();
+    let state = StateCache::open(&tmp.path().join("state.json")).unwrap();
+    state.set_cache_control(Some(CACHE_CONTROL.to_string()));
+
+    tcfs_sync::engine::upload_file_with_device(
+        &op,
+        &src,
+        PREFIX,
+        &state,
+        None,
+        "dev1",
+        Some("data.bin"),
+        None,
+        false,
+    )
+    .await
+    .unwrap();
+
+    let chunks = objects(&op, "chunks").await;
+    assert!(!chunks.is_empty());
+    for key in &chunks {
+        let meta = op.stat(key).await.unwrap();
+        assert_eq!(meta.content_type(), Some(CHUNK_CONTENT_TYPE), "{key}");
+        assert_eq!(meta.cache_control(), Some(CACHE_CONTROL), "{key}");
+    }
+
+    let manifests = objects(&op, "manifests").await;
+    assert_eq!(manifests.len(), 1);
+    let meta = op.stat(&manifests[0]).await.unwrap();
+    assert_eq!(meta.content_type(), Some(MANIFEST_CONTENT_TYPE));
+    // Manifests are rewritten in place, so they are never marked cacheable
+    assert_eq!(meta.cache_control(), None);
+}
+
+#[tokio::test]
+async fn cache_control_is_unset_by_default() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let src = tmp.path().join("data.bin");
+    std::fs::write(&src, b"no cache header").unwrap();
+    let state = StateCache::open(&tmp.path().join("state.json")).unwrap();
+
+    tcfs_sync::engine::upload_file(&op, &src, PREFIX, &state, None)
+        .await
+        .unwrap();
+
+    for key in objects(&op, "chunks").await {
+        let meta = op.stat(&key).await.unwrap();
+        assert_eq!(meta.content_type(), Some(CHUNK_CONTENT_TYPE), "{key}");
+        assert_eq!(meta.cache_control(), None, "{key}");
+    }
+}
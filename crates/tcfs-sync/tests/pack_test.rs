@@ -0,0 +1,235 @@
+//! Integration test: packing small files
+//!
+//! Pushes 100 tiny files with a pack threshold set and checks that they land
+//! in a single pack object (with no manifests or chunks), that the pack
+//! index and the per-file index entries agree, and that files come back
+//! through ranged reads: by pattern, by tree snapshot and one at a time.
+
+use opendal::Operator;
+use tcfs_core::index::IndexEntry;
+use tcfs_core::layout::RemoteLayout;
+use tcfs_sync::engine::{push_tree_with_stats, PushTreeStats};
+use tcfs_sync::state::StateCache;
+use tempfile::TempDir;
+
+const PREFIX: &str = "test/packs";
+
+fn content(i: usize) -> Vec<u8> {
+    format!("tiny file number {i}\n")
+        .repeat(i % 7 + 1)
+        .into_bytes()
+}
+
+async fn keys_under(op: &Operator, dir: &str) -> Vec<String> {
+    match op.list_with(dir).recursive(true).await {
+        Ok(entries) => entries
+            .iter()
+            .filter(|e| !e.metadata().is_dir())
+            .map(|e| e.path().to_string())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+async fn push(op: &Operator, src: &std::path::Path, state: &StateCache) -> PushTreeStats {
+    push_tree_with_stats(op, src, PREFIX, state, None, "dev-a", None, None, 1, None)
+        .await
+        .expect("push_tree")
+}
+
+#[tokio::test]
+async fn hundred_tiny_files_share_one_pack() {
+    let tmp = TempDir::new().unwrap();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish();
+    let layout = RemoteLayout::new(PREFIX);
+
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(src.join("nested")).unwrap();
+    for i in 0..100 {
+        let dir = if i % 2 == 0 {
+            src.clone()
+        } else {
+            src.join("nested")
+        };
+        std::fs::write(dir.join(format!("f{i:03}.txt")), content(i)).unwrap();
+    }
+    let state = StateCache::open(&tmp.path().join("state.db")).unwrap();
+    state.set_pack_threshold(Some(4096));
+
+    let stats = push(&op, &src, &state).await;
+    assert_eq!(stats.uploaded, 100);
+    let root_hash = stats.root_hash.expect("complete push writes a tree");
+
+    // One pack plus its index; nothing stored per file but the index entry
+    let packs = keys_under(&op, &layout.pack_key("")).await;
+    let pack_objects: Vec<_> = packs.iter().filter(|k| !k.ends_with(".idx")).collect();
+    assert_eq!(pack_objects.len(), 1, "{packs:?}");
+    assert_eq!(packs.len(), 2, "{packs:?}");
+    assert!(keys_under(&op, &layout.manifests_dir()).await.is_empty());
+    assert!(keys_under(&op, &layout.chunks_dir()).await.is_empty());
+
+    let id = pack_objects[0].rsplit('/').next().unwrap();
+    let pack_index = tcfs_sync::pack::read_pack_index(&op, PREFIX, id)
+        .await
+        .unwrap();
+    assert_eq!(pack_index.files.len(), 100);
+    assert_eq!(pack_index.written_by, "dev-a");
+
+    // Index entries point at the same ranges the pack index lists
+    let entry_key = layout.index_key("nested/f037.txt");
+    let entry = IndexEntry::from_bytes(&op.read(&entry_key).await.unwrap().to_bytes()).unwrap();
+    let pack = entry.pack.clone().expect("packed entry");
+    let slot = &pack_index.files["nested/f037.txt"];
+    assert_eq!(
+        (pack.id.as_str(), pack.offset, pack.len),
+        (id, slot.offset, slot.len)
+    );
+    assert_eq!(entry.size, content(37).len() as u64);
+
+    // A single file is read by range
+    let data =
+        tcfs_sync::pack::read_packed(&op, PREFIX, "nested/f037.txt", &entry.manifest_hash, &pack)
+            .await
+            .unwrap();
+    assert_eq!(data, content(37));
+
+    // Several files pulled by pattern
+    let out = tmp.path().join("pulled");
+    let (files, _) = tcfs_sync::engine::pull_matching(
+        &op,
+        PREFIX,
+        "f04*.txt",
+        &out,
+        None,
+        tcfs_sync::engine::DEFAULT_MODE_UMASK,
+    )
+    .await
+    .unwrap();
+    assert_eq!(files, 10);
+    for i in 40..50 {
+        let rel = if i % 2 == 0 {
+            format!("f{i:03}.txt")
+        } else {
+            format!("nested/f{i:03}.txt")
+        };
+        assert_eq!(std::fs::read(out.join(rel)).unwrap(), content(i));
+    }
+
+    // The tree snapshot restores every packed file
+    let restored = tmp.path().join("restored");
+    let pulled = tcfs_sync::tree::pull_tree(&op, PREFIX, &root_hash, &restored, None)
+        .await
+        .unwrap();
+    assert_eq!(pulled.files, 100);
+    assert_eq!(
+        std::fs::read(restored.join("f000.txt")).unwrap(),
+        content(0)
+    );
+    assert_eq!(
+        std::fs::read(restored.join("nested/f099.txt")).unwrap(),
+        content(99)
+    );
+
+    // Pushing again writes nothing and names the same tree
+    let again = push(&op, &src, &state).await;
+    assert_eq!((again.uploaded, again.skipped), (0, 100));
+    assert_eq!(again.root_hash.as_deref(), Some(root_hash.as_str()));
+    assert_eq!(keys_under(&op, &layout.pack_key("")).await.len(), 2);
+}
+
+#[tokio::test]
+async fn corrupt_pack_range_is_refused() {
+    let tmp = TempDir::new().unwrap();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish();
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::write(src.join("a.txt"), b"alpha").unwrap();
+    std::fs::write(src.join("b.txt"), b"bravo").unwrap();
+    let state = StateCache::open(&tmp.path().join("state.db")).unwrap();
+    state.set_pack_threshold(Some(4096));
+    push(&op, &src, &state).await;
+
+    // Shift b.txt's range onto a.txt's bytes
+    let layout = RemoteLayout::new(PREFIX);
+    let key = layout.index_key("b.txt");
+    let mut entry = IndexEntry::from_bytes(&op.read(&key).await.unwrap().to_bytes()).unwrap();
+    entry.pack.as_mut().unwrap().offset = 0;
+    op.write(&key, entry.to_bytes()).await.unwrap();
+
+    let err = tcfs_sync::pack::pull_packed(
+        &op,
+        PREFIX,
+        "b.txt",
+        &entry,
+        &tmp.path().join("b.txt"),
+        0o022,
+    )
+    .await
+    .expect_err("mismatched range");
+    assert!(err
+        .downcast_ref::<tcfs_sync::pack::PackedHashMismatch>()
+        .is_some());
+    assert!(!tmp.path().join("b.txt").exists());
+}
+
+#[tokio::test]
+async fn paths_tracked_from_another_device_are_not_packed() {
+    let tmp = TempDir::new().unwrap();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish();
+    let layout = RemoteLayout::new(PREFIX);
+
+    // Device B pushes shared.txt as chunks
+    let src_b = tmp.path().join("b");
+    std::fs::create_dir_all(&src_b).unwrap();
+    std::fs::write(src_b.join("shared.txt"), b"from device b").unwrap();
+    let state_b = StateCache::open(&tmp.path().join("b.db")).unwrap();
+    push_tree_with_stats(
+        &op, &src_b, PREFIX, &state_b, None, "dev-b", None, None, 1, None,
+    )
+    .await
+    .unwrap();
+
+    // Device A, which never synced it, packs its own copy alongside a new file
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::write(src.join("shared.txt"), b"from device a").unwrap();
+    std::fs::write(src.join("own.txt"), b"only on a").unwrap();
+    let state = StateCache::open(&tmp.path().join("state.db")).unwrap();
+    state.set_pack_threshold(Some(4096));
+    let stats = push(&op, &src, &state).await;
+    assert_eq!(stats.uploaded, 1);
+    assert_eq!(stats.conflicts, 1);
+
+    let own = IndexEntry::from_bytes(
+        &op.read(&layout.index_key("own.txt"))
+            .await
+            .unwrap()
+            .to_bytes(),
+    )
+    .unwrap();
+    let pack = own.pack.expect("own.txt packed");
+    let index = tcfs_sync::pack::read_pack_index(&op, PREFIX, &pack.id)
+        .await
+        .unwrap();
+    assert!(!index.files.contains_key("shared.txt"));
+
+    // shared.txt went through the chunked path, which found device B's
+    // version and left it in place as a conflict
+    let key = layout.index_key("shared.txt");
+    let entry = IndexEntry::from_bytes(&op.read(&key).await.unwrap().to_bytes()).unwrap();
+    assert!(!entry.is_packed());
+    let manifest = tcfs_sync::manifest::SyncManifest::from_bytes(
+        &op.read(&layout.manifest_key(&entry.manifest_hash))
+            .await
+            .unwrap()
+            .to_bytes(),
+    )
+    .unwrap();
+    assert_eq!(manifest.written_by, "dev-b");
+}
@@ -0,0 +1,84 @@
+//! Integration test: `prefix_stats` over a synthetic prefix
+//!
+//! Three manifests share chunks of known stored sizes, one referencing a
+//! chunk that was never stored: the report must count each distinct chunk
+//! once for the average and median, every reference for the dedup ratio,
+//! and list the largest files first.
+
+use opendal::Operator;
+use tcfs_core::layout::RemoteLayout;
+use tcfs_sync::engine::prefix_stats;
+use tcfs_sync::manifest::SyncManifest;
+
+const PREFIX: &str = "test/stats";
+
+fn manifest(file_hash: &str, rel_path: &str, file_size: u64, chunks: &[&str]) -> SyncManifest {
+    SyncManifest {
+        version: 2,
+        file_hash: file_hash.to_string(),
+        file_size,
+        chunks: chunks.iter().map(|c| c.to_string()).collect(),
+        vclock: Default::default(),
+        written_by: "dev-a".into(),
+        written_at: 0,
+        rel_path: Some(rel_path.to_string()),
+        encrypted_file_key: None,
+        mode: None,
+        compressed: Vec::new(),
+        chunk_keys: Vec::new(),
+        chunk_shard_depth: 0,
+        signature: None,
+        manifest_checksum: None,
+    }
+}
+
+#[tokio::test]
+async fn stats_average_and_dedup_over_synthetic_manifests() {
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish();
+    let layout = RemoteLayout::new(PREFIX);
+
+    // Stored chunks a, b, c; "d" is referenced but missing
+    for (hash, len) in [("a", 1000), ("b", 3000), ("c", 8000)] {
+        op.write(&layout.chunk_key(hash), vec![0u8; len])
+            .await
+            .unwrap();
+    }
+    for m in [
+        manifest("f1", "one.bin", 4000, &["a", "b"]),
+        manifest("f2", "two.bin", 12000, &["a", "b", "c"]),
+        manifest("f3", "three.bin", 1500, &["a", "d"]),
+    ] {
+        op.write(&layout.manifest_key(&m.file_hash), m.to_bytes().unwrap())
+            .await
+            .unwrap();
+    }
+
+    let stats = prefix_stats(&op, PREFIX, 2).await.unwrap();
+    assert_eq!(stats.manifests, 3);
+    assert_eq!(stats.logical_bytes, 17500);
+    assert_eq!(stats.chunk_refs, 7);
+    assert_eq!(stats.unique_chunks, 3);
+    assert_eq!(stats.missing_chunks, 1);
+    // a three times, b twice, c once
+    assert_eq!(stats.referenced_bytes, 3 * 1000 + 2 * 3000 + 8000);
+    assert_eq!(stats.unique_bytes, 12000);
+    assert_eq!(stats.average_chunk(), 4000);
+    assert_eq!(stats.median_chunk, 3000);
+    assert!((stats.dedup_ratio() - 17000.0 / 12000.0).abs() < 1e-9);
+    assert_eq!(stats.histogram, [(1024, 1), (4096, 1), (8192, 1)]);
+    assert_eq!(
+        stats.largest,
+        [
+            ("two.bin".to_string(), 12000),
+            ("one.bin".to_string(), 4000)
+        ]
+    );
+
+    // Nothing stored: no chunks, no dedup
+    let empty = prefix_stats(&op, "test/empty", 10).await.unwrap();
+    assert_eq!(empty.manifests, 0);
+    assert_eq!(empty.average_chunk(), 0);
+    assert_eq!(empty.dedup_ratio(), 1.0);
+}
@@ -0,0 +1,96 @@
+//! Integration test: `pull_tree_since` mirrors only what changed
+//!
+//! A device pushes a tree, then an hour later rewrites one file and adds
+//! another. Pulling since a time between the two pushes must download just
+//! those two files; pulling since before the first push gets everything.
+
+use opendal::Operator;
+use std::sync::Arc;
+use std::time::Duration;
+use tcfs_core::clock::MockClock;
+use tcfs_sync::engine::{pull_tree_since, push_tree_with_stats, DEFAULT_MODE_UMASK};
+use tcfs_sync::state::StateCache;
+use tempfile::TempDir;
+
+const PREFIX: &str = "test/since";
+const FIRST_PUSH: u64 = 1_700_000_000;
+
+#[tokio::test]
+async fn since_pulls_only_files_written_after_it() {
+    let tmp = TempDir::new().unwrap();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish();
+
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(src.join("docs")).unwrap();
+    std::fs::write(src.join("old.txt"), "untouched").unwrap();
+    std::fs::write(src.join("edited.txt"), "v1").unwrap();
+    std::fs::write(src.join("docs/readme.md"), "also untouched").unwrap();
+
+    let clock = Arc::new(MockClock::new(FIRST_PUSH));
+    let mut state = StateCache::open(&tmp.path().join("state.json")).unwrap();
+    state.set_clock(clock.clone());
+    let push = || {
+        push_tree_with_stats(
+            &op, &src, PREFIX, &state, None, "dev-a", None, None, 1, None,
+        )
+    };
+    push().await.unwrap();
+
+    // An hour later: one file rewritten (new length, so the same mtime
+    // second still reads as a change) and one added
+    clock.advance(Duration::from_secs(3600));
+    std::fs::write(src.join("edited.txt"), "v2, longer").unwrap();
+    std::fs::write(src.join("docs/new.md"), "fresh").unwrap();
+    push().await.unwrap();
+
+    let mirror = tmp.path().join("mirror");
+    let (files, _, bytes) = pull_tree_since(
+        &op,
+        PREFIX,
+        &mirror,
+        FIRST_PUSH + 60,
+        None,
+        DEFAULT_MODE_UMASK,
+    )
+    .await
+    .unwrap();
+    assert_eq!(files, 2);
+    assert_eq!(bytes, ("v2, longer".len() + "fresh".len()) as u64);
+    assert_eq!(
+        std::fs::read_to_string(mirror.join("edited.txt")).unwrap(),
+        "v2, longer"
+    );
+    assert_eq!(
+        std::fs::read_to_string(mirror.join("docs/new.md")).unwrap(),
+        "fresh"
+    );
+    assert!(!mirror.join("old.txt").exists());
+    assert!(!mirror.join("docs/readme.md").exists());
+
+    // Nothing written after the second push
+    let (files, _, _) = pull_tree_since(
+        &op,
+        PREFIX,
+        &mirror,
+        FIRST_PUSH + 3600,
+        None,
+        DEFAULT_MODE_UMASK,
+    )
+    .await
+    .unwrap();
+    assert_eq!(files, 0);
+
+    // Since before the first push: the whole tree
+    let full = tmp.path().join("full");
+    let (files, _, _) =
+        pull_tree_since(&op, PREFIX, &full, FIRST_PUSH - 1, None, DEFAULT_MODE_UMASK)
+            .await
+            .unwrap();
+    assert_eq!(files, 4);
+    assert_eq!(
+        std::fs::read_to_string(full.join("old.txt")).unwrap(),
+        "untouched"
+    );
+}
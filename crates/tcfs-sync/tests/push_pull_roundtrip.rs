@@ -4,6 +4,8 @@
 //! verify integrity → reassemble → byte-equal output. Uses OpenDAL's
 //! in-memory backend so no live SeaweedFS is required.
 
+mod common;
+
 use opendal::Operator;
 use std::path::Path;
 use tempfile::TempDir;
@@ -30,10 +32,10 @@ async fn roundtrip_small_file() {
     let src = write_test_file(tmp.path(), "small.txt", original);
     let dst = tmp.path().join("output/small.txt");
 
-    let mut state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
 
     // Push
-    let upload = tcfs_sync::engine::upload_file(&op, &src, prefix, &mut state, None)
+    let upload = tcfs_sync::engine::upload_file(&op, &src, prefix, &state, None)
         .await
         .expect("upload should succeed");
 
@@ -66,9 +68,9 @@ async fn roundtrip_binary_data() {
     let src = write_test_file(tmp.path(), "binary.bin", &original);
     let dst = tmp.path().join("output/binary.bin");
 
-    let mut state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
 
-    let upload = tcfs_sync::engine::upload_file(&op, &src, prefix, &mut state, None)
+    let upload = tcfs_sync::engine::upload_file(&op, &src, prefix, &state, None)
         .await
         .expect("upload binary");
 
@@ -97,16 +99,16 @@ async fn roundtrip_dedup_skips_rechunk() {
     let original = b"deduplicated content test";
     let src = write_test_file(tmp.path(), "dedup.txt", original);
 
-    let mut state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
 
     // First upload
-    let first = tcfs_sync::engine::upload_file(&op, &src, prefix, &mut state, None)
+    let first = tcfs_sync::engine::upload_file(&op, &src, prefix, &state, None)
         .await
         .expect("first upload");
     assert!(!first.skipped);
 
     // Second upload of same file should be skipped (state cache hit)
-    let second = tcfs_sync::engine::upload_file(&op, &src, prefix, &mut state, None)
+    let second = tcfs_sync::engine::upload_file(&op, &src, prefix, &state, None)
         .await
         .expect("second upload");
     assert!(second.skipped, "unchanged file should be skipped");
@@ -121,9 +123,9 @@ async fn roundtrip_integrity_verification() {
     // Upload a file
     let original = b"integrity verification test data";
     let src = write_test_file(tmp.path(), "verify.txt", original);
-    let mut state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
 
-    let upload = tcfs_sync::engine::upload_file(&op, &src, prefix, &mut state, None)
+    let upload = tcfs_sync::engine::upload_file(&op, &src, prefix, &state, None)
         .await
         .expect("upload");
 
@@ -155,6 +157,476 @@ async fn roundtrip_integrity_verification() {
     );
 }
 
+#[tokio::test]
+async fn scrambled_chunk_order_fails_file_hash() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/scrambled";
+
+    let original: Vec<u8> = (0u64..1048576)
+        .map(|i| (i.wrapping_mul(13) ^ (i >> 5)) as u8)
+        .collect();
+    let src = write_test_file(tmp.path(), "large.bin", &original);
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+
+    let upload = tcfs_sync::engine::upload_file(&op, &src, prefix, &state, None)
+        .await
+        .expect("upload");
+    assert!(upload.chunks >= 2, "need several chunks to scramble");
+
+    // Every chunk still verifies individually, only the order is wrong
+    let manifest_bytes = op.read(&upload.remote_path).await.unwrap();
+    let mut manifest =
+        tcfs_sync::manifest::SyncManifest::from_bytes(&manifest_bytes.to_bytes()).unwrap();
+    manifest.chunks.reverse();
+    op.write(&upload.remote_path, manifest.to_bytes().unwrap())
+        .await
+        .unwrap();
+
+    let dst = tmp.path().join("output/large.bin");
+    let err = tcfs_sync::engine::download_file(&op, &upload.remote_path, &dst, prefix, None)
+        .await
+        .unwrap_err();
+
+    let mismatch = err
+        .downcast_ref::<tcfs_sync::engine::FileHashMismatch>()
+        .expect("FileHashMismatch");
+    assert_eq!(mismatch.expected, upload.hash);
+    assert_ne!(mismatch.actual, upload.hash);
+    assert!(!dst.exists(), "corrupt content must not be written");
+}
+
+#[tokio::test]
+async fn compressible_chunks_are_stored_compressed() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/zstd";
+
+    let original = b"the same log line, over and over again\n".repeat(20_000);
+    let src = write_test_file(tmp.path(), "app.log", &original);
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+
+    let upload = tcfs_sync::engine::upload_file(&op, &src, prefix, &state, None)
+        .await
+        .expect("upload");
+
+    let manifest_bytes = op.read(&upload.remote_path).await.unwrap();
+    let manifest =
+        tcfs_sync::manifest::SyncManifest::from_bytes(&manifest_bytes.to_bytes()).unwrap();
+    assert!((0..manifest.chunks.len()).all(|i| manifest.chunk_compressed(i)));
+
+    let mut stored = 0usize;
+    for hash in manifest.chunk_hashes() {
+        stored += op
+            .read(&format!("{prefix}/chunks/{hash}"))
+            .await
+            .unwrap()
+            .len();
+    }
+    assert!(
+        stored < original.len() / 10,
+        "stored {stored} bytes for {} plaintext bytes",
+        original.len()
+    );
+
+    let dst = tmp.path().join("output/app.log");
+    tcfs_sync::engine::download_file(&op, &upload.remote_path, &dst, prefix, None)
+        .await
+        .expect("download");
+    assert_eq!(std::fs::read(&dst).unwrap(), original);
+}
+
+#[tokio::test]
+async fn incompressible_chunks_are_stored_raw() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/zstd-raw";
+
+    let mut x: u64 = 0x2545_f491_4f6c_dd1d;
+    let original: Vec<u8> = (0..256 * 1024)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x as u8
+        })
+        .collect();
+    let src = write_test_file(tmp.path(), "noise.bin", &original);
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+
+    let upload = tcfs_sync::engine::upload_file(&op, &src, prefix, &state, None)
+        .await
+        .expect("upload");
+
+    let manifest_bytes = op.read(&upload.remote_path).await.unwrap();
+    let manifest =
+        tcfs_sync::manifest::SyncManifest::from_bytes(&manifest_bytes.to_bytes()).unwrap();
+    assert!((0..manifest.chunks.len()).all(|i| !manifest.chunk_compressed(i)));
+
+    let dst = tmp.path().join("output/noise.bin");
+    tcfs_sync::engine::download_file(&op, &upload.remote_path, &dst, prefix, None)
+        .await
+        .expect("download");
+    assert_eq!(std::fs::read(&dst).unwrap(), original);
+}
+
+/// Per-chunk compression flags of the manifest the state cache recorded for `path`.
+async fn compressed_flags(
+    op: &Operator,
+    state: &tcfs_sync::state::StateCache,
+    path: &Path,
+) -> Vec<bool> {
+    let remote = &state.get(path).expect("tracked").remote_path;
+    let bytes = op.read(remote).await.unwrap();
+    let manifest = tcfs_sync::manifest::SyncManifest::from_bytes(&bytes.to_bytes()).unwrap();
+    (0..manifest.chunks.len())
+        .map(|i| manifest.chunk_compressed(i))
+        .collect()
+}
+
+#[tokio::test]
+async fn compression_skipped_for_random_data_and_listed_extensions() {
+    use tcfs_sync::engine::should_compress;
+
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+
+    let mut x: u64 = 0x9e37_79b9_7f4a_7c15;
+    let random: Vec<u8> = (0..128 * 1024)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x as u8
+        })
+        .collect();
+    let text = b"GET /index.html 200 1532 Mozilla/5.0\n".repeat(4_000);
+    // Distinct content, so the two text files don't share a manifest
+    let other_text = b"POST /api/login 401 88 curl/8.5\n".repeat(4_000);
+
+    assert!(!should_compress(Path::new("random.dat"), &random));
+    assert!(should_compress(Path::new("access.log"), &text));
+    assert!(!should_compress(Path::new("photo.JPG"), &text));
+
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    write_test_file(&src, "random.dat", &random);
+    write_test_file(&src, "access.log", &text);
+    write_test_file(&src, "archive.log.gz", &other_text);
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    tcfs_sync::engine::push_tree(&op, &src, "test/zstd-sniff", &state, None)
+        .await
+        .expect("push_tree");
+
+    let flags = compressed_flags(&op, &state, &src.join("random.dat")).await;
+    assert!(flags.iter().all(|c| !c));
+    let flags = compressed_flags(&op, &state, &src.join("access.log")).await;
+    assert!(flags.iter().all(|c| *c));
+    // Compressible text under a known-compressed extension never reaches zstd
+    let flags = compressed_flags(&op, &state, &src.join("archive.log.gz")).await;
+    assert!(flags.iter().all(|c| !c));
+
+    // An override list replaces the built-in one
+    let config = tcfs_sync::engine::CollectConfig {
+        compress_skip_extensions: Some(vec![".log".into()]),
+        ..Default::default()
+    };
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state2.db")).unwrap();
+    tcfs_sync::engine::push_tree_with_device(
+        &op,
+        &src,
+        "test/zstd-override",
+        &state,
+        None,
+        "",
+        Some(&config),
+        None,
+        0,
+        None,
+    )
+    .await
+    .expect("push_tree with override");
+
+    let flags = compressed_flags(&op, &state, &src.join("access.log")).await;
+    assert!(flags.iter().all(|c| !c));
+    let flags = compressed_flags(&op, &state, &src.join("archive.log.gz")).await;
+    assert!(flags.iter().all(|c| *c));
+}
+
+#[tokio::test]
+async fn near_identical_copy_reports_deduped_chunks() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/dedup-stats";
+
+    let mut x: u64 = 0x1234_5678_9abc_def1;
+    let original: Vec<u8> = (0..256 * 1024)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x as u8
+        })
+        .collect();
+    let mut edited = original.clone();
+    edited.extend_from_slice(b"one more line at the end\n");
+
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    let first = write_test_file(&src, "report.dat", &original);
+    let second = write_test_file(&src, "report-copy.dat", &edited);
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+
+    let a = tcfs_sync::engine::upload_file(&op, &first, prefix, &state, None)
+        .await
+        .expect("first upload");
+    assert!(a.chunks > 2, "expected several chunks");
+    assert_eq!((a.new_chunks, a.deduped_chunks), (a.chunks, 0));
+
+    let b = tcfs_sync::engine::upload_file(&op, &second, prefix, &state, None)
+        .await
+        .expect("second upload");
+    assert_eq!(b.new_chunks + b.deduped_chunks, b.chunks);
+    assert!(
+        b.deduped_chunks > b.new_chunks,
+        "{} new, {} deduplicated",
+        b.new_chunks,
+        b.deduped_chunks
+    );
+
+    // The tree aggregate sums the per-file counts
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("tree.db")).unwrap();
+    let stats = tcfs_sync::engine::push_tree_with_stats(
+        &op,
+        &src,
+        "test/dedup-stats-tree",
+        &state,
+        None,
+        "",
+        None,
+        None,
+        1,
+        None,
+    )
+    .await
+    .expect("push_tree_with_stats");
+    assert_eq!(stats.uploaded, 2);
+    assert_eq!(stats.new_chunks + stats.deduped_chunks, a.chunks + b.chunks);
+    assert_eq!(stats.deduped_chunks, b.deduped_chunks);
+}
+
+#[tokio::test]
+async fn small_edit_reuses_previous_version_chunks() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/delta";
+
+    let mut x: u64 = 0x0ddb_a11c_afe5_eed5;
+    let mut noise = |len: usize| -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect()
+    };
+    let original = noise(10 * 1024 * 1024);
+    let src = write_test_file(tmp.path(), "disk.dat", &original);
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+
+    let first = tcfs_sync::engine::upload_file(&op, &src, prefix, &state, None)
+        .await
+        .expect("first upload");
+    assert_eq!(first.reused_chunks, 0);
+
+    // Insert 1KB mid-file, shifting every later byte
+    let mut edited = original.clone();
+    edited.splice(5_000_000..5_000_000, noise(1024));
+    std::fs::write(&src, &edited).unwrap();
+
+    let second = tcfs_sync::engine::upload_file(&op, &src, prefix, &state, None)
+        .await
+        .expect("second upload");
+    assert!(!second.skipped);
+    assert_ne!(second.hash, first.hash);
+    assert_eq!(second.new_chunks + second.deduped_chunks, second.chunks);
+    assert!(second.new_chunks <= 4, "{} new chunks", second.new_chunks);
+    assert!(
+        second.reused_chunks * 100 >= second.chunks * 99,
+        "{} of {} chunks reused",
+        second.reused_chunks,
+        second.chunks
+    );
+
+    let dst = tmp.path().join("pulled.dat");
+    tcfs_sync::engine::download_file(&op, &second.remote_path, &dst, prefix, None)
+        .await
+        .expect("download");
+    assert_eq!(std::fs::read(&dst).unwrap(), edited);
+}
+
+#[tokio::test]
+async fn reused_chunk_missing_from_the_store_is_uploaded_again() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/delta-missing";
+    let layout = tcfs_core::layout::RemoteLayout::new(prefix);
+
+    let original = common::noise(77, 1024 * 1024);
+    let src = write_test_file(tmp.path(), "disk.dat", &original);
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let first = tcfs_sync::engine::upload_file(&op, &src, prefix, &state, None)
+        .await
+        .expect("first upload");
+    assert!(first.chunks > 1);
+
+    // The first chunk disappears from the store after the push
+    let raw = op.read(&first.remote_path).await.unwrap();
+    let manifest = tcfs_sync::manifest::SyncManifest::from_bytes(&raw.to_bytes()).unwrap();
+    let lost = layout.chunk_key(&manifest.chunks[0]);
+    op.delete(&lost).await.unwrap();
+
+    let mut edited = original.clone();
+    edited.extend_from_slice(b"appended");
+    std::fs::write(&src, &edited).unwrap();
+    let second = tcfs_sync::engine::upload_file(&op, &src, prefix, &state, None)
+        .await
+        .expect("second upload");
+    assert!(op.exists(&lost).await.unwrap(), "lost chunk re-uploaded");
+    // The lost chunk and the edited tail; neither counts as reused
+    assert!(second.new_chunks >= 2, "{} new chunks", second.new_chunks);
+    assert!(second.reused_chunks <= second.chunks - 2);
+
+    let dst = tmp.path().join("pulled.dat");
+    tcfs_sync::engine::download_file(&op, &second.remote_path, &dst, prefix, None)
+        .await
+        .expect("download");
+    assert_eq!(std::fs::read(&dst).unwrap(), edited);
+}
+
+#[tokio::test]
+async fn read_only_store_refuses_push_without_writing() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/templates";
+
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    let file = write_test_file(&src, "template.txt", b"do not overwrite me");
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    state.set_read_only(true);
+
+    let err = tcfs_sync::engine::upload_file(&op, &file, prefix, &state, None)
+        .await
+        .expect_err("upload to a read-only store");
+    assert!(err.is::<tcfs_sync::engine::ReadOnlyStore>(), "{err:#}");
+
+    let err = tcfs_sync::engine::push_tree(&op, &src, prefix, &state, None)
+        .await
+        .expect_err("tree push to a read-only store");
+    assert!(err.is::<tcfs_sync::engine::ReadOnlyStore>(), "{err:#}");
+
+    let written = op.list_with("").recursive(true).await.unwrap();
+    assert!(written.is_empty(), "nothing may be written: {written:?}");
+    assert!(state.get(&file).is_none());
+}
+
+#[tokio::test]
+async fn push_over_quota_is_rejected() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/quota";
+    let quota = 4096;
+
+    let small = write_test_file(tmp.path(), "small.txt", b"fits comfortably\n");
+    let mut x = 0x1234_5678_9abc_def1u64;
+    let noise: Vec<u8> = (0..64 * 1024)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x as u8
+        })
+        .collect();
+    let big = write_test_file(tmp.path(), "big.bin", &noise);
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    state.set_quota(Some(quota));
+
+    tcfs_sync::engine::upload_file(&op, &small, prefix, &state, None)
+        .await
+        .expect("push under quota");
+    let used = tcfs_sync::engine::prefix_usage(&op, prefix).await.unwrap();
+    assert!(used > 0 && used <= quota);
+    assert_eq!(
+        state.cached_usage(prefix),
+        Some(used),
+        "usage kept incrementally"
+    );
+
+    let err = tcfs_sync::engine::upload_file(&op, &big, prefix, &state, None)
+        .await
+        .expect_err("push over quota");
+    let exceeded = err
+        .downcast_ref::<tcfs_sync::engine::QuotaExceeded>()
+        .unwrap_or_else(|| panic!("{err:#}"));
+    assert_eq!(exceeded.quota, quota);
+    assert!(state.get(&big).is_none());
+    assert!(tcfs_sync::engine::prefix_usage(&op, prefix).await.unwrap() <= quota);
+}
+
+#[tokio::test]
+async fn sharded_chunks_land_under_first_byte_subdir() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/sharded";
+
+    let original = b"sharded chunk store contents\n".repeat(64);
+    let src = write_test_file(tmp.path(), "sharded.txt", &original);
+    let dst = tmp.path().join("output/sharded.txt");
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    state.set_chunk_shard_depth(1);
+    let upload = tcfs_sync::engine::upload_file(&op, &src, prefix, &state, None)
+        .await
+        .expect("upload should succeed");
+
+    let manifest_bytes = op.read(&upload.remote_path).await.unwrap().to_bytes();
+    let manifest = tcfs_sync::manifest::SyncManifest::from_bytes(&manifest_bytes).unwrap();
+    assert_eq!(manifest.chunk_shard_depth, 1);
+    let hashes = manifest.chunk_hashes();
+    for hash in hashes {
+        let key = format!("{prefix}/chunks/{}/{hash}", &hash[..2]);
+        assert!(op.exists(&key).await.unwrap(), "missing {key}");
+        assert!(!op.exists(&format!("{prefix}/chunks/{hash}")).await.unwrap());
+    }
+
+    // GC sees the sharded chunks, and usage counts them
+    let mut stored = tcfs_sync::engine::list_chunks(&op, prefix).await.unwrap();
+    stored.sort();
+    let mut expected = hashes.to_vec();
+    expected.sort();
+    expected.dedup();
+    assert_eq!(stored, expected);
+    assert!(
+        tcfs_sync::engine::prefix_usage(&op, prefix).await.unwrap() > manifest_bytes.len() as u64
+    );
+
+    // Readers follow the depth recorded in the manifest
+    tcfs_sync::engine::download_file(&op, &upload.remote_path, &dst, prefix, None)
+        .await
+        .expect("download should succeed");
+    assert_eq!(std::fs::read(&dst).unwrap(), original);
+    assert!(tcfs_sync::engine::verify_prefix(&op, prefix)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
 #[tokio::test]
 async fn roundtrip_large_file_many_chunks() {
     let tmp = TempDir::new().unwrap();
@@ -168,9 +640,9 @@ async fn roundtrip_large_file_many_chunks() {
     let src = write_test_file(tmp.path(), "large.bin", &original);
     let dst = tmp.path().join("output/large.bin");
 
-    let mut state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
 
-    let upload = tcfs_sync::engine::upload_file(&op, &src, prefix, &mut state, None)
+    let upload = tcfs_sync::engine::upload_file(&op, &src, prefix, &state, None)
         .await
         .expect("upload large");
 
@@ -202,10 +674,10 @@ async fn roundtrip_push_tree() {
     write_test_file(&src_dir, "b.txt", b"file b content");
     write_test_file(&src_dir.join("subdir"), "c.txt", b"file c in subdir");
 
-    let mut state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
 
     let (uploaded, skipped, _bytes) =
-        tcfs_sync::engine::push_tree(&op, &src_dir, prefix, &mut state, None)
+        tcfs_sync::engine::push_tree(&op, &src_dir, prefix, &state, None)
             .await
             .expect("push_tree");
 
@@ -214,7 +686,7 @@ async fn roundtrip_push_tree() {
 
     // Push again — should skip all
     let (uploaded2, skipped2, _) =
-        tcfs_sync::engine::push_tree(&op, &src_dir, prefix, &mut state, None)
+        tcfs_sync::engine::push_tree(&op, &src_dir, prefix, &state, None)
             .await
             .expect("push_tree second");
 
@@ -233,18 +705,19 @@ async fn roundtrip_with_device_identity() {
     let src = write_test_file(tmp.path(), "device.txt", original);
     let dst = tmp.path().join("output/device.txt");
 
-    let mut state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
 
     // Upload with device identity
     let upload = tcfs_sync::engine::upload_file_with_device(
         &op,
         &src,
         prefix,
-        &mut state,
+        &state,
         None,
         device_id,
         Some("device.txt"),
         None,
+        false,
     )
     .await
     .expect("upload with device");
@@ -259,8 +732,9 @@ async fn roundtrip_with_device_identity() {
         prefix,
         None,
         device_id,
-        Some(&mut state),
+        Some(&state),
         None,
+        tcfs_sync::engine::RestoreMode::default(),
     )
     .await
     .expect("download with device");
@@ -276,3 +750,858 @@ async fn roundtrip_with_device_identity() {
         "vclock should be non-empty after device-aware sync"
     );
 }
+
+#[tokio::test]
+async fn dry_run_writes_nothing() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/dryrun";
+
+    let original = b"dry run content that must never reach storage";
+    let src = write_test_file(tmp.path(), "dry.txt", original);
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+
+    let result = tcfs_sync::engine::upload_file_with_device(
+        &op,
+        &src,
+        prefix,
+        &state,
+        None,
+        "test-device",
+        Some("dry.txt"),
+        None,
+        true,
+    )
+    .await
+    .expect("dry run upload");
+
+    assert!(result.dry_run);
+    assert!(!result.skipped);
+    assert_eq!(result.bytes, original.len() as u64);
+    assert_eq!(
+        result.new_chunks, result.chunks,
+        "empty backend: all chunks new"
+    );
+
+    let objects = op
+        .list_with("/")
+        .recursive(true)
+        .await
+        .expect("list backend");
+    assert!(
+        objects.iter().all(|e| e.metadata().is_dir()),
+        "dry run must not write any objects, found: {:?}",
+        objects.iter().map(|e| e.path()).collect::<Vec<_>>()
+    );
+    assert!(state.get(&src).is_none(), "dry run must not touch state");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn roundtrip_preserves_executable_mode() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/mode";
+
+    let src = write_test_file(tmp.path(), "build.sh", b"#!/bin/sh\necho built\n");
+    std::fs::set_permissions(&src, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let dst = tmp.path().join("output/build.sh");
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let upload = tcfs_sync::engine::upload_file(&op, &src, prefix, &state, None)
+        .await
+        .expect("upload");
+
+    let manifest = op.read(&upload.remote_path).await.unwrap();
+    let manifest = tcfs_sync::manifest::SyncManifest::from_bytes(&manifest.to_bytes()).unwrap();
+    assert_eq!(manifest.mode, Some(0o755));
+
+    tcfs_sync::engine::download_file(&op, &upload.remote_path, &dst, prefix, None)
+        .await
+        .expect("download");
+    let mode = std::fs::metadata(&dst).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode, 0o755, "pulled script must stay executable");
+
+    // A stricter umask caps the restored mode
+    let strict = tmp.path().join("output/strict.sh");
+    tcfs_sync::engine::download_file_with_device(
+        &op,
+        &upload.remote_path,
+        &strict,
+        prefix,
+        None,
+        "",
+        None,
+        None,
+        tcfs_sync::engine::RestoreMode::manifest(0o077),
+    )
+    .await
+    .expect("download with umask");
+    let mode = std::fs::metadata(&strict).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode, 0o700);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn pull_tree_restores_modes_per_path() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/path-modes";
+
+    // Identical bytes share one manifest, which records only one mode
+    let src_dir = tmp.path().join("src");
+    std::fs::create_dir_all(&src_dir).unwrap();
+    let script = write_test_file(&src_dir, "run.sh", b"echo same bytes\n");
+    let notes = write_test_file(&src_dir, "notes.txt", b"echo same bytes\n");
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::fs::set_permissions(&notes, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    tcfs_sync::engine::push_tree(&op, &src_dir, prefix, &state, None)
+        .await
+        .expect("push_tree");
+
+    let mode = |p: &std::path::Path| std::fs::metadata(p).unwrap().permissions().mode() & 0o777;
+    let dst = tmp.path().join("dst");
+    tcfs_sync::engine::pull_tree(
+        &op,
+        prefix,
+        &dst,
+        None,
+        tcfs_sync::engine::DEFAULT_MODE_UMASK,
+    )
+    .await
+    .expect("pull_tree");
+    assert_eq!(mode(&dst.join("run.sh")), 0o755);
+    assert_eq!(mode(&dst.join("notes.txt")), 0o644);
+
+    // The configured umask is honoured rather than the default
+    let strict = tmp.path().join("strict");
+    tcfs_sync::engine::pull_tree(&op, prefix, &strict, None, 0o077)
+        .await
+        .expect("pull_tree with umask");
+    assert_eq!(mode(&strict.join("run.sh")), 0o700);
+    assert_eq!(mode(&strict.join("notes.txt")), 0o600);
+}
+
+#[tokio::test]
+async fn pull_tree_reproduces_empty_dirs() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/dirs";
+
+    let src_dir = tmp.path().join("src");
+    std::fs::create_dir_all(src_dir.join("logs")).unwrap();
+    std::fs::create_dir_all(src_dir.join("cache/tmp")).unwrap();
+    std::fs::create_dir_all(src_dir.join("docs")).unwrap();
+    write_test_file(&src_dir.join("docs"), "readme.md", b"# docs");
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    tcfs_sync::engine::push_tree(&op, &src_dir, prefix, &state, None)
+        .await
+        .expect("push_tree");
+
+    // Only leaf directories with nothing in them get markers
+    assert!(op
+        .exists(&format!("{prefix}/index/logs/.tcfsdir"))
+        .await
+        .unwrap());
+    assert!(op
+        .exists(&format!("{prefix}/index/cache/tmp/.tcfsdir"))
+        .await
+        .unwrap());
+    assert!(!op
+        .exists(&format!("{prefix}/index/docs/.tcfsdir"))
+        .await
+        .unwrap());
+
+    // A deleted file's tombstone is not pulled
+    let tombstone = tcfs_core::index::IndexEntry::new_tombstone(Some(1_700_000_000));
+    op.write(&format!("{prefix}/index/docs/old.md"), tombstone.to_bytes())
+        .await
+        .unwrap();
+
+    let dst_dir = tmp.path().join("dst");
+    let (files, dirs, _bytes) = tcfs_sync::engine::pull_tree(
+        &op,
+        prefix,
+        &dst_dir,
+        None,
+        tcfs_sync::engine::DEFAULT_MODE_UMASK,
+    )
+    .await
+    .expect("pull_tree");
+
+    assert_eq!(files, 1);
+    assert_eq!(dirs, 2);
+    assert!(dst_dir.join("logs").is_dir());
+    assert_eq!(std::fs::read_dir(dst_dir.join("logs")).unwrap().count(), 0);
+    assert!(dst_dir.join("cache/tmp").is_dir());
+    assert_eq!(
+        std::fs::read(dst_dir.join("docs/readme.md")).unwrap(),
+        b"# docs"
+    );
+    assert!(!dst_dir.join("docs/old.md").exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn pull_tree_never_writes_through_hostile_symlinks() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/hostile";
+    let outside = tmp.path().join("outside");
+    std::fs::create_dir_all(&outside).unwrap();
+
+    let src_dir = tmp.path().join("src");
+    std::fs::create_dir_all(src_dir.join("z")).unwrap();
+    write_test_file(&src_dir.join("z"), "passwd", b"root::0:0::/:/bin/sh");
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    tcfs_sync::engine::push_tree(&op, &src_dir, prefix, &state, None)
+        .await
+        .expect("push_tree");
+    let file_entry = op
+        .read(&format!("{prefix}/index/z/passwd"))
+        .await
+        .unwrap()
+        .to_vec();
+
+    // `a` -> an absolute directory, then `a/passwd`, which sorts after it;
+    // `b` -> a relative escape, then `b/passwd`. The absolute target stands
+    // in for `/etc`
+    let hostile = [
+        ("a", outside.to_str().unwrap().to_string()),
+        ("b", "../outside".to_string()),
+    ];
+    for (name, target) in &hostile {
+        let link = tcfs_core::index::IndexEntry::new_symlink(target, None);
+        op.write(&format!("{prefix}/index/{name}"), link.to_bytes())
+            .await
+            .unwrap();
+        op.write(&format!("{prefix}/index/{name}/passwd"), file_entry.clone())
+            .await
+            .unwrap();
+    }
+
+    let dst_dir = tmp.path().join("dst");
+    tcfs_sync::engine::pull_tree(
+        &op,
+        prefix,
+        &dst_dir,
+        None,
+        tcfs_sync::engine::DEFAULT_MODE_UMASK,
+    )
+    .await
+    .expect("pull_tree");
+    assert_eq!(std::fs::read_dir(&outside).unwrap().count(), 0);
+    for (name, _) in &hostile {
+        let meta = std::fs::symlink_metadata(dst_dir.join(name)).unwrap();
+        assert!(meta.is_dir(), "{name} restored as a symlink");
+    }
+
+    // A symlink already in the local tree is not written through either
+    let dst_dir = tmp.path().join("dst2");
+    std::fs::create_dir_all(&dst_dir).unwrap();
+    std::os::unix::fs::symlink(&outside, dst_dir.join("a")).unwrap();
+    tcfs_sync::engine::pull_tree(
+        &op,
+        prefix,
+        &dst_dir,
+        None,
+        tcfs_sync::engine::DEFAULT_MODE_UMASK,
+    )
+    .await
+    .expect("pull_tree");
+    assert_eq!(std::fs::read_dir(&outside).unwrap().count(), 0);
+    assert!(!dst_dir.join("b").is_symlink());
+}
+
+#[tokio::test]
+async fn pull_matching_fetches_only_matching_files() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/glob";
+
+    let src_dir = tmp.path().join("src");
+    for dir in ["src/net", "docs", "empty"] {
+        std::fs::create_dir_all(src_dir.join(dir)).unwrap();
+    }
+    write_test_file(&src_dir, "build.rs", b"fn main() {}");
+    write_test_file(&src_dir.join("src"), "lib.rs", b"pub mod net;");
+    write_test_file(&src_dir.join("src/net"), "mod.rs", b"// net");
+    write_test_file(&src_dir.join("src"), "notes.txt", b"not rust");
+    write_test_file(&src_dir.join("docs"), "guide.md", b"# guide");
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    tcfs_sync::engine::push_tree(&op, &src_dir, prefix, &state, None)
+        .await
+        .expect("push_tree");
+
+    // A bare name pattern matches at any depth
+    let dst = tmp.path().join("all-rs");
+    let (files, bytes) = tcfs_sync::engine::pull_matching(
+        &op,
+        prefix,
+        "*.rs",
+        &dst,
+        None,
+        tcfs_sync::engine::DEFAULT_MODE_UMASK,
+    )
+    .await
+    .expect("pull_matching");
+    assert_eq!(files, 3);
+    assert_eq!(bytes, (12 + 12 + 6) as u64);
+    assert_eq!(
+        std::fs::read(dst.join("build.rs")).unwrap(),
+        b"fn main() {}"
+    );
+    assert_eq!(
+        std::fs::read(dst.join("src/lib.rs")).unwrap(),
+        b"pub mod net;"
+    );
+    assert_eq!(
+        std::fs::read(dst.join("src/net/mod.rs")).unwrap(),
+        b"// net"
+    );
+    assert!(!dst.join("src/notes.txt").exists());
+    assert!(!dst.join("docs").exists());
+    assert!(!dst.join("empty").exists());
+
+    // A path pattern is anchored at the prefix root
+    let dst = tmp.path().join("src-rs");
+    let (files, _) = tcfs_sync::engine::pull_matching(
+        &op,
+        prefix,
+        "src/**/*.rs",
+        &dst,
+        None,
+        tcfs_sync::engine::DEFAULT_MODE_UMASK,
+    )
+    .await
+    .expect("pull_matching");
+    assert_eq!(files, 2);
+    assert!(dst.join("src/lib.rs").exists());
+    assert!(dst.join("src/net/mod.rs").exists());
+    assert!(!dst.join("build.rs").exists());
+
+    let err = tcfs_sync::engine::pull_matching(
+        &op,
+        prefix,
+        "src/[",
+        &dst,
+        None,
+        tcfs_sync::engine::DEFAULT_MODE_UMASK,
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("invalid pull pattern"), "{err}");
+}
+
+#[tokio::test]
+async fn failed_download_write_keeps_original() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/atomic";
+
+    let src = write_test_file(tmp.path(), "src.txt", b"new remote content");
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let upload = tcfs_sync::engine::upload_file(&op, &src, prefix, &state, None)
+        .await
+        .expect("upload");
+
+    let dst = write_test_file(tmp.path(), "dst.txt", b"original local content");
+    let dst_state = tcfs_sync::state::StateCache::open(&tmp.path().join("dst.db")).unwrap();
+
+    // Inject a write error: the temp path is occupied by a directory
+    let tmp_path = tcfs_sync::engine::download_tmp_path(&dst);
+    assert_eq!(tmp_path, tmp.path().join("dst.txt.tcfs_tmp"));
+    std::fs::create_dir(&tmp_path).unwrap();
+
+    let result = tcfs_sync::engine::download_file_with_device(
+        &op,
+        &upload.remote_path,
+        &dst,
+        prefix,
+        None,
+        "test-device",
+        Some(&dst_state),
+        None,
+        tcfs_sync::engine::RestoreMode::default(),
+    )
+    .await;
+
+    assert!(result.is_err(), "download should fail");
+    assert_eq!(std::fs::read(&dst).unwrap(), b"original local content");
+    assert!(
+        dst_state.get(&dst).is_none(),
+        "state must not change on failure"
+    );
+}
+
+#[tokio::test]
+async fn failed_download_rename_leaves_no_partial_file() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/atomic";
+
+    let src = write_test_file(tmp.path(), "src.txt", b"content that never lands");
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let upload = tcfs_sync::engine::upload_file(&op, &src, prefix, &state, None)
+        .await
+        .expect("upload");
+
+    // Inject a rename error: the destination is a non-empty directory
+    let dst = tmp.path().join("out/report.txt");
+    std::fs::create_dir_all(dst.join("inner")).unwrap();
+
+    let result =
+        tcfs_sync::engine::download_file(&op, &upload.remote_path, &dst, prefix, None).await;
+
+    assert!(result.is_err(), "download should fail");
+    assert!(
+        dst.join("inner").is_dir(),
+        "destination must be left intact"
+    );
+    let leftovers: Vec<_> = std::fs::read_dir(tmp.path().join("out"))
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect();
+    assert_eq!(leftovers, vec![std::ffi::OsString::from("report.txt")]);
+}
+
+#[tokio::test]
+async fn tree_status_classifies_each_file() {
+    use tcfs_sync::engine::FileSyncStatus;
+
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(src.join("sub")).unwrap();
+    write_test_file(&src, "kept.txt", b"unchanged since push");
+    write_test_file(&src, "sub/edited.txt", b"before");
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    tcfs_sync::engine::push_tree(&op, &src, "test/status", &state, None)
+        .await
+        .expect("push_tree");
+
+    write_test_file(&src, "sub/edited.txt", b"after the push");
+    write_test_file(&src, "fresh.txt", b"never pushed");
+    write_test_file(&src, "debug.log", b"excluded");
+
+    let config = tcfs_sync::engine::CollectConfig {
+        exclude_patterns: vec!["*.log".into()],
+        ..Default::default()
+    };
+    let rows = tcfs_sync::engine::tree_status(&src, &state, &config).unwrap();
+    let status = |name: &str| {
+        rows.iter()
+            .find(|r| r.path == src.join(name))
+            .unwrap_or_else(|| panic!("{name} not reported"))
+    };
+
+    assert_eq!(rows.len(), 3, "excluded file must not be reported");
+    assert_eq!(status("kept.txt").status, FileSyncStatus::Synced);
+    assert!(status("kept.txt").last_synced.is_some());
+    assert!(matches!(
+        status("sub/edited.txt").status,
+        FileSyncStatus::NeedsSync(_)
+    ));
+    assert_eq!(status("fresh.txt").status, FileSyncStatus::Untracked);
+    assert_eq!(status("fresh.txt").last_synced, None);
+}
+
+#[tokio::test]
+async fn pull_by_rel_path_resolves_index_entry() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/byname";
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(src.join("docs")).unwrap();
+    let content = b"# readme\npulled back by its logical path\n";
+    write_test_file(&src, "docs/readme.md", content);
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    tcfs_sync::engine::push_tree(&op, &src, prefix, &state, None)
+        .await
+        .expect("push_tree");
+
+    let manifest = tcfs_sync::engine::resolve_manifest_path(&op, prefix, "docs/readme.md")
+        .await
+        .expect("resolve rel path");
+    assert!(manifest.starts_with(&format!("{prefix}/manifests/")));
+
+    let dst = tmp.path().join("out/docs/readme.md");
+    tcfs_sync::engine::download_file(&op, &manifest, &dst, prefix, None)
+        .await
+        .expect("download");
+    assert_eq!(std::fs::read(&dst).unwrap(), content);
+
+    // Unknown paths fail instead of guessing
+    assert!(
+        tcfs_sync::engine::resolve_manifest_path(&op, prefix, "docs/missing.md")
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn trailing_slash_and_empty_prefixes_share_one_layout() {
+    for (push_prefix, pull_prefix, root) in [("team/", "team", "team/"), ("", "/", "")] {
+        let tmp = TempDir::new().unwrap();
+        let op = memory_operator();
+        let src = tmp.path().join("src");
+        std::fs::create_dir_all(src.join("a/b")).unwrap();
+        let content = b"nested under a normalized prefix\n";
+        write_test_file(&src, "a/b/c.txt", content);
+
+        let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+        tcfs_sync::engine::push_tree(&op, &src, push_prefix, &state, None)
+            .await
+            .expect("push_tree");
+
+        // No doubled or leading slashes in what was written
+        let hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(content));
+        assert!(op.exists(&format!("{root}index/a/b/c.txt")).await.unwrap());
+        assert!(op.exists(&format!("{root}manifests/{hash}")).await.unwrap());
+        let cached = state.get(&src.join("a/b/c.txt")).unwrap();
+        assert_eq!(cached.remote_path, format!("{root}manifests/{hash}"));
+
+        let manifest = tcfs_sync::engine::resolve_manifest_path(&op, pull_prefix, "/a/b/c.txt")
+            .await
+            .expect("resolve");
+        assert_eq!(manifest, cached.remote_path);
+
+        let dst = tmp.path().join("dst");
+        let (files, _, _) = tcfs_sync::engine::pull_tree(
+            &op,
+            pull_prefix,
+            &dst,
+            None,
+            tcfs_sync::engine::DEFAULT_MODE_UMASK,
+        )
+        .await
+        .expect("pull_tree");
+        assert_eq!(files, 1);
+        assert_eq!(std::fs::read(dst.join("a/b/c.txt")).unwrap(), content);
+    }
+}
+
+#[tokio::test]
+async fn byte_progress_tracks_chunk_lengths() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+
+    let original: Vec<u8> = (0u64..200_000)
+        .map(|i| (i.wrapping_mul(13) ^ (i >> 5)) as u8)
+        .collect();
+    let src = write_test_file(tmp.path(), "progress.dat", &original);
+    let (chunks, _) = tcfs_chunks::chunk_file(&src).unwrap();
+    assert!(chunks.len() > 1, "need several chunks of varying size");
+
+    let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let seen_cb = seen.clone();
+    let bytes: tcfs_sync::engine::ProgressFn = Box::new(move |done, total, _| {
+        seen_cb.lock().unwrap().push((done, total));
+    });
+    let chunk_calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let chunk_calls_cb = chunk_calls.clone();
+    let per_chunk: tcfs_sync::engine::ProgressFn = Box::new(move |_, _, _| {
+        chunk_calls_cb.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    });
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    tcfs_sync::engine::upload_file_with_progress(
+        &op,
+        &src,
+        "test/progress",
+        &state,
+        tcfs_sync::engine::UploadProgress {
+            chunks: Some(&per_chunk),
+            bytes: Some(&bytes),
+        },
+        "",
+        None,
+        None,
+        None,
+        false,
+    )
+    .await
+    .unwrap();
+
+    let mut expected = Vec::new();
+    let mut sum = 0u64;
+    for chunk in &chunks {
+        sum += chunk.length as u64;
+        expected.push((sum, original.len() as u64));
+    }
+    assert_eq!(*seen.lock().unwrap(), expected);
+    assert_eq!(sum, original.len() as u64);
+    assert_eq!(
+        chunk_calls.load(std::sync::atomic::Ordering::Relaxed),
+        chunks.len()
+    );
+}
+
+#[tokio::test]
+async fn timestamps_come_from_the_state_clock() {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tcfs_core::clock::MockClock;
+    use tcfs_sync::conflict::{compare_clocks_at, SyncOutcome, VectorClock};
+
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let src = write_test_file(tmp.path(), "clocked.txt", b"stamped by a mock clock");
+
+    let clock = Arc::new(MockClock::new(1_700_000_000));
+    let mut state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    state.set_clock(clock.clone());
+
+    let upload = tcfs_sync::engine::upload_file_with_device(
+        &op,
+        &src,
+        "test/clock",
+        &state,
+        None,
+        "dev-a",
+        None,
+        None,
+        false,
+    )
+    .await
+    .unwrap();
+    let raw = op.read(&upload.remote_path).await.unwrap();
+    let manifest = tcfs_sync::manifest::SyncManifest::from_bytes(&raw.to_bytes()).unwrap();
+    assert_eq!(manifest.written_at, 1_700_000_000);
+    assert_eq!(state.get(&src).unwrap().last_synced, 1_700_000_000);
+
+    clock.advance(Duration::from_secs(90));
+    state.set_tombstone(&src, VectorClock::new());
+    assert_eq!(state.tombstone(&src).unwrap().deleted_at, 1_700_000_090);
+
+    let mut a = VectorClock::new();
+    a.tick("dev-a");
+    let mut b = VectorClock::new();
+    b.tick("dev-b");
+    match compare_clocks_at(
+        &a,
+        &b,
+        "h1",
+        "h2",
+        "f.txt",
+        "dev-a",
+        "dev-b",
+        clock.as_ref(),
+    ) {
+        SyncOutcome::Conflict(info) => assert_eq!(info.detected_at, 1_700_000_090),
+        other => panic!("expected conflict, got {other:?}"),
+    }
+}
+
+/// Download `manifest` over `dst` as `test-device-001`, through `state`.
+async fn pull_into(
+    op: &Operator,
+    manifest: &str,
+    dst: &Path,
+    state: &tcfs_sync::state::StateCache,
+) -> anyhow::Result<tcfs_sync::engine::DownloadResult> {
+    tcfs_sync::engine::download_file_with_device(
+        op,
+        manifest,
+        dst,
+        "test/local-edits",
+        None,
+        "test-device-001",
+        Some(state),
+        None,
+        tcfs_sync::engine::RestoreMode::default(),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn pull_refuses_to_overwrite_local_edits() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/local-edits";
+    let device_id = "test-device-001";
+
+    let src_a = write_test_file(tmp.path(), "v1.txt", b"version one");
+    let src_b = write_test_file(tmp.path(), "v2.txt", b"version two");
+    let dst = tmp.path().join("output/notes.txt");
+    let mut state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    state.set_guard_local_edits(true);
+
+    let mut uploads = Vec::new();
+    for src in [&src_a, &src_b] {
+        let upload = tcfs_sync::engine::upload_file_with_device(
+            &op, src, prefix, &state, None, device_id, None, None, false,
+        )
+        .await
+        .expect("upload");
+        uploads.push(upload.remote_path);
+    }
+
+    // A file that still matches its last sync is updated as usual
+    pull_into(&op, &uploads[0], &dst, &state)
+        .await
+        .expect("first pull");
+    pull_into(&op, &uploads[1], &dst, &state)
+        .await
+        .expect("clean update");
+    assert_eq!(std::fs::read(&dst).unwrap(), b"version two");
+
+    // An unpushed edit stops the next pull and survives it
+    std::fs::write(&dst, b"my local edit").unwrap();
+    let err = pull_into(&op, &uploads[0], &dst, &state)
+        .await
+        .expect_err("local edit must not be clobbered");
+    let modified = err
+        .downcast_ref::<tcfs_sync::engine::LocallyModified>()
+        .expect("typed error");
+    assert_eq!(
+        modified.local_hash,
+        tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(b"my local edit"))
+    );
+    assert!(modified.synced_hash.is_some());
+    assert!(err.to_string().contains("--force"), "{err}");
+    assert_eq!(std::fs::read(&dst).unwrap(), b"my local edit");
+
+    // Forcing (no guard) overwrites it
+    state.set_guard_local_edits(false);
+    pull_into(&op, &uploads[0], &dst, &state)
+        .await
+        .expect("forced pull");
+    assert_eq!(std::fs::read(&dst).unwrap(), b"version one");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn push_tree_skips_fifos_sockets_and_optionally_empty_files() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let src = tmp.path().join("tree");
+    std::fs::create_dir_all(&src).unwrap();
+    write_test_file(&src, "notes.txt", b"regular file");
+    write_test_file(&src, "empty.txt", b"");
+    let status = std::process::Command::new("mkfifo")
+        .arg(src.join("pipe"))
+        .status()
+        .expect("run mkfifo");
+    assert!(status.success());
+    let _socket = std::os::unix::net::UnixListener::bind(src.join("daemon.sock")).unwrap();
+
+    // Reading the FIFO would block the push forever
+    let config = tcfs_sync::engine::CollectConfig::default();
+    let files = tcfs_sync::engine::collect_files(&src, &config).unwrap();
+    assert_eq!(files, [src.join("empty.txt"), src.join("notes.txt")]);
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let stats = tokio::time::timeout(
+        std::time::Duration::from_secs(30),
+        tcfs_sync::engine::push_tree_with_stats(
+            &op,
+            &src,
+            "test/special",
+            &state,
+            None,
+            "",
+            Some(&config),
+            None,
+            1,
+            None,
+        ),
+    )
+    .await
+    .expect("push must not hang on the fifo")
+    .unwrap();
+    assert_eq!(stats.uploaded, 2);
+    let layout = tcfs_core::layout::RemoteLayout::new("test/special");
+    for name in ["notes.txt", "empty.txt"] {
+        assert!(op.exists(&layout.index_key(name)).await.unwrap(), "{name}");
+    }
+    for name in ["pipe", "daemon.sock"] {
+        assert!(!op.exists(&layout.index_key(name)).await.unwrap(), "{name}");
+    }
+
+    // Empty files can be left out too
+    let config = tcfs_sync::engine::CollectConfig {
+        sync_empty_files: false,
+        ..Default::default()
+    };
+    let files = tcfs_sync::engine::collect_files(&src, &config).unwrap();
+    assert_eq!(files, [src.join("notes.txt")]);
+}
+
+#[tokio::test]
+async fn case_colliding_paths_both_survive_case_insensitive_pull() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/case";
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(src.join("docs")).unwrap();
+    write_test_file(&src, "README.md", b"upper");
+    write_test_file(&src, "readme.md", b"lower");
+    write_test_file(&src, "docs/Guide.md", b"guide upper");
+    write_test_file(&src, "docs/guide.md", b"guide lower");
+    write_test_file(&src, "other.md", b"no collision");
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    tcfs_sync::engine::push_tree(&op, &src, prefix, &state, None)
+        .await
+        .expect("push_tree");
+
+    // A case-insensitive volume, simulated: colliding entries get new names
+    let dst = tmp.path().join("mac");
+    let (files, _, _) = tcfs_sync::engine::pull_tree_with_case(
+        &op,
+        prefix,
+        &dst,
+        None,
+        true,
+        tcfs_sync::engine::DEFAULT_MODE_UMASK,
+    )
+    .await
+    .expect("pull_tree");
+    assert_eq!(files, 5);
+    for (name, content) in [
+        ("README.md", "upper"),
+        ("readme (2).md", "lower"),
+        ("docs/Guide.md", "guide upper"),
+        ("docs/guide (2).md", "guide lower"),
+        ("other.md", "no collision"),
+    ] {
+        assert_eq!(
+            std::fs::read_to_string(dst.join(name)).unwrap(),
+            content,
+            "{name}"
+        );
+    }
+
+    // A case-sensitive destination keeps every name as pushed
+    let dst = tmp.path().join("linux");
+    tcfs_sync::engine::pull_tree_with_case(
+        &op,
+        prefix,
+        &dst,
+        None,
+        false,
+        tcfs_sync::engine::DEFAULT_MODE_UMASK,
+    )
+    .await
+    .expect("pull_tree");
+    assert_eq!(
+        std::fs::read_to_string(dst.join("readme.md")).unwrap(),
+        "lower"
+    );
+    assert!(!dst.join("readme (2).md").exists());
+
+    #[cfg(target_os = "linux")]
+    assert!(!tcfs_core::paths::is_case_insensitive(tmp.path()).unwrap());
+}
@@ -0,0 +1,120 @@
+//! Integration test: bounded-concurrency tree push
+//!
+//! Wraps the in-memory OpenDAL backend in a probe layer that records how many
+//! metadata lookups are in flight at once, then verifies that `push_tree`
+//! uploads every file while never exceeding the configured concurrency.
+
+use opendal::raw::*;
+use opendal::Operator;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// Layer that tracks the peak number of concurrent `stat` calls.
+///
+/// Every upload starts with an existence check, so concurrent uploads show up
+/// as overlapping stats. A short sleep widens the window so overlap is visible.
+#[derive(Debug, Clone, Default)]
+struct InFlightProbe {
+    current: Arc<AtomicUsize>,
+    peak: Arc<AtomicUsize>,
+}
+
+impl<A: Access> Layer<A> for InFlightProbe {
+    type LayeredAccess = ProbeAccessor<A>;
+
+    fn layer(&self, inner: A) -> Self::LayeredAccess {
+        ProbeAccessor {
+            inner,
+            probe: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ProbeAccessor<A> {
+    inner: A,
+    probe: InFlightProbe,
+}
+
+impl<A: Access> LayeredAccess for ProbeAccessor<A> {
+    type Inner = A;
+    type Reader = A::Reader;
+    type Writer = A::Writer;
+    type Lister = A::Lister;
+    type Deleter = A::Deleter;
+
+    fn inner(&self) -> &Self::Inner {
+        &self.inner
+    }
+
+    async fn read(&self, path: &str, args: OpRead) -> opendal::Result<(RpRead, Self::Reader)> {
+        self.inner.read(path, args).await
+    }
+
+    async fn write(&self, path: &str, args: OpWrite) -> opendal::Result<(RpWrite, Self::Writer)> {
+        self.inner.write(path, args).await
+    }
+
+    async fn stat(&self, path: &str, args: OpStat) -> opendal::Result<RpStat> {
+        let now = self.probe.current.fetch_add(1, Ordering::SeqCst) + 1;
+        self.probe.peak.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let result = self.inner.stat(path, args).await;
+        self.probe.current.fetch_sub(1, Ordering::SeqCst);
+        result
+    }
+
+    async fn delete(&self) -> opendal::Result<(RpDelete, Self::Deleter)> {
+        self.inner.delete().await
+    }
+
+    async fn list(&self, path: &str, args: OpList) -> opendal::Result<(RpList, Self::Lister)> {
+        self.inner.list(path, args).await
+    }
+}
+
+#[tokio::test]
+async fn push_tree_respects_concurrency_cap() {
+    let tmp = TempDir::new().unwrap();
+    let probe = InFlightProbe::default();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .layer(probe.clone())
+        .finish();
+    let prefix = "test/concurrency";
+
+    let src_dir = tmp.path().join("src");
+    std::fs::create_dir_all(&src_dir).unwrap();
+    for i in 0..50 {
+        std::fs::write(
+            src_dir.join(format!("file_{i:02}.txt")),
+            format!("small file number {i}"),
+        )
+        .unwrap();
+    }
+
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+
+    let cap = 4;
+    let (uploaded, skipped, bytes) = tcfs_sync::engine::push_tree_with_device(
+        &op, &src_dir, prefix, &state, None, "", None, None, cap, None,
+    )
+    .await
+    .expect("push_tree");
+
+    assert_eq!(uploaded, 50, "all files should upload");
+    assert_eq!(skipped, 0);
+    assert!(bytes > 0);
+    assert_eq!(state.len(), 50);
+
+    let peak = probe.peak.load(Ordering::SeqCst);
+    assert!(peak <= cap, "peak concurrency {peak} exceeded cap {cap}");
+    assert!(peak > 1, "uploads should overlap, peak was {peak}");
+
+    for i in 0..50 {
+        let key = format!("{prefix}/index/file_{i:02}.txt");
+        assert!(op.exists(&key).await.unwrap(), "missing index entry {key}");
+    }
+}
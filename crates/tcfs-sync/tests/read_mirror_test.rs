@@ -0,0 +1,137 @@
+//! Integration test: downloads read through to mirrors
+//!
+//! Pushes a file to a mirror only, then pulls it through a primary that
+//! has nothing: the manifest and chunks come from the mirror, a mirror with
+//! a corrupt copy is skipped, and with backfill on the primary ends up
+//! holding the objects it was missing.
+
+use opendal::Operator;
+use tcfs_sync::engine::{download_file_with_device, EngineError, RestoreMode};
+use tcfs_sync::state::StateCache;
+use tcfs_sync::store::ReadMirrors;
+use tempfile::TempDir;
+
+fn memory_operator() -> Operator {
+    Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish()
+}
+
+/// Push `data` to a fresh mirror and return it with the manifest key.
+async fn seeded_mirror(tmp: &TempDir, data: &[u8], prefix: &str) -> (Operator, String) {
+    let mirror = memory_operator();
+    let src = tmp.path().join("src.bin");
+    std::fs::write(&src, data).unwrap();
+    let state = StateCache::open(&tmp.path().join("push-state.json")).unwrap();
+    let upload = tcfs_sync::engine::upload_file(&mirror, &src, prefix, &state, None)
+        .await
+        .expect("upload to mirror");
+    (mirror, upload.remote_path)
+}
+
+async fn pull(
+    primary: &Operator,
+    manifest: &str,
+    dest: &std::path::Path,
+    prefix: &str,
+    state: &StateCache,
+) -> anyhow::Result<u64> {
+    download_file_with_device(
+        primary,
+        manifest,
+        dest,
+        prefix,
+        None,
+        "dev-a",
+        Some(state),
+        None,
+        RestoreMode::default(),
+    )
+    .await
+    .map(|r| r.bytes)
+}
+
+#[tokio::test]
+async fn missing_objects_are_read_from_mirror() {
+    let tmp = TempDir::new().unwrap();
+    let data = b"regional copy of the data ".repeat(4096);
+    let (mirror, manifest) = seeded_mirror(&tmp, &data, "test/mirror").await;
+    let primary = memory_operator();
+    let dest = tmp.path().join("out.bin");
+
+    // Without mirrors the primary's miss is reported as such
+    let state = StateCache::open(&tmp.path().join("state.json")).unwrap();
+    let err = pull(&primary, &manifest, &dest, "test/mirror", &state)
+        .await
+        .expect_err("primary is empty");
+    assert!(
+        matches!(EngineError::classify(&err), EngineError::NotFound(_)),
+        "{err:#}"
+    );
+
+    state.set_read_mirrors(ReadMirrors::new(vec![mirror], false));
+    let bytes = pull(&primary, &manifest, &dest, "test/mirror", &state)
+        .await
+        .expect("download through mirror");
+    assert_eq!(bytes, data.len() as u64);
+    assert_eq!(std::fs::read(&dest).unwrap(), data);
+
+    // Without backfill the primary is left alone
+    assert!(primary.list("test/").await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn corrupt_mirror_is_skipped_and_primary_backfilled() {
+    let tmp = TempDir::new().unwrap();
+    let data = b"backfilled from the second mirror ".repeat(4096);
+    let (good, manifest) = seeded_mirror(&tmp, &data, "test/mirror").await;
+
+    // The first mirror holds the same keys with every chunk rotted
+    let bad = memory_operator();
+    let entries = good
+        .list_with("test/mirror/")
+        .recursive(true)
+        .await
+        .unwrap();
+    let mut chunk_keys = Vec::new();
+    for entry in entries.iter().filter(|e| e.metadata().is_file()) {
+        let bytes = good.read(entry.path()).await.unwrap().to_vec();
+        if entry.path().contains("/chunks/") {
+            chunk_keys.push(entry.path().to_string());
+            bad.write(entry.path(), b"bit rot".to_vec()).await.unwrap();
+        } else {
+            bad.write(entry.path(), bytes).await.unwrap();
+        }
+    }
+    assert!(!chunk_keys.is_empty());
+
+    let primary = memory_operator();
+    let state = StateCache::open(&tmp.path().join("state.json")).unwrap();
+    state.set_read_mirrors(ReadMirrors::new(vec![bad, good.clone()], true));
+    let dest = tmp.path().join("out.bin");
+    pull(&primary, &manifest, &dest, "test/mirror", &state)
+        .await
+        .expect("download through second mirror");
+    assert_eq!(std::fs::read(&dest).unwrap(), data);
+
+    // The primary now has good copies of the manifest and every chunk
+    assert_eq!(
+        primary.read(&manifest).await.unwrap().to_vec(),
+        good.read(&manifest).await.unwrap().to_vec()
+    );
+    for key in &chunk_keys {
+        assert_eq!(
+            primary.read(key).await.unwrap().to_vec(),
+            good.read(key).await.unwrap().to_vec(),
+            "{key}"
+        );
+    }
+
+    // ...so the next pull needs no mirror at all
+    std::fs::remove_file(&dest).unwrap();
+    state.set_read_mirrors(ReadMirrors::default());
+    pull(&primary, &manifest, &dest, "test/mirror", &state)
+        .await
+        .expect("download from backfilled primary");
+    assert_eq!(std::fs::read(&dest).unwrap(), data);
+}
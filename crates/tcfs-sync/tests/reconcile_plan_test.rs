@@ -0,0 +1,175 @@
+//! Integration test: `plan_reconcile` previews a sweep
+//!
+//! Two devices share a prefix. After the second device has synced, the
+//! first pushes a newer version of one file while the second edits another
+//! locally: the plan must pull the first, push the second, leave the rest
+//! up to date, and change nothing on either side. Running the sweep then
+//! does what was planned.
+
+use opendal::Operator;
+use tcfs_sync::engine::{
+    plan_reconcile, push_tree_with_stats, reconcile_tree, ReconcileAction, ReconcileStats,
+    DEFAULT_MODE_UMASK,
+};
+use tcfs_sync::state::StateCache;
+use tempfile::TempDir;
+
+const PREFIX: &str = "test/plan";
+
+#[tokio::test]
+async fn plan_lists_pull_push_and_up_to_date() {
+    let tmp = TempDir::new().unwrap();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish();
+
+    // Device A pushes three files (distinct content, so distinct manifests)
+    let root_a = tmp.path().join("a");
+    std::fs::create_dir_all(&root_a).unwrap();
+    for (name, body) in [
+        ("remote.txt", "remote v1"),
+        ("local.txt", "local v1"),
+        ("same.txt", "same v1"),
+    ] {
+        std::fs::write(root_a.join(name), body).unwrap();
+    }
+    let state_a = StateCache::open(&tmp.path().join("a.json")).unwrap();
+    let push_a = || {
+        push_tree_with_stats(
+            &op, &root_a, PREFIX, &state_a, None, "dev-a", None, None, 1, None,
+        )
+    };
+    push_a().await.unwrap();
+
+    // Device B syncs them down
+    let root_b = tmp.path().join("b");
+    std::fs::create_dir_all(&root_b).unwrap();
+    let state_b = StateCache::open(&tmp.path().join("b.json")).unwrap();
+    let sweep_b = || {
+        reconcile_tree(
+            &op,
+            &root_b,
+            PREFIX,
+            &state_b,
+            "dev-b",
+            None,
+            None,
+            DEFAULT_MODE_UMASK,
+        )
+    };
+    let stats = sweep_b().await.unwrap();
+    assert_eq!(stats.pulled, 3);
+
+    // A updates one file remotely, B edits another locally
+    std::fs::write(root_a.join("remote.txt"), "v2 from device a").unwrap();
+    push_a().await.unwrap();
+    std::fs::write(root_b.join("local.txt"), "v2 from device b").unwrap();
+
+    let plan = plan_reconcile(&op, &root_b, PREFIX, &state_b, "dev-b", None)
+        .await
+        .unwrap();
+    let action = |name: &str| {
+        plan.iter()
+            .find(|a| a.local_path() == root_b.join(name))
+            .unwrap_or_else(|| panic!("no action for {name}: {plan:?}"))
+    };
+    assert_eq!(plan.len(), 3, "{plan:?}");
+    assert!(
+        matches!(action("remote.txt"), ReconcileAction::PullRemote { .. }),
+        "{plan:?}"
+    );
+    assert!(
+        matches!(action("local.txt"), ReconcileAction::PushLocal { .. }),
+        "{plan:?}"
+    );
+    assert!(
+        matches!(action("same.txt"), ReconcileAction::UpToDate { .. }),
+        "{plan:?}"
+    );
+
+    // Planning touched nothing: same plan again, old local copy, old remote
+    let again = plan_reconcile(&op, &root_b, PREFIX, &state_b, "dev-b", None)
+        .await
+        .unwrap();
+    assert_eq!(again, plan);
+    assert_eq!(
+        std::fs::read(root_b.join("remote.txt")).unwrap(),
+        b"remote v1"
+    );
+    let root_c = tmp.path().join("c");
+    std::fs::create_dir_all(&root_c).unwrap();
+    let (files, _, _) = tcfs_sync::engine::pull_tree(
+        &op,
+        PREFIX,
+        &root_c,
+        None,
+        tcfs_sync::engine::DEFAULT_MODE_UMASK,
+    )
+    .await
+    .unwrap();
+    assert_eq!(files, 3);
+    assert_eq!(
+        std::fs::read(root_c.join("local.txt")).unwrap(),
+        b"local v1"
+    );
+
+    // The sweep carries out the plan
+    let stats = sweep_b().await.unwrap();
+    assert_eq!(
+        stats,
+        ReconcileStats {
+            pulled: 1,
+            pushed: 1,
+            conflicts: 0,
+        }
+    );
+    let plan = plan_reconcile(&op, &root_b, PREFIX, &state_b, "dev-b", None)
+        .await
+        .unwrap();
+    assert!(
+        plan.iter()
+            .all(|a| matches!(a, ReconcileAction::UpToDate { .. })),
+        "{plan:?}"
+    );
+}
+
+#[tokio::test]
+async fn diverged_file_is_planned_as_conflict() {
+    let tmp = TempDir::new().unwrap();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish();
+
+    // B has its own, never-pushed copy of a file A pushed
+    let root_a = tmp.path().join("a");
+    std::fs::create_dir_all(&root_a).unwrap();
+    std::fs::write(root_a.join("notes.txt"), "from a").unwrap();
+    let state_a = StateCache::open(&tmp.path().join("a.json")).unwrap();
+    push_tree_with_stats(
+        &op, &root_a, PREFIX, &state_a, None, "dev-a", None, None, 1, None,
+    )
+    .await
+    .unwrap();
+
+    let root_b = tmp.path().join("b");
+    std::fs::create_dir_all(&root_b).unwrap();
+    std::fs::write(root_b.join("notes.txt"), "from b").unwrap();
+    std::fs::write(root_b.join("new.txt"), "only on b").unwrap();
+    let state_b = StateCache::open(&tmp.path().join("b.json")).unwrap();
+
+    let plan = plan_reconcile(&op, &root_b, PREFIX, &state_b, "dev-b", None)
+        .await
+        .unwrap();
+    assert_eq!(
+        plan,
+        [
+            ReconcileAction::Conflict {
+                local_path: root_b.join("notes.txt"),
+            },
+            ReconcileAction::PushLocal {
+                local_path: root_b.join("new.txt"),
+                reason: "new file".into(),
+            },
+        ]
+    );
+}
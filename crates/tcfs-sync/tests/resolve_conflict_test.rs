@@ -32,17 +32,18 @@ async fn resolve_keep_remote_downloads_remote() {
     // Device A uploads its version
     let content_a = b"device A's version of the file";
     let src_a = write_test_file(tmp.path(), "src_a/doc.txt", content_a);
-    let mut state_a = tcfs_sync::state::StateCache::open(&tmp.path().join("state_a.db")).unwrap();
+    let state_a = tcfs_sync::state::StateCache::open(&tmp.path().join("state_a.db")).unwrap();
 
     let upload = tcfs_sync::engine::upload_file_with_device(
         &op,
         &src_a,
         prefix,
-        &mut state_a,
+        &state_a,
         None,
         "device-a",
         Some("doc.txt"),
         None,
+        false,
     )
     .await
     .expect("device A upload");
@@ -51,7 +52,7 @@ async fn resolve_keep_remote_downloads_remote() {
 
     // Device B "resolves" by downloading remote (device A's version)
     let dst_b = tmp.path().join("dst_b/doc.txt");
-    let mut state_b = tcfs_sync::state::StateCache::open(&tmp.path().join("state_b.db")).unwrap();
+    let state_b = tcfs_sync::state::StateCache::open(&tmp.path().join("state_b.db")).unwrap();
 
     let download = tcfs_sync::engine::download_file_with_device(
         &op,
@@ -60,8 +61,9 @@ async fn resolve_keep_remote_downloads_remote() {
         prefix,
         None,
         "device-b",
-        Some(&mut state_b),
+        Some(&state_b),
         None,
+        tcfs_sync::engine::RestoreMode::default(),
     )
     .await
     .expect("device B download");
@@ -88,17 +90,18 @@ async fn resolve_keep_local_re_uploads() {
     // Device A uploads first
     let content_a = b"original from device A";
     let src_a = write_test_file(tmp.path(), "src_a/notes.txt", content_a);
-    let mut state_a = tcfs_sync::state::StateCache::open(&tmp.path().join("state_a.db")).unwrap();
+    let state_a = tcfs_sync::state::StateCache::open(&tmp.path().join("state_a.db")).unwrap();
 
     let _upload_a = tcfs_sync::engine::upload_file_with_device(
         &op,
         &src_a,
         prefix,
-        &mut state_a,
+        &state_a,
         None,
         "device-a",
         Some("notes.txt"),
         None,
+        false,
     )
     .await
     .expect("device A upload");
@@ -106,7 +109,7 @@ async fn resolve_keep_local_re_uploads() {
     // Device B uploads its own version (keep_local scenario: re-upload with ticked clock)
     let content_b = b"device B's local version that wins";
     let src_b = write_test_file(tmp.path(), "src_b/notes.txt", content_b);
-    let mut state_b = tcfs_sync::state::StateCache::open(&tmp.path().join("state_b.db")).unwrap();
+    let state_b = tcfs_sync::state::StateCache::open(&tmp.path().join("state_b.db")).unwrap();
 
     // Tick B's clock to make it newer
     let mut vclock = tcfs_sync::conflict::VectorClock::new();
@@ -117,11 +120,12 @@ async fn resolve_keep_local_re_uploads() {
         &op,
         &src_b,
         prefix,
-        &mut state_b,
+        &state_b,
         None,
         "device-b",
         Some("notes.txt"),
         None,
+        false,
     )
     .await
     .expect("device B re-upload");
@@ -153,17 +157,18 @@ async fn resolve_keep_both_preserves_files() {
     // Device A uploads
     let content_a = b"device A content for keep_both";
     let src_a = write_test_file(tmp.path(), "src/report.txt", content_a);
-    let mut state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
 
     let upload = tcfs_sync::engine::upload_file_with_device(
         &op,
         &src_a,
         prefix,
-        &mut state,
+        &state,
         None,
         "device-a",
         Some("report.txt"),
         None,
+        false,
     )
     .await
     .expect("upload");
@@ -185,8 +190,9 @@ async fn resolve_keep_both_preserves_files() {
         prefix,
         None,
         "device-b",
-        Some(&mut state),
+        Some(&state),
         None,
+        tcfs_sync::engine::RestoreMode::default(),
     )
     .await
     .expect("download remote to original path");
@@ -220,10 +226,10 @@ async fn unsync_removes_from_state_cache() {
 
     let content = b"file to unsync";
     let src = write_test_file(tmp.path(), "synced.txt", content);
-    let mut state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
 
     // Push the file
-    let _upload = tcfs_sync::engine::upload_file(&op, &src, prefix, &mut state, None)
+    let _upload = tcfs_sync::engine::upload_file(&op, &src, prefix, &state, None)
         .await
         .expect("upload");
 
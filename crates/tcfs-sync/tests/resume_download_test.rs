@@ -0,0 +1,235 @@
+//! Integration test: interrupted downloads resume
+//!
+//! A flaky layer fails chunk reads once a budget runs out, interrupting a
+//! download part way. The temp file and its sidecar must survive, and the
+//! next download must fetch only the chunks the sidecar does not vouch for:
+//! lines lost from the end of the sidecar, chunks whose bytes no longer
+//! hash as recorded, or lines claiming more bytes than the file has, are
+//! fetched again.
+
+mod common;
+
+use common::noise;
+use opendal::raw::*;
+use opendal::Operator;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tcfs_sync::engine::{download_file, download_resume_path, download_tmp_path};
+use tcfs_sync::state::StateCache;
+use tempfile::TempDir;
+
+const PREFIX: &str = "test/resume";
+
+#[derive(Debug, Clone)]
+struct Flaky {
+    /// Chunk reads still allowed before they start failing
+    budget: Arc<AtomicUsize>,
+    /// Chunk reads seen
+    reads: Arc<AtomicUsize>,
+}
+
+impl Flaky {
+    fn new() -> Self {
+        Self {
+            budget: Arc::new(AtomicUsize::new(usize::MAX)),
+            reads: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+impl<A: Access> Layer<A> for Flaky {
+    type LayeredAccess = FlakyAccessor<A>;
+
+    fn layer(&self, inner: A) -> Self::LayeredAccess {
+        FlakyAccessor {
+            inner,
+            flaky: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FlakyAccessor<A> {
+    inner: A,
+    flaky: Flaky,
+}
+
+impl<A: Access> LayeredAccess for FlakyAccessor<A> {
+    type Inner = A;
+    type Reader = A::Reader;
+    type Writer = A::Writer;
+    type Lister = A::Lister;
+    type Deleter = A::Deleter;
+
+    fn inner(&self) -> &Self::Inner {
+        &self.inner
+    }
+
+    async fn read(&self, path: &str, args: OpRead) -> opendal::Result<(RpRead, Self::Reader)> {
+        if path.contains("/chunks/") {
+            self.flaky.reads.fetch_add(1, Ordering::SeqCst);
+            let allowed = self
+                .flaky
+                .budget
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok();
+            if !allowed {
+                return Err(opendal::Error::new(
+                    opendal::ErrorKind::Unexpected,
+                    "connection reset",
+                ));
+            }
+        }
+        self.inner.read(path, args).await
+    }
+
+    async fn write(&self, path: &str, args: OpWrite) -> opendal::Result<(RpWrite, Self::Writer)> {
+        self.inner.write(path, args).await
+    }
+
+    async fn delete(&self) -> opendal::Result<(RpDelete, Self::Deleter)> {
+        self.inner.delete().await
+    }
+
+    async fn list(&self, path: &str, args: OpList) -> opendal::Result<(RpList, Self::Lister)> {
+        self.inner.list(path, args).await
+    }
+}
+
+/// Upload a multi-chunk file and return the operator, its manifest path,
+/// its content and its chunk count.
+async fn pushed(tmp: &TempDir, flaky: &Flaky) -> (Operator, String, Vec<u8>, usize) {
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .layer(flaky.clone())
+        .finish();
+    let content = noise(0x9e37_79b9, 4 * 1024 * 1024);
+    let src = tmp.path().join("disk.img");
+    std::fs::write(&src, &content).unwrap();
+    let state = StateCache::open(&tmp.path().join("push.db")).unwrap();
+    let upload = tcfs_sync::engine::upload_file(&op, &src, PREFIX, &state, None)
+        .await
+        .expect("upload");
+    assert!(upload.chunks >= 8, "{} chunks", upload.chunks);
+    (op, upload.remote_path, content, upload.chunks)
+}
+
+/// Chunk lines recorded in the sidecar.
+fn recorded(dest: &Path) -> Vec<String> {
+    let sidecar = std::fs::read_to_string(download_resume_path(dest)).expect("sidecar kept");
+    sidecar.lines().skip(1).map(str::to_string).collect()
+}
+
+#[tokio::test]
+async fn interrupted_download_resumes_from_sidecar() {
+    let tmp = TempDir::new().unwrap();
+    let flaky = Flaky::new();
+    let (op, manifest, content, chunks) = pushed(&tmp, &flaky).await;
+    let dest = tmp.path().join("pulled/disk.img");
+
+    // Fail part way through
+    flaky.budget.store(chunks / 2, Ordering::SeqCst);
+    download_file(&op, &manifest, &dest, PREFIX, None)
+        .await
+        .expect_err("interrupted");
+    assert!(!dest.exists());
+    assert!(download_tmp_path(&dest).exists());
+    let written = recorded(&dest);
+    assert!(!written.is_empty() && written.len() < chunks, "{written:?}");
+    assert!(written[0].starts_with("0 "), "{written:?}");
+
+    // Lose the last lines, as a crash between writing a chunk and
+    // recording it would
+    let kept = written.len() / 2;
+    let sidecar = std::fs::read_to_string(download_resume_path(&dest)).unwrap();
+    let truncated: Vec<_> = sidecar.lines().take(1 + kept).collect();
+    std::fs::write(download_resume_path(&dest), truncated.join("\n") + "\n").unwrap();
+
+    flaky.budget.store(usize::MAX, Ordering::SeqCst);
+    flaky.reads.store(0, Ordering::SeqCst);
+    let result = download_file(&op, &manifest, &dest, PREFIX, None)
+        .await
+        .expect("resumed download");
+    assert_eq!(result.bytes, content.len() as u64);
+    assert_eq!(std::fs::read(&dest).unwrap(), content);
+    assert_eq!(flaky.reads.load(Ordering::SeqCst), chunks - kept);
+    assert!(!download_tmp_path(&dest).exists());
+    assert!(!download_resume_path(&dest).exists());
+}
+
+#[tokio::test]
+async fn damaged_partial_chunks_are_fetched_again() {
+    let tmp = TempDir::new().unwrap();
+    let flaky = Flaky::new();
+    let (op, manifest, content, chunks) = pushed(&tmp, &flaky).await;
+    let dest = tmp.path().join("disk.img.out");
+
+    flaky.budget.store(chunks - 2, Ordering::SeqCst);
+    download_file(&op, &manifest, &dest, PREFIX, None)
+        .await
+        .expect_err("interrupted");
+    let written = recorded(&dest);
+    assert!(written.len() >= 2, "{written:?}");
+
+    // Flip a byte inside the second chunk: it and everything after it are
+    // fetched again
+    let first_len: usize = written[0].split(' ').nth(1).unwrap().parse().unwrap();
+    let part = download_tmp_path(&dest);
+    let mut bytes = std::fs::read(&part).unwrap();
+    bytes[first_len + 1] ^= 0xff;
+    std::fs::write(&part, bytes).unwrap();
+
+    flaky.budget.store(usize::MAX, Ordering::SeqCst);
+    flaky.reads.store(0, Ordering::SeqCst);
+    download_file(&op, &manifest, &dest, PREFIX, None)
+        .await
+        .expect("resumed download");
+    assert_eq!(std::fs::read(&dest).unwrap(), content);
+    assert_eq!(flaky.reads.load(Ordering::SeqCst), chunks - 1);
+
+    // A sidecar for other content is ignored: the download starts over
+    flaky.budget.store(1, Ordering::SeqCst);
+    let other = tmp.path().join("other.img");
+    download_file(&op, &manifest, &other, PREFIX, None)
+        .await
+        .expect_err("interrupted");
+    let sidecar = std::fs::read_to_string(download_resume_path(&other)).unwrap();
+    let stale = sidecar.replacen(&sidecar[..64], &"0".repeat(64), 1);
+    std::fs::write(download_resume_path(&other), stale).unwrap();
+    flaky.budget.store(usize::MAX, Ordering::SeqCst);
+    flaky.reads.store(0, Ordering::SeqCst);
+    download_file(&op, &manifest, &other, PREFIX, None)
+        .await
+        .expect("fresh download");
+    assert_eq!(std::fs::read(&other).unwrap(), content);
+    assert_eq!(flaky.reads.load(Ordering::SeqCst), chunks);
+}
+
+#[tokio::test]
+async fn oversized_sidecar_lengths_are_not_trusted() {
+    let tmp = TempDir::new().unwrap();
+    let flaky = Flaky::new();
+    let (op, manifest, content, chunks) = pushed(&tmp, &flaky).await;
+    let dest = tmp.path().join("disk.img.out");
+
+    flaky.budget.store(chunks / 2, Ordering::SeqCst);
+    download_file(&op, &manifest, &dest, PREFIX, None)
+        .await
+        .expect_err("interrupted");
+
+    // A sidecar claiming a 1 TiB first chunk is dropped, not allocated for
+    let sidecar = std::fs::read_to_string(download_resume_path(&dest)).unwrap();
+    let mut lines: Vec<String> = sidecar.lines().map(str::to_string).collect();
+    let hash = lines[1].rsplit(' ').next().unwrap().to_string();
+    lines[1] = format!("0 {} {hash}", 1u64 << 40);
+    std::fs::write(download_resume_path(&dest), lines.join("\n") + "\n").unwrap();
+
+    flaky.budget.store(usize::MAX, Ordering::SeqCst);
+    flaky.reads.store(0, Ordering::SeqCst);
+    download_file(&op, &manifest, &dest, PREFIX, None)
+        .await
+        .expect("fresh download");
+    assert_eq!(std::fs::read(&dest).unwrap(), content);
+    assert_eq!(flaky.reads.load(Ordering::SeqCst), chunks);
+}
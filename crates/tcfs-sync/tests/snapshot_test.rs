@@ -0,0 +1,74 @@
+//! Integration test: prefix snapshots
+//!
+//! Takes two snapshots of a prefix with edits pushed in between, then
+//! restores the earlier one and checks it holds the old content.
+
+use opendal::Operator;
+use std::path::Path;
+use tcfs_sync::snapshot;
+use tempfile::TempDir;
+
+fn memory_operator() -> Operator {
+    Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish()
+}
+
+async fn push(op: &Operator, src: &Path, prefix: &str, state: &tcfs_sync::state::StateCache) {
+    tcfs_sync::engine::push_tree_with_stats(
+        op, src, prefix, state, None, "dev-a", None, None, 0, None,
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn restore_earlier_of_two_snapshots() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/snapshots";
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(src.join("notes")).unwrap();
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+
+    std::fs::write(src.join("notes/plan.md"), b"plan v1\n").unwrap();
+    std::fs::write(src.join("keep.txt"), b"unchanged\n").unwrap();
+    push(&op, &src, prefix, &state).await;
+    let first = snapshot::create_snapshot(&op, prefix, "dev-a", 1_700_000_000)
+        .await
+        .unwrap();
+    assert_eq!(first.files, 2);
+
+    std::fs::write(src.join("notes/plan.md"), b"plan v2, much revised\n").unwrap();
+    std::fs::write(src.join("notes/extra.md"), b"added later\n").unwrap();
+    push(&op, &src, prefix, &state).await;
+    let second = snapshot::create_snapshot(&op, prefix, "dev-a", 1_700_000_600)
+        .await
+        .unwrap();
+    assert_eq!(second.files, 3);
+    assert_ne!(first.root_hash, second.root_hash);
+
+    let listed = snapshot::list_snapshots(&op, prefix).await.unwrap();
+    assert_eq!(listed, vec![first.clone(), second.clone()]);
+
+    let dest = tmp.path().join("restored");
+    let (restored, stats) = snapshot::restore_snapshot(&op, prefix, &first.id, &dest, None)
+        .await
+        .unwrap();
+    assert_eq!(restored, first);
+    assert_eq!(stats.files, 2);
+    assert_eq!(
+        std::fs::read(dest.join("notes/plan.md")).unwrap(),
+        b"plan v1\n"
+    );
+    assert_eq!(
+        std::fs::read(dest.join("keep.txt")).unwrap(),
+        b"unchanged\n"
+    );
+    assert!(!dest.join("notes/extra.md").exists());
+
+    let err = snapshot::restore_snapshot(&op, prefix, "42-nope", &tmp.path().join("x"), None)
+        .await
+        .expect_err("unknown snapshot");
+    assert!(format!("{err:#}").contains("no snapshot"), "{err:#}");
+}
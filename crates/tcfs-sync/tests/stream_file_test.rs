@@ -0,0 +1,153 @@
+//! Integration test: `stream_file` of a compressed, encrypted file
+//!
+//! Pushes a large, compressible log with encryption on, then streams it to
+//! a writer that only hashes what it is given. A tracking allocator checks
+//! that the bytes held at any moment stay a small fraction of the file,
+//! i.e. chunks are decrypted and decompressed one at a time rather than
+//! the file being assembled in memory.
+
+#![cfg(feature = "crypto")]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use opendal::Operator;
+use tcfs_sync::manifest::SyncManifest;
+use tempfile::TempDir;
+
+struct Tracking;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Tracking {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let live = LIVE.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+            PEAK.fetch_max(live, Ordering::SeqCst);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        LIVE.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static ALLOC: Tracking = Tracking;
+
+const PREFIX: &str = "test/stream";
+const FILE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Hashes and counts what is written, keeping none of it.
+struct HashingWriter {
+    hasher: tcfs_chunks::Hasher,
+    bytes: u64,
+}
+
+impl std::io::Write for HashingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.hasher.update(buf);
+        self.bytes += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A log of distinct, compressible lines.
+fn log_lines(len: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(len + 128);
+    let mut i = 0u64;
+    while out.len() < len {
+        let line = format!(
+            "2026-10-15T12:{:02}:{:02} worker-{} request {} served in {}ms\n",
+            (i / 60) % 60,
+            i % 60,
+            i % 7,
+            i * 7919 % 1_000_003,
+            i % 250
+        );
+        out.extend_from_slice(line.as_bytes());
+        i += 1;
+    }
+    out.truncate(len);
+    out
+}
+
+#[tokio::test]
+async fn streams_compressed_encrypted_file_in_bounded_memory() {
+    let tmp = TempDir::new().unwrap();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish();
+    let ctx = tcfs_sync::engine::EncryptionContext {
+        master_key: tcfs_crypto::MasterKey::from_bytes([3u8; 32]),
+        convergent: false,
+    };
+
+    let src = tmp.path().join("service.img");
+    let expected = {
+        let content = log_lines(FILE_SIZE);
+        std::fs::write(&src, &content).unwrap();
+        tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&content))
+    };
+    let state = tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let upload = tcfs_sync::engine::upload_file_with_device(
+        &op,
+        &src,
+        PREFIX,
+        &state,
+        None,
+        "dev-a",
+        Some("service.img"),
+        Some(&ctx),
+        false,
+    )
+    .await
+    .expect("encrypted upload");
+
+    let data = op.read(&upload.remote_path).await.unwrap();
+    let manifest = SyncManifest::from_bytes(&data.to_bytes()).unwrap();
+    assert!(manifest.encrypted_file_key.is_some());
+    assert!(
+        (0..manifest.chunk_hashes().len()).all(|i| manifest.chunk_compressed(i)),
+        "every chunk of a log should compress"
+    );
+    drop((data, manifest));
+
+    let mut out = HashingWriter {
+        hasher: tcfs_chunks::Hasher::new(),
+        bytes: 0,
+    };
+    let baseline = LIVE.load(Ordering::SeqCst);
+    PEAK.store(baseline, Ordering::SeqCst);
+    let written =
+        tcfs_sync::engine::stream_file(&op, &upload.remote_path, PREFIX, Some(&ctx), &mut out)
+            .await
+            .expect("stream");
+    let peak = PEAK.load(Ordering::SeqCst) - baseline;
+
+    assert_eq!(written, FILE_SIZE as u64);
+    assert_eq!(out.bytes, FILE_SIZE as u64);
+    assert_eq!(tcfs_chunks::hash_to_hex(&out.hasher.finalize()), expected);
+    assert!(
+        peak < FILE_SIZE / 8,
+        "streaming held {peak} bytes at once for a {FILE_SIZE}-byte file"
+    );
+
+    // Without the key nothing is written
+    let mut locked = HashingWriter {
+        hasher: tcfs_chunks::Hasher::new(),
+        bytes: 0,
+    };
+    tcfs_sync::engine::stream_file(&op, &upload.remote_path, PREFIX, None, &mut locked)
+        .await
+        .expect_err("key required");
+    assert_eq!(locked.bytes, 0);
+}
@@ -0,0 +1,89 @@
+//! Integration test: downloads stream to disk
+//!
+//! Pulls a file of several hundred chunks through a counting allocator and
+//! checks that the heap never grows by more than a fraction of the file:
+//! chunks are written to the destination as they arrive rather than
+//! assembled in memory first. The test lives in its own binary so no other
+//! test's allocations land in the count.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use opendal::Operator;
+use tcfs_sync::state::StateCache;
+use tempfile::TempDir;
+
+struct Counting;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            let live = LIVE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(live, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOC: Counting = Counting;
+
+const FILE_SIZE: usize = 32 * 1024 * 1024;
+
+/// `len` bytes that neither compress nor dedup.
+fn noise(len: usize) -> Vec<u8> {
+    let mut x = 0x2545_f491u32;
+    (0..len)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x as u8
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn large_download_keeps_memory_bounded() {
+    let tmp = TempDir::new().unwrap();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish();
+    let src = tmp.path().join("disk.img");
+    let content = noise(FILE_SIZE);
+    std::fs::write(&src, &content).unwrap();
+    let expected = tcfs_chunks::hash_bytes(&content);
+    drop(content);
+
+    let state = StateCache::open(&tmp.path().join("push.db")).unwrap();
+    let pushed = tcfs_sync::engine::upload_file(&op, &src, "test/stream", &state, None)
+        .await
+        .expect("upload");
+    assert!(pushed.chunks > 100, "{} chunks", pushed.chunks);
+
+    let dest = tmp.path().join("pulled/disk.img");
+    let baseline = LIVE.load(Ordering::Relaxed);
+    PEAK.store(baseline, Ordering::Relaxed);
+    let pulled =
+        tcfs_sync::engine::download_file(&op, &pushed.remote_path, &dest, "test/stream", None)
+            .await
+            .expect("download");
+    let grown = PEAK.load(Ordering::Relaxed) - baseline;
+
+    assert_eq!(pulled.bytes, FILE_SIZE as u64);
+    assert!(
+        grown < FILE_SIZE / 4,
+        "heap grew by {grown} bytes while downloading a {FILE_SIZE} byte file"
+    );
+    assert_eq!(tcfs_chunks::hash_file(&dest).unwrap(), expected);
+    assert!(!tcfs_sync::engine::download_tmp_path(&dest).exists());
+}
@@ -0,0 +1,252 @@
+//! Integration test: torn chunk writes are caught and rewritten
+//!
+//! A tearing layer stores only the first half of chosen chunk writes, as an
+//! interrupted upload would. The engine must read each chunk back, rewrite
+//! a torn one, and give up with `ChunkHashMismatch` if it never sticks. A
+//! short object left behind by someone else's interrupted upload must not
+//! be trusted by dedup either.
+
+mod common;
+
+use common::noise;
+use opendal::raw::*;
+use opendal::{Buffer, Metadata, Operator};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tcfs_sync::engine::{download_file_with_device, ChunkHashMismatch, RestoreMode};
+use tcfs_sync::state::StateCache;
+use tempfile::TempDir;
+
+const PREFIX: &str = "test/torn";
+
+#[derive(Debug, Clone, Default)]
+struct Tear {
+    /// Chunk writes still to tear
+    remaining: Arc<AtomicUsize>,
+    /// Chunk writes seen
+    writes: Arc<AtomicUsize>,
+}
+
+impl<A: Access> Layer<A> for Tear {
+    type LayeredAccess = TearAccessor<A>;
+
+    fn layer(&self, inner: A) -> Self::LayeredAccess {
+        TearAccessor {
+            inner,
+            tear: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TearAccessor<A> {
+    inner: A,
+    tear: Tear,
+}
+
+impl<A: Access> LayeredAccess for TearAccessor<A> {
+    type Inner = A;
+    type Reader = A::Reader;
+    type Writer = TornWriter<A::Writer>;
+    type Lister = A::Lister;
+    type Deleter = A::Deleter;
+
+    fn inner(&self) -> &Self::Inner {
+        &self.inner
+    }
+
+    async fn read(&self, path: &str, args: OpRead) -> opendal::Result<(RpRead, Self::Reader)> {
+        self.inner.read(path, args).await
+    }
+
+    async fn write(&self, path: &str, args: OpWrite) -> opendal::Result<(RpWrite, Self::Writer)> {
+        let mut torn = false;
+        if path.contains("/chunks/") {
+            self.tear.writes.fetch_add(1, Ordering::SeqCst);
+            torn = self
+                .tear
+                .remaining
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok();
+        }
+        let (rp, inner) = self.inner.write(path, args).await?;
+        Ok((rp, TornWriter { inner, torn }))
+    }
+
+    async fn delete(&self) -> opendal::Result<(RpDelete, Self::Deleter)> {
+        self.inner.delete().await
+    }
+
+    async fn list(&self, path: &str, args: OpList) -> opendal::Result<(RpList, Self::Lister)> {
+        self.inner.list(path, args).await
+    }
+}
+
+struct TornWriter<W> {
+    inner: W,
+    torn: bool,
+}
+
+impl<W: oio::Write> oio::Write for TornWriter<W> {
+    async fn write(&mut self, bs: Buffer) -> opendal::Result<()> {
+        let bs = if self.torn {
+            bs.slice(..bs.len() / 2)
+        } else {
+            bs
+        };
+        self.inner.write(bs).await
+    }
+
+    async fn close(&mut self) -> opendal::Result<Metadata> {
+        self.inner.close().await
+    }
+
+    async fn abort(&mut self) -> opendal::Result<()> {
+        self.inner.abort().await
+    }
+}
+
+fn memory_operator() -> Operator {
+    Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish()
+}
+
+async fn push(
+    op: &Operator,
+    src: &std::path::Path,
+    state: &StateCache,
+) -> anyhow::Result<tcfs_sync::engine::UploadResult> {
+    tcfs_sync::engine::upload_file_with_device(
+        op,
+        src,
+        PREFIX,
+        state,
+        None,
+        "dev1",
+        Some("data.bin"),
+        None,
+        false,
+    )
+    .await
+}
+
+/// Every chunk object under the prefix, with its bytes.
+async fn chunk_objects(op: &Operator) -> Vec<(String, Vec<u8>)> {
+    let entries = op
+        .list_with(&format!("{PREFIX}/chunks/"))
+        .recursive(true)
+        .await
+        .unwrap();
+    let mut objects = Vec::new();
+    for entry in entries.iter().filter(|e| e.metadata().is_file()) {
+        let data = op.read(entry.path()).await.unwrap().to_vec();
+        objects.push((entry.path().to_string(), data));
+    }
+    objects.sort();
+    objects
+}
+
+/// Each chunk's bytes hash to the name it is stored under.
+fn assert_intact(objects: &[(String, Vec<u8>)]) {
+    for (key, data) in objects {
+        let hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(data));
+        assert!(key.ends_with(&hash), "{key} holds {} bytes", data.len());
+    }
+}
+
+#[tokio::test]
+async fn torn_write_is_read_back_and_rewritten() {
+    let tmp = TempDir::new().unwrap();
+    let tear = Tear::default();
+    tear.remaining.store(1, Ordering::SeqCst);
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .layer(tear.clone())
+        .finish();
+    let data = noise(31, 256 * 1024);
+    let src = tmp.path().join("data.bin");
+    std::fs::write(&src, &data).unwrap();
+    let state = StateCache::open(&tmp.path().join("state.json")).unwrap();
+
+    let upload = push(&op, &src, &state).await.expect("upload");
+    assert!(upload.chunks > 1);
+    // One extra write: the torn chunk, rewritten
+    assert_eq!(tear.writes.load(Ordering::SeqCst), upload.chunks + 1);
+    assert_intact(&chunk_objects(&op).await);
+
+    let dest = tmp.path().join("out.bin");
+    download_file_with_device(
+        &op,
+        &upload.remote_path,
+        &dest,
+        PREFIX,
+        None,
+        "dev2",
+        None,
+        None,
+        RestoreMode::default(),
+    )
+    .await
+    .expect("download");
+    assert_eq!(std::fs::read(&dest).unwrap(), data);
+}
+
+#[tokio::test]
+async fn write_that_never_sticks_fails_the_upload() {
+    let tmp = TempDir::new().unwrap();
+    let tear = Tear::default();
+    tear.remaining.store(usize::MAX, Ordering::SeqCst);
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .layer(tear)
+        .finish();
+    let src = tmp.path().join("data.bin");
+    std::fs::write(&src, noise(32, 64 * 1024)).unwrap();
+    let state = StateCache::open(&tmp.path().join("state.json")).unwrap();
+    state.set_quota(Some(1 << 30));
+
+    let err = push(&op, &src, &state)
+        .await
+        .expect_err("every write tears");
+    assert!(err.chain().any(|e| e.is::<ChunkHashMismatch>()), "{err:#}");
+    // Nothing stuck, so nothing stays charged against the quota
+    assert_eq!(state.cached_usage(PREFIX), Some(0));
+    // No manifest points at the bad chunks
+    assert!(op
+        .list_with(&format!("{PREFIX}/manifests/"))
+        .recursive(true)
+        .await
+        .unwrap()
+        .iter()
+        .all(|e| !e.metadata().is_file()));
+}
+
+#[tokio::test]
+async fn truncated_chunk_left_behind_is_reuploaded() {
+    let tmp = TempDir::new().unwrap();
+    let data = noise(33, 256 * 1024);
+    let src = tmp.path().join("data.bin");
+    std::fs::write(&src, &data).unwrap();
+
+    // The chunks as a clean push stores them
+    let clean = memory_operator();
+    let scratch = StateCache::open(&tmp.path().join("scratch.json")).unwrap();
+    push(&clean, &src, &scratch).await.unwrap();
+    let expected = chunk_objects(&clean).await;
+    assert!(expected.len() > 1);
+
+    // Another device's upload died halfway through every chunk
+    let op = memory_operator();
+    for (key, bytes) in &expected {
+        op.write(key, bytes[..bytes.len() / 2].to_vec())
+            .await
+            .unwrap();
+    }
+
+    let state = StateCache::open(&tmp.path().join("state.json")).unwrap();
+    let upload = push(&op, &src, &state).await.expect("upload");
+    assert_eq!(upload.new_chunks, expected.len());
+    assert_eq!(chunk_objects(&op).await, expected);
+    assert_intact(&expected);
+}
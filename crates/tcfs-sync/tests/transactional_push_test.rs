@@ -0,0 +1,112 @@
+//! Integration test: all-or-nothing tree pushes
+//!
+//! Runs `push_tree_transactional` into a quota that runs out part way
+//! through the tree, and checks that the files which did upload were not
+//! published: the prefix has no new index entries and an existing entry
+//! still names its old content. With room to finish, the same push
+//! publishes every entry and a tree manifest.
+
+use opendal::Operator;
+use tcfs_core::index::IndexEntry;
+use tcfs_core::layout::RemoteLayout;
+use tcfs_sync::engine::{push_tree_transactional, PushTreeStats, TreePushRolledBack};
+use tcfs_sync::state::StateCache;
+use tempfile::TempDir;
+
+const PREFIX: &str = "test/transactional";
+
+/// `len` bytes that neither compress nor dedup against other seeds.
+fn noise(seed: u32, len: usize) -> Vec<u8> {
+    let mut x = seed.wrapping_mul(2_654_435_761) | 1;
+    (0..len)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x as u8
+        })
+        .collect()
+}
+
+async fn keys_under(op: &Operator, dir: &str) -> Vec<String> {
+    match op.list_with(dir).recursive(true).await {
+        Ok(entries) => entries
+            .iter()
+            .filter(|e| !e.metadata().is_dir())
+            .map(|e| e.path().to_string())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+async fn index_hash(op: &Operator, rel: &str) -> String {
+    let key = RemoteLayout::new(PREFIX).index_key(rel);
+    IndexEntry::from_bytes(&op.read(&key).await.unwrap().to_bytes())
+        .unwrap()
+        .manifest_hash
+}
+
+async fn push(
+    op: &Operator,
+    src: &std::path::Path,
+    state: &StateCache,
+) -> anyhow::Result<PushTreeStats> {
+    push_tree_transactional(op, src, PREFIX, state, None, "dev-a", None, None, 1, None).await
+}
+
+#[tokio::test]
+async fn failed_push_publishes_no_index_entries() {
+    let tmp = TempDir::new().unwrap();
+    let op = Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish();
+    let layout = RemoteLayout::new(PREFIX);
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::write(src.join("keep.txt"), noise(1, 2000)).unwrap();
+    let state = StateCache::open(&tmp.path().join("state.db")).unwrap();
+
+    let first = push(&op, &src, &state).await.expect("first push");
+    assert_eq!(first.uploaded, 1);
+    let kept_hash = index_hash(&op, "keep.txt").await;
+    let published = keys_under(&op, &layout.index_dir("")).await;
+
+    // Edit the published file and add more than the quota leaves room for
+    std::fs::write(src.join("keep.txt"), noise(2, 2100)).unwrap();
+    std::fs::create_dir_all(src.join("docs")).unwrap();
+    for i in 0..6 {
+        std::fs::write(src.join(format!("docs/new{i}.bin")), noise(10 + i, 2000)).unwrap();
+    }
+    let used = tcfs_sync::engine::prefix_usage(&op, PREFIX).await.unwrap();
+    state.set_quota(Some(used + 7000));
+
+    let err = push(&op, &src, &state)
+        .await
+        .expect_err("push past the quota must roll back");
+    let rolled_back = err
+        .downcast_ref::<TreePushRolledBack>()
+        .expect("typed error");
+    assert!(rolled_back.failed > 0);
+    assert!(
+        rolled_back.staged > 0,
+        "some files uploaded before the failure"
+    );
+
+    // Some content landed, but none of it is visible through the index
+    let used_after = tcfs_sync::engine::prefix_usage(&op, PREFIX).await.unwrap();
+    assert!(used_after > used);
+    assert_eq!(keys_under(&op, &layout.index_dir("")).await, published);
+    assert_eq!(index_hash(&op, "keep.txt").await, kept_hash);
+
+    // With room to finish, the whole tree is published at once
+    state.set_quota(None);
+    let stats = push(&op, &src, &state).await.expect("retry");
+    assert!(stats.root_hash.is_some());
+    assert_eq!(keys_under(&op, &layout.index_dir("")).await.len(), 7);
+    assert_ne!(index_hash(&op, "keep.txt").await, kept_hash);
+    for i in 0..6 {
+        let expected = noise(10 + i, 2000);
+        let hash = tcfs_chunks::hash_to_hex(&tcfs_chunks::hash_bytes(&expected));
+        assert_eq!(index_hash(&op, &format!("docs/new{i}.bin")).await, hash);
+    }
+}
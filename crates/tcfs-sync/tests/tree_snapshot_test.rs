@@ -0,0 +1,136 @@
+//! Integration test: tree manifests and `pull_tree`
+//!
+//! Pushes a directory tree, captures the root hash of the tree manifest the
+//! push records, then restores the whole snapshot into a fresh directory and
+//! compares it against the original.
+
+use opendal::Operator;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn memory_operator() -> Operator {
+    Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish()
+}
+
+/// Relative path → contents of every regular file under `root`.
+fn read_tree(root: &Path) -> std::collections::BTreeMap<String, Vec<u8>> {
+    let mut files = std::collections::BTreeMap::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else {
+                let rel = path
+                    .strip_prefix(root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned();
+                files.insert(rel, std::fs::read(&path).unwrap());
+            }
+        }
+    }
+    files
+}
+
+async fn push(op: &Operator, src: &Path, prefix: &str, state_dir: &Path) -> Option<String> {
+    let state = tcfs_sync::state::StateCache::open(&state_dir.join("state.db")).unwrap();
+    tcfs_sync::engine::push_tree_with_stats(
+        op, src, prefix, &state, None, "dev-a", None, None, 0, None,
+    )
+    .await
+    .unwrap()
+    .root_hash
+}
+
+#[tokio::test]
+async fn pushed_tree_restores_from_its_root_hash() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/trees";
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(src.join("docs/drafts")).unwrap();
+    std::fs::create_dir_all(src.join("empty")).unwrap();
+    std::fs::write(src.join("readme.md"), b"# tree snapshot\n").unwrap();
+    std::fs::write(src.join("docs/guide.md"), b"guide body\n".repeat(200)).unwrap();
+    std::fs::write(src.join("docs/drafts/next.txt"), b"unfinished").unwrap();
+
+    let root_hash = push(&op, &src, prefix, tmp.path())
+        .await
+        .expect("a clean push records a tree manifest");
+    let tree = tcfs_sync::tree::read_tree(&op, prefix, &root_hash)
+        .await
+        .unwrap();
+    assert_eq!(tree.files.len(), 3);
+    assert_eq!(tree.empty_dirs, vec!["empty".to_string()]);
+    assert_eq!(tree.written_by, "dev-a");
+
+    // Later pushes move the index on, but the snapshot still restores as it was
+    let original = read_tree(&src);
+    std::fs::write(src.join("readme.md"), b"# rewritten\n").unwrap();
+    let newer = push(&op, &src, prefix, tmp.path()).await.unwrap();
+    assert_ne!(newer, root_hash);
+
+    let dest = tmp.path().join("restored");
+    let stats = tcfs_sync::tree::pull_tree(&op, prefix, &root_hash, &dest, None)
+        .await
+        .expect("pull tree");
+    assert_eq!(stats.files, 3);
+    assert_eq!(read_tree(&dest), original);
+    assert!(dest.join("empty").is_dir());
+    assert!(!tcfs_sync::engine::download_tmp_path(&dest).exists());
+
+    // A non-empty destination is refused and left alone
+    let err = tcfs_sync::tree::pull_tree(&op, prefix, &newer, &dest, None)
+        .await
+        .expect_err("destination not empty");
+    assert!(format!("{err:#}").contains("not empty"), "{err:#}");
+    assert_eq!(read_tree(&dest), original);
+}
+
+#[tokio::test]
+async fn identical_content_names_the_same_tree() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let a = tmp.path().join("a");
+    let b = tmp.path().join("b");
+    for dir in [&a, &b] {
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub/file.txt"), b"same bytes").unwrap();
+    }
+
+    let first = push(&op, &a, "test/same", &tmp.path().join("sa")).await;
+    let second = push(&op, &b, "test/same", &tmp.path().join("sb")).await;
+    assert!(first.is_some());
+    assert_eq!(first, second);
+}
+
+#[tokio::test]
+async fn tampered_tree_manifest_is_rejected() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/tamper";
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::write(src.join("a.txt"), b"alpha").unwrap();
+
+    let root_hash = push(&op, &src, prefix, tmp.path()).await.unwrap();
+    let key = tcfs_core::layout::RemoteLayout::new(prefix).tree_key(&root_hash);
+    let mut tree = tcfs_sync::tree::read_tree(&op, prefix, &root_hash)
+        .await
+        .unwrap();
+    tree.files.insert("b.txt".into(), "00".repeat(32));
+    op.write(&key, serde_json::to_vec(&tree).unwrap())
+        .await
+        .unwrap();
+
+    let err = tcfs_sync::tree::read_tree(&op, prefix, &root_hash)
+        .await
+        .expect_err("tampered tree");
+    assert!(err
+        .downcast_ref::<tcfs_sync::tree::TreeHashMismatch>()
+        .is_some());
+}
@@ -0,0 +1,183 @@
+//! Integration test: verifying stored chunks and repairing them locally
+//!
+//! Pushes a tree, then deletes or corrupts chunks behind the engine's back.
+//! `verify_prefix` must report each damaged manifest slot, and `repair_chunk`
+//! must rebuild it from the local copy so the file pulls cleanly again.
+
+mod common;
+
+use common::noise;
+use opendal::Operator;
+use std::path::Path;
+use tcfs_sync::engine::{self, ChunkProblem, ChunkUnrepairable, VerifyItem};
+use tcfs_sync::state::StateCache;
+use tempfile::TempDir;
+
+fn memory_operator() -> Operator {
+    Operator::new(opendal::services::Memory::default())
+        .expect("memory operator")
+        .finish()
+}
+
+async fn push(op: &Operator, src: &Path, prefix: &str, state: &StateCache) {
+    let (uploaded, _, _) =
+        engine::push_tree_with_device(op, src, prefix, state, None, "", None, None, 1, None)
+            .await
+            .expect("push_tree");
+    assert_eq!(uploaded, 1);
+}
+
+fn chunk_key(prefix: &str, item: &VerifyItem) -> String {
+    format!("{prefix}/chunks/{}", item.chunk_hash)
+}
+
+#[tokio::test]
+async fn deleted_chunk_is_repaired_from_local_copy() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/verify";
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    let file = src.join("data.bin");
+    let content = noise(0x9e37_79b9, 256 * 1024);
+    std::fs::write(&file, &content).unwrap();
+
+    let state = StateCache::open(&tmp.path().join("state.db")).unwrap();
+    push(&op, &src, prefix, &state).await;
+    assert!(engine::verify_prefix(&op, prefix).await.unwrap().is_empty());
+
+    let manifest_path = engine::resolve_manifest_path(&op, prefix, "data.bin")
+        .await
+        .unwrap();
+    let manifest = tcfs_sync::manifest::SyncManifest::from_bytes(
+        &op.read(&manifest_path).await.unwrap().to_bytes(),
+    )
+    .unwrap();
+    assert!(manifest.chunks.len() > 2, "expected several chunks");
+
+    // Lose one chunk and corrupt another
+    op.delete(&format!("{prefix}/chunks/{}", manifest.chunks[0]))
+        .await
+        .unwrap();
+    op.write(
+        &format!("{prefix}/chunks/{}", manifest.chunks[1]),
+        b"bit rot".to_vec(),
+    )
+    .await
+    .unwrap();
+
+    let items = engine::verify_prefix(&op, prefix).await.unwrap();
+    assert_eq!(items.len(), 2, "{items:?}");
+    assert_eq!(items[0].problem, ChunkProblem::Missing);
+    assert_eq!(items[0].rel_path.as_deref(), Some("data.bin"));
+    assert!(matches!(
+        items[1].problem,
+        ChunkProblem::HashMismatch { .. }
+    ));
+
+    // A local file with different content is refused
+    let other = tmp.path().join("other.bin");
+    std::fs::write(&other, noise(7, 1024)).unwrap();
+    let err = engine::repair_chunk(&op, prefix, &items[0], &other, None)
+        .await
+        .unwrap_err();
+    assert!(err.is::<ChunkUnrepairable>(), "{err:#}");
+    assert!(!op.exists(&chunk_key(prefix, &items[0])).await.unwrap());
+
+    for item in &items {
+        engine::repair_chunk(&op, prefix, item, &file, None)
+            .await
+            .expect("repair");
+    }
+    assert!(engine::verify_prefix(&op, prefix).await.unwrap().is_empty());
+
+    let dst = tmp.path().join("pulled.bin");
+    engine::download_file(&op, &manifest_path, &dst, prefix, None)
+        .await
+        .expect("download after repair");
+    assert_eq!(std::fs::read(&dst).unwrap(), content);
+}
+
+#[cfg(feature = "crypto")]
+#[tokio::test]
+async fn encrypted_chunk_is_reencrypted_and_manifest_updated() {
+    let tmp = TempDir::new().unwrap();
+    let op = memory_operator();
+    let prefix = "test/verify-encrypted";
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    let file = src.join("secret.bin");
+    let content = noise(0x5151_2323, 192 * 1024);
+    std::fs::write(&file, &content).unwrap();
+
+    let ctx = engine::EncryptionContext {
+        master_key: tcfs_crypto::MasterKey::from_bytes([42u8; 32]),
+        convergent: false,
+    };
+    let state = StateCache::open(&tmp.path().join("state.db")).unwrap();
+    let (uploaded, _, _) = engine::push_tree_with_device(
+        &op,
+        &src,
+        prefix,
+        &state,
+        None,
+        "",
+        None,
+        Some(&ctx),
+        1,
+        None,
+    )
+    .await
+    .expect("encrypted push");
+    assert_eq!(uploaded, 1);
+
+    let items = engine::verify_prefix(&op, prefix).await.unwrap();
+    assert!(items.is_empty());
+    let manifest_path = engine::resolve_manifest_path(&op, prefix, "secret.bin")
+        .await
+        .unwrap();
+    let before = tcfs_sync::manifest::SyncManifest::from_bytes(
+        &op.read(&manifest_path).await.unwrap().to_bytes(),
+    )
+    .unwrap();
+    op.delete(&format!("{prefix}/chunks/{}", before.chunks[1]))
+        .await
+        .unwrap();
+
+    let items = engine::verify_prefix(&op, prefix).await.unwrap();
+    assert_eq!(items.len(), 1);
+
+    // Without the master key the chunk cannot be re-encrypted
+    let err = engine::repair_chunk(&op, prefix, &items[0], &file, None)
+        .await
+        .unwrap_err();
+    assert!(err.is::<engine::KeyRequired>(), "{err:#}");
+
+    engine::repair_chunk(&op, prefix, &items[0], &file, Some(&ctx))
+        .await
+        .expect("repair with key");
+    assert!(engine::verify_prefix(&op, prefix).await.unwrap().is_empty());
+
+    let after = tcfs_sync::manifest::SyncManifest::from_bytes(
+        &op.read(&manifest_path).await.unwrap().to_bytes(),
+    )
+    .unwrap();
+    assert_ne!(after.chunks[1], before.chunks[1]);
+    assert_eq!(after.chunks[0], before.chunks[0]);
+
+    let dst = tmp.path().join("pulled.bin");
+    engine::download_file_with_device(
+        &op,
+        &manifest_path,
+        &dst,
+        prefix,
+        None,
+        "",
+        None,
+        Some(&ctx),
+        engine::RestoreMode::default(),
+    )
+    .await
+    .expect("download after repair");
+    assert_eq!(std::fs::read(&dst).unwrap(), content);
+}
@@ -63,6 +63,7 @@ pub struct PendingConflict {
     pub remote_device: String,
     pub local_hash: String,
     pub remote_hash: String,
+    #[allow(dead_code)]
     pub detected_at: u64,
 }
 
@@ -106,18 +107,18 @@ impl App {
             KeyCode::Char('4') => self.tab = Tab::Secrets,
             KeyCode::Char('5') => self.tab = Tab::Conflicts,
             // Conflicts tab shortcuts
-            KeyCode::Char('j') | KeyCode::Down if self.tab == Tab::Conflicts => {
-                if !self.conflicts.is_empty() {
-                    self.conflict_selected = (self.conflict_selected + 1) % self.conflicts.len();
-                }
+            KeyCode::Char('j') | KeyCode::Down
+                if self.tab == Tab::Conflicts && !self.conflicts.is_empty() =>
+            {
+                self.conflict_selected = (self.conflict_selected + 1) % self.conflicts.len();
             }
-            KeyCode::Char('k') | KeyCode::Up if self.tab == Tab::Conflicts => {
-                if !self.conflicts.is_empty() {
-                    self.conflict_selected = self
-                        .conflict_selected
-                        .checked_sub(1)
-                        .unwrap_or(self.conflicts.len() - 1);
-                }
+            KeyCode::Char('k') | KeyCode::Up
+                if self.tab == Tab::Conflicts && !self.conflicts.is_empty() =>
+            {
+                self.conflict_selected = self
+                    .conflict_selected
+                    .checked_sub(1)
+                    .unwrap_or(self.conflicts.len() - 1);
             }
             _ => {}
         }
@@ -31,21 +31,13 @@ fn parse_args() -> (PathBuf, PathBuf) {
     let mut i = 1;
     while i < args.len() {
         match args[i].as_str() {
-            "--config" | "-c" => {
-                if i + 1 < args.len() {
-                    config_path = PathBuf::from(&args[i + 1]);
-                    i += 2;
-                } else {
-                    i += 1;
-                }
+            "--config" | "-c" if i + 1 < args.len() => {
+                config_path = PathBuf::from(&args[i + 1]);
+                i += 2;
             }
-            "--socket" | "-s" => {
-                if i + 1 < args.len() {
-                    socket_path = Some(PathBuf::from(&args[i + 1]));
-                    i += 2;
-                } else {
-                    i += 1;
-                }
+            "--socket" | "-s" if i + 1 < args.len() => {
+                socket_path = Some(PathBuf::from(&args[i + 1]));
+                i += 2;
             }
             _ => {
                 i += 1;
@@ -43,7 +43,7 @@ pub fn render(frame: &mut Frame, area: Rect, app: &App) {
                     Span::styled(&c.rel_path, style),
                     Span::raw("  "),
                     Span::styled(
-                        format!("{} vs {}", &c.local_device, &c.remote_device),
+                        format!("{} vs {}", c.local_device, c.remote_device),
                         Style::default().fg(Color::DarkGray),
                     ),
                 ]);
@@ -71,7 +71,7 @@ pub fn render(frame: &mut Frame, area: Rect, app: &App) {
                 Span::styled("Local:   ", Style::default().add_modifier(Modifier::BOLD)),
                 Span::raw(format!(
                     "{} ({})",
-                    &conflict.local_device,
+                    conflict.local_device,
                     &conflict.local_hash[..16.min(conflict.local_hash.len())]
                 )),
             ]),
@@ -79,7 +79,7 @@ pub fn render(frame: &mut Frame, area: Rect, app: &App) {
                 Span::styled("Remote:  ", Style::default().add_modifier(Modifier::BOLD)),
                 Span::raw(format!(
                     "{} ({})",
-                    &conflict.remote_device,
+                    conflict.remote_device,
                     &conflict.remote_hash[..16.min(conflict.remote_hash.len())]
                 )),
             ]),
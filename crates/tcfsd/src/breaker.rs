@@ -0,0 +1,124 @@
+//! Circuit breaker for auto-pull downloads
+//!
+//! When storage is degraded every remote `FileSynced` event would otherwise
+//! start a download that fails the same way. After `threshold` consecutive
+//! failures the breaker opens and auto-pulls are skipped for `cooldown`;
+//! the first pull after that probes storage with `check_health` and either
+//! resumes or keeps the breaker open for another cooldown.
+
+use std::time::{Duration, Instant};
+
+/// What the breaker allows for the next auto-pull.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Gate {
+    /// Storage is considered healthy: download
+    Closed,
+    /// Cooldown elapsed: probe storage, then download if it is reachable
+    Probe,
+    /// Within the cooldown: skip the download
+    Open,
+}
+
+/// Consecutive-failure circuit breaker (see module docs).
+#[derive(Debug)]
+pub(crate) struct PullBreaker {
+    /// Failures that open the breaker (0 = never open)
+    threshold: u32,
+    cooldown: Duration,
+    failures: u32,
+    opened_at: Option<Instant>,
+}
+
+impl PullBreaker {
+    pub(crate) fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            failures: 0,
+            opened_at: None,
+        }
+    }
+
+    /// Apply reloaded limits; an open breaker keeps its opening time.
+    pub(crate) fn set_limits(&mut self, threshold: u32, cooldown: Duration) {
+        self.threshold = threshold;
+        self.cooldown = cooldown;
+        if threshold == 0 {
+            self.opened_at = None;
+        }
+    }
+
+    pub(crate) fn gate(&self, now: Instant) -> Gate {
+        match self.opened_at {
+            None => Gate::Closed,
+            Some(at) if now.saturating_duration_since(at) < self.cooldown => Gate::Open,
+            Some(_) => Gate::Probe,
+        }
+    }
+
+    pub(crate) fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    pub(crate) fn cooldown(&self) -> Duration {
+        self.cooldown
+    }
+
+    pub(crate) fn record_success(&mut self) {
+        self.failures = 0;
+        self.opened_at = None;
+    }
+
+    /// Count a failed download; returns true when this opens the breaker.
+    pub(crate) fn record_failure(&mut self, now: Instant) -> bool {
+        self.failures = self.failures.saturating_add(1);
+        if self.threshold == 0 || self.failures < self.threshold {
+            return false;
+        }
+        let opened = self.opened_at.is_none();
+        self.opened_at = Some(now);
+        opened
+    }
+
+    /// Keep the breaker open for another cooldown after a failed probe.
+    pub(crate) fn trip(&mut self, now: Instant) {
+        self.opened_at = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_after_threshold_and_probes_after_cooldown() {
+        let start = Instant::now();
+        let mut breaker = PullBreaker::new(3, Duration::from_secs(60));
+
+        assert!(!breaker.record_failure(start));
+        assert!(!breaker.record_failure(start));
+        assert_eq!(breaker.gate(start), Gate::Closed);
+        assert!(breaker.record_failure(start));
+        assert_eq!(breaker.gate(start + Duration::from_secs(59)), Gate::Open);
+
+        // A failed probe re-opens for a full cooldown
+        let probe = start + Duration::from_secs(60);
+        assert_eq!(breaker.gate(probe), Gate::Probe);
+        breaker.trip(probe);
+        assert_eq!(breaker.gate(probe + Duration::from_secs(30)), Gate::Open);
+
+        breaker.record_success();
+        assert_eq!(breaker.gate(probe), Gate::Closed);
+        assert_eq!(breaker.failures(), 0);
+    }
+
+    #[test]
+    fn zero_threshold_never_opens() {
+        let now = Instant::now();
+        let mut breaker = PullBreaker::new(0, Duration::from_secs(60));
+        for _ in 0..100 {
+            assert!(!breaker.record_failure(now));
+        }
+        assert_eq!(breaker.gate(now), Gate::Closed);
+    }
+}
@@ -5,36 +5,119 @@
 //! This enables zero-downtime credential rotation: an external process
 //! (or `tcfs rotate-credentials`) updates the SOPS file, and tcfsd
 //! picks up the new credentials within seconds.
+//!
+//! Changes are debounced and validated before they go live: the file must
+//! fully decrypt and carry a non-empty key pair. A change that cannot be
+//! applied (a half-written or corrupt file) leaves the previous credentials
+//! in place and raises `needs_reload`, which `CredentialStatus` reports
+//! until credentials are successfully loaded again.
 
 use anyhow::Result;
 use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, RwLockReadGuard};
+
+/// Live credentials plus the load bookkeeping reported by `CredentialStatus`
+pub struct CredStoreHandle {
+    store: RwLock<Option<tcfs_secrets::CredStore>>,
+    /// Unix seconds of the last successful load (0 = never)
+    loaded_at: AtomicI64,
+    /// Set when a credential file change could not be applied
+    needs_reload: AtomicBool,
+}
+
+impl CredStoreHandle {
+    /// Borrow the current credentials
+    pub async fn read(&self) -> RwLockReadGuard<'_, Option<tcfs_secrets::CredStore>> {
+        self.store.read().await
+    }
+
+    /// Install freshly loaded credentials, stamping `loaded_at` and clearing `needs_reload`
+    pub async fn install(&self, cs: tcfs_secrets::CredStore) {
+        self.store.write().await.replace(cs);
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        self.loaded_at.store(now, Ordering::Relaxed);
+        self.needs_reload.store(false, Ordering::Relaxed);
+    }
+
+    /// Record that the credential file changed but the change was not applied
+    pub fn mark_needs_reload(&self) {
+        self.needs_reload.store(true, Ordering::Relaxed);
+    }
+
+    /// Unix seconds of the last successful load, or 0 if none
+    pub fn loaded_at(&self) -> i64 {
+        self.loaded_at.load(Ordering::Relaxed)
+    }
+
+    /// True if a credential change is pending a manual reload
+    pub fn needs_reload(&self) -> bool {
+        self.needs_reload.load(Ordering::Relaxed)
+    }
+}
 
 /// Shared reference to a credential store instance
-pub type SharedCredStore = Arc<RwLock<Option<tcfs_secrets::CredStore>>>;
+pub type SharedCredStore = Arc<CredStoreHandle>;
 
 /// Create a new empty shared credential store
 pub fn new_shared() -> SharedCredStore {
-    Arc::new(RwLock::new(None))
+    Arc::new(CredStoreHandle {
+        store: RwLock::new(None),
+        loaded_at: AtomicI64::new(0),
+        needs_reload: AtomicBool::new(false),
+    })
+}
+
+/// The master key `tcfs auth unlock` left in the platform keychain (base64
+/// of the raw 32 bytes), or `None` while the session is locked.
+pub fn keychain_master_key() -> Option<tcfs_crypto::MasterKey> {
+    use secrecy::ExposeSecret;
+
+    let secret = match tcfs_secrets::keychain::get_secret(tcfs_secrets::keychain::keys::MASTER_KEY)
+    {
+        Ok(secret) => secret?,
+        Err(e) => {
+            tracing::debug!("keychain unavailable: {e}");
+            return None;
+        }
+    };
+    let bytes = base64::Engine::decode(
+        &base64::engine::general_purpose::STANDARD,
+        secret.expose_secret().trim(),
+    )
+    .ok()?;
+    match <[u8; tcfs_crypto::KEY_SIZE]>::try_from(bytes.as_slice()) {
+        Ok(key) => Some(tcfs_crypto::MasterKey::from_bytes(key)),
+        Err(_) => {
+            tracing::warn!(
+                "ignoring keychain master key: expected {} bytes",
+                tcfs_crypto::KEY_SIZE
+            );
+            None
+        }
+    }
 }
 
 /// Start watching a SOPS credential file for changes.
 ///
 /// When the file is modified (or created), re-decrypts it and updates
-/// the shared credential store. The watcher runs in a background tokio
+/// the shared credential store. Only the SOPS file itself is consulted (no
+/// env fallback); if it does not yield valid credentials, the previous
+/// ones are kept and the store is marked `needs_reload`. The watcher runs in a background tokio
 /// task and continues until the returned `CredentialWatcher` is dropped.
 ///
 /// # Arguments
 /// * `cred_file` — Path to the SOPS-encrypted YAML credential file
 /// * `secrets_config` — Secrets configuration (age identity, etc.)
-/// * `storage_config` — Storage configuration (for credential parsing)
 /// * `store` — Shared credential store to update on reload
 pub fn watch_credentials(
     cred_file: PathBuf,
     secrets_config: tcfs_core::config::SecretsConfig,
-    storage_config: tcfs_core::config::StorageConfig,
     store: SharedCredStore,
 ) -> Result<CredentialWatcher> {
     let (tx, mut rx) = tokio::sync::mpsc::channel::<()>(16);
@@ -42,10 +125,19 @@ pub fn watch_credentials(
     // Set up the file watcher
     let tx_clone = tx.clone();
     let watch_path = cred_file.clone();
+    let file_name = cred_file.file_name().map(|n| n.to_os_string());
     let mut watcher: RecommendedWatcher =
         notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
             match res {
                 Ok(event) => {
+                    // Ignore siblings, including rotation's temp file
+                    if !event
+                        .paths
+                        .iter()
+                        .any(|p| p.file_name() == file_name.as_deref())
+                    {
+                        return;
+                    }
                     match event.kind {
                         EventKind::Modify(_) | EventKind::Create(_) => {
                             tracing::debug!("credential file changed: {:?}", event.kind);
@@ -90,15 +182,16 @@ pub fn watch_credentials(
 
             tracing::info!("reloading credentials from {}", cred_file_clone.display());
 
-            match tcfs_secrets::CredStore::load(&secrets_config, &storage_config).await {
+            match tcfs_secrets::CredStore::load_from_sops(&cred_file_clone, &secrets_config).await {
                 Ok(cs) => {
                     let source = cs.source.clone();
-                    store.write().await.replace(cs);
+                    store.install(cs).await;
                     tracing::info!(source = %source, "credentials reloaded successfully");
                 }
                 Err(e) => {
                     tracing::error!("credential reload failed: {e}");
                     tracing::warn!("keeping previous credentials — fix the file and save again");
+                    store.mark_needs_reload();
                 }
             }
         }
@@ -129,3 +222,189 @@ impl CredentialWatcher {
         &self.path
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::path::Path;
+
+    fn test_creds() -> tcfs_secrets::CredStore {
+        tcfs_secrets::CredStore {
+            s3: None,
+            source: "test".into(),
+        }
+    }
+
+    /// Encrypt one value in SOPS `ENC[AES256_GCM,...]` form
+    fn enc_value(value: &str, data_key: &[u8; 32], iv: [u8; 12]) -> String {
+        use aes_gcm::{aead::Aead, Aes256Gcm, KeyInit, Nonce};
+        use base64::{engine::general_purpose::STANDARD as B64, Engine};
+
+        let cipher = Aes256Gcm::new_from_slice(data_key).unwrap();
+        let sealed = cipher
+            .encrypt(Nonce::from_slice(&iv), value.as_bytes())
+            .unwrap();
+        let (data, tag) = sealed.split_at(sealed.len() - 16);
+        format!(
+            "ENC[AES256_GCM,data:{},iv:{},tag:{},type:str]",
+            B64.encode(data),
+            B64.encode(iv),
+            B64.encode(tag)
+        )
+    }
+
+    /// Render a SOPS credential file readable by `identity`
+    fn sops_yaml(identity: &age::x25519::Identity, access_key: &str, secret: &str) -> String {
+        let data_key = [access_key.len() as u8; 32];
+        let encryptor = age::Encryptor::with_recipients(std::iter::once(
+            &identity.to_public() as &dyn age::Recipient
+        ))
+        .unwrap();
+        let mut armored = Vec::new();
+        let out =
+            age::armor::ArmoredWriter::wrap_output(&mut armored, age::armor::Format::AsciiArmor)
+                .unwrap();
+        let mut writer = encryptor.wrap_output(out).unwrap();
+        writer.write_all(&data_key).unwrap();
+        writer.finish().and_then(|a| a.finish()).unwrap();
+
+        let enc_key: String = String::from_utf8(armored)
+            .unwrap()
+            .lines()
+            .map(|l| format!("            {l}\n"))
+            .collect();
+        format!(
+            "access_key_id: {}\nsecret_access_key: {}\nsops:\n    age:\n        - recipient: {}\n          enc: |\n{enc_key}",
+            enc_value(access_key, &data_key, [1; 12]),
+            enc_value(secret, &data_key, [2; 12]),
+            identity.to_public(),
+        )
+    }
+
+    fn secrets_with_identity(
+        dir: &Path,
+    ) -> (age::x25519::Identity, tcfs_core::config::SecretsConfig) {
+        use secrecy::ExposeSecret;
+
+        let identity = age::x25519::Identity::generate();
+        let key_file = dir.join("keys.txt");
+        std::fs::write(&key_file, identity.to_string().expose_secret()).unwrap();
+        let secrets = tcfs_core::config::SecretsConfig {
+            age_identity: Some(key_file),
+            ..Default::default()
+        };
+        (identity, secrets)
+    }
+
+    async fn wait_for(deadline_secs: u64, mut done: impl FnMut() -> bool) -> bool {
+        let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(deadline_secs);
+        while !done() && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        done()
+    }
+
+    #[tokio::test]
+    async fn unapplied_file_change_sets_needs_reload() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let cred_file = tmp.path().join("creds.sops.yaml");
+        std::fs::write(&cred_file, "not: sops\n").unwrap();
+
+        let store = new_shared();
+        assert_eq!(store.loaded_at(), 0);
+        store.install(test_creds()).await;
+        assert!(store.loaded_at() > 0);
+        assert!(!store.needs_reload());
+
+        let _watcher = watch_credentials(
+            cred_file.clone(),
+            tcfs_core::config::SecretsConfig::default(),
+            store.clone(),
+        )
+        .unwrap();
+
+        std::fs::write(&cred_file, "still: not sops\n").unwrap();
+        assert!(
+            wait_for(10, || store.needs_reload()).await,
+            "watcher did not flag the change"
+        );
+        assert_eq!(store.read().await.as_ref().unwrap().source, "test");
+
+        // A successful reload clears the flag
+        store.install(test_creds()).await;
+        assert!(!store.needs_reload());
+    }
+
+    #[tokio::test]
+    async fn rotation_never_empties_live_store() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let (identity, secrets) = secrets_with_identity(tmp.path());
+        let cred_file = tmp.path().join("creds.sops.yaml");
+        std::fs::write(&cred_file, sops_yaml(&identity, "OLDKEY", "old-secret")).unwrap();
+
+        let store = new_shared();
+        store
+            .install(
+                tcfs_secrets::CredStore::load_from_sops(&cred_file, &secrets)
+                    .await
+                    .unwrap(),
+            )
+            .await;
+        let _watcher = watch_credentials(cred_file.clone(), secrets, store.clone()).unwrap();
+
+        // Sample the live store throughout the rotation
+        let stop = Arc::new(AtomicBool::new(false));
+        let observer = {
+            let store = store.clone();
+            let stop = stop.clone();
+            tokio::spawn(async move {
+                let mut seen = Vec::new();
+                while !stop.load(Ordering::Relaxed) {
+                    let key = store
+                        .read()
+                        .await
+                        .as_ref()
+                        .and_then(|cs| cs.s3.as_ref())
+                        .map(|s3| s3.access_key_id.clone())
+                        .unwrap_or_default();
+                    seen.push(key);
+                    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+                }
+                seen
+            })
+        };
+
+        // A torn write (plaintext keys with no values) is rejected
+        std::fs::write(&cred_file, "access_key_id: \n").unwrap();
+        assert!(wait_for(10, || store.needs_reload()).await);
+
+        // The rotated file lands atomically and is applied
+        tcfs_secrets::rotate::atomic_replace(
+            &cred_file,
+            &sops_yaml(&identity, "NEWKEY", "new-secret"),
+        )
+        .await
+        .unwrap();
+        assert!(wait_for(10, || !store.needs_reload()).await);
+
+        stop.store(true, Ordering::Relaxed);
+        let seen = observer.await.unwrap();
+        assert!(
+            seen.iter().all(|k| k == "OLDKEY" || k == "NEWKEY"),
+            "{seen:?}"
+        );
+        assert_eq!(
+            store
+                .read()
+                .await
+                .as_ref()
+                .unwrap()
+                .s3
+                .as_ref()
+                .unwrap()
+                .access_key_id,
+            "NEWKEY"
+        );
+    }
+}
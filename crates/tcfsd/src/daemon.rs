@@ -3,28 +3,29 @@
 use anyhow::Result;
 use secrecy::ExposeSecret;
 use std::sync::Arc;
-use tcfs_core::config::TcfsConfig;
+use std::time::Duration;
+use tcfs_core::config::{ConflictMode, TcfsConfig};
 use tcfs_sync::conflict::ConflictResolver;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
+use crate::breaker::{Gate, PullBreaker};
 use crate::cred_store::{new_shared as new_cred_store, SharedCredStore};
 use crate::grpc::TcfsDaemonImpl;
 
-pub async fn run(config: TcfsConfig) -> Result<()> {
-    info!("daemon starting");
-
-    // ── Device identity ──────────────────────────────────────────────────
-    let device_name = config
-        .sync
-        .device_name
-        .clone()
-        .unwrap_or_else(tcfs_secrets::device::default_device_name);
+/// The device id this daemon writes into vector clocks and NATS events.
+///
+/// A `sync.device_id` in the config is validated and used verbatim, without
+/// touching the registry. Otherwise the id is looked up in the device
+/// registry by name, auto-enrolling this device on first run.
+pub fn resolve_device_id(config: &TcfsConfig, device_name: &str) -> Result<String> {
+    if let Some(pinned) = &config.sync.device_id {
+        tcfs_secrets::device::validate_device_id(pinned)
+            .map_err(|e| anyhow::anyhow!("invalid sync.device_id: {e}"))?;
+        info!(device = %device_name, id = %pinned, "device id pinned by config");
+        return Ok(pinned.clone());
+    }
 
-    let registry_path = config
-        .sync
-        .device_identity
-        .clone()
-        .unwrap_or_else(tcfs_secrets::device::default_registry_path);
+    let registry_path = registry_path(config);
 
     let mut registry =
         tcfs_secrets::device::DeviceRegistry::load(&registry_path).unwrap_or_else(|e| {
@@ -33,28 +34,318 @@ pub async fn run(config: TcfsConfig) -> Result<()> {
         });
 
     // Auto-enroll this device on first run
-    let device_id = if let Some(dev) = registry.find(&device_name) {
+    if let Some(dev) = registry.find(device_name) {
         info!(device = %device_name, id = %dev.device_id, "device identity loaded");
-        dev.device_id.clone()
-    } else {
-        let public_key = format!(
-            "age1-device-{}",
-            &blake3::hash(device_name.as_bytes()).to_hex().as_str()[..8]
-        );
-        let id = registry.enroll(&device_name, &public_key, None);
-        if let Err(e) = registry.save(&registry_path) {
-            warn!("failed to save device registry: {e}");
+        return Ok(dev.device_id.clone());
+    }
+    let public_key = format!(
+        "age1-device-{}",
+        &blake3::hash(device_name.as_bytes()).to_hex().as_str()[..8]
+    );
+    let id = registry.enroll(device_name, &public_key, None);
+    if let Err(e) = registry.save(&registry_path) {
+        warn!("failed to save device registry: {e}");
+    }
+    info!(device = %device_name, id = %id, "device auto-enrolled");
+    Ok(id)
+}
+
+/// The device registry file: `sync.device_identity`, or the default path.
+pub(crate) fn registry_path(config: &TcfsConfig) -> std::path::PathBuf {
+    config
+        .sync
+        .device_identity
+        .clone()
+        .unwrap_or_else(tcfs_secrets::device::default_registry_path)
+}
+
+/// Configure a freshly opened state cache from `config`: the chunk filter
+/// and transfer concurrency, which are fixed until restart, then everything
+/// [`apply_state_settings`] covers. Shared by the daemon and the k8s worker
+/// so both enforce the same read-only prefixes and quota.
+pub(crate) fn configure_state_cache(
+    state_cache: &mut tcfs_sync::state::StateCache,
+    config: &TcfsConfig,
+    credentials: Option<(&str, &str)>,
+) {
+    state_cache.enable_chunk_filter(config.sync.chunk_filter_fp_rate);
+    state_cache.set_transfer_concurrency(
+        config.sync.transfer_concurrency_min,
+        config.sync.transfer_concurrency_max,
+    );
+    apply_state_settings(state_cache, config, credentials);
+}
+
+/// Apply the settings in `config` that the state cache can change while
+/// running: the read-only flag, quota, chunk sharding, `Cache-Control`,
+/// read mirrors (built with `credentials`, an access key id and secret),
+/// clock skew, and packing. Called at startup and by the `Reload` RPC.
+pub(crate) fn apply_state_settings(
+    state_cache: &tcfs_sync::state::StateCache,
+    config: &TcfsConfig,
+    credentials: Option<(&str, &str)>,
+) {
+    let read_only = config.sync.is_read_only_prefix(&config.storage.bucket);
+    if read_only {
+        info!(prefix = %config.storage.bucket, "storage prefix is read-only; pushes will be refused");
+    }
+    state_cache.set_read_only(read_only);
+    state_cache.set_quota(config.storage.quota_bytes);
+    state_cache.set_chunk_shard_depth(config.storage.chunk_shard_depth);
+    state_cache.set_cache_control(config.storage.cache_control.clone());
+    let mirrors = match credentials {
+        Some((access_key_id, secret)) => {
+            tcfs_storage::operator::build_read_mirrors(&config.storage, access_key_id, secret)
+                .map(|ops| {
+                    tcfs_sync::store::ReadMirrors::new(ops, config.storage.read_mirror_backfill)
+                })
+                .unwrap_or_else(|e| {
+                    warn!("read mirrors disabled: {e:#}");
+                    Default::default()
+                })
+        }
+        None => Default::default(),
+    };
+    state_cache.set_read_mirrors(mirrors);
+    state_cache.set_max_clock_skew(config.sync.max_clock_skew_secs);
+    state_cache.set_pack_threshold(
+        config
+            .sync
+            .pack_small_files
+            .then_some(config.sync.pack_threshold_bytes),
+    );
+}
+
+/// This device's manifest signing key, loaded from (or created next to) the
+/// device registry, with its public half enrolled for `device_id`.
+///
+/// Returns `None`, and manifests are written unsigned, if the key cannot be
+/// loaded.
+fn load_signing_key(config: &TcfsConfig, device_id: &str) -> Option<tcfs_crypto::DeviceSigningKey> {
+    let registry_path = registry_path(config);
+    let key_path = tcfs_secrets::device::signing_key_path(&registry_path);
+    let key = match tcfs_crypto::DeviceSigningKey::load_or_generate(&key_path) {
+        Ok(key) => key,
+        Err(e) => {
+            warn!("manifest signing disabled: {e:#}");
+            return None;
         }
-        info!(device = %device_name, id = %id, "device auto-enrolled");
-        id
     };
+    match tcfs_secrets::device::DeviceRegistry::load(&registry_path) {
+        Ok(mut registry) => {
+            if registry.set_signing_key(device_id, &key.public_key()) {
+                match registry.save(&registry_path) {
+                    Ok(()) => info!(id = %device_id, "manifest signing key enrolled"),
+                    Err(e) => warn!("failed to save device registry: {e}"),
+                }
+            }
+        }
+        Err(e) => warn!("device registry load failed: {e} (signing key not enrolled)"),
+    }
+    Some(key)
+}
+
+/// Publish this device's signing key under `prefix`, bound to `device_id`
+/// by the master key, for the rest of the fleet to enroll.
+async fn publish_signing_key(
+    op: &opendal::Operator,
+    prefix: &str,
+    device_id: &str,
+    device_name: &str,
+    key: &tcfs_crypto::DeviceSigningKey,
+    master_key: &tcfs_crypto::MasterKey,
+) -> Result<()> {
+    let public_key = key.public_key();
+    tcfs_secrets::device::PublishedSigningKey {
+        device_id: device_id.to_string(),
+        name: device_name.to_string(),
+        tag: tcfs_crypto::key_binding_tag(master_key, device_id, &public_key),
+        signing_public_key: public_key,
+    }
+    .publish(op, prefix)
+    .await
+}
+
+/// Enroll the signing key `device_id` published under `prefix`, if it has
+/// one whose binding tag checks out under `master_key`. Returns whether
+/// `registry` changed.
+async fn enroll_published_key(
+    op: &opendal::Operator,
+    prefix: &str,
+    device_id: &str,
+    registry: &mut tcfs_secrets::device::DeviceRegistry,
+    master_key: &tcfs_crypto::MasterKey,
+) -> Result<bool> {
+    let Some(published) =
+        tcfs_secrets::device::PublishedSigningKey::load(op, prefix, device_id).await?
+    else {
+        return Ok(false);
+    };
+    anyhow::ensure!(
+        published.device_id == device_id,
+        "signing key record for {device_id} names {}",
+        published.device_id
+    );
+    tcfs_crypto::verify_key_binding(
+        master_key,
+        device_id,
+        &published.signing_public_key,
+        &published.tag,
+    )?;
+    registry.enroll_published(&published)
+}
+
+/// Check that a remote manifest was signed by the device it names.
+///
+/// `written_by` must be an enrolled, unrevoked device, and when it has a
+/// signing key the manifest must carry a valid signature from it. Unknown
+/// and keyless devices are rejected unless listed in `unsigned_devices`
+/// (`sync.unsigned_manifest_devices`, for devices enrolled before manifest
+/// signing).
+fn check_manifest_author(
+    manifest: &tcfs_sync::manifest::SyncManifest,
+    registry: &tcfs_secrets::device::DeviceRegistry,
+    unsigned_devices: &[String],
+) -> Result<()> {
+    let author = &manifest.written_by;
+    let device = registry.find_by_id(author);
+    anyhow::ensure!(
+        !device.is_some_and(|d| d.revoked),
+        "manifest written by revoked device {author}"
+    );
+    match device.and_then(|d| d.signing_public_key.as_deref()) {
+        Some(public_key) => manifest.verify_signature(public_key).map_err(|e| {
+            anyhow::anyhow!(
+                "manifest claims to be written by {author} but is not signed by it: {e}"
+            )
+        }),
+        None if unsigned_devices.iter().any(|d| d == author) => Ok(()),
+        None if device.is_none() => {
+            anyhow::bail!("manifest written by unknown device {author:?}")
+        }
+        None => anyhow::bail!("device {author} has no manifest signing key enrolled"),
+    }
+}
+
+/// Read the manifest behind a `FileSynced` event and check its author
+/// against the device registry at `registry_path`.
+///
+/// An author without a signing key in the registry is first looked up among
+/// the keys published under the manifest's prefix; with `master_key` to
+/// check its binding tag, a published key is enrolled and saved.
+async fn verify_remote_manifest(
+    operator: &Arc<tokio::sync::Mutex<Option<opendal::Operator>>>,
+    manifest_path: &str,
+    registry_path: &std::path::Path,
+    unsigned_devices: &[String],
+    master_key: Option<&tcfs_crypto::MasterKey>,
+) -> Result<tcfs_sync::manifest::SyncManifest> {
+    let op = operator
+        .lock()
+        .await
+        .clone()
+        .ok_or_else(|| anyhow::anyhow!("no storage operator"))?;
+    let data = op
+        .read(manifest_path)
+        .await
+        .map_err(|e| anyhow::anyhow!("reading manifest {manifest_path}: {e}"))?;
+    let manifest = tcfs_sync::manifest::SyncManifest::from_bytes(&data.to_bytes())?;
+    let mut registry = tcfs_secrets::device::DeviceRegistry::load(registry_path)?;
+
+    let keyless = registry
+        .find_by_id(&manifest.written_by)
+        .is_none_or(|d| d.signing_public_key.is_none());
+    let prefix = tcfs_core::layout::manifest_key_prefix(manifest_path);
+    if let (true, Some(master_key), Some(prefix)) = (keyless, master_key, prefix) {
+        match enroll_published_key(&op, prefix, &manifest.written_by, &mut registry, master_key)
+            .await
+        {
+            Ok(true) => {
+                info!(id = %manifest.written_by, "enrolled published signing key");
+                if let Err(e) = registry.save(registry_path) {
+                    warn!("failed to save device registry: {e}");
+                }
+            }
+            Ok(false) => {}
+            Err(e) => warn!(id = %manifest.written_by, "published signing key rejected: {e:#}"),
+        }
+    }
+    check_manifest_author(&manifest, &registry, unsigned_devices)?;
+    Ok(manifest)
+}
+
+/// Check that the verified `manifest` is the version a `FileSynced` event
+/// announced: the same content hash and the same vector clock. A clock the
+/// publisher trimmed (`summarized`) only has to agree on the entries it kept.
+fn check_event_manifest(
+    manifest: &tcfs_sync::manifest::SyncManifest,
+    blake3: &str,
+    vclock: &tcfs_sync::conflict::VectorClock,
+    summarized: bool,
+) -> Result<()> {
+    if manifest.file_hash != blake3 {
+        anyhow::bail!(
+            "event names content {blake3} but the manifest holds {}",
+            manifest.file_hash
+        );
+    }
+    let matches = if summarized {
+        vclock
+            .clocks
+            .iter()
+            .all(|(device, &counter)| manifest.vclock.get(device) == counter)
+    } else {
+        manifest.vclock == *vclock
+    };
+    if !matches {
+        anyhow::bail!("event clock does not match the manifest's");
+    }
+    Ok(())
+}
+
+/// The remote modification time to resolve a conflict on `manifest` with.
+///
+/// This is the event's `timestamp`, unless the manifest's `written_at` lies
+/// more than `max_skew` seconds ahead of `now`: the writer's clock is off,
+/// so 0 (unknown) is returned and timestamp-based strategies fall back to
+/// vector-clock and device ordering.
+fn remote_modified(
+    manifest: &tcfs_sync::manifest::SyncManifest,
+    timestamp: u64,
+    now: u64,
+    max_skew: u64,
+) -> u64 {
+    match manifest.clock_skew(now, max_skew) {
+        Some(ahead) => {
+            warn!(
+                from_device = %manifest.written_by,
+                ahead_secs = ahead,
+                "remote manifest is stamped in the future; ignoring its timestamp"
+            );
+            0
+        }
+        None => timestamp,
+    }
+}
+
+pub async fn run(config: TcfsConfig, config_path: std::path::PathBuf) -> Result<()> {
+    info!("daemon starting");
+
+    // ── Device identity ──────────────────────────────────────────────────
+    let device_name = config
+        .sync
+        .device_name
+        .clone()
+        .unwrap_or_else(tcfs_secrets::device::default_device_name);
+
+    let device_id = resolve_device_id(&config, &device_name)?;
 
     // Load credentials
     let cred_store: SharedCredStore = new_cred_store();
     match tcfs_secrets::CredStore::load(&config.secrets, &config.storage).await {
         Ok(cs) => {
             info!(source = %cs.source, "credentials loaded");
-            cred_store.write().await.replace(cs);
+            cred_store.install(cs).await;
         }
         Err(e) => {
             warn!("credential load failed: {e}  (daemon will start without creds)");
@@ -65,31 +356,17 @@ pub async fn run(config: TcfsConfig) -> Result<()> {
     let mut operator: Option<opendal::Operator> = None;
     let storage_ok = if let Some(s3) = cred_store.read().await.as_ref().and_then(|c| c.s3.as_ref())
     {
-        let op = tcfs_storage::operator::build_from_core_config(
-            &config.storage,
-            &s3.access_key_id,
-            s3.secret_access_key.expose_secret(),
-        )?;
-        match tcfs_storage::check_health(&op).await {
-            Ok(()) => {
-                info!(endpoint = %config.storage.endpoint, "SeaweedFS: connected");
-                operator = Some(op);
-                true
-            }
-            Err(e) => {
-                warn!(endpoint = %config.storage.endpoint, "SeaweedFS: {e}");
-                // Still keep the operator for retry
-                operator = Some(op);
-                false
-            }
-        }
+        let (op, ok) = connect_storage(&config.storage, s3).await?;
+        // Keep the operator even when unreachable, for retry
+        operator = Some(op);
+        ok
     } else {
         warn!("no S3 credentials — storage connectivity not verified");
         false
     };
 
     // Open state cache
-    let state_cache =
+    let mut state_cache =
         tcfs_sync::state::StateCache::open(&config.sync.state_db).unwrap_or_else(|e| {
             warn!("state cache open failed: {e}  (starting fresh)");
             tcfs_sync::state::StateCache::open(&std::path::PathBuf::from(
@@ -97,6 +374,38 @@ pub async fn run(config: TcfsConfig) -> Result<()> {
             ))
             .expect("fallback state cache")
         });
+    {
+        let creds = cred_store.read().await;
+        let s3 = creds.as_ref().and_then(|c| c.s3.as_ref());
+        let credentials = s3.map(|s3| {
+            (
+                s3.access_key_id.as_str(),
+                s3.secret_access_key.expose_secret(),
+            )
+        });
+        configure_state_cache(&mut state_cache, &config, credentials);
+    }
+    let signing_key = load_signing_key(&config, &device_id).map(Arc::new);
+    state_cache.set_signing_key(signing_key.clone());
+
+    // Let the rest of the fleet verify this device's manifests
+    if let (Some(key), Some(op), true) = (&signing_key, &operator, storage_ok) {
+        match crate::cred_store::keychain_master_key() {
+            Some(master_key) => {
+                let prefixes = std::iter::once(config.storage.bucket.as_str())
+                    .chain(config.sync.roots.iter().map(|r| r.prefix.trim_matches('/')));
+                for prefix in prefixes {
+                    if let Err(e) =
+                        publish_signing_key(op, prefix, &device_id, &device_name, key, &master_key)
+                            .await
+                    {
+                        warn!(prefix, "publishing signing key failed: {e:#}");
+                    }
+                }
+            }
+            None => info!("master key locked; signing key not published"),
+        }
+    }
 
     // Wrap operator in Arc<Mutex> for shared access
     let operator = Arc::new(tokio::sync::Mutex::new(operator));
@@ -121,7 +430,6 @@ pub async fn run(config: TcfsConfig) -> Result<()> {
             match crate::cred_store::watch_credentials(
                 cred_file.clone(),
                 config.secrets.clone(),
-                config.storage.clone(),
                 cred_store.clone(),
             ) {
                 Ok(watcher) => {
@@ -140,77 +448,117 @@ pub async fn run(config: TcfsConfig) -> Result<()> {
         None
     };
 
+    // Conflict resolver for conflict_mode = "auto"
+    let resolver: Arc<dyn ConflictResolver> = Arc::from(
+        tcfs_sync::conflict::resolver_for(&config.sync.auto_strategy)
+            .map_err(|e| anyhow::anyhow!("invalid sync.auto_strategy: {e}"))?,
+    );
+
     // Log device identity for troubleshooting
     info!(
         device_name = %device_name,
         device_id = %device_id,
         conflict_mode = %config.sync.conflict_mode,
+        auto_strategy = %config.sync.auto_strategy,
         "fleet identity ready"
     );
 
     // Send systemd ready notification
     notify_ready();
 
+    let clock = state_cache.clock().clone();
+
     // Start gRPC server
     let socket_path = config.daemon.socket.clone();
-    let config = Arc::new(config);
+    let shared_config = crate::reload::new_shared(config.clone());
     let impl_ = TcfsDaemonImpl::new(
         cred_store,
-        config.clone(),
+        shared_config.clone(),
+        config_path,
         storage_ok,
-        config.storage.endpoint.clone(),
         state_cache,
         operator.clone(),
         device_id.clone(),
         device_name.clone(),
     );
 
-    // Connect to NATS for fleet state sync (non-blocking, best-effort)
+    // Connect to NATS for fleet state sync (non-blocking, best-effort); if
+    // it is down, keep retrying in the background so fleet sync comes up
+    // once it is back
     let nats_url = &config.sync.nats_url;
     if nats_url != "nats://localhost:4222" || std::env::var("TCFS_NATS_URL").is_ok() {
         let url = std::env::var("TCFS_NATS_URL").unwrap_or_else(|_| nats_url.clone());
-        match tcfs_sync::NatsClient::connect(&url).await {
-            Ok(nats) => {
-                if let Err(e) = nats.ensure_streams().await {
-                    warn!("NATS stream setup failed: {e}");
-                } else {
-                    // Publish DeviceOnline event
-                    let online_event = tcfs_sync::StateEvent::DeviceOnline {
-                        device_id: device_id.clone(),
-                        last_seq: 0,
-                        timestamp: tcfs_sync::StateEvent::now(),
-                    };
-                    if let Err(e) = nats.publish_state_event(&online_event).await {
-                        warn!("failed to publish DeviceOnline: {e}");
-                    } else {
-                        info!("NATS: published DeviceOnline");
-                    }
-
-                    // Spawn state sync loop with auto-pull support
-                    let sync_device_id = device_id.clone();
-                    let sync_conflict_mode = config.sync.conflict_mode.clone();
-                    let sync_root = config.sync.sync_root.clone();
-                    let storage_prefix = config.storage.bucket.clone();
-                    spawn_state_sync_loop(
-                        &nats,
-                        &sync_device_id,
-                        &sync_conflict_mode,
-                        operator.clone(),
-                        impl_.state_cache_handle(),
-                        sync_root,
-                        storage_prefix,
+        let setup = {
+            let shared_config = shared_config.clone();
+            let operator = operator.clone();
+            let state_cache = impl_.state_cache_handle();
+            let fleet = impl_.fleet_handle();
+            let resolver = resolver.clone();
+            let device_id = device_id.clone();
+            let clock = clock.clone();
+            move |nats: tcfs_sync::NatsClient| {
+                let (shared_config, operator, state_cache, fleet, resolver, device_id, clock) = (
+                    shared_config.clone(),
+                    operator.clone(),
+                    state_cache.clone(),
+                    fleet.clone(),
+                    resolver.clone(),
+                    device_id.clone(),
+                    clock.clone(),
+                );
+                async move {
+                    start_fleet_sync(
+                        nats,
+                        &device_id,
+                        clock.unix_secs(),
+                        shared_config,
+                        operator,
+                        state_cache,
+                        fleet,
+                        resolver,
                     )
-                    .await;
-
-                    impl_.set_nats(nats);
+                    .await
                 }
             }
+        };
+        let connected = match tcfs_sync::NatsClient::connect(&url, config.fleet_id()).await {
+            Ok(nats) => setup(nats).await,
+            Err(e) => Err(e),
+        };
+        match connected {
+            Ok(nats) => impl_.set_nats(nats),
             Err(e) => {
-                warn!("NATS connection failed: {e} (fleet sync disabled)");
+                warn!("NATS unavailable: {e:#} (fleet sync disabled, retrying in the background)");
+                spawn_nats_reconnect(
+                    url,
+                    config.fleet_id().to_string(),
+                    impl_.nats_handle(),
+                    impl_.nats_ok_handle(),
+                    NATS_RETRY_INITIAL,
+                    NATS_RETRY_MAX,
+                    setup,
+                );
             }
         }
     }
 
+    // Re-count stored bytes for Status now and then, not per request
+    {
+        let operator = operator.clone();
+        let stored = impl_.stored_usage_handle();
+        let prefix = config.storage.bucket.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(crate::grpc::STORED_USAGE_REFRESH);
+            loop {
+                interval.tick().await;
+                match crate::grpc::refresh_stored_usage(&operator, &prefix, &stored).await {
+                    Ok(bytes) => debug!(prefix = %prefix, bytes, "counted stored bytes"),
+                    Err(e) => debug!("stored usage refresh skipped: {e:#}"),
+                }
+            }
+        });
+    }
+
     // Prepare shutdown handles
     let state_cache_for_shutdown = impl_.state_cache_handle();
     let nats_for_shutdown = impl_.nats_handle();
@@ -234,7 +582,7 @@ pub async fn run(config: TcfsConfig) -> Result<()> {
         notify_stopping();
 
         // Flush state cache before exit
-        let mut cache = state_cache_for_shutdown.lock().await;
+        let cache = state_cache_for_shutdown.as_ref();
         if let Err(e) = cache.flush() {
             error!("failed to flush state cache on shutdown: {e}");
         } else {
@@ -246,7 +594,7 @@ pub async fn run(config: TcfsConfig) -> Result<()> {
             let offline_event = tcfs_sync::StateEvent::DeviceOffline {
                 device_id: device_id_for_shutdown.clone(),
                 last_seq: 0,
-                timestamp: tcfs_sync::StateEvent::now(),
+                timestamp: cache.now_secs(),
             };
             if let Err(e) = nats.publish_state_event(&offline_event).await {
                 warn!("failed to publish DeviceOffline: {e}");
@@ -268,23 +616,138 @@ pub async fn run(config: TcfsConfig) -> Result<()> {
     Ok(())
 }
 
-/// Spawn a background task that consumes state events from NATS.
+/// Store `timestamp` as the last time `device_id` was seen online, in the
+/// device registry at `registry_path`.
+fn record_device_seen(
+    registry_path: &std::path::Path,
+    device_id: &str,
+    timestamp: u64,
+) -> Result<()> {
+    let mut registry = tcfs_secrets::device::DeviceRegistry::load(registry_path)?;
+    if registry.record_seen(device_id, timestamp) {
+        registry.save(registry_path)?;
+    }
+    Ok(())
+}
+
+/// First wait before retrying an unreachable NATS server.
+pub const NATS_RETRY_INITIAL: Duration = Duration::from_secs(1);
+
+/// Longest wait between NATS reconnect attempts.
+pub const NATS_RETRY_MAX: Duration = Duration::from_secs(60);
+
+/// Set up fleet sync over a fresh NATS connection: apply the configured
+/// event codec, make sure the streams exist, announce the device and start
+/// the state sync loop. Returns the client for `set_nats`.
 #[allow(clippy::too_many_arguments)]
+async fn start_fleet_sync(
+    nats: tcfs_sync::NatsClient,
+    device_id: &str,
+    now: u64,
+    config: crate::reload::SharedConfig,
+    operator: Arc<tokio::sync::Mutex<Option<opendal::Operator>>>,
+    state_cache: Arc<tcfs_sync::state::StateCache>,
+    fleet: crate::fleet::SharedFleet,
+    resolver: Arc<dyn ConflictResolver>,
+) -> Result<tcfs_sync::NatsClient> {
+    let codec = tcfs_sync::nats::EventCodec::from_config(&crate::reload::current(&config).sync);
+    let nats = nats.with_codec(codec);
+    nats.ensure_streams()
+        .await
+        .map_err(|e| anyhow::anyhow!("NATS stream setup failed: {e}"))?;
+
+    let online_event = tcfs_sync::StateEvent::DeviceOnline {
+        device_id: device_id.to_string(),
+        last_seq: 0,
+        timestamp: now,
+    };
+    if let Err(e) = nats.publish_state_event(&online_event).await {
+        warn!("failed to publish DeviceOnline: {e}");
+    } else {
+        info!("NATS: published DeviceOnline");
+    }
+
+    spawn_state_sync_loop(
+        &nats,
+        device_id,
+        config,
+        operator,
+        state_cache,
+        fleet,
+        resolver,
+    )
+    .await;
+    Ok(nats)
+}
+
+/// Keep connecting to NATS at `url` in the background after a failed start.
+///
+/// Waits `initial` before the first attempt and doubles the wait after each
+/// failure, up to `max`. Once a connection is made and `setup` accepts it,
+/// the client is stored in `nats` and `nats_ok` is raised, so `Status` and
+/// shutdown see it exactly as if the daemon had connected at startup.
+pub fn spawn_nats_reconnect<F, Fut>(
+    url: String,
+    fleet_id: String,
+    nats: Arc<tokio::sync::Mutex<Option<tcfs_sync::NatsClient>>>,
+    nats_ok: Arc<std::sync::atomic::AtomicBool>,
+    initial: Duration,
+    max: Duration,
+    setup: F,
+) -> tokio::task::JoinHandle<()>
+where
+    F: Fn(tcfs_sync::NatsClient) -> Fut + Send + 'static,
+    Fut: std::future::Future<Output = Result<tcfs_sync::NatsClient>> + Send,
+{
+    tokio::spawn(async move {
+        let mut delay = initial;
+        loop {
+            tokio::time::sleep(delay).await;
+            let attempt = match tcfs_sync::NatsClient::connect(&url, &fleet_id).await {
+                Ok(client) => setup(client).await,
+                Err(e) => Err(e),
+            };
+            match attempt {
+                Ok(client) => {
+                    *nats.lock().await = Some(client);
+                    nats_ok.store(true, std::sync::atomic::Ordering::Relaxed);
+                    info!(url = %url, "NATS reconnected, fleet sync enabled");
+                    return;
+                }
+                Err(e) => {
+                    delay = (delay * 2).min(max);
+                    debug!(url = %url, retry_in = ?delay, "NATS still unavailable: {e:#}");
+                }
+            }
+        }
+    })
+}
+
+/// Spawn a background task that consumes state events from NATS.
+///
+/// The config is re-read for every event, so a `Reload` that changes
+/// `conflict_mode`, `auto_strategy`, `sync_root`, `roots`, `mode_umask`, or
+/// the auto-pull breaker limits applies to the next event. Events are
+/// routed to a `[[sync.roots]]` entry by prefix (see [`route_synced`]).
+/// Every event from another device updates its liveness in `fleet`.
+/// `resolver` is the one built for the startup config.
 async fn spawn_state_sync_loop(
     nats: &tcfs_sync::NatsClient,
     device_id: &str,
-    conflict_mode: &str,
+    config: crate::reload::SharedConfig,
     operator: Arc<tokio::sync::Mutex<Option<opendal::Operator>>>,
-    state_cache: Arc<tokio::sync::Mutex<tcfs_sync::state::StateCache>>,
-    sync_root: Option<std::path::PathBuf>,
-    storage_prefix: String,
+    state_cache: Arc<tcfs_sync::state::StateCache>,
+    fleet: crate::fleet::SharedFleet,
+    mut resolver: Arc<dyn ConflictResolver>,
 ) {
     use futures::StreamExt;
 
-    match nats.state_consumer(device_id).await {
+    let suffix = crate::reload::current(&config).sync.consumer_suffix.clone();
+    match nats.state_consumer(device_id, &suffix).await {
         Ok(stream) => {
             let device_id = device_id.to_string();
-            let conflict_mode = conflict_mode.to_string();
+            let mut strategy = crate::reload::current(&config).sync.auto_strategy.clone();
+            let mut breaker = PullBreaker::new(0, Duration::ZERO);
             tokio::spawn(async move {
                 let mut stream = std::pin::pin!(stream);
                 info!(device = %device_id, "state sync loop started");
@@ -293,6 +756,21 @@ async fn spawn_state_sync_loop(
                         Ok(msg) => {
                             let event_type = msg.event.event_type();
                             let event_device = msg.event.device_id().to_string();
+                            let cfg = crate::reload::current(&config);
+                            if cfg.sync.auto_strategy != strategy {
+                                match tcfs_sync::conflict::resolver_for(&cfg.sync.auto_strategy) {
+                                    Ok(r) => {
+                                        info!(strategy = %cfg.sync.auto_strategy, "auto strategy changed");
+                                        resolver = Arc::from(r);
+                                    }
+                                    Err(e) => warn!("keeping auto strategy {strategy}: {e}"),
+                                }
+                                strategy = cfg.sync.auto_strategy.clone();
+                            }
+                            breaker.set_limits(
+                                cfg.sync.auto_pull_failure_threshold,
+                                Duration::from_secs(cfg.sync.auto_pull_cooldown_secs),
+                            );
 
                             // Skip events from our own device
                             if event_device == device_id {
@@ -301,6 +779,7 @@ async fn spawn_state_sync_loop(
                                 }
                                 continue;
                             }
+                            fleet.observe(&msg.event);
 
                             match &msg.event {
                                 tcfs_sync::StateEvent::FileSynced {
@@ -309,8 +788,23 @@ async fn spawn_state_sync_loop(
                                     size,
                                     vclock: remote_vclock,
                                     manifest_path,
+                                    timestamp,
                                     ..
                                 } => {
+                                    let Some(route) = route_synced(&cfg, manifest_path, rel_path)
+                                    else {
+                                        info!(
+                                            from_device = %event_device,
+                                            path = %rel_path,
+                                            manifest = %manifest_path,
+                                            "no sync root for the event's prefix, skipping"
+                                        );
+                                        if let Err(e) = msg.ack().await {
+                                            warn!("ack unrouted event failed: {e}");
+                                        }
+                                        continue;
+                                    };
+                                    let conflict_mode = route.conflict_mode;
                                     info!(
                                         from_device = %event_device,
                                         path = %rel_path,
@@ -320,31 +814,102 @@ async fn spawn_state_sync_loop(
                                         "remote file synced"
                                     );
 
-                                    match conflict_mode.as_str() {
-                                        "auto" => {
+                                    let master_key = crate::cred_store::keychain_master_key();
+                                    let manifest = match verify_remote_manifest(
+                                        &operator,
+                                        manifest_path,
+                                        &registry_path(&cfg),
+                                        &cfg.sync.unsigned_manifest_devices,
+                                        master_key.as_ref(),
+                                    )
+                                    .await
+                                    {
+                                        Ok(manifest) => check_event_manifest(
+                                            &manifest,
+                                            blake3,
+                                            remote_vclock,
+                                            msg.clock_summarized,
+                                        )
+                                        .map(|()| manifest),
+                                        Err(e) => Err(e),
+                                    };
+                                    // Everything below works from the manifest
+                                    // checked here, never a later read of it
+                                    let manifest = match manifest {
+                                        Ok(manifest) => manifest,
+                                        Err(e) => {
+                                            warn!(
+                                                from_device = %event_device,
+                                                path = %rel_path,
+                                                "rejecting remote manifest: {e:#}"
+                                            );
+                                            if let Err(e) = msg.ack().await {
+                                                warn!("ack rejected event failed: {e}");
+                                            }
+                                            continue;
+                                        }
+                                    };
+                                    let remote_modified = remote_modified(
+                                        &manifest,
+                                        *timestamp,
+                                        state_cache.now_secs(),
+                                        cfg.sync.max_clock_skew_secs,
+                                    );
+
+                                    match conflict_mode {
+                                        ConflictMode::Auto | ConflictMode::Interactive => {
                                             handle_auto_pull(
+                                                conflict_mode,
                                                 &device_id,
                                                 &event_device,
                                                 rel_path,
-                                                blake3,
-                                                remote_vclock,
+                                                &manifest,
                                                 manifest_path,
+                                                remote_modified,
+                                                resolver.as_ref(),
                                                 &operator,
                                                 &state_cache,
-                                                sync_root.as_deref(),
-                                                &storage_prefix,
+                                                route.local_root,
+                                                route.prefix,
+                                                cfg.sync.mode_umask,
+                                                &mut breaker,
                                             )
                                             .await;
                                         }
-                                        "interactive" => {
-                                            info!(
-                                                path = %rel_path,
-                                                from = %event_device,
-                                                "conflict queued for review"
+                                        ConflictMode::Defer => {
+                                            // left for the next reconcile sweep
+                                        }
+                                    }
+                                }
+                                tcfs_sync::StateEvent::FileDeleted {
+                                    rel_path,
+                                    vclock: remote_vclock,
+                                    ..
+                                } => {
+                                    let route = route_tracked(&cfg, &state_cache, rel_path);
+                                    let conflict_mode = route.map_or_else(
+                                        || cfg.sync.conflict_mode_for(rel_path),
+                                        |r| r.conflict_mode,
+                                    );
+                                    info!(
+                                        from_device = %event_device,
+                                        path = %rel_path,
+                                        mode = %conflict_mode,
+                                        "remote file deleted"
+                                    );
+                                    match route {
+                                        Some(route) if conflict_mode == ConflictMode::Auto => {
+                                            handle_remote_delete(
+                                                &event_device,
+                                                rel_path,
+                                                remote_vclock,
+                                                &state_cache,
+                                                route.local_root,
                                             );
                                         }
-                                        _ => {
-                                            // defer or unknown — log and skip
+                                        Some(_) => {}
+                                        None => {
+                                            info!(path = %rel_path, "no single sync root tracks the deleted file, skipping");
                                         }
                                     }
                                 }
@@ -359,24 +924,42 @@ async fn spawn_state_sync_loop(
                                         "remote conflict resolved, merging vclock"
                                     );
                                     // Merge the resolved vclock into our local state
-                                    let mut cache = state_cache.lock().await;
-                                    let local_path = sync_root
-                                        .as_ref()
-                                        .map(|r| r.join(rel_path))
-                                        .unwrap_or_else(|| std::path::PathBuf::from(rel_path));
-                                    if let Some(entry) = cache.get(&local_path).cloned() {
-                                        let mut updated_vclock = entry.vclock.clone();
-                                        updated_vclock.merge(merged_vclock);
-                                        let updated = tcfs_sync::state::SyncState {
-                                            vclock: updated_vclock,
-                                            ..entry
-                                        };
-                                        cache.set(&local_path, updated);
-                                        let _ = cache.flush();
+                                    let local_root = route_tracked(&cfg, &state_cache, rel_path)
+                                        .and_then(|r| r.local_root);
+                                    let local_path = match local_root {
+                                        Some(root) => join_rel_path(root, rel_path),
+                                        None => Ok(std::path::PathBuf::from(rel_path)),
+                                    };
+                                    match local_path {
+                                        Ok(local_path) => {
+                                            let cache = state_cache.as_ref();
+                                            if let Some(entry) = cache.get(&local_path) {
+                                                let mut updated_vclock = entry.vclock.clone();
+                                                updated_vclock.merge(merged_vclock);
+                                                let updated = tcfs_sync::state::SyncState {
+                                                    vclock: updated_vclock,
+                                                    ..entry
+                                                };
+                                                cache.set(&local_path, updated);
+                                                let _ = cache.flush();
+                                            }
+                                        }
+                                        Err(e) => {
+                                            warn!(path = %rel_path, "rejecting remote path: {e}");
+                                        }
                                     }
                                 }
-                                tcfs_sync::StateEvent::DeviceOnline { device_id: did, .. } => {
+                                tcfs_sync::StateEvent::DeviceOnline {
+                                    device_id: did,
+                                    timestamp,
+                                    ..
+                                } => {
                                     info!(device = %did, "remote device online");
+                                    if let Err(e) =
+                                        record_device_seen(&registry_path(&cfg), did, *timestamp)
+                                    {
+                                        warn!(device = %did, "recording last-seen failed: {e:#}");
+                                    }
                                 }
                                 tcfs_sync::StateEvent::DeviceOffline { device_id: did, .. } => {
                                     info!(device = %did, "remote device offline");
@@ -408,26 +991,150 @@ async fn spawn_state_sync_loop(
     }
 }
 
+/// Where a remote event for one file lands locally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct EventRoute<'a> {
+    /// Directory the event's `rel_path` is joined onto (`None`: find the
+    /// file in the state cache by its relative path)
+    local_root: Option<&'a std::path::Path>,
+    /// Remote prefix the file is downloaded from
+    prefix: &'a str,
+    /// Conflict mode for the file
+    conflict_mode: ConflictMode,
+}
+
+/// Route for files of `root`, or the `sync_root` / `storage.bucket`
+/// defaults without one.
+fn route_to<'a>(
+    cfg: &'a TcfsConfig,
+    root: Option<&'a tcfs_core::config::SyncRoot>,
+    rel_path: &str,
+) -> EventRoute<'a> {
+    EventRoute {
+        local_root: match root {
+            Some(root) => Some(root.local_path.as_path()),
+            None => cfg.sync.sync_root.as_deref(),
+        },
+        prefix: root.map_or(cfg.storage.bucket.as_str(), |r| r.prefix.trim_matches('/')),
+        conflict_mode: cfg.sync.conflict_mode_in(root, rel_path),
+    }
+}
+
+/// Route a `FileSynced` event for `rel_path` whose manifest is
+/// `manifest_path` to the `[[sync.roots]]` entry syncing the manifest's
+/// prefix, falling back to `sync_root`. `None` when roots are configured,
+/// none syncs the prefix, and there is no `sync_root` to fall back to.
+fn route_synced<'a>(
+    cfg: &'a TcfsConfig,
+    manifest_path: &str,
+    rel_path: &str,
+) -> Option<EventRoute<'a>> {
+    let root = tcfs_core::layout::manifest_key_prefix(manifest_path)
+        .and_then(|prefix| cfg.sync.root_for_prefix(prefix));
+    if root.is_none() && !cfg.sync.roots.is_empty() && cfg.sync.sync_root.is_none() {
+        return None;
+    }
+    Some(route_to(cfg, root, rel_path))
+}
+
+/// Route an event that names only `rel_path` (a delete or a resolved
+/// conflict) to the one `[[sync.roots]]` entry whose copy of the file the
+/// state cache tracks or holds a tombstone for, falling back to
+/// `sync_root`. `None` when roots are configured and none, or more than
+/// one, tracks the file and there is no `sync_root` to fall back to.
+fn route_tracked<'a>(
+    cfg: &'a TcfsConfig,
+    state_cache: &tcfs_sync::state::StateCache,
+    rel_path: &str,
+) -> Option<EventRoute<'a>> {
+    let mut tracking = cfg.sync.roots.iter().filter(|root| {
+        join_rel_path(&root.local_path, rel_path).is_ok_and(|path| {
+            state_cache.get(&path).is_some() || state_cache.tombstone(&path).is_some()
+        })
+    });
+    let root = match (tracking.next(), tracking.next()) {
+        (Some(root), None) => Some(root),
+        (Some(_), Some(_)) => {
+            warn!(path = %rel_path, "file is tracked under several sync roots");
+            return None;
+        }
+        (None, _) => None,
+    };
+    if root.is_none() && !cfg.sync.roots.is_empty() && cfg.sync.sync_root.is_none() {
+        return None;
+    }
+    Some(route_to(cfg, root, rel_path))
+}
+
+/// Resolve a remote device's `rel_path` to a local path under `root`.
+///
+/// Rejects absolute and `..` paths so a remote event can never write
+/// outside the sync root.
+fn join_rel_path(root: &std::path::Path, rel_path: &str) -> Result<std::path::PathBuf> {
+    Ok(root.join(tcfs_core::paths::normalize_rel_path(rel_path)?))
+}
+
+/// Build a storage operator from `s3` credentials and check connectivity.
+///
+/// Returns the operator even when the health check fails, so callers can
+/// keep it for retry; the flag reports whether storage was reachable.
+pub(crate) async fn connect_storage(
+    storage: &tcfs_core::config::StorageConfig,
+    s3: &tcfs_secrets::S3Credentials,
+) -> Result<(opendal::Operator, bool)> {
+    let op = tcfs_storage::operator::build_from_core_config(
+        storage,
+        &s3.access_key_id,
+        s3.secret_access_key.expose_secret(),
+    )?;
+    match tcfs_storage::check_health(&op).await {
+        Ok(()) => {
+            info!(endpoint = %storage.endpoint, "SeaweedFS: connected");
+            Ok((op, true))
+        }
+        Err(e) => {
+            warn!(endpoint = %storage.endpoint, "SeaweedFS: {e}");
+            Ok((op, false))
+        }
+    }
+}
+
 /// Handle auto-pull logic for a remote FileSynced event.
+///
+/// `conflict_mode` is the mode configured for `rel_path`: under "auto" a
+/// concurrent edit goes to `resolver`, under "interactive" it is left alone
+/// and returned for review. Fast-forwards are applied in both modes.
+/// `manifest` is the already verified manifest stored at `manifest_path`;
+/// its hash and clock are compared, and a pull restores from it.
 #[allow(clippy::too_many_arguments)]
 async fn handle_auto_pull(
+    conflict_mode: ConflictMode,
     device_id: &str,
     remote_device: &str,
     rel_path: &str,
-    remote_blake3: &str,
-    remote_vclock: &tcfs_sync::conflict::VectorClock,
+    manifest: &tcfs_sync::manifest::SyncManifest,
     manifest_path: &str,
+    remote_modified: u64,
+    resolver: &dyn ConflictResolver,
     operator: &Arc<tokio::sync::Mutex<Option<opendal::Operator>>>,
-    state_cache: &Arc<tokio::sync::Mutex<tcfs_sync::state::StateCache>>,
+    state_cache: &Arc<tcfs_sync::state::StateCache>,
     sync_root: Option<&std::path::Path>,
     storage_prefix: &str,
-) {
+    mode_umask: u32,
+    breaker: &mut PullBreaker,
+) -> Option<tcfs_sync::conflict::ConflictInfo> {
     // Determine local path for this rel_path
     let local_path = match sync_root {
-        Some(root) => root.join(rel_path),
+        Some(root) => match join_rel_path(root, rel_path) {
+            Ok(path) => path,
+            Err(e) => {
+                warn!(path = %rel_path, from = %remote_device, "rejecting remote path: {e}");
+                return None;
+            }
+        },
         None => {
             // Try to find in state cache by rel_path
-            let cache = state_cache.lock().await;
+            let cache = state_cache.as_ref();
             match cache.get_by_rel_path(rel_path) {
                 Some((key, _)) => std::path::PathBuf::from(key),
                 None => {
@@ -435,43 +1142,61 @@ async fn handle_auto_pull(
                         path = %rel_path,
                         "no sync_root configured and file not in state cache, skipping auto-pull"
                     );
-                    return;
+                    return None;
                 }
             }
         }
     };
 
+    let remote_vclock = &manifest.vclock;
     // Compare vector clocks
     let (local_blake3, local_vclock) = {
-        let cache = state_cache.lock().await;
+        let cache = state_cache.as_ref();
         match cache.get(&local_path) {
             Some(entry) => (entry.blake3.clone(), entry.vclock.clone()),
             None => {
+                // A deletion we applied that is not older than this version
+                // means the event is stale
+                if let Some(tombstone) = cache.tombstone(&local_path) {
+                    if tombstone
+                        .vclock
+                        .partial_cmp_vc(remote_vclock)
+                        .is_some_and(|o| o.is_ge())
+                    {
+                        info!(path = %rel_path, from = %remote_device, "ignoring stale re-add of deleted file");
+                        return None;
+                    }
+                    cache.clear_tombstone(&local_path);
+                }
                 // New file from remote — download it
                 info!(path = %rel_path, from = %remote_device, "new file from remote, pulling");
-                drop(cache);
                 do_auto_download(
                     device_id,
+                    manifest,
                     manifest_path,
+                    rel_path,
                     &local_path,
                     operator,
                     state_cache,
                     storage_prefix,
+                    mode_umask,
+                    breaker,
                 )
                 .await;
-                return;
+                return None;
             }
         }
     };
 
-    let outcome = tcfs_sync::conflict::compare_clocks(
+    let outcome = tcfs_sync::conflict::compare_clocks_at(
         &local_vclock,
         remote_vclock,
         &local_blake3,
-        remote_blake3,
+        &manifest.file_hash,
         rel_path,
         device_id,
         remote_device,
+        state_cache.clock().as_ref(),
     );
 
     match outcome {
@@ -485,54 +1210,146 @@ async fn handle_auto_pull(
             info!(path = %rel_path, from = %remote_device, "remote is newer, auto-pulling");
             do_auto_download(
                 device_id,
+                manifest,
                 manifest_path,
+                rel_path,
                 &local_path,
                 operator,
                 state_cache,
                 storage_prefix,
+                mode_umask,
+                breaker,
             )
             .await;
         }
-        tcfs_sync::conflict::SyncOutcome::Conflict(ref conflict_info) => {
+        tcfs_sync::conflict::SyncOutcome::Conflict(mut conflict_info) => {
             info!(
                 path = %rel_path,
                 local_device = %conflict_info.local_device,
                 remote_device = %conflict_info.remote_device,
-                "conflict detected, applying AutoResolver"
+                "conflict detected"
             );
-            let resolver = tcfs_sync::conflict::AutoResolver;
-            match resolver.resolve(conflict_info) {
+            if conflict_mode != ConflictMode::Auto {
+                info!(path = %rel_path, from = %remote_device, "conflict queued for review");
+                return Some(conflict_info);
+            }
+            conflict_info.local_modified = std::fs::metadata(&local_path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs());
+            conflict_info.remote_modified = remote_modified;
+            match resolver.resolve(&conflict_info) {
                 Some(tcfs_sync::conflict::Resolution::KeepLocal) => {
-                    info!(path = %rel_path, "AutoResolver: keeping local");
+                    info!(path = %rel_path, "auto strategy: keeping local");
                 }
                 Some(tcfs_sync::conflict::Resolution::KeepRemote) => {
-                    info!(path = %rel_path, "AutoResolver: keeping remote");
+                    info!(path = %rel_path, "auto strategy: keeping remote");
                     do_auto_download(
                         device_id,
+                        manifest,
                         manifest_path,
+                        rel_path,
                         &local_path,
                         operator,
                         state_cache,
                         storage_prefix,
+                        mode_umask,
+                        breaker,
                     )
                     .await;
                 }
                 _ => {
-                    info!(path = %rel_path, "AutoResolver: deferred");
+                    info!(path = %rel_path, "auto strategy: deferred");
                 }
             }
         }
     }
+    None
+}
+
+/// Apply a remote `FileDeleted` event to the local copy of `rel_path`.
+///
+/// The file and its state entry are removed only when the deletion is
+/// causally newer than the last sync and the file is unchanged since; a
+/// tombstone is always recorded so an older `FileSynced` event for the path
+/// does not resurrect it.
+fn handle_remote_delete(
+    remote_device: &str,
+    rel_path: &str,
+    remote_vclock: &tcfs_sync::conflict::VectorClock,
+    state_cache: &Arc<tcfs_sync::state::StateCache>,
+    sync_root: Option<&std::path::Path>,
+) {
+    let cache = state_cache.as_ref();
+    let local_path = match sync_root {
+        Some(root) => match join_rel_path(root, rel_path) {
+            Ok(path) => path,
+            Err(e) => {
+                warn!(path = %rel_path, from = %remote_device, "rejecting remote path: {e}");
+                return;
+            }
+        },
+        None => match cache.get_by_rel_path(rel_path) {
+            Some((key, _)) => std::path::PathBuf::from(key),
+            None => {
+                info!(path = %rel_path, "no sync_root configured and file not in state cache, skipping delete");
+                return;
+            }
+        },
+    };
+
+    if let Some(entry) = cache.get(&local_path) {
+        if entry.vclock.partial_cmp_vc(remote_vclock) != Some(std::cmp::Ordering::Less) {
+            info!(path = %rel_path, from = %remote_device, "local version not older than deletion, keeping");
+            return;
+        }
+        match cache.needs_sync(&local_path) {
+            Ok(None) | Err(_) => {}
+            Ok(Some(reason)) => {
+                info!(path = %rel_path, %reason, "local changes since last sync, keeping despite remote delete");
+                return;
+            }
+        }
+        // Drop the entry while the path still canonicalizes to its key
+        cache.remove(&local_path);
+        match std::fs::remove_file(&local_path) {
+            Ok(()) => {
+                info!(path = %local_path.display(), from = %remote_device, "applied remote delete")
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                warn!(path = %local_path.display(), "remote delete failed: {e}");
+                cache.set(&local_path, entry);
+                return;
+            }
+        }
+    }
+
+    cache.set_tombstone(&local_path, remote_vclock.clone());
+    if let Err(e) = cache.flush() {
+        warn!("state flush after remote delete failed: {e}");
+    }
 }
 
 /// Download a file from remote and update state cache.
+///
+/// The content comes from `manifest`, as verified, rather than from a fresh
+/// read of `manifest_path`. Skipped while `breaker` is open; once its
+/// cooldown has elapsed, storage is probed with `check_health` before
+/// downloading.
+#[allow(clippy::too_many_arguments)]
 async fn do_auto_download(
     device_id: &str,
+    manifest: &tcfs_sync::manifest::SyncManifest,
     manifest_path: &str,
+    rel_path: &str,
     local_path: &std::path::Path,
     operator: &Arc<tokio::sync::Mutex<Option<opendal::Operator>>>,
-    state_cache: &Arc<tokio::sync::Mutex<tcfs_sync::state::StateCache>>,
+    state_cache: &Arc<tcfs_sync::state::StateCache>,
     storage_prefix: &str,
+    mode_umask: u32,
+    breaker: &mut PullBreaker,
 ) {
     // Ensure parent directory exists
     if let Some(parent) = local_path.parent() {
@@ -542,40 +1359,96 @@ async fn do_auto_download(
         }
     }
 
-    let op = operator.lock().await;
-    let op = match op.as_ref() {
-        Some(op) => op.clone(),
+    let op = match operator.lock().await.clone() {
+        Some(op) => op,
         None => {
             warn!("no storage operator for auto-pull");
             return;
         }
     };
-    drop(operator.lock().await);
 
-    let result = {
-        let mut cache = state_cache.lock().await;
-        tcfs_sync::engine::download_file_with_device(
-            &op,
-            manifest_path,
-            local_path,
-            storage_prefix,
-            None,
-            device_id,
-            Some(&mut cache),
-            None,
-        )
+    let now = state_cache.clock().instant();
+    match breaker.gate(now) {
+        Gate::Closed => {}
+        Gate::Open => {
+            info!(
+                path = %local_path.display(),
+                failures = breaker.failures(),
+                "storage unhealthy, auto-pull skipped"
+            );
+            return;
+        }
+        Gate::Probe => match tcfs_storage::check_health(&op).await {
+            Ok(()) => info!("storage reachable again, resuming auto-pulls"),
+            Err(e) => {
+                warn!(
+                    path = %local_path.display(),
+                    cooldown_secs = breaker.cooldown().as_secs(),
+                    "storage still unhealthy, auto-pull skipped: {e}"
+                );
+                breaker.trip(now);
+                return;
+            }
+        },
+    }
+
+    // The index entry, while it still names the event's content, says how
+    // the file is stored and carries its own mode; the manifest's mode is
+    // shared by every path with this content
+    let indexed = tcfs_sync::engine::resolve_index_entry(&op, storage_prefix, rel_path)
         .await
+        .ok()
+        .filter(|entry| entry.manifest_path(storage_prefix) == manifest_path);
+    let result = {
+        let cache = state_cache.as_ref();
+        match indexed {
+            Some(entry) if entry.is_packed() => {
+                tcfs_sync::engine::download_indexed(
+                    &op,
+                    storage_prefix,
+                    rel_path,
+                    &entry,
+                    local_path,
+                    Default::default(),
+                    device_id,
+                    Some(cache),
+                    None,
+                    mode_umask,
+                )
+                .await
+            }
+            indexed => {
+                let mode = match &indexed {
+                    Some(entry) => tcfs_sync::engine::RestoreMode::indexed(entry, mode_umask),
+                    None => tcfs_sync::engine::RestoreMode::manifest(mode_umask),
+                };
+                tcfs_sync::engine::download_manifest(
+                    &op,
+                    manifest,
+                    manifest_path,
+                    local_path,
+                    storage_prefix,
+                    Default::default(),
+                    device_id,
+                    Some(cache),
+                    None,
+                    mode,
+                )
+                .await
+            }
+        }
     };
 
     match result {
         Ok(dl) => {
+            breaker.record_success();
             info!(
                 path = %local_path.display(),
                 bytes = dl.bytes,
                 "auto-pull complete"
             );
             // Flush state cache
-            let mut cache = state_cache.lock().await;
+            let cache = state_cache.as_ref();
             let _ = cache.flush();
         }
         Err(e) => {
@@ -583,6 +1456,13 @@ async fn do_auto_download(
                 path = %local_path.display(),
                 "auto-pull failed: {e}"
             );
+            if breaker.record_failure(now) {
+                warn!(
+                    failures = breaker.failures(),
+                    cooldown_secs = breaker.cooldown().as_secs(),
+                    "auto-pulls paused after repeated failures"
+                );
+            }
         }
     }
 }
@@ -608,3 +1488,758 @@ fn notify_stopping() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::time::Instant;
+
+    use tcfs_sync::conflict::VectorClock;
+    use tcfs_sync::manifest::SyncManifest;
+
+    fn unsigned_manifest(file_hash: &str, vclock: VectorClock) -> SyncManifest {
+        SyncManifest {
+            version: 2,
+            file_hash: file_hash.into(),
+            file_size: 3,
+            chunks: vec![file_hash.into()],
+            vclock,
+            written_by: "desktop".into(),
+            written_at: 1000,
+            rel_path: None,
+            encrypted_file_key: None,
+            mode: None,
+            compressed: Vec::new(),
+            chunk_keys: Vec::new(),
+            chunk_shard_depth: 0,
+            signature: None,
+            manifest_checksum: None,
+        }
+    }
+
+    async fn read_manifest(op: &opendal::Operator, path: &str) -> SyncManifest {
+        SyncManifest::from_bytes(&op.read(path).await.unwrap().to_bytes()).unwrap()
+    }
+
+    fn pinned_config(dir: &Path, registry: &str, device_id: &str) -> TcfsConfig {
+        let mut config = TcfsConfig::default();
+        config.sync.device_id = Some(device_id.into());
+        config.sync.device_identity = Some(dir.join(registry));
+        config
+    }
+
+    #[test]
+    fn pinned_device_id_skips_registry() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let config = pinned_config(tmp.path(), "devices.json", "ci-node-1");
+
+        assert_eq!(resolve_device_id(&config, "runner").unwrap(), "ci-node-1");
+        assert!(!tmp.path().join("devices.json").exists());
+
+        let bad = pinned_config(tmp.path(), "devices.json", "ci/node");
+        assert!(resolve_device_id(&bad, "runner").is_err());
+    }
+
+    #[tokio::test]
+    async fn daemons_with_same_pinned_id_share_clock_entries() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let op = opendal::Operator::new(opendal::services::Memory::default())
+            .unwrap()
+            .finish();
+        let prefix = "fleet";
+
+        // Two daemons with different names and registries, same pin
+        let id_a =
+            resolve_device_id(&pinned_config(tmp.path(), "a.json", "ci-node"), "host-a").unwrap();
+        let id_b =
+            resolve_device_id(&pinned_config(tmp.path(), "b.json", "ci-node"), "host-b").unwrap();
+        assert_eq!(id_a, id_b);
+
+        // Daemon A pushes
+        let file_a = tmp.path().join("a.txt");
+        std::fs::write(&file_a, b"v1").unwrap();
+        let state_a = tcfs_sync::state::StateCache::open(&tmp.path().join("a.db")).unwrap();
+        let pushed = tcfs_sync::engine::upload_file_with_device(
+            &op,
+            &file_a,
+            prefix,
+            &state_a,
+            None,
+            &id_a,
+            Some("shared.txt"),
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+
+        // Daemon B pulls, edits and pushes on top
+        let file_b = tmp.path().join("b.txt");
+        let state_b = tcfs_sync::state::StateCache::open(&tmp.path().join("b.db")).unwrap();
+        tcfs_sync::engine::download_file_with_device(
+            &op,
+            &pushed.remote_path,
+            &file_b,
+            prefix,
+            None,
+            &id_b,
+            Some(&state_b),
+            None,
+            tcfs_sync::engine::RestoreMode::default(),
+        )
+        .await
+        .unwrap();
+        std::fs::write(&file_b, b"v2, edited on b").unwrap();
+        let repushed = tcfs_sync::engine::upload_file_with_device(
+            &op,
+            &file_b,
+            prefix,
+            &state_b,
+            None,
+            &id_b,
+            Some("shared.txt"),
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+
+        // One clock entry, advanced by both daemons
+        let raw = op.read(&repushed.remote_path).await.unwrap();
+        let manifest = tcfs_sync::manifest::SyncManifest::from_bytes(&raw.to_bytes()).unwrap();
+        assert_eq!(manifest.vclock.clocks.len(), 1);
+        assert_eq!(manifest.vclock.get("ci-node"), 2);
+    }
+
+    #[tokio::test]
+    async fn remote_delete_removes_file_and_blocks_stale_readd() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let root = tmp.path().join("sync");
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        let cache =
+            Arc::new(tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap());
+
+        let mut synced = VectorClock::new();
+        synced.tick("laptop");
+        let track = |rel: &str, vclock: &VectorClock| {
+            let path = root.join(rel);
+            std::fs::write(&path, rel).unwrap();
+            let state = tcfs_sync::state::make_sync_state_full(
+                &path,
+                blake3::hash(rel.as_bytes()).to_hex().to_string(),
+                1,
+                format!("tcfs/manifests/{rel}"),
+                vclock.clone(),
+                "laptop".into(),
+            )
+            .unwrap();
+            cache.set(&path, state);
+            path
+        };
+        let notes = track("docs/notes.md", &synced);
+        let mut edited = synced.clone();
+        edited.tick("laptop");
+        let plan = track("docs/plan.md", &edited);
+
+        // The desktop deleted notes.md after seeing our version
+        let mut deletion = synced.clone();
+        deletion.tick("desktop");
+        handle_remote_delete("desktop", "docs/notes.md", &deletion, &cache, Some(&root));
+        assert!(!notes.exists());
+        assert!(cache.get(&notes).is_none());
+        assert_eq!(cache.tombstone(&notes).unwrap().vclock, deletion);
+
+        // A deletion that did not see our latest edit leaves the file alone
+        handle_remote_delete("desktop", "docs/plan.md", &deletion, &cache, Some(&root));
+        assert!(plan.exists());
+        assert!(cache.get(&plan).is_some());
+
+        // A late FileSynced for the version that was deleted does not bring it back
+        let no_storage = Arc::new(tokio::sync::Mutex::new(None));
+        handle_auto_pull(
+            ConflictMode::Auto,
+            "laptop",
+            "desktop",
+            "docs/notes.md",
+            &unsigned_manifest("0123", synced.clone()),
+            "tcfs/manifests/0123",
+            0,
+            &tcfs_sync::conflict::AutoResolver,
+            &no_storage,
+            &cache,
+            Some(&root),
+            "tcfs",
+            0o022,
+            &mut PullBreaker::new(5, Duration::from_secs(60)),
+        )
+        .await;
+        assert!(!notes.exists());
+        assert!(cache.tombstone(&notes).is_some());
+    }
+
+    #[tokio::test]
+    async fn synced_events_route_to_the_root_for_their_prefix() {
+        use tcfs_core::config::SyncRoot;
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let mut config = TcfsConfig::default();
+        for (dir, prefix, mode) in [
+            ("a", "alpha", None),
+            ("b", "/beta/", Some(ConflictMode::Interactive)),
+        ] {
+            config.sync.roots.push(SyncRoot {
+                local_path: tmp.path().join(dir),
+                prefix: prefix.into(),
+                conflict_mode: mode,
+            });
+        }
+        let op = opendal::Operator::new(opendal::services::Memory::default())
+            .unwrap()
+            .finish();
+        let operator = Arc::new(tokio::sync::Mutex::new(Some(op.clone())));
+        let cache =
+            Arc::new(tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap());
+
+        let src = tmp.path().join("notes.md");
+        std::fs::write(&src, b"alpha notes").unwrap();
+        let remote_state = tcfs_sync::state::StateCache::open(&tmp.path().join("r.db")).unwrap();
+        let pushed = tcfs_sync::engine::upload_file(&op, &src, "alpha", &remote_state, None)
+            .await
+            .unwrap();
+
+        let route = route_synced(&config, &pushed.remote_path, "notes.md").unwrap();
+        assert_eq!(route.local_root, Some(tmp.path().join("a").as_path()));
+        assert_eq!(route.prefix, "alpha");
+        assert_eq!(route.conflict_mode, ConflictMode::Auto);
+
+        handle_auto_pull(
+            route.conflict_mode,
+            "laptop",
+            "desktop",
+            "notes.md",
+            &read_manifest(&op, &pushed.remote_path).await,
+            &pushed.remote_path,
+            0,
+            &tcfs_sync::conflict::AutoResolver,
+            &operator,
+            &cache,
+            route.local_root,
+            route.prefix,
+            0o022,
+            &mut PullBreaker::new(5, Duration::from_secs(60)),
+        )
+        .await;
+        assert_eq!(
+            std::fs::read(tmp.path().join("a/notes.md")).unwrap(),
+            b"alpha notes"
+        );
+        assert!(!tmp.path().join("b/notes.md").exists());
+
+        // A delete names only the path: it goes to the root tracking it
+        let tracked = route_tracked(&config, &cache, "notes.md").unwrap();
+        assert_eq!(tracked.local_root, route.local_root);
+
+        // The other prefix goes to the other root with its own mode
+        let beta = route_synced(&config, "beta/manifests/0123", "notes.md").unwrap();
+        assert_eq!(beta.local_root, Some(tmp.path().join("b").as_path()));
+        assert_eq!(beta.prefix, "beta");
+        assert_eq!(beta.conflict_mode, ConflictMode::Interactive);
+
+        // Prefixes no root syncs are skipped, unless sync_root catches them
+        assert_eq!(route_synced(&config, "gamma/manifests/0123", "x"), None);
+        config.sync.sync_root = Some(tmp.path().join("legacy"));
+        let legacy = route_synced(&config, "gamma/manifests/0123", "x").unwrap();
+        assert_eq!(legacy.local_root, Some(tmp.path().join("legacy").as_path()));
+        assert_eq!(legacy.prefix, config.storage.bucket);
+    }
+
+    #[tokio::test]
+    async fn repeated_pull_failures_pause_auto_pull_until_cooldown() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let op = opendal::Operator::new(opendal::services::Memory::default())
+            .unwrap()
+            .finish();
+        let operator = Arc::new(tokio::sync::Mutex::new(Some(op.clone())));
+        let cache =
+            Arc::new(tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap());
+        let mut breaker = PullBreaker::new(3, Duration::from_secs(3600));
+
+        let src = tmp.path().join("src.txt");
+        std::fs::write(&src, b"remote content").unwrap();
+        let remote_state = tcfs_sync::state::StateCache::open(&tmp.path().join("r.db")).unwrap();
+        let pushed = tcfs_sync::engine::upload_file(&op, &src, "tcfs", &remote_state, None)
+            .await
+            .unwrap();
+        let dest = tmp.path().join("sync/file.txt");
+        let manifest = read_manifest(&op, &pushed.remote_path).await;
+        let mut missing = manifest.clone();
+        missing.chunks = vec!["0".repeat(64)];
+
+        // Three failed pulls (a chunk that is not there) open the breaker
+        for _ in 0..3 {
+            do_auto_download(
+                "laptop",
+                &missing,
+                "tcfs/manifests/missing",
+                "file.txt",
+                &dest,
+                &operator,
+                &cache,
+                "tcfs",
+                0o022,
+                &mut breaker,
+            )
+            .await;
+        }
+        assert_eq!(breaker.gate(Instant::now()), Gate::Open);
+        assert_eq!(breaker.failures(), 3);
+
+        // While open, even a pull that would succeed is not attempted
+        do_auto_download(
+            "laptop",
+            &manifest,
+            &pushed.remote_path,
+            "file.txt",
+            &dest,
+            &operator,
+            &cache,
+            "tcfs",
+            0o022,
+            &mut breaker,
+        )
+        .await;
+        assert!(!dest.exists());
+
+        // Once the cooldown has passed, a healthy probe lets it through
+        breaker.set_limits(3, Duration::ZERO);
+        do_auto_download(
+            "laptop",
+            &manifest,
+            &pushed.remote_path,
+            "file.txt",
+            &dest,
+            &operator,
+            &cache,
+            "tcfs",
+            0o022,
+            &mut breaker,
+        )
+        .await;
+        assert_eq!(std::fs::read(&dest).unwrap(), b"remote content");
+        assert_eq!(breaker.gate(Instant::now()), Gate::Closed);
+        assert_eq!(breaker.failures(), 0);
+    }
+
+    #[tokio::test]
+    async fn auto_pull_restores_the_verified_manifest() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let op = opendal::Operator::new(opendal::services::Memory::default())
+            .unwrap()
+            .finish();
+        let operator = Arc::new(tokio::sync::Mutex::new(Some(op.clone())));
+        let cache =
+            Arc::new(tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap());
+        let remote_state = tcfs_sync::state::StateCache::open(&tmp.path().join("r.db")).unwrap();
+        let mut pushed = Vec::new();
+        for (name, content) in [("a.txt", b"verified"), ("b.txt", b"swapped!")] {
+            let src = tmp.path().join(name);
+            std::fs::write(&src, content).unwrap();
+            pushed.push(
+                tcfs_sync::engine::upload_file(&op, &src, "tcfs", &remote_state, None)
+                    .await
+                    .unwrap(),
+            );
+        }
+        let manifest = read_manifest(&op, &pushed[0].remote_path).await;
+        let mut vclock = VectorClock::new();
+        vclock.tick("desktop");
+        assert!(check_event_manifest(&manifest, &pushed[1].hash, &manifest.vclock, false).is_err());
+        assert!(check_event_manifest(&manifest, &pushed[0].hash, &vclock, false).is_err());
+        assert!(check_event_manifest(&manifest, &pushed[0].hash, &vclock, true).is_err());
+        check_event_manifest(&manifest, &pushed[0].hash, &manifest.vclock, false).unwrap();
+
+        // The object is replaced between verification and the pull
+        let swapped = op.read(&pushed[1].remote_path).await.unwrap();
+        op.write(&pushed[0].remote_path, swapped).await.unwrap();
+
+        let dest = tmp.path().join("sync/a.txt");
+        do_auto_download(
+            "laptop",
+            &manifest,
+            &pushed[0].remote_path,
+            "a.txt",
+            &dest,
+            &operator,
+            &cache,
+            "tcfs",
+            0o022,
+            &mut PullBreaker::new(5, Duration::from_secs(60)),
+        )
+        .await;
+        assert_eq!(std::fs::read(&dest).unwrap(), b"verified");
+        assert_eq!(cache.get(&dest).unwrap().blake3, pushed[0].hash);
+    }
+
+    #[tokio::test]
+    async fn path_rules_queue_conflicts_under_interactive_paths() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let op = opendal::Operator::new(opendal::services::Memory::default())
+            .unwrap()
+            .finish();
+        let operator = Arc::new(tokio::sync::Mutex::new(Some(op.clone())));
+        let root = tmp.path().join("sync");
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("build")).unwrap();
+        let cache =
+            Arc::new(tcfs_sync::state::StateCache::open(&tmp.path().join("state.db")).unwrap());
+
+        let src = tmp.path().join("remote.txt");
+        std::fs::write(&src, b"remote edit").unwrap();
+        let remote_state = tcfs_sync::state::StateCache::open(&tmp.path().join("r.db")).unwrap();
+        let pushed = tcfs_sync::engine::upload_file_with_device(
+            &op,
+            &src,
+            "tcfs",
+            &remote_state,
+            None,
+            "desktop",
+            None,
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+        let manifest = read_manifest(&op, &pushed.remote_path).await;
+
+        let mut local = VectorClock::new();
+        local.tick("laptop");
+        for rel in ["src/lib.rs", "build/lib.o"] {
+            let path = root.join(rel);
+            std::fs::write(&path, b"local edit").unwrap();
+            let state = tcfs_sync::state::make_sync_state_full(
+                &path,
+                blake3::hash(b"local edit").to_hex().to_string(),
+                1,
+                format!("tcfs/manifests/{rel}"),
+                local.clone(),
+                "laptop".into(),
+            )
+            .unwrap();
+            cache.set(&path, state);
+        }
+
+        let sync = tcfs_core::config::SyncConfig {
+            conflict_mode: ConflictMode::Auto,
+            path_rules: vec![tcfs_core::config::PathRule {
+                pattern: "src/**".into(),
+                conflict_mode: ConflictMode::Interactive,
+            }],
+            ..Default::default()
+        };
+        let resolver = tcfs_sync::conflict::resolver_for("remote-wins").unwrap();
+        let mut queued = Vec::new();
+        for rel in ["src/lib.rs", "build/lib.o"] {
+            let conflict = handle_auto_pull(
+                sync.conflict_mode_for(rel),
+                "laptop",
+                "desktop",
+                rel,
+                &manifest,
+                &pushed.remote_path,
+                0,
+                resolver.as_ref(),
+                &operator,
+                &cache,
+                Some(&root),
+                "tcfs",
+                0o022,
+                &mut PullBreaker::new(5, Duration::from_secs(60)),
+            )
+            .await;
+            queued.extend(conflict);
+        }
+
+        // The source file waits for review; the build output took the remote side
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].rel_path, "src/lib.rs");
+        assert_eq!(
+            std::fs::read(root.join("src/lib.rs")).unwrap(),
+            b"local edit"
+        );
+        assert_eq!(
+            std::fs::read(root.join("build/lib.o")).unwrap(),
+            b"remote edit"
+        );
+    }
+
+    #[test]
+    fn manifest_signed_by_another_device_is_rejected() {
+        let key_a = tcfs_crypto::DeviceSigningKey::generate();
+        let key_b = tcfs_crypto::DeviceSigningKey::generate();
+        let mut registry = tcfs_secrets::device::DeviceRegistry::default();
+        let id_a = registry.enroll("device-a", "age1a", None);
+        let id_b = registry.enroll("device-b", "age1b", None);
+        registry.set_signing_key(&id_a, &key_a.public_key());
+        registry.set_signing_key(&id_b, &key_b.public_key());
+
+        let mut vclock = tcfs_sync::conflict::VectorClock::new();
+        vclock.tick(&id_b);
+        let mut manifest = tcfs_sync::manifest::SyncManifest {
+            version: 2,
+            file_hash: "abc123".into(),
+            file_size: 3,
+            chunks: vec!["abc123".into()],
+            vclock,
+            written_by: id_b.clone(),
+            written_at: 1000,
+            rel_path: Some("notes.txt".into()),
+            encrypted_file_key: None,
+            mode: None,
+            compressed: Vec::new(),
+            chunk_keys: Vec::new(),
+            chunk_shard_depth: 0,
+            signature: None,
+            manifest_checksum: None,
+        };
+
+        // A signs a manifest claiming to be from B
+        manifest.sign(&key_a).unwrap();
+        let parsed =
+            tcfs_sync::manifest::SyncManifest::from_bytes(&manifest.to_bytes().unwrap()).unwrap();
+        let err = check_manifest_author(&parsed, &registry, &[]).unwrap_err();
+        assert!(err.to_string().contains("not signed by it"), "{err:#}");
+
+        // Unsigned is rejected too; B's own signature passes
+        manifest.signature = None;
+        assert!(check_manifest_author(&manifest, &registry, &[]).is_err());
+        manifest.sign(&key_b).unwrap();
+        let parsed =
+            tcfs_sync::manifest::SyncManifest::from_bytes(&manifest.to_bytes().unwrap()).unwrap();
+        check_manifest_author(&parsed, &registry, &[]).unwrap();
+
+        // Tampering after signing breaks it
+        let mut tampered = parsed.clone();
+        tampered.chunks = vec!["evil".into()];
+        assert!(check_manifest_author(&tampered, &registry, &[]).is_err());
+
+        // Naming an unknown or keyless device does not skip verification
+        let legacy = registry.enroll("legacy", "age1legacy", None);
+        for author in ["unknown-device", legacy.as_str()] {
+            tampered.written_by = author.into();
+            let err = check_manifest_author(&tampered, &registry, &[]).unwrap_err();
+            assert!(
+                err.to_string().contains("unknown device")
+                    || err.to_string().contains("no manifest signing key"),
+                "{err:#}"
+            );
+        }
+
+        // Only an explicit opt-in accepts a legacy device's unsigned manifests
+        manifest.written_by = legacy.clone();
+        manifest.signature = None;
+        check_manifest_author(&manifest, &registry, std::slice::from_ref(&legacy)).unwrap();
+        assert!(check_manifest_author(&manifest, &registry, &["other".into()]).is_err());
+
+        // A revoked device is rejected even with a valid signature
+        manifest.written_by = id_b.clone();
+        manifest.sign(&key_b).unwrap();
+        registry.revoke("device-b");
+        assert!(check_manifest_author(&manifest, &registry, &[]).is_err());
+    }
+
+    #[tokio::test]
+    async fn published_signing_key_is_enrolled_only_with_valid_binding() {
+        let op = opendal::Operator::new(opendal::services::Memory::default())
+            .unwrap()
+            .finish();
+        let master = tcfs_crypto::MasterKey::from_bytes([9u8; 32]);
+        let key_c = tcfs_crypto::DeviceSigningKey::generate();
+        publish_signing_key(&op, "work", "device-c", "laptop-c", &key_c, &master)
+            .await
+            .unwrap();
+
+        // Without the master key's binding the record is worthless
+        let mut registry = tcfs_secrets::device::DeviceRegistry::default();
+        let wrong = tcfs_crypto::MasterKey::from_bytes([1u8; 32]);
+        assert!(
+            enroll_published_key(&op, "work", "device-c", &mut registry, &wrong)
+                .await
+                .is_err()
+        );
+        assert!(registry.find_by_id("device-c").is_none());
+
+        // A forger with bucket access can publish, but cannot bind
+        let forged = tcfs_crypto::DeviceSigningKey::generate();
+        tcfs_secrets::device::PublishedSigningKey {
+            device_id: "device-f".into(),
+            name: "forger".into(),
+            signing_public_key: forged.public_key(),
+            tag: tcfs_crypto::key_binding_tag(&wrong, "device-f", &forged.public_key()),
+        }
+        .publish(&op, "work")
+        .await
+        .unwrap();
+        assert!(
+            enroll_published_key(&op, "work", "device-f", &mut registry, &master)
+                .await
+                .is_err()
+        );
+
+        assert!(
+            enroll_published_key(&op, "work", "device-c", &mut registry, &master)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            registry
+                .find_by_id("device-c")
+                .unwrap()
+                .signing_public_key
+                .as_deref(),
+            Some(key_c.public_key().as_str())
+        );
+        assert!(
+            !enroll_published_key(&op, "work", "nobody", &mut registry, &master)
+                .await
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn future_stamped_manifest_skips_timestamp_tiebreak() {
+        use tcfs_sync::conflict::{ConflictResolver, NewestResolver, Resolution, VectorClock};
+
+        let now = 1_700_000_000;
+        let mut manifest = tcfs_sync::manifest::SyncManifest {
+            version: 2,
+            file_hash: "abc123".into(),
+            file_size: 3,
+            chunks: vec!["abc123".into()],
+            vclock: VectorClock::new(),
+            written_by: "zz-desktop".into(),
+            written_at: now + 86_400,
+            rel_path: Some("notes.txt".into()),
+            encrypted_file_key: None,
+            mode: None,
+            compressed: Vec::new(),
+            chunk_keys: Vec::new(),
+            chunk_shard_depth: 0,
+            signature: None,
+            manifest_checksum: None,
+        };
+        assert_eq!(manifest.clock_skew(now, 3600), Some(86_400));
+
+        let mut conflict = tcfs_sync::conflict::ConflictInfo {
+            rel_path: "notes.txt".into(),
+            local_vclock: VectorClock::new(),
+            remote_vclock: VectorClock::new(),
+            local_blake3: "local".into(),
+            remote_blake3: "abc123".into(),
+            local_device: "laptop".into(),
+            remote_device: "zz-desktop".into(),
+            detected_at: now,
+            local_modified: now - 60,
+            remote_modified: 0,
+        };
+
+        // A day ahead: the timestamp is dropped and "newest" falls back to
+        // device order, where laptop wins
+        conflict.remote_modified = remote_modified(&manifest, now + 86_400, now, 3600);
+        assert_eq!(conflict.remote_modified, 0);
+        assert_eq!(
+            NewestResolver.resolve(&conflict),
+            Some(Resolution::KeepLocal)
+        );
+
+        // Within the allowed skew the remote timestamp still counts
+        manifest.written_at = now + 600;
+        assert_eq!(manifest.clock_skew(now, 3600), None);
+        conflict.remote_modified = remote_modified(&manifest, now + 600, now, 3600);
+        assert_eq!(conflict.remote_modified, now + 600);
+        assert_eq!(
+            NewestResolver.resolve(&conflict),
+            Some(Resolution::KeepRemote)
+        );
+    }
+
+    /// Answer the NATS client handshake on `listener`: send INFO, then
+    /// PONG to every PING. Enough for a connect to succeed.
+    async fn fake_nats_server(listener: tokio::net::TcpListener) {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let port = listener.local_addr().unwrap().port();
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                return;
+            };
+            tokio::spawn(async move {
+                let (read, mut write) = stream.into_split();
+                let info = format!(
+                    "INFO {{\"server_id\":\"fake\",\"version\":\"2.10.0\",\"go\":\"go1.22\",\
+                     \"host\":\"127.0.0.1\",\"port\":{port},\"headers\":true,\
+                     \"max_payload\":1048576,\"proto\":1}}\r\n"
+                );
+                if write.write_all(info.as_bytes()).await.is_err() {
+                    return;
+                }
+                let mut lines = BufReader::new(read).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if line.starts_with("PING") && write.write_all(b"PONG\r\n").await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    }
+
+    #[tokio::test]
+    async fn nats_reconnect_raises_nats_ok_once_reachable() {
+        // A port nothing listens on yet
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let url = format!("nats://127.0.0.1:{port}");
+        assert!(tcfs_sync::NatsClient::connect(&url, "fleet").await.is_err());
+
+        let nats = Arc::new(tokio::sync::Mutex::new(None));
+        let nats_ok = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let setups = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let task = {
+            let setups = setups.clone();
+            spawn_nats_reconnect(
+                url,
+                "fleet".into(),
+                nats.clone(),
+                nats_ok.clone(),
+                Duration::from_millis(20),
+                Duration::from_millis(100),
+                move |client| {
+                    setups.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    async move { Ok(client) }
+                },
+            )
+        };
+
+        // Still unreachable: a few attempts fail and nothing flips
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(!nats_ok.load(std::sync::atomic::Ordering::Relaxed));
+        assert!(nats.lock().await.is_none());
+
+        // NATS comes up on the same address
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", port))
+            .await
+            .unwrap();
+        tokio::spawn(fake_nats_server(listener));
+        tokio::time::timeout(Duration::from_secs(10), task)
+            .await
+            .expect("reconnect within the backoff")
+            .unwrap();
+
+        assert!(nats_ok.load(std::sync::atomic::Ordering::Relaxed));
+        assert_eq!(nats.lock().await.as_ref().unwrap().fleet(), "fleet");
+        assert_eq!(setups.load(std::sync::atomic::Ordering::Relaxed), 1);
+    }
+}
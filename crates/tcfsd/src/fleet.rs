@@ -0,0 +1,64 @@
+//! In-memory liveness of the other devices in the fleet
+//!
+//! The state sync loop feeds every event from another device to
+//! [`FleetTracker::observe`]: `DeviceOnline` and `DeviceOffline` set the
+//! device's online flag, and any event moves its last-seen time forward.
+//! Events are ordered by the timestamp their device stamped them with, so a
+//! stale event redelivered after a reconnect does not flip a device back.
+//! Nothing is persisted; `FleetStatus` fills in devices not heard from since
+//! startup from the registry.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// What the daemon has heard from one device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceLiveness {
+    /// Announced online (or active) and not since announced offline
+    pub online: bool,
+    /// Timestamp of the newest event from the device
+    pub last_seen: u64,
+}
+
+/// Liveness of each device heard from, keyed by device id.
+#[derive(Debug, Default)]
+pub struct FleetTracker {
+    devices: Mutex<BTreeMap<String, DeviceLiveness>>,
+}
+
+/// Tracker shared between the state sync loop and the gRPC service.
+pub type SharedFleet = Arc<FleetTracker>;
+
+impl FleetTracker {
+    /// Record `event`. A device that publishes anything but
+    /// `DeviceOffline` is running, so it counts as online.
+    pub fn observe(&self, event: &tcfs_sync::StateEvent) {
+        let online = !matches!(event, tcfs_sync::StateEvent::DeviceOffline { .. });
+        let timestamp = event.timestamp();
+        let mut devices = self.devices.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = devices
+            .entry(event.device_id().to_string())
+            .or_insert(DeviceLiveness {
+                online,
+                last_seen: timestamp,
+            });
+        if timestamp >= entry.last_seen {
+            *entry = DeviceLiveness {
+                online,
+                last_seen: timestamp,
+            };
+        }
+    }
+
+    /// What is known about `device_id`.
+    pub fn get(&self, device_id: &str) -> Option<DeviceLiveness> {
+        let devices = self.devices.lock().unwrap_or_else(|e| e.into_inner());
+        devices.get(device_id).copied()
+    }
+
+    /// Every device heard from, ordered by id.
+    pub fn snapshot(&self) -> Vec<(String, DeviceLiveness)> {
+        let devices = self.devices.lock().unwrap_or_else(|e| e.into_inner());
+        devices.iter().map(|(id, l)| (id.clone(), *l)).collect()
+    }
+}
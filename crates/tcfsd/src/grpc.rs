@@ -1,45 +1,78 @@
 //! tonic gRPC server over Unix domain socket
 
 use anyhow::Result;
-use std::path::Path;
+use secrecy::ExposeSecret;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::net::UnixListener;
 use tokio::sync::Mutex as TokioMutex;
 use tokio_stream::wrappers::UnixListenerStream;
+use tokio_util::sync::CancellationToken;
 use tonic::transport::Server;
-use tracing::info;
+use tracing::{debug, info};
 
 use crate::cred_store::SharedCredStore;
+use crate::reload::SharedConfig;
 
 use tcfs_core::config::TcfsConfig;
 use tcfs_core::proto::{
     tcfs_daemon_server::{TcfsDaemon, TcfsDaemonServer},
     *,
 };
+use tcfs_sync::engine::{CollectConfig, EngineError};
+use tcfs_sync::watcher::{Debouncer, WatchFilter, DEFAULT_DEBOUNCE};
 
 /// Implementation of the TcfsDaemon gRPC service
 pub struct TcfsDaemonImpl {
     cred_store: SharedCredStore,
-    config: Arc<TcfsConfig>,
-    storage_ok: bool,
-    storage_endpoint: String,
+    config: SharedConfig,
+    config_path: PathBuf,
+    storage_ok: std::sync::atomic::AtomicBool,
     start_time: std::time::Instant,
-    state_cache: Arc<TokioMutex<tcfs_sync::state::StateCache>>,
+    state_cache: Arc<tcfs_sync::state::StateCache>,
     operator: Arc<TokioMutex<Option<opendal::Operator>>>,
     device_id: String,
     device_name: String,
-    nats_ok: std::sync::atomic::AtomicBool,
+    nats_ok: Arc<std::sync::atomic::AtomicBool>,
     nats: Arc<TokioMutex<Option<tcfs_sync::NatsClient>>>,
     active_mounts: Arc<TokioMutex<std::collections::HashMap<String, tokio::process::Child>>>,
+    /// Where encrypted hydration gets the master key; `None` = locked
+    master_key_source: fn() -> Option<tcfs_crypto::MasterKey>,
+    /// Held while a `SyncNow` sweep runs so sweeps never overlap
+    sync_lock: TokioMutex<()>,
+    /// Bytes stored under the storage prefix, re-counted every
+    /// [`STORED_USAGE_REFRESH`] (0 = not counted yet)
+    stored_bytes: Arc<std::sync::atomic::AtomicU64>,
+    /// Liveness of other devices, fed by the state sync loop
+    fleet: crate::fleet::SharedFleet,
+}
+
+/// How often tcfsd re-counts the bytes stored under its prefix for `Status`.
+pub const STORED_USAGE_REFRESH: std::time::Duration = std::time::Duration::from_secs(600);
+
+/// Count the chunk and manifest bytes stored under `prefix` into `stored`.
+pub async fn refresh_stored_usage(
+    operator: &TokioMutex<Option<opendal::Operator>>,
+    prefix: &str,
+    stored: &std::sync::atomic::AtomicU64,
+) -> Result<u64> {
+    let op = operator
+        .lock()
+        .await
+        .clone()
+        .ok_or_else(|| anyhow::anyhow!("no storage operator"))?;
+    let bytes = tcfs_sync::engine::prefix_usage(&op, prefix).await?;
+    stored.store(bytes, std::sync::atomic::Ordering::Relaxed);
+    Ok(bytes)
 }
 
 impl TcfsDaemonImpl {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         cred_store: SharedCredStore,
-        config: Arc<TcfsConfig>,
+        config: SharedConfig,
+        config_path: PathBuf,
         storage_ok: bool,
-        storage_endpoint: String,
         state_cache: tcfs_sync::state::StateCache,
         operator: Arc<TokioMutex<Option<opendal::Operator>>>,
         device_id: String,
@@ -48,35 +81,236 @@ impl TcfsDaemonImpl {
         Self {
             cred_store,
             config,
-            storage_ok,
-            storage_endpoint,
+            config_path,
+            storage_ok: std::sync::atomic::AtomicBool::new(storage_ok),
             start_time: std::time::Instant::now(),
-            state_cache: Arc::new(TokioMutex::new(state_cache)),
+            state_cache: Arc::new(state_cache),
             operator,
             device_id,
             device_name,
-            nats_ok: std::sync::atomic::AtomicBool::new(false),
+            nats_ok: Arc::new(std::sync::atomic::AtomicBool::new(false)),
             nats: Arc::new(TokioMutex::new(None)),
             active_mounts: Arc::new(TokioMutex::new(std::collections::HashMap::new())),
+            master_key_source: crate::cred_store::keychain_master_key,
+            sync_lock: TokioMutex::new(()),
+            stored_bytes: Arc::new(std::sync::atomic::AtomicU64::new(0)),
+            fleet: Arc::default(),
+        }
+    }
+
+    /// Snapshot of the active configuration (swapped by `Reload`).
+    fn config(&self) -> Arc<TcfsConfig> {
+        crate::reload::current(&self.config)
+    }
+
+    /// Refuse paths inside a mount this daemon started or any tcfs FUSE
+    /// mount on the machine, since writing there goes back through FUSE.
+    async fn ensure_outside_mounts(&self, path: &Path) -> Result<(), tonic::Status> {
+        let mut mountpoints: Vec<PathBuf> = self
+            .active_mounts
+            .lock()
+            .await
+            .keys()
+            .map(PathBuf::from)
+            .collect();
+        mountpoints.extend(tcfs_fuse::tcfs_mountpoints());
+        tcfs_fuse::ensure_outside_mounts(path, &mountpoints)
+            .map_err(|e| tonic::Status::failed_precondition(e.to_string()))
+    }
+
+    /// Encryption context for hydrating `manifest`: `None` for plaintext
+    /// manifests, the unlocked master key for encrypted ones, and
+    /// `FAILED_PRECONDITION` when the session is locked.
+    fn hydrate_encryption(
+        &self,
+        manifest: &tcfs_sync::manifest::SyncManifest,
+    ) -> Result<Option<tcfs_sync::engine::EncryptionContext>, tonic::Status> {
+        if manifest.encrypted_file_key.is_none() && !manifest.is_convergent() {
+            return Ok(None);
         }
+        let master_key = (self.master_key_source)().ok_or_else(|| {
+            tonic::Status::failed_precondition(
+                "file is encrypted and the session is locked: run `tcfs auth unlock`",
+            )
+        })?;
+        Ok(Some(tcfs_sync::engine::EncryptionContext {
+            master_key,
+            convergent: self.config().crypto.convergent,
+        }))
     }
 
     /// Get a handle to the state cache for shutdown flushing.
-    pub fn state_cache_handle(&self) -> Arc<TokioMutex<tcfs_sync::state::StateCache>> {
+    pub fn state_cache_handle(&self) -> Arc<tcfs_sync::state::StateCache> {
         self.state_cache.clone()
     }
 
+    /// Get a handle to the stored-bytes count shown by `Status`, for the
+    /// periodic refresh.
+    pub fn stored_usage_handle(&self) -> Arc<std::sync::atomic::AtomicU64> {
+        self.stored_bytes.clone()
+    }
+
+    /// Get a handle to the fleet liveness tracker, for the state sync loop
+    /// to feed.
+    pub fn fleet_handle(&self) -> crate::fleet::SharedFleet {
+        self.fleet.clone()
+    }
+
     /// Get a handle to the NATS client for shutdown notification.
     pub fn nats_handle(&self) -> Arc<TokioMutex<Option<tcfs_sync::NatsClient>>> {
         self.nats.clone()
     }
 
+    /// Shared NATS health flag reported by `Status`, for a background
+    /// reconnect to raise once it gets through.
+    pub fn nats_ok_handle(&self) -> Arc<std::sync::atomic::AtomicBool> {
+        self.nats_ok.clone()
+    }
+
+    /// Upload a file received over the `Push` stream.
+    ///
+    /// Failures are reported twice: as the `error` string of the final
+    /// progress message (for older clients) and as the stream's status, with
+    /// a code from [`engine_status`]. The upload runs on its own task and is
+    /// abandoned if the client drops the response stream.
+    async fn push_buffer(
+        &self,
+        path: String,
+        data: Vec<u8>,
+    ) -> Result<tonic::Response<<Self as TcfsDaemon>::PushStream>, tonic::Status> {
+        let op = self.operator.lock().await;
+        let op = op
+            .as_ref()
+            .ok_or_else(|| tonic::Status::unavailable("no storage operator — check credentials"))?;
+        let op = op.clone();
+
+        let state_cache = self.state_cache.clone();
+        let config = self.config();
+        let prefix = config.storage.bucket.clone();
+
+        if path.is_empty() {
+            return Err(tonic::Status::invalid_argument(
+                "no path provided in push stream",
+            ));
+        }
+        // The path is joined onto the staging directory and names the file
+        // remotely, so it must stay relative
+        let rel = tcfs_core::paths::normalize_rel_path(&path)
+            .map_err(|e| tonic::Status::invalid_argument(format!("push path: {e:#}")))?;
+        let path = rel
+            .iter()
+            .map(|part| part.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        // Stage in a directory of this push's own, so concurrent pushes of
+        // the same path do not overwrite each other, and upload from there
+        let staging = config
+            .daemon
+            .push_staging_dir
+            .clone()
+            .unwrap_or_else(std::env::temp_dir);
+        std::fs::create_dir_all(&staging)
+            .map_err(|e| tonic::Status::internal(format!("mkdir {}: {e}", staging.display())))?;
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("tcfs-push-")
+            .tempdir_in(&staging)
+            .map_err(|e| tonic::Status::internal(format!("tempdir: {e}")))?;
+        let local_path = tmp_dir.path().join(&rel);
+        if let Some(parent) = local_path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| tonic::Status::internal(format!("mkdir: {e}")))?;
+        }
+        std::fs::write(&local_path, &data)
+            .map_err(|e| tonic::Status::internal(format!("write temp: {e}")))?;
+
+        let total_bytes = data.len() as u64;
+        let device_id = self.device_id.clone();
+        let nats = self.nats.clone();
+
+        Ok(spawn_transfer(async move {
+            // Removed when the upload finishes or is cancelled
+            let _tmp_dir = tmp_dir;
+            let cache = state_cache.as_ref();
+            let result = tcfs_sync::engine::upload_file_with_device(
+                &op,
+                &local_path,
+                &prefix,
+                cache,
+                None,
+                &device_id,
+                Some(&path),
+                None,
+                false,
+            )
+            .await;
+
+            let result = result.and_then(|upload| match EngineError::from_upload(&upload) {
+                Some(conflict) => Err(conflict.into()),
+                None => Ok(upload),
+            });
+
+            match result {
+                Ok(upload) => {
+                    // Publish state event if NATS is connected and file was actually uploaded
+                    if !upload.skipped {
+                        let timestamp = cache.now_secs();
+                        let device_id = device_id.clone();
+                        let rel_path = path.clone();
+                        let blake3 = upload.hash.clone();
+                        let size = total_bytes;
+                        let remote_path = upload.remote_path.clone();
+                        tokio::spawn(async move {
+                            if let Some(nats) = nats.lock().await.as_ref() {
+                                let event = tcfs_sync::StateEvent::FileSynced {
+                                    device_id,
+                                    rel_path,
+                                    blake3,
+                                    size,
+                                    vclock: tcfs_sync::conflict::VectorClock::default(),
+                                    manifest_path: remote_path,
+                                    timestamp,
+                                };
+                                if let Err(e) = nats.publish_state_event(&event).await {
+                                    tracing::warn!("failed to publish state event: {e}");
+                                }
+                            }
+                        });
+                    }
+
+                    let progress = PushProgress {
+                        bytes_sent: total_bytes,
+                        total_bytes,
+                        chunk_hash: upload.hash,
+                        done: true,
+                        error: String::new(),
+                        new_chunks: upload.new_chunks as u64,
+                        deduped_chunks: upload.deduped_chunks as u64,
+                    };
+                    vec![Ok(progress)]
+                }
+                Err(e) => {
+                    let progress = PushProgress {
+                        bytes_sent: 0,
+                        total_bytes,
+                        chunk_hash: String::new(),
+                        done: true,
+                        error: format!("{e}"),
+                        new_chunks: 0,
+                        deduped_chunks: 0,
+                    };
+                    failed_messages(progress, &e)
+                }
+            }
+        }))
+    }
+
     /// Publish a ConflictResolved event via NATS (best-effort).
     async fn publish_conflict_resolved(&self, rel_path: &str, resolution: &str) {
         if let Some(nats) = self.nats.lock().await.as_ref() {
             // Build merged vclock from state cache
             let merged_vclock = {
-                let cache = self.state_cache.lock().await;
+                let cache = self.state_cache.as_ref();
                 let path = std::path::PathBuf::from(rel_path);
                 cache
                     .get(&path)
@@ -89,7 +323,7 @@ impl TcfsDaemonImpl {
                 rel_path: rel_path.to_string(),
                 resolution: resolution.to_string(),
                 merged_vclock,
-                timestamp: tcfs_sync::StateEvent::now(),
+                timestamp: self.state_cache.now_secs(),
             };
             if let Err(e) = nats.publish_state_event(&event).await {
                 tracing::warn!("failed to publish ConflictResolved: {e}");
@@ -97,6 +331,66 @@ impl TcfsDaemonImpl {
         }
     }
 
+    /// Re-read the config file and apply it in place.
+    ///
+    /// The new file must parse and validate, otherwise nothing changes.
+    /// Credentials are reloaded via `CredStore::load` (a failure keeps the
+    /// previous ones) and the storage operator is rebuilt when credentials
+    /// or storage settings changed. The state cache's storage settings are
+    /// re-applied. The gRPC server, NATS connection, and active mounts are
+    /// left running. Returns the changed settings now in effect, and those
+    /// that only take effect after a restart.
+    async fn reload_config(&self) -> Result<(Vec<String>, Vec<String>)> {
+        let new_config = crate::reload::read_config(&self.config_path).await?;
+        crate::reload::validate(&new_config)?;
+
+        let old_config = self.config();
+        let mut changed = crate::reload::changed_fields(&old_config, &new_config);
+        let storage_changed = changed.iter().any(|f| f.starts_with("storage."));
+
+        let creds_changed =
+            match tcfs_secrets::CredStore::load(&new_config.secrets, &new_config.storage).await {
+                Ok(cs) => {
+                    let differs = creds_differ(self.cred_store.read().await.as_ref(), &cs);
+                    self.cred_store.install(cs).await;
+                    differs
+                }
+                Err(e) => {
+                    tracing::warn!("credential reload failed: {e}  (keeping previous credentials)");
+                    false
+                }
+            };
+        if creds_changed {
+            changed.push("credentials".into());
+        }
+
+        let store = self.cred_store.read().await;
+        let s3 = store.as_ref().and_then(|c| c.s3.as_ref());
+        if creds_changed || storage_changed {
+            if let Some(s3) = s3 {
+                let (op, ok) = crate::daemon::connect_storage(&new_config.storage, s3).await?;
+                *self.operator.lock().await = Some(op);
+                self.storage_ok
+                    .store(ok, std::sync::atomic::Ordering::Relaxed);
+            }
+        }
+        let credentials = s3.map(|s3| {
+            (
+                s3.access_key_id.as_str(),
+                s3.secret_access_key.expose_secret(),
+            )
+        });
+        crate::daemon::apply_state_settings(&self.state_cache, &new_config, credentials);
+        drop(store);
+
+        let (restart_required, changed): (Vec<String>, Vec<String>) = changed
+            .into_iter()
+            .partition(|f| crate::reload::requires_restart(f));
+        crate::reload::replace(&self.config, new_config);
+        info!(changed = ?changed, restart_required = ?restart_required, "config reloaded");
+        Ok((changed, restart_required))
+    }
+
     /// Set the NATS client (called from daemon after connecting).
     pub fn set_nats(&self, client: tcfs_sync::NatsClient) {
         // set_nats_ok is implicitly true if we have a client
@@ -119,18 +413,306 @@ impl TcfsDaemon for TcfsDaemonImpl {
         &self,
         _request: tonic::Request<StatusRequest>,
     ) -> Result<tonic::Response<StatusResponse>, tonic::Status> {
+        let config = self.config();
         let uptime = self.start_time.elapsed().as_secs() as i64;
         let mount_count = self.active_mounts.lock().await.len() as i32;
+        let totals = self.state_cache.content_totals();
+        let stored = self.stored_bytes.load(std::sync::atomic::Ordering::Relaxed);
         Ok(tonic::Response::new(StatusResponse {
             version: env!("CARGO_PKG_VERSION").into(),
-            storage_endpoint: self.storage_endpoint.clone(),
-            storage_ok: self.storage_ok,
+            storage_endpoint: config.storage.endpoint.clone(),
+            storage_ok: self.storage_ok.load(std::sync::atomic::Ordering::Relaxed),
             nats_ok: self.nats_ok.load(std::sync::atomic::Ordering::Relaxed),
             active_mounts: mount_count,
             uptime_secs: uptime,
             device_id: self.device_id.clone(),
             device_name: self.device_name.clone(),
-            conflict_mode: self.config.sync.conflict_mode.clone(),
+            conflict_mode: config.sync.conflict_mode.to_string(),
+            total_logical_bytes: totals.logical_bytes,
+            total_stored_bytes: stored,
+            dedup_ratio: totals.dedup_ratio(),
+            compression_ratio: totals.compression_ratio(stored),
+        }))
+    }
+
+    async fn reload(
+        &self,
+        _request: tonic::Request<ReloadRequest>,
+    ) -> Result<tonic::Response<ReloadResponse>, tonic::Status> {
+        match self.reload_config().await {
+            Ok((changed, restart_required)) => Ok(tonic::Response::new(ReloadResponse {
+                success: true,
+                changed,
+                error: String::new(),
+                restart_required,
+            })),
+            Err(e) => {
+                tracing::warn!("config reload rejected: {e:#}");
+                Ok(tonic::Response::new(ReloadResponse {
+                    success: false,
+                    changed: Vec::new(),
+                    error: format!("{e:#}"),
+                    restart_required: Vec::new(),
+                }))
+            }
+        }
+    }
+
+    async fn sync_now(
+        &self,
+        request: tonic::Request<SyncNowRequest>,
+    ) -> Result<tonic::Response<SyncNowResponse>, tonic::Status> {
+        let _running = self
+            .sync_lock
+            .try_lock()
+            .map_err(|_| tonic::Status::aborted("a sync is already running"))?;
+
+        let config = self.config();
+        let req = request.into_inner();
+        let prefix = if req.prefix.is_empty() {
+            config.storage.bucket.clone()
+        } else {
+            req.prefix
+        };
+        let sync_root = config
+            .sync
+            .local_root_for(&prefix)
+            .map(Path::to_path_buf)
+            .ok_or_else(|| {
+                tonic::Status::failed_precondition(format!(
+                    "no sync root for prefix '{prefix}': set sync.sync_root or add a [[sync.roots]] entry"
+                ))
+            })?;
+        if config.sync.is_read_only_prefix(&prefix) && !self.state_cache.is_read_only() {
+            return Err(tonic::Status::failed_precondition(format!(
+                "prefix '{prefix}' is read-only: use `tcfs pull` instead"
+            )));
+        }
+        let encryption = if config.crypto.enabled {
+            let master_key = (self.master_key_source)().ok_or_else(|| {
+                tonic::Status::failed_precondition(
+                    "encryption is enabled and the session is locked: run `tcfs auth unlock`",
+                )
+            })?;
+            Some(tcfs_sync::engine::EncryptionContext {
+                master_key,
+                convergent: config.crypto.convergent,
+            })
+        } else {
+            None
+        };
+        let op =
+            self.operator.lock().await.clone().ok_or_else(|| {
+                tonic::Status::unavailable("no storage operator — check credentials")
+            })?;
+
+        let collect = CollectConfig::from_config(&config.sync);
+        let stats = tcfs_sync::engine::reconcile_tree(
+            &op,
+            &sync_root,
+            &prefix,
+            &self.state_cache,
+            &self.device_id,
+            Some(&collect),
+            encryption.as_ref(),
+            config.sync.mode_umask,
+        )
+        .await
+        .map_err(|e| engine_status(EngineError::classify(&e)))?;
+
+        info!(
+            prefix = %prefix,
+            pulled = stats.pulled,
+            pushed = stats.pushed,
+            conflicts = stats.conflicts,
+            "sync sweep finished"
+        );
+        Ok(tonic::Response::new(SyncNowResponse {
+            pulled: stats.pulled as u64,
+            pushed: stats.pushed as u64,
+            conflicts: stats.conflicts as u64,
+        }))
+    }
+
+    // ── Fleet ─────────────────────────────────────────────────────────────
+
+    async fn fleet_status(
+        &self,
+        _request: tonic::Request<Empty>,
+    ) -> Result<tonic::Response<FleetStatusResponse>, tonic::Status> {
+        let config = self.config();
+        let registry_path = crate::daemon::registry_path(&config);
+        let registry = tcfs_secrets::device::DeviceRegistry::load(&registry_path)
+            .map_err(|e| tonic::Status::internal(format!("{e:#}")))?;
+
+        let mut heard = self.fleet.snapshot();
+        let mut devices: Vec<FleetDevice> = registry
+            .active_devices()
+            .map(|device| {
+                let liveness = self.fleet.get(&device.device_id);
+                FleetDevice {
+                    device_id: device.device_id.clone(),
+                    name: device.name.clone(),
+                    online: liveness.is_some_and(|l| l.online),
+                    last_seen: liveness
+                        .map(|l| l.last_seen)
+                        .or(device.last_seen)
+                        .unwrap_or(0),
+                    this_device: false,
+                }
+            })
+            .collect();
+        heard.retain(|(id, _)| registry.find_by_id(id).is_none());
+        devices.extend(heard.into_iter().map(|(device_id, l)| FleetDevice {
+            device_id,
+            name: String::new(),
+            online: l.online,
+            last_seen: l.last_seen,
+            this_device: false,
+        }));
+
+        // This daemon is online by definition
+        let now = self.state_cache.now_secs();
+        match devices.iter_mut().find(|d| d.device_id == self.device_id) {
+            Some(me) => {
+                me.online = true;
+                me.last_seen = now;
+                me.this_device = true;
+            }
+            None => devices.push(FleetDevice {
+                device_id: self.device_id.clone(),
+                name: self.device_name.clone(),
+                online: true,
+                last_seen: now,
+                this_device: true,
+            }),
+        }
+        devices.sort_by(|a, b| (&a.name, &a.device_id).cmp(&(&b.name, &b.device_id)));
+        Ok(tonic::Response::new(FleetStatusResponse { devices }))
+    }
+
+    // ── Inspection ────────────────────────────────────────────────────────
+
+    async fn read_manifest(
+        &self,
+        request: tonic::Request<ReadManifestRequest>,
+    ) -> Result<tonic::Response<ReadManifestResponse>, tonic::Status> {
+        let req = request.into_inner();
+        let config = self.config();
+        let prefix = if req.prefix.is_empty() {
+            config.storage.bucket.clone()
+        } else {
+            req.prefix
+        };
+        let op =
+            self.operator.lock().await.clone().ok_or_else(|| {
+                tonic::Status::unavailable("no storage operator — check credentials")
+            })?;
+        let layout = tcfs_core::layout::RemoteLayout::new(&prefix);
+
+        let file_hash = match (req.file_hash.is_empty(), req.rel_path.is_empty()) {
+            (false, true) => req.file_hash,
+            (true, false) => {
+                let index_key = layout.index_key(&req.rel_path);
+                let data = op.read(&index_key).await.map_err(|e| {
+                    engine_status(EngineError::classify(
+                        &anyhow::Error::new(e).context(format!("reading index entry: {index_key}")),
+                    ))
+                })?;
+                let entry = tcfs_core::index::IndexEntry::from_bytes(&data.to_bytes())
+                    .map_err(|e| tonic::Status::data_loss(format!("{index_key}: {e:#}")))?;
+                if entry.deleted {
+                    return Err(tonic::Status::not_found(format!(
+                        "{} was deleted",
+                        req.rel_path
+                    )));
+                }
+                if entry.manifest_hash.is_empty() {
+                    return Err(tonic::Status::failed_precondition(format!(
+                        "{} has no manifest (symlink)",
+                        req.rel_path
+                    )));
+                }
+                if entry.is_packed() {
+                    return Err(tonic::Status::failed_precondition(format!(
+                        "{} is stored in a pack and has no manifest",
+                        req.rel_path
+                    )));
+                }
+                entry.manifest_hash
+            }
+            _ => {
+                return Err(tonic::Status::invalid_argument(
+                    "set exactly one of file_hash and rel_path",
+                ))
+            }
+        };
+
+        let manifest_path = layout.manifest_key(&file_hash);
+        let manifest = tcfs_sync::store::ChunkStore::new(op, layout)
+            .get_manifest(&file_hash)
+            .await
+            .map_err(|e| engine_status(EngineError::classify(&e)))?;
+        let manifest_json = serde_json::to_string(&manifest)
+            .map_err(|e| tonic::Status::internal(format!("serializing manifest: {e}")))?;
+        Ok(tonic::Response::new(ReadManifestResponse {
+            manifest_path,
+            manifest_json,
+        }))
+    }
+
+    async fn chunk_info(
+        &self,
+        request: tonic::Request<ChunkInfoRequest>,
+    ) -> Result<tonic::Response<ChunkInfoResponse>, tonic::Status> {
+        let req = request.into_inner();
+        if req.chunk_hash.is_empty() {
+            return Err(tonic::Status::invalid_argument("chunk_hash is empty"));
+        }
+        let config = self.config();
+        let prefix = if req.prefix.is_empty() {
+            config.storage.bucket.clone()
+        } else {
+            req.prefix
+        };
+        let op =
+            self.operator.lock().await.clone().ok_or_else(|| {
+                tonic::Status::unavailable("no storage operator — check credentials")
+            })?;
+
+        // Chunks stay at the shard depth they were written with: look at the
+        // configured depth first, then at the others
+        let configured = config.storage.chunk_shard_depth;
+        let depths = std::iter::once(configured)
+            .chain((0..=tcfs_core::layout::MAX_CHUNK_SHARD_DEPTH).filter(|&d| d != configured));
+        let keys: Vec<String> = depths
+            .map(|depth| {
+                tcfs_core::layout::RemoteLayout::new(&prefix)
+                    .with_chunk_shard_depth(depth)
+                    .chunk_key(&req.chunk_hash)
+            })
+            .collect();
+        for key in &keys {
+            match op.stat(key).await {
+                Ok(meta) => {
+                    return Ok(tonic::Response::new(ChunkInfoResponse {
+                        exists: true,
+                        size: meta.content_length(),
+                        key: key.clone(),
+                    }))
+                }
+                Err(e) if e.kind() == opendal::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(engine_status(EngineError::classify(
+                        &anyhow::Error::new(e).context(format!("checking chunk: {key}")),
+                    )))
+                }
+            }
+        }
+        Ok(tonic::Response::new(ChunkInfoResponse {
+            exists: false,
+            size: 0,
+            key: keys[0].clone(),
         }))
     }
 
@@ -143,8 +725,8 @@ impl TcfsDaemon for TcfsDaemonImpl {
             Some(cs) => Ok(tonic::Response::new(CredentialStatusResponse {
                 loaded: true,
                 source: cs.source.clone(),
-                loaded_at: 0,
-                needs_reload: false,
+                loaded_at: self.cred_store.loaded_at(),
+                needs_reload: self.cred_store.needs_reload(),
             })),
             None => Ok(tonic::Response::new(CredentialStatusResponse {
                 loaded: false,
@@ -293,15 +875,6 @@ impl TcfsDaemon for TcfsDaemonImpl {
     ) -> Result<tonic::Response<Self::PushStream>, tonic::Status> {
         use tokio_stream::StreamExt;
 
-        let op = self.operator.lock().await;
-        let op = op
-            .as_ref()
-            .ok_or_else(|| tonic::Status::unavailable("no storage operator — check credentials"))?;
-        let op = op.clone();
-
-        let state_cache = self.state_cache.clone();
-        let prefix = self.config.storage.bucket.clone();
-
         let mut stream = request.into_inner();
 
         // Collect the streamed chunks into a file buffer
@@ -316,93 +889,7 @@ impl TcfsDaemon for TcfsDaemonImpl {
             data.extend_from_slice(&chunk.data);
         }
 
-        if path.is_empty() {
-            return Err(tonic::Status::invalid_argument(
-                "no path provided in push stream",
-            ));
-        }
-
-        // Write to a temp file and upload via sync engine
-        let tmp_dir =
-            tempfile::tempdir().map_err(|e| tonic::Status::internal(format!("tempdir: {e}")))?;
-        let local_path = tmp_dir.path().join(&path);
-        if let Some(parent) = local_path.parent() {
-            std::fs::create_dir_all(parent)
-                .map_err(|e| tonic::Status::internal(format!("mkdir: {e}")))?;
-        }
-        std::fs::write(&local_path, &data)
-            .map_err(|e| tonic::Status::internal(format!("write temp: {e}")))?;
-
-        let total_bytes = data.len() as u64;
-        let device_id = self.device_id.clone();
-
-        let result = {
-            let mut cache = state_cache.lock().await;
-            tcfs_sync::engine::upload_file_with_device(
-                &op,
-                &local_path,
-                &prefix,
-                &mut cache,
-                None,
-                &device_id,
-                Some(&path),
-                None,
-            )
-            .await
-        };
-
-        match result {
-            Ok(upload) => {
-                // Publish state event if NATS is connected and file was actually uploaded
-                if !upload.skipped {
-                    let nats = self.nats.clone();
-                    let device_id = self.device_id.clone();
-                    let rel_path = path.clone();
-                    let blake3 = upload.hash.clone();
-                    let size = total_bytes;
-                    let remote_path = upload.remote_path.clone();
-                    tokio::spawn(async move {
-                        if let Some(nats) = nats.lock().await.as_ref() {
-                            let event = tcfs_sync::StateEvent::FileSynced {
-                                device_id,
-                                rel_path,
-                                blake3,
-                                size,
-                                vclock: tcfs_sync::conflict::VectorClock::default(),
-                                manifest_path: remote_path,
-                                timestamp: tcfs_sync::StateEvent::now(),
-                            };
-                            if let Err(e) = nats.publish_state_event(&event).await {
-                                tracing::warn!("failed to publish state event: {e}");
-                            }
-                        }
-                    });
-                }
-
-                let progress = PushProgress {
-                    bytes_sent: total_bytes,
-                    total_bytes,
-                    chunk_hash: upload.hash,
-                    done: true,
-                    error: String::new(),
-                };
-                Ok(tonic::Response::new(Box::pin(tokio_stream::once(Ok(
-                    progress,
-                )))))
-            }
-            Err(e) => {
-                let progress = PushProgress {
-                    bytes_sent: 0,
-                    total_bytes,
-                    chunk_hash: String::new(),
-                    done: true,
-                    error: format!("{e}"),
-                };
-                Ok(tonic::Response::new(Box::pin(tokio_stream::once(Ok(
-                    progress,
-                )))))
-            }
-        }
+        self.push_buffer(path, data).await
     }
 
     // ── Pull: server-streaming download ───────────────────────────────────
@@ -423,50 +910,48 @@ impl TcfsDaemon for TcfsDaemonImpl {
             .ok_or_else(|| tonic::Status::unavailable("no storage operator — check credentials"))?;
         let op = op.clone();
 
-        let prefix = self.config.storage.bucket.clone();
+        let prefix = self.config().storage.bucket.clone();
         let local_path = std::path::PathBuf::from(&req.local_path);
         let device_id = self.device_id.clone();
         let state_cache = self.state_cache.clone();
+        let mode_umask = self.config().sync.mode_umask;
 
-        let result = {
-            let mut cache = state_cache.lock().await;
-            tcfs_sync::engine::download_file_with_device(
+        Ok(spawn_transfer(async move {
+            let cache = state_cache.as_ref();
+            let result = tcfs_sync::engine::download_file_with_device(
                 &op,
                 &req.remote_path,
                 &local_path,
                 &prefix,
                 None,
                 &device_id,
-                Some(&mut cache),
+                Some(cache),
                 None,
+                tcfs_sync::engine::RestoreMode::manifest(mode_umask),
             )
-            .await
-        };
+            .await;
 
-        match result {
-            Ok(dl) => {
-                let progress = PullProgress {
-                    bytes_received: dl.bytes,
-                    total_bytes: dl.bytes,
-                    done: true,
-                    error: String::new(),
-                };
-                Ok(tonic::Response::new(Box::pin(tokio_stream::once(Ok(
-                    progress,
-                )))))
-            }
-            Err(e) => {
-                let progress = PullProgress {
-                    bytes_received: 0,
-                    total_bytes: 0,
-                    done: true,
-                    error: format!("{e}"),
-                };
-                Ok(tonic::Response::new(Box::pin(tokio_stream::once(Ok(
-                    progress,
-                )))))
+            match result {
+                Ok(dl) => {
+                    let progress = PullProgress {
+                        bytes_received: dl.bytes,
+                        total_bytes: dl.bytes,
+                        done: true,
+                        error: String::new(),
+                    };
+                    vec![Ok(progress)]
+                }
+                Err(e) => {
+                    let progress = PullProgress {
+                        bytes_received: 0,
+                        total_bytes: 0,
+                        done: true,
+                        error: format!("{e}"),
+                    };
+                    failed_messages(progress, &e)
+                }
             }
-        }
+        }))
     }
 
     // ── Hydrate ───────────────────────────────────────────────────────────
@@ -483,6 +968,7 @@ impl TcfsDaemon for TcfsDaemonImpl {
         let stub_path = std::path::PathBuf::from(&req.stub_path);
 
         info!(stub = %req.stub_path, "hydrate requested");
+        self.ensure_outside_mounts(&stub_path).await?;
 
         // Read and parse stub file
         let stub_content = std::fs::read_to_string(&stub_path)
@@ -503,68 +989,132 @@ impl TcfsDaemon for TcfsDaemonImpl {
         let blake3_hex = meta
             .blake3_hex()
             .ok_or_else(|| tonic::Status::invalid_argument("stub oid missing blake3: prefix"))?;
-        let prefix = self.config.storage.bucket.clone();
-        let manifest_path = format!("{prefix}/manifests/{blake3_hex}");
+        let prefix = self.config().storage.bucket.clone();
+        let manifest_path = tcfs_core::layout::RemoteLayout::new(&prefix).manifest_key(blake3_hex);
 
-        let op = self.operator.lock().await;
-        let op = op
-            .as_ref()
+        let op = self
+            .operator
+            .lock()
+            .await
+            .clone()
             .ok_or_else(|| tonic::Status::unavailable("no storage operator"))?;
-        let op = op.clone();
-        drop(self.operator.lock().await);
-
-        let total_bytes = meta.size;
 
-        let result = {
-            let mut cache = self.state_cache.lock().await;
-            tcfs_sync::engine::download_file_with_device(
-                &op,
-                &manifest_path,
-                &real_path,
-                &prefix,
-                None,
-                &self.device_id,
-                Some(&mut cache),
-                None,
-            )
-            .await
+        // A packed file's stub points at its pack, which has no manifest
+        let pack_id = meta
+            .origin
+            .rsplit_once("/packs/")
+            .map(|(_, id)| id)
+            .filter(|id| !id.is_empty() && !id.contains('/'));
+        let (packed, encryption) = match pack_id {
+            Some(id) => {
+                let packed = tcfs_sync::pack::find_packed(&op, &prefix, id, blake3_hex)
+                    .await
+                    .map_err(|e| engine_status(EngineError::classify(&e)))?;
+                (Some(packed), None)
+            }
+            None => {
+                // Encrypted content needs the master key; refuse up front if locked
+                let manifest_bytes = op.read(&manifest_path).await.map_err(|e| {
+                    engine_status(EngineError::classify(
+                        &anyhow::Error::new(e)
+                            .context(format!("reading manifest: {manifest_path}")),
+                    ))
+                })?;
+                let manifest =
+                    tcfs_sync::manifest::SyncManifest::from_bytes(&manifest_bytes.to_bytes())
+                        .map_err(|e| engine_status(EngineError::classify(&e)))?;
+                (None, self.hydrate_encryption(&manifest)?)
+            }
         };
 
-        match result {
-            Ok(dl) => {
-                // Remove stub file after successful hydration
-                let _ = std::fs::remove_file(&stub_path);
-
-                info!(
-                    real_path = %real_path.display(),
-                    bytes = dl.bytes,
-                    "hydration complete"
-                );
-
-                let progress = HydrateProgress {
-                    bytes_received: dl.bytes,
-                    total_bytes,
-                    local_path: real_path.to_string_lossy().to_string(),
-                    done: true,
-                    error: String::new(),
-                };
-                Ok(tonic::Response::new(Box::pin(tokio_stream::once(Ok(
-                    progress,
-                )))))
-            }
-            Err(e) => {
-                let progress = HydrateProgress {
-                    bytes_received: 0,
-                    total_bytes,
-                    local_path: String::new(),
-                    done: true,
-                    error: format!("{e}"),
-                };
-                Ok(tonic::Response::new(Box::pin(tokio_stream::once(Ok(
-                    progress,
-                )))))
+        let total_bytes = meta.size;
+        let state_cache = self.state_cache.clone();
+        let device_id = self.device_id.clone();
+        let mode_umask = self.config().sync.mode_umask;
+
+        Ok(spawn_transfer_with(move |tx| async move {
+            let cache = state_cache.as_ref();
+            let local_path = real_path.to_string_lossy().to_string();
+            let report: tcfs_sync::engine::ProgressFn = {
+                let local_path = local_path.clone();
+                Box::new(move |done, _total, _msg| {
+                    let _ = tx.try_send(Ok(HydrateProgress {
+                        bytes_received: done,
+                        total_bytes,
+                        local_path: local_path.clone(),
+                        done: false,
+                        error: String::new(),
+                    }));
+                })
+            };
+            let progress = tcfs_sync::engine::DownloadProgress {
+                chunks: None,
+                bytes: Some(&report),
+            };
+            let result = match &packed {
+                Some((rel_path, entry)) => {
+                    tcfs_sync::engine::download_indexed(
+                        &op,
+                        &prefix,
+                        rel_path,
+                        entry,
+                        &real_path,
+                        progress,
+                        &device_id,
+                        Some(cache),
+                        None,
+                        mode_umask,
+                    )
+                    .await
+                }
+                None => {
+                    tcfs_sync::engine::download_file_with_progress(
+                        &op,
+                        &manifest_path,
+                        &real_path,
+                        &prefix,
+                        progress,
+                        &device_id,
+                        Some(cache),
+                        encryption.as_ref(),
+                        tcfs_sync::engine::RestoreMode::manifest(mode_umask),
+                    )
+                    .await
+                }
+            };
+
+            match result {
+                Ok(dl) => {
+                    // Remove stub file after successful hydration
+                    let _ = std::fs::remove_file(&stub_path);
+
+                    info!(
+                        real_path = %real_path.display(),
+                        bytes = dl.bytes,
+                        "hydration complete"
+                    );
+
+                    let progress = HydrateProgress {
+                        bytes_received: dl.bytes,
+                        total_bytes,
+                        local_path,
+                        done: true,
+                        error: String::new(),
+                    };
+                    vec![Ok(progress)]
+                }
+                Err(e) => {
+                    let progress = HydrateProgress {
+                        bytes_received: 0,
+                        total_bytes,
+                        local_path: String::new(),
+                        done: true,
+                        error: format!("{e}"),
+                    };
+                    failed_messages(progress, &e)
+                }
             }
-        }
+        }))
     }
 
     // ── Unsync ────────────────────────────────────────────────────────────
@@ -578,7 +1128,7 @@ impl TcfsDaemon for TcfsDaemonImpl {
 
         info!(path = %req.path, force = req.force, "unsync requested");
 
-        let mut cache = self.state_cache.lock().await;
+        let cache = self.state_cache.as_ref();
         if cache.get(&path).is_none() {
             return Ok(tonic::Response::new(UnsyncResponse {
                 success: false,
@@ -614,7 +1164,7 @@ impl TcfsDaemon for TcfsDaemonImpl {
         let req = request.into_inner();
         let path = std::path::PathBuf::from(&req.path);
 
-        let cache = self.state_cache.lock().await;
+        let cache = self.state_cache.as_ref();
 
         match cache.get(&path) {
             Some(entry) => Ok(tonic::Response::new(SyncStatusResponse {
@@ -684,10 +1234,7 @@ impl TcfsDaemon for TcfsDaemonImpl {
             }
             "keep_local" => {
                 // Read local state, tick vclock, build new manifest, upload
-                let local_state = {
-                    let cache = self.state_cache.lock().await;
-                    cache.get(&path).cloned()
-                };
+                let local_state = self.state_cache.get(&path);
 
                 let local_state = match local_state {
                     Some(s) => s,
@@ -711,9 +1258,15 @@ impl TcfsDaemon for TcfsDaemonImpl {
                     chunks: vec![],
                     vclock: vclock.clone(),
                     written_by: self.device_id.clone(),
-                    written_at: tcfs_sync::StateEvent::now(),
+                    written_at: self.state_cache.now_secs(),
                     rel_path: Some(req.path.clone()),
                     encrypted_file_key: None,
+                    mode: tcfs_sync::engine::file_mode(&path),
+                    compressed: Vec::new(),
+                    chunk_keys: Vec::new(),
+                    chunk_shard_depth: 0,
+                    signature: None,
+                    manifest_checksum: None,
                 };
 
                 // Upload updated manifest
@@ -723,19 +1276,27 @@ impl TcfsDaemon for TcfsDaemonImpl {
                     let manifest_bytes = manifest
                         .to_bytes()
                         .map_err(|e| tonic::Status::internal(format!("manifest serialize: {e}")))?;
-                    op.write(&manifest_key, manifest_bytes)
-                        .await
-                        .map_err(|e| tonic::Status::internal(format!("manifest upload: {e}")))?;
+                    tcfs_sync::engine::write_conditional_with(
+                        op,
+                        &manifest_key,
+                        manifest_bytes,
+                        &tcfs_sync::engine::ObjectVersion::Unknown,
+                        tcfs_sync::store::ObjectMeta::MANIFEST,
+                    )
+                    .await
+                    .map_err(|e| {
+                        engine_status(EngineError::classify(&e.context("manifest upload")))
+                    })?;
                 }
                 drop(op);
 
                 // Update state cache
                 {
-                    let mut cache = self.state_cache.lock().await;
-                    if let Some(entry) = cache.get(&path).cloned() {
+                    let cache = self.state_cache.as_ref();
+                    if let Some(entry) = cache.get(&path) {
                         let updated = tcfs_sync::state::SyncState {
                             vclock,
-                            last_synced: tcfs_sync::StateEvent::now(),
+                            last_synced: cache.now_secs(),
                             ..entry
                         };
                         cache.set(&path, updated);
@@ -756,10 +1317,10 @@ impl TcfsDaemon for TcfsDaemonImpl {
             "keep_remote" => {
                 // Download remote version to local path
                 let (remote_path, prefix) = {
-                    let cache = self.state_cache.lock().await;
+                    let cache = self.state_cache.as_ref();
                     let entry = cache.get(&path);
                     let remote = entry.map(|e| e.remote_path.clone()).unwrap_or_default();
-                    let prefix = self.config.storage.bucket.clone();
+                    let prefix = self.config().storage.bucket.clone();
                     (remote, prefix)
                 };
 
@@ -779,7 +1340,7 @@ impl TcfsDaemon for TcfsDaemonImpl {
                 drop(self.operator.lock().await);
 
                 let result = {
-                    let mut cache = self.state_cache.lock().await;
+                    let cache = self.state_cache.as_ref();
                     tcfs_sync::engine::download_file_with_device(
                         &op,
                         &remote_path,
@@ -787,8 +1348,9 @@ impl TcfsDaemon for TcfsDaemonImpl {
                         &prefix,
                         None,
                         &self.device_id,
-                        Some(&mut cache),
+                        Some(cache),
                         None,
+                        tcfs_sync::engine::RestoreMode::manifest(self.config().sync.mode_umask),
                     )
                     .await
                 };
@@ -813,10 +1375,10 @@ impl TcfsDaemon for TcfsDaemonImpl {
             "keep_both" => {
                 // Rename local file to {stem}.conflict-{device_id}{ext}, then download remote
                 let (remote_path, prefix) = {
-                    let cache = self.state_cache.lock().await;
+                    let cache = self.state_cache.as_ref();
                     let entry = cache.get(&path);
                     let remote = entry.map(|e| e.remote_path.clone()).unwrap_or_default();
-                    let prefix = self.config.storage.bucket.clone();
+                    let prefix = self.config().storage.bucket.clone();
                     (remote, prefix)
                 };
 
@@ -862,7 +1424,7 @@ impl TcfsDaemon for TcfsDaemonImpl {
                 drop(self.operator.lock().await);
 
                 let result = {
-                    let mut cache = self.state_cache.lock().await;
+                    let cache = self.state_cache.as_ref();
                     tcfs_sync::engine::download_file_with_device(
                         &op,
                         &remote_path,
@@ -870,8 +1432,9 @@ impl TcfsDaemon for TcfsDaemonImpl {
                         &prefix,
                         None,
                         &self.device_id,
-                        Some(&mut cache),
+                        Some(cache),
                         None,
+                        tcfs_sync::engine::RestoreMode::manifest(self.config().sync.mode_umask),
                     )
                     .await
                 };
@@ -919,7 +1482,22 @@ impl TcfsDaemon for TcfsDaemonImpl {
             ));
         }
 
-        info!(paths = ?req.paths, "watch requested");
+        let collect = CollectConfig::from_config(&self.config().sync);
+        let filters: Vec<WatchFilter> = if req.unfiltered {
+            Vec::new()
+        } else {
+            req.paths
+                .iter()
+                .map(|p| {
+                    WatchFilter::new(Path::new(p), &collect).with_patterns(&req.exclude_patterns)
+                })
+                .collect()
+        };
+        info!(
+            paths = ?req.paths,
+            ignore = ?filters.first().map(|f| f.patterns()),
+            "watch requested"
+        );
 
         let (sync_tx, sync_rx) = std::sync::mpsc::channel();
 
@@ -941,14 +1519,28 @@ impl TcfsDaemon for TcfsDaemonImpl {
         }
 
         let (async_tx, async_rx) = tokio::sync::mpsc::channel(256);
+        let window = match req.debounce_ms {
+            0 => DEFAULT_DEBOUNCE,
+            ms => std::time::Duration::from_millis(ms),
+        };
 
-        // Bridge sync watcher events to async channel
+        // Bridge sync watcher events to async channel, coalescing each burst
         tokio::task::spawn_blocking(move || {
             // Keep watcher alive while client is connected
             let _watcher = watcher;
-            while let Ok(result) = sync_rx.recv() {
-                let event = match result {
-                    Ok(event) => {
+            let mut debouncer = Debouncer::new(window);
+            loop {
+                let received = match debouncer.deadline() {
+                    Some(deadline) => sync_rx.recv_timeout(
+                        deadline.saturating_duration_since(std::time::Instant::now()),
+                    ),
+                    None => sync_rx
+                        .recv()
+                        .map_err(|_| std::sync::mpsc::RecvTimeoutError::Disconnected),
+                };
+                let mut ready = Vec::new();
+                match received {
+                    Ok(Ok(event)) => {
                         let event_type = match event.kind {
                             notify::EventKind::Create(_) => "created",
                             notify::EventKind::Modify(_) => "modified",
@@ -957,29 +1549,40 @@ impl TcfsDaemon for TcfsDaemonImpl {
                             notify::EventKind::Other => continue,
                             notify::EventKind::Any => continue,
                         };
-                        let path = event
-                            .paths
-                            .first()
+                        let first = event.paths.first();
+                        if first.is_some_and(|p| filters.iter().any(|f| f.ignores(p))) {
+                            continue;
+                        }
+                        let path = first
                             .map(|p| p.to_string_lossy().to_string())
                             .unwrap_or_default();
-                        let timestamp = std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap_or_default()
-                            .as_secs() as i64;
+                        debouncer.push((path, event_type));
+                    }
+                    Ok(Err(e)) => ready.push(WatchEvent {
+                        path: String::new(),
+                        event_type: format!("error: {e}"),
+                        timestamp: 0,
+                    }),
+                    Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+                    Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+                if debouncer.is_due(std::time::Instant::now()) {
+                    let timestamp = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs() as i64;
+                    ready.extend(debouncer.flush().into_iter().map(|(path, event_type)| {
                         WatchEvent {
                             path,
                             event_type: event_type.to_string(),
                             timestamp,
                         }
+                    }));
+                }
+                for event in ready {
+                    if async_tx.blocking_send(Ok(event)).is_err() {
+                        return; // Client disconnected
                     }
-                    Err(e) => WatchEvent {
-                        path: String::new(),
-                        event_type: format!("error: {e}"),
-                        timestamp: 0,
-                    },
-                };
-                if async_tx.blocking_send(Ok(event)).is_err() {
-                    break; // Client disconnected
                 }
             }
         });
@@ -1016,3 +1619,1181 @@ pub async fn serve(
         .await
         .map_err(|e| anyhow::anyhow!("gRPC server error: {e}"))
 }
+
+/// gRPC status for an engine failure, so clients can branch on the code
+/// instead of parsing the message.
+fn engine_status(err: EngineError) -> tonic::Status {
+    match err {
+        EngineError::Conflict { message, info } => {
+            let details = info
+                .and_then(|info| serde_json::to_vec(&info).ok())
+                .unwrap_or_default();
+            tonic::Status::with_details(
+                tonic::Code::FailedPrecondition,
+                message,
+                tonic::codegen::Bytes::from(details),
+            )
+        }
+        EngineError::NotFound(message) => tonic::Status::not_found(message),
+        EngineError::Storage(message) => tonic::Status::unavailable(message),
+        EngineError::Integrity(message) => tonic::Status::data_loss(message),
+        EngineError::Other(message) => tonic::Status::internal(message),
+    }
+}
+
+/// Server-streaming response body used by the progress RPCs.
+type ProgressStream<T> =
+    std::pin::Pin<Box<dyn tokio_stream::Stream<Item = Result<T, tonic::Status>> + Send>>;
+
+/// Stream messages for a failed transfer: `last` (carrying the legacy
+/// `error` string), then the status `err` maps to.
+fn failed_messages<T>(last: T, err: &anyhow::Error) -> Vec<Result<T, tonic::Status>> {
+    vec![Ok(last), Err(engine_status(EngineError::classify(err)))]
+}
+
+/// Runs a transfer on its own task and streams the messages it returns.
+///
+/// The response stream holds a drop guard for the task's
+/// `CancellationToken`: when the client goes away tonic drops the stream,
+/// the token fires and the engine future is dropped at its next await point
+/// instead of running to completion.
+fn spawn_transfer<T, F>(work: F) -> tonic::Response<ProgressStream<T>>
+where
+    T: Send + 'static,
+    F: std::future::Future<Output = Vec<Result<T, tonic::Status>>> + Send + 'static,
+{
+    spawn_transfer_with(|_| work)
+}
+
+/// [`spawn_transfer`] for transfers that report progress while they run:
+/// `work` gets a sender for intermediate messages, which go out ahead of
+/// the ones it returns. Intermediate messages are best-effort and dropped
+/// while the client is not keeping up (`try_send`).
+fn spawn_transfer_with<T, F, W>(work: W) -> tonic::Response<ProgressStream<T>>
+where
+    T: Send + 'static,
+    F: std::future::Future<Output = Vec<Result<T, tonic::Status>>> + Send + 'static,
+    W: FnOnce(tokio::sync::mpsc::Sender<Result<T, tonic::Status>>) -> F,
+{
+    use tokio_stream::StreamExt;
+
+    let (tx, rx) = tokio::sync::mpsc::channel(4);
+    let work = work(tx.clone());
+    let cancel = CancellationToken::new();
+    let token = cancel.clone();
+    tokio::spawn(async move {
+        let messages = tokio::select! {
+            messages = work => messages,
+            _ = token.cancelled() => {
+                debug!("transfer cancelled: client dropped the stream");
+                return;
+            }
+        };
+        for message in messages {
+            if tx.send(message).await.is_err() {
+                break; // Client disconnected
+            }
+        }
+    });
+
+    let guard = cancel.drop_guard();
+    let stream = tokio_stream::wrappers::ReceiverStream::new(rx).map(move |message| {
+        let _ = &guard;
+        message
+    });
+    tonic::Response::new(Box::pin(stream))
+}
+
+/// Whether reloaded credentials differ from the installed ones. A rotated
+/// secret under the same access key id counts, so the operator is rebuilt
+/// with it.
+fn creds_differ(old: Option<&tcfs_secrets::CredStore>, new: &tcfs_secrets::CredStore) -> bool {
+    let Some(old) = old else {
+        return true;
+    };
+    let key = |s: &tcfs_secrets::S3Credentials| {
+        (
+            s.access_key_id.clone(),
+            s.secret_access_key.expose_secret().to_string(),
+            s.endpoint.clone(),
+            s.region.clone(),
+        )
+    };
+    old.source != new.source || old.s3.as_ref().map(key) != new.s3.as_ref().map(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(path: &Path, conflict_mode: &str) {
+        let toml = format!(
+            "[storage]\nendpoint = \"http://127.0.0.1:1\"\n\n[sync]\nconflict_mode = \"{conflict_mode}\"\n"
+        );
+        std::fs::write(path, toml).unwrap();
+    }
+
+    async fn daemon_for(config_path: &Path, state_db: &Path) -> TcfsDaemonImpl {
+        let config = crate::reload::read_config(config_path).await.unwrap();
+        TcfsDaemonImpl::new(
+            crate::cred_store::new_shared(),
+            crate::reload::new_shared(config),
+            config_path.to_path_buf(),
+            false,
+            tcfs_sync::state::StateCache::open(state_db).unwrap(),
+            Arc::new(TokioMutex::new(None)),
+            "device-1".into(),
+            "test".into(),
+        )
+    }
+
+    async fn conflict_mode(daemon: &TcfsDaemonImpl) -> String {
+        daemon
+            .status(tonic::Request::new(StatusRequest {}))
+            .await
+            .unwrap()
+            .into_inner()
+            .conflict_mode
+    }
+
+    #[tokio::test]
+    async fn reload_applies_conflict_mode_from_disk() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let config_path = tmp.path().join("tcfs.toml");
+        write_config(&config_path, "auto");
+        let daemon = daemon_for(&config_path, &tmp.path().join("state.db")).await;
+        assert_eq!(conflict_mode(&daemon).await, "auto");
+
+        write_config(&config_path, "defer");
+        let reply = daemon
+            .reload(tonic::Request::new(ReloadRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert!(reply.success, "reload failed: {}", reply.error);
+        assert!(reply.changed.contains(&"sync.conflict_mode".to_string()));
+        assert_eq!(conflict_mode(&daemon).await, "defer");
+    }
+
+    #[tokio::test]
+    async fn reload_reapplies_storage_settings() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let config_path = tmp.path().join("tcfs.toml");
+        write_config(&config_path, "auto");
+        let daemon = daemon_for(&config_path, &tmp.path().join("state.db")).await;
+        assert!(!daemon.state_cache.is_read_only());
+        assert_eq!(daemon.state_cache.quota(), None);
+
+        std::fs::write(
+            &config_path,
+            "[storage]\nendpoint = \"http://127.0.0.1:1\"\nbucket = \"templates\"\nquota_bytes = 4096\n\n\
+             [sync]\nconflict_mode = \"auto\"\nread_only_prefixes = [\"templates\"]\n\
+             nats_url = \"nats://elsewhere:4222\"\n",
+        )
+        .unwrap();
+        let reply = daemon
+            .reload(tonic::Request::new(ReloadRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert!(reply.success, "reload failed: {}", reply.error);
+        assert!(daemon.state_cache.is_read_only());
+        assert_eq!(daemon.state_cache.quota(), Some(4096));
+        assert!(reply.changed.contains(&"storage.quota_bytes".to_string()));
+        assert!(!reply.changed.contains(&"sync.nats_url".to_string()));
+        assert_eq!(reply.restart_required, vec!["sync.nats_url"]);
+    }
+
+    #[tokio::test]
+    async fn reload_rejects_invalid_config_and_keeps_current() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let config_path = tmp.path().join("tcfs.toml");
+        write_config(&config_path, "interactive");
+        let daemon = daemon_for(&config_path, &tmp.path().join("state.db")).await;
+
+        write_config(&config_path, "sometimes");
+        let reply = daemon
+            .reload(tonic::Request::new(ReloadRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert!(!reply.success);
+        assert!(reply.error.contains("conflict_mode"), "{}", reply.error);
+        assert_eq!(conflict_mode(&daemon).await, "interactive");
+    }
+
+    #[test]
+    fn rotated_secret_counts_as_changed_credentials() {
+        let store = |secret: &str| tcfs_secrets::CredStore {
+            s3: Some(tcfs_secrets::S3Credentials {
+                access_key_id: "AKID".into(),
+                secret_access_key: secret.to_string().into(),
+                endpoint: "http://127.0.0.1:8333".into(),
+                region: "us-east-1".into(),
+            }),
+            source: "env".into(),
+        };
+        assert!(creds_differ(None, &store("old")));
+        assert!(!creds_differ(Some(&store("old")), &store("old")));
+        assert!(creds_differ(Some(&store("old")), &store("rotated")));
+    }
+
+    #[tokio::test]
+    async fn status_reports_dedup_and_compression_ratios() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let config_path = tmp.path().join("tcfs.toml");
+        write_config(&config_path, "auto");
+        let daemon = daemon_for(&config_path, &tmp.path().join("state.db")).await;
+
+        // Two copies of the same 1000 bytes and one distinct 500-byte file
+        let track = |name: &str, content: &[u8]| {
+            let path = tmp.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            let state = tcfs_sync::state::make_sync_state_full(
+                &path,
+                blake3::hash(content).to_hex().to_string(),
+                1,
+                format!("tcfs/manifests/{name}"),
+                Default::default(),
+                "device-1".into(),
+            )
+            .unwrap();
+            daemon.state_cache.set(&path, state);
+        };
+        track("a.bin", &[1u8; 1000]);
+        track("a-copy.bin", &[1u8; 1000]);
+        track("b.bin", &[2u8; 500]);
+
+        let status = || async {
+            daemon
+                .status(tonic::Request::new(StatusRequest {}))
+                .await
+                .unwrap()
+                .into_inner()
+        };
+        let before = status().await;
+        assert_eq!(before.total_logical_bytes, 2500);
+        assert_eq!(before.total_stored_bytes, 0);
+        assert!((before.dedup_ratio - 2500.0 / 1500.0).abs() < 1e-9);
+        assert_eq!(before.compression_ratio, 0.0);
+
+        // 600 bytes of chunks and 150 of manifest under the prefix
+        let op = opendal::Operator::new(opendal::services::Memory::default())
+            .unwrap()
+            .finish();
+        let prefix = daemon.config().storage.bucket.clone();
+        op.write(&format!("{prefix}/chunks/c1"), vec![0u8; 400])
+            .await
+            .unwrap();
+        op.write(&format!("{prefix}/chunks/c2"), vec![0u8; 200])
+            .await
+            .unwrap();
+        op.write(&format!("{prefix}/manifests/m1"), vec![0u8; 150])
+            .await
+            .unwrap();
+        *daemon.operator.lock().await = Some(op);
+        let counted =
+            refresh_stored_usage(&daemon.operator, &prefix, &daemon.stored_usage_handle())
+                .await
+                .unwrap();
+        assert_eq!(counted, 750);
+
+        let after = status().await;
+        assert_eq!(after.total_stored_bytes, 750);
+        assert!((after.compression_ratio - 2.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn hydrate_refuses_stub_inside_active_mount() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let config_path = tmp.path().join("tcfs.toml");
+        write_config(&config_path, "auto");
+        let daemon = daemon_for(&config_path, &tmp.path().join("state.db")).await;
+
+        // Register a fake mount; any long-lived child stands in for `tcfs mount`
+        let mountpoint = tmp.path().join("mnt");
+        std::fs::create_dir_all(mountpoint.join("docs")).unwrap();
+        let child = tokio::process::Command::new("sleep")
+            .arg("30")
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        daemon
+            .active_mounts
+            .lock()
+            .await
+            .insert(mountpoint.to_string_lossy().into_owned(), child);
+
+        let stub = mountpoint.join("docs/report.pdf.tc");
+        std::fs::write(&stub, b"not read").unwrap();
+        let err = daemon
+            .hydrate(tonic::Request::new(HydrateRequest {
+                stub_path: stub.to_string_lossy().into_owned(),
+                partial_ok: false,
+            }))
+            .await
+            .err()
+            .expect("hydrate inside a mount must fail");
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        assert!(
+            err.message().contains("outside the mount"),
+            "{}",
+            err.message()
+        );
+        assert!(stub.exists());
+    }
+
+    #[tokio::test]
+    async fn conflicting_push_fails_with_failed_precondition() {
+        use tokio_stream::StreamExt;
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let config_path = tmp.path().join("tcfs.toml");
+        write_config(&config_path, "auto");
+        let daemon = daemon_for(&config_path, &tmp.path().join("state.db")).await;
+        let op = opendal::Operator::new(opendal::services::Memory::default())
+            .unwrap()
+            .finish();
+        *daemon.operator.lock().await = Some(op.clone());
+
+        // Another device left a manifest at this content's key with the same
+        // (empty) clock but different content
+        let content = b"edited on this device\n".to_vec();
+        let hash = blake3::hash(&content).to_hex().to_string();
+        let remote = tcfs_sync::manifest::SyncManifest {
+            version: 2,
+            file_hash: blake3::hash(b"edited elsewhere").to_hex().to_string(),
+            file_size: 16,
+            chunks: vec![],
+            vclock: Default::default(),
+            written_by: "device-2".into(),
+            written_at: 0,
+            rel_path: Some("notes.txt".into()),
+            encrypted_file_key: None,
+            mode: None,
+            compressed: Vec::new(),
+            chunk_keys: Vec::new(),
+            chunk_shard_depth: 0,
+            signature: None,
+            manifest_checksum: None,
+        };
+        op.write(
+            &format!("tcfs/manifests/{hash}"),
+            remote.to_bytes().unwrap(),
+        )
+        .await
+        .unwrap();
+
+        let mut stream = daemon
+            .push_buffer("notes.txt".into(), content)
+            .await
+            .unwrap()
+            .into_inner();
+
+        // The legacy error string is still sent before the status
+        let progress = stream.next().await.unwrap().unwrap();
+        assert!(progress.done);
+        assert!(progress.error.contains("conflict"), "{}", progress.error);
+
+        let status = stream.next().await.unwrap().unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+        let info: tcfs_sync::conflict::ConflictInfo =
+            serde_json::from_slice(status.details()).unwrap();
+        assert_eq!(info.rel_path, "notes.txt");
+        assert_eq!(info.remote_device, "device-2");
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn push_rejects_escaping_paths_and_stages_each_push_apart() {
+        use tokio_stream::StreamExt;
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let staging = tmp.path().join("a/b/staging");
+        let config_path = tmp.path().join("tcfs.toml");
+        std::fs::write(
+            &config_path,
+            format!(
+                "[daemon]\npush_staging_dir = {:?}\n\n[storage]\nendpoint = \"http://127.0.0.1:1\"\n",
+                staging.to_string_lossy()
+            ),
+        )
+        .unwrap();
+        let daemon = daemon_for(&config_path, &tmp.path().join("state.db")).await;
+        let op = opendal::Operator::new(opendal::services::Memory::default())
+            .unwrap()
+            .finish();
+        *daemon.operator.lock().await = Some(op.clone());
+
+        for path in ["../../evil", "docs/../../evil", "/tmp/evil", ".."] {
+            let status = daemon
+                .push_buffer(path.into(), b"payload".to_vec())
+                .await
+                .map(drop)
+                .unwrap_err();
+            assert_eq!(status.code(), tonic::Code::InvalidArgument, "{path}");
+        }
+        // Rejected before anything was staged, inside or outside staging
+        for dir in [tmp.path(), &tmp.path().join("a"), &tmp.path().join("a/b")] {
+            assert!(!dir.join("evil").exists(), "{}", dir.display());
+        }
+        assert!(!Path::new("/tmp/evil").exists());
+        assert!(!staging.exists());
+
+        // Two pushes of the same path in flight stage separately
+        let (a, b) = tokio::join!(
+            daemon.push_buffer("docs/notes.txt".into(), b"first".to_vec()),
+            daemon.push_buffer("docs/notes.txt".into(), b"second, longer".to_vec()),
+        );
+        let (mut a, mut b) = (a.unwrap().into_inner(), b.unwrap().into_inner());
+        let a = a.next().await.unwrap().unwrap();
+        let b = b.next().await.unwrap().unwrap();
+        assert_eq!(a.chunk_hash, blake3::hash(b"first").to_hex().to_string());
+        assert_eq!(
+            b.chunk_hash,
+            blake3::hash(b"second, longer").to_hex().to_string()
+        );
+        assert!(op
+            .exists(&format!("tcfs/manifests/{}", a.chunk_hash))
+            .await
+            .unwrap());
+        // Each push's staging directory is gone once it finishes
+        assert_eq!(std::fs::read_dir(&staging).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn watch_skips_ignored_files() {
+        use tokio_stream::StreamExt;
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let config_path = tmp.path().join("tcfs.toml");
+        write_config(&config_path, "auto");
+        let daemon = daemon_for(&config_path, &tmp.path().join("state.db")).await;
+        let tree = tmp.path().join("tree");
+        std::fs::create_dir_all(&tree).unwrap();
+        std::fs::write(tree.join(".tcfsignore"), "*.tmp\n").unwrap();
+
+        let mut stream = daemon
+            .watch(tonic::Request::new(WatchRequest {
+                paths: vec![tree.to_string_lossy().into_owned()],
+                exclude_patterns: vec![],
+                unfiltered: false,
+                debounce_ms: 0,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        std::fs::write(tree.join(".notes.txt.swp"), b"swap").unwrap();
+        std::fs::write(tree.join("build.tmp"), b"scratch").unwrap();
+        std::fs::write(tree.join("notes.txt"), b"real edit").unwrap();
+
+        let event = tokio::time::timeout(std::time::Duration::from_secs(10), stream.next())
+            .await
+            .expect("no watch event for the real file")
+            .unwrap()
+            .unwrap();
+        assert!(event.path.ends_with("notes.txt"), "{}", event.path);
+        assert!(!event.path.ends_with(".swp"), "{}", event.path);
+    }
+
+    #[tokio::test]
+    async fn watch_coalesces_a_burst_of_writes() {
+        use tokio_stream::StreamExt;
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let config_path = tmp.path().join("tcfs.toml");
+        write_config(&config_path, "auto");
+        let daemon = daemon_for(&config_path, &tmp.path().join("state.db")).await;
+        let tree = tmp.path().join("tree");
+        std::fs::create_dir_all(&tree).unwrap();
+        let file = tree.join("draft.md");
+        std::fs::write(&file, b"v0").unwrap();
+
+        let mut stream = daemon
+            .watch(tonic::Request::new(WatchRequest {
+                paths: vec![tree.to_string_lossy().into_owned()],
+                exclude_patterns: vec![],
+                unfiltered: false,
+                debounce_ms: 500,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        for i in 1..=10 {
+            std::fs::write(&file, format!("v{i}")).unwrap();
+        }
+
+        let event = tokio::time::timeout(std::time::Duration::from_secs(10), stream.next())
+            .await
+            .expect("no coalesced event")
+            .unwrap()
+            .unwrap();
+        assert!(event.path.ends_with("draft.md"), "{}", event.path);
+        assert_eq!(event.event_type, "modified");
+
+        let extra = tokio::time::timeout(std::time::Duration::from_secs(1), stream.next()).await;
+        assert!(extra.is_err(), "expected one event, got {extra:?}");
+    }
+
+    /// Counts chunk reads and stalls each one, so a pull is still fetching
+    /// when the client walks away.
+    #[derive(Debug, Clone, Default)]
+    struct SlowChunks {
+        reads: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl<A: opendal::raw::Access> opendal::raw::Layer<A> for SlowChunks {
+        type LayeredAccess = SlowChunksAccessor<A>;
+
+        fn layer(&self, inner: A) -> Self::LayeredAccess {
+            SlowChunksAccessor {
+                inner,
+                layer: self.clone(),
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct SlowChunksAccessor<A> {
+        inner: A,
+        layer: SlowChunks,
+    }
+
+    impl<A: opendal::raw::Access> opendal::raw::LayeredAccess for SlowChunksAccessor<A> {
+        type Inner = A;
+        type Reader = A::Reader;
+        type Writer = A::Writer;
+        type Lister = A::Lister;
+        type Deleter = A::Deleter;
+
+        fn inner(&self) -> &Self::Inner {
+            &self.inner
+        }
+
+        async fn read(
+            &self,
+            path: &str,
+            args: opendal::raw::OpRead,
+        ) -> opendal::Result<(opendal::raw::RpRead, Self::Reader)> {
+            if path.contains("/chunks/") {
+                self.layer
+                    .reads
+                    .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            }
+            self.inner.read(path, args).await
+        }
+
+        async fn write(
+            &self,
+            path: &str,
+            args: opendal::raw::OpWrite,
+        ) -> opendal::Result<(opendal::raw::RpWrite, Self::Writer)> {
+            self.inner.write(path, args).await
+        }
+
+        async fn delete(&self) -> opendal::Result<(opendal::raw::RpDelete, Self::Deleter)> {
+            self.inner.delete().await
+        }
+
+        async fn list(
+            &self,
+            path: &str,
+            args: opendal::raw::OpList,
+        ) -> opendal::Result<(opendal::raw::RpList, Self::Lister)> {
+            self.inner.list(path, args).await
+        }
+    }
+
+    #[tokio::test]
+    async fn dropping_pull_stream_stops_chunk_fetches() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let config_path = tmp.path().join("tcfs.toml");
+        write_config(&config_path, "auto");
+        let daemon = daemon_for(&config_path, &tmp.path().join("state.db")).await;
+        let slow = SlowChunks::default();
+        let op = opendal::Operator::new(opendal::services::Memory::default())
+            .unwrap()
+            .layer(slow.clone())
+            .finish();
+        *daemon.operator.lock().await = Some(op.clone());
+
+        // Non-repeating content so FastCDC cuts many chunks
+        let mut x = 0x2545_f491_4f6c_dd1du64;
+        let content: Vec<u8> = (0..2 * 1024 * 1024)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect();
+        let src = tmp.path().join("big.bin");
+        std::fs::write(&src, &content).unwrap();
+        let pusher = tcfs_sync::state::StateCache::open(&tmp.path().join("push.db")).unwrap();
+        let upload = tcfs_sync::engine::upload_file(&op, &src, "tcfs", &pusher, None)
+            .await
+            .unwrap();
+        assert!(upload.chunks > 4, "expected several chunks");
+        // The upload reads each chunk back; count only the pull's reads
+        slow.reads.store(0, std::sync::atomic::Ordering::SeqCst);
+
+        let stream = daemon
+            .pull(tonic::Request::new(PullRequest {
+                remote_path: upload.remote_path.clone(),
+                local_path: tmp.path().join("pulled.bin").to_string_lossy().into_owned(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let reads = || slow.reads.load(std::sync::atomic::Ordering::SeqCst);
+        tokio::time::timeout(std::time::Duration::from_secs(10), async {
+            while reads() == 0 {
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("pull never fetched a chunk");
+        drop(stream);
+
+        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+        let after_drop = reads();
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        assert_eq!(reads(), after_drop, "chunk fetches continued after drop");
+        assert!(after_drop < upload.chunks, "pull ran to completion");
+    }
+
+    fn unlocked() -> Option<tcfs_crypto::MasterKey> {
+        Some(tcfs_crypto::MasterKey::from_bytes([42u8; 32]))
+    }
+
+    fn locked() -> Option<tcfs_crypto::MasterKey> {
+        None
+    }
+
+    #[tokio::test]
+    async fn hydrate_decrypts_encrypted_stub_when_unlocked() {
+        use tokio_stream::StreamExt;
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let config_path = tmp.path().join("tcfs.toml");
+        write_config(&config_path, "auto");
+        let mut daemon = daemon_for(&config_path, &tmp.path().join("state.db")).await;
+        let op = opendal::Operator::new(opendal::services::Memory::default())
+            .unwrap()
+            .finish();
+        *daemon.operator.lock().await = Some(op.clone());
+
+        let content = b"quarterly numbers, encrypted at rest".repeat(100);
+        let src = tmp.path().join("upload/secret.txt");
+        std::fs::create_dir_all(src.parent().unwrap()).unwrap();
+        std::fs::write(&src, &content).unwrap();
+        let ctx = tcfs_sync::engine::EncryptionContext {
+            master_key: unlocked().unwrap(),
+            convergent: false,
+        };
+        let pusher = tcfs_sync::state::StateCache::open(&tmp.path().join("push.db")).unwrap();
+        let upload = tcfs_sync::engine::upload_file_with_device(
+            &op,
+            &src,
+            "tcfs",
+            &pusher,
+            None,
+            "device-2",
+            None,
+            Some(&ctx),
+            false,
+        )
+        .await
+        .unwrap();
+        let manifest_hash = upload.remote_path.rsplit('/').next().unwrap();
+
+        let stub = tmp.path().join("secret.txt.tc");
+        let meta = tcfs_fuse::stub::StubMeta::for_upload(
+            manifest_hash,
+            content.len() as u64,
+            upload.chunks,
+            "tcfs",
+            "secret.txt",
+        );
+        std::fs::write(&stub, meta.to_bytes()).unwrap();
+        let request = || {
+            tonic::Request::new(HydrateRequest {
+                stub_path: stub.to_string_lossy().into_owned(),
+                partial_ok: false,
+            })
+        };
+
+        daemon.master_key_source = locked;
+        let err = daemon
+            .hydrate(request())
+            .await
+            .err()
+            .expect("hydrating encrypted content while locked must fail");
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        assert!(
+            err.message().contains("tcfs auth unlock"),
+            "{}",
+            err.message()
+        );
+        assert!(stub.exists());
+
+        daemon.master_key_source = unlocked;
+        let messages: Vec<_> = daemon
+            .hydrate(request())
+            .await
+            .unwrap()
+            .into_inner()
+            .collect()
+            .await;
+        let last = messages.last().unwrap().as_ref().unwrap();
+        assert!(last.done && last.error.is_empty(), "{last:?}");
+        assert_eq!(
+            std::fs::read(tmp.path().join("secret.txt")).unwrap(),
+            content
+        );
+        assert!(!stub.exists());
+    }
+
+    #[tokio::test]
+    async fn hydrate_reports_progress_as_chunks_are_written() {
+        use tokio_stream::StreamExt;
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let config_path = tmp.path().join("tcfs.toml");
+        write_config(&config_path, "auto");
+        let daemon = daemon_for(&config_path, &tmp.path().join("state.db")).await;
+        let op = opendal::Operator::new(opendal::services::Memory::default())
+            .unwrap()
+            .finish();
+        *daemon.operator.lock().await = Some(op.clone());
+
+        // Incompressible content large enough for dozens of chunks
+        let mut x = 0x9e37_79b9u32;
+        let content: Vec<u8> = (0..2 * 1024 * 1024)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                x as u8
+            })
+            .collect();
+        let src = tmp.path().join("upload/disk.img");
+        std::fs::create_dir_all(src.parent().unwrap()).unwrap();
+        std::fs::write(&src, &content).unwrap();
+        let pusher = tcfs_sync::state::StateCache::open(&tmp.path().join("push.db")).unwrap();
+        let upload = tcfs_sync::engine::upload_file(&op, &src, "tcfs", &pusher, None)
+            .await
+            .unwrap();
+        assert!(upload.chunks > 8, "{} chunks", upload.chunks);
+        let manifest_hash = upload.remote_path.rsplit('/').next().unwrap();
+
+        let stub = tmp.path().join("disk.img.tc");
+        let meta = tcfs_fuse::stub::StubMeta::for_upload(
+            manifest_hash,
+            content.len() as u64,
+            upload.chunks,
+            "tcfs",
+            "disk.img",
+        );
+        std::fs::write(&stub, meta.to_bytes()).unwrap();
+        let messages: Vec<_> = daemon
+            .hydrate(tonic::Request::new(HydrateRequest {
+                stub_path: stub.to_string_lossy().into_owned(),
+                partial_ok: false,
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .collect()
+            .await;
+        let messages: Vec<_> = messages.into_iter().map(Result::unwrap).collect();
+
+        let (last, partial) = messages.split_last().unwrap();
+        assert!(last.done && last.error.is_empty(), "{last:?}");
+        assert_eq!(last.bytes_received, content.len() as u64);
+        assert!(!partial.is_empty(), "no progress before completion");
+        let mut seen = 0;
+        for progress in partial {
+            assert!(!progress.done);
+            assert!(progress.bytes_received > seen);
+            assert!(progress.bytes_received <= content.len() as u64);
+            assert_eq!(progress.total_bytes, content.len() as u64);
+            seen = progress.bytes_received;
+        }
+        assert_eq!(std::fs::read(tmp.path().join("disk.img")).unwrap(), content);
+        assert!(!tmp.path().join("disk.img.tcfs_tmp").exists());
+    }
+
+    #[tokio::test]
+    async fn hydrate_reads_packed_stub_from_its_pack() {
+        use tokio_stream::StreamExt;
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let config_path = tmp.path().join("tcfs.toml");
+        write_config(&config_path, "auto");
+        let daemon = daemon_for(&config_path, &tmp.path().join("state.db")).await;
+        let op = opendal::Operator::new(opendal::services::Memory::default())
+            .unwrap()
+            .finish();
+        *daemon.operator.lock().await = Some(op.clone());
+
+        let root = tmp.path().join("upload");
+        std::fs::create_dir_all(&root).unwrap();
+        let src = root.join("note.txt");
+        std::fs::write(&src, b"small and packed").unwrap();
+        let pusher = tcfs_sync::state::StateCache::open(&tmp.path().join("push.db")).unwrap();
+        let packed = tcfs_sync::pack::push_packed(
+            &op,
+            &root,
+            "tcfs",
+            &pusher,
+            std::slice::from_ref(&src),
+            "pusher",
+        )
+        .await;
+        assert!(packed[0].as_ref().unwrap().pack.is_some());
+
+        let stub = tmp.path().join("note.txt.tc");
+        let meta = tcfs_fuse::stub::StubMeta::for_synced(&pusher.get(&src).unwrap(), "tcfs");
+        std::fs::write(&stub, meta.to_bytes()).unwrap();
+        let messages: Vec<_> = daemon
+            .hydrate(tonic::Request::new(HydrateRequest {
+                stub_path: stub.to_string_lossy().into_owned(),
+                partial_ok: false,
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .collect()
+            .await;
+
+        let last = messages.last().unwrap().as_ref().unwrap();
+        assert!(last.done && last.error.is_empty(), "{last:?}");
+        assert_eq!(
+            std::fs::read(tmp.path().join("note.txt")).unwrap(),
+            b"small and packed"
+        );
+        assert!(!stub.exists());
+    }
+
+    #[tokio::test]
+    async fn sync_now_pulls_remote_newer_file() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let root = tmp.path().join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        let config_path = tmp.path().join("tcfs.toml");
+        std::fs::write(
+            &config_path,
+            format!(
+                "[storage]\nendpoint = \"http://127.0.0.1:1\"\n\n[sync]\nsync_root = {:?}\n",
+                root.to_string_lossy()
+            ),
+        )
+        .unwrap();
+        let daemon = daemon_for(&config_path, &tmp.path().join("state.db")).await;
+        let op = opendal::Operator::new(opendal::services::Memory::default())
+            .unwrap()
+            .finish();
+        *daemon.operator.lock().await = Some(op.clone());
+
+        // Another device pushes a tree into the bucket prefix
+        let other = tmp.path().join("other");
+        std::fs::create_dir_all(&other).unwrap();
+        std::fs::write(other.join("notes.txt"), b"first draft").unwrap();
+        let other_state = tcfs_sync::state::StateCache::open(&tmp.path().join("other.db")).unwrap();
+        let push_other = || {
+            tcfs_sync::engine::push_tree_with_stats(
+                &op,
+                &other,
+                "tcfs",
+                &other_state,
+                None,
+                "device-2",
+                None,
+                None,
+                1,
+                None,
+            )
+        };
+        push_other().await.unwrap();
+
+        let sync = || daemon.sync_now(tonic::Request::new(SyncNowRequest::default()));
+        let reply = sync().await.unwrap().into_inner();
+        assert_eq!((reply.pulled, reply.pushed, reply.conflicts), (1, 0, 0));
+        assert_eq!(
+            std::fs::read(root.join("notes.txt")).unwrap(),
+            b"first draft"
+        );
+
+        // A newer remote version replaces the unchanged local copy
+        std::fs::write(other.join("notes.txt"), b"second draft, longer").unwrap();
+        push_other().await.unwrap();
+        let reply = sync().await.unwrap().into_inner();
+        assert_eq!((reply.pulled, reply.pushed, reply.conflicts), (1, 0, 0));
+        assert_eq!(
+            std::fs::read(root.join("notes.txt")).unwrap(),
+            b"second draft, longer"
+        );
+
+        // Nothing left to do, and an overlapping sweep is refused
+        let reply = sync().await.unwrap().into_inner();
+        assert_eq!((reply.pulled, reply.pushed, reply.conflicts), (0, 0, 0));
+        let _running = daemon.sync_lock.lock().await;
+        assert_eq!(sync().await.unwrap_err().code(), tonic::Code::Aborted);
+    }
+
+    #[tokio::test]
+    async fn fleet_status_follows_online_and_offline_events() {
+        use tcfs_sync::StateEvent;
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let registry_path = tmp.path().join("devices.json");
+        let mut registry = tcfs_secrets::device::DeviceRegistry::default();
+        let desktop = registry.enroll("desktop", "age1desktop", None);
+        registry.record_seen(&desktop, 500);
+        registry.save(&registry_path).unwrap();
+        let config_path = tmp.path().join("tcfs.toml");
+        std::fs::write(
+            &config_path,
+            format!(
+                "[storage]\nendpoint = \"http://127.0.0.1:1\"\n\n[sync]\ndevice_identity = {:?}\n",
+                registry_path.to_string_lossy()
+            ),
+        )
+        .unwrap();
+        let daemon = daemon_for(&config_path, &tmp.path().join("state.db")).await;
+        let fleet = daemon.fleet_handle();
+
+        let status = || async {
+            daemon
+                .fleet_status(tonic::Request::new(Empty {}))
+                .await
+                .unwrap()
+                .into_inner()
+                .devices
+        };
+        let device = |devices: &[FleetDevice], id: &str| {
+            devices
+                .iter()
+                .find(|d| d.device_id == id)
+                .cloned()
+                .unwrap_or_else(|| panic!("{id} missing: {devices:?}"))
+        };
+        let online = |device_id: &str, timestamp| StateEvent::DeviceOnline {
+            device_id: device_id.into(),
+            last_seq: 0,
+            timestamp,
+        };
+
+        // Before any event: known from the registry only
+        let devices = status().await;
+        assert_eq!(devices.len(), 2, "{devices:?}");
+        let d = device(&devices, &desktop);
+        assert_eq!(
+            (d.name.as_str(), d.online, d.last_seen),
+            ("desktop", false, 500)
+        );
+        let me = device(&devices, "device-1");
+        assert!(me.online && me.this_device, "{me:?}");
+
+        fleet.observe(&online(&desktop, 1000));
+        let d = device(&status().await, &desktop);
+        assert_eq!((d.online, d.last_seen), (true, 1000));
+
+        fleet.observe(&StateEvent::DeviceOffline {
+            device_id: desktop.clone(),
+            last_seq: 0,
+            timestamp: 2000,
+        });
+        let d = device(&status().await, &desktop);
+        assert_eq!((d.online, d.last_seen), (false, 2000));
+
+        // A stale announcement redelivered later does not bring it back
+        fleet.observe(&online(&desktop, 1000));
+        assert!(!device(&status().await, &desktop).online);
+
+        // A device outside the registry shows up once it publishes anything
+        fleet.observe(&StateEvent::FileSynced {
+            device_id: "phone".into(),
+            rel_path: "a.txt".into(),
+            blake3: "00".into(),
+            size: 1,
+            vclock: Default::default(),
+            manifest_path: "tcfs/manifests/00".into(),
+            timestamp: 3000,
+        });
+        let phone = device(&status().await, "phone");
+        assert_eq!((phone.name.as_str(), phone.online), ("", true));
+    }
+
+    #[tokio::test]
+    async fn manifests_and_chunks_can_be_inspected_after_a_push() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let config_path = tmp.path().join("tcfs.toml");
+        write_config(&config_path, "auto");
+        let daemon = daemon_for(&config_path, &tmp.path().join("state.db")).await;
+        let op = opendal::Operator::new(opendal::services::Memory::default())
+            .unwrap()
+            .finish();
+        *daemon.operator.lock().await = Some(op.clone());
+
+        let root = tmp.path().join("root");
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        let content = b"inspect me\n".repeat(20_000);
+        std::fs::write(root.join("docs/notes.txt"), &content).unwrap();
+        let state = tcfs_sync::state::StateCache::open(&tmp.path().join("push.db")).unwrap();
+        tcfs_sync::engine::push_tree_with_stats(
+            &op, &root, "tcfs", &state, None, "device-2", None, None, 1, None,
+        )
+        .await
+        .unwrap();
+        let file_hash = blake3::hash(&content).to_hex().to_string();
+
+        let read = |file_hash: &str, rel_path: &str| {
+            daemon.read_manifest(tonic::Request::new(ReadManifestRequest {
+                prefix: String::new(),
+                file_hash: file_hash.into(),
+                rel_path: rel_path.into(),
+            }))
+        };
+        let by_path = read("", "docs/notes.txt").await.unwrap().into_inner();
+        assert_eq!(by_path.manifest_path, format!("tcfs/manifests/{file_hash}"));
+        let manifest: serde_json::Value = serde_json::from_str(&by_path.manifest_json).unwrap();
+        assert_eq!(manifest["file_hash"], file_hash);
+        assert_eq!(manifest["file_size"], content.len() as u64);
+        assert_eq!(manifest["rel_path"], "docs/notes.txt");
+        assert_eq!(manifest["written_by"], "device-2");
+        let chunks = manifest["chunks"].as_array().unwrap();
+        assert!(!chunks.is_empty());
+
+        let by_hash = read(&file_hash, "").await.unwrap().into_inner();
+        assert_eq!(by_hash, by_path);
+        assert_eq!(
+            read("", "docs/missing.txt").await.unwrap_err().code(),
+            tonic::Code::NotFound
+        );
+        assert_eq!(
+            read(&file_hash, "docs/notes.txt").await.unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+
+        let info = |chunk_hash: &str| {
+            daemon.chunk_info(tonic::Request::new(ChunkInfoRequest {
+                prefix: "tcfs".into(),
+                chunk_hash: chunk_hash.into(),
+            }))
+        };
+        let chunk = chunks[0].as_str().unwrap();
+        let found = info(chunk).await.unwrap().into_inner();
+        assert!(found.exists);
+        assert!(found.size > 0);
+        assert_eq!(found.key, format!("tcfs/chunks/{chunk}"));
+        assert_eq!(
+            op.stat(&found.key).await.unwrap().content_length(),
+            found.size
+        );
+        let missing = info(&"0".repeat(64)).await.unwrap().into_inner();
+        assert!(!missing.exists);
+        assert_eq!(missing.key, format!("tcfs/chunks/{}", "0".repeat(64)));
+    }
+
+    #[tokio::test]
+    async fn typed_client_round_trips_against_served_daemon() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let config_path = tmp.path().join("tcfs.toml");
+        write_config(&config_path, "auto");
+        let daemon = daemon_for(&config_path, &tmp.path().join("state.db")).await;
+        let op = opendal::Operator::new(opendal::services::Memory::default())
+            .unwrap()
+            .finish();
+        *daemon.operator.lock().await = Some(op.clone());
+
+        let socket = tmp.path().join("tcfsd.sock");
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn({
+            let socket = socket.clone();
+            async move {
+                serve(&socket, daemon, async {
+                    let _ = stopped.await;
+                })
+                .await
+            }
+        });
+        while !socket.exists() {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        let mut client = tcfs_client::TcfsClient::connect(&socket).await.unwrap();
+
+        let status = client.status().await.unwrap();
+        assert_eq!(status.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(status.device_id, "device-1");
+        assert!(!client.credential_status().await.unwrap().loaded);
+
+        // Push streams the buffer in and reports the content hash
+        let content = b"round trip through tcfsd\n".repeat(1000);
+        let pushed = client.push("notes.txt", &content).await.unwrap();
+        assert!(pushed.done);
+        assert_eq!(pushed.total_bytes, content.len() as u64);
+        assert_eq!(
+            pushed.chunk_hash,
+            blake3::hash(&content).to_hex().to_string()
+        );
+
+        // Pull writes it back out and records it as synced
+        let local = tmp.path().join("pulled/notes.txt");
+        let pulled = client
+            .pull(
+                &format!("tcfs/manifests/{}", pushed.chunk_hash),
+                local.to_str().unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(pulled.bytes_received, content.len() as u64);
+        assert_eq!(std::fs::read(&local).unwrap(), content);
+
+        let synced = client.sync_status(local.to_str().unwrap()).await.unwrap();
+        assert_eq!(synced.state, "synced");
+        assert_eq!(synced.blake3, pushed.chunk_hash);
+
+        // The stored manifest and its chunks can be inspected
+        let manifest = client
+            .read_manifest("", &pushed.chunk_hash, "")
+            .await
+            .unwrap();
+        let manifest: serde_json::Value = serde_json::from_str(&manifest.manifest_json).unwrap();
+        assert_eq!(manifest["file_size"], content.len() as u64);
+        let chunk = manifest["chunks"][0].as_str().unwrap();
+        assert!(client.chunk_info("", chunk).await.unwrap().exists);
+
+        // Failures surface as typed errors carrying the gRPC code
+        let err = client
+            .pull("tcfs/manifests/missing", local.to_str().unwrap())
+            .await
+            .unwrap_err();
+        match err {
+            tcfs_client::ClientError::Rpc { rpc, status } => {
+                assert_eq!(rpc, "pull");
+                assert_eq!(status.code(), tonic::Code::NotFound);
+            }
+            other => panic!("unexpected error: {other}"),
+        }
+
+        let resolved = client.resolve_conflict("notes.txt", "defer").await.unwrap();
+        assert_eq!(resolved, "notes.txt");
+        let err = client
+            .resolve_conflict("notes.txt", "flip_a_coin")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("invalid resolution"), "{err}");
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
@@ -7,10 +7,13 @@
 //!   daemon  - Full local daemon (FUSE + gRPC + sync) [default]
 //!   worker  - Stateless NATS consumer for K8s pods (feature: k8s-worker)
 
+mod breaker;
 mod cred_store;
 mod daemon;
+mod fleet;
 mod grpc;
 mod metrics;
+mod reload;
 mod worker;
 
 use anyhow::Result;
@@ -72,10 +75,10 @@ async fn main() -> Result<()> {
     );
 
     // Load configuration
-    let config = load_config(&cli.config).await?;
+    let config = reload::load_config(&cli.config).await?;
 
     match cli.mode {
-        Mode::Daemon => daemon::run(config).await,
+        Mode::Daemon => daemon::run(config, cli.config).await,
         Mode::Worker => {
             #[cfg(feature = "k8s-worker")]
             return worker::run(config).await;
@@ -87,22 +90,6 @@ async fn main() -> Result<()> {
     }
 }
 
-async fn load_config(path: &PathBuf) -> Result<tcfs_core::config::TcfsConfig> {
-    if path.exists() {
-        let content = tokio::fs::read_to_string(path)
-            .await
-            .map_err(|e| anyhow::anyhow!("reading config {}: {e}", path.display()))?;
-        toml::from_str(&content)
-            .map_err(|e| anyhow::anyhow!("parsing config {}: {e}", path.display()))
-    } else {
-        tracing::warn!(
-            "config file not found: {}  (using defaults)",
-            path.display()
-        );
-        Ok(tcfs_core::config::TcfsConfig::default())
-    }
-}
-
 fn init_logging(level: &str, format: &LogFormat) {
     use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
@@ -0,0 +1,221 @@
+//! Config hot-reload: shared config handle, loading, validation, and diffing
+//!
+//! The daemon keeps its `TcfsConfig` behind a `SharedConfig` so the `Reload`
+//! RPC can swap in a freshly parsed file without restarting the gRPC server
+//! or dropping active mounts. Readers take a cheap `Arc` snapshot per use.
+
+use anyhow::Result;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use tcfs_core::config::TcfsConfig;
+
+/// Shared reference to the daemon's active configuration
+pub type SharedConfig = Arc<RwLock<Arc<TcfsConfig>>>;
+
+/// Wrap a config for shared, swappable access
+pub fn new_shared(config: TcfsConfig) -> SharedConfig {
+    Arc::new(RwLock::new(Arc::new(config)))
+}
+
+/// Snapshot of the current configuration
+pub fn current(shared: &SharedConfig) -> Arc<TcfsConfig> {
+    shared.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Replace the current configuration
+pub fn replace(shared: &SharedConfig, config: TcfsConfig) {
+    *shared.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(config);
+}
+
+/// Load the config file at `path`, falling back to defaults when it is missing.
+pub async fn load_config(path: &Path) -> Result<TcfsConfig> {
+    if path.exists() {
+        read_config(path).await
+    } else {
+        tracing::warn!(
+            "config file not found: {}  (using defaults)",
+            path.display()
+        );
+        Ok(TcfsConfig::default())
+    }
+}
+
+/// Read and parse the config file at `path`; a missing file is an error.
+pub async fn read_config(path: &Path) -> Result<TcfsConfig> {
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| anyhow::anyhow!("reading config {}: {e}", path.display()))?;
+    toml::from_str(&content).map_err(|e| anyhow::anyhow!("parsing config {}: {e}", path.display()))
+}
+
+/// Reject configurations the daemon cannot run with. Conflict modes need
+/// no check here: unknown ones already fail to parse.
+pub fn validate(config: &TcfsConfig) -> Result<()> {
+    for rule in &config.sync.path_rules {
+        globset::Glob::new(&rule.pattern)
+            .map_err(|e| anyhow::anyhow!("invalid sync.path_rules pattern: {e}"))?;
+    }
+    tcfs_sync::conflict::resolver_for(&config.sync.auto_strategy)
+        .map_err(|e| anyhow::anyhow!("invalid sync.auto_strategy: {e}"))?;
+    anyhow::ensure!(
+        config.sync.mode_umask <= 0o777,
+        "invalid sync.mode_umask {:o} (must be at most 777)",
+        config.sync.mode_umask
+    );
+    anyhow::ensure!(
+        (0.0..1.0).contains(&config.sync.chunk_filter_fp_rate),
+        "invalid sync.chunk_filter_fp_rate {} (expected 0 to disable, or a rate below 1)",
+        config.sync.chunk_filter_fp_rate
+    );
+    if let Some(id) = &config.sync.device_id {
+        tcfs_secrets::device::validate_device_id(id)
+            .map_err(|e| anyhow::anyhow!("invalid sync.device_id: {e}"))?;
+    }
+    anyhow::ensure!(
+        config.sync.transfer_concurrency_min <= config.sync.transfer_concurrency_max,
+        "sync.transfer_concurrency_min ({}) exceeds sync.transfer_concurrency_max ({})",
+        config.sync.transfer_concurrency_min,
+        config.sync.transfer_concurrency_max
+    );
+    anyhow::ensure!(
+        config.storage.chunk_shard_depth <= tcfs_core::layout::MAX_CHUNK_SHARD_DEPTH,
+        "invalid storage.chunk_shard_depth {} (at most {})",
+        config.storage.chunk_shard_depth,
+        tcfs_core::layout::MAX_CHUNK_SHARD_DEPTH
+    );
+    anyhow::ensure!(
+        !config.storage.bucket.is_empty(),
+        "storage.bucket must not be empty"
+    );
+    Ok(())
+}
+
+/// Settings the daemon only reads at startup: a reload records a new value,
+/// but it takes effect on the next restart.
+const RESTART_FIELDS: &[&str] = &[
+    "daemon.metrics_addr",
+    "daemon.socket",
+    "storage.credentials_file",
+    "sync.auto_strategy",
+    "sync.chunk_filter_fp_rate",
+    "sync.consumer_suffix",
+    "sync.device_id",
+    "sync.device_identity",
+    "sync.fleet_id",
+    "sync.nats_url",
+    "sync.state_db",
+    "sync.transfer_concurrency_max",
+    "sync.transfer_concurrency_min",
+];
+
+/// Whether a changed setting, as named by [`changed_fields`], only takes
+/// effect after a restart.
+pub fn requires_restart(field: &str) -> bool {
+    field == "*"
+        || RESTART_FIELDS.iter().any(|f| {
+            field == *f
+                || field
+                    .strip_prefix(f)
+                    .is_some_and(|rest| rest.starts_with('.'))
+        })
+}
+
+/// Dotted names (`sync.conflict_mode`) of the settings that differ.
+pub fn changed_fields(old: &TcfsConfig, new: &TcfsConfig) -> Vec<String> {
+    let (Ok(old), Ok(new)) = (toml::Value::try_from(old), toml::Value::try_from(new)) else {
+        return vec!["*".into()];
+    };
+    let mut changed = Vec::new();
+    diff_values("", &old, &new, &mut changed);
+    changed.sort();
+    changed
+}
+
+fn diff_values(path: &str, old: &toml::Value, new: &toml::Value, out: &mut Vec<String>) {
+    match (old, new) {
+        (toml::Value::Table(a), toml::Value::Table(b)) => {
+            let keys: std::collections::BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+            for key in keys {
+                let child = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                match (a.get(key), b.get(key)) {
+                    (Some(x), Some(y)) => diff_values(&child, x, y, out),
+                    _ => out.push(child),
+                }
+            }
+        }
+        (a, b) if a != b => out.push(path.to_string()),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tcfs_core::config::ConflictMode;
+
+    #[test]
+    fn changed_fields_names_dotted_keys() {
+        let old = TcfsConfig::default();
+        let mut new = old.clone();
+        new.sync.conflict_mode = ConflictMode::Defer;
+        new.storage.bucket = "other".into();
+
+        assert_eq!(
+            changed_fields(&old, &new),
+            vec!["storage.bucket", "sync.conflict_mode"]
+        );
+        assert!(changed_fields(&old, &old.clone()).is_empty());
+    }
+
+    #[test]
+    fn requires_restart_matches_startup_settings() {
+        assert!(requires_restart("sync.nats_url"));
+        assert!(requires_restart("sync.chunk_filter_fp_rate"));
+        assert!(!requires_restart("sync.conflict_mode"));
+        assert!(!requires_restart("storage.quota_bytes"));
+        assert!(!requires_restart("sync.nats_url_extra"));
+    }
+
+    #[tokio::test]
+    async fn read_config_rejects_unknown_conflict_mode() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("tcfs.toml");
+        std::fs::write(&path, "[sync]\nconflict_mode = \"yolo\"\n").unwrap();
+        let err = read_config(&path).await.unwrap_err();
+        assert!(err.to_string().contains("invalid conflict_mode"), "{err}");
+
+        std::fs::write(&path, "[sync]\nconflict_mode = \"defer\"\n").unwrap();
+        let config = read_config(&path).await.unwrap();
+        assert!(validate(&config).is_ok());
+        assert_eq!(config.sync.conflict_mode, ConflictMode::Defer);
+    }
+
+    #[test]
+    fn validate_checks_path_rules() {
+        let mut config = TcfsConfig::default();
+        config.sync.path_rules = vec![tcfs_core::config::PathRule {
+            pattern: "src/**".into(),
+            conflict_mode: ConflictMode::Interactive,
+        }];
+        assert!(validate(&config).is_ok());
+
+        config.sync.path_rules[0].pattern = "src/[".into();
+        let err = validate(&config).unwrap_err();
+        assert!(err.to_string().contains("path_rules"), "{err}");
+    }
+
+    #[test]
+    fn validate_rejects_unknown_auto_strategy() {
+        let mut config = TcfsConfig::default();
+        config.sync.auto_strategy = "prefer-device:laptop".into();
+        assert!(validate(&config).is_ok());
+
+        config.sync.auto_strategy = "largest".into();
+        let err = validate(&config).unwrap_err();
+        assert!(err.to_string().contains("auto_strategy"), "{err}");
+    }
+}
@@ -112,15 +112,16 @@ mod inner {
         )
         .context("building storage operator")?;
 
-        // State cache (JSON, shared across tasks with Arc<TokioMutex>)
+        // State cache (JSON, shared across tasks; locks internally per entry)
         let state_path = config.sync.state_db.with_extension("json");
-        let state = Arc::new(TokioMutex::new(
-            tcfs_sync::state::StateCache::open(&state_path)
-                .with_context(|| format!("opening state cache: {}", state_path.display()))?,
-        ));
+        let mut state = tcfs_sync::state::StateCache::open(&state_path)
+            .with_context(|| format!("opening state cache: {}", state_path.display()))?;
+        crate::daemon::configure_state_cache(&mut state, &config, Some((&access_key, &secret_key)));
+        let state = Arc::new(state);
 
         // Connect to NATS
-        let nats: NatsClient = NatsClient::connect(&config.sync.nats_url).await?;
+        let nats: NatsClient =
+            NatsClient::connect(&config.sync.nats_url, config.fleet_id()).await?;
         nats.ensure_streams().await?;
 
         // Concurrency limit: configurable via TCFS_WORKER_CONCURRENCY or CPU count
@@ -209,7 +210,7 @@ mod inner {
     async fn execute_task(
         msg: tcfs_sync::nats::TaskMessage,
         op: opendal::Operator,
-        state: Arc<TokioMutex<tcfs_sync::state::StateCache>>,
+        state: Arc<tcfs_sync::state::StateCache>,
         metrics: WorkerMetrics,
     ) {
         let task_type = msg.task.type_name().to_string();
@@ -248,7 +249,7 @@ mod inner {
     async fn dispatch_task(
         task: &SyncTask,
         op: &opendal::Operator,
-        state: &Arc<TokioMutex<tcfs_sync::state::StateCache>>,
+        state: &Arc<tcfs_sync::state::StateCache>,
     ) -> anyhow::Result<()> {
         match task {
             SyncTask::Push {
@@ -257,19 +258,19 @@ mod inner {
                 ..
             } => {
                 let local = std::path::Path::new(local_path);
-                let mut guard = state.lock().await;
+                let cache = state.as_ref();
                 if local.is_file() {
-                    tcfs_sync::engine::upload_file(op, local, remote_prefix, &mut guard, None)
+                    tcfs_sync::engine::upload_file(op, local, remote_prefix, cache, None)
                         .await
                         .map(|_| ())?;
                 } else if local.is_dir() {
-                    tcfs_sync::engine::push_tree(op, local, remote_prefix, &mut guard, None)
+                    tcfs_sync::engine::push_tree(op, local, remote_prefix, cache, None)
                         .await
                         .map(|_| ())?;
                 } else {
                     anyhow::bail!("push: path not found: {local_path}");
                 }
-                guard.flush().context("flushing state cache")
+                cache.flush().context("flushing state cache")
             }
             SyncTask::Pull {
                 manifest_path,